@@ -0,0 +1,14 @@
+#!/bin/sh
+# Compiles smoke.c against the static library and runs it.
+#
+#   ctest/run.sh [cc flags...]
+set -eu
+
+root=$(cd "$(dirname "$0")/.." && pwd)
+out="$root/target/c-smoke"
+cargo build --manifest-path "$root/Cargo.toml" --lib
+mkdir -p "$out"
+${CC:-cc} -std=c11 -D_GNU_SOURCE -Wall -Wextra -Werror "$@" -I "$root/include" \
+    -o "$out/smoke" "$root/ctest/smoke.c" \
+    "$root/target/debug/liblg_rust_binding.a" -lpthread -ldl -lm
+"$out/smoke"
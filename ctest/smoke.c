@@ -0,0 +1,119 @@
+// Builds a small module through the C API, checks that it verifies, prints, round-trips through
+// the binary encoding and generates C, and that errors come back as statuses. Run by run.sh.
+
+#include "lg_rust_binding.h"
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#define CHECK(condition)                                                        \
+    do {                                                                        \
+        if (!(condition)) {                                                     \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__,    \
+                    #condition);                                                \
+            exit(1);                                                            \
+        }                                                                       \
+    } while (0)
+
+static const char *EXPECTED =
+    "function i32 add(i32 a, i32 b) {\n"
+    "entry:\n"
+    "    %pa = `field_address([a], [])\n"
+    "    %a = get i32, %pa\n"
+    "    %pb = `field_address([b], [])\n"
+    "    %b = get i32, %pb\n"
+    "    %r = add i32 %a, %b\n"
+    "    return %r\n"
+    "}\n";
+
+// Appends `%p<name> = field_address(name)` and `%<name> = get i32, %p<name>`.
+static void load_argument(struct IRBasicBlock *block, const struct IRTypeHandle *i32,
+                          const char *name) {
+    char address[16];
+    snprintf(address, sizeof address, "p%s", name);
+    struct IROperandHandle *field = lg_operand_macro("field_address", &name, 1, NULL, 0);
+    CHECK(lg_basic_block_append(block, lg_instruction_set_virtual_register(field, address)));
+    lg_operand_free(field);
+    struct IROperandHandle *pointer = lg_operand_register(address);
+    CHECK(lg_basic_block_append(block, lg_instruction_get(i32, pointer, name)));
+    lg_operand_free(pointer);
+}
+
+static struct IRModule *build(void) {
+    struct IRModule *module = lg_module_new();
+    struct IRTypeHandle *i32 = lg_type_integer(32, false);
+    const char *names[] = {"a", "b"};
+    const struct IRTypeHandle *types[] = {i32, i32};
+    struct IRFunction *function = lg_module_add_function(module, i32, "add", names, types, 2);
+    CHECK(function != NULL);
+    CHECK(lg_module_add_function(module, i32, "add", names, types, 2) == NULL);
+    struct IRBasicBlock *entry = lg_function_append_basic_block(function, "entry");
+    CHECK(entry != NULL);
+    load_argument(entry, i32, "a");
+    load_argument(entry, i32, "b");
+    struct IROperandHandle *a = lg_operand_register("a");
+    struct IROperandHandle *b = lg_operand_register("b");
+    struct IROperandHandle *r = lg_operand_register("r");
+    CHECK(lg_basic_block_append(entry,
+                                lg_instruction_calculate(false, LG_OPERATOR_ADD, i32, a, b, "r")));
+    CHECK(lg_basic_block_append(entry, lg_instruction_return(r)));
+    lg_operand_free(a);
+    lg_operand_free(b);
+    lg_operand_free(r);
+    lg_type_free(i32);
+    return module;
+}
+
+static void check_dump(const struct IRModule *module) {
+    char *text = lg_module_dump(module);
+    CHECK(text != NULL);
+    if (strcmp(text, EXPECTED) != 0) {
+        fprintf(stderr, "unexpected dump:\n%s", text);
+        exit(1);
+    }
+    lg_string_free(text);
+}
+
+int main(void) {
+    struct IRModule *module = build();
+    char *error = NULL;
+    CHECK(lg_module_verify(module, &error) == LG_OK);
+    CHECK(error == NULL);
+    check_dump(module);
+
+    size_t length = 0;
+    uint8_t *data = lg_module_write(module, &length);
+    CHECK(data != NULL && length > 0);
+    struct IRModule *read = NULL;
+    CHECK(lg_module_read(data, length, &read, &error) == LG_OK);
+    lg_bytes_free(data, length);
+    check_dump(read);
+    lg_module_free(read);
+
+    const char *options[] = {"--target=c"};
+    CHECK(lg_generate(module, options, 1, &data, &length, &error) == LG_OK);
+    CHECK(length > 0 && memmem(data, length, "add", 3) != NULL);
+    lg_bytes_free(data, length);
+
+    const char *unknown[] = {"--target=vax"};
+    CHECK(lg_generate(module, unknown, 1, &data, &length, &error) == LG_ERROR_INVALID_OPTION);
+    CHECK(error != NULL);
+    lg_string_free(error);
+    error = NULL;
+    lg_module_free(module);
+
+    CHECK(lg_module_parse(EXPECTED, &module, &error) == LG_OK);
+    check_dump(module);
+    lg_module_free(module);
+    CHECK(lg_module_parse("function", &module, &error) == LG_ERROR_PARSE);
+    CHECK(error != NULL);
+    lg_string_free(error);
+    CHECK(lg_module_parse(NULL, &module, NULL) == LG_ERROR_NULL_ARGUMENT);
+
+    CHECK(lg_type_integer(12, false) == NULL);
+    CHECK(lg_instruction_goto(NULL) == NULL);
+
+    puts("ok");
+    return 0;
+}
@@ -0,0 +1,383 @@
+#ifndef LG_RUST_BINDING_H
+#define LG_RUST_BINDING_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit by hand. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#define LG_CONDITION_EQUAL 0
+
+#define LG_CONDITION_NOT_EQUAL 1
+
+#define LG_CONDITION_LESS 2
+
+#define LG_CONDITION_LESS_EQUAL 3
+
+#define LG_CONDITION_GREATER 4
+
+#define LG_CONDITION_GREATER_EQUAL 5
+
+#define LG_CONDITION_IF_TRUE 6
+
+#define LG_CONDITION_IF_FALSE 7
+
+#define LG_OPERATOR_ADD 0
+
+#define LG_OPERATOR_SUB 1
+
+#define LG_OPERATOR_MUL 2
+
+#define LG_OPERATOR_DIV 3
+
+#define LG_OPERATOR_MOD 4
+
+#define LG_OPERATOR_AND 5
+
+#define LG_OPERATOR_OR 6
+
+#define LG_OPERATOR_XOR 7
+
+#define LG_OPERATOR_SHL 8
+
+#define LG_OPERATOR_SHR 9
+
+#define LG_OPERATOR_USHR 10
+
+#define LG_CAST_ZERO_EXTEND 0
+
+#define LG_CAST_SIGN_EXTEND 1
+
+#define LG_CAST_TRUNCATE 2
+
+#define LG_CAST_INT_TO_FLOAT 3
+
+#define LG_CAST_FLOAT_TO_INT 4
+
+#define LG_CAST_FLOAT_EXTEND 5
+
+#define LG_CAST_FLOAT_TRUNCATE 6
+
+typedef struct IRBasicBlock IRBasicBlock;
+
+typedef struct IRFunction IRFunction;
+
+typedef struct IRInstructionHandle IRInstructionHandle;
+
+typedef struct IRModule IRModule;
+
+typedef struct IROperandHandle IROperandHandle;
+
+typedef struct IRTypeHandle IRTypeHandle;
+
+typedef uint32_t LGStatus;
+
+#define LG_OK 0
+
+// A required pointer was null.
+#define LG_ERROR_NULL_ARGUMENT 1
+
+// A string was not UTF-8.
+#define LG_ERROR_INVALID_UTF8 2
+
+// An option passed to `lg_generate` was not recognized.
+#define LG_ERROR_INVALID_OPTION 3
+
+// The backend cannot generate code for something in the module.
+#define LG_ERROR_UNSUPPORTED 4
+
+// The text passed to `lg_module_parse` is not a valid module.
+#define LG_ERROR_PARSE 5
+
+// `lg_module_verify` found problems.
+#define LG_ERROR_VERIFY 6
+
+// The library panicked; the module may be left half-changed.
+#define LG_ERROR_PANIC 7
+
+// The data passed to `lg_module_read` is not a valid encoded module.
+#define LG_ERROR_DECODE 8
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+void lg_string_free(char *value);
+
+// Releases the output of `lg_generate` and `lg_module_write`.
+void lg_bytes_free(uint8_t *data, size_t length);
+
+struct IRModule *lg_module_new(void);
+
+void lg_module_free(struct IRModule *module);
+
+// Parses the textual form printed by `lg_module_dump` into a new module stored in `module`.
+LGStatus lg_module_parse(const char *source, struct IRModule **module, char **error);
+
+// The textual form of the module, to be released with `lg_string_free`.
+char *lg_module_dump(const struct IRModule *module);
+
+// Decodes a module encoded by `lg_module_write` into a new module stored in `module`.
+LGStatus lg_module_read(const uint8_t *data, size_t length, struct IRModule **module, char **error);
+
+// The binary encoding of the module, to be released with `lg_bytes_free`. Its size is stored in
+// `length`.
+uint8_t *lg_module_write(const struct IRModule *module, size_t *length);
+
+// Checks the module, storing one problem per line in `error` if there are any.
+LGStatus lg_module_verify(struct IRModule *module, char **error);
+
+// Generates code for the module with the same options as `IRGenerator::generate`, e.g.
+// `--target=c`. On success the output is stored in `data` and `length`, to be released with
+// `lg_bytes_free`.
+//
+// Instructions appended through this API do not keep the control flow edges up to date, so
+// they are recomputed first.
+LGStatus lg_generate(struct IRModule *module,
+                     const char *const *options,
+                     size_t options_count,
+                     uint8_t **data,
+                     size_t *length,
+                     char **error);
+
+// Sets the function the program starts at, or clears it when `name` is null.
+bool lg_module_set_entry_point(struct IRModule *module, const char *name);
+
+bool lg_module_add_structure(struct IRModule *module,
+                             const char *name,
+                             const char *const *field_names,
+                             const struct IRTypeHandle *const *field_types,
+                             size_t fields_count);
+
+// Adds a global; `size` and `values` are optional, and `values` is only used when
+// `has_values` is set, so that an empty list can be told from no list.
+bool lg_module_add_global_data(struct IRModule *module,
+                               const char *name,
+                               const struct IROperandHandle *size,
+                               bool has_values,
+                               const struct IROperandHandle *const *values,
+                               size_t values_count);
+
+// Adds a function whose fields are its arguments. Returns null if the module already has a
+// function of that name.
+struct IRFunction *lg_module_add_function(struct IRModule *module,
+                                          const struct IRTypeHandle *return_type,
+                                          const char *name,
+                                          const char *const *argument_names,
+                                          const struct IRTypeHandle *const *argument_types,
+                                          size_t arguments_count);
+
+struct IRFunction *lg_module_get_function(struct IRModule *module, const char *name);
+
+// Adds a local variable after the arguments and other fields.
+bool lg_function_add_local(struct IRFunction *function,
+                           const char *name,
+                           const struct IRTypeHandle *_type);
+
+// Appends an empty block to the function. Returns null if it already has a block of that
+// name.
+struct IRBasicBlock *lg_function_append_basic_block(struct IRFunction *function, const char *name);
+
+// Like `lg_function_append_basic_block`, for the global init section.
+struct IRBasicBlock *lg_module_append_global_init_block(struct IRModule *module, const char *name);
+
+// Moves `instruction` to the end of the block; the handle must not be used afterwards, even
+// when this returns false because `block` is null.
+bool lg_basic_block_append(struct IRBasicBlock *block, struct IRInstructionHandle *instruction);
+
+void lg_type_free(struct IRTypeHandle *_type);
+
+// An integer type of 1, 8, 16, 32 or 64 bits.
+struct IRTypeHandle *lg_type_integer(uint32_t bits, bool unsigned_);
+
+struct IRTypeHandle *lg_type_float(void);
+
+struct IRTypeHandle *lg_type_double(void);
+
+struct IRTypeHandle *lg_type_void(void);
+
+struct IRTypeHandle *lg_type_pointer(const struct IRTypeHandle *base);
+
+// The structure of that name in the module the type is used in.
+struct IRTypeHandle *lg_type_structure(const char *name);
+
+struct IRTypeHandle *lg_type_array(const struct IRTypeHandle *element, uint64_t length);
+
+struct IRTypeHandle *lg_type_function(const struct IRTypeHandle *return_type,
+                                      const struct IRTypeHandle *const *params,
+                                      size_t params_count,
+                                      bool variadic);
+
+void lg_operand_free(struct IROperandHandle *operand);
+
+struct IROperandHandle *lg_operand_register(const char *name);
+
+// Refers to the constant pool entry at `index`; see `lg_module_constant_*` for adding entries.
+struct IROperandHandle *lg_operand_constant(uint32_t index);
+
+// An integer constant of an integer type, holding the low bits of `value`.
+struct IROperandHandle *lg_module_constant_integer(struct IRModule *module,
+                                                   const struct IRTypeHandle *_type,
+                                                   int64_t value);
+
+struct IROperandHandle *lg_module_constant_float(struct IRModule *module, float value);
+
+struct IROperandHandle *lg_module_constant_double(struct IRModule *module, double value);
+
+// The null pointer of a pointer type.
+struct IROperandHandle *lg_module_constant_null(struct IRModule *module,
+                                                const struct IRTypeHandle *_type);
+
+// The address of a NUL-terminated copy of `bytes`, typed as a pointer to `i8`.
+struct IROperandHandle *lg_module_constant_string(struct IRModule *module,
+                                                  const uint8_t *bytes,
+                                                  size_t length);
+
+// A value of `_type` with all bits zero.
+struct IROperandHandle *lg_module_constant_zero(struct IRModule *module,
+                                                const struct IRTypeHandle *_type);
+
+// An array or structure constant of `_type` whose elements are the values of the constants in
+// `elements`, which must belong to `module`.
+struct IROperandHandle *lg_module_constant_aggregate(struct IRModule *module,
+                                                     const struct IRTypeHandle *_type,
+                                                     const struct IROperandHandle *const *elements,
+                                                     size_t elements_count);
+
+// A macro such as `field_address` or `function_address`, written `` `name([args], [operands]) ``
+// in the textual form.
+struct IROperandHandle *lg_operand_macro(const char *name,
+                                         const char *const *args,
+                                         size_t args_count,
+                                         const struct IROperandHandle *const *operands,
+                                         size_t operands_count);
+
+// A phi taking `operands[i]` when control comes from the block `labels[i]`.
+struct IROperandHandle *lg_operand_phi(const struct IRTypeHandle *_type,
+                                       const char *const *labels,
+                                       const struct IROperandHandle *const *operands,
+                                       size_t count);
+
+struct IROperandHandle *lg_operand_virtual_table(const char *const *functions,
+                                                 size_t functions_count);
+
+// An interface table whose entry `i` is named `names[i]` and lists `functions_counts[i]`
+// functions, taken in order from `functions`.
+struct IROperandHandle *lg_operand_interface_table(const char *const *names,
+                                                   const size_t *functions_counts,
+                                                   size_t entries_count,
+                                                   const char *const *functions,
+                                                   size_t functions_count);
+
+void lg_instruction_free(struct IRInstructionHandle *instruction);
+
+// The textual form of the instruction, to be released with `lg_string_free`.
+char *lg_instruction_to_string(const struct IRInstructionHandle *instruction);
+
+struct IRInstructionHandle *lg_instruction_goto(const char *target);
+
+// Jumps to the block `target` when the condition holds. `operand2` is null for the
+// `LG_CONDITION_IF_TRUE` and `LG_CONDITION_IF_FALSE` conditions.
+struct IRInstructionHandle *lg_instruction_conditional_jump(bool is_atomic,
+                                                            const struct IRTypeHandle *_type,
+                                                            uint32_t condition,
+                                                            const struct IROperandHandle *operand1,
+                                                            const struct IROperandHandle *operand2,
+                                                            const char *target);
+
+struct IRInstructionHandle *lg_instruction_no_operate(void);
+
+// Returns `operand`, or nothing when it is null.
+struct IRInstructionHandle *lg_instruction_return(const struct IROperandHandle *operand);
+
+struct IRInstructionHandle *lg_instruction_malloc(const struct IROperandHandle *size,
+                                                  const char *target);
+
+// The `free` instruction, releasing memory from `malloc`.
+struct IRInstructionHandle *lg_instruction_free_memory(const struct IROperandHandle *ptr);
+
+struct IRInstructionHandle *lg_instruction_realloc(const struct IROperandHandle *ptr,
+                                                   const struct IROperandHandle *size,
+                                                   const char *target);
+
+struct IRInstructionHandle *lg_instruction_set(const struct IRTypeHandle *_type,
+                                               const struct IROperandHandle *address,
+                                               const struct IROperandHandle *value);
+
+struct IRInstructionHandle *lg_instruction_get(const struct IRTypeHandle *_type,
+                                               const struct IROperandHandle *address,
+                                               const char *target);
+
+struct IRInstructionHandle *lg_instruction_set_virtual_register(const struct IROperandHandle *source,
+                                                                const char *target);
+
+struct IRInstructionHandle *lg_instruction_type_cast(uint32_t kind,
+                                                     const struct IRTypeHandle *original_type,
+                                                     const struct IROperandHandle *source,
+                                                     const struct IRTypeHandle *target_type,
+                                                     const char *target);
+
+struct IRInstructionHandle *lg_instruction_stack_allocate(const struct IROperandHandle *size,
+                                                          const char *target);
+
+// The address of an element of the `_type` value at `base`. Step `i` of the path is the field
+// `fields[i]` of a structure or, when that is null, the element `indices[i]` of an array or
+// pointer.
+struct IRInstructionHandle *lg_instruction_element_address(const struct IRTypeHandle *_type,
+                                                           const struct IROperandHandle *base,
+                                                           const char *const *fields,
+                                                           const struct IROperandHandle *const *indices,
+                                                           size_t path_length,
+                                                           const char *target);
+
+struct IRInstructionHandle *lg_instruction_calculate(bool is_atomic,
+                                                     uint32_t operator_,
+                                                     const struct IRTypeHandle *_type,
+                                                     const struct IROperandHandle *operand1,
+                                                     const struct IROperandHandle *operand2,
+                                                     const char *target);
+
+// Increments a value of `_type`. When `target` is null, the value at the address `operand` is
+// incremented in place, atomically. Otherwise `operand` is the value itself: it is left
+// unchanged and the register `target` is set to it plus one.
+struct IRInstructionHandle *lg_instruction_increase(const struct IRTypeHandle *_type,
+                                                    const struct IROperandHandle *operand,
+                                                    const char *target);
+
+// Like `lg_instruction_increase`, decrementing.
+struct IRInstructionHandle *lg_instruction_decrease(const struct IRTypeHandle *_type,
+                                                    const struct IROperandHandle *operand,
+                                                    const char *target);
+
+struct IRInstructionHandle *lg_instruction_not(bool is_atomic,
+                                               const struct IRTypeHandle *_type,
+                                               const struct IROperandHandle *operand,
+                                               const char *target);
+
+struct IRInstructionHandle *lg_instruction_negate(bool is_atomic,
+                                                  const struct IRTypeHandle *_type,
+                                                  const struct IROperandHandle *operand,
+                                                  const char *target);
+
+// Calls `address` with `arguments[i]` of type `argument_types[i]`; `target` is null when the
+// result is not kept.
+struct IRInstructionHandle *lg_instruction_invoke(const struct IRTypeHandle *return_type,
+                                                  const struct IROperandHandle *address,
+                                                  const struct IRTypeHandle *const *argument_types,
+                                                  const struct IROperandHandle *const *arguments,
+                                                  size_t arguments_count,
+                                                  const char *target);
+
+// Inline assembly, with `resources[i]` of type `types[i]` bound to the name `names[i]`.
+struct IRInstructionHandle *lg_instruction_asm(const char *code,
+                                               const struct IRTypeHandle *const *types,
+                                               const struct IROperandHandle *const *resources,
+                                               const char *const *names,
+                                               size_t resources_count);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LG_RUST_BINDING_H */
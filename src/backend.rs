@@ -0,0 +1,495 @@
+//! Code generation from `IRModule` into target formats, selected through the options passed to
+//! `IRGenerator::generate`.
+
+use crate::ir::base::{IRControlFlowGraph, IRGlobalData, IRNode};
+use crate::ir::layout::{DataLayout, IREndianness};
+use crate::ir::lower::{has_element_addresses, lower_element_addresses};
+use crate::ir::operand::{
+    IRConstant, IRInterfaceTable, IRMacro, IROperand, IRPhi, IRVirtualRegister, IRVirtualTable,
+};
+use crate::ir::type_check::{IRFunctionTypes, find_aggregate_value};
+use crate::ir::types::{IRType, IRTypeKind};
+use crate::ir::verify::IRLocation;
+use crate::ir::{IRConstantData, IRConstantPoolEntry, IRModule, IRVisitor};
+use indexmap::IndexMap;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt;
+
+pub mod aarch64;
+pub mod c;
+pub mod elf;
+pub mod llvm;
+pub mod riscv64;
+pub mod wasm;
+pub mod x86_64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IRTarget {
+    X86_64,
+    AArch64,
+    RiscV64,
+    C,
+    LLVM,
+    Wasm32,
+}
+
+impl IRTarget {
+    /// Every backend holds addresses in 64 bits, little-endian; the wasm backend included, which
+    /// keeps addresses as `i64` values and wraps them on memory access.
+    ///
+    /// The 8-byte pointers of `Wasm32` are deliberate rather than the 4 bytes of the wasm32 C
+    /// ABI: the IR lets pointers and `i64` values stand in for each other, so a pointer stored
+    /// in a structure or a global must fill 8 bytes to be read back as an `i64`, and modules
+    /// then lay out their data the same way on every target. Structures holding pointers are
+    /// therefore not laid out like those of C code compiled for wasm32.
+    pub fn data_layout(&self) -> DataLayout {
+        DataLayout::new(8, IREndianness::Little)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IREmitKind {
+    Assembly,
+    Source,
+    LLVMIR,
+    Object,
+    WebAssemblyText,
+}
+
+/// Options accepted by `IRGenerator::generate`: `--target=<name>` and `--emit=<kind>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IRGenerateOptions {
+    pub target: IRTarget,
+    pub emit: IREmitKind,
+}
+
+impl IRGenerateOptions {
+    pub fn parse(options: &[String]) -> Result<Self, IRGenerateError> {
+        let mut target = IRTarget::X86_64;
+        let mut emit = None;
+        for option in options {
+            let invalid = || IRGenerateError::InvalidOption(option.clone());
+            let (key, value) = option.split_once('=').ok_or_else(invalid)?;
+            match (key, value) {
+                ("--target", "x86_64" | "x86-64" | "amd64") => target = IRTarget::X86_64,
+                ("--target", "aarch64" | "arm64") => target = IRTarget::AArch64,
+                ("--target", "riscv64" | "rv64gc") => target = IRTarget::RiscV64,
+                ("--target", "c") => target = IRTarget::C,
+                ("--target", "llvm") => target = IRTarget::LLVM,
+                ("--target", "wasm32" | "wasm") => target = IRTarget::Wasm32,
+                ("--emit", "asm") => emit = Some(IREmitKind::Assembly),
+                ("--emit", "c") => emit = Some(IREmitKind::Source),
+                ("--emit", "llvm-ir") => emit = Some(IREmitKind::LLVMIR),
+                ("--emit", "obj") => emit = Some(IREmitKind::Object),
+                ("--emit", "wat") => emit = Some(IREmitKind::WebAssemblyText),
+                _ => return Err(invalid()),
+            }
+        }
+        // Each target has one natural output, so `--emit` is only needed to be explicit.
+        let emit = emit.unwrap_or(match target {
+            IRTarget::X86_64 | IRTarget::AArch64 | IRTarget::RiscV64 => IREmitKind::Assembly,
+            IRTarget::C => IREmitKind::Source,
+            IRTarget::LLVM => IREmitKind::LLVMIR,
+            IRTarget::Wasm32 => IREmitKind::Object,
+        });
+        Ok(Self { target, emit })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IRGenerateError {
+    InvalidOption(String),
+    Unsupported {
+        location: IRLocation,
+        message: String,
+    },
+}
+
+impl fmt::Display for IRGenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IRGenerateError::InvalidOption(option) => write!(f, "invalid option '{}'", option),
+            IRGenerateError::Unsupported { location, message } => {
+                write!(f, "{}: unsupported {}", location, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IRGenerateError {}
+
+pub type IRGenerateResult<T> = Result<T, IRGenerateError>;
+
+pub fn generate(ir_module: &IRModule, options: &IRGenerateOptions) -> IRGenerateResult<Vec<u8>> {
+    match (options.target, options.emit) {
+        (IRTarget::X86_64, IREmitKind::Assembly) => {
+            x86_64::generate_assembly(ir_module).map(String::into_bytes)
+        }
+        (IRTarget::AArch64, IREmitKind::Assembly) => {
+            aarch64::generate_assembly(ir_module).map(String::into_bytes)
+        }
+        (IRTarget::RiscV64, IREmitKind::Assembly) => {
+            riscv64::generate_assembly(ir_module).map(String::into_bytes)
+        }
+        (IRTarget::X86_64, IREmitKind::Object) => x86_64::generate_assembly(ir_module)
+            .and_then(|assembly| elf::generate_object(&assembly, &elf::x86_64::IRX86Encoder)),
+        (IRTarget::AArch64, IREmitKind::Object) => aarch64::generate_assembly(ir_module)
+            .and_then(|assembly| elf::generate_object(&assembly, &elf::aarch64::IRAArch64Encoder)),
+        (IRTarget::RiscV64, IREmitKind::Object) => riscv64::generate_assembly(ir_module)
+            .and_then(|assembly| elf::generate_object(&assembly, &elf::riscv64::IRRISCVEncoder)),
+        (IRTarget::C, IREmitKind::Source) => c::generate_source(ir_module).map(String::into_bytes),
+        (IRTarget::LLVM, IREmitKind::LLVMIR) => {
+            llvm::generate_ir(ir_module).map(String::into_bytes)
+        }
+        (IRTarget::Wasm32, IREmitKind::Object) => wasm::generate_binary(ir_module),
+        (IRTarget::Wasm32, IREmitKind::WebAssemblyText) => {
+            wasm::generate_text(ir_module).map(String::into_bytes)
+        }
+        (target, emit) => Err(IRGenerateError::InvalidOption(format!(
+            "--emit={:?} for --target={:?}",
+            emit, target
+        ))),
+    }
+}
+
+/// The module with every `element_address` lowered to arithmetic in the data layout of `target`,
+/// copied only if there is one to lower.
+pub(crate) fn lower_for_target(
+    ir_module: &IRModule,
+    target: IRTarget,
+) -> IRGenerateResult<Cow<'_, IRModule>> {
+    if !has_element_addresses(ir_module) {
+        return Ok(Cow::Borrowed(ir_module));
+    }
+    let mut lowered = ir_module.clone();
+    lower_element_addresses(&mut lowered, &target.data_layout()).map_err(|error| {
+        IRGenerateError::Unsupported {
+            location: error.location,
+            message: format!("element_address: {}", error.kind),
+        }
+    })?;
+    Ok(Cow::Owned(lowered))
+}
+
+/// Fails on the first structure, array or function type used by value, for the targets that
+/// hold every value in a register or an 8-byte slot.
+pub(crate) fn check_scalar_values(ir_module: &IRModule) -> IRGenerateResult<()> {
+    match find_aggregate_value(ir_module) {
+        Some((location, _type)) => Err(IRGenerateError::Unsupported {
+            location,
+            message: format!("value of type {}", _type),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Turns an IR name into a symbol any assembler accepts: ASCII letters, digits and `_` are kept,
+/// every other byte (and a leading digit) is written as `.` followed by its hex value.
+pub(crate) fn mangle_symbol(name: &str) -> String {
+    let mut symbol = String::new();
+    for (index, byte) in name.bytes().enumerate() {
+        if byte.is_ascii_alphabetic() || byte == b'_' || (byte.is_ascii_digit() && index > 0) {
+            symbol.push(byte as char);
+        } else {
+            symbol.push_str(&format!(".{:02x}", byte));
+        }
+    }
+    symbol
+}
+
+/// How a value of some type is held in a machine register and in memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum IRValueType {
+    Integer { bits: u32, unsigned: bool },
+    Float,
+    Double,
+    Void,
+}
+
+impl IRValueType {
+    pub(crate) const ADDRESS: IRValueType = IRValueType::Integer {
+        bits: 64,
+        unsigned: true,
+    };
+
+    pub(crate) fn of(_type: &dyn IRType) -> Self {
+        match IRTypeKind::of(_type) {
+            IRTypeKind::Integer(integer) => IRValueType::Integer {
+                bits: integer.size as u32,
+                unsigned: integer.unsigned,
+            },
+            IRTypeKind::Float(_) => IRValueType::Float,
+            IRTypeKind::Double(_) => IRValueType::Double,
+            IRTypeKind::Pointer(_) => IRValueType::ADDRESS,
+            IRTypeKind::Void(_) => IRValueType::Void,
+            // Rejected up front by `check_scalar_values` on the targets that use this.
+            IRTypeKind::Structure(_) | IRTypeKind::Array(_) | IRTypeKind::Function(_) => {
+                IRValueType::Void
+            }
+        }
+    }
+
+    pub(crate) fn size(self) -> u64 {
+        match self {
+            IRValueType::Integer { bits, .. } => (bits as u64).div_ceil(8),
+            IRValueType::Float => 4,
+            IRValueType::Double => 8,
+            IRValueType::Void => 0,
+        }
+    }
+
+    pub(crate) fn is_floating_point(self) -> bool {
+        matches!(self, IRValueType::Float | IRValueType::Double)
+    }
+
+    pub(crate) fn is_unsigned(self) -> bool {
+        matches!(self, IRValueType::Integer { unsigned: true, .. })
+    }
+}
+
+/// An operand, classified by how a backend materializes it.
+#[derive(Clone, Debug)]
+pub(crate) enum IROperandValue {
+    Register(String),
+    Constant(u32),
+    FieldAddress(String),
+    GlobalDataAddress(String),
+    FunctionAddress(String),
+    Phi(IRPhi),
+    VirtualTable(Vec<String>),
+    InterfaceTable(IRInterfaceTable),
+    Unsupported,
+}
+
+impl IROperandValue {
+    pub(crate) fn of(operand: &dyn IROperand) -> Self {
+        let classifier = IROperandClassifier {
+            value: RefCell::new(IROperandValue::Unsupported),
+        };
+        operand.accept(&classifier);
+        classifier.value.into_inner()
+    }
+}
+
+struct IROperandClassifier {
+    value: RefCell<IROperandValue>,
+}
+
+impl IRVisitor for IROperandClassifier {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_virtual_register(&self, ir_virtual_register: &IRVirtualRegister) {
+        *self.value.borrow_mut() = IROperandValue::Register(ir_virtual_register.name.clone());
+    }
+    fn visit_constant(&self, ir_constant: &IRConstant) {
+        *self.value.borrow_mut() = IROperandValue::Constant(ir_constant.index);
+    }
+    fn visit_phi(&self, ir_phi: &IRPhi) {
+        *self.value.borrow_mut() = IROperandValue::Phi(ir_phi.clone());
+    }
+    fn visit_macro(&self, ir_macro: &IRMacro) {
+        let name = ir_macro.args.first().cloned().unwrap_or_default();
+        *self.value.borrow_mut() = match ir_macro.name.as_str() {
+            "field_address" => IROperandValue::FieldAddress(name),
+            "global_data_address" => IROperandValue::GlobalDataAddress(name),
+            "function_address" => IROperandValue::FunctionAddress(name),
+            _ => IROperandValue::Unsupported,
+        };
+    }
+    fn visit_virtual_table(&self, ir_virtual_table: &IRVirtualTable) {
+        *self.value.borrow_mut() = IROperandValue::VirtualTable(ir_virtual_table.functions.clone());
+    }
+    fn visit_interface_table(&self, ir_interface_table: &IRInterfaceTable) {
+        *self.value.borrow_mut() = IROperandValue::InterfaceTable(ir_interface_table.clone());
+    }
+}
+
+/// A piece of the initial contents of global data.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum IRDataItem {
+    Bytes(Vec<u8>),
+    /// The 8-byte address of a function or global data.
+    Symbol(String),
+    /// The 8-byte address of a string constant.
+    Constant(usize),
+    Zero(u64),
+}
+
+/// The contents of one global, and whether it has initial values at all.
+pub(crate) struct IRDataLayout {
+    pub(crate) items: Vec<IRDataItem>,
+    pub(crate) size: u64,
+    pub(crate) initialized: bool,
+}
+
+/// Lays out a global: its values in order (constants in their own width, addresses and table
+/// entries as 8 bytes, interface tables as their entries' functions back to back) padded with
+/// zeros to its declared size.
+pub(crate) fn global_data_layout(
+    ir_module: &IRModule,
+    ir_global_data: &IRGlobalData,
+) -> IRGenerateResult<IRDataLayout> {
+    let unsupported = |message: String| IRGenerateError::Unsupported {
+        location: IRLocation::GlobalData(ir_global_data.name.clone()),
+        message,
+    };
+    let mut items = vec![];
+    let mut size = 0;
+    for value in ir_global_data.values.iter().flatten() {
+        let mut functions = vec![];
+        match IROperandValue::of(value.as_ref()) {
+            IROperandValue::Constant(index) => {
+                let (entry, data) = constant(ir_module, index)
+                    .ok_or_else(|| unsupported(format!("constant ${}", index)))?;
+                match data {
+                    IRConstantData::String(_) => {
+                        items.push(IRDataItem::Constant(index as usize));
+                        size += 8;
+                    }
+                    data => {
+                        let width = IRValueType::of(entry._type.as_ref()).size() as usize;
+                        let bytes = data.bits().unwrap().to_le_bytes()[..width].to_vec();
+                        size += width as u64;
+                        items.push(IRDataItem::Bytes(bytes));
+                    }
+                }
+            }
+            IROperandValue::GlobalDataAddress(name) | IROperandValue::FunctionAddress(name) => {
+                functions.push(name)
+            }
+            IROperandValue::VirtualTable(names) => functions = names,
+            IROperandValue::InterfaceTable(table) => {
+                for entry in table.entries {
+                    functions.extend(entry.functions);
+                }
+            }
+            _ => return Err(unsupported(format!("global value {}", value))),
+        }
+        for function in functions {
+            items.push(IRDataItem::Symbol(function));
+            size += 8;
+        }
+    }
+    if let Some(declared) = ir_global_data.size.as_ref() {
+        let declared = match IROperandValue::of(declared.as_ref()) {
+            IROperandValue::Constant(index) => constant(ir_module, index)
+                .and_then(|(_, data)| data.bits())
+                .ok_or_else(|| unsupported(format!("global size ${}", index)))?,
+            _ => return Err(unsupported(format!("global size {}", declared))),
+        };
+        if declared > size {
+            items.push(IRDataItem::Zero(declared - size));
+            size = declared;
+        }
+    }
+    Ok(IRDataLayout {
+        items,
+        size,
+        initialized: ir_global_data.values.is_some(),
+    })
+}
+
+pub(crate) fn constant(
+    ir_module: &IRModule,
+    index: u32,
+) -> Option<(&IRConstantPoolEntry, IRConstantData)> {
+    let entry = ir_module.constant_pool.entries.get(index as usize)?;
+    Some((entry, IRConstantData::of(entry)?))
+}
+
+/// A copy performed on a control flow edge to feed a phi in the successor block.
+pub(crate) struct IRPhiMove {
+    pub(crate) target: String,
+    pub(crate) value_type: IRValueType,
+    pub(crate) operand: Box<dyn IROperand>,
+}
+
+/// The phi copies needed when control moves from block `from` to block `to`.
+pub(crate) fn phi_moves(
+    ir_control_flow_graph: &IRControlFlowGraph,
+    from: &str,
+    to: &str,
+) -> Vec<IRPhiMove> {
+    let Some(ir_basic_block) = ir_control_flow_graph.basic_blocks.get(to) else {
+        return vec![];
+    };
+    ir_basic_block
+        .phis()
+        .into_iter()
+        .filter_map(|(target, ir_phi)| {
+            let (_, operand) = ir_phi
+                .labels
+                .iter()
+                .zip(ir_phi.operands.iter())
+                .find(|(label, _)| *label == from)?;
+            Some(IRPhiMove {
+                target,
+                value_type: IRValueType::of(ir_phi._type.as_ref()),
+                operand: operand.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Stack slots of a function whose virtual registers all live in memory. Offsets are negative,
+/// relative to the frame pointer, and every slot is 8 bytes wide.
+pub(crate) struct IRFrameLayout {
+    pub(crate) fields: IndexMap<String, (i64, IRValueType)>,
+    pub(crate) registers: IndexMap<String, (i64, IRValueType)>,
+    /// Where a phi's incoming value is parked between the edge copy and the phi itself, so that
+    /// phis of one block read each other's old values.
+    pub(crate) phi_shadows: IndexMap<String, i64>,
+    pub(crate) scratch: Vec<i64>,
+    pub(crate) size: i64,
+}
+
+impl IRFrameLayout {
+    pub(crate) fn new(
+        fields: &[(String, IRValueType)],
+        types: &IRFunctionTypes,
+        ir_control_flow_graph: &IRControlFlowGraph,
+        reserved: i64,
+        scratch_count: usize,
+    ) -> Self {
+        let mut offset = reserved;
+        let mut next_slot = || {
+            offset += 8;
+            -offset
+        };
+        let fields = fields
+            .iter()
+            .map(|(name, value_type)| (name.clone(), (next_slot(), *value_type)))
+            .collect();
+        let registers = types
+            .registers
+            .iter()
+            .map(|(name, _type)| {
+                let value_type = match IRValueType::of(_type.as_ref()) {
+                    IRValueType::Void => IRValueType::ADDRESS,
+                    value_type => value_type,
+                };
+                (name.clone(), (next_slot(), value_type))
+            })
+            .collect();
+        let mut phi_shadows = IndexMap::new();
+        for ir_basic_block in ir_control_flow_graph.basic_blocks.values() {
+            for (target, _) in ir_basic_block.phis() {
+                phi_shadows.entry(target).or_insert_with(&mut next_slot);
+            }
+        }
+        let scratch = (0..scratch_count).map(|_| next_slot()).collect();
+        Self {
+            fields,
+            registers,
+            phi_shadows,
+            scratch,
+            size: (offset - reserved + 15) / 16 * 16,
+        }
+    }
+
+    pub(crate) fn register(&self, name: &str) -> Option<(i64, IRValueType)> {
+        self.registers.get(name).copied()
+    }
+}
@@ -0,0 +1,1299 @@
+//! GNU as AArch64 assembly for the AAPCS64 procedure call standard.
+//!
+//! The scheme is the one of the x86-64 backend: every virtual register and field lives in an
+//! 8-byte slot below `x29`, and each instruction loads its operands into `x0`/`x1`, computes and
+//! stores the result back. `x9`-`x13` are scratch registers and `x16`/`x17` address memory.
+//! Integers are kept extended to 64 bits according to their signedness; floats and doubles are
+//! kept as their bit patterns and moved to `s0`/`d0` and `s1`/`d1` only to compute. Atomic
+//! instructions are compare-and-swap loops built from exclusive loads and stores, which every
+//! ARMv8 core has.
+
+use crate::backend::x86_64::{constant_label, emit_constants, emit_global_data};
+use crate::backend::{
+    IRConstantData, IRFrameLayout, IRGenerateError, IRGenerateResult, IROperandValue, IRTarget,
+    IRValueType, check_scalar_values, constant, lower_for_target, mangle_symbol, phi_moves,
+};
+use crate::ir::base::{IRCondition, IRControlFlowGraph, IRFunction, IRNode};
+use crate::ir::instruction::{
+    IRAsm, IRCalculate, IRCalculateOperator, IRConditionalJump, IRDecrease, IRFree, IRGet, IRGoto,
+    IRIncrease, IRInvoke, IRMalloc, IRNegate, IRNoOperate, IRNot, IRRealloc, IRReturn, IRSet,
+    IRSetVirtualRegister, IRStackAllocate, IRTypeCast, IRTypeCastKind,
+};
+use crate::ir::operand::{IROperand, IRVirtualRegister};
+use crate::ir::type_check::infer_register_types;
+use crate::ir::verify::IRLocation;
+use crate::ir::{IRModule, IRVisitor};
+use std::cell::{Cell, RefCell};
+use std::fmt::{self, Write};
+
+const GLOBAL_INIT_SYMBOL: &str = "__lg_global_init";
+const ARGUMENT_REGISTERS: usize = 8;
+const SCRATCH_SLOTS: usize = 3;
+
+pub fn generate_assembly(ir_module: &IRModule) -> IRGenerateResult<String> {
+    let ir_module = &*lower_for_target(ir_module, IRTarget::AArch64)?;
+    check_scalar_values(ir_module)?;
+    let mut output = String::new();
+    emit_constants(ir_module, &mut output);
+    emit_global_data(ir_module, &mut output)?;
+    if !ir_module.global_init_section.basic_blocks.is_empty() {
+        emit_text_section(GLOBAL_INIT_SYMBOL, &mut output);
+        IRAArch64Emitter::new(ir_module, None).emit_function(GLOBAL_INIT_SYMBOL, &mut output)?;
+        output.push_str("\t.section .init_array,\"aw\"\n\t.balign 8\n");
+        let _ = writeln!(output, "\t.xword {}", GLOBAL_INIT_SYMBOL);
+    }
+    for ir_function in ir_module.functions.values() {
+        let symbol = mangle_symbol(&ir_function.name);
+        emit_text_section(&symbol, &mut output);
+        let _ = writeln!(output, "\t.globl {}", symbol);
+        IRAArch64Emitter::new(ir_module, Some(ir_function)).emit_function(&symbol, &mut output)?;
+    }
+    if let Some(entry_point) = ir_module.entry_point.as_ref()
+        && entry_point != "main"
+        && !ir_module.functions.contains_key("main")
+    {
+        emit_text_section("main", &mut output);
+        let _ = writeln!(
+            output,
+            "\t.globl main\n\t.type main, %function\n\t.p2align 2\nmain:\n\tb {}\n\t.size main, .-main",
+            mangle_symbol(entry_point)
+        );
+    }
+    output.push_str("\t.section .note.GNU-stack,\"\",%progbits\n");
+    Ok(output)
+}
+
+/// Switches to the section of one function, so each function is in a section of its own.
+fn emit_text_section(symbol: &str, output: &mut String) {
+    let _ = writeln!(output, "\t.section .text.{},\"ax\",%progbits", symbol);
+}
+
+/// The 32-bit view of a 64-bit general purpose register.
+fn w(register: &str) -> String {
+    format!("w{}", &register[1..])
+}
+
+/// The register an `asm` resource name refers to: `xN` or `wN`, except the registers the
+/// linker, the platform and the frame own.
+fn asm_register(name: &str) -> Option<String> {
+    let number = name
+        .strip_prefix('x')
+        .or_else(|| name.strip_prefix('w'))?
+        .parse::<u32>()
+        .ok()?;
+    match number {
+        0..=15 | 19..=28 => Some(format!("x{}", number)),
+        _ => None,
+    }
+}
+
+/// The suffix of the exclusive and acquire/release loads and stores for a width in bytes.
+fn exclusive_suffix(size: u64) -> &'static str {
+    match size {
+        1 => "b",
+        2 => "h",
+        _ => "",
+    }
+}
+
+/// The register holding a value of the given width in bytes: `wN` up to 4 bytes, `xN` above.
+fn sized_register(register: &str, size: u64) -> String {
+    if size <= 4 {
+        w(register)
+    } else {
+        register.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IRArithmetic {
+    Binary(IRCalculateOperator),
+    Not,
+    Negate,
+}
+
+struct IRAArch64Emitter<'a> {
+    ir_module: &'a IRModule,
+    ir_function: Option<&'a IRFunction>,
+    ir_control_flow_graph: &'a IRControlFlowGraph,
+    frame: IRFrameLayout,
+    output: RefCell<String>,
+    location: RefCell<IRLocation>,
+    label_prefix: RefCell<String>,
+    labels: Cell<usize>,
+    terminated: Cell<bool>,
+    error: RefCell<Option<IRGenerateError>>,
+}
+
+impl<'a> IRAArch64Emitter<'a> {
+    /// Creates an emitter for a function, or for the global init section when `ir_function` is
+    /// `None`.
+    fn new(ir_module: &'a IRModule, ir_function: Option<&'a IRFunction>) -> Self {
+        let ir_control_flow_graph = match ir_function {
+            Some(ir_function) => &ir_function.control_flow_graph,
+            None => &ir_module.global_init_section,
+        };
+        let fields = ir_function
+            .iter()
+            .flat_map(|ir_function| ir_function.fields.iter())
+            .map(|field| (field.name.clone(), IRValueType::of(field._type.as_ref())))
+            .collect::<Vec<_>>();
+        let types = infer_register_types(ir_module, ir_function);
+        Self {
+            ir_module,
+            ir_function,
+            ir_control_flow_graph,
+            frame: IRFrameLayout::new(&fields, &types, ir_control_flow_graph, 0, SCRATCH_SLOTS),
+            output: RefCell::new(String::new()),
+            location: RefCell::new(IRLocation::Module),
+            label_prefix: RefCell::new(String::new()),
+            labels: Cell::new(0),
+            terminated: Cell::new(false),
+            error: RefCell::new(None),
+        }
+    }
+
+    fn emit(&self, args: fmt::Arguments) {
+        let mut output = self.output.borrow_mut();
+        output.push('\t');
+        let _ = output.write_fmt(args);
+        output.push('\n');
+    }
+
+    fn emit_label(&self, label: &str) {
+        let _ = writeln!(self.output.borrow_mut(), "{}:", label);
+    }
+
+    fn unsupported(&self, message: String) -> IRGenerateError {
+        IRGenerateError::Unsupported {
+            location: self.location.borrow().clone(),
+            message,
+        }
+    }
+
+    fn finish(&self, result: IRGenerateResult<()>) {
+        if let Err(error) = result {
+            self.error.borrow_mut().get_or_insert(error);
+        }
+    }
+
+    fn block_label(&self, name: &str) -> String {
+        format!("{}.{}", self.label_prefix.borrow(), mangle_symbol(name))
+    }
+
+    fn fresh_label(&self) -> String {
+        self.labels.set(self.labels.get() + 1);
+        format!("{}..{}", self.label_prefix.borrow(), self.labels.get())
+    }
+
+    fn emit_function(&self, symbol: &str, output: &mut String) -> IRGenerateResult<()> {
+        self.label_prefix.replace(format!(".L{}", symbol));
+        self.emit(format_args!(".type {}, %function", symbol));
+        self.emit(format_args!(".p2align 2"));
+        self.emit_label(symbol);
+        self.emit(format_args!("stp x29, x30, [sp, #-16]!"));
+        self.emit(format_args!("mov x29, sp"));
+        if self.frame.size > 0 {
+            self.emit_adjust_stack("sub", self.frame.size as u64);
+        }
+        self.emit_arguments();
+        for (position, (name, ir_basic_block)) in
+            self.ir_control_flow_graph.basic_blocks.iter().enumerate()
+        {
+            self.emit_label(&self.block_label(name));
+            self.terminated.set(false);
+            for (index, ir_instruction) in ir_basic_block.instructions.iter().enumerate() {
+                self.location.replace(IRLocation::Instruction {
+                    function: self.ir_function.map(|ir_function| ir_function.name.clone()),
+                    basic_block: name.clone(),
+                    index,
+                });
+                self.terminated.set(false);
+                ir_instruction.accept(self);
+            }
+            if !self.terminated.get() {
+                match self
+                    .ir_control_flow_graph
+                    .basic_blocks
+                    .get_index(position + 1)
+                {
+                    Some((next, _)) => {
+                        let result = self.emit_edge(name, next);
+                        self.finish(result);
+                    }
+                    None => self.emit_epilogue(),
+                }
+            }
+        }
+        self.emit(format_args!(".size {}, .-{}", symbol, symbol));
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        output.push_str(&self.output.borrow());
+        Ok(())
+    }
+
+    fn emit_epilogue(&self) {
+        self.emit(format_args!("mov sp, x29"));
+        self.emit(format_args!("ldp x29, x30, [sp], #16"));
+        self.emit(format_args!("ret"));
+    }
+
+    /// `sp = sp op amount`, for amounts beyond the 12-bit immediate too.
+    fn emit_adjust_stack(&self, op: &str, amount: u64) {
+        if amount < 4096 {
+            self.emit(format_args!("{} sp, sp, #{}", op, amount));
+        } else {
+            self.emit_immediate("x16", amount);
+            self.emit(format_args!("{} sp, sp, x16", op));
+        }
+    }
+
+    /// Materializes a 64-bit constant with `movz`/`movk`, or `movn` when it is a small negative.
+    fn emit_immediate(&self, register: &str, value: u64) {
+        if value < 0x10000 {
+            self.emit(format_args!("mov {}, #{}", register, value));
+            return;
+        }
+        if !value < 0x10000 {
+            self.emit(format_args!("mov {}, #{}", register, value as i64));
+            return;
+        }
+        let mut first = true;
+        for shift in (0..64).step_by(16) {
+            let chunk = (value >> shift) & 0xFFFF;
+            if chunk == 0 {
+                continue;
+            }
+            let mnemonic = if first { "movz" } else { "movk" };
+            self.emit(format_args!(
+                "{} {}, #{}, lsl #{}",
+                mnemonic, register, chunk, shift
+            ));
+            first = false;
+        }
+    }
+
+    /// A memory operand for a frame slot; offsets beyond the reach of `ldur`/`stur` go through
+    /// `x17`.
+    fn frame_slot(&self, offset: i64) -> String {
+        if (-256..256).contains(&offset) {
+            return format!("[x29, #{}]", offset);
+        }
+        self.emit_immediate("x17", offset.unsigned_abs());
+        let op = if offset < 0 { "sub" } else { "add" };
+        self.emit(format_args!("{} x17, x29, x17", op));
+        "[x17]".to_string()
+    }
+
+    /// Emits `instruction` with a frame slot as its memory operand.
+    fn emit_slot(&self, instruction: &str, offset: i64) {
+        let memory = self.frame_slot(offset);
+        self.emit(format_args!("{}, {}", instruction, memory));
+    }
+
+    /// Stores incoming arguments into the slots of the leading fields.
+    fn emit_arguments(&self) {
+        let mut integers = 0;
+        let mut floats = 0;
+        let mut stack = 0;
+        for (offset, value_type) in self.frame.fields.values().take(
+            self.ir_function
+                .map_or(0, |ir_function| ir_function.arguments_count),
+        ) {
+            let register = if value_type.is_floating_point() && floats < ARGUMENT_REGISTERS {
+                self.move_from_vector(*value_type, floats, "x9");
+                floats += 1;
+                "x9".to_string()
+            } else if !value_type.is_floating_point() && integers < ARGUMENT_REGISTERS {
+                integers += 1;
+                format!("x{}", integers - 1)
+            } else {
+                self.emit(format_args!("ldr x9, [x29, #{}]", 16 + stack * 8));
+                stack += 1;
+                "x9".to_string()
+            };
+            let memory = self.frame_slot(*offset);
+            self.store_from(*value_type, &register, &memory);
+        }
+    }
+
+    /// Loads a value of the given type from memory into `x0`, extending it to 64 bits.
+    fn load(&self, value_type: IRValueType, memory: &str) {
+        let (instruction, register) = match value_type {
+            IRValueType::Integer { bits: 1, .. }
+            | IRValueType::Integer {
+                bits: 8,
+                unsigned: true,
+            } => ("ldrb", "w0"),
+            IRValueType::Integer { bits: 8, .. } => ("ldrsb", "x0"),
+            IRValueType::Integer {
+                bits: 16,
+                unsigned: true,
+            } => ("ldrh", "w0"),
+            IRValueType::Integer { bits: 16, .. } => ("ldrsh", "x0"),
+            IRValueType::Integer {
+                bits: 32,
+                unsigned: false,
+            } => ("ldrsw", "x0"),
+            IRValueType::Integer { bits: 32, .. } | IRValueType::Float => ("ldr", "w0"),
+            _ => ("ldr", "x0"),
+        };
+        self.emit(format_args!("{} {}, {}", instruction, register, memory));
+    }
+
+    /// Stores `x0` to memory with the width of the given type.
+    fn store(&self, value_type: IRValueType, memory: &str) {
+        self.store_from(value_type, "x0", memory);
+    }
+
+    fn store_from(&self, value_type: IRValueType, register: &str, memory: &str) {
+        let size = value_type.size();
+        if size == 0 {
+            return;
+        }
+        if let IRValueType::Integer { bits: 1, .. } = value_type {
+            self.emit(format_args!("and {}, {}, #1", w(register), w(register)));
+        }
+        let instruction = match size {
+            1 => "strb",
+            2 => "strh",
+            _ => "str",
+        };
+        self.emit(format_args!(
+            "{} {}, {}",
+            instruction,
+            sized_register(register, size),
+            memory
+        ));
+    }
+
+    /// Re-extends the low bits of `x0` according to the type.
+    fn extend(&self, value_type: IRValueType) {
+        match value_type {
+            IRValueType::Integer { bits: 1, .. } => self.emit(format_args!("and x0, x0, #1")),
+            IRValueType::Integer { bits: 8, unsigned } => {
+                if unsigned {
+                    self.emit(format_args!("uxtb w0, w0"))
+                } else {
+                    self.emit(format_args!("sxtb x0, w0"))
+                }
+            }
+            IRValueType::Integer { bits: 16, unsigned } => {
+                if unsigned {
+                    self.emit(format_args!("uxth w0, w0"))
+                } else {
+                    self.emit(format_args!("sxth x0, w0"))
+                }
+            }
+            IRValueType::Integer {
+                bits: 32,
+                unsigned: true,
+            }
+            | IRValueType::Float => self.emit(format_args!("mov w0, w0")),
+            IRValueType::Integer {
+                bits: 32,
+                unsigned: false,
+            } => self.emit(format_args!("sxtw x0, w0")),
+            _ => {}
+        }
+    }
+
+    fn zero_extend(&self, bits: u32) {
+        self.extend(IRValueType::Integer {
+            bits,
+            unsigned: true,
+        });
+    }
+
+    fn sign_extend(&self, bits: u32) {
+        self.extend(IRValueType::Integer {
+            bits,
+            unsigned: false,
+        });
+    }
+
+    fn slot(&self, name: &str) -> IRGenerateResult<(i64, IRValueType)> {
+        self.frame
+            .register(name)
+            .ok_or_else(|| self.unsupported(format!("register %{} without a known type", name)))
+    }
+
+    fn store_register(&self, target: &IRVirtualRegister) -> IRGenerateResult<()> {
+        let (offset, value_type) = self.slot(&target.name)?;
+        let memory = self.frame_slot(offset);
+        self.store(value_type, &memory);
+        Ok(())
+    }
+
+    fn symbol_address(&self, register: &str, symbol: &str, defined: bool) {
+        if defined {
+            self.emit(format_args!("adrp {}, {}", register, symbol));
+            self.emit(format_args!(
+                "add {}, {}, :lo12:{}",
+                register, register, symbol
+            ));
+        } else {
+            self.emit(format_args!("adrp {}, :got:{}", register, symbol));
+            self.emit(format_args!(
+                "ldr {}, [{}, :got_lo12:{}]",
+                register, register, symbol
+            ));
+        }
+    }
+
+    /// Loads an operand into `x0`; only `x16` and `x17` are used on the way.
+    fn load_operand(&self, operand: &dyn IROperand) -> IRGenerateResult<()> {
+        match IROperandValue::of(operand) {
+            IROperandValue::Register(name) => {
+                let (offset, value_type) = self.slot(&name)?;
+                let memory = self.frame_slot(offset);
+                self.load(value_type, &memory);
+            }
+            IROperandValue::Constant(index) => {
+                let (entry, data) = constant(self.ir_module, index)
+                    .ok_or_else(|| self.unsupported(format!("constant ${}", index)))?;
+                let label = constant_label(index as usize);
+                match data {
+                    IRConstantData::String(_) => self.symbol_address("x0", &label, true),
+                    _ => {
+                        self.symbol_address("x16", &label, true);
+                        self.load(IRValueType::of(entry._type.as_ref()), "[x16]");
+                    }
+                }
+            }
+            IROperandValue::FieldAddress(name) => {
+                let (offset, _) = self
+                    .frame
+                    .fields
+                    .get(&name)
+                    .ok_or_else(|| self.unsupported(format!("field '{}'", name)))?;
+                if -offset < 4096 {
+                    self.emit(format_args!("sub x0, x29, #{}", -offset));
+                } else {
+                    self.emit_immediate("x0", offset.unsigned_abs());
+                    self.emit(format_args!("sub x0, x29, x0"));
+                }
+            }
+            IROperandValue::GlobalDataAddress(name) => {
+                let defined = self
+                    .ir_module
+                    .global_data_section
+                    .data
+                    .iter()
+                    .any(|data| data.name == name);
+                self.symbol_address("x0", &mangle_symbol(&name), defined);
+            }
+            IROperandValue::FunctionAddress(name) => {
+                let defined = self.ir_module.functions.contains_key(&name);
+                self.symbol_address("x0", &mangle_symbol(&name), defined);
+            }
+            _ => return Err(self.unsupported(format!("operand {}", operand))),
+        }
+        Ok(())
+    }
+
+    /// Loads `value` into `x1` and `operand` into `x0`.
+    fn load_pair(&self, operand: &dyn IROperand, value: &dyn IROperand) -> IRGenerateResult<()> {
+        self.load_operand(value)?;
+        self.emit(format_args!("mov x1, x0"));
+        self.load_operand(operand)
+    }
+
+    /// Copies the phi inputs of block `to` for the edge leaving block `from`.
+    fn emit_edge(&self, from: &str, to: &str) -> IRGenerateResult<()> {
+        for phi_move in phi_moves(self.ir_control_flow_graph, from, to) {
+            self.load_operand(phi_move.operand.as_ref())?;
+            let memory = self.frame_slot(self.frame.phi_shadows[&phi_move.target]);
+            self.store(phi_move.value_type, &memory);
+        }
+        Ok(())
+    }
+
+    fn current_block(&self) -> String {
+        match &*self.location.borrow() {
+            IRLocation::Instruction { basic_block, .. } => basic_block.clone(),
+            _ => String::new(),
+        }
+    }
+
+    fn emit_jump(&self, target: &str) -> IRGenerateResult<()> {
+        if !self.ir_control_flow_graph.basic_blocks.contains_key(target) {
+            return Err(self.unsupported(format!("jump to missing block '{}'", target)));
+        }
+        self.emit_edge(&self.current_block(), target)?;
+        self.emit(format_args!("b {}", self.block_label(target)));
+        Ok(())
+    }
+
+    /// Moves the bits of a float or double from a general purpose register to `s<n>`/`d<n>`.
+    fn move_to_vector(&self, value_type: IRValueType, register: &str, vector: usize) {
+        match value_type {
+            IRValueType::Float => self.emit(format_args!("fmov s{}, {}", vector, w(register))),
+            _ => self.emit(format_args!("fmov d{}, {}", vector, register)),
+        }
+    }
+
+    fn move_from_vector(&self, value_type: IRValueType, vector: usize, register: &str) {
+        match value_type {
+            IRValueType::Float => self.emit(format_args!("fmov {}, s{}", w(register), vector)),
+            _ => self.emit(format_args!("fmov {}, d{}", register, vector)),
+        }
+    }
+
+    /// Computes `x0 op x1` into `x0`. Clobbers `x2`, `v0`, `v1` and, for floating point
+    /// remainders, every caller-saved register.
+    fn compute(&self, arithmetic: IRArithmetic, value_type: IRValueType) -> IRGenerateResult<()> {
+        use IRCalculateOperator::*;
+        match (value_type, arithmetic) {
+            (IRValueType::Integer { bits, unsigned }, IRArithmetic::Binary(operator)) => {
+                let division = if unsigned { "udiv" } else { "sdiv" };
+                match operator {
+                    ADD => self.emit(format_args!("add x0, x0, x1")),
+                    SUB => self.emit(format_args!("sub x0, x0, x1")),
+                    MUL => self.emit(format_args!("mul x0, x0, x1")),
+                    AND => self.emit(format_args!("and x0, x0, x1")),
+                    OR => self.emit(format_args!("orr x0, x0, x1")),
+                    XOR => self.emit(format_args!("eor x0, x0, x1")),
+                    DIV => self.emit(format_args!("{} x0, x0, x1", division)),
+                    MOD => {
+                        self.emit(format_args!("{} x2, x0, x1", division));
+                        self.emit(format_args!("msub x0, x2, x1, x0"));
+                    }
+                    SHL | SHR | USHR => {
+                        // Shift amounts are taken modulo the width of the type.
+                        if bits == 1 {
+                            self.emit(format_args!("mov x1, xzr"));
+                        } else {
+                            self.emit(format_args!("and x1, x1, #{}", bits - 1));
+                        }
+                        match operator {
+                            SHL => self.emit(format_args!("lsl x0, x0, x1")),
+                            SHR if !unsigned => self.emit(format_args!("asr x0, x0, x1")),
+                            _ => {
+                                self.zero_extend(bits);
+                                self.emit(format_args!("lsr x0, x0, x1"));
+                            }
+                        }
+                    }
+                }
+                self.extend(value_type);
+            }
+            (IRValueType::Integer { .. }, IRArithmetic::Not) => {
+                self.emit(format_args!("mvn x0, x0"));
+                self.extend(value_type);
+            }
+            (IRValueType::Integer { .. }, IRArithmetic::Negate) => {
+                self.emit(format_args!("neg x0, x0"));
+                self.extend(value_type);
+            }
+            (IRValueType::Float, IRArithmetic::Negate) => {
+                self.emit(format_args!("eor w0, w0, #0x80000000"))
+            }
+            (IRValueType::Double, IRArithmetic::Negate) => {
+                self.emit(format_args!("eor x0, x0, #0x8000000000000000"))
+            }
+            (IRValueType::Float | IRValueType::Double, IRArithmetic::Binary(operator)) => {
+                let prefix = if value_type == IRValueType::Float {
+                    "s"
+                } else {
+                    "d"
+                };
+                self.move_to_vector(value_type, "x0", 0);
+                self.move_to_vector(value_type, "x1", 1);
+                let instruction = match operator {
+                    ADD => "fadd",
+                    SUB => "fsub",
+                    MUL => "fmul",
+                    DIV => "fdiv",
+                    MOD => {
+                        let function = if value_type == IRValueType::Float {
+                            "fmodf"
+                        } else {
+                            "fmod"
+                        };
+                        self.emit(format_args!("bl {}", function));
+                        self.move_from_vector(value_type, 0, "x0");
+                        return Ok(());
+                    }
+                    _ => {
+                        return Err(self.unsupported(format!("{} on floating point", operator)));
+                    }
+                };
+                self.emit(format_args!(
+                    "{} {}0, {}0, {}1",
+                    instruction, prefix, prefix, prefix
+                ));
+                self.move_from_vector(value_type, 0, "x0");
+            }
+            (value_type, arithmetic) => {
+                return Err(self.unsupported(format!("{:?} on {:?}", arithmetic, value_type)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Atomically replaces the value at `address` with `value op rhs` through a compare-and-swap
+    /// loop, leaving the new value in `x0`. The operation runs outside of the exclusive section
+    /// since it may call `fmod`.
+    fn emit_atomic_update(
+        &self,
+        arithmetic: IRArithmetic,
+        value_type: IRValueType,
+        address: &dyn IROperand,
+        rhs: Option<&dyn IROperand>,
+        one: bool,
+    ) -> IRGenerateResult<()> {
+        let [address_slot, rhs_slot, expected_slot] = self.frame.scratch[..] else {
+            unreachable!()
+        };
+        let size = value_type.size();
+        let suffix = exclusive_suffix(size);
+        self.load_operand(address)?;
+        self.emit_slot("str x0", address_slot);
+        if let Some(rhs) = rhs {
+            self.load_operand(rhs)?;
+            self.emit_slot("str x0", rhs_slot);
+        } else if one {
+            self.load_one(value_type, "x1");
+            self.emit_slot("str x1", rhs_slot);
+        }
+        self.emit_slot("ldr x9", address_slot);
+        self.load(value_type, "[x9]");
+        self.emit_label("1");
+        self.extend(value_type);
+        self.emit_slot("str x0", expected_slot);
+        self.emit_slot("ldr x1", rhs_slot);
+        self.compute(arithmetic, value_type)?;
+        self.emit(format_args!("mov x10, x0"));
+        self.emit_slot("ldr x11", expected_slot);
+        self.emit_slot("ldr x9", address_slot);
+        self.emit_label("2");
+        self.emit(format_args!(
+            "ldaxr{} {}, [x9]",
+            suffix,
+            sized_register("x12", size)
+        ));
+        match size {
+            1 => self.emit(format_args!("cmp w12, w11, uxtb")),
+            2 => self.emit(format_args!("cmp w12, w11, uxth")),
+            _ => self.emit(format_args!(
+                "cmp {}, {}",
+                sized_register("x12", size),
+                sized_register("x11", size)
+            )),
+        }
+        self.emit(format_args!("b.ne 3f"));
+        self.emit(format_args!(
+            "stlxr{} w13, {}, [x9]",
+            suffix,
+            sized_register("x10", size)
+        ));
+        self.emit(format_args!("cbnz w13, 2b"));
+        self.emit(format_args!("b 4f"));
+        self.emit_label("3");
+        self.emit(format_args!("clrex"));
+        self.emit(format_args!("mov x0, x12"));
+        self.emit(format_args!("b 1b"));
+        self.emit_label("4");
+        self.emit(format_args!("mov x0, x10"));
+        Ok(())
+    }
+
+    fn load_one(&self, value_type: IRValueType, register: &str) {
+        match value_type {
+            IRValueType::Float => self.emit_immediate(register, 1f32.to_bits() as u64),
+            IRValueType::Double => self.emit_immediate(register, 1f64.to_bits()),
+            _ => self.emit(format_args!("mov {}, #1", register)),
+        }
+    }
+
+    fn emit_unary(
+        &self,
+        arithmetic: IRArithmetic,
+        is_atomic: bool,
+        value_type: IRValueType,
+        operand: &dyn IROperand,
+        target: &IRVirtualRegister,
+    ) -> IRGenerateResult<()> {
+        if is_atomic {
+            self.emit_atomic_update(arithmetic, value_type, operand, None, false)?;
+        } else {
+            self.load_operand(operand)?;
+            self.compute(arithmetic, value_type)?;
+        }
+        self.store_register(target)
+    }
+
+    fn emit_step(
+        &self,
+        operator: IRCalculateOperator,
+        value_type: IRValueType,
+        operand: &dyn IROperand,
+        target: Option<&IRVirtualRegister>,
+    ) -> IRGenerateResult<()> {
+        let arithmetic = IRArithmetic::Binary(operator);
+        match (target, value_type) {
+            (Some(target), _) => {
+                self.load_operand(operand)?;
+                self.load_one(value_type, "x1");
+                self.compute(arithmetic, value_type)?;
+                self.store_register(target)
+            }
+            (None, IRValueType::Integer { .. }) => {
+                let size = value_type.size();
+                let suffix = exclusive_suffix(size);
+                let register = sized_register("x12", size);
+                let mnemonic = if operator == IRCalculateOperator::ADD {
+                    "add"
+                } else {
+                    "sub"
+                };
+                self.load_operand(operand)?;
+                self.emit(format_args!("mov x9, x0"));
+                self.emit_label("1");
+                self.emit(format_args!("ldaxr{} {}, [x9]", suffix, register));
+                self.emit(format_args!("{} {}, {}, #1", mnemonic, register, register));
+                self.emit(format_args!("stlxr{} w13, {}, [x9]", suffix, register));
+                self.emit(format_args!("cbnz w13, 1b"));
+                Ok(())
+            }
+            (None, _) => self.emit_atomic_update(arithmetic, value_type, operand, None, true),
+        }
+    }
+
+    /// Branches to `label` if `x0 cond x1` holds.
+    fn emit_branch(&self, condition: IRCondition, value_type: IRValueType, label: &str) {
+        if value_type.is_floating_point() {
+            let prefix = if value_type == IRValueType::Float {
+                "s"
+            } else {
+                "d"
+            };
+            self.move_to_vector(value_type, "x0", 0);
+            match condition {
+                IRCondition::IfTrue | IRCondition::IfFalse => {
+                    self.emit(format_args!("fcmp {}0, #0.0", prefix))
+                }
+                _ => {
+                    self.move_to_vector(value_type, "x1", 1);
+                    self.emit(format_args!("fcmp {}0, {}1", prefix, prefix));
+                }
+            }
+            // These are false when either side is NaN, except for `ne`.
+            let code = match condition {
+                IRCondition::Less => "mi",
+                IRCondition::LessEqual => "ls",
+                IRCondition::Greater => "gt",
+                IRCondition::GreaterEqual => "ge",
+                IRCondition::Equal | IRCondition::IfFalse => "eq",
+                IRCondition::NotEqual | IRCondition::IfTrue => "ne",
+            };
+            self.emit(format_args!("b.{} {}", code, label));
+            return;
+        }
+        let code = match (condition, value_type.is_unsigned()) {
+            (IRCondition::IfTrue, _) => {
+                self.emit(format_args!("cbnz x0, {}", label));
+                return;
+            }
+            (IRCondition::IfFalse, _) => {
+                self.emit(format_args!("cbz x0, {}", label));
+                return;
+            }
+            (IRCondition::Equal, _) => "eq",
+            (IRCondition::NotEqual, _) => "ne",
+            (IRCondition::Less, false) => "lt",
+            (IRCondition::LessEqual, false) => "le",
+            (IRCondition::Greater, false) => "gt",
+            (IRCondition::GreaterEqual, false) => "ge",
+            (IRCondition::Less, true) => "lo",
+            (IRCondition::LessEqual, true) => "ls",
+            (IRCondition::Greater, true) => "hi",
+            (IRCondition::GreaterEqual, true) => "hs",
+        };
+        self.emit(format_args!("cmp x0, x1"));
+        self.emit(format_args!("b.{} {}", code, label));
+    }
+
+    fn emit_conditional_jump(
+        &self,
+        ir_conditional_jump: &IRConditionalJump,
+    ) -> IRGenerateResult<()> {
+        let value_type = IRValueType::of(ir_conditional_jump._type.as_ref());
+        if let Some(operand2) = ir_conditional_jump.operand2.as_ref() {
+            self.load_operand(operand2.as_ref())?;
+            self.emit(format_args!("mov x1, x0"));
+        }
+        self.load_operand(ir_conditional_jump.operand1.as_ref())?;
+        if ir_conditional_jump.is_atomic {
+            let size = value_type.size();
+            self.emit(format_args!("mov x9, x0"));
+            self.emit(format_args!(
+                "ldar{} {}, [x9]",
+                exclusive_suffix(size),
+                sized_register("x0", size)
+            ));
+            self.extend(value_type);
+        }
+        let target = &ir_conditional_jump.target;
+        if !self.ir_control_flow_graph.basic_blocks.contains_key(target) {
+            return Err(self.unsupported(format!("jump to missing block '{}'", target)));
+        }
+        if phi_moves(self.ir_control_flow_graph, &self.current_block(), target).is_empty() {
+            self.emit_branch(
+                ir_conditional_jump.condition,
+                value_type,
+                &self.block_label(target),
+            );
+            return Ok(());
+        }
+        let taken = self.fresh_label();
+        let skipped = self.fresh_label();
+        self.emit_branch(ir_conditional_jump.condition, value_type, &taken);
+        self.emit(format_args!("b {}", skipped));
+        self.emit_label(&taken);
+        self.emit_jump(target)?;
+        self.emit_label(&skipped);
+        Ok(())
+    }
+
+    fn emit_invoke(&self, ir_invoke: &IRInvoke) -> IRGenerateResult<()> {
+        if ir_invoke.argument_types.len() != ir_invoke.arguments.len() {
+            return Err(self.unsupported("invoke with mismatched argument types".to_string()));
+        }
+        let mut registers = vec![];
+        let mut stack = vec![];
+        let mut floats = 0;
+        let mut integers = 0;
+        for (argument_type, argument) in ir_invoke
+            .argument_types
+            .iter()
+            .zip(ir_invoke.arguments.iter())
+        {
+            let value_type = IRValueType::of(argument_type.as_ref());
+            if value_type.is_floating_point() && floats < ARGUMENT_REGISTERS {
+                registers.push((value_type, floats, argument));
+                floats += 1;
+            } else if !value_type.is_floating_point() && integers < ARGUMENT_REGISTERS {
+                registers.push((value_type, integers, argument));
+                integers += 1;
+            } else {
+                stack.push(argument);
+            }
+        }
+        let callee = match IROperandValue::of(ir_invoke.address.as_ref()) {
+            IROperandValue::FunctionAddress(name) => Some(mangle_symbol(&name)),
+            _ => {
+                self.load_operand(ir_invoke.address.as_ref())?;
+                self.emit(format_args!("mov x9, x0"));
+                None
+            }
+        };
+        let stack_size = (stack.len() as u64 * 8).next_multiple_of(16);
+        if stack_size > 0 {
+            self.emit_adjust_stack("sub", stack_size);
+        }
+        for (index, argument) in stack.iter().enumerate() {
+            self.load_operand(argument.as_ref())?;
+            self.emit(format_args!("str x0, [sp, #{}]", index * 8));
+        }
+        // Loading goes through `x0`, so `x0` itself is filled last.
+        for (value_type, register, argument) in registers.iter().rev() {
+            self.load_operand(argument.as_ref())?;
+            if value_type.is_floating_point() {
+                self.move_to_vector(*value_type, "x0", *register);
+            } else if *register != 0 {
+                self.emit(format_args!("mov x{}, x0", register));
+            }
+        }
+        match callee {
+            Some(symbol) => self.emit(format_args!("bl {}", symbol)),
+            None => self.emit(format_args!("blr x9")),
+        }
+        if stack_size > 0 {
+            self.emit_adjust_stack("add", stack_size);
+        }
+        if let Some(target) = ir_invoke.target.as_ref() {
+            let return_type = IRValueType::of(ir_invoke.return_type.as_ref());
+            if return_type.is_floating_point() {
+                self.move_from_vector(return_type, 0, "x0");
+            }
+            self.store_register(target)?;
+        }
+        Ok(())
+    }
+
+    fn emit_type_cast(&self, ir_type_cast: &IRTypeCast) -> IRGenerateResult<()> {
+        let from = IRValueType::of(ir_type_cast.original_type.as_ref());
+        let to = IRValueType::of(ir_type_cast.target_type.as_ref());
+        let prefix = |value_type| {
+            if value_type == IRValueType::Float {
+                "s"
+            } else {
+                "d"
+            }
+        };
+        self.load_operand(ir_type_cast.source.as_ref())?;
+        self.extend(from);
+        let bits = match from {
+            IRValueType::Integer { bits, .. } => bits,
+            _ => 64,
+        };
+        match ir_type_cast.kind {
+            IRTypeCastKind::ZeroExtend => self.zero_extend(bits),
+            IRTypeCastKind::SignExtend => self.sign_extend(bits),
+            IRTypeCastKind::Truncate => {}
+            IRTypeCastKind::IntToFloat => {
+                let instruction = if from.is_unsigned() { "ucvtf" } else { "scvtf" };
+                self.emit(format_args!("{} {}0, x0", instruction, prefix(to)));
+                self.move_from_vector(to, 0, "x0");
+            }
+            IRTypeCastKind::FloatToInt => {
+                self.move_to_vector(from, "x0", 0);
+                self.emit(format_args!("fcvtzs x0, {}0", prefix(from)));
+            }
+            IRTypeCastKind::FloatExtend | IRTypeCastKind::FloatTruncate => {
+                if from != to {
+                    self.move_to_vector(from, "x0", 0);
+                    self.emit(format_args!("fcvt {}0, {}0", prefix(to), prefix(from)));
+                    self.move_from_vector(to, 0, "x0");
+                }
+            }
+        }
+        self.store_register(&ir_type_cast.target)
+    }
+
+    fn emit_asm(&self, ir_asm: &IRAsm) -> IRGenerateResult<()> {
+        let mut bindings = vec![];
+        for (resource, name) in ir_asm.resources.iter().zip(ir_asm.names.iter()) {
+            let register = asm_register(name)
+                .ok_or_else(|| self.unsupported(format!("asm register '{}'", name)))?;
+            bindings.push((register, resource));
+        }
+        // x0 is the scratch register for loading, so it is bound last.
+        bindings.sort_by_key(|(register, _)| register == "x0");
+        let saved = bindings
+            .iter()
+            .map(|(register, _)| register.clone())
+            .filter(|register| register[1..].parse::<u32>().is_ok_and(|n| n >= 19))
+            .collect::<Vec<_>>();
+        for register in saved.iter() {
+            self.emit(format_args!("str {}, [sp, #-16]!", register));
+        }
+        for (register, resource) in bindings.iter() {
+            self.load_operand(resource.as_ref())?;
+            if register != "x0" {
+                self.emit(format_args!("mov {}, x0", register));
+            }
+        }
+        // Front ends write multi-line code with `\n` escapes as often as with real newlines.
+        for line in ir_asm.code.replace("\\n", "\n").lines() {
+            self.emit(format_args!("{}", line.trim()));
+        }
+        for register in saved.iter().rev() {
+            self.emit(format_args!("ldr {}, [sp], #16", register));
+        }
+        Ok(())
+    }
+}
+
+impl IRVisitor for IRAArch64Emitter<'_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_goto(&self, ir_goto: &IRGoto) {
+        self.terminated.set(true);
+        self.finish(self.emit_jump(&ir_goto.target));
+    }
+    fn visit_conditional_jump(&self, ir_conditional_jump: &IRConditionalJump) {
+        self.finish(self.emit_conditional_jump(ir_conditional_jump));
+    }
+    fn visit_return(&self, ir_return: &IRReturn) {
+        self.terminated.set(true);
+        if let Some(operand) = ir_return.operand.as_ref() {
+            self.finish(self.load_operand(operand.as_ref()));
+            let return_type = self.ir_function.map_or(IRValueType::Void, |ir_function| {
+                IRValueType::of(ir_function.return_type.as_ref())
+            });
+            if return_type.is_floating_point() {
+                self.move_to_vector(return_type, "x0", 0);
+            }
+        }
+        self.emit_epilogue();
+    }
+    fn visit_calculate(&self, ir_calculate: &IRCalculate) {
+        let value_type = IRValueType::of(ir_calculate._type.as_ref());
+        let arithmetic = IRArithmetic::Binary(ir_calculate.operator);
+        let result = (|| {
+            if ir_calculate.is_atomic {
+                self.emit_atomic_update(
+                    arithmetic,
+                    value_type,
+                    ir_calculate.operand1.as_ref(),
+                    Some(ir_calculate.operand2.as_ref()),
+                    false,
+                )?;
+            } else {
+                self.load_pair(
+                    ir_calculate.operand1.as_ref(),
+                    ir_calculate.operand2.as_ref(),
+                )?;
+                self.compute(arithmetic, value_type)?;
+            }
+            self.store_register(&ir_calculate.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_not(&self, ir_not: &IRNot) {
+        self.finish(self.emit_unary(
+            IRArithmetic::Not,
+            ir_not.is_atomic,
+            IRValueType::of(ir_not._type.as_ref()),
+            ir_not.operand.as_ref(),
+            &ir_not.target,
+        ));
+    }
+    fn visit_negate(&self, ir_negate: &IRNegate) {
+        self.finish(self.emit_unary(
+            IRArithmetic::Negate,
+            ir_negate.is_atomic,
+            IRValueType::of(ir_negate._type.as_ref()),
+            ir_negate.operand.as_ref(),
+            &ir_negate.target,
+        ));
+    }
+    fn visit_malloc(&self, ir_malloc: &IRMalloc) {
+        let result = (|| {
+            self.load_operand(ir_malloc.size.as_ref())?;
+            self.emit(format_args!("bl malloc"));
+            self.store_register(&ir_malloc.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_free(&self, ir_free: &IRFree) {
+        let result = self.load_operand(ir_free.ptr.as_ref());
+        self.emit(format_args!("bl free"));
+        self.finish(result);
+    }
+    fn visit_realloc(&self, ir_realloc: &IRRealloc) {
+        let result = (|| {
+            self.load_pair(ir_realloc.ptr.as_ref(), ir_realloc.size.as_ref())?;
+            self.emit(format_args!("bl realloc"));
+            self.store_register(&ir_realloc.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_get(&self, ir_get: &IRGet) {
+        let result = (|| {
+            self.load_operand(ir_get.address.as_ref())?;
+            self.emit(format_args!("mov x9, x0"));
+            self.load(IRValueType::of(ir_get._type.as_ref()), "[x9]");
+            self.store_register(&ir_get.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_set(&self, ir_set: &IRSet) {
+        let result = (|| {
+            self.load_operand(ir_set.address.as_ref())?;
+            self.emit(format_args!("mov x9, x0"));
+            self.load_operand(ir_set.value.as_ref())?;
+            self.store(IRValueType::of(ir_set._type.as_ref()), "[x9]");
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_set_virtual_register(&self, ir_set_virtual_register: &IRSetVirtualRegister) {
+        let target = &ir_set_virtual_register.target;
+        let result = (|| {
+            match IROperandValue::of(ir_set_virtual_register.source.as_ref()) {
+                IROperandValue::Phi(ir_phi) => {
+                    let memory = self.frame_slot(self.frame.phi_shadows[&target.name]);
+                    self.load(IRValueType::of(ir_phi._type.as_ref()), &memory)
+                }
+                _ => self.load_operand(ir_set_virtual_register.source.as_ref())?,
+            }
+            self.store_register(target)
+        })();
+        self.finish(result);
+    }
+    fn visit_invoke(&self, ir_invoke: &IRInvoke) {
+        self.finish(self.emit_invoke(ir_invoke));
+    }
+    fn visit_no_operate(&self, _ir_no_operate: &IRNoOperate) {
+        self.emit(format_args!("nop"));
+    }
+    fn visit_increase(&self, ir_increase: &IRIncrease) {
+        self.finish(self.emit_step(
+            IRCalculateOperator::ADD,
+            IRValueType::of(ir_increase._type.as_ref()),
+            ir_increase.operand.as_ref(),
+            ir_increase.target.as_deref(),
+        ));
+    }
+    fn visit_decrease(&self, ir_decrease: &IRDecrease) {
+        self.finish(self.emit_step(
+            IRCalculateOperator::SUB,
+            IRValueType::of(ir_decrease._type.as_ref()),
+            ir_decrease.operand.as_ref(),
+            ir_decrease.target.as_deref(),
+        ));
+    }
+    fn visit_stack_allocate(&self, ir_stack_allocate: &IRStackAllocate) {
+        let result = (|| {
+            self.load_operand(ir_stack_allocate.size.as_ref())?;
+            self.emit(format_args!("add x0, x0, #15"));
+            self.emit(format_args!("and x0, x0, #-16"));
+            self.emit(format_args!("sub sp, sp, x0"));
+            self.emit(format_args!("mov x0, sp"));
+            self.store_register(&ir_stack_allocate.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_type_cast(&self, ir_type_cast: &IRTypeCast) {
+        self.finish(self.emit_type_cast(ir_type_cast));
+    }
+    fn visit_asm(&self, ir_asm: &IRAsm) {
+        self.finish(self.emit_asm(ir_asm));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parser::parse_module;
+
+    const LOOP: &str = "\
+constant $0 = i64 0
+constant $1 = i64 1
+function i64 sum(i64 n) {
+entry:
+    %pn = `field_address([n], [])
+    %n = get i64, %pn
+    goto loop
+loop:
+    %i = phi i64 [entry, $0], [loop, %next_i]
+    %total = phi i64 [entry, $0], [loop, %next_total]
+    %next_total = add i64 %total, %i
+    %next_i = add i64 %i, $1
+    conditional_jump i64 le, %next_i, %n, #loop
+done:
+    %r = invoke i64 `function_address([twice], []), [i64, %next_total]
+    return %r
+}
+function i64 twice(i64 x) {
+entry:
+    %px = `field_address([x], [])
+    %x = get i64, %px
+    %r = add i64 %x, %x
+    return %r
+}
+";
+
+    #[test]
+    fn phis_are_copied_on_each_edge_and_calls_use_bl() {
+        assert_eq!(
+            generate_assembly(&parse_module(LOOP).unwrap()).unwrap(),
+            "\
+\t.section .rodata
+\t.balign 8
+.LC0:
+\t.quad 0
+\t.balign 8
+.LC1:
+\t.quad 1
+\t.section .text.sum,\"ax\",%progbits
+\t.globl sum
+\t.type sum, %function
+\t.p2align 2
+sum:
+\tstp x29, x30, [sp, #-16]!
+\tmov x29, sp
+\tsub sp, sp, #112
+\tstr x0, [x29, #-8]
+.Lsum.entry:
+\tsub x0, x29, #8
+\tstr x0, [x29, #-16]
+\tldr x0, [x29, #-16]
+\tmov x9, x0
+\tldr x0, [x9]
+\tstr x0, [x29, #-24]
+\tadrp x16, .LC0
+\tadd x16, x16, :lo12:.LC0
+\tldr x0, [x16]
+\tstr x0, [x29, #-72]
+\tadrp x16, .LC0
+\tadd x16, x16, :lo12:.LC0
+\tldr x0, [x16]
+\tstr x0, [x29, #-80]
+\tb .Lsum.loop
+.Lsum.loop:
+\tldr x0, [x29, #-72]
+\tstr x0, [x29, #-32]
+\tldr x0, [x29, #-80]
+\tstr x0, [x29, #-40]
+\tldr x0, [x29, #-32]
+\tmov x1, x0
+\tldr x0, [x29, #-40]
+\tadd x0, x0, x1
+\tstr x0, [x29, #-48]
+\tadrp x16, .LC1
+\tadd x16, x16, :lo12:.LC1
+\tldr x0, [x16]
+\tmov x1, x0
+\tldr x0, [x29, #-32]
+\tadd x0, x0, x1
+\tstr x0, [x29, #-56]
+\tldr x0, [x29, #-24]
+\tmov x1, x0
+\tldr x0, [x29, #-56]
+\tcmp x0, x1
+\tb.le .Lsum..1
+\tb .Lsum..2
+.Lsum..1:
+\tldr x0, [x29, #-56]
+\tstr x0, [x29, #-72]
+\tldr x0, [x29, #-48]
+\tstr x0, [x29, #-80]
+\tb .Lsum.loop
+.Lsum..2:
+.Lsum.done:
+\tldr x0, [x29, #-48]
+\tbl twice
+\tstr x0, [x29, #-64]
+\tldr x0, [x29, #-64]
+\tmov sp, x29
+\tldp x29, x30, [sp], #16
+\tret
+\t.size sum, .-sum
+\t.section .text.twice,\"ax\",%progbits
+\t.globl twice
+\t.type twice, %function
+\t.p2align 2
+twice:
+\tstp x29, x30, [sp, #-16]!
+\tmov x29, sp
+\tsub sp, sp, #64
+\tstr x0, [x29, #-8]
+.Ltwice.entry:
+\tsub x0, x29, #8
+\tstr x0, [x29, #-16]
+\tldr x0, [x29, #-16]
+\tmov x9, x0
+\tldr x0, [x9]
+\tstr x0, [x29, #-24]
+\tldr x0, [x29, #-24]
+\tmov x1, x0
+\tldr x0, [x29, #-24]
+\tadd x0, x0, x1
+\tstr x0, [x29, #-32]
+\tldr x0, [x29, #-32]
+\tmov sp, x29
+\tldp x29, x30, [sp], #16
+\tret
+\t.size twice, .-twice
+\t.section .note.GNU-stack,\"\",%progbits
+"
+        );
+    }
+}
@@ -0,0 +1,1408 @@
+//! A single C11 translation unit, for targets without a native backend.
+//!
+//! Virtual registers become locals declared at the top of each function and basic blocks become
+//! labels reached with `goto`. Fields are addressable locals, arguments are copied into them on
+//! entry. Phis read a shadow local written on every incoming edge, so phis of one block see each
+//! other's old values. Atomic instructions use `<stdatomic.h>`, stack allocations are heap blocks
+//! released when the function returns, and loads and stores go through `memcpy` so that the
+//! output does not depend on strict aliasing.
+
+use crate::backend::{
+    IRDataItem, IRGenerateError, IRGenerateResult, IROperandValue, IRTarget, IRValueType, constant,
+    global_data_layout, lower_for_target, phi_moves,
+};
+use crate::ir::base::{IRCondition, IRControlFlowGraph, IRFunction, IRNode};
+use crate::ir::instruction::{
+    IRAsm, IRCalculate, IRCalculateOperator, IRConditionalJump, IRDecrease, IRFree, IRGet, IRGoto,
+    IRIncrease, IRInvoke, IRMalloc, IRNegate, IRNoOperate, IRNot, IRRealloc, IRReturn, IRSet,
+    IRSetVirtualRegister, IRStackAllocate, IRTypeCast, IRTypeCastKind,
+};
+use crate::ir::operand::{IROperand, IRVirtualRegister};
+use crate::ir::type_check::{IRFunctionTypes, infer_register_types, operand_type};
+use crate::ir::types::{
+    IRArrayType, IRIntegerType, IRIntegerTypeSize, IRPointerType, IRStructureType, IRType,
+    IRTypeKind, IRVoidType,
+};
+use crate::ir::verify::IRLocation;
+use crate::ir::{IRConstantData, IRModule, IRVisitor};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::fmt::{self, Write};
+
+const GLOBAL_INIT_FUNCTION: &str = "lg_global_init";
+
+const PRELUDE: &str = "\
+#include <math.h>
+#include <stdatomic.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+static void *lg_stack_allocate(void **frame, size_t size) {
+    void **block = malloc(sizeof(max_align_t) + size);
+    if (!block) abort();
+    block[0] = *frame;
+    *frame = block;
+    return (char *)block + sizeof(max_align_t);
+}
+
+static void lg_stack_release(void *frame) {
+    while (frame) {
+        void *next = *(void **)frame;
+        free(frame);
+        frame = next;
+    }
+}
+";
+
+/// Names already declared by the headers of the prelude.
+const LIBRARY_FUNCTIONS: [&str; 10] = [
+    "abort", "calloc", "exit", "fmod", "fmodf", "free", "malloc", "memcpy", "memset", "realloc",
+];
+
+const KEYWORDS: [&str; 44] = [
+    "auto",
+    "break",
+    "case",
+    "char",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extern",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "register",
+    "restrict",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "typedef",
+    "union",
+    "unsigned",
+    "void",
+    "volatile",
+    "while",
+    "_Alignas",
+    "_Alignof",
+    "_Atomic",
+    "_Bool",
+    "_Complex",
+    "_Generic",
+    "_Imaginary",
+    "_Noreturn",
+    "_Static_assert",
+    "_Thread_local",
+];
+
+pub fn generate_source(ir_module: &IRModule) -> IRGenerateResult<String> {
+    let ir_module = &*lower_for_target(ir_module, IRTarget::C)?;
+    let mut output = String::from(PRELUDE);
+    emit_structures(ir_module, &mut output);
+    emit_constants(ir_module, &mut output);
+    emit_global_data(ir_module, &mut output)?;
+    emit_prototypes(ir_module, &mut output);
+    if !ir_module.global_init_section.basic_blocks.is_empty() {
+        output.push_str("\n#if defined(__GNUC__)\n__attribute__((constructor))\n#endif\n");
+        IRCEmitter::new(ir_module, None)
+            .emit_function(&format!("void {}(void)", GLOBAL_INIT_FUNCTION), &mut output)?;
+    }
+    for ir_function in ir_module.functions.values() {
+        output.push('\n');
+        IRCEmitter::new(ir_module, Some(ir_function))
+            .emit_function(&signature(ir_function, true), &mut output)?;
+    }
+    emit_entry_point(ir_module, &mut output);
+    Ok(output)
+}
+
+/// Turns an IR name into a C identifier. Names that already are one, and are not keywords, are
+/// kept so that functions and globals link against C code under their own names. Any other name
+/// gets an `lg_` prefix, with ASCII letters and digits kept, `_` doubled and every other byte
+/// written as `_` followed by its hex value.
+pub(crate) fn mangle_identifier(name: &str) -> String {
+    let valid = name.bytes().enumerate().all(|(index, byte)| {
+        byte.is_ascii_alphabetic() || byte == b'_' || (byte.is_ascii_digit() && index > 0)
+    });
+    if valid && !name.is_empty() && !name.starts_with("lg_") && !KEYWORDS.contains(&name) {
+        return name.to_string();
+    }
+    let mut identifier = String::from("lg_");
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() {
+            identifier.push(byte as char);
+        } else if byte == b'_' {
+            identifier.push_str("__");
+        } else {
+            let _ = write!(identifier, "_{:02x}", byte);
+        }
+    }
+    identifier
+}
+
+fn c_type(_type: &dyn IRType) -> String {
+    match IRTypeKind::of(_type) {
+        IRTypeKind::Integer(integer) => match (integer.size as u32, integer.unsigned) {
+            (1, _) => "uint8_t".to_string(),
+            (bits, true) => format!("uint{}_t", bits),
+            (bits, false) => format!("int{}_t", bits),
+        },
+        IRTypeKind::Float(_) => "float".to_string(),
+        IRTypeKind::Double(_) => "double".to_string(),
+        IRTypeKind::Void(_) => "void".to_string(),
+        IRTypeKind::Pointer(_) => "void *".to_string(),
+        IRTypeKind::Structure(structure) => {
+            format!("struct {}", mangle_identifier(&structure.name))
+        }
+        IRTypeKind::Array(array) => format!("struct {}", array_type_name(&array)),
+        // Functions are only reached through pointers.
+        IRTypeKind::Function(_) => "void".to_string(),
+    }
+}
+
+/// The unsigned type integer arithmetic of the given width is carried out in, so that overflow
+/// wraps instead of being undefined.
+fn arithmetic_type(value_type: IRValueType) -> &'static str {
+    match value_type {
+        IRValueType::Integer { bits: 64, .. } => "uint64_t",
+        _ => "uint32_t",
+    }
+}
+
+fn signature(ir_function: &IRFunction, named: bool) -> String {
+    let parameters = ir_function
+        .fields
+        .iter()
+        .take(ir_function.arguments_count)
+        .enumerate()
+        .map(|(index, field)| {
+            if named {
+                format!("{} a{}", c_type(field._type.as_ref()), index)
+            } else {
+                c_type(field._type.as_ref())
+            }
+        })
+        .collect::<Vec<_>>();
+    format!(
+        "{} {}({})",
+        c_type(ir_function.return_type.as_ref()),
+        mangle_identifier(&ir_function.name),
+        if parameters.is_empty() {
+            "void".to_string()
+        } else {
+            parameters.join(", ")
+        }
+    )
+}
+
+fn constant_name(index: usize) -> String {
+    format!("lg_constant_{}", index)
+}
+
+fn global_type_name(name: &str) -> String {
+    format!("lg_global_{}", mangle_identifier(name))
+}
+
+/// The tag of the structure an array is wrapped in, so that it can be copied and assigned like
+/// any other value.
+fn array_type_name(array: &IRArrayType) -> String {
+    mangle_identifier(&format!("array{}", array))
+}
+
+/// Defines the structures of the module and the wrappers of the arrays it uses by value, each
+/// after the ones it holds by value.
+fn emit_structures(ir_module: &IRModule, output: &mut String) {
+    let collector = IRArrayTypeCollector {
+        arrays: RefCell::new(vec![]),
+    };
+    collector.visit_module(ir_module);
+    let mut defined = HashSet::new();
+    for ir_structure in ir_module.structures.values() {
+        let _type = IRStructureType::new(ir_structure.name.clone());
+        emit_type_definition(ir_module, &_type, &mut defined, output);
+    }
+    for array in collector.arrays.into_inner() {
+        emit_type_definition(ir_module, &array, &mut defined, output);
+    }
+}
+
+fn emit_type_definition(
+    ir_module: &IRModule,
+    _type: &dyn IRType,
+    defined: &mut HashSet<String>,
+    output: &mut String,
+) {
+    match IRTypeKind::of(_type) {
+        IRTypeKind::Structure(structure) => {
+            let Some(ir_structure) = structure.structure(ir_module) else {
+                return;
+            };
+            if !defined.insert(c_type(_type)) {
+                return;
+            }
+            for field in ir_structure.fields.iter() {
+                emit_type_definition(ir_module, field._type.as_ref(), defined, output);
+            }
+            let _ = writeln!(
+                output,
+                "\nstruct {} {{",
+                mangle_identifier(&ir_structure.name)
+            );
+            for field in ir_structure.fields.iter() {
+                let _ = writeln!(
+                    output,
+                    "    {} {};",
+                    c_type(field._type.as_ref()),
+                    mangle_identifier(&field.name)
+                );
+            }
+            output.push_str("};\n");
+        }
+        IRTypeKind::Array(array) => {
+            if !defined.insert(c_type(_type)) {
+                return;
+            }
+            emit_type_definition(ir_module, array.element.as_ref(), defined, output);
+            let _ = writeln!(
+                output,
+                "\nstruct {} {{\n    {} items[{}];\n}};",
+                array_type_name(&array),
+                c_type(array.element.as_ref()),
+                array.length
+            );
+        }
+        _ => {}
+    }
+}
+
+/// Collects the array types used by value anywhere in a module.
+struct IRArrayTypeCollector {
+    arrays: RefCell<Vec<IRArrayType>>,
+}
+
+impl IRVisitor for IRArrayTypeCollector {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_pointer_type(&self, _ir_pointer_type: &IRPointerType) {}
+    fn visit_array_type(&self, ir_array_type: &IRArrayType) {
+        self.arrays.borrow_mut().push(ir_array_type.clone());
+    }
+}
+
+fn emit_constants(ir_module: &IRModule, output: &mut String) {
+    for (index, entry) in ir_module.constant_pool.entries.iter().enumerate() {
+        if let Some(IRConstantData::String(bytes)) = IRConstantData::of(entry) {
+            let _ = writeln!(
+                output,
+                "\nstatic const unsigned char {}[] = {{{}}};",
+                constant_name(index),
+                bytes
+                    .iter()
+                    .chain([0].iter())
+                    .map(|byte| byte.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+    }
+}
+
+/// Emits each global as a packed structure mirroring its layout, so that values of mixed widths
+/// and addresses sit exactly where the native backends put them.
+fn emit_global_data(ir_module: &IRModule, output: &mut String) -> IRGenerateResult<()> {
+    let mut definitions = String::new();
+    for ir_global_data in ir_module.global_data_section.data.iter() {
+        let layout = global_data_layout(ir_module, ir_global_data)?;
+        let name = mangle_identifier(&ir_global_data.name);
+        let type_name = global_type_name(&ir_global_data.name);
+        let mut members = vec![];
+        let mut initializers = vec![];
+        for (index, item) in layout.items.iter().enumerate() {
+            match item {
+                IRDataItem::Bytes(bytes) => {
+                    members.push(format!("uint8_t m{}[{}];", index, bytes.len()));
+                    initializers.push(format!(
+                        "{{{}}}",
+                        bytes
+                            .iter()
+                            .map(|byte| byte.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
+                }
+                IRDataItem::Symbol(symbol) => {
+                    members.push(format!("const void *m{};", index));
+                    initializers.push(format!("(const void *)&{}", mangle_identifier(symbol)));
+                }
+                IRDataItem::Constant(constant) => {
+                    members.push(format!("const void *m{};", index));
+                    initializers.push(constant_name(*constant));
+                }
+                IRDataItem::Zero(size) => {
+                    members.push(format!("uint8_t m{}[{}];", index, size));
+                    initializers.push("{0}".to_string());
+                }
+            }
+        }
+        if members.is_empty() {
+            members.push("uint8_t m0[1];".to_string());
+            initializers.push("{0}".to_string());
+        }
+        let _ = writeln!(
+            output,
+            "\n#pragma pack(push, 1)\nstruct {} {{ {} }};\n#pragma pack(pop)\n\
+             extern _Alignas(8) struct {} {};",
+            type_name,
+            members.join(" "),
+            type_name,
+            name
+        );
+        let _ = writeln!(
+            definitions,
+            "_Alignas(8) struct {} {} = {{{}}};",
+            type_name,
+            name,
+            initializers.join(", ")
+        );
+    }
+    // Definitions come after every declaration since globals may refer to each other and to
+    // functions.
+    let mut declared = vec![];
+    for ir_global_data in ir_module.global_data_section.data.iter() {
+        for item in global_data_layout(ir_module, ir_global_data)?.items {
+            if let IRDataItem::Symbol(symbol) = item
+                && !ir_module
+                    .global_data_section
+                    .data
+                    .iter()
+                    .any(|data| data.name == symbol)
+                && !declared.contains(&symbol)
+            {
+                emit_external(ir_module, &symbol, output);
+                declared.push(symbol);
+            }
+        }
+    }
+    if !definitions.is_empty() {
+        output.push('\n');
+        output.push_str(&definitions);
+    }
+    Ok(())
+}
+
+/// Declares a symbol that is not global data: a module function or an external function.
+fn emit_external(ir_module: &IRModule, name: &str, output: &mut String) {
+    match ir_module.functions.get(name) {
+        Some(ir_function) => {
+            let _ = writeln!(output, "{};", signature(ir_function, false));
+        }
+        None if LIBRARY_FUNCTIONS.contains(&name) => {}
+        None => {
+            let _ = writeln!(output, "extern void {}(void);", mangle_identifier(name));
+        }
+    }
+}
+
+fn emit_prototypes(ir_module: &IRModule, output: &mut String) {
+    output.push('\n');
+    for ir_function in ir_module.functions.values() {
+        let _ = writeln!(output, "{};", signature(ir_function, false));
+    }
+    let collector = IRExternalCollector {
+        ir_module,
+        names: RefCell::new(vec![]),
+    };
+    let control_flow_graphs = std::iter::once(ir_module.global_init_section.as_ref()).chain(
+        ir_module
+            .functions
+            .values()
+            .map(|ir_function| ir_function.control_flow_graph.as_ref()),
+    );
+    for ir_control_flow_graph in control_flow_graphs {
+        for ir_basic_block in ir_control_flow_graph.basic_blocks.values() {
+            for ir_instruction in ir_basic_block.instructions.iter() {
+                ir_instruction.accept(&collector);
+            }
+        }
+    }
+    for name in collector.names.into_inner() {
+        emit_external(ir_module, &name, output);
+    }
+}
+
+fn emit_entry_point(ir_module: &IRModule, output: &mut String) {
+    let Some(ir_function) = ir_module
+        .entry_point
+        .as_ref()
+        .and_then(|entry_point| ir_module.functions.get(entry_point))
+    else {
+        return;
+    };
+    if mangle_identifier(&ir_function.name) == "main" {
+        return;
+    }
+    let arguments = ir_function
+        .fields
+        .iter()
+        .take(ir_function.arguments_count)
+        .map(|field| format!("({})0", c_type(field._type.as_ref())))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let call = format!("{}({})", mangle_identifier(&ir_function.name), arguments);
+    output.push_str("\nint main(void) {\n");
+    if !ir_module.global_init_section.basic_blocks.is_empty() {
+        let _ = writeln!(
+            output,
+            "#if !defined(__GNUC__)\n    {}();\n#endif",
+            GLOBAL_INIT_FUNCTION
+        );
+    }
+    let _ = match IRTypeKind::of(ir_function.return_type.as_ref()) {
+        IRTypeKind::Integer(_) => writeln!(output, "    return (int){};", call),
+        _ => writeln!(output, "    {};\n    return 0;", call),
+    };
+    output.push_str("}\n");
+}
+
+/// Collects functions invoked directly that are not defined in the module.
+struct IRExternalCollector<'a> {
+    ir_module: &'a IRModule,
+    names: RefCell<Vec<String>>,
+}
+
+impl IRExternalCollector<'_> {
+    fn collect(&self, operand: &dyn IROperand) {
+        if let IROperandValue::FunctionAddress(name) = IROperandValue::of(operand)
+            && !self.ir_module.functions.contains_key(&name)
+            && !self.names.borrow().contains(&name)
+        {
+            self.names.borrow_mut().push(name);
+        }
+    }
+}
+
+impl IRVisitor for IRExternalCollector<'_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_invoke(&self, ir_invoke: &IRInvoke) {
+        self.collect(ir_invoke.address.as_ref());
+        for argument in ir_invoke.arguments.iter() {
+            self.collect(argument.as_ref());
+        }
+    }
+    fn visit_set_virtual_register(&self, ir_set_virtual_register: &IRSetVirtualRegister) {
+        self.collect(ir_set_virtual_register.source.as_ref());
+    }
+    fn visit_set(&self, ir_set: &IRSet) {
+        self.collect(ir_set.value.as_ref());
+    }
+}
+
+fn float_literal(data: &IRConstantData) -> String {
+    let (text, suffix) = match data {
+        IRConstantData::Float(value) if value.is_finite() => (format!("{:?}", value), "f"),
+        IRConstantData::Double(value) if value.is_finite() => (format!("{:?}", value), ""),
+        IRConstantData::Float(value) => (non_finite(*value as f64), ""),
+        IRConstantData::Double(value) => (non_finite(*value), ""),
+        _ => unreachable!(),
+    };
+    format!("{}{}", text, suffix)
+}
+
+fn non_finite(value: f64) -> String {
+    if value.is_nan() {
+        "NAN".to_string()
+    } else if value > 0.0 {
+        "INFINITY".to_string()
+    } else {
+        "(-INFINITY)".to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IRArithmetic {
+    Binary(IRCalculateOperator),
+    Not,
+    Negate,
+}
+
+struct IRCEmitter<'a> {
+    ir_module: &'a IRModule,
+    ir_function: Option<&'a IRFunction>,
+    ir_control_flow_graph: &'a IRControlFlowGraph,
+    types: IRFunctionTypes,
+    output: RefCell<String>,
+    location: RefCell<IRLocation>,
+    uses_stack: Cell<bool>,
+    terminated: Cell<bool>,
+    error: RefCell<Option<IRGenerateError>>,
+}
+
+impl<'a> IRCEmitter<'a> {
+    fn new(ir_module: &'a IRModule, ir_function: Option<&'a IRFunction>) -> Self {
+        let ir_control_flow_graph = match ir_function {
+            Some(ir_function) => &ir_function.control_flow_graph,
+            None => &ir_module.global_init_section,
+        };
+        Self {
+            ir_module,
+            ir_function,
+            ir_control_flow_graph,
+            types: infer_register_types(ir_module, ir_function),
+            output: RefCell::new(String::new()),
+            location: RefCell::new(IRLocation::Module),
+            uses_stack: Cell::new(false),
+            terminated: Cell::new(false),
+            error: RefCell::new(None),
+        }
+    }
+
+    fn emit(&self, args: fmt::Arguments) {
+        let mut output = self.output.borrow_mut();
+        output.push_str("    ");
+        let _ = output.write_fmt(args);
+        output.push('\n');
+    }
+
+    fn unsupported(&self, message: String) -> IRGenerateError {
+        IRGenerateError::Unsupported {
+            location: self.location.borrow().clone(),
+            message,
+        }
+    }
+
+    fn finish(&self, result: IRGenerateResult<()>) {
+        if let Err(error) = result {
+            self.error.borrow_mut().get_or_insert(error);
+        }
+    }
+
+    fn emit_function(&self, signature: &str, output: &mut String) -> IRGenerateResult<()> {
+        let count = self.ir_control_flow_graph.basic_blocks.len();
+        for (position, (name, ir_basic_block)) in
+            self.ir_control_flow_graph.basic_blocks.iter().enumerate()
+        {
+            let _ = writeln!(self.output.borrow_mut(), "{}:;", self.label(name));
+            self.terminated.set(false);
+            for (index, ir_instruction) in ir_basic_block.instructions.iter().enumerate() {
+                self.location.replace(IRLocation::Instruction {
+                    function: self.ir_function.map(|ir_function| ir_function.name.clone()),
+                    basic_block: name.clone(),
+                    index,
+                });
+                self.terminated.set(false);
+                ir_instruction.accept(self);
+            }
+            if !self.terminated.get() {
+                match self
+                    .ir_control_flow_graph
+                    .basic_blocks
+                    .get_index(position + 1)
+                {
+                    Some((next, _)) if position + 1 < count => {
+                        let result = self.emit_edge(name, next);
+                        self.finish(result);
+                    }
+                    _ => self.emit_return(None),
+                }
+            }
+        }
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        let _ = writeln!(output, "{} {{", signature);
+        if let Some(ir_function) = self.ir_function {
+            for (index, field) in ir_function.fields.iter().enumerate() {
+                let name = self.field(&field.name);
+                let _type = c_type(field._type.as_ref());
+                if index < ir_function.arguments_count {
+                    let _ = writeln!(output, "    {} {} = a{};", _type, name, index);
+                } else {
+                    let _ = writeln!(output, "    {} {};", _type, name);
+                }
+            }
+        }
+        for (name, _type) in self.types.registers.iter() {
+            if !matches!(IRTypeKind::of(_type.as_ref()), IRTypeKind::Void(_)) {
+                let _ = writeln!(
+                    output,
+                    "    {} {};",
+                    c_type(_type.as_ref()),
+                    self.register(name)
+                );
+            }
+        }
+        for ir_basic_block in self.ir_control_flow_graph.basic_blocks.values() {
+            for (target, ir_phi) in ir_basic_block.phis() {
+                let _ = writeln!(
+                    output,
+                    "    {} {};",
+                    c_type(ir_phi._type.as_ref()),
+                    self.phi_shadow(&target)
+                );
+            }
+        }
+        if self.uses_stack.get() {
+            output.push_str("    void *lg_frame = NULL;\n");
+        }
+        output.push_str(&self.output.borrow());
+        output.push_str("}\n");
+        Ok(())
+    }
+
+    fn void_pointer(&self) -> IRPointerType {
+        IRPointerType::new(Box::new(IRVoidType::new()))
+    }
+
+    fn size_type(&self) -> IRIntegerType {
+        IRIntegerType::new(IRIntegerTypeSize::EightBytes, true)
+    }
+
+    fn label(&self, name: &str) -> String {
+        format!("L_{}", mangle_identifier(name))
+    }
+
+    fn register(&self, name: &str) -> String {
+        format!("r_{}", mangle_identifier(name))
+    }
+
+    fn field(&self, name: &str) -> String {
+        format!("f_{}", mangle_identifier(name))
+    }
+
+    fn phi_shadow(&self, name: &str) -> String {
+        format!("p_{}", mangle_identifier(name))
+    }
+
+    fn target(&self, target: &IRVirtualRegister) -> IRGenerateResult<(String, &dyn IRType)> {
+        let _type = self.types.register_type(&target.name).ok_or_else(|| {
+            self.unsupported(format!("register %{} without a known type", target.name))
+        })?;
+        Ok((self.register(&target.name), _type))
+    }
+
+    /// A C expression for an operand, converted to `_type` when its own type differs.
+    fn operand(&self, operand: &dyn IROperand, _type: &dyn IRType) -> IRGenerateResult<String> {
+        let expression = match IROperandValue::of(operand) {
+            IROperandValue::Register(name) => self.register(&name),
+            IROperandValue::Constant(index) => {
+                let (entry, data) = constant(self.ir_module, index)
+                    .ok_or_else(|| self.unsupported(format!("constant ${}", index)))?;
+                match data {
+                    IRConstantData::String(_) => {
+                        format!("(void *){}", constant_name(index as usize))
+                    }
+                    _ if matches!(
+                        IRTypeKind::of(entry._type.as_ref()),
+                        IRTypeKind::Structure(_) | IRTypeKind::Array(_)
+                    ) =>
+                    {
+                        format!("(({}){{0}})", c_type(entry._type.as_ref()))
+                    }
+                    IRConstantData::Integer(value) => {
+                        format!("(({})UINT64_C({}))", c_type(entry._type.as_ref()), value)
+                    }
+                    data => float_literal(&data),
+                }
+            }
+            IROperandValue::FieldAddress(name) => {
+                let defined = self.ir_function.is_some_and(|ir_function| {
+                    ir_function.fields.iter().any(|field| field.name == name)
+                });
+                if !defined {
+                    return Err(self.unsupported(format!("field '{}'", name)));
+                }
+                format!("(void *)&{}", self.field(&name))
+            }
+            IROperandValue::GlobalDataAddress(name) | IROperandValue::FunctionAddress(name) => {
+                format!("(void *)&{}", mangle_identifier(&name))
+            }
+            IROperandValue::Phi(_) => {
+                return Err(
+                    self.unsupported(format!("phi outside of a register copy: {}", operand))
+                );
+            }
+            _ => return Err(self.unsupported(format!("operand {}", operand))),
+        };
+        let own_type = operand_type(self.ir_module, &self.types, operand);
+        match own_type {
+            Some(own_type) if c_type(own_type.as_ref()) == c_type(_type) => Ok(expression),
+            _ => Ok(format!("(({}){})", c_type(_type), expression)),
+        }
+    }
+
+    fn emit_edge(&self, from: &str, to: &str) -> IRGenerateResult<()> {
+        for phi_move in phi_moves(self.ir_control_flow_graph, from, to) {
+            let ir_phi = self.ir_control_flow_graph.basic_blocks[to]
+                .phis()
+                .into_iter()
+                .find(|(target, _)| *target == phi_move.target)
+                .map(|(_, ir_phi)| ir_phi)
+                .unwrap();
+            let value = self.operand(phi_move.operand.as_ref(), ir_phi._type.as_ref())?;
+            self.emit(format_args!(
+                "{} = {};",
+                self.phi_shadow(&phi_move.target),
+                value
+            ));
+        }
+        Ok(())
+    }
+
+    fn current_block(&self) -> String {
+        match &*self.location.borrow() {
+            IRLocation::Instruction { basic_block, .. } => basic_block.clone(),
+            _ => String::new(),
+        }
+    }
+
+    fn emit_jump(&self, target: &str) -> IRGenerateResult<()> {
+        if !self.ir_control_flow_graph.basic_blocks.contains_key(target) {
+            return Err(self.unsupported(format!("jump to missing block '{}'", target)));
+        }
+        self.emit_edge(&self.current_block(), target)?;
+        self.emit(format_args!("goto {};", self.label(target)));
+        Ok(())
+    }
+
+    fn emit_return(&self, value: Option<String>) {
+        let release = if self.uses_stack.get() {
+            "lg_stack_release(lg_frame); "
+        } else {
+            ""
+        };
+        match value {
+            Some(value) => {
+                let return_type = self.ir_function.map_or("void".to_string(), |ir_function| {
+                    c_type(ir_function.return_type.as_ref())
+                });
+                self.emit(format_args!(
+                    "{{ {} lg_result = {}; {}return lg_result; }}",
+                    return_type, value, release
+                ));
+            }
+            None if self.ir_function.is_some_and(|ir_function| {
+                !matches!(
+                    IRTypeKind::of(ir_function.return_type.as_ref()),
+                    IRTypeKind::Void(_)
+                )
+            }) =>
+            {
+                let return_type = c_type(self.ir_function.unwrap().return_type.as_ref());
+                self.emit(format_args!("{}return ({})0;", release, return_type));
+            }
+            None => self.emit(format_args!("{}return;", release)),
+        }
+    }
+
+    /// The expression computing `a op b` in `_type`.
+    fn arithmetic(
+        &self,
+        arithmetic: IRArithmetic,
+        _type: &dyn IRType,
+        a: &str,
+        b: &str,
+    ) -> IRGenerateResult<String> {
+        use IRCalculateOperator::*;
+        let value_type = IRValueType::of(_type);
+        let c = c_type(_type);
+        let expression = match value_type {
+            IRValueType::Integer { bits, unsigned } => {
+                let wide = arithmetic_type(value_type);
+                let mask = bits - 1;
+                let value = match arithmetic {
+                    IRArithmetic::Binary(ADD) => format!("({}){} + ({}){}", wide, a, wide, b),
+                    IRArithmetic::Binary(SUB) => format!("({}){} - ({}){}", wide, a, wide, b),
+                    IRArithmetic::Binary(MUL) => format!("({}){} * ({}){}", wide, a, wide, b),
+                    IRArithmetic::Binary(DIV) => format!("{} / {}", a, b),
+                    IRArithmetic::Binary(MOD) => format!("{} % {}", a, b),
+                    IRArithmetic::Binary(AND) => format!("{} & {}", a, b),
+                    IRArithmetic::Binary(OR) => format!("{} | {}", a, b),
+                    IRArithmetic::Binary(XOR) => format!("{} ^ {}", a, b),
+                    IRArithmetic::Binary(SHL) => format!("({}){} << ({} & {})", wide, a, b, mask),
+                    IRArithmetic::Binary(SHR) if !unsigned => {
+                        format!("{} >> ({} & {})", a, b, mask)
+                    }
+                    IRArithmetic::Binary(SHR | USHR) => {
+                        format!("(uint{}_t){} >> ({} & {})", bits.max(8), a, b, mask)
+                    }
+                    IRArithmetic::Not => format!("~{}", a),
+                    IRArithmetic::Negate => format!("({})0 - ({}){}", wide, wide, a),
+                };
+                if bits == 1 {
+                    format!("({})(({}) & 1)", c, value)
+                } else {
+                    format!("({})({})", c, value)
+                }
+            }
+            IRValueType::Float | IRValueType::Double => {
+                let fmod = if value_type == IRValueType::Float {
+                    "fmodf"
+                } else {
+                    "fmod"
+                };
+                match arithmetic {
+                    IRArithmetic::Binary(ADD) => format!("{} + {}", a, b),
+                    IRArithmetic::Binary(SUB) => format!("{} - {}", a, b),
+                    IRArithmetic::Binary(MUL) => format!("{} * {}", a, b),
+                    IRArithmetic::Binary(DIV) => format!("{} / {}", a, b),
+                    IRArithmetic::Binary(MOD) => format!("{}({}, {})", fmod, a, b),
+                    IRArithmetic::Negate => format!("-{}", a),
+                    arithmetic => {
+                        return Err(self.unsupported(format!("{:?} on {}", arithmetic, _type)));
+                    }
+                }
+            }
+            IRValueType::Void => return Err(self.unsupported(format!("arithmetic on {}", _type))),
+        };
+        Ok(expression)
+    }
+
+    /// Atomically replaces `*address` with `*address op rhs` and assigns the new value to
+    /// `target`, if any.
+    fn emit_atomic_update(
+        &self,
+        arithmetic: IRArithmetic,
+        _type: &dyn IRType,
+        address: &dyn IROperand,
+        rhs: Option<String>,
+        target: Option<&str>,
+    ) -> IRGenerateResult<()> {
+        let c = c_type(_type);
+        let address = self.operand(address, &self.void_pointer())?;
+        let rhs = rhs.unwrap_or_default();
+        let fetch = match (arithmetic, IRValueType::of(_type)) {
+            (IRArithmetic::Binary(operator), IRValueType::Integer { bits, .. }) if bits > 1 => {
+                match operator {
+                    IRCalculateOperator::ADD => Some(("add", "+")),
+                    IRCalculateOperator::SUB => Some(("sub", "-")),
+                    IRCalculateOperator::AND => Some(("and", "&")),
+                    IRCalculateOperator::OR => Some(("or", "|")),
+                    IRCalculateOperator::XOR => Some(("xor", "^")),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        if let Some((name, operator)) = fetch {
+            let call = format!(
+                "atomic_fetch_{}((_Atomic {} *){}, {})",
+                name, c, address, rhs
+            );
+            match target {
+                Some(target) => self.emit(format_args!(
+                    "{} = ({})({} {} {});",
+                    target, c, call, operator, rhs
+                )),
+                None => self.emit(format_args!("{};", call)),
+            }
+            return Ok(());
+        }
+        let value = self.arithmetic(arithmetic, _type, "lg_old", &rhs)?;
+        self.emit(format_args!(
+            "{{ _Atomic {} *lg_address = (_Atomic {} *){}; {} lg_old = atomic_load(lg_address), lg_new;",
+            c, c, address, c
+        ));
+        self.emit(format_args!(
+            "  do lg_new = {}; while (!atomic_compare_exchange_weak(lg_address, &lg_old, lg_new));",
+            value
+        ));
+        match target {
+            Some(target) => self.emit(format_args!("  {} = lg_new; }}", target)),
+            None => self.emit(format_args!("}}")),
+        }
+        Ok(())
+    }
+
+    fn emit_unary(
+        &self,
+        arithmetic: IRArithmetic,
+        is_atomic: bool,
+        _type: &dyn IRType,
+        operand: &dyn IROperand,
+        target: &IRVirtualRegister,
+    ) -> IRGenerateResult<()> {
+        let (target, _) = self.target(target)?;
+        if is_atomic {
+            return self.emit_atomic_update(arithmetic, _type, operand, None, Some(&target));
+        }
+        let value = self.operand(operand, _type)?;
+        let value = self.arithmetic(arithmetic, _type, &value, "")?;
+        self.emit(format_args!("{} = {};", target, value));
+        Ok(())
+    }
+
+    fn emit_step(
+        &self,
+        operator: IRCalculateOperator,
+        _type: &dyn IRType,
+        operand: &dyn IROperand,
+        target: Option<&IRVirtualRegister>,
+    ) -> IRGenerateResult<()> {
+        let one = format!("({})1", c_type(_type));
+        let arithmetic = IRArithmetic::Binary(operator);
+        match target {
+            Some(target) => {
+                let (target, _) = self.target(target)?;
+                let value = self.operand(operand, _type)?;
+                let value = self.arithmetic(arithmetic, _type, &value, &one)?;
+                self.emit(format_args!("{} = {};", target, value));
+                Ok(())
+            }
+            None => self.emit_atomic_update(arithmetic, _type, operand, Some(one), None),
+        }
+    }
+
+    fn condition(
+        &self,
+        condition: IRCondition,
+        a: &str,
+        b: Option<String>,
+    ) -> IRGenerateResult<String> {
+        let b = || {
+            b.clone().ok_or_else(|| {
+                self.unsupported(format!("condition {} without a second operand", condition))
+            })
+        };
+        Ok(match condition {
+            IRCondition::Equal => format!("{} == {}", a, b()?),
+            IRCondition::NotEqual => format!("{} != {}", a, b()?),
+            IRCondition::Less => format!("{} < {}", a, b()?),
+            IRCondition::LessEqual => format!("{} <= {}", a, b()?),
+            IRCondition::Greater => format!("{} > {}", a, b()?),
+            IRCondition::GreaterEqual => format!("{} >= {}", a, b()?),
+            IRCondition::IfTrue => format!("{} != 0", a),
+            IRCondition::IfFalse => format!("{} == 0", a),
+        })
+    }
+
+    fn emit_conditional_jump(
+        &self,
+        ir_conditional_jump: &IRConditionalJump,
+    ) -> IRGenerateResult<()> {
+        let _type = ir_conditional_jump._type.as_ref();
+        let c = c_type(_type);
+        let operand1 = if ir_conditional_jump.is_atomic {
+            let address =
+                self.operand(ir_conditional_jump.operand1.as_ref(), &self.void_pointer())?;
+            format!("atomic_load((_Atomic {} *){})", c, address)
+        } else {
+            self.operand(ir_conditional_jump.operand1.as_ref(), _type)?
+        };
+        let operand2 = match ir_conditional_jump.operand2.as_ref() {
+            Some(operand2) => Some(self.operand(operand2.as_ref(), _type)?),
+            None => None,
+        };
+        let condition = self.condition(ir_conditional_jump.condition, &operand1, operand2)?;
+        let target = &ir_conditional_jump.target;
+        if !self.ir_control_flow_graph.basic_blocks.contains_key(target) {
+            return Err(self.unsupported(format!("jump to missing block '{}'", target)));
+        }
+        self.emit(format_args!("if ({}) {{", condition));
+        self.emit_jump(target)?;
+        self.emit(format_args!("}}"));
+        Ok(())
+    }
+
+    fn emit_invoke(&self, ir_invoke: &IRInvoke) -> IRGenerateResult<()> {
+        if ir_invoke.argument_types.len() != ir_invoke.arguments.len() {
+            return Err(self.unsupported("invoke with mismatched argument types".to_string()));
+        }
+        let direct = match IROperandValue::of(ir_invoke.address.as_ref()) {
+            IROperandValue::FunctionAddress(name) => self.ir_module.functions.get(&name),
+            _ => None,
+        };
+        let mut arguments = vec![];
+        for (index, argument) in ir_invoke.arguments.iter().enumerate() {
+            let _type = match direct {
+                Some(ir_function) if ir_function.arguments_count == ir_invoke.arguments.len() => {
+                    ir_function.fields[index]._type.as_ref()
+                }
+                _ => ir_invoke.argument_types[index].as_ref(),
+            };
+            arguments.push(self.operand(argument.as_ref(), _type)?);
+        }
+        let callee = match direct {
+            Some(ir_function) if ir_function.arguments_count == ir_invoke.arguments.len() => {
+                mangle_identifier(&ir_function.name)
+            }
+            _ => {
+                let parameters = ir_invoke
+                    .argument_types
+                    .iter()
+                    .map(|_type| c_type(_type.as_ref()))
+                    .collect::<Vec<_>>();
+                let address = match IROperandValue::of(ir_invoke.address.as_ref()) {
+                    IROperandValue::FunctionAddress(name) => {
+                        format!("&{}", mangle_identifier(&name))
+                    }
+                    _ => self.operand(ir_invoke.address.as_ref(), &self.void_pointer())?,
+                };
+                format!(
+                    "(({} (*)({})){})",
+                    c_type(ir_invoke.return_type.as_ref()),
+                    if parameters.is_empty() {
+                        "void".to_string()
+                    } else {
+                        parameters.join(", ")
+                    },
+                    address
+                )
+            }
+        };
+        let call = format!("{}({})", callee, arguments.join(", "));
+        match ir_invoke.target.as_ref() {
+            Some(target) => {
+                let (target, _type) = self.target(target)?;
+                self.emit(format_args!("{} = ({}){};", target, c_type(_type), call));
+            }
+            None => self.emit(format_args!("{};", call)),
+        }
+        Ok(())
+    }
+
+    fn emit_type_cast(&self, ir_type_cast: &IRTypeCast) -> IRGenerateResult<()> {
+        let (target, _) = self.target(&ir_type_cast.target)?;
+        let from = ir_type_cast.original_type.as_ref();
+        let to = c_type(ir_type_cast.target_type.as_ref());
+        let source = self.operand(ir_type_cast.source.as_ref(), from)?;
+        let bits = match IRValueType::of(from) {
+            IRValueType::Integer { bits, .. } => bits.max(8),
+            _ => 64,
+        };
+        let value = match ir_type_cast.kind {
+            IRTypeCastKind::ZeroExtend => format!("({})(uint{}_t){}", to, bits, source),
+            IRTypeCastKind::SignExtend => format!("({})(int{}_t){}", to, bits, source),
+            IRTypeCastKind::Truncate
+                if matches!(
+                    IRValueType::of(ir_type_cast.target_type.as_ref()),
+                    IRValueType::Integer { bits: 1, .. }
+                ) =>
+            {
+                format!("({})({} & 1)", to, source)
+            }
+            _ => format!("({}){}", to, source),
+        };
+        self.emit(format_args!("{} = {};", target, value));
+        Ok(())
+    }
+}
+
+impl IRVisitor for IRCEmitter<'_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_goto(&self, ir_goto: &IRGoto) {
+        self.terminated.set(true);
+        self.finish(self.emit_jump(&ir_goto.target));
+    }
+    fn visit_conditional_jump(&self, ir_conditional_jump: &IRConditionalJump) {
+        self.finish(self.emit_conditional_jump(ir_conditional_jump));
+    }
+    fn visit_return(&self, ir_return: &IRReturn) {
+        self.terminated.set(true);
+        let value = match (ir_return.operand.as_ref(), self.ir_function) {
+            (Some(operand), Some(ir_function)) => {
+                match self.operand(operand.as_ref(), ir_function.return_type.as_ref()) {
+                    Ok(value) => Some(value),
+                    Err(error) => return self.finish(Err(error)),
+                }
+            }
+            _ => None,
+        };
+        self.emit_return(value);
+    }
+    fn visit_calculate(&self, ir_calculate: &IRCalculate) {
+        let _type = ir_calculate._type.as_ref();
+        let arithmetic = IRArithmetic::Binary(ir_calculate.operator);
+        let result = (|| {
+            let (target, _) = self.target(&ir_calculate.target)?;
+            let operand2 = self.operand(ir_calculate.operand2.as_ref(), _type)?;
+            if ir_calculate.is_atomic {
+                return self.emit_atomic_update(
+                    arithmetic,
+                    _type,
+                    ir_calculate.operand1.as_ref(),
+                    Some(operand2),
+                    Some(&target),
+                );
+            }
+            let operand1 = self.operand(ir_calculate.operand1.as_ref(), _type)?;
+            let value = self.arithmetic(arithmetic, _type, &operand1, &operand2)?;
+            self.emit(format_args!("{} = {};", target, value));
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_not(&self, ir_not: &IRNot) {
+        self.finish(self.emit_unary(
+            IRArithmetic::Not,
+            ir_not.is_atomic,
+            ir_not._type.as_ref(),
+            ir_not.operand.as_ref(),
+            &ir_not.target,
+        ));
+    }
+    fn visit_negate(&self, ir_negate: &IRNegate) {
+        self.finish(self.emit_unary(
+            IRArithmetic::Negate,
+            ir_negate.is_atomic,
+            ir_negate._type.as_ref(),
+            ir_negate.operand.as_ref(),
+            &ir_negate.target,
+        ));
+    }
+    fn visit_malloc(&self, ir_malloc: &IRMalloc) {
+        let result = (|| {
+            let (target, _type) = self.target(&ir_malloc.target)?;
+            let size = self.operand(ir_malloc.size.as_ref(), &self.size_type())?;
+            self.emit(format_args!(
+                "{} = ({})malloc((size_t){});",
+                target,
+                c_type(_type),
+                size
+            ));
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_free(&self, ir_free: &IRFree) {
+        let result = (|| {
+            let pointer = self.operand(ir_free.ptr.as_ref(), &self.void_pointer())?;
+            self.emit(format_args!("free({});", pointer));
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_realloc(&self, ir_realloc: &IRRealloc) {
+        let result = (|| {
+            let (target, _type) = self.target(&ir_realloc.target)?;
+            let pointer = self.operand(ir_realloc.ptr.as_ref(), &self.void_pointer())?;
+            let size = self.operand(ir_realloc.size.as_ref(), &self.size_type())?;
+            self.emit(format_args!(
+                "{} = ({})realloc({}, (size_t){});",
+                target,
+                c_type(_type),
+                pointer,
+                size
+            ));
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_get(&self, ir_get: &IRGet) {
+        let result = (|| {
+            let (target, _type) = self.target(&ir_get.target)?;
+            let address = self.operand(ir_get.address.as_ref(), &self.void_pointer())?;
+            let c = c_type(ir_get._type.as_ref());
+            // Structures cannot be cast, even to their own type.
+            let value = if c == c_type(_type) {
+                "lg_value".to_string()
+            } else {
+                format!("({})lg_value", c_type(_type))
+            };
+            self.emit(format_args!(
+                "{{ {} lg_value; memcpy(&lg_value, {}, sizeof lg_value); {} = {}; }}",
+                c, address, target, value
+            ));
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_set(&self, ir_set: &IRSet) {
+        let result = (|| {
+            let _type = ir_set._type.as_ref();
+            let address = self.operand(ir_set.address.as_ref(), &self.void_pointer())?;
+            let value = self.operand(ir_set.value.as_ref(), _type)?;
+            self.emit(format_args!(
+                "{{ {} lg_value = {}; memcpy({}, &lg_value, sizeof lg_value); }}",
+                c_type(_type),
+                value,
+                address
+            ));
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_set_virtual_register(&self, ir_set_virtual_register: &IRSetVirtualRegister) {
+        let result = (|| {
+            let (target, _type) = self.target(&ir_set_virtual_register.target)?;
+            let value = match IROperandValue::of(ir_set_virtual_register.source.as_ref()) {
+                IROperandValue::Phi(_) => self.phi_shadow(&ir_set_virtual_register.target.name),
+                _ => self.operand(ir_set_virtual_register.source.as_ref(), _type)?,
+            };
+            self.emit(format_args!("{} = {};", target, value));
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_invoke(&self, ir_invoke: &IRInvoke) {
+        self.finish(self.emit_invoke(ir_invoke));
+    }
+    fn visit_no_operate(&self, _ir_no_operate: &IRNoOperate) {
+        self.emit(format_args!(";"));
+    }
+    fn visit_increase(&self, ir_increase: &IRIncrease) {
+        self.finish(self.emit_step(
+            IRCalculateOperator::ADD,
+            ir_increase._type.as_ref(),
+            ir_increase.operand.as_ref(),
+            ir_increase.target.as_deref(),
+        ));
+    }
+    fn visit_decrease(&self, ir_decrease: &IRDecrease) {
+        self.finish(self.emit_step(
+            IRCalculateOperator::SUB,
+            ir_decrease._type.as_ref(),
+            ir_decrease.operand.as_ref(),
+            ir_decrease.target.as_deref(),
+        ));
+    }
+    fn visit_stack_allocate(&self, ir_stack_allocate: &IRStackAllocate) {
+        let result = (|| {
+            let (target, _type) = self.target(&ir_stack_allocate.target)?;
+            let size = self.operand(ir_stack_allocate.size.as_ref(), &self.size_type())?;
+            self.uses_stack.set(true);
+            self.emit(format_args!(
+                "{} = ({})lg_stack_allocate(&lg_frame, (size_t){});",
+                target,
+                c_type(_type),
+                size
+            ));
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_type_cast(&self, ir_type_cast: &IRTypeCast) {
+        self.finish(self.emit_type_cast(ir_type_cast));
+    }
+    fn visit_asm(&self, _ir_asm: &IRAsm) {
+        self.finish(Err(self.unsupported("inline assembly in C".to_string())));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parser::parse_module;
+
+    const LOOP: &str = "\
+constant $0 = i64 0
+constant $1 = i64 1
+function i64 sum(i64 n) {
+entry:
+    %pn = `field_address([n], [])
+    %n = get i64, %pn
+    goto loop
+loop:
+    %i = phi i64 [entry, $0], [loop, %next_i]
+    %total = phi i64 [entry, $0], [loop, %next_total]
+    %next_total = add i64 %total, %i
+    %next_i = add i64 %i, $1
+    conditional_jump i64 le, %next_i, %n, #loop
+done:
+    %r = invoke i64 `function_address([twice], []), [i64, %next_total]
+    return %r
+}
+function i64 twice(i64 x) {
+entry:
+    %px = `field_address([x], [])
+    %x = get i64, %px
+    %r = add i64 %x, %x
+    return %r
+}
+";
+
+    #[test]
+    fn phis_are_assigned_on_the_edges_and_calls_go_through_prototypes() {
+        assert_eq!(
+            generate_source(&parse_module(LOOP).unwrap()).unwrap(),
+            "\
+#include <math.h>
+#include <stdatomic.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+static void *lg_stack_allocate(void **frame, size_t size) {
+    void **block = malloc(sizeof(max_align_t) + size);
+    if (!block) abort();
+    block[0] = *frame;
+    *frame = block;
+    return (char *)block + sizeof(max_align_t);
+}
+
+static void lg_stack_release(void *frame) {
+    while (frame) {
+        void *next = *(void **)frame;
+        free(frame);
+        frame = next;
+    }
+}
+
+int64_t sum(int64_t);
+int64_t twice(int64_t);
+
+int64_t sum(int64_t a0) {
+    int64_t f_n = a0;
+    void * r_pn;
+    int64_t r_n;
+    int64_t r_i;
+    int64_t r_total;
+    int64_t r_next_total;
+    int64_t r_next_i;
+    int64_t r_r;
+    int64_t p_i;
+    int64_t p_total;
+L_entry:;
+    r_pn = (void *)&f_n;
+    { int64_t lg_value; memcpy(&lg_value, r_pn, sizeof lg_value); r_n = lg_value; }
+    p_i = ((int64_t)UINT64_C(0));
+    p_total = ((int64_t)UINT64_C(0));
+    goto L_loop;
+L_loop:;
+    r_i = p_i;
+    r_total = p_total;
+    r_next_total = (int64_t)((uint64_t)r_total + (uint64_t)r_i);
+    r_next_i = (int64_t)((uint64_t)r_i + (uint64_t)((int64_t)UINT64_C(1)));
+    if (r_next_i <= r_n) {
+    p_i = r_next_i;
+    p_total = r_next_total;
+    goto L_loop;
+    }
+L_done:;
+    r_r = (int64_t)twice(r_next_total);
+    { int64_t lg_result = r_r; return lg_result; }
+}
+
+int64_t twice(int64_t a0) {
+    int64_t f_x = a0;
+    void * r_px;
+    int64_t r_x;
+    int64_t r_r;
+L_entry:;
+    r_px = (void *)&f_x;
+    { int64_t lg_value; memcpy(&lg_value, r_px, sizeof lg_value); r_x = lg_value; }
+    r_r = (int64_t)((uint64_t)r_x + (uint64_t)r_x);
+    { int64_t lg_result = r_r; return lg_result; }
+}
+"
+        );
+    }
+}
@@ -0,0 +1,899 @@
+//! ELF64 relocatable objects for the native targets, assembled in-process from the text the
+//! assembly backends produce so that no external assembler is needed.
+//!
+//! The reader understands the directives the backends emit: every function is in a section of its
+//! own, global data goes to `.data` or `.bss`, and the constant pool to `.rodata`. Each target
+//! brings an `IREncoder` for its instructions. Branches within a section are resolved here; calls,
+//! references to data and the `.quad`s naming symbols are left to the linker as relocations.
+
+pub mod aarch64;
+pub mod riscv64;
+pub mod x86_64;
+
+use crate::backend::{IRGenerateError, IRGenerateResult};
+use crate::ir::verify::IRLocation;
+use indexmap::{IndexMap, IndexSet};
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+const SHT_INIT_ARRAY: u32 = 14;
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const SHF_INFO_LINK: u64 = 0x40;
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+/// A reference to a symbol from the bytes at `offset`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct IRFixup {
+    pub(crate) offset: u64,
+    pub(crate) symbol: String,
+    pub(crate) relocation: u32,
+    pub(crate) addend: i64,
+}
+
+/// The bytes of one instruction, with the offsets of its fixups and labels relative to its start.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct IREncoding {
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) fixups: Vec<IRFixup>,
+    pub(crate) labels: Vec<(String, u64)>,
+    /// Unique within the object, for naming the labels an instruction defines.
+    pub(crate) serial: usize,
+}
+
+impl IREncoding {
+    pub(crate) fn push_u32(&mut self, word: u32) {
+        self.bytes.extend(word.to_le_bytes());
+    }
+
+    /// Adds a fixup for the bytes about to be pushed.
+    pub(crate) fn fixup(&mut self, symbol: &str, relocation: u32, addend: i64) {
+        self.fixups.push(IRFixup {
+            offset: self.bytes.len() as u64,
+            symbol: symbol.to_string(),
+            relocation,
+            addend,
+        });
+    }
+
+    /// Defines a local label at the bytes about to be pushed and returns its name.
+    pub(crate) fn label(&mut self, prefix: &str) -> String {
+        let name = format!(".L{}{}", prefix, self.serial);
+        self.labels.push((name.clone(), self.bytes.len() as u64));
+        name
+    }
+}
+
+pub(crate) trait IREncoder {
+    /// `e_machine` of the ELF header.
+    const MACHINE: u16;
+    /// `e_flags` of the ELF header.
+    const FLAGS: u32;
+    /// The relocation of a `.quad` naming a symbol.
+    const ABSOLUTE_64: u32;
+    const TEXT_ALIGNMENT: u64;
+
+    fn encode(
+        &self,
+        mnemonic: &str,
+        operands: &[&str],
+        encoding: &mut IREncoding,
+    ) -> Result<(), String>;
+
+    /// Writes the PC-relative `value` of a fixup into `bytes`, which start at the fixup, or
+    /// returns `false` to leave the fixup to the linker.
+    fn resolve(&self, relocation: u32, bytes: &mut [u8], value: i64) -> bool;
+}
+
+/// Splits operands at the commas outside of parentheses and brackets.
+pub(crate) fn split_operands(text: &str) -> Vec<&str> {
+    let mut operands = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, character) in text.char_indices() {
+        match character {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                operands.push(text[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    if !text[start..].trim().is_empty() {
+        operands.push(text[start..].trim());
+    }
+    operands
+}
+
+/// Parses a decimal or `0x` hexadecimal integer with an optional sign.
+pub(crate) fn parse_integer(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<u64>().ok()?,
+    } as i64;
+    Some(if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    })
+}
+
+/// Splits a symbol reference like `name+8` or `name-4` into the name and the addend.
+pub(crate) fn parse_symbol(text: &str) -> Option<(&str, i64)> {
+    let (name, addend) = match text.get(1..)?.find(['+', '-']) {
+        Some(index) => {
+            let (name, addend) = text.split_at(index + 1);
+            (name, parse_integer(addend)?)
+        }
+        None => (text, 0),
+    };
+    let valid = name
+        .bytes()
+        .all(|byte| byte.is_ascii_alphanumeric() || b"_.$".contains(&byte));
+    (valid && !name.as_bytes()[0].is_ascii_digit()).then_some((name, addend))
+}
+
+struct IRObjectSection {
+    name: String,
+    kind: u32,
+    flags: u64,
+    alignment: u64,
+    bytes: Vec<u8>,
+    /// The size of `SHT_NOBITS` sections, which have no bytes.
+    size: u64,
+    fixups: Vec<IRFixup>,
+}
+
+impl IRObjectSection {
+    fn new(name: &str, text_alignment: u64) -> Option<Self> {
+        let (kind, flags, alignment) = match name {
+            _ if name == ".text" || name.starts_with(".text.") => {
+                (SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, text_alignment)
+            }
+            _ if name == ".data" || name.starts_with(".data.") => {
+                (SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, 1)
+            }
+            _ if name == ".bss" || name.starts_with(".bss.") => {
+                (SHT_NOBITS, SHF_ALLOC | SHF_WRITE, 1)
+            }
+            _ if name == ".rodata" || name.starts_with(".rodata.") => (SHT_PROGBITS, SHF_ALLOC, 1),
+            ".init_array" => (SHT_INIT_ARRAY, SHF_ALLOC | SHF_WRITE, 8),
+            ".note.GNU-stack" => (SHT_PROGBITS, 0, 1),
+            _ => return None,
+        };
+        Some(Self {
+            name: name.to_string(),
+            kind,
+            flags,
+            alignment,
+            bytes: vec![],
+            size: 0,
+            fixups: vec![],
+        })
+    }
+
+    fn offset(&self) -> u64 {
+        if self.kind == SHT_NOBITS {
+            self.size
+        } else {
+            self.bytes.len() as u64
+        }
+    }
+}
+
+/// A section as written to the file, with the relocation and table sections included.
+struct IRSectionHeader {
+    name: String,
+    kind: u32,
+    flags: u64,
+    contents: Vec<u8>,
+    size: u64,
+    link: u32,
+    info: u32,
+    alignment: u64,
+    entry_size: u64,
+}
+
+impl IRSectionHeader {
+    fn table(name: &str, kind: u32, contents: Vec<u8>) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            flags: 0,
+            size: contents.len() as u64,
+            contents,
+            link: 0,
+            info: 0,
+            alignment: 1,
+            entry_size: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct IRObjectSymbol {
+    section: usize,
+    value: u64,
+}
+
+struct IRObjectAssembler<'a, E: IREncoder> {
+    encoder: &'a E,
+    sections: Vec<IRObjectSection>,
+    current: Option<usize>,
+    symbols: IndexMap<String, IRObjectSymbol>,
+    globals: IndexSet<String>,
+    types: IndexMap<String, u8>,
+    sizes: IndexMap<String, u64>,
+    /// How many times each numeric label has been defined so far.
+    numeric_labels: IndexMap<String, usize>,
+    serial: usize,
+}
+
+pub(crate) fn generate_object<E: IREncoder>(
+    assembly: &str,
+    encoder: &E,
+) -> IRGenerateResult<Vec<u8>> {
+    let mut assembler = IRObjectAssembler {
+        encoder,
+        sections: vec![],
+        current: None,
+        symbols: IndexMap::new(),
+        globals: IndexSet::new(),
+        types: IndexMap::new(),
+        sizes: IndexMap::new(),
+        numeric_labels: IndexMap::new(),
+        serial: 0,
+    };
+    for line in assembly.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        assembler
+            .assemble_line(line)
+            .map_err(|message| IRGenerateError::Unsupported {
+                location: IRLocation::Module,
+                message: format!("'{}' in an object file: {}", line, message),
+            })?;
+    }
+    Ok(assembler.write())
+}
+
+impl<E: IREncoder> IRObjectAssembler<'_, E> {
+    fn section(&mut self) -> Result<&mut IRObjectSection, String> {
+        match self.current {
+            Some(current) => Ok(&mut self.sections[current]),
+            None => Err("no section".to_string()),
+        }
+    }
+
+    fn switch_section(&mut self, name: &str) -> Result<(), String> {
+        let index = match self
+            .sections
+            .iter()
+            .position(|section| section.name == name)
+        {
+            Some(index) => index,
+            None => {
+                let section = IRObjectSection::new(name, E::TEXT_ALIGNMENT)
+                    .ok_or_else(|| "unknown section".to_string())?;
+                self.sections.push(section);
+                self.sections.len() - 1
+            }
+        };
+        self.current = Some(index);
+        Ok(())
+    }
+
+    /// The name numeric label references like `1b` and `1f` stand for.
+    fn numeric_label(&self, reference: &str) -> Option<String> {
+        let (number, direction) = reference.split_at(reference.len().checked_sub(1)?);
+        if number.is_empty() || !number.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        let defined = self.numeric_labels.get(number).copied().unwrap_or(0);
+        let instance = match direction {
+            "b" => defined.checked_sub(1)?,
+            "f" => defined,
+            _ => return None,
+        };
+        Some(format!(".Lnumeric.{}.{}", number, instance))
+    }
+
+    fn define(&mut self, name: &str) -> Result<(), String> {
+        let name = if name.bytes().all(|byte| byte.is_ascii_digit()) {
+            let defined = self.numeric_labels.entry(name.to_string()).or_insert(0);
+            *defined += 1;
+            format!(".Lnumeric.{}.{}", name, *defined - 1)
+        } else {
+            name.to_string()
+        };
+        let section = self
+            .current
+            .ok_or_else(|| "label outside of a section".to_string())?;
+        let value = self.sections[section].offset();
+        if self
+            .symbols
+            .insert(name, IRObjectSymbol { section, value })
+            .is_some()
+        {
+            return Err("symbol defined twice".to_string());
+        }
+        Ok(())
+    }
+
+    fn assemble_line(&mut self, line: &str) -> Result<(), String> {
+        if let Some(label) = line.strip_suffix(':') {
+            return self.define(label);
+        }
+        let (mnemonic, rest) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(mnemonic, rest)| (mnemonic, rest.trim()));
+        if mnemonic.starts_with('.') {
+            return self.assemble_directive(mnemonic, rest);
+        }
+        let operands = split_operands(rest)
+            .into_iter()
+            .map(|operand| {
+                self.numeric_label(operand)
+                    .unwrap_or_else(|| operand.to_string())
+            })
+            .collect::<Vec<_>>();
+        let operands = operands.iter().map(String::as_str).collect::<Vec<_>>();
+        self.serial += 1;
+        let mut encoding = IREncoding {
+            serial: self.serial,
+            ..IREncoding::default()
+        };
+        self.encoder.encode(mnemonic, &operands, &mut encoding)?;
+        let section = self.section()?;
+        if section.kind == SHT_NOBITS {
+            return Err("instruction in a section without contents".to_string());
+        }
+        let start = section.bytes.len() as u64;
+        section.bytes.extend(encoding.bytes);
+        section
+            .fixups
+            .extend(encoding.fixups.into_iter().map(|fixup| IRFixup {
+                offset: start + fixup.offset,
+                ..fixup
+            }));
+        let section = self.current.unwrap();
+        for (name, offset) in encoding.labels {
+            self.symbols.insert(
+                name,
+                IRObjectSymbol {
+                    section,
+                    value: start + offset,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn assemble_directive(&mut self, directive: &str, arguments: &str) -> Result<(), String> {
+        let arguments = split_operands(arguments);
+        match directive {
+            ".text" | ".data" | ".bss" => self.switch_section(directive),
+            ".section" => self.switch_section(arguments.first().copied().unwrap_or_default()),
+            ".globl" | ".global" => {
+                self.globals
+                    .extend(arguments.iter().map(|name| name.to_string()));
+                Ok(())
+            }
+            ".type" => {
+                let [name, kind] = arguments[..] else {
+                    return Err("expected a symbol and a type".to_string());
+                };
+                let kind = match &kind[1..] {
+                    "function" => STT_FUNC,
+                    "object" => STT_OBJECT,
+                    _ => STT_NOTYPE,
+                };
+                self.types.insert(name.to_string(), kind);
+                Ok(())
+            }
+            ".size" => {
+                let [name, size] = arguments[..] else {
+                    return Err("expected a symbol and a size".to_string());
+                };
+                let size = match size.strip_prefix(".-") {
+                    Some(start) => {
+                        let start = self
+                            .symbols
+                            .get(start)
+                            .ok_or_else(|| format!("undefined symbol '{}'", start))?
+                            .value;
+                        self.section()?.offset() - start
+                    }
+                    None => parse_integer(size).ok_or_else(|| "invalid size".to_string())? as u64,
+                };
+                self.sizes.insert(name.to_string(), size);
+                Ok(())
+            }
+            ".balign" | ".p2align" => {
+                let value = arguments
+                    .first()
+                    .and_then(|value| parse_integer(value))
+                    .ok_or_else(|| "invalid alignment".to_string())?
+                    as u64;
+                let alignment = if directive == ".p2align" {
+                    1 << value
+                } else {
+                    value
+                };
+                let section = self.section()?;
+                section.alignment = section.alignment.max(alignment);
+                let padding = section.offset().next_multiple_of(alignment) - section.offset();
+                self.fill(padding)
+            }
+            ".zero" | ".skip" | ".space" => {
+                let size = arguments
+                    .first()
+                    .and_then(|size| parse_integer(size))
+                    .ok_or_else(|| "invalid size".to_string())?;
+                self.fill(size as u64)
+            }
+            ".byte" | ".short" | ".hword" | ".2byte" | ".long" | ".word" | ".4byte" | ".quad"
+            | ".xword" | ".8byte" => {
+                let size = match directive {
+                    ".byte" => 1,
+                    ".short" | ".hword" | ".2byte" => 2,
+                    ".long" | ".word" | ".4byte" => 4,
+                    _ => 8,
+                };
+                for argument in arguments {
+                    self.data(size, argument)?;
+                }
+                Ok(())
+            }
+            _ => Err("unknown directive".to_string()),
+        }
+    }
+
+    fn fill(&mut self, size: u64) -> Result<(), String> {
+        let section = self.section()?;
+        if section.kind == SHT_NOBITS {
+            section.size += size;
+        } else {
+            section.bytes.resize(section.bytes.len() + size as usize, 0);
+        }
+        Ok(())
+    }
+
+    fn data(&mut self, size: usize, value: &str) -> Result<(), String> {
+        let section = self.section()?;
+        if section.kind == SHT_NOBITS {
+            return Err("data in a section without contents".to_string());
+        }
+        if let Some(value) = parse_integer(value) {
+            section.bytes.extend(&value.to_le_bytes()[..size]);
+            return Ok(());
+        }
+        let (symbol, addend) = parse_symbol(value).ok_or_else(|| "invalid value".to_string())?;
+        if size != 8 {
+            return Err("symbol in data narrower than 8 bytes".to_string());
+        }
+        section.fixups.push(IRFixup {
+            offset: section.bytes.len() as u64,
+            symbol: symbol.to_string(),
+            relocation: E::ABSOLUTE_64,
+            addend,
+        });
+        section.bytes.extend([0; 8]);
+        Ok(())
+    }
+
+    /// Resolves what can be resolved and lays out the object file.
+    fn write(mut self) -> Vec<u8> {
+        let mut relocations = vec![vec![]; self.sections.len()];
+        for (index, section) in self.sections.iter_mut().enumerate() {
+            for fixup in std::mem::take(&mut section.fixups) {
+                let symbol = self.symbols.get(&fixup.symbol);
+                if let Some(symbol) = symbol
+                    && symbol.section == index
+                    && !self.globals.contains(&fixup.symbol)
+                {
+                    let value = symbol.value as i64 + fixup.addend - fixup.offset as i64;
+                    let bytes = &mut section.bytes[fixup.offset as usize..];
+                    if self.encoder.resolve(fixup.relocation, bytes, value) {
+                        continue;
+                    }
+                }
+                relocations[index].push(fixup);
+            }
+        }
+
+        // Local symbols come first; labels only matter when a relocation refers to them.
+        let referenced = relocations
+            .iter()
+            .flatten()
+            .map(|fixup| fixup.symbol.as_str())
+            .collect::<IndexSet<_>>();
+        let mut symbol_table = vec![];
+        for (name, symbol) in self.symbols.iter() {
+            if !self.globals.contains(name)
+                && (!name.starts_with(".L") || referenced.contains(name.as_str()))
+            {
+                symbol_table.push((name.as_str(), STB_LOCAL, Some(*symbol)));
+            }
+        }
+        let first_global = symbol_table.len() + 1;
+        for name in self.globals.iter() {
+            symbol_table.push((name, STB_GLOBAL, self.symbols.get(name).copied()));
+        }
+        for name in referenced.iter() {
+            if !self.symbols.contains_key(*name) && !self.globals.contains(*name) {
+                symbol_table.push((name, STB_GLOBAL, None));
+            }
+        }
+        let symbol_indices = symbol_table
+            .iter()
+            .enumerate()
+            .map(|(index, (name, _, _))| (*name, index as u32 + 1))
+            .collect::<IndexMap<_, _>>();
+
+        let mut strings = vec![0];
+        let mut symbols = vec![0; 24];
+        for (name, binding, symbol) in symbol_table.iter() {
+            symbols.extend((strings.len() as u32).to_le_bytes());
+            strings.extend(name.bytes().chain([0]));
+            let kind = self.types.get(*name).copied().unwrap_or(STT_NOTYPE);
+            symbols.push(binding << 4 | kind);
+            symbols.push(0);
+            let section = symbol.map_or(0, |symbol| symbol.section as u16 + 1);
+            symbols.extend(section.to_le_bytes());
+            symbols.extend(symbol.map_or(0, |symbol| symbol.value).to_le_bytes());
+            symbols.extend(self.sizes.get(*name).copied().unwrap_or(0).to_le_bytes());
+        }
+
+        // Sections follow the null section in order, then the relocations, the symbol table and
+        // the string tables.
+        let relocation_count = relocations.iter().filter(|list| !list.is_empty()).count();
+        let symbol_table_index = (1 + self.sections.len() + relocation_count) as u32;
+        let mut headers = self
+            .sections
+            .iter()
+            .map(|section| IRSectionHeader {
+                name: section.name.clone(),
+                kind: section.kind,
+                flags: section.flags,
+                contents: section.bytes.clone(),
+                size: section.offset(),
+                link: 0,
+                info: 0,
+                alignment: section.alignment,
+                entry_size: 0,
+            })
+            .collect::<Vec<_>>();
+        for (index, list) in relocations.iter().enumerate() {
+            if list.is_empty() {
+                continue;
+            }
+            let mut contents = vec![];
+            for fixup in list {
+                contents.extend(fixup.offset.to_le_bytes());
+                let symbol = symbol_indices[fixup.symbol.as_str()] as u64;
+                contents.extend((symbol << 32 | fixup.relocation as u64).to_le_bytes());
+                contents.extend(fixup.addend.to_le_bytes());
+            }
+            headers.push(IRSectionHeader {
+                flags: SHF_INFO_LINK,
+                link: symbol_table_index,
+                info: index as u32 + 1,
+                alignment: 8,
+                entry_size: 24,
+                ..IRSectionHeader::table(
+                    &format!(".rela{}", self.sections[index].name),
+                    SHT_RELA,
+                    contents,
+                )
+            });
+        }
+        headers.push(IRSectionHeader {
+            link: symbol_table_index + 1,
+            info: first_global as u32,
+            alignment: 8,
+            entry_size: 24,
+            ..IRSectionHeader::table(".symtab", SHT_SYMTAB, symbols)
+        });
+        headers.push(IRSectionHeader::table(".strtab", SHT_STRTAB, strings));
+        let mut section_names = vec![0];
+        let mut name_offsets = vec![];
+        for header in headers.iter() {
+            name_offsets.push(section_names.len() as u32);
+            section_names.extend(header.name.bytes().chain([0]));
+        }
+        name_offsets.push(section_names.len() as u32);
+        section_names.extend(b".shstrtab\0");
+        headers.push(IRSectionHeader::table(
+            ".shstrtab",
+            SHT_STRTAB,
+            section_names,
+        ));
+
+        let mut output = vec![0; 64];
+        let mut offsets = vec![];
+        for header in headers.iter() {
+            output.resize(output.len().next_multiple_of(header.alignment as usize), 0);
+            offsets.push(output.len() as u64);
+            if header.kind != SHT_NOBITS {
+                output.extend(&header.contents);
+            }
+        }
+        output.resize(output.len().next_multiple_of(8), 0);
+        let section_headers = output.len() as u64;
+        output.extend([0; 64]);
+        for (index, header) in headers.iter().enumerate() {
+            output.extend(name_offsets[index].to_le_bytes());
+            output.extend(header.kind.to_le_bytes());
+            output.extend(header.flags.to_le_bytes());
+            output.extend(0u64.to_le_bytes());
+            output.extend(offsets[index].to_le_bytes());
+            output.extend(header.size.to_le_bytes());
+            output.extend(header.link.to_le_bytes());
+            output.extend(header.info.to_le_bytes());
+            output.extend(header.alignment.to_le_bytes());
+            output.extend(header.entry_size.to_le_bytes());
+        }
+
+        let mut header = vec![0x7F, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        header.extend(1u16.to_le_bytes());
+        header.extend(E::MACHINE.to_le_bytes());
+        header.extend(1u32.to_le_bytes());
+        header.extend(0u64.to_le_bytes());
+        header.extend(0u64.to_le_bytes());
+        header.extend(section_headers.to_le_bytes());
+        header.extend(E::FLAGS.to_le_bytes());
+        header.extend(64u16.to_le_bytes());
+        header.extend(0u16.to_le_bytes());
+        header.extend(0u16.to_le_bytes());
+        header.extend(64u16.to_le_bytes());
+        header.extend((headers.len() as u16 + 1).to_le_bytes());
+        header.extend((headers.len() as u16).to_le_bytes());
+        output[..64].copy_from_slice(&header);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::elf::x86_64::IRX86Encoder;
+
+    /// Encodes one line the way the object assembler does.
+    pub(super) fn encode<E: IREncoder>(encoder: &E, line: &str) -> IREncoding {
+        let (mnemonic, operands) = line.split_once(' ').unwrap_or((line, ""));
+        let mut encoding = IREncoding::default();
+        encoder
+            .encode(mnemonic, &split_operands(operands), &mut encoding)
+            .unwrap_or_else(|message| panic!("'{}': {}", line, message));
+        encoding
+    }
+
+    /// Checks that each line encodes to the given bytes, where fixups are left as zeros.
+    pub(super) fn assert_encodings<E: IREncoder>(encoder: &E, encodings: &[(&str, &[u8])]) {
+        for (line, bytes) in encodings {
+            assert_eq!(encode(encoder, line).bytes, *bytes, "'{}'", line);
+        }
+    }
+
+    pub(super) fn fixup(offset: u64, symbol: &str, relocation: u32, addend: i64) -> IRFixup {
+        IRFixup {
+            offset,
+            symbol: symbol.to_string(),
+            relocation,
+            addend,
+        }
+    }
+
+    /// Checks that each line encodes to the given bytes with a single fixup.
+    pub(super) fn assert_fixups<E: IREncoder>(encoder: &E, cases: &[(&str, &[u8], IRFixup)]) {
+        for (line, bytes, fixup) in cases {
+            let encoding = encode(encoder, line);
+            assert_eq!(encoding.bytes, *bytes, "'{}'", line);
+            assert_eq!(encoding.fixups, std::slice::from_ref(fixup), "'{}'", line);
+        }
+    }
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn string_at(bytes: &[u8], offset: usize) -> &str {
+        let length = bytes[offset..].iter().position(|&byte| byte == 0).unwrap();
+        std::str::from_utf8(&bytes[offset..offset + length]).unwrap()
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    pub(super) struct Section<'a> {
+        pub(super) name: &'a str,
+        kind: u32,
+        flags: u64,
+        link: u32,
+        info: u32,
+        alignment: u64,
+        entry_size: u64,
+        pub(super) contents: &'a [u8],
+    }
+
+    /// The sections of an object after the null section.
+    pub(super) fn sections(object: &[u8]) -> Vec<Section<'_>> {
+        let start = u64_at(object, 40) as usize;
+        let count = u16_at(object, 60) as usize;
+        let header = |index: usize| &object[start + index * 64..start + (index + 1) * 64];
+        let names = header(u16_at(object, 62) as usize);
+        let names = &object[u64_at(names, 24) as usize..];
+        (1..count)
+            .map(|index| {
+                let header = header(index);
+                let offset = u64_at(header, 24) as usize;
+                let size = u64_at(header, 32) as usize;
+                let kind = u32_at(header, 4);
+                Section {
+                    name: string_at(names, u32_at(header, 0) as usize),
+                    kind,
+                    flags: u64_at(header, 8),
+                    link: u32_at(header, 40),
+                    info: u32_at(header, 44),
+                    alignment: u64_at(header, 48),
+                    entry_size: u64_at(header, 56),
+                    contents: if kind == SHT_NOBITS {
+                        &[]
+                    } else {
+                        &object[offset..offset + size]
+                    },
+                }
+            })
+            .collect()
+    }
+
+    const OBJECT: &str = "\
+\t.section .rodata
+\t.balign 8
+.LC0:
+\t.quad 42
+\t.data
+\t.balign 8
+\t.globl table
+\t.type table, @object
+table:
+\t.quad f
+\t.quad table+8
+\t.size table, 16
+\t.bss
+\t.globl counter
+counter:
+\t.zero 8
+\t.section .text.f,\"ax\",@progbits
+\t.globl f
+\t.type f, @function
+f:
+\tmovq .LC0(%rip), %rax
+\tjmp .Lf.done
+\tnop
+.Lf.done:
+\tcall g
+\tret
+\t.size f, .-f
+\t.section .note.GNU-stack,\"\",@progbits
+";
+
+    #[test]
+    fn objects_are_relocatable_elf64_files() {
+        let object = generate_object(OBJECT, &IRX86Encoder).unwrap();
+        assert_eq!(object[..8], [0x7F, b'E', b'L', b'F', 2, 1, 1, 0]);
+        assert_eq!(u16_at(&object, 16), 1);
+        assert_eq!(u16_at(&object, 18), IRX86Encoder::MACHINE);
+        assert_eq!(u16_at(&object, 52), 64);
+        assert_eq!(u16_at(&object, 58), 64);
+        assert_eq!(u16_at(&object, 60), 11);
+        assert_eq!(u16_at(&object, 62), 10);
+        assert_eq!(u64_at(&object, 40) as usize, object.len() - 11 * 64);
+
+        let sections = sections(&object);
+        let layout = sections
+            .iter()
+            .map(|section| (section.name, section.kind, section.flags, section.alignment))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            layout,
+            [
+                (".rodata", SHT_PROGBITS, SHF_ALLOC, 8),
+                (".data", SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, 8),
+                (".bss", SHT_NOBITS, SHF_ALLOC | SHF_WRITE, 1),
+                (".text.f", SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, 16),
+                (".note.GNU-stack", SHT_PROGBITS, 0, 1),
+                (".rela.data", SHT_RELA, SHF_INFO_LINK, 8),
+                (".rela.text.f", SHT_RELA, SHF_INFO_LINK, 8),
+                (".symtab", SHT_SYMTAB, 0, 8),
+                (".strtab", SHT_STRTAB, 0, 1),
+                (".shstrtab", SHT_STRTAB, 0, 1),
+            ]
+        );
+        assert_eq!(sections[0].contents, 42u64.to_le_bytes());
+        // The jump within the section is resolved; the load and the call are left to the linker.
+        assert_eq!(
+            sections[3].contents,
+            [
+                0x48, 0x8B, 0x05, 0, 0, 0, 0, 0xE9, 1, 0, 0, 0, 0x90, 0xE8, 0, 0, 0, 0, 0xC3
+            ]
+        );
+
+        // Symbols: null, the local .LC0, then the globals with g undefined.
+        let symtab = &sections[7];
+        let strtab = sections[8].contents;
+        assert_eq!((symtab.link, symtab.info, symtab.entry_size), (9, 2, 24));
+        let symbols = symtab
+            .contents
+            .chunks(24)
+            .map(|symbol| {
+                (
+                    string_at(strtab, u32_at(symbol, 0) as usize),
+                    symbol[4],
+                    u16_at(symbol, 6),
+                    u64_at(symbol, 8),
+                    u64_at(symbol, 16),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            symbols,
+            [
+                ("", 0, 0, 0, 0),
+                (".LC0", STB_LOCAL << 4 | STT_NOTYPE, 1, 0, 0),
+                ("table", STB_GLOBAL << 4 | STT_OBJECT, 2, 0, 16),
+                ("counter", STB_GLOBAL << 4 | STT_NOTYPE, 3, 0, 0),
+                ("f", STB_GLOBAL << 4 | STT_FUNC, 4, 0, 19),
+                ("g", STB_GLOBAL << 4 | STT_NOTYPE, 0, 0, 0),
+            ]
+        );
+
+        // Relocations: offset, symbol index, type and addend.
+        let relocations = |section: &Section| {
+            assert_eq!((section.link, section.entry_size), (8, 24));
+            section
+                .contents
+                .chunks(24)
+                .map(|relocation| {
+                    let info = u64_at(relocation, 8);
+                    (
+                        u64_at(relocation, 0),
+                        info >> 32,
+                        info as u32,
+                        u64_at(relocation, 16) as i64,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sections[5].info, 2);
+        assert_eq!(relocations(&sections[5]), [(0, 4, 1, 0), (8, 2, 1, 8)]);
+        assert_eq!(sections[6].info, 4);
+        assert_eq!(relocations(&sections[6]), [(3, 1, 2, -4), (14, 5, 4, -4)]);
+    }
+}
@@ -0,0 +1,1150 @@
+//! Machine code for the A64 instructions the AArch64 backend emits, plus the common integer,
+//! memory and scalar floating point instructions `asm` blocks tend to use.
+
+use crate::backend::elf::{IREncoder, IREncoding, parse_integer, parse_symbol};
+
+const R_AARCH64_ABS64: u32 = 257;
+const R_AARCH64_ADR_PREL_LO21: u32 = 274;
+const R_AARCH64_ADR_PREL_PG_HI21: u32 = 275;
+const R_AARCH64_ADD_ABS_LO12_NC: u32 = 277;
+const R_AARCH64_LDST8_ABS_LO12_NC: u32 = 278;
+const R_AARCH64_CONDBR19: u32 = 280;
+const R_AARCH64_JUMP26: u32 = 282;
+const R_AARCH64_CALL26: u32 = 283;
+const R_AARCH64_LDST16_ABS_LO12_NC: u32 = 284;
+const R_AARCH64_LDST32_ABS_LO12_NC: u32 = 285;
+const R_AARCH64_LDST64_ABS_LO12_NC: u32 = 286;
+const R_AARCH64_ADR_GOT_PAGE: u32 = 311;
+const R_AARCH64_LD64_GOT_LO12_NC: u32 = 312;
+
+const CONDITIONS: [&str; 16] = [
+    "eq", "ne", "hs", "lo", "mi", "pl", "vs", "vc", "hi", "ls", "ge", "lt", "gt", "le", "al", "nv",
+];
+const EXTENDS: [&str; 8] = [
+    "uxtb", "uxth", "uxtw", "uxtx", "sxtb", "sxth", "sxtw", "sxtx",
+];
+
+pub(crate) struct IRAArch64Encoder;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IRA64RegisterKind {
+    X,
+    W,
+    S,
+    D,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct IRA64Register {
+    number: u32,
+    kind: IRA64RegisterKind,
+    /// Whether register 31 is `sp` rather than the zero register.
+    sp: bool,
+}
+
+impl IRA64Register {
+    fn parse(name: &str) -> Option<Self> {
+        let register = |number, kind, sp| Some(Self { number, kind, sp });
+        match name {
+            "sp" => return register(31, IRA64RegisterKind::X, true),
+            "wsp" => return register(31, IRA64RegisterKind::W, true),
+            "xzr" => return register(31, IRA64RegisterKind::X, false),
+            "wzr" => return register(31, IRA64RegisterKind::W, false),
+            "lr" => return register(30, IRA64RegisterKind::X, false),
+            "fp" => return register(29, IRA64RegisterKind::X, false),
+            _ => {}
+        }
+        let kind = match name.get(..1)? {
+            "x" => IRA64RegisterKind::X,
+            "w" => IRA64RegisterKind::W,
+            "s" => IRA64RegisterKind::S,
+            "d" => IRA64RegisterKind::D,
+            _ => return None,
+        };
+        let number = name[1..].parse().ok()?;
+        let limit = match kind {
+            IRA64RegisterKind::X | IRA64RegisterKind::W => 31,
+            _ => 32,
+        };
+        (number < limit && !name[1..].starts_with('0') || &name[1..] == "0").then_some(Self {
+            number,
+            kind,
+            sp: false,
+        })
+    }
+
+    fn sf(self) -> u32 {
+        matches!(self.kind, IRA64RegisterKind::X | IRA64RegisterKind::D) as u32
+    }
+
+    fn is_general(self) -> bool {
+        matches!(self.kind, IRA64RegisterKind::X | IRA64RegisterKind::W)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IRA64Addressing {
+    Offset,
+    PreIndex,
+    PostIndex,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum IRA64Operand {
+    Register(IRA64Register),
+    Immediate(i64),
+    /// `#0.0`, the only floating point immediate `fcmp` takes.
+    FloatZero,
+    Memory {
+        base: IRA64Register,
+        offset: i64,
+        addressing: IRA64Addressing,
+        /// `:lo12:symbol` or `:got_lo12:symbol` as the offset, with its addend.
+        symbol: Option<(String, i64, bool)>,
+    },
+    /// A symbol with its addend, or a word like a condition code.
+    Symbol(String, i64),
+    /// `:lo12:symbol` in `add`.
+    Low12(String, i64),
+    /// `:got:symbol` in `adrp`.
+    GotPage(String, i64),
+    Shift(u32, i64),
+    Extend(u32, i64),
+}
+
+impl IRA64Operand {
+    fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid operand '{}'", text);
+        if let Some(register) = IRA64Register::parse(text) {
+            return Ok(IRA64Operand::Register(register));
+        }
+        if let Some(immediate) = text.strip_prefix('#') {
+            if immediate == "0.0" {
+                return Ok(IRA64Operand::FloatZero);
+            }
+            return parse_integer(immediate)
+                .map(IRA64Operand::Immediate)
+                .ok_or_else(invalid);
+        }
+        let symbol = |text: &str| {
+            parse_symbol(text)
+                .map(|(symbol, addend)| (symbol.to_string(), addend))
+                .ok_or_else(invalid)
+        };
+        if let Some(text) = text.strip_prefix(":lo12:") {
+            let (symbol, addend) = symbol(text)?;
+            return Ok(IRA64Operand::Low12(symbol, addend));
+        }
+        if let Some(text) = text.strip_prefix(":got:") {
+            let (symbol, addend) = symbol(text)?;
+            return Ok(IRA64Operand::GotPage(symbol, addend));
+        }
+        if let Some(inner) = text.strip_prefix('[') {
+            let (inner, addressing) = match inner.strip_suffix("]!") {
+                Some(inner) => (inner, IRA64Addressing::PreIndex),
+                None => (
+                    inner.strip_suffix(']').ok_or_else(invalid)?,
+                    IRA64Addressing::Offset,
+                ),
+            };
+            let (base, offset) = inner.split_once(',').unwrap_or((inner, ""));
+            let base = IRA64Register::parse(base.trim())
+                .filter(|base| base.kind == IRA64RegisterKind::X)
+                .ok_or_else(invalid)?;
+            let offset = offset.trim();
+            let (offset, symbol) = if let Some(text) = offset.strip_prefix(":lo12:") {
+                let (symbol, addend) = symbol(text)?;
+                (0, Some((symbol, addend, false)))
+            } else if let Some(text) = offset.strip_prefix(":got_lo12:") {
+                let (symbol, addend) = symbol(text)?;
+                (0, Some((symbol, addend, true)))
+            } else if offset.is_empty() {
+                (0, None)
+            } else {
+                let offset = offset.strip_prefix('#').ok_or_else(invalid)?;
+                (parse_integer(offset).ok_or_else(invalid)?, None)
+            };
+            return Ok(IRA64Operand::Memory {
+                base,
+                offset,
+                addressing,
+                symbol,
+            });
+        }
+        let (name, amount) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let amount = match amount.trim().strip_prefix('#') {
+            Some(amount) => parse_integer(amount).ok_or_else(invalid)?,
+            None if amount.trim().is_empty() => 0,
+            None => return Err(invalid()),
+        };
+        if let Some(shift) = ["lsl", "lsr", "asr", "ror"]
+            .iter()
+            .position(|shift| *shift == name)
+        {
+            return Ok(IRA64Operand::Shift(shift as u32, amount));
+        }
+        if let Some(extend) = EXTENDS.iter().position(|extend| *extend == name) {
+            return Ok(IRA64Operand::Extend(extend as u32, amount));
+        }
+        let (symbol, addend) = symbol(text)?;
+        Ok(IRA64Operand::Symbol(symbol, addend))
+    }
+}
+
+/// The `N:immr:imms` encoding of a bitmask immediate for a logical instruction, if `value` is
+/// one: a rotated run of ones, repeated in elements of 2 to 64 bits.
+fn logical_immediate(value: u64, width: u32) -> Option<u32> {
+    let value = if width == 32 {
+        let low = value & 0xFFFF_FFFF;
+        low | low << 32
+    } else {
+        value
+    };
+    if value == 0 || value == u64::MAX {
+        return None;
+    }
+    let mut size = 64;
+    while size > 2 {
+        let half = size / 2;
+        let mask = (1u64 << half) - 1;
+        if value & mask != (value >> half) & mask {
+            break;
+        }
+        size = half;
+    }
+    let mask = if size == 64 {
+        u64::MAX
+    } else {
+        (1u64 << size) - 1
+    };
+    let element = value & mask;
+    let ones = element.count_ones();
+    let run = (1u64 << ones) - 1;
+    let rotate_left = |bits: u64, amount: u32| {
+        if amount == 0 {
+            bits
+        } else {
+            (bits << amount | bits >> (size - amount)) & mask
+        }
+    };
+    let rotation = (0..size).find(|rotation| rotate_left(element, *rotation) == run)?;
+    let n = (size == 64) as u32;
+    let imms = (!(size * 2 - 1) & 0x3F) | (ones - 1);
+    Some(n << 12 | rotation << 6 | imms)
+}
+
+fn condition(name: &str) -> Option<u32> {
+    let name = match name {
+        "cs" => "hs",
+        "cc" => "lo",
+        name => name,
+    };
+    CONDITIONS
+        .iter()
+        .position(|condition| *condition == name)
+        .map(|condition| condition as u32)
+}
+
+fn general(operand: &IRA64Operand) -> Result<IRA64Register, String> {
+    match operand {
+        IRA64Operand::Register(register) if register.is_general() => Ok(*register),
+        _ => Err("expected a general purpose register".to_string()),
+    }
+}
+
+fn floating(operand: &IRA64Operand) -> Result<IRA64Register, String> {
+    match operand {
+        IRA64Operand::Register(register) if !register.is_general() => Ok(*register),
+        _ => Err("expected a floating point register".to_string()),
+    }
+}
+
+/// The `ftype` field of scalar floating point instructions.
+fn float_type(register: IRA64Register) -> u32 {
+    (register.kind == IRA64RegisterKind::D) as u32 * 0x0040_0000
+}
+
+impl IREncoder for IRAArch64Encoder {
+    const MACHINE: u16 = 183;
+    const FLAGS: u32 = 0;
+    const ABSOLUTE_64: u32 = R_AARCH64_ABS64;
+    const TEXT_ALIGNMENT: u64 = 4;
+
+    fn encode(
+        &self,
+        mnemonic: &str,
+        operands: &[&str],
+        encoding: &mut IREncoding,
+    ) -> Result<(), String> {
+        let operands = operands
+            .iter()
+            .map(|operand| IRA64Operand::parse(operand))
+            .collect::<Result<Vec<_>, _>>()?;
+        let word = encode_instruction(mnemonic, &operands, encoding)?;
+        encoding.push_u32(word);
+        Ok(())
+    }
+
+    fn resolve(&self, relocation: u32, bytes: &mut [u8], value: i64) -> bool {
+        let (bits, shift) = match relocation {
+            R_AARCH64_JUMP26 | R_AARCH64_CALL26 => (26, 0),
+            R_AARCH64_CONDBR19 => (19, 5),
+            _ => return false,
+        };
+        if value % 4 != 0 || !(-(1 << (bits + 1))..1 << (bits + 1)).contains(&value) {
+            return false;
+        }
+        let mut word = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+        word |= ((value >> 2) as u32 & ((1 << bits) - 1)) << shift;
+        bytes[..4].copy_from_slice(&word.to_le_bytes());
+        true
+    }
+}
+
+fn encode_instruction(
+    mnemonic: &str,
+    operands: &[IRA64Operand],
+    encoding: &mut IREncoding,
+) -> Result<u32, String> {
+    use IRA64Operand::*;
+    let invalid = || "invalid operands".to_string();
+    let zero = |register: IRA64Register| IRA64Register {
+        number: 31,
+        sp: false,
+        ..register
+    };
+    if let Some(code) = mnemonic.strip_prefix("b.").and_then(condition) {
+        let [Symbol(label, addend)] = operands else {
+            return Err(invalid());
+        };
+        encoding.fixup(label, R_AARCH64_CONDBR19, *addend);
+        return Ok(0x5400_0000 | code);
+    }
+    Ok(match (mnemonic, operands) {
+        ("nop", []) => 0xD503_201F,
+        ("clrex", []) => 0xD503_3F5F,
+        ("isb", []) => 0xD503_3FDF,
+        ("dmb", [Symbol(option, 0)]) => {
+            let option = match option.as_str() {
+                "ish" => 0xB,
+                "ishld" => 0x9,
+                "ishst" => 0xA,
+                "sy" => 0xF,
+                _ => return Err(invalid()),
+            };
+            0xD503_30BF | option << 8
+        }
+        ("ret", []) => 0xD65F_03C0,
+        ("ret" | "br" | "blr", [target]) => {
+            let opcode = match mnemonic {
+                "br" => 0xD61F_0000,
+                "blr" => 0xD63F_0000,
+                _ => 0xD65F_0000,
+            };
+            opcode | general(target)?.number << 5
+        }
+        ("b" | "bl", [Symbol(label, addend)]) => {
+            let (opcode, relocation) = if mnemonic == "b" {
+                (0x1400_0000, R_AARCH64_JUMP26)
+            } else {
+                (0x9400_0000, R_AARCH64_CALL26)
+            };
+            encoding.fixup(label, relocation, *addend);
+            opcode
+        }
+        ("cbz" | "cbnz", [register, Symbol(label, addend)]) => {
+            let register = general(register)?;
+            encoding.fixup(label, R_AARCH64_CONDBR19, *addend);
+            register.sf() << 31
+                | 0x3400_0000
+                | ((mnemonic == "cbnz") as u32) << 24
+                | register.number
+        }
+        ("adrp", [target, Symbol(symbol, addend)]) => {
+            encoding.fixup(symbol, R_AARCH64_ADR_PREL_PG_HI21, *addend);
+            0x9000_0000 | general(target)?.number
+        }
+        ("adrp", [target, GotPage(symbol, addend)]) => {
+            encoding.fixup(symbol, R_AARCH64_ADR_GOT_PAGE, *addend);
+            0x9000_0000 | general(target)?.number
+        }
+        ("adr", [target, Symbol(symbol, addend)]) => {
+            encoding.fixup(symbol, R_AARCH64_ADR_PREL_LO21, *addend);
+            0x1000_0000 | general(target)?.number
+        }
+        ("add" | "sub" | "adds" | "subs", [target, source, rest @ ..]) => {
+            let subtract = mnemonic.starts_with("sub");
+            let flags = mnemonic.ends_with('s');
+            add_subtract(
+                subtract,
+                flags,
+                general(target)?,
+                general(source)?,
+                rest,
+                encoding,
+            )?
+        }
+        ("cmp" | "cmn", [source, rest @ ..]) => {
+            let source = general(source)?;
+            add_subtract(
+                mnemonic == "cmp",
+                true,
+                zero(source),
+                source,
+                rest,
+                encoding,
+            )?
+        }
+        ("neg", [target, source]) => {
+            let target = general(target)?;
+            add_subtract(
+                true,
+                false,
+                target,
+                zero(target),
+                std::slice::from_ref(source),
+                encoding,
+            )?
+        }
+        (
+            "and" | "orr" | "eor" | "ands" | "bic" | "orn" | "eon" | "bics",
+            [target, source, rest @ ..],
+        ) => logical(mnemonic, general(target)?, general(source)?, rest)?,
+        ("tst", [source, rest @ ..]) => {
+            let source = general(source)?;
+            logical("ands", zero(source), source, rest)?
+        }
+        ("mvn", [target, source]) => {
+            let target = general(target)?;
+            logical("orn", target, zero(target), std::slice::from_ref(source))?
+        }
+        ("mov", [target, Immediate(value)]) => move_immediate(general(target)?, *value)?,
+        ("mov", [target, source]) => {
+            let (target, source) = (general(target)?, general(source)?);
+            if target.sp || source.sp {
+                add_subtract(false, false, target, source, &[Immediate(0)], encoding)?
+            } else {
+                logical("orr", target, zero(target), &[Register(source)])?
+            }
+        }
+        ("movz" | "movn" | "movk", [target, Immediate(value), rest @ ..]) => {
+            let target = general(target)?;
+            let shift = match rest {
+                [] => 0,
+                [Shift(0, shift)] if shift % 16 == 0 && *shift < 64 => *shift as u32,
+                _ => return Err(invalid()),
+            };
+            let opcode = match mnemonic {
+                "movn" => 0,
+                "movz" => 2,
+                _ => 3,
+            };
+            if !(0..0x10000).contains(value) {
+                return Err(invalid());
+            }
+            target.sf() << 31
+                | opcode << 29
+                | 0x1280_0000
+                | (shift / 16) << 21
+                | (*value as u32) << 5
+                | target.number
+        }
+        ("mul" | "mneg" | "madd" | "msub", [target, left, right, rest @ ..]) => {
+            let target = general(target)?;
+            let accumulator = match rest {
+                [] => 31,
+                [accumulator] => general(accumulator)?.number,
+                _ => return Err(invalid()),
+            };
+            let subtract = matches!(mnemonic, "mneg" | "msub") as u32;
+            target.sf() << 31
+                | 0x1B00_0000
+                | general(right)?.number << 16
+                | subtract << 15
+                | accumulator << 10
+                | general(left)?.number << 5
+                | target.number
+        }
+        ("udiv" | "sdiv" | "lsl" | "lsr" | "asr" | "ror", [target, left, Register(right)]) => {
+            let target = general(target)?;
+            let opcode = match mnemonic {
+                "udiv" => 0x0800,
+                "sdiv" => 0x0C00,
+                "lsl" => 0x2000,
+                "lsr" => 0x2400,
+                "asr" => 0x2800,
+                _ => 0x2C00,
+            };
+            target.sf() << 31
+                | 0x1AC0_0000
+                | right.number << 16
+                | opcode
+                | general(left)?.number << 5
+                | target.number
+        }
+        ("lsl" | "lsr" | "asr", [target, source, Immediate(amount)]) => {
+            let target = general(target)?;
+            let width = if target.sf() == 1 { 64 } else { 32 };
+            if !(0..width).contains(amount) {
+                return Err(invalid());
+            }
+            let amount = *amount as u32;
+            let (immr, imms) = match mnemonic {
+                "lsl" => (
+                    (width as u32 - amount) % width as u32,
+                    width as u32 - 1 - amount,
+                ),
+                _ => (amount, width as u32 - 1),
+            };
+            bitfield(mnemonic == "asr", target, general(source)?, immr, imms)
+        }
+        ("sxtb" | "sxth" | "sxtw" | "uxtb" | "uxth", [target, source]) => {
+            let target = general(target)?;
+            let imms = match &mnemonic[3..] {
+                "b" => 7,
+                "h" => 15,
+                _ => 31,
+            };
+            bitfield(mnemonic.starts_with('s'), target, general(source)?, 0, imms)
+        }
+        ("csel" | "csinc", [target, left, right, Symbol(code, 0)]) => {
+            let target = general(target)?;
+            let code = condition(code).ok_or_else(invalid)?;
+            let increment = (mnemonic == "csinc") as u32;
+            target.sf() << 31
+                | 0x1A80_0000
+                | general(right)?.number << 16
+                | code << 12
+                | increment << 10
+                | general(left)?.number << 5
+                | target.number
+        }
+        ("cset", [target, Symbol(code, 0)]) => {
+            let target = general(target)?;
+            let code = condition(code).ok_or_else(invalid)?;
+            target.sf() << 31 | 0x1A9F_07E0 | (code ^ 1) << 12 | target.number
+        }
+        ("ldp" | "stp", [first, second, rest @ ..]) => {
+            load_store_pair(mnemonic == "ldp", first, second, rest)?
+        }
+        ("ldxr" | "ldaxr" | "ldar" | "stxr" | "stlxr" | "stlr", _)
+        | ("ldxrb" | "ldaxrb" | "ldarb" | "stxrb" | "stlxrb" | "stlrb", _)
+        | ("ldxrh" | "ldaxrh" | "ldarh" | "stxrh" | "stlxrh" | "stlrh", _) => {
+            exclusive(mnemonic, operands)?
+        }
+        (_, [register, memory, rest @ ..]) if is_load_store(mnemonic) => {
+            load_store(mnemonic, register, memory, rest, encoding)?
+        }
+        ("fmov", [target, source]) => {
+            let (Register(target), Register(source)) = (target, source) else {
+                return Err(invalid());
+            };
+            use IRA64RegisterKind::*;
+            let opcode = match (target.kind, source.kind) {
+                (D, X) => 0x9E67_0000,
+                (X, D) => 0x9E66_0000,
+                (S, W) => 0x1E27_0000,
+                (W, S) => 0x1E26_0000,
+                (S, S) => 0x1E20_4000,
+                (D, D) => 0x1E60_4000,
+                _ => return Err(invalid()),
+            };
+            opcode | source.number << 5 | target.number
+        }
+        ("fadd" | "fsub" | "fmul" | "fdiv" | "fmax" | "fmin", [target, left, right]) => {
+            let target = floating(target)?;
+            let opcode = match mnemonic {
+                "fmul" => 0x0800,
+                "fdiv" => 0x1800,
+                "fadd" => 0x2800,
+                "fsub" => 0x3800,
+                "fmax" => 0x4800,
+                _ => 0x5800,
+            };
+            0x1E20_0000
+                | float_type(target)
+                | floating(right)?.number << 16
+                | opcode
+                | floating(left)?.number << 5
+                | target.number
+        }
+        ("fneg" | "fabs" | "fsqrt", [target, source]) => {
+            let target = floating(target)?;
+            let opcode = match mnemonic {
+                "fneg" => 0x1E21_4000,
+                "fabs" => 0x1E20_C000,
+                _ => 0x1E21_C000,
+            };
+            opcode | float_type(target) | floating(source)?.number << 5 | target.number
+        }
+        ("fcvt", [target, source]) => {
+            let (target, source) = (floating(target)?, floating(source)?);
+            let opcode = match (target.kind, source.kind) {
+                (IRA64RegisterKind::D, IRA64RegisterKind::S) => 0x1E22_C000,
+                (IRA64RegisterKind::S, IRA64RegisterKind::D) => 0x1E62_4000,
+                _ => return Err(invalid()),
+            };
+            opcode | source.number << 5 | target.number
+        }
+        ("fcmp", [left, right]) => {
+            let left = floating(left)?;
+            let right = match right {
+                FloatZero => 0x8,
+                right => floating(right)?.number << 16,
+            };
+            0x1E20_2000 | float_type(left) | right | left.number << 5
+        }
+        ("scvtf" | "ucvtf", [target, source]) => {
+            let (target, source) = (floating(target)?, general(source)?);
+            let unsigned = (mnemonic == "ucvtf") as u32;
+            source.sf() << 31
+                | 0x1E22_0000
+                | float_type(target)
+                | unsigned << 16
+                | source.number << 5
+                | target.number
+        }
+        ("fcvtzs" | "fcvtzu", [target, source]) => {
+            let (target, source) = (general(target)?, floating(source)?);
+            let unsigned = (mnemonic == "fcvtzu") as u32;
+            target.sf() << 31
+                | 0x1E38_0000
+                | float_type(source)
+                | unsigned << 16
+                | source.number << 5
+                | target.number
+        }
+        ("svc", [Immediate(value)]) if (0..0x10000).contains(value) => {
+            0xD400_0001 | (*value as u32) << 5
+        }
+        _ => return Err("unknown instruction".to_string()),
+    })
+}
+
+fn add_subtract(
+    subtract: bool,
+    flags: bool,
+    target: IRA64Register,
+    source: IRA64Register,
+    rest: &[IRA64Operand],
+    encoding: &mut IREncoding,
+) -> Result<u32, String> {
+    use IRA64Operand::*;
+    let invalid = || "invalid operands".to_string();
+    let head = target.sf() << 31 | (flags as u32) << 29 | target.number | source.number << 5;
+    let operation = |subtract: bool| (subtract as u32) << 30;
+    Ok(match rest {
+        [Immediate(value), shift @ ..] => {
+            let value = match shift {
+                [] => *value,
+                [Shift(0, 12)] => *value << 12,
+                [Shift(0, 0)] => *value,
+                _ => return Err(invalid()),
+            };
+            let (subtract, value) = if value < 0 {
+                (!subtract, -value)
+            } else {
+                (subtract, value)
+            };
+            let (shift, value) = if value < 0x1000 {
+                (0, value)
+            } else if value & 0xFFF == 0 && value >> 12 < 0x1000 {
+                (1, value >> 12)
+            } else {
+                return Err("immediate out of range".to_string());
+            };
+            head | operation(subtract) | 0x1100_0000 | shift << 22 | (value as u32) << 10
+        }
+        [Low12(symbol, addend)] => {
+            encoding.fixup(symbol, R_AARCH64_ADD_ABS_LO12_NC, *addend);
+            head | operation(subtract) | 0x1100_0000
+        }
+        [Register(register), Extend(extend, amount)] => {
+            head | operation(subtract)
+                | 0x0B20_0000
+                | register.number << 16
+                | extend << 13
+                | (*amount as u32 & 7) << 10
+        }
+        [Register(register), shift @ ..] if target.sp || source.sp => {
+            let amount = match shift {
+                [] => 0,
+                [Shift(0, amount)] if (0..5).contains(amount) => *amount as u32,
+                _ => return Err(invalid()),
+            };
+            // `uxtx`, or `uxtw` for 32-bit operations, is a plain `lsl` next to `sp`.
+            let extend = 2 | target.sf();
+            head | operation(subtract)
+                | 0x0B20_0000
+                | register.number << 16
+                | extend << 13
+                | amount << 10
+        }
+        [Register(register), shift @ ..] => {
+            let (kind, amount) = match shift {
+                [] => (0, 0),
+                [Shift(kind, amount)] if *kind < 3 => (*kind, *amount as u32 & 0x3F),
+                _ => return Err(invalid()),
+            };
+            head | operation(subtract)
+                | 0x0B00_0000
+                | kind << 22
+                | register.number << 16
+                | amount << 10
+        }
+        _ => return Err(invalid()),
+    })
+}
+
+fn logical(
+    mnemonic: &str,
+    target: IRA64Register,
+    source: IRA64Register,
+    rest: &[IRA64Operand],
+) -> Result<u32, String> {
+    use IRA64Operand::*;
+    let invalid = || "invalid operands".to_string();
+    let (opcode, negate) = match mnemonic {
+        "and" => (0, 0),
+        "orr" => (1, 0),
+        "eor" => (2, 0),
+        "ands" => (3, 0),
+        "bic" => (0, 1),
+        "orn" => (1, 1),
+        "eon" => (2, 1),
+        _ => (3, 1),
+    };
+    let head = target.sf() << 31 | opcode << 29 | source.number << 5 | target.number;
+    Ok(match rest {
+        [Immediate(value)] if negate == 0 => {
+            let width = if target.sf() == 1 { 64 } else { 32 };
+            let bitmask = logical_immediate(*value as u64, width)
+                .ok_or_else(|| "immediate is not a bitmask".to_string())?;
+            head | 0x1200_0000 | bitmask << 10
+        }
+        [Register(register), shift @ ..] => {
+            let (kind, amount) = match shift {
+                [] => (0, 0),
+                [Shift(kind, amount)] => (*kind, *amount as u32 & 0x3F),
+                _ => return Err(invalid()),
+            };
+            head | 0x0A00_0000 | kind << 22 | negate << 21 | register.number << 16 | amount << 10
+        }
+        _ => return Err(invalid()),
+    })
+}
+
+/// `mov` with an immediate: `movz` or `movn` when one 16-bit chunk is enough, and `orr` with a
+/// bitmask immediate otherwise.
+fn move_immediate(target: IRA64Register, value: i64) -> Result<u32, String> {
+    let width = if target.sf() == 1 { 64 } else { 32 };
+    let mask = if width == 64 { u64::MAX } else { 0xFFFF_FFFF };
+    let value = value as u64 & mask;
+    for (opcode, bits) in [(2, value), (0, !value & mask)] {
+        for chunk in 0..width / 16 {
+            if bits & !(0xFFFF << (chunk * 16)) == 0 {
+                let immediate = (bits >> (chunk * 16)) as u32 & 0xFFFF;
+                return Ok(target.sf() << 31
+                    | opcode << 29
+                    | 0x1280_0000
+                    | chunk << 21
+                    | immediate << 5
+                    | target.number);
+            }
+        }
+    }
+    let bitmask = logical_immediate(value, width)
+        .ok_or_else(|| "immediate needs more than one instruction".to_string())?;
+    Ok(target.sf() << 31 | 1 << 29 | 0x1200_0000 | bitmask << 10 | 31 << 5 | target.number)
+}
+
+/// `sbfm` or `ubfm`, sized by the target.
+fn bitfield(
+    signed: bool,
+    target: IRA64Register,
+    source: IRA64Register,
+    immr: u32,
+    imms: u32,
+) -> u32 {
+    let opcode = if signed { 0x1300_0000 } else { 0x5300_0000 };
+    target.sf() << 31
+        | opcode
+        | target.sf() << 22
+        | immr << 16
+        | imms << 10
+        | source.number << 5
+        | target.number
+}
+
+fn is_load_store(mnemonic: &str) -> bool {
+    matches!(
+        mnemonic,
+        "ldr"
+            | "str"
+            | "ldrb"
+            | "strb"
+            | "ldrh"
+            | "strh"
+            | "ldrsb"
+            | "ldrsh"
+            | "ldrsw"
+            | "ldur"
+            | "stur"
+            | "ldurb"
+            | "sturb"
+            | "ldurh"
+            | "sturh"
+            | "ldursb"
+            | "ldursh"
+            | "ldursw"
+    )
+}
+
+/// Single register loads and stores: scaled unsigned offsets when possible, unscaled 9-bit
+/// offsets otherwise, and pre- and post-indexing.
+fn load_store(
+    mnemonic: &str,
+    register: &IRA64Operand,
+    memory: &IRA64Operand,
+    rest: &[IRA64Operand],
+    encoding: &mut IREncoding,
+) -> Result<u32, String> {
+    let invalid = || "invalid operands".to_string();
+    let IRA64Operand::Register(register) = register else {
+        return Err(invalid());
+    };
+    let IRA64Operand::Memory {
+        base,
+        offset,
+        addressing,
+        symbol,
+    } = memory
+    else {
+        return Err(invalid());
+    };
+    let (offset, addressing) = match rest {
+        [] => (*offset, *addressing),
+        [IRA64Operand::Immediate(offset)] if *addressing == IRA64Addressing::Offset => {
+            (*offset, IRA64Addressing::PostIndex)
+        }
+        _ => return Err(invalid()),
+    };
+    let unscaled = mnemonic.starts_with("ldu") || mnemonic.starts_with("stu");
+    let name = mnemonic
+        .replacen("ldur", "ldr", 1)
+        .replacen("stur", "str", 1);
+    let load = name.starts_with("ld");
+    let vector = !register.is_general();
+    let (size, opcode) = match (name.as_str(), register.kind) {
+        ("ldr" | "str", IRA64RegisterKind::X | IRA64RegisterKind::D) => (3, load as u32),
+        ("ldr" | "str", _) => (2, load as u32),
+        ("ldrb" | "strb", _) => (0, load as u32),
+        ("ldrh" | "strh", _) => (1, load as u32),
+        ("ldrsb", kind) => (0, 2 | (kind == IRA64RegisterKind::W) as u32),
+        ("ldrsh", kind) => (1, 2 | (kind == IRA64RegisterKind::W) as u32),
+        ("ldrsw", IRA64RegisterKind::X) => (2, 2),
+        _ => return Err(invalid()),
+    };
+    if vector && name.len() > 3 {
+        return Err(invalid());
+    }
+    let head =
+        size << 30 | (vector as u32) << 26 | opcode << 22 | base.number << 5 | register.number;
+    if let Some((symbol, addend, got)) = symbol {
+        let relocation = match (got, size) {
+            (true, 3) => R_AARCH64_LD64_GOT_LO12_NC,
+            (true, _) => return Err(invalid()),
+            (false, 0) => R_AARCH64_LDST8_ABS_LO12_NC,
+            (false, 1) => R_AARCH64_LDST16_ABS_LO12_NC,
+            (false, 2) => R_AARCH64_LDST32_ABS_LO12_NC,
+            (false, _) => R_AARCH64_LDST64_ABS_LO12_NC,
+        };
+        encoding.fixup(symbol, relocation, *addend);
+        return Ok(head | 0x3900_0000);
+    }
+    let scale = 1 << size;
+    let unscaled_offset = |mode: u32| {
+        if (-256..256).contains(&offset) {
+            Ok(head | 0x3800_0000 | (offset as u32 & 0x1FF) << 12 | mode << 10)
+        } else {
+            Err("offset out of range".to_string())
+        }
+    };
+    match addressing {
+        IRA64Addressing::PreIndex => unscaled_offset(3),
+        IRA64Addressing::PostIndex => unscaled_offset(1),
+        IRA64Addressing::Offset
+            if !unscaled && offset >= 0 && offset % scale == 0 && offset / scale < 0x1000 =>
+        {
+            Ok(head | 0x3900_0000 | ((offset / scale) as u32) << 10)
+        }
+        IRA64Addressing::Offset => unscaled_offset(0),
+    }
+}
+
+fn load_store_pair(
+    load: bool,
+    first: &IRA64Operand,
+    second: &IRA64Operand,
+    rest: &[IRA64Operand],
+) -> Result<u32, String> {
+    let invalid = || "invalid operands".to_string();
+    let (IRA64Operand::Register(first), IRA64Operand::Register(second)) = (first, second) else {
+        return Err(invalid());
+    };
+    let (base, offset, addressing) = match rest {
+        [
+            IRA64Operand::Memory {
+                base,
+                offset,
+                addressing,
+                symbol: None,
+            },
+        ] => (base, *offset, *addressing),
+        [
+            IRA64Operand::Memory {
+                base,
+                offset: 0,
+                addressing: IRA64Addressing::Offset,
+                symbol: None,
+            },
+            IRA64Operand::Immediate(offset),
+        ] => (base, *offset, IRA64Addressing::PostIndex),
+        _ => return Err(invalid()),
+    };
+    let (opcode, vector, scale) = match first.kind {
+        IRA64RegisterKind::X => (2, 0, 8),
+        IRA64RegisterKind::W => (0, 0, 4),
+        IRA64RegisterKind::D => (1, 1, 8),
+        IRA64RegisterKind::S => (0, 1, 4),
+    };
+    if first.kind != second.kind || offset % scale != 0 || !(-64..64).contains(&(offset / scale)) {
+        return Err(invalid());
+    }
+    let mode = match addressing {
+        IRA64Addressing::PostIndex => 1,
+        IRA64Addressing::Offset => 2,
+        IRA64Addressing::PreIndex => 3,
+    };
+    Ok(opcode << 30
+        | 0x2800_0000
+        | vector << 26
+        | mode << 23
+        | (load as u32) << 22
+        | ((offset / scale) as u32 & 0x7F) << 15
+        | second.number << 10
+        | base.number << 5
+        | first.number)
+}
+
+/// Exclusive and acquire/release loads and stores.
+fn exclusive(mnemonic: &str, operands: &[IRA64Operand]) -> Result<u32, String> {
+    let invalid = || "invalid operands".to_string();
+    let (name, byte_size) = match mnemonic.strip_suffix('b') {
+        Some(name) => (name, Some(0)),
+        None => match mnemonic.strip_suffix('h') {
+            Some(name) => (name, Some(1)),
+            None => (mnemonic, None),
+        },
+    };
+    let (status, register, memory) = match (name, operands) {
+        ("stxr" | "stlxr", [status, register, memory]) => {
+            (general(status)?.number, general(register)?, memory)
+        }
+        ("ldxr" | "ldaxr" | "ldar" | "stlr", [register, memory]) => {
+            (31, general(register)?, memory)
+        }
+        _ => return Err(invalid()),
+    };
+    let IRA64Operand::Memory {
+        base,
+        offset: 0,
+        addressing: IRA64Addressing::Offset,
+        symbol: None,
+    } = memory
+    else {
+        return Err(invalid());
+    };
+    let size = byte_size.unwrap_or(2 + register.sf());
+    // The `o2`, `L` and `o0` bits.
+    let opcode = match name {
+        "ldxr" => 0x0040_0000,
+        "ldaxr" => 0x0040_8000,
+        "ldar" => 0x00C0_8000,
+        "stxr" => 0,
+        "stlxr" => 0x0000_8000,
+        _ => 0x0080_8000,
+    };
+    Ok(size << 30
+        | 0x0800_0000
+        | opcode
+        | status << 16
+        | 31 << 10
+        | base.number << 5
+        | register.number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::elf::tests::{assert_encodings, assert_fixups, fixup, sections};
+    use crate::backend::elf::{IRFixup, generate_object};
+
+    /// As GNU as and llvm-mc encode them.
+    const ENCODINGS: &[(&str, &[u8])] = &[
+        ("stp x29, x30, [sp, #-16]!", &[0xFD, 0x7B, 0xBF, 0xA9]),
+        ("mov x29, sp", &[0xFD, 0x03, 0x00, 0x91]),
+        ("sub sp, sp, #272", &[0xFF, 0x43, 0x04, 0xD1]),
+        ("mov sp, x29", &[0xBF, 0x03, 0x00, 0x91]),
+        ("ldp x29, x30, [sp], #16", &[0xFD, 0x7B, 0xC1, 0xA8]),
+        ("ret", &[0xC0, 0x03, 0x5F, 0xD6]),
+        ("mov x0, x10", &[0xE0, 0x03, 0x0A, 0xAA]),
+        ("mov w0, w0", &[0xE0, 0x03, 0x00, 0x2A]),
+        ("mov x17, #264", &[0x11, 0x21, 0x80, 0xD2]),
+        ("movk x0, #1, lsl #16", &[0x20, 0x00, 0xA0, 0xF2]),
+        ("movz x0, #4660, lsl #32", &[0x80, 0x46, 0xC2, 0xD2]),
+        ("sub x17, x29, x17", &[0xB1, 0x03, 0x11, 0xCB]),
+        ("str x0, [x17]", &[0x20, 0x02, 0x00, 0xF9]),
+        ("ldr x0, [x29, #-16]", &[0xA0, 0x03, 0x5F, 0xF8]),
+        ("str x0, [x29, #-256]", &[0xA0, 0x03, 0x10, 0xF8]),
+        ("ldr x0, [x1, #8]", &[0x20, 0x04, 0x40, 0xF9]),
+        ("ldrsw x0, [x29, #-40]", &[0xA0, 0x83, 0x9D, 0xB8]),
+        ("str w0, [x29, #-8]", &[0xA0, 0x83, 0x1F, 0xB8]),
+        ("ldrb w0, [x29, #-168]", &[0xA0, 0x83, 0x55, 0x38]),
+        ("strb w1, [x29, #-24]", &[0xA1, 0x83, 0x1E, 0x38]),
+        ("ldrh w0, [x1]", &[0x20, 0x00, 0x40, 0x79]),
+        ("strh w0, [x1, #2]", &[0x20, 0x04, 0x00, 0x79]),
+        ("ldrsb x0, [x1]", &[0x20, 0x00, 0x80, 0x39]),
+        ("ldrsh x0, [x1]", &[0x20, 0x00, 0x80, 0x79]),
+        ("ldr d0, [x29, #-16]", &[0xA0, 0x03, 0x5F, 0xFC]),
+        ("str s0, [x0]", &[0x00, 0x00, 0x00, 0xBD]),
+        ("add x0, x0, x1", &[0x00, 0x00, 0x01, 0x8B]),
+        ("add w0, w0, w1", &[0x00, 0x00, 0x01, 0x0B]),
+        ("add x12, x12, #1", &[0x8C, 0x05, 0x00, 0x91]),
+        ("sub x0, x29, #16", &[0xA0, 0x43, 0x00, 0xD1]),
+        ("sub x0, x0, #4095", &[0x00, 0xFC, 0x3F, 0xD1]),
+        ("and x0, x0, x1", &[0x00, 0x00, 0x01, 0x8A]),
+        ("and x1, x1, #31", &[0x21, 0x10, 0x40, 0x92]),
+        ("orr x0, x0, x1", &[0x00, 0x00, 0x01, 0xAA]),
+        ("eor x0, x0, x1", &[0x00, 0x00, 0x01, 0xCA]),
+        ("mvn x0, x0", &[0xE0, 0x03, 0x20, 0xAA]),
+        ("neg x0, x0", &[0xE0, 0x03, 0x00, 0xCB]),
+        ("lsl x0, x0, x1", &[0x00, 0x20, 0xC1, 0x9A]),
+        ("lsr x0, x0, x1", &[0x00, 0x24, 0xC1, 0x9A]),
+        ("asr x0, x0, x1", &[0x00, 0x28, 0xC1, 0x9A]),
+        ("mul x0, x0, x1", &[0x00, 0x7C, 0x01, 0x9B]),
+        ("sdiv x2, x0, x1", &[0x02, 0x0C, 0xC1, 0x9A]),
+        ("udiv w0, w0, w1", &[0x00, 0x08, 0xC1, 0x1A]),
+        ("msub x0, x2, x1, x0", &[0x40, 0x80, 0x01, 0x9B]),
+        ("sxtw x0, w0", &[0x00, 0x7C, 0x40, 0x93]),
+        ("uxtb w0, w0", &[0x00, 0x1C, 0x00, 0x53]),
+        ("cmp x12, x11", &[0x9F, 0x01, 0x0B, 0xEB]),
+        ("ldaxr x12, [x9]", &[0x2C, 0xFD, 0x5F, 0xC8]),
+        ("stlxr w13, x10, [x9]", &[0x2A, 0xFD, 0x0D, 0xC8]),
+        ("clrex", &[0x5F, 0x3F, 0x03, 0xD5]),
+        ("blr x16", &[0x00, 0x02, 0x3F, 0xD6]),
+        ("br x16", &[0x00, 0x02, 0x1F, 0xD6]),
+        ("fmov d0, x0", &[0x00, 0x00, 0x67, 0x9E]),
+        ("fmov x9, d0", &[0x09, 0x00, 0x66, 0x9E]),
+        ("fmov s0, w0", &[0x00, 0x00, 0x27, 0x1E]),
+        ("fadd d0, d0, d1", &[0x00, 0x28, 0x61, 0x1E]),
+        ("fsub d0, d0, d1", &[0x00, 0x38, 0x61, 0x1E]),
+        ("fmul d0, d0, d1", &[0x00, 0x08, 0x61, 0x1E]),
+        ("fdiv s0, s0, s1", &[0x00, 0x18, 0x21, 0x1E]),
+        ("fcmp d0, d1", &[0x00, 0x20, 0x61, 0x1E]),
+        ("fcvt d0, s0", &[0x00, 0xC0, 0x22, 0x1E]),
+        ("fcvt s0, d0", &[0x00, 0x40, 0x62, 0x1E]),
+        ("fcvtzs x0, d0", &[0x00, 0x00, 0x78, 0x9E]),
+        ("scvtf d0, x0", &[0x00, 0x00, 0x62, 0x9E]),
+        ("ucvtf d0, x0", &[0x00, 0x00, 0x63, 0x9E]),
+    ];
+
+    #[test]
+    fn encodes_like_the_reference_assemblers() {
+        assert_encodings(&IRAArch64Encoder, ENCODINGS);
+    }
+
+    #[test]
+    fn symbols_are_left_as_fixups() {
+        let cases: &[(&str, &[u8], IRFixup)] = &[
+            (
+                "adrp x16, .LC0",
+                &[0x10, 0x00, 0x00, 0x90],
+                fixup(0, ".LC0", R_AARCH64_ADR_PREL_PG_HI21, 0),
+            ),
+            (
+                "add x16, x16, :lo12:.LC0",
+                &[0x10, 0x02, 0x00, 0x91],
+                fixup(0, ".LC0", R_AARCH64_ADD_ABS_LO12_NC, 0),
+            ),
+            (
+                "ldr x0, [x16, :lo12:counter]",
+                &[0x00, 0x02, 0x40, 0xF9],
+                fixup(0, "counter", R_AARCH64_LDST64_ABS_LO12_NC, 0),
+            ),
+            (
+                "ldr w0, [x16, :lo12:counter+4]",
+                &[0x00, 0x02, 0x40, 0xB9],
+                fixup(0, "counter", R_AARCH64_LDST32_ABS_LO12_NC, 4),
+            ),
+            (
+                "adrp x16, :got:puts",
+                &[0x10, 0x00, 0x00, 0x90],
+                fixup(0, "puts", R_AARCH64_ADR_GOT_PAGE, 0),
+            ),
+            (
+                "ldr x16, [x16, :got_lo12:puts]",
+                &[0x10, 0x02, 0x40, 0xF9],
+                fixup(0, "puts", R_AARCH64_LD64_GOT_LO12_NC, 0),
+            ),
+            (
+                "bl puts",
+                &[0x00, 0x00, 0x00, 0x94],
+                fixup(0, "puts", R_AARCH64_CALL26, 0),
+            ),
+            (
+                "b .Lf.loop",
+                &[0x00, 0x00, 0x00, 0x14],
+                fixup(0, ".Lf.loop", R_AARCH64_JUMP26, 0),
+            ),
+            (
+                "b.lt .Lf.loop",
+                &[0x0B, 0x00, 0x00, 0x54],
+                fixup(0, ".Lf.loop", R_AARCH64_CONDBR19, 0),
+            ),
+            (
+                "adr x0, .Lf.loop",
+                &[0x00, 0x00, 0x00, 0x10],
+                fixup(0, ".Lf.loop", R_AARCH64_ADR_PREL_LO21, 0),
+            ),
+        ];
+        assert_fixups(&IRAArch64Encoder, cases);
+    }
+
+    #[test]
+    fn branches_within_a_section_are_resolved() {
+        let object = generate_object(
+            "\t.text
+f:
+\tcbz x0, .Lf.done
+\tb.ne .Lf.done
+\tb .Lf.done
+\tmov x0, x1
+.Lf.done:
+\tcbnz x1, f
+\tb f
+\tret
+",
+            &IRAArch64Encoder,
+        )
+        .unwrap();
+        // As llvm-mc assembles the same text.
+        assert_eq!(
+            sections(&object)[0].contents,
+            [
+                0x80, 0x00, 0x00, 0xB4, 0x61, 0x00, 0x00, 0x54, 0x02, 0x00, 0x00, 0x14, 0xE0, 0x03,
+                0x01, 0xAA, 0x81, 0xFF, 0xFF, 0xB5, 0xFB, 0xFF, 0xFF, 0x17, 0xC0, 0x03, 0x5F, 0xD6,
+            ]
+        );
+    }
+}
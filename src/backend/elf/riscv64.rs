@@ -0,0 +1,930 @@
+//! Machine code for the RV64GC instructions and pseudo-instructions the RISC-V backend emits, plus
+//! the common integer, atomic and floating point instructions `asm` blocks tend to use.
+//!
+//! Everything is written in the uncompressed encoding.
+
+use crate::backend::elf::{IREncoder, IREncoding, parse_integer, parse_symbol};
+
+const R_RISCV_64: u32 = 2;
+const R_RISCV_BRANCH: u32 = 16;
+const R_RISCV_JAL: u32 = 17;
+const R_RISCV_CALL_PLT: u32 = 19;
+const R_RISCV_GOT_HI20: u32 = 20;
+const R_RISCV_PCREL_HI20: u32 = 23;
+const R_RISCV_PCREL_LO12_I: u32 = 24;
+const R_RISCV_PCREL_LO12_S: u32 = 25;
+const EF_RISCV_FLOAT_ABI_DOUBLE: u32 = 0x4;
+
+const LOAD: u32 = 0x03;
+const LOAD_FP: u32 = 0x07;
+const MISC_MEM: u32 = 0x0F;
+const OP_IMM: u32 = 0x13;
+const AUIPC: u32 = 0x17;
+const OP_IMM_32: u32 = 0x1B;
+const STORE: u32 = 0x23;
+const STORE_FP: u32 = 0x27;
+const AMO: u32 = 0x2F;
+const OP: u32 = 0x33;
+const LUI: u32 = 0x37;
+const OP_32: u32 = 0x3B;
+const OP_FP: u32 = 0x53;
+const BRANCH: u32 = 0x63;
+const JALR: u32 = 0x67;
+const JAL: u32 = 0x6F;
+
+const ZERO: u32 = 0;
+const RA: u32 = 1;
+const T1: u32 = 6;
+
+const LOADS: [(&str, u32); 7] = [
+    ("lb", 0),
+    ("lh", 1),
+    ("lw", 2),
+    ("ld", 3),
+    ("lbu", 4),
+    ("lhu", 5),
+    ("lwu", 6),
+];
+const STORES: [(&str, u32); 4] = [("sb", 0), ("sh", 1), ("sw", 2), ("sd", 3)];
+const IMMEDIATES: [(&str, u32, u32); 7] = [
+    ("addi", OP_IMM, 0),
+    ("slti", OP_IMM, 2),
+    ("sltiu", OP_IMM, 3),
+    ("xori", OP_IMM, 4),
+    ("ori", OP_IMM, 6),
+    ("andi", OP_IMM, 7),
+    ("addiw", OP_IMM_32, 0),
+];
+/// The shifts by an immediate, with the bits above the shift amount.
+const SHIFTS: [(&str, u32, u32, u32); 6] = [
+    ("slli", OP_IMM, 1, 0),
+    ("srli", OP_IMM, 5, 0),
+    ("srai", OP_IMM, 5, 0x400),
+    ("slliw", OP_IMM_32, 1, 0),
+    ("srliw", OP_IMM_32, 5, 0),
+    ("sraiw", OP_IMM_32, 5, 0x400),
+];
+const REGISTERS: [(&str, u32, u32, u32); 28] = [
+    ("add", OP, 0, 0x00),
+    ("sub", OP, 0, 0x20),
+    ("sll", OP, 1, 0x00),
+    ("slt", OP, 2, 0x00),
+    ("sltu", OP, 3, 0x00),
+    ("xor", OP, 4, 0x00),
+    ("srl", OP, 5, 0x00),
+    ("sra", OP, 5, 0x20),
+    ("or", OP, 6, 0x00),
+    ("and", OP, 7, 0x00),
+    ("mul", OP, 0, 0x01),
+    ("mulh", OP, 1, 0x01),
+    ("mulhsu", OP, 2, 0x01),
+    ("mulhu", OP, 3, 0x01),
+    ("div", OP, 4, 0x01),
+    ("divu", OP, 5, 0x01),
+    ("rem", OP, 6, 0x01),
+    ("remu", OP, 7, 0x01),
+    ("addw", OP_32, 0, 0x00),
+    ("subw", OP_32, 0, 0x20),
+    ("sllw", OP_32, 1, 0x00),
+    ("srlw", OP_32, 5, 0x00),
+    ("sraw", OP_32, 5, 0x20),
+    ("mulw", OP_32, 0, 0x01),
+    ("divw", OP_32, 4, 0x01),
+    ("divuw", OP_32, 5, 0x01),
+    ("remw", OP_32, 6, 0x01),
+    ("remuw", OP_32, 7, 0x01),
+];
+const BRANCHES: [(&str, u32); 6] = [
+    ("beq", 0),
+    ("bne", 1),
+    ("blt", 4),
+    ("bge", 5),
+    ("bltu", 6),
+    ("bgeu", 7),
+];
+const ATOMICS: [(&str, u32); 11] = [
+    ("amoadd", 0x00),
+    ("amoswap", 0x01),
+    ("lr", 0x02),
+    ("sc", 0x03),
+    ("amoxor", 0x04),
+    ("amoor", 0x08),
+    ("amoand", 0x0C),
+    ("amomin", 0x10),
+    ("amomax", 0x14),
+    ("amominu", 0x18),
+    ("amomaxu", 0x1C),
+];
+const ROUNDING_MODES: [(&str, u32); 6] = [
+    ("rne", 0),
+    ("rtz", 1),
+    ("rdn", 2),
+    ("rup", 3),
+    ("rmm", 4),
+    ("dyn", 7),
+];
+/// The rounding mode of floating point instructions that do not name one.
+const DYNAMIC: u32 = 7;
+
+pub(crate) struct IRRISCVEncoder;
+
+/// `offset(base)`, where the offset may also be `%pcrel_lo(label)`.
+struct IRRISCVMemory<'a> {
+    base: u32,
+    offset: i64,
+    low: Option<&'a str>,
+}
+
+fn numbered(text: &str, prefix: &str) -> Option<u32> {
+    let digits = text.strip_prefix(prefix)?;
+    let number = digits.parse().ok()?;
+    (number < 32 && (digits == "0" || !digits.starts_with('0'))).then_some(number)
+}
+
+fn integer(text: &str) -> Result<u32, String> {
+    const NAMES: [&str; 32] = [
+        "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+        "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+        "t5", "t6",
+    ];
+    match text {
+        "fp" => Some(8),
+        _ => NAMES
+            .iter()
+            .position(|name| *name == text)
+            .map(|number| number as u32)
+            .or_else(|| numbered(text, "x")),
+    }
+    .ok_or_else(|| format!("invalid register '{}'", text))
+}
+
+fn float(text: &str) -> Result<u32, String> {
+    const NAMES: [&str; 32] = [
+        "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2",
+        "fa3", "fa4", "fa5", "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9",
+        "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+    ];
+    NAMES
+        .iter()
+        .position(|name| *name == text)
+        .map(|number| number as u32)
+        .or_else(|| numbered(text, "f"))
+        .ok_or_else(|| format!("invalid register '{}'", text))
+}
+
+fn immediate(text: &str) -> Result<i64, String> {
+    parse_integer(text).ok_or_else(|| format!("invalid immediate '{}'", text))
+}
+
+/// The argument of a relocation function like `%pcrel_lo(label)`.
+fn relocation_function<'a>(text: &'a str, function: &str) -> Option<&'a str> {
+    text.strip_prefix(function)?
+        .strip_prefix('(')?
+        .strip_suffix(')')
+}
+
+fn memory(text: &str) -> Result<IRRISCVMemory<'_>, String> {
+    let invalid = || format!("invalid operand '{}'", text);
+    let start = text.rfind('(').ok_or_else(invalid)?;
+    let base = integer(text[start + 1..].strip_suffix(')').ok_or_else(invalid)?)?;
+    let offset = &text[..start];
+    if let Some(label) = relocation_function(offset, "%pcrel_lo") {
+        return Ok(IRRISCVMemory {
+            base,
+            offset: 0,
+            low: Some(label),
+        });
+    }
+    Ok(IRRISCVMemory {
+        base,
+        offset: if offset.is_empty() {
+            0
+        } else {
+            immediate(offset)?
+        },
+        low: None,
+    })
+}
+
+fn symbol(text: &str) -> Result<(&str, i64), String> {
+    parse_symbol(text).ok_or_else(|| format!("invalid symbol '{}'", text))
+}
+
+fn check_signed(value: i64, bits: u32) -> Result<u32, String> {
+    if (-(1 << (bits - 1))..1 << (bits - 1)).contains(&value) {
+        Ok(value as u32 & ((1 << bits) - 1))
+    } else {
+        Err(format!("immediate {} out of range", value))
+    }
+}
+
+fn r_type(opcode: u32, rd: u32, funct3: u32, rs1: u32, rs2: u32, funct7: u32) -> u32 {
+    funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+
+fn i_type(opcode: u32, rd: u32, funct3: u32, rs1: u32, immediate: i64) -> Result<u32, String> {
+    Ok(check_signed(immediate, 12)? << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode)
+}
+
+fn s_type(opcode: u32, funct3: u32, rs1: u32, rs2: u32, immediate: i64) -> Result<u32, String> {
+    let immediate = check_signed(immediate, 12)?;
+    Ok((immediate >> 5) << 25
+        | rs2 << 20
+        | rs1 << 15
+        | funct3 << 12
+        | (immediate & 0x1F) << 7
+        | opcode)
+}
+
+fn u_type(opcode: u32, rd: u32, immediate: i64) -> Result<u32, String> {
+    if !(0..1 << 20).contains(&immediate) {
+        return Err(format!("immediate {} out of range", immediate));
+    }
+    Ok((immediate as u32) << 12 | rd << 7 | opcode)
+}
+
+fn b_immediate(value: i64) -> u32 {
+    let value = value as u32;
+    (value >> 12 & 1) << 31
+        | (value >> 5 & 0x3F) << 25
+        | (value >> 1 & 0xF) << 8
+        | (value >> 11 & 1) << 7
+}
+
+fn j_immediate(value: i64) -> u32 {
+    let value = value as u32;
+    (value >> 20 & 1) << 31
+        | (value >> 1 & 0x3FF) << 21
+        | (value >> 11 & 1) << 20
+        | (value >> 12 & 0xFF) << 12
+}
+
+/// `lui`/`addiw` for 32-bit values, otherwise the upper bits followed by `slli` and `addi`.
+fn load_immediate(rd: u32, value: i64, encoding: &mut IREncoding) -> Result<(), String> {
+    let low = value << 52 >> 52;
+    if i32::try_from(value).is_ok() {
+        let high = (value.wrapping_add(0x800) >> 12) & 0xF_FFFF;
+        if high == 0 {
+            encoding.push_u32(i_type(OP_IMM, rd, 0, ZERO, low)?);
+        } else {
+            encoding.push_u32(u_type(LUI, rd, high)?);
+            if low != 0 {
+                encoding.push_u32(i_type(OP_IMM_32, rd, 0, rd, low)?);
+            }
+        }
+        return Ok(());
+    }
+    let rest = value.wrapping_sub(low);
+    let shift = rest.trailing_zeros();
+    load_immediate(rd, rest >> shift, encoding)?;
+    encoding.push_u32(i_type(OP_IMM, rd, 1, rd, shift as i64)?);
+    if low != 0 {
+        encoding.push_u32(i_type(OP_IMM, rd, 0, rd, low)?);
+    }
+    Ok(())
+}
+
+/// An immediate operand, or `%pcrel_lo(label)` as a fixup.
+fn low_immediate(text: &str, relocation: u32, encoding: &mut IREncoding) -> Result<i64, String> {
+    match relocation_function(text, "%pcrel_lo") {
+        Some(label) => {
+            let (label, addend) = symbol(label)?;
+            encoding.fixup(label, relocation, addend);
+            Ok(0)
+        }
+        None => immediate(text),
+    }
+}
+
+fn memory_offset(
+    memory: &IRRISCVMemory,
+    relocation: u32,
+    encoding: &mut IREncoding,
+) -> Result<i64, String> {
+    if let Some(label) = memory.low {
+        let (label, addend) = symbol(label)?;
+        encoding.fixup(label, relocation, addend);
+    }
+    Ok(memory.offset)
+}
+
+fn branch(
+    funct3: u32,
+    rs1: u32,
+    rs2: u32,
+    target: &str,
+    encoding: &mut IREncoding,
+) -> Result<(), String> {
+    let (target, addend) = symbol(target)?;
+    encoding.fixup(target, R_RISCV_BRANCH, addend);
+    encoding.push_u32(r_type(BRANCH, 0, funct3, rs1, rs2, 0));
+    Ok(())
+}
+
+fn jump(rd: u32, target: &str, encoding: &mut IREncoding) -> Result<(), String> {
+    let (target, addend) = symbol(target)?;
+    encoding.fixup(target, R_RISCV_JAL, addend);
+    encoding.push_u32(rd << 7 | JAL);
+    Ok(())
+}
+
+/// `auipc` and `jalr` through `link`, which is left to the linker.
+fn call(link: u32, rd: u32, target: &str, encoding: &mut IREncoding) -> Result<(), String> {
+    let (target, addend) = symbol(target)?;
+    encoding.fixup(target, R_RISCV_CALL_PLT, addend);
+    encoding.push_u32(link << 7 | AUIPC);
+    encoding.push_u32(i_type(JALR, rd, 0, link, 0)?);
+    Ok(())
+}
+
+impl IREncoder for IRRISCVEncoder {
+    const MACHINE: u16 = 243;
+    const FLAGS: u32 = EF_RISCV_FLOAT_ABI_DOUBLE;
+    const ABSOLUTE_64: u32 = R_RISCV_64;
+    const TEXT_ALIGNMENT: u64 = 4;
+
+    fn encode(
+        &self,
+        mnemonic: &str,
+        operands: &[&str],
+        encoding: &mut IREncoding,
+    ) -> Result<(), String> {
+        if mnemonic.starts_with('f') && !mnemonic.starts_with("fence") {
+            return encode_floating(mnemonic, operands, encoding);
+        }
+        if mnemonic.starts_with("amo") || mnemonic.starts_with("lr.") || mnemonic.starts_with("sc.")
+        {
+            return encode_atomic(mnemonic, operands, encoding);
+        }
+        encode_integer(mnemonic, operands, encoding)
+    }
+
+    fn resolve(&self, relocation: u32, bytes: &mut [u8], value: i64) -> bool {
+        let immediate = match relocation {
+            R_RISCV_BRANCH if value % 2 == 0 && (-(1 << 12)..1 << 12).contains(&value) => {
+                b_immediate(value)
+            }
+            R_RISCV_JAL if value % 2 == 0 && (-(1 << 20)..1 << 20).contains(&value) => {
+                j_immediate(value)
+            }
+            _ => return false,
+        };
+        let word = u32::from_le_bytes(bytes[..4].try_into().unwrap()) | immediate;
+        bytes[..4].copy_from_slice(&word.to_le_bytes());
+        true
+    }
+}
+
+fn encode_integer(
+    mnemonic: &str,
+    operands: &[&str],
+    encoding: &mut IREncoding,
+) -> Result<(), String> {
+    let invalid = || "invalid operands".to_string();
+    if let Some((_, funct3)) = LOADS.iter().find(|(name, _)| *name == mnemonic) {
+        let [rd, address] = operands else {
+            return Err(invalid());
+        };
+        let address = memory(address)?;
+        let offset = memory_offset(&address, R_RISCV_PCREL_LO12_I, encoding)?;
+        encoding.push_u32(i_type(LOAD, integer(rd)?, *funct3, address.base, offset)?);
+        return Ok(());
+    }
+    if let Some((_, funct3)) = STORES.iter().find(|(name, _)| *name == mnemonic) {
+        let [rs2, address] = operands else {
+            return Err(invalid());
+        };
+        let address = memory(address)?;
+        let offset = memory_offset(&address, R_RISCV_PCREL_LO12_S, encoding)?;
+        encoding.push_u32(s_type(STORE, *funct3, address.base, integer(rs2)?, offset)?);
+        return Ok(());
+    }
+    if let Some((_, opcode, funct3)) = IMMEDIATES.iter().find(|(name, ..)| *name == mnemonic) {
+        let [rd, rs1, value] = operands else {
+            return Err(invalid());
+        };
+        let value = low_immediate(value, R_RISCV_PCREL_LO12_I, encoding)?;
+        encoding.push_u32(i_type(
+            *opcode,
+            integer(rd)?,
+            *funct3,
+            integer(rs1)?,
+            value,
+        )?);
+        return Ok(());
+    }
+    if let Some((_, opcode, funct3, high)) = SHIFTS.iter().find(|(name, ..)| *name == mnemonic) {
+        let [rd, rs1, amount] = operands else {
+            return Err(invalid());
+        };
+        let limit = if *opcode == OP_IMM { 64 } else { 32 };
+        let amount = immediate(amount)?;
+        if !(0..limit).contains(&amount) {
+            return Err(format!("shift amount {} out of range", amount));
+        }
+        encoding.push_u32(i_type(
+            *opcode,
+            integer(rd)?,
+            *funct3,
+            integer(rs1)?,
+            *high as i64 | amount,
+        )?);
+        return Ok(());
+    }
+    if let Some((_, opcode, funct3, funct7)) = REGISTERS.iter().find(|(name, ..)| *name == mnemonic)
+    {
+        let [rd, rs1, rs2] = operands else {
+            return Err(invalid());
+        };
+        encoding.push_u32(r_type(
+            *opcode,
+            integer(rd)?,
+            *funct3,
+            integer(rs1)?,
+            integer(rs2)?,
+            *funct7,
+        ));
+        return Ok(());
+    }
+    if let Some((_, funct3)) = BRANCHES.iter().find(|(name, _)| *name == mnemonic) {
+        let [rs1, rs2, target] = operands else {
+            return Err(invalid());
+        };
+        return branch(*funct3, integer(rs1)?, integer(rs2)?, target, encoding);
+    }
+    let word = match (mnemonic, operands) {
+        ("nop", []) => i_type(OP_IMM, ZERO, 0, ZERO, 0)?,
+        ("ret", []) => i_type(JALR, ZERO, 0, RA, 0)?,
+        ("ecall", []) => 0x0000_0073,
+        ("ebreak", []) => 0x0010_0073,
+        ("fence", []) => 0x0FF0_000F,
+        ("fence.i", []) => 0x0000_100F,
+        ("fence.tso", []) => 0x8330_000F,
+        ("fence", [predecessor, successor]) => {
+            let set = |text: &str| {
+                let mut bits = 0;
+                for access in text.chars() {
+                    bits |= match access {
+                        'i' => 8,
+                        'o' => 4,
+                        'r' => 2,
+                        'w' => 1,
+                        _ => return Err(invalid()),
+                    };
+                }
+                Ok(bits)
+            };
+            set(predecessor)? << 24 | set(successor)? << 20 | MISC_MEM
+        }
+        ("lui", [rd, value]) => u_type(LUI, integer(rd)?, immediate(value)?)?,
+        ("auipc", [rd, value]) => {
+            let function = [
+                ("%pcrel_hi", R_RISCV_PCREL_HI20),
+                ("%got_pcrel_hi", R_RISCV_GOT_HI20),
+            ]
+            .iter()
+            .find_map(|(function, relocation)| {
+                relocation_function(value, function).map(|target| (target, *relocation))
+            });
+            match function {
+                Some((target, relocation)) => {
+                    let (target, addend) = symbol(target)?;
+                    encoding.fixup(target, relocation, addend);
+                    integer(rd)? << 7 | AUIPC
+                }
+                None => u_type(AUIPC, integer(rd)?, immediate(value)?)?,
+            }
+        }
+        ("lla", [rd, target]) => {
+            let rd = integer(rd)?;
+            let (target, addend) = symbol(target)?;
+            let label = encoding.label("pcrel_hi");
+            encoding.fixup(target, R_RISCV_PCREL_HI20, addend);
+            encoding.push_u32(rd << 7 | AUIPC);
+            encoding.fixup(&label, R_RISCV_PCREL_LO12_I, 0);
+            i_type(OP_IMM, rd, 0, rd, 0)?
+        }
+        ("li", [rd, value]) => return load_immediate(integer(rd)?, immediate(value)?, encoding),
+        ("mv", [rd, rs]) => i_type(OP_IMM, integer(rd)?, 0, integer(rs)?, 0)?,
+        ("not", [rd, rs]) => i_type(OP_IMM, integer(rd)?, 4, integer(rs)?, -1)?,
+        ("neg", [rd, rs]) => r_type(OP, integer(rd)?, 0, ZERO, integer(rs)?, 0x20),
+        ("negw", [rd, rs]) => r_type(OP_32, integer(rd)?, 0, ZERO, integer(rs)?, 0x20),
+        ("sext.w", [rd, rs]) => i_type(OP_IMM_32, integer(rd)?, 0, integer(rs)?, 0)?,
+        ("zext.b", [rd, rs]) => i_type(OP_IMM, integer(rd)?, 7, integer(rs)?, 0xFF)?,
+        ("seqz", [rd, rs]) => i_type(OP_IMM, integer(rd)?, 3, integer(rs)?, 1)?,
+        ("snez", [rd, rs]) => r_type(OP, integer(rd)?, 3, ZERO, integer(rs)?, 0),
+        ("sltz", [rd, rs]) => r_type(OP, integer(rd)?, 2, integer(rs)?, ZERO, 0),
+        ("sgtz", [rd, rs]) => r_type(OP, integer(rd)?, 2, ZERO, integer(rs)?, 0),
+        ("beqz", [rs, target]) => return branch(0, integer(rs)?, ZERO, target, encoding),
+        ("bnez", [rs, target]) => return branch(1, integer(rs)?, ZERO, target, encoding),
+        ("bltz", [rs, target]) => return branch(4, integer(rs)?, ZERO, target, encoding),
+        ("bgez", [rs, target]) => return branch(5, integer(rs)?, ZERO, target, encoding),
+        ("bgtz", [rs, target]) => return branch(4, ZERO, integer(rs)?, target, encoding),
+        ("blez", [rs, target]) => return branch(5, ZERO, integer(rs)?, target, encoding),
+        ("bgt", [rs1, rs2, target]) => {
+            return branch(4, integer(rs2)?, integer(rs1)?, target, encoding);
+        }
+        ("ble", [rs1, rs2, target]) => {
+            return branch(5, integer(rs2)?, integer(rs1)?, target, encoding);
+        }
+        ("bgtu", [rs1, rs2, target]) => {
+            return branch(6, integer(rs2)?, integer(rs1)?, target, encoding);
+        }
+        ("bleu", [rs1, rs2, target]) => {
+            return branch(7, integer(rs2)?, integer(rs1)?, target, encoding);
+        }
+        ("j", [target]) => return jump(ZERO, target, encoding),
+        ("jal", [target]) => return jump(RA, target, encoding),
+        ("jal", [rd, target]) => return jump(integer(rd)?, target, encoding),
+        ("jr", [address]) if address.ends_with(')') => {
+            let address = memory(address)?;
+            i_type(JALR, ZERO, 0, address.base, address.offset)?
+        }
+        ("jr", [rs]) => i_type(JALR, ZERO, 0, integer(rs)?, 0)?,
+        ("jalr", [rs]) => i_type(JALR, RA, 0, integer(rs)?, 0)?,
+        ("jalr", [rd, address]) if address.ends_with(')') => {
+            let address = memory(address)?;
+            i_type(JALR, integer(rd)?, 0, address.base, address.offset)?
+        }
+        ("jalr", [rd, rs]) => i_type(JALR, integer(rd)?, 0, integer(rs)?, 0)?,
+        ("jalr", [rd, rs, offset]) => {
+            i_type(JALR, integer(rd)?, 0, integer(rs)?, immediate(offset)?)?
+        }
+        ("call", [target]) => return call(RA, RA, target, encoding),
+        ("tail", [target]) => return call(T1, ZERO, target, encoding),
+        _ => return Err(invalid()),
+    };
+    encoding.push_u32(word);
+    Ok(())
+}
+
+/// `lr`, `sc` and the `amo` instructions, with an optional `.aq`, `.rl` or `.aqrl` ordering.
+fn encode_atomic(
+    mnemonic: &str,
+    operands: &[&str],
+    encoding: &mut IREncoding,
+) -> Result<(), String> {
+    let invalid = || "invalid operands".to_string();
+    let mut parts = mnemonic.split('.');
+    let name = parts.next().unwrap_or_default();
+    let Some((_, funct5)) = ATOMICS.iter().find(|(candidate, _)| *candidate == name) else {
+        return Err(invalid());
+    };
+    let funct3 = match parts.next() {
+        Some("w") => 2,
+        Some("d") => 3,
+        _ => return Err(invalid()),
+    };
+    let ordering = match parts.next() {
+        None => 0,
+        Some("aq") => 2,
+        Some("rl") => 1,
+        Some("aqrl") => 3,
+        Some(_) => return Err(invalid()),
+    };
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+    let (rd, rs2, address) = match (name, operands) {
+        ("lr", [rd, address]) => (integer(rd)?, ZERO, memory(address)?),
+        (_, [rd, rs2, address]) if name != "lr" => (integer(rd)?, integer(rs2)?, memory(address)?),
+        _ => return Err(invalid()),
+    };
+    if address.offset != 0 || address.low.is_some() {
+        return Err(invalid());
+    }
+    encoding.push_u32(r_type(
+        AMO,
+        rd,
+        funct3,
+        address.base,
+        rs2,
+        funct5 << 2 | ordering,
+    ));
+    Ok(())
+}
+
+/// The F and D extensions, with an optional rounding mode as the last operand.
+fn encode_floating(
+    mnemonic: &str,
+    operands: &[&str],
+    encoding: &mut IREncoding,
+) -> Result<(), String> {
+    let invalid = || "invalid operands".to_string();
+    let (operands, rounding) = match operands.split_last() {
+        Some((last, rest)) => match ROUNDING_MODES.iter().find(|(name, _)| name == last) {
+            Some((_, mode)) => (rest, Some(*mode)),
+            None => (operands, None),
+        },
+        None => (operands, None),
+    };
+    let parts = mnemonic.split('.').collect::<Vec<_>>();
+    let format = |text: &str| match text {
+        "s" => Ok(0),
+        "d" => Ok(1),
+        _ => Err(invalid()),
+    };
+    let integer_type = |text: &str| match text {
+        "w" => Some(0),
+        "wu" => Some(1),
+        "l" => Some(2),
+        "lu" => Some(3),
+        _ => None,
+    };
+    let operation = |funct5: u32, fmt: u32, rd: u32, funct3: u32, rs1: u32, rs2: u32| {
+        r_type(OP_FP, rd, funct3, rs1, rs2, funct5 << 2 | fmt)
+    };
+    let word = match (parts.as_slice(), operands) {
+        (["flw" | "fld"], [rd, address]) | (["fsw" | "fsd"], [rd, address]) => {
+            let address = memory(address)?;
+            let width = if mnemonic.ends_with('w') { 2 } else { 3 };
+            if mnemonic.starts_with("fl") {
+                let offset = memory_offset(&address, R_RISCV_PCREL_LO12_I, encoding)?;
+                i_type(LOAD_FP, float(rd)?, width, address.base, offset)?
+            } else {
+                let offset = memory_offset(&address, R_RISCV_PCREL_LO12_S, encoding)?;
+                s_type(STORE_FP, width, address.base, float(rd)?, offset)?
+            }
+        }
+        (
+            [
+                name @ ("fadd" | "fsub" | "fmul" | "fdiv" | "fmin" | "fmax"),
+                fmt,
+            ],
+            [rd, rs1, rs2],
+        ) => {
+            let (funct5, funct3) = match *name {
+                "fadd" => (0x00, rounding.unwrap_or(DYNAMIC)),
+                "fsub" => (0x01, rounding.unwrap_or(DYNAMIC)),
+                "fmul" => (0x02, rounding.unwrap_or(DYNAMIC)),
+                "fdiv" => (0x03, rounding.unwrap_or(DYNAMIC)),
+                "fmin" => (0x05, 0),
+                _ => (0x05, 1),
+            };
+            operation(
+                funct5,
+                format(fmt)?,
+                float(rd)?,
+                funct3,
+                float(rs1)?,
+                float(rs2)?,
+            )
+        }
+        ([name @ ("fsgnj" | "fsgnjn" | "fsgnjx"), fmt], [rd, rs1, rs2]) => {
+            let funct3 = match *name {
+                "fsgnj" => 0,
+                "fsgnjn" => 1,
+                _ => 2,
+            };
+            operation(
+                0x04,
+                format(fmt)?,
+                float(rd)?,
+                funct3,
+                float(rs1)?,
+                float(rs2)?,
+            )
+        }
+        ([name @ ("fmv" | "fneg" | "fabs"), fmt], [rd, rs]) => {
+            let funct3 = match *name {
+                "fmv" => 0,
+                "fneg" => 1,
+                _ => 2,
+            };
+            let rs = float(rs)?;
+            operation(0x04, format(fmt)?, float(rd)?, funct3, rs, rs)
+        }
+        (["fsqrt", fmt], [rd, rs]) => operation(
+            0x0B,
+            format(fmt)?,
+            float(rd)?,
+            rounding.unwrap_or(DYNAMIC),
+            float(rs)?,
+            0,
+        ),
+        ([name @ ("feq" | "flt" | "fle" | "fgt" | "fge"), fmt], [rd, rs1, rs2]) => {
+            let (funct3, rs1, rs2) = match *name {
+                "feq" => (2, rs1, rs2),
+                "flt" => (1, rs1, rs2),
+                "fle" => (0, rs1, rs2),
+                "fgt" => (1, rs2, rs1),
+                _ => (0, rs2, rs1),
+            };
+            operation(
+                0x14,
+                format(fmt)?,
+                integer(rd)?,
+                funct3,
+                float(rs1)?,
+                float(rs2)?,
+            )
+        }
+        (["fclass", fmt], [rd, rs]) => {
+            operation(0x1C, format(fmt)?, integer(rd)?, 1, float(rs)?, 0)
+        }
+        (["fmv", "x", fmt @ ("w" | "d")], [rd, rs]) => {
+            operation(0x1C, (*fmt == "d") as u32, integer(rd)?, 0, float(rs)?, 0)
+        }
+        (["fmv", fmt @ ("w" | "d"), "x"], [rd, rs]) => {
+            operation(0x1E, (*fmt == "d") as u32, float(rd)?, 0, integer(rs)?, 0)
+        }
+        (["fcvt", to, from], [rd, rs]) => {
+            match (integer_type(to), integer_type(from)) {
+                (Some(to), None) => operation(
+                    0x18,
+                    format(from)?,
+                    integer(rd)?,
+                    rounding.unwrap_or(DYNAMIC),
+                    float(rs)?,
+                    to,
+                ),
+                // Widening conversions are exact, so they are written with `rne`.
+                (None, Some(from)) => {
+                    let exact = *to == "d" && from < 2;
+                    operation(
+                        0x1A,
+                        format(to)?,
+                        float(rd)?,
+                        rounding.unwrap_or(if exact { 0 } else { DYNAMIC }),
+                        integer(rs)?,
+                        from,
+                    )
+                }
+                (None, None) => {
+                    let (to, from) = (format(to)?, format(from)?);
+                    let exact = to == 1 && from == 0;
+                    operation(
+                        0x08,
+                        to,
+                        float(rd)?,
+                        rounding.unwrap_or(if exact { 0 } else { DYNAMIC }),
+                        float(rs)?,
+                        from,
+                    )
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        ([name @ ("fmadd" | "fmsub" | "fnmsub" | "fnmadd"), fmt], [rd, rs1, rs2, rs3]) => {
+            let opcode = match *name {
+                "fmadd" => 0x43,
+                "fmsub" => 0x47,
+                "fnmsub" => 0x4B,
+                _ => 0x4F,
+            };
+            float(rs3)? << 27
+                | r_type(
+                    opcode,
+                    float(rd)?,
+                    rounding.unwrap_or(DYNAMIC),
+                    float(rs1)?,
+                    float(rs2)?,
+                    format(fmt)?,
+                )
+        }
+        _ => return Err(invalid()),
+    };
+    encoding.push_u32(word);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::elf::tests::{assert_encodings, assert_fixups, encode, fixup, sections};
+    use crate::backend::elf::{IRFixup, generate_object};
+
+    /// As GNU as and llvm-mc encode them, without compressed instructions.
+    const ENCODINGS: &[(&str, &[u8])] = &[
+        ("addi sp, sp, -16", &[0x13, 0x01, 0x01, 0xFF]),
+        ("sd ra, 8(sp)", &[0x23, 0x34, 0x11, 0x00]),
+        ("ld ra, 8(sp)", &[0x83, 0x30, 0x81, 0x00]),
+        ("sw a0, -20(s0)", &[0x23, 0x26, 0xA4, 0xFE]),
+        ("lw a0, -20(s0)", &[0x03, 0x25, 0xC4, 0xFE]),
+        ("lbu a0, 0(a0)", &[0x03, 0x45, 0x05, 0x00]),
+        ("sb a0, -17(s0)", &[0xA3, 0x07, 0xA4, 0xFE]),
+        ("fld fa0, -16(s0)", &[0x07, 0x35, 0x04, 0xFF]),
+        ("fsd fa0, 8(sp)", &[0x27, 0x34, 0xA1, 0x00]),
+        ("flw fa0, 0(a0)", &[0x07, 0x25, 0x05, 0x00]),
+        ("mv a1, a0", &[0x93, 0x05, 0x05, 0x00]),
+        ("li a1, 1", &[0x93, 0x05, 0x10, 0x00]),
+        ("li a0, -1", &[0x13, 0x05, 0xF0, 0xFF]),
+        (
+            "li a0, 100000",
+            &[0x37, 0x85, 0x01, 0x00, 0x1B, 0x05, 0x05, 0x6A],
+        ),
+        (
+            "li t0, 0x123456789",
+            &[
+                0xB7, 0x22, 0x09, 0x00, 0x9B, 0x82, 0xB2, 0xA2, 0x93, 0x92, 0xD2, 0x00, 0x93, 0x82,
+                0x92, 0x78,
+            ],
+        ),
+        ("not a0, a0", &[0x13, 0x45, 0xF5, 0xFF]),
+        ("neg a0, a0", &[0x33, 0x05, 0xA0, 0x40]),
+        ("sext.w a0, a0", &[0x1B, 0x05, 0x05, 0x00]),
+        ("add a0, a0, a1", &[0x33, 0x05, 0xB5, 0x00]),
+        ("and a0, a0, a1", &[0x33, 0x75, 0xB5, 0x00]),
+        ("andi a0, a0, 255", &[0x13, 0x75, 0xF5, 0x0F]),
+        ("or a0, a0, a1", &[0x33, 0x65, 0xB5, 0x00]),
+        ("xor a0, a0, a1", &[0x33, 0x45, 0xB5, 0x00]),
+        ("sll a0, a0, a1", &[0x33, 0x15, 0xB5, 0x00]),
+        ("srl a0, a0, a1", &[0x33, 0x55, 0xB5, 0x00]),
+        ("sra a0, a0, a1", &[0x33, 0x55, 0xB5, 0x40]),
+        ("slli a0, a0, 32", &[0x13, 0x15, 0x05, 0x02]),
+        ("srli a0, a0, 32", &[0x13, 0x55, 0x05, 0x02]),
+        ("mul a0, a0, a1", &[0x33, 0x05, 0xB5, 0x02]),
+        ("div a0, a0, a1", &[0x33, 0x45, 0xB5, 0x02]),
+        ("divu a0, a0, a1", &[0x33, 0x55, 0xB5, 0x02]),
+        ("rem a0, a0, a1", &[0x33, 0x65, 0xB5, 0x02]),
+        ("remw a0, a0, a1", &[0x3B, 0x65, 0xB5, 0x02]),
+        ("amoadd.d.aqrl a0, a1, (t0)", &[0x2F, 0xB5, 0xB2, 0x06]),
+        ("lr.w.aqrl t1, (a0)", &[0x2F, 0x23, 0x05, 0x16]),
+        ("sc.w.aqrl t2, t1, (a0)", &[0xAF, 0x23, 0x65, 0x1E]),
+        ("fmv.d.x fa0, a0", &[0x53, 0x05, 0x05, 0xF2]),
+        ("fmv.x.d a0, fa0", &[0x53, 0x05, 0x05, 0xE2]),
+        ("fadd.d fa0, fa0, fa1", &[0x53, 0x75, 0xB5, 0x02]),
+        ("fsub.s fa0, fa0, fa1", &[0x53, 0x75, 0xB5, 0x08]),
+        ("fmul.d fa0, fa0, fa1", &[0x53, 0x75, 0xB5, 0x12]),
+        ("flt.d a0, fa0, fa1", &[0x53, 0x15, 0xB5, 0xA2]),
+        ("feq.d a0, fa0, fa1", &[0x53, 0x25, 0xB5, 0xA2]),
+        ("fcvt.l.d a0, fa0, rtz", &[0x53, 0x15, 0x25, 0xC2]),
+        ("fcvt.d.l fa0, a0", &[0x53, 0x75, 0x25, 0xD2]),
+        ("fcvt.s.d fa0, fa1", &[0x53, 0xF5, 0x15, 0x40]),
+        ("fcvt.d.s fa0, fa1", &[0x53, 0x85, 0x05, 0x42]),
+        ("jalr t0", &[0xE7, 0x80, 0x02, 0x00]),
+        ("ret", &[0x67, 0x80, 0x00, 0x00]),
+    ];
+
+    #[test]
+    fn encodes_like_the_reference_assemblers() {
+        assert_encodings(&IRRISCVEncoder, ENCODINGS);
+    }
+
+    #[test]
+    fn symbols_are_left_as_fixups() {
+        let cases: &[(&str, &[u8], IRFixup)] = &[
+            (
+                "call puts",
+                &[0x97, 0x00, 0x00, 0x00, 0xE7, 0x80, 0x00, 0x00],
+                fixup(0, "puts", R_RISCV_CALL_PLT, 0),
+            ),
+            (
+                "beq a0, a1, .Lf.loop",
+                &[0x63, 0x00, 0xB5, 0x00],
+                fixup(0, ".Lf.loop", R_RISCV_BRANCH, 0),
+            ),
+            (
+                "j .Lf.loop",
+                &[0x6F, 0x00, 0x00, 0x00],
+                fixup(0, ".Lf.loop", R_RISCV_JAL, 0),
+            ),
+        ];
+        assert_fixups(&IRRISCVEncoder, cases);
+    }
+
+    #[test]
+    fn pc_relative_addresses_pair_their_halves_through_a_label() {
+        // The low half refers to the label on the auipc, not to the symbol.
+        let encoding = encode(&IRRISCVEncoder, "lla a0, .LC4+8");
+        assert_eq!(
+            encoding.bytes,
+            [0x17, 0x05, 0x00, 0x00, 0x13, 0x05, 0x05, 0x00]
+        );
+        assert_eq!(encoding.labels, [(".Lpcrel_hi0".to_string(), 0)]);
+        assert_eq!(
+            encoding.fixups,
+            [
+                fixup(0, ".LC4", R_RISCV_PCREL_HI20, 8),
+                fixup(4, ".Lpcrel_hi0", R_RISCV_PCREL_LO12_I, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn branches_within_a_section_are_resolved() {
+        let object = generate_object(
+            "\
+\t.text
+f:
+\tbeq a0, a1, .Lf.done
+\tj .Lf.done
+\tmv a0, a1
+.Lf.done:
+\tbnez a0, f
+\tj f
+\tret
+",
+            &IRRISCVEncoder,
+        )
+        .unwrap();
+        // As llvm-mc assembles the same text with relaxation off.
+        assert_eq!(
+            sections(&object)[0].contents,
+            [
+                0x63, 0x06, 0xB5, 0x00, 0x6F, 0x00, 0x80, 0x00, 0x13, 0x85, 0x05, 0x00, 0xE3, 0x1A,
+                0x05, 0xFE, 0x6F, 0xF0, 0x1F, 0xFF, 0x67, 0x80, 0x00, 0x00,
+            ]
+        );
+    }
+}
@@ -0,0 +1,1077 @@
+//! Machine code for the AT&T syntax the x86-64 backend emits, plus the common general purpose and
+//! SSE instructions `asm` blocks tend to use.
+//!
+//! Jumps and calls always use 32-bit displacements, so every instruction has its final size as
+//! soon as it is read.
+
+use crate::backend::elf::{IREncoder, IREncoding, parse_integer, parse_symbol, split_operands};
+
+const R_X86_64_64: u32 = 1;
+const R_X86_64_PC32: u32 = 2;
+const R_X86_64_PLT32: u32 = 4;
+const R_X86_64_REX_GOTPCRELX: u32 = 42;
+
+const ARITHMETIC: [&str; 8] = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"];
+const CONDITIONS: [(&str, u8); 30] = [
+    ("o", 0),
+    ("no", 1),
+    ("b", 2),
+    ("c", 2),
+    ("nae", 2),
+    ("ae", 3),
+    ("nb", 3),
+    ("nc", 3),
+    ("e", 4),
+    ("z", 4),
+    ("ne", 5),
+    ("nz", 5),
+    ("be", 6),
+    ("na", 6),
+    ("a", 7),
+    ("nbe", 7),
+    ("s", 8),
+    ("ns", 9),
+    ("p", 10),
+    ("pe", 10),
+    ("np", 11),
+    ("po", 11),
+    ("l", 12),
+    ("nge", 12),
+    ("ge", 13),
+    ("nl", 13),
+    ("le", 14),
+    ("ng", 14),
+    ("g", 15),
+    ("nle", 15),
+];
+/// Mnemonics without their size suffix.
+const BASES: [&str; 35] = [
+    "mov", "movabs", "add", "or", "adc", "sbb", "and", "sub", "xor", "cmp", "test", "lea", "imul",
+    "not", "neg", "mul", "div", "idiv", "inc", "dec", "shl", "sal", "shr", "sar", "rol", "ror",
+    "bt", "bts", "btr", "btc", "push", "pop", "cmpxchg", "xadd", "xchg",
+];
+
+pub(crate) struct IRX86Encoder;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct IRX86Register {
+    number: u8,
+    /// The width in bytes, 16 for `xmm` registers.
+    size: u8,
+}
+
+impl IRX86Register {
+    fn parse(name: &str) -> Option<Self> {
+        const NAMES: [[&str; 16]; 4] = [
+            [
+                "al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil", "r8b", "r9b", "r10b", "r11b",
+                "r12b", "r13b", "r14b", "r15b",
+            ],
+            [
+                "ax", "cx", "dx", "bx", "sp", "bp", "si", "di", "r8w", "r9w", "r10w", "r11w",
+                "r12w", "r13w", "r14w", "r15w",
+            ],
+            [
+                "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d",
+                "r11d", "r12d", "r13d", "r14d", "r15d",
+            ],
+            [
+                "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11",
+                "r12", "r13", "r14", "r15",
+            ],
+        ];
+        let name = name.strip_prefix('%')?;
+        if let Some(number) = name.strip_prefix("xmm") {
+            let number = number.parse().ok().filter(|number| *number < 16)?;
+            return Some(Self { number, size: 16 });
+        }
+        NAMES.iter().enumerate().find_map(|(width, names)| {
+            let number = names.iter().position(|candidate| *candidate == name)?;
+            Some(Self {
+                number: number as u8,
+                size: 1 << width,
+            })
+        })
+    }
+
+    fn is_xmm(self) -> bool {
+        self.size == 16
+    }
+
+    /// `spl`, `bpl`, `sil` and `dil` need a REX prefix to not mean `ah`, `ch`, `dh` and `bh`.
+    fn needs_rex(self) -> bool {
+        self.size == 1 && (4..8).contains(&self.number)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum IRX86Base {
+    None,
+    Register(u8),
+    Rip,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct IRX86Memory {
+    base: IRX86Base,
+    index: Option<(u8, u8)>,
+    displacement: i64,
+    symbol: Option<(String, u32)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum IRX86Operand {
+    Register(IRX86Register),
+    Immediate(i64),
+    Memory(IRX86Memory),
+    /// A jump or call target.
+    Symbol(String, u32),
+    /// `*%r11` or `*(%rax)`.
+    Indirect(Box<IRX86Operand>),
+}
+
+impl IRX86Operand {
+    fn parse(text: &str) -> Result<Self, String> {
+        if let Some(register) = IRX86Register::parse(text) {
+            return Ok(IRX86Operand::Register(register));
+        }
+        if let Some(immediate) = text.strip_prefix('$') {
+            return parse_integer(immediate)
+                .map(IRX86Operand::Immediate)
+                .ok_or_else(|| format!("invalid immediate '{}'", text));
+        }
+        if let Some(target) = text.strip_prefix('*') {
+            return Ok(IRX86Operand::Indirect(Box::new(Self::parse(target)?)));
+        }
+        let invalid = || format!("invalid operand '{}'", text);
+        let (displacement, registers) = match text.find('(') {
+            Some(index) => (
+                &text[..index],
+                Some(text[index + 1..].strip_suffix(')').ok_or_else(invalid)?),
+            ),
+            None => (text, None),
+        };
+        let mut memory = IRX86Memory {
+            base: IRX86Base::None,
+            index: None,
+            displacement: 0,
+            symbol: None,
+        };
+        if let Some(displacement) = parse_integer(displacement) {
+            memory.displacement = displacement;
+        } else if !displacement.is_empty() {
+            let (symbol, relocation) = match displacement.split_once('@') {
+                Some((symbol, "GOTPCREL")) => (symbol, R_X86_64_REX_GOTPCRELX),
+                Some((symbol, "PLT")) => (symbol, R_X86_64_PLT32),
+                Some(_) => return Err(invalid()),
+                None => (displacement, R_X86_64_PC32),
+            };
+            let (symbol, addend) = parse_symbol(symbol).ok_or_else(invalid)?;
+            if registers.is_none() {
+                return Ok(IRX86Operand::Symbol(symbol.to_string(), relocation));
+            }
+            memory.displacement = addend;
+            memory.symbol = Some((symbol.to_string(), relocation));
+        }
+        let Some(registers) = registers else {
+            return Err(invalid());
+        };
+        let parts = registers.split(',').map(str::trim).collect::<Vec<_>>();
+        let register = |name: &str| {
+            IRX86Register::parse(name)
+                .filter(|register| register.size == 8)
+                .map(|register| register.number)
+                .ok_or_else(invalid)
+        };
+        memory.base = match parts[0] {
+            "" => IRX86Base::None,
+            "%rip" => IRX86Base::Rip,
+            base => IRX86Base::Register(register(base)?),
+        };
+        if let Some(index) = parts.get(1) {
+            let scale = match parts.get(2) {
+                Some(scale) => parse_integer(scale).ok_or_else(invalid)?,
+                None => 1,
+            };
+            let scale = [1, 2, 4, 8]
+                .iter()
+                .position(|candidate| *candidate == scale)
+                .ok_or_else(invalid)?;
+            let index = register(index)?;
+            if index == 4 {
+                return Err(invalid());
+            }
+            memory.index = Some((index, scale as u8));
+        }
+        if memory.symbol.is_some() && memory.base != IRX86Base::Rip {
+            return Err("symbols are only addressed relative to %rip".to_string());
+        }
+        Ok(IRX86Operand::Memory(memory))
+    }
+
+    fn register(&self) -> Option<IRX86Register> {
+        match self {
+            IRX86Operand::Register(register) => Some(*register),
+            _ => None,
+        }
+    }
+}
+
+/// The `r/m` operand of an instruction.
+enum IRX86RegisterOrMemory<'a> {
+    Register(IRX86Register),
+    Memory(&'a IRX86Memory),
+}
+
+impl<'a> IRX86RegisterOrMemory<'a> {
+    fn of(operand: &'a IRX86Operand) -> Option<Self> {
+        match operand {
+            IRX86Operand::Register(register) => Some(Self::Register(*register)),
+            IRX86Operand::Memory(memory) => Some(Self::Memory(memory)),
+            _ => None,
+        }
+    }
+}
+
+/// The parts of one instruction: legacy prefixes, `REX.W`, the opcode, the `reg` field of the
+/// ModRM byte, the `r/m` operand and the immediate.
+struct IRX86Instruction<'a> {
+    prefixes: Vec<u8>,
+    wide: bool,
+    opcode: Vec<u8>,
+    reg: u8,
+    rm: Option<IRX86RegisterOrMemory<'a>>,
+    immediate: Option<(i64, usize)>,
+}
+
+impl<'a> IRX86Instruction<'a> {
+    fn new(opcode: &[u8]) -> Self {
+        Self {
+            prefixes: vec![],
+            wide: false,
+            opcode: opcode.to_vec(),
+            reg: 0,
+            rm: None,
+            immediate: None,
+        }
+    }
+
+    fn sized(mut self, size: u8) -> Self {
+        if size == 2 {
+            self.prefixes.push(0x66);
+        }
+        self.wide = size == 8;
+        self
+    }
+
+    fn prefix(mut self, prefix: u8) -> Self {
+        self.prefixes.push(prefix);
+        self
+    }
+
+    fn wide(mut self, wide: bool) -> Self {
+        self.wide = wide;
+        self
+    }
+
+    fn reg(mut self, reg: u8) -> Self {
+        self.reg = reg;
+        self
+    }
+
+    fn rm(mut self, rm: IRX86RegisterOrMemory<'a>) -> Self {
+        self.rm = Some(rm);
+        self
+    }
+
+    fn immediate(mut self, value: i64, size: usize) -> Self {
+        self.immediate = Some((value, size));
+        self
+    }
+
+    fn encode(self, encoding: &mut IREncoding, force_rex: bool) {
+        encoding.bytes.extend(&self.prefixes);
+        let (base, index) = match &self.rm {
+            Some(IRX86RegisterOrMemory::Register(register)) => (register.number, 0),
+            Some(IRX86RegisterOrMemory::Memory(memory)) => (
+                match memory.base {
+                    IRX86Base::Register(base) => base,
+                    _ => 0,
+                },
+                memory.index.map_or(0, |(index, _)| index),
+            ),
+            None => (0, 0),
+        };
+        let rex = (self.wide as u8) << 3
+            | (self.reg >> 3 & 1) << 2
+            | (index >> 3 & 1) << 1
+            | (base >> 3 & 1);
+        let byte_register = matches!(self.rm, Some(IRX86RegisterOrMemory::Register(register)) if register.needs_rex());
+        if rex != 0 || force_rex || byte_register {
+            encoding.bytes.push(0x40 | rex);
+        }
+        encoding.bytes.extend(&self.opcode);
+        let immediate_size = self.immediate.map_or(0, |(_, size)| size);
+        let reg = (self.reg & 7) << 3;
+        match &self.rm {
+            Some(IRX86RegisterOrMemory::Register(register)) => {
+                encoding.bytes.push(0xC0 | reg | register.number & 7);
+            }
+            Some(IRX86RegisterOrMemory::Memory(memory)) => {
+                encode_memory(encoding, reg, memory, immediate_size)
+            }
+            None => {}
+        }
+        if let Some((value, size)) = self.immediate {
+            encoding.bytes.extend(&value.to_le_bytes()[..size]);
+        }
+    }
+}
+
+fn encode_memory(encoding: &mut IREncoding, reg: u8, memory: &IRX86Memory, immediate_size: usize) {
+    let displacement = memory.displacement;
+    let sib = |index: Option<(u8, u8)>, base: u8| {
+        let (index, scale) = index.unwrap_or((4, 0));
+        scale << 6 | (index & 7) << 3 | base & 7
+    };
+    match memory.base {
+        IRX86Base::Rip => {
+            encoding.bytes.push(reg | 0b101);
+            match &memory.symbol {
+                Some((symbol, relocation)) => {
+                    let addend = displacement - 4 - immediate_size as i64;
+                    encoding.fixup(symbol, *relocation, addend);
+                    encoding.bytes.extend([0; 4]);
+                }
+                None => encoding.bytes.extend((displacement as i32).to_le_bytes()),
+            }
+        }
+        IRX86Base::None => {
+            encoding.bytes.push(reg | 0b100);
+            encoding.bytes.push(sib(memory.index, 0b101));
+            encoding.bytes.extend((displacement as i32).to_le_bytes());
+        }
+        IRX86Base::Register(base) => {
+            let mode = if displacement == 0 && base & 7 != 5 {
+                0b00
+            } else if i8::try_from(displacement).is_ok() {
+                0b01
+            } else {
+                0b10
+            };
+            if memory.index.is_some() || base & 7 == 4 {
+                encoding.bytes.push(mode << 6 | reg | 0b100);
+                encoding.bytes.push(sib(memory.index, base));
+            } else {
+                encoding.bytes.push(mode << 6 | reg | base & 7);
+            }
+            match mode {
+                0b01 => encoding.bytes.push(displacement as u8),
+                0b10 => encoding.bytes.extend((displacement as i32).to_le_bytes()),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn size_of_suffix(suffix: char) -> Option<u8> {
+    match suffix {
+        'b' => Some(1),
+        'w' => Some(2),
+        'l' => Some(4),
+        'q' => Some(8),
+        _ => None,
+    }
+}
+
+fn condition(code: &str) -> Option<u8> {
+    CONDITIONS
+        .iter()
+        .find(|(name, _)| *name == code)
+        .map(|(_, value)| *value)
+}
+
+/// The width of an immediate for an operation of the given size; 64-bit operations take
+/// sign-extended 32-bit immediates.
+fn immediate_size(size: u8) -> usize {
+    (size as usize).min(4)
+}
+
+impl IREncoder for IRX86Encoder {
+    const MACHINE: u16 = 62;
+    const FLAGS: u32 = 0;
+    const ABSOLUTE_64: u32 = R_X86_64_64;
+    const TEXT_ALIGNMENT: u64 = 16;
+
+    fn encode(
+        &self,
+        mnemonic: &str,
+        operands: &[&str],
+        encoding: &mut IREncoding,
+    ) -> Result<(), String> {
+        if let Some(fixed) = fixed_instruction(mnemonic) {
+            if !operands.is_empty() {
+                return Err("unexpected operands".to_string());
+            }
+            encoding.bytes.extend(fixed);
+            return Ok(());
+        }
+        if let Some(prefix) = match mnemonic {
+            "lock" => Some(0xF0),
+            "rep" => Some(0xF3),
+            _ => None,
+        } {
+            let rest = operands.join(", ");
+            let (mnemonic, operands) = rest
+                .split_once(char::is_whitespace)
+                .unwrap_or((rest.as_str(), ""));
+            encoding.bytes.push(prefix);
+            return self.encode(mnemonic, &split_operands(operands), encoding);
+        }
+        let operands = operands
+            .iter()
+            .map(|operand| IRX86Operand::parse(operand))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(result) = encode_sse(mnemonic, &operands, encoding) {
+            return result;
+        }
+        if let Some(result) = encode_control(mnemonic, &operands, encoding) {
+            return result;
+        }
+        let (base, size) = if BASES.contains(&mnemonic) {
+            (mnemonic, None)
+        } else {
+            let suffix = mnemonic.chars().last().and_then(size_of_suffix);
+            match &mnemonic[..mnemonic.len() - 1] {
+                base if suffix.is_some() && BASES.contains(&base) => (base, suffix),
+                _ => return encode_extension(mnemonic, &operands, encoding),
+            }
+        };
+        let size = size
+            .or_else(|| {
+                let register = operands.iter().rev().find_map(IRX86Operand::register)?;
+                (!register.is_xmm()).then_some(register.size)
+            })
+            .unwrap_or(8);
+        encode_general(base, size, &operands, encoding)
+    }
+
+    fn resolve(&self, relocation: u32, bytes: &mut [u8], value: i64) -> bool {
+        let Ok(value) = i32::try_from(value) else {
+            return false;
+        };
+        match relocation {
+            R_X86_64_PC32 | R_X86_64_PLT32 => {
+                bytes[..4].copy_from_slice(&value.to_le_bytes());
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn fixed_instruction(mnemonic: &str) -> Option<&'static [u8]> {
+    Some(match mnemonic {
+        "ret" | "retq" => &[0xC3],
+        "leave" | "leaveq" => &[0xC9],
+        "nop" => &[0x90],
+        "cqto" | "cqo" => &[0x48, 0x99],
+        "cltq" | "cdqe" => &[0x48, 0x98],
+        "cltd" | "cdq" => &[0x99],
+        "hlt" => &[0xF4],
+        "pause" => &[0xF3, 0x90],
+        "mfence" => &[0x0F, 0xAE, 0xF0],
+        "lfence" => &[0x0F, 0xAE, 0xE8],
+        "sfence" => &[0x0F, 0xAE, 0xF8],
+        "ud2" => &[0x0F, 0x0B],
+        "syscall" => &[0x0F, 0x05],
+        "int3" => &[0xCC],
+        _ => return None,
+    })
+}
+
+/// Jumps, calls and conditional moves and sets.
+fn encode_control(
+    mnemonic: &str,
+    operands: &[IRX86Operand],
+    encoding: &mut IREncoding,
+) -> Option<Result<(), String>> {
+    let (opcode, relocation, indirect) = match mnemonic {
+        "call" | "callq" => (vec![0xE8], R_X86_64_PLT32, 2),
+        "jmp" | "jmpq" => (vec![0xE9], R_X86_64_PLT32, 4),
+        _ => {
+            if let Some(code) = mnemonic.strip_prefix('j').and_then(condition) {
+                (vec![0x0F, 0x80 + code], R_X86_64_PC32, 0)
+            } else if let Some(code) = mnemonic.strip_prefix("set").and_then(condition) {
+                let [IRX86Operand::Register(register)] = operands else {
+                    return Some(Err("expected a byte register".to_string()));
+                };
+                IRX86Instruction::new(&[0x0F, 0x90 + code])
+                    .rm(IRX86RegisterOrMemory::Register(*register))
+                    .encode(encoding, false);
+                return Some(Ok(()));
+            } else if let Some(code) = mnemonic.strip_prefix("cmov").and_then(|code| {
+                condition(code).or_else(|| condition(code.strip_suffix(['w', 'l', 'q'])?))
+            }) {
+                let [source, IRX86Operand::Register(target)] = operands else {
+                    return Some(Err("expected a register target".to_string()));
+                };
+                let Some(source) = IRX86RegisterOrMemory::of(source) else {
+                    return Some(Err("invalid source".to_string()));
+                };
+                IRX86Instruction::new(&[0x0F, 0x40 + code])
+                    .sized(target.size)
+                    .reg(target.number)
+                    .rm(source)
+                    .encode(encoding, false);
+                return Some(Ok(()));
+            } else {
+                return None;
+            }
+        }
+    };
+    Some(match operands {
+        [IRX86Operand::Symbol(symbol, _)] => {
+            encoding.bytes.extend(&opcode);
+            encoding.fixup(symbol, relocation, -4);
+            encoding.bytes.extend([0; 4]);
+            Ok(())
+        }
+        [IRX86Operand::Indirect(target)] if indirect != 0 => {
+            match IRX86RegisterOrMemory::of(target) {
+                Some(target) => {
+                    IRX86Instruction::new(&[0xFF])
+                        .reg(indirect)
+                        .rm(target)
+                        .encode(encoding, false);
+                    Ok(())
+                }
+                None => Err("invalid target".to_string()),
+            }
+        }
+        _ => Err("expected a label".to_string()),
+    })
+}
+
+/// Instructions that carry the sizes of both operands in their name, like `movzbq`, and the
+/// conversions between integers and floating point.
+fn encode_extension(
+    mnemonic: &str,
+    operands: &[IRX86Operand],
+    encoding: &mut IREncoding,
+) -> Result<(), String> {
+    let unknown = || "unknown instruction".to_string();
+    let [source, IRX86Operand::Register(target)] = operands else {
+        return Err(unknown());
+    };
+    let source_rm = IRX86RegisterOrMemory::of(source).ok_or_else(unknown)?;
+    let opcode: &[u8] = match mnemonic.get(..5) {
+        Some("movzb") => &[0x0F, 0xB6],
+        Some("movzw") => &[0x0F, 0xB7],
+        Some("movsb") => &[0x0F, 0xBE],
+        Some("movsw") => &[0x0F, 0xBF],
+        Some("movsl") => &[0x63],
+        _ => return Err(unknown()),
+    };
+    let size = mnemonic[5..]
+        .chars()
+        .next()
+        .and_then(size_of_suffix)
+        .unwrap_or(target.size);
+    let force_rex = matches!(source, IRX86Operand::Register(register) if register.needs_rex());
+    IRX86Instruction::new(opcode)
+        .sized(size)
+        .reg(target.number)
+        .rm(source_rm)
+        .encode(encoding, force_rex);
+    Ok(())
+}
+
+/// SSE instructions, and `movd`/`movq` between general purpose and `xmm` registers.
+fn encode_sse(
+    mnemonic: &str,
+    operands: &[IRX86Operand],
+    encoding: &mut IREncoding,
+) -> Option<Result<(), String>> {
+    let [source, target] = operands else {
+        return None;
+    };
+    let is_xmm = |operand: &IRX86Operand| operand.register().is_some_and(IRX86Register::is_xmm);
+    let rm = |operand| IRX86RegisterOrMemory::of(operand).ok_or("invalid operand".to_string());
+    let scalar = |mnemonic: &str| match mnemonic {
+        "ss" => Some(0xF3),
+        "sd" => Some(0xF2),
+        _ => None,
+    };
+    // Operations `xmm ← xmm op r/m`, with a mandatory prefix.
+    let arithmetic = |encoding: &mut IREncoding, prefix: Option<u8>, opcode: u8, wide: bool| {
+        let IRX86Operand::Register(target) = target else {
+            return Err("expected a register target".to_string());
+        };
+        let mut instruction = IRX86Instruction::new(&[0x0F, opcode])
+            .wide(wide)
+            .reg(target.number)
+            .rm(rm(source)?);
+        if let Some(prefix) = prefix {
+            instruction = instruction.prefix(prefix);
+        }
+        instruction.encode(encoding, false);
+        Ok(())
+    };
+    if matches!(mnemonic, "movd" | "movq") && (is_xmm(source) || is_xmm(target)) {
+        let wide = mnemonic == "movq";
+        return Some(match (source, target) {
+            (IRX86Operand::Register(source), IRX86Operand::Register(target))
+                if source.is_xmm() && target.is_xmm() =>
+            {
+                IRX86Instruction::new(&[0x0F, 0x7E])
+                    .prefix(0xF3)
+                    .reg(target.number)
+                    .rm(IRX86RegisterOrMemory::Register(*source))
+                    .encode(encoding, false);
+                Ok(())
+            }
+            (_, IRX86Operand::Register(target)) if target.is_xmm() => {
+                arithmetic(encoding, Some(0x66), 0x6E, wide)
+            }
+            (IRX86Operand::Register(source), _) => {
+                match rm(target) {
+                    Ok(target) => IRX86Instruction::new(&[0x0F, 0x7E])
+                        .prefix(0x66)
+                        .wide(wide)
+                        .reg(source.number)
+                        .rm(target)
+                        .encode(encoding, false),
+                    Err(error) => return Some(Err(error)),
+                }
+                Ok(())
+            }
+            _ => Err("invalid operands".to_string()),
+        });
+    }
+    if let Some(prefix) = mnemonic
+        .strip_prefix("mov")
+        .and_then(scalar)
+        .filter(|_| is_xmm(source) || is_xmm(target))
+    {
+        return Some(match (source, target) {
+            (_, IRX86Operand::Register(target)) if target.is_xmm() => {
+                arithmetic(encoding, Some(prefix), 0x10, false)
+            }
+            (IRX86Operand::Register(source), IRX86Operand::Memory(target)) => {
+                IRX86Instruction::new(&[0x0F, 0x11])
+                    .prefix(prefix)
+                    .reg(source.number)
+                    .rm(IRX86RegisterOrMemory::Memory(target))
+                    .encode(encoding, false);
+                Ok(())
+            }
+            _ => Err("invalid operands".to_string()),
+        });
+    }
+    for (name, opcode) in [
+        ("add", 0x58),
+        ("mul", 0x59),
+        ("sub", 0x5C),
+        ("div", 0x5E),
+        ("sqrt", 0x51),
+        ("min", 0x5D),
+        ("max", 0x5F),
+    ] {
+        if let Some(prefix) = mnemonic.strip_prefix(name).and_then(scalar) {
+            return Some(arithmetic(encoding, Some(prefix), opcode, false));
+        }
+    }
+    let (prefix, opcode) = match mnemonic {
+        "ucomiss" => (None, 0x2E),
+        "ucomisd" => (Some(0x66), 0x2E),
+        "comiss" => (None, 0x2F),
+        "comisd" => (Some(0x66), 0x2F),
+        "xorps" => (None, 0x57),
+        "xorpd" => (Some(0x66), 0x57),
+        "andps" => (None, 0x54),
+        "andpd" => (Some(0x66), 0x54),
+        "cvtss2sd" => (Some(0xF3), 0x5A),
+        "cvtsd2ss" => (Some(0xF2), 0x5A),
+        _ => {
+            // `cvtsi2sdq`, `cvttss2siq` and the like: the suffix is the integer width.
+            let (name, wide) = match mnemonic.strip_suffix(['l', 'q']) {
+                Some(name) if name.starts_with("cvt") => (name, mnemonic.ends_with('q')),
+                _ => (
+                    mnemonic,
+                    operands.iter().any(|operand| {
+                        operand
+                            .register()
+                            .is_some_and(|register| register.size == 8)
+                    }),
+                ),
+            };
+            let (prefix, opcode) = match name {
+                "cvtsi2ss" => (0xF3, 0x2A),
+                "cvtsi2sd" => (0xF2, 0x2A),
+                "cvttss2si" => (0xF3, 0x2C),
+                "cvttsd2si" => (0xF2, 0x2C),
+                "cvtss2si" => (0xF3, 0x2D),
+                "cvtsd2si" => (0xF2, 0x2D),
+                _ => return None,
+            };
+            return Some(arithmetic(encoding, Some(prefix), opcode, wide));
+        }
+    };
+    Some(arithmetic(encoding, prefix, opcode, false))
+}
+
+fn encode_general(
+    base: &str,
+    size: u8,
+    operands: &[IRX86Operand],
+    encoding: &mut IREncoding,
+) -> Result<(), String> {
+    let invalid = || "invalid operands".to_string();
+    // The bit selecting a full-size operation over a byte one.
+    let w = (size != 1) as u8;
+    let force_rex = operands
+        .iter()
+        .any(|operand| operand.register().is_some_and(IRX86Register::needs_rex));
+    let instruction = |opcode: &[u8]| IRX86Instruction::new(opcode).sized(size);
+    let rm = |operand| IRX86RegisterOrMemory::of(operand).ok_or_else(invalid);
+    let immediate = |value: i64| {
+        let width = immediate_size(size);
+        let fits = match width {
+            1 => i8::try_from(value).is_ok() || u8::try_from(value).is_ok(),
+            2 => i16::try_from(value).is_ok() || u16::try_from(value).is_ok(),
+            _ if size == 4 => i32::try_from(value).is_ok() || u32::try_from(value).is_ok(),
+            _ => i32::try_from(value).is_ok(),
+        };
+        if fits {
+            Ok(width)
+        } else {
+            Err("immediate out of range".to_string())
+        }
+    };
+    if let Some(extension) = ARITHMETIC.iter().position(|name| *name == base) {
+        let extension = extension as u8;
+        match operands {
+            [IRX86Operand::Immediate(value), target] => {
+                let target = rm(target)?;
+                if size != 1 && i8::try_from(*value).is_ok() {
+                    instruction(&[0x83])
+                        .reg(extension)
+                        .rm(target)
+                        .immediate(*value, 1)
+                        .encode(encoding, force_rex);
+                } else {
+                    instruction(&[0x80 | w])
+                        .reg(extension)
+                        .rm(target)
+                        .immediate(*value, immediate(*value)?)
+                        .encode(encoding, force_rex);
+                }
+            }
+            [IRX86Operand::Register(source), target] => instruction(&[extension << 3 | w])
+                .reg(source.number)
+                .rm(rm(target)?)
+                .encode(encoding, force_rex),
+            [
+                source @ IRX86Operand::Memory(_),
+                IRX86Operand::Register(target),
+            ] => instruction(&[extension << 3 | 2 | w])
+                .reg(target.number)
+                .rm(rm(source)?)
+                .encode(encoding, force_rex),
+            _ => return Err(invalid()),
+        }
+        return Ok(());
+    }
+    match (base, operands) {
+        (
+            "mov",
+            [
+                IRX86Operand::Immediate(value),
+                IRX86Operand::Register(target),
+            ],
+        ) if size != 8 => {
+            // The register is part of the opcode.
+            let width = immediate(*value)?;
+            if size == 2 {
+                encoding.bytes.push(0x66);
+            }
+            if target.number >= 8 || target.needs_rex() {
+                encoding.bytes.push(0x40 | target.number >> 3);
+            }
+            encoding.bytes.push(0xB0 | w << 3 | target.number & 7);
+            encoding.bytes.extend(&value.to_le_bytes()[..width]);
+        }
+        ("mov", [IRX86Operand::Immediate(value), target]) => instruction(&[0xC6 | w])
+            .rm(rm(target)?)
+            .immediate(*value, immediate(*value)?)
+            .encode(encoding, force_rex),
+        (
+            "movabs",
+            [
+                IRX86Operand::Immediate(value),
+                IRX86Operand::Register(target),
+            ],
+        ) => {
+            encoding.bytes.push(0x48 | target.number >> 3);
+            encoding.bytes.push(0xB8 | target.number & 7);
+            encoding.bytes.extend(value.to_le_bytes());
+        }
+        ("mov", [IRX86Operand::Register(source), target]) => instruction(&[0x88 | w])
+            .reg(source.number)
+            .rm(rm(target)?)
+            .encode(encoding, force_rex),
+        (
+            "mov",
+            [
+                source @ IRX86Operand::Memory(_),
+                IRX86Operand::Register(target),
+            ],
+        ) => instruction(&[0x8A | w])
+            .reg(target.number)
+            .rm(rm(source)?)
+            .encode(encoding, force_rex),
+        (
+            "lea",
+            [
+                source @ IRX86Operand::Memory(_),
+                IRX86Operand::Register(target),
+            ],
+        ) => instruction(&[0x8D])
+            .reg(target.number)
+            .rm(rm(source)?)
+            .encode(encoding, force_rex),
+        ("test", [IRX86Operand::Immediate(value), target]) => instruction(&[0xF6 | w])
+            .rm(rm(target)?)
+            .immediate(*value, immediate(*value)?)
+            .encode(encoding, force_rex),
+        ("test", [IRX86Operand::Register(source), target]) => instruction(&[0x84 | w])
+            .reg(source.number)
+            .rm(rm(target)?)
+            .encode(encoding, force_rex),
+        ("imul", [source, IRX86Operand::Register(target)]) => instruction(&[0x0F, 0xAF])
+            .reg(target.number)
+            .rm(rm(source)?)
+            .encode(encoding, force_rex),
+        ("not" | "neg" | "mul" | "imul" | "div" | "idiv", [target]) => {
+            let extension = match base {
+                "not" => 2,
+                "neg" => 3,
+                "mul" => 4,
+                "imul" => 5,
+                "div" => 6,
+                _ => 7,
+            };
+            instruction(&[0xF6 | w])
+                .reg(extension)
+                .rm(rm(target)?)
+                .encode(encoding, force_rex)
+        }
+        ("inc" | "dec", [target]) => instruction(&[0xFE | w])
+            .reg((base == "dec") as u8)
+            .rm(rm(target)?)
+            .encode(encoding, force_rex),
+        ("shl" | "sal" | "shr" | "sar" | "rol" | "ror", _) => {
+            let extension = match base {
+                "rol" => 0,
+                "ror" => 1,
+                "shl" | "sal" => 4,
+                "shr" => 5,
+                _ => 7,
+            };
+            match operands {
+                [target] | [IRX86Operand::Immediate(1), target] => instruction(&[0xD0 | w])
+                    .reg(extension)
+                    .rm(rm(target)?)
+                    .encode(encoding, force_rex),
+                [
+                    IRX86Operand::Register(IRX86Register { number: 1, size: 1 }),
+                    target,
+                ] => instruction(&[0xD2 | w])
+                    .reg(extension)
+                    .rm(rm(target)?)
+                    .encode(encoding, force_rex),
+                [IRX86Operand::Immediate(count), target] => instruction(&[0xC0 | w])
+                    .reg(extension)
+                    .rm(rm(target)?)
+                    .immediate(*count, 1)
+                    .encode(encoding, force_rex),
+                _ => return Err(invalid()),
+            }
+        }
+        ("bt" | "bts" | "btr" | "btc", [IRX86Operand::Immediate(bit), target]) => {
+            let extension = match base {
+                "bt" => 4,
+                "bts" => 5,
+                "btr" => 6,
+                _ => 7,
+            };
+            instruction(&[0x0F, 0xBA])
+                .reg(extension)
+                .rm(rm(target)?)
+                .immediate(*bit, 1)
+                .encode(encoding, force_rex)
+        }
+        ("push" | "pop", [IRX86Operand::Register(register)]) if register.size == 8 => {
+            if register.number >= 8 {
+                encoding.bytes.push(0x41);
+            }
+            let opcode = if base == "push" { 0x50 } else { 0x58 };
+            encoding.bytes.push(opcode | register.number & 7);
+        }
+        ("cmpxchg" | "xadd" | "xchg", [IRX86Operand::Register(source), target]) => {
+            let opcode: &[u8] = match base {
+                "cmpxchg" => &[0x0F, 0xB0 | w],
+                "xadd" => &[0x0F, 0xC0 | w],
+                _ => &[0x86 | w],
+            };
+            instruction(opcode)
+                .reg(source.number)
+                .rm(rm(target)?)
+                .encode(encoding, force_rex)
+        }
+        _ => return Err(invalid()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::elf::IRFixup;
+    use crate::backend::elf::tests::{assert_encodings, assert_fixups, fixup};
+
+    /// As GNU as and llvm-mc encode them.
+    const ENCODINGS: &[(&str, &[u8])] = &[
+        ("pushq %rbp", &[0x55]),
+        ("popq %r12", &[0x41, 0x5C]),
+        ("movq %rsp, %rbp", &[0x48, 0x89, 0xE5]),
+        ("movq %rdi, %rax", &[0x48, 0x89, 0xF8]),
+        ("movq %rax, %r11", &[0x49, 0x89, 0xC3]),
+        ("movq %r8, %rcx", &[0x4C, 0x89, 0xC1]),
+        ("movq -8(%rbp), %rax", &[0x48, 0x8B, 0x45, 0xF8]),
+        (
+            "movq %rax, -200(%rbp)",
+            &[0x48, 0x89, 0x85, 0x38, 0xFF, 0xFF, 0xFF],
+        ),
+        ("movq (%r11), %rax", &[0x49, 0x8B, 0x03]),
+        ("movq %rax, 0(%rsp)", &[0x48, 0x89, 0x04, 0x24]),
+        ("movq 8(%r12), %r13", &[0x4D, 0x8B, 0x6C, 0x24, 0x08]),
+        ("movq (%r13), %rax", &[0x49, 0x8B, 0x45, 0x00]),
+        (
+            "movq 16(%rax,%rcx,8), %rdx",
+            &[0x48, 0x8B, 0x54, 0xC8, 0x10],
+        ),
+        ("movl $1, %eax", &[0xB8, 0x01, 0x00, 0x00, 0x00]),
+        (
+            "movq $-1, %rax",
+            &[0x48, 0xC7, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF],
+        ),
+        (
+            "movabsq $0x123456789abcdef0, %rax",
+            &[0x48, 0xB8, 0xF0, 0xDE, 0xBC, 0x9A, 0x78, 0x56, 0x34, 0x12],
+        ),
+        ("movslq %eax, %rax", &[0x48, 0x63, 0xC0]),
+        ("movzbq %al, %rax", &[0x48, 0x0F, 0xB6, 0xC0]),
+        ("movsbq %al, %rax", &[0x48, 0x0F, 0xBE, 0xC0]),
+        ("movzwq %ax, %rax", &[0x48, 0x0F, 0xB7, 0xC0]),
+        ("movswq %ax, %rax", &[0x48, 0x0F, 0xBF, 0xC0]),
+        ("movl (%r11), %eax", &[0x41, 0x8B, 0x03]),
+        ("movb %al, (%r11)", &[0x41, 0x88, 0x03]),
+        ("movw %ax, (%r11)", &[0x66, 0x41, 0x89, 0x03]),
+        ("movl %eax, (%r11)", &[0x41, 0x89, 0x03]),
+        ("leaq -24(%rbp), %rax", &[0x48, 0x8D, 0x45, 0xE8]),
+        ("subq $80, %rsp", &[0x48, 0x83, 0xEC, 0x50]),
+        (
+            "subq $1024, %rsp",
+            &[0x48, 0x81, 0xEC, 0x00, 0x04, 0x00, 0x00],
+        ),
+        ("addq %rcx, %rax", &[0x48, 0x01, 0xC8]),
+        ("addq $16, %rsp", &[0x48, 0x83, 0xC4, 0x10]),
+        ("andq %rcx, %rax", &[0x48, 0x21, 0xC8]),
+        ("orq %rcx, %rax", &[0x48, 0x09, 0xC8]),
+        ("xorq %rcx, %rax", &[0x48, 0x31, 0xC8]),
+        ("xorl %eax, %eax", &[0x31, 0xC0]),
+        ("andl $255, %ecx", &[0x81, 0xE1, 0xFF, 0x00, 0x00, 0x00]),
+        ("cmpq %rcx, %rax", &[0x48, 0x39, 0xC8]),
+        ("testq %rax, %rax", &[0x48, 0x85, 0xC0]),
+        ("imulq %rcx, %rax", &[0x48, 0x0F, 0xAF, 0xC1]),
+        ("idivq %rcx", &[0x48, 0xF7, 0xF9]),
+        ("divq %rcx", &[0x48, 0xF7, 0xF1]),
+        ("negq %rax", &[0x48, 0xF7, 0xD8]),
+        ("notq %rax", &[0x48, 0xF7, 0xD0]),
+        ("shlq %cl, %rax", &[0x48, 0xD3, 0xE0]),
+        ("sarq %cl, %rax", &[0x48, 0xD3, 0xF8]),
+        ("shrq %cl, %rax", &[0x48, 0xD3, 0xE8]),
+        ("btcq $63, %rax", &[0x48, 0x0F, 0xBA, 0xF8, 0x3F]),
+        ("cqto", &[0x48, 0x99]),
+        ("leave", &[0xC9]),
+        ("ret", &[0xC3]),
+        ("nop", &[0x90]),
+        ("lock xaddq %rax, (%r11)", &[0xF0, 0x49, 0x0F, 0xC1, 0x03]),
+        (
+            "lock cmpxchgq %rcx, (%r11)",
+            &[0xF0, 0x49, 0x0F, 0xB1, 0x0B],
+        ),
+        ("movq %rax, %xmm0", &[0x66, 0x48, 0x0F, 0x6E, 0xC0]),
+        ("movq %xmm1, %rax", &[0x66, 0x48, 0x0F, 0x7E, 0xC8]),
+        ("movd %eax, %xmm0", &[0x66, 0x0F, 0x6E, 0xC0]),
+        ("movd %xmm0, %eax", &[0x66, 0x0F, 0x7E, 0xC0]),
+        ("addsd %xmm1, %xmm0", &[0xF2, 0x0F, 0x58, 0xC1]),
+        ("subss %xmm1, %xmm0", &[0xF3, 0x0F, 0x5C, 0xC1]),
+        ("mulsd %xmm1, %xmm0", &[0xF2, 0x0F, 0x59, 0xC1]),
+        ("divss %xmm1, %xmm0", &[0xF3, 0x0F, 0x5E, 0xC1]),
+        ("ucomisd %xmm1, %xmm0", &[0x66, 0x0F, 0x2E, 0xC1]),
+        ("ucomiss %xmm1, %xmm0", &[0x0F, 0x2E, 0xC1]),
+        ("xorps %xmm1, %xmm0", &[0x0F, 0x57, 0xC1]),
+        ("cvtsi2sdq %rax, %xmm0", &[0xF2, 0x48, 0x0F, 0x2A, 0xC0]),
+        ("cvtsi2ssq %rax, %xmm0", &[0xF3, 0x48, 0x0F, 0x2A, 0xC0]),
+        ("cvttsd2siq %xmm0, %rax", &[0xF2, 0x48, 0x0F, 0x2C, 0xC0]),
+        ("cvttss2siq %xmm0, %rax", &[0xF3, 0x48, 0x0F, 0x2C, 0xC0]),
+        ("cvtss2sd %xmm0, %xmm0", &[0xF3, 0x0F, 0x5A, 0xC0]),
+        ("cvtsd2ss %xmm0, %xmm0", &[0xF2, 0x0F, 0x5A, 0xC0]),
+        ("call *%r11", &[0x41, 0xFF, 0xD3]),
+    ];
+
+    #[test]
+    fn encodes_like_the_reference_assemblers() {
+        assert_encodings(&IRX86Encoder, ENCODINGS);
+    }
+
+    #[test]
+    fn symbols_are_left_as_fixups() {
+        let cases: &[(&str, &[u8], IRFixup)] = &[
+            (
+                "movq .LC0(%rip), %rax",
+                &[0x48, 0x8B, 0x05, 0, 0, 0, 0],
+                fixup(3, ".LC0", R_X86_64_PC32, -4),
+            ),
+            (
+                "leaq counter+8(%rip), %rax",
+                &[0x48, 0x8D, 0x05, 0, 0, 0, 0],
+                fixup(3, "counter", R_X86_64_PC32, 4),
+            ),
+            (
+                "movq counter@GOTPCREL(%rip), %rax",
+                &[0x48, 0x8B, 0x05, 0, 0, 0, 0],
+                fixup(3, "counter", R_X86_64_REX_GOTPCRELX, -4),
+            ),
+            (
+                "call puts@PLT",
+                &[0xE8, 0, 0, 0, 0],
+                fixup(1, "puts", R_X86_64_PLT32, -4),
+            ),
+            (
+                "jmp .Lf.loop",
+                &[0xE9, 0, 0, 0, 0],
+                fixup(1, ".Lf.loop", R_X86_64_PLT32, -4),
+            ),
+            (
+                "jl .Lf.loop",
+                &[0x0F, 0x8C, 0, 0, 0, 0],
+                fixup(2, ".Lf.loop", R_X86_64_PC32, -4),
+            ),
+        ];
+        assert_fixups(&IRX86Encoder, cases);
+    }
+}
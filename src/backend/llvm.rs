@@ -0,0 +1,1551 @@
+//! LLVM IR in its textual `.ll` form, using opaque `ptr` types.
+//!
+//! Opaque pointers are the default from LLVM 15 on; LLVM 14 reads the output only when given
+//! `-opaque-pointers` (e.g. `llvm-as -opaque-pointers`, `llc -opaque-pointers`), and older
+//! versions not at all.
+//!
+//! Like the C backend this keeps every virtual register and field in an `alloca` and leaves it to
+//! `mem2reg` to build SSA form. Basic blocks map to labels; a conditional jump that does not end
+//! its block continues in a fresh block. Phis become LLVM `phi`s at the top of their block, fed by
+//! values loaded in each predecessor right before it branches, and the phi instruction itself
+//! stores that value into the register.
+
+use crate::backend::x86_64::asm_register;
+use crate::backend::{
+    IRDataItem, IRGenerateError, IRGenerateResult, IROperandValue, IRTarget, IRValueType, constant,
+    global_data_layout, lower_for_target, phi_moves,
+};
+use crate::ir::base::{IRCondition, IRControlFlowGraph, IRFunction, IRNode};
+use crate::ir::instruction::{
+    IRAsm, IRCalculate, IRCalculateOperator, IRConditionalJump, IRDecrease, IRFree, IRGet, IRGoto,
+    IRIncrease, IRInvoke, IRMalloc, IRNegate, IRNoOperate, IRNot, IRRealloc, IRReturn, IRSet,
+    IRSetVirtualRegister, IRStackAllocate, IRTypeCast, IRTypeCastKind,
+};
+use crate::ir::operand::{IROperand, IRVirtualRegister};
+use crate::ir::type_check::{IRFunctionTypes, infer_register_types, operand_type};
+use crate::ir::types::{
+    IRIntegerType, IRIntegerTypeSize, IRPointerType, IRType, IRTypeKind, IRVoidType,
+};
+use crate::ir::verify::IRLocation;
+use crate::ir::{IRConstantData, IRModule, IRVisitor};
+use indexmap::IndexMap;
+use std::cell::{Cell, RefCell};
+use std::fmt::{self, Write};
+
+const GLOBAL_INIT_FUNCTION: &str = "lg.global_init";
+
+pub fn generate_ir(ir_module: &IRModule) -> IRGenerateResult<String> {
+    let ir_module = &*lower_for_target(ir_module, IRTarget::LLVM)?;
+    let declarations = RefCell::new(IndexMap::new());
+    let mut output = String::new();
+    for ir_structure in ir_module.structures.values() {
+        let fields = ir_structure
+            .fields
+            .iter()
+            .map(|field| llvm_type(field._type.as_ref()))
+            .collect::<Vec<_>>();
+        let _ = writeln!(
+            output,
+            "{} = type {{ {} }}",
+            identifier('%', &ir_structure.name),
+            fields.join(", ")
+        );
+    }
+    emit_constants(ir_module, &mut output);
+    emit_global_data(ir_module, &declarations, &mut output)?;
+    let mut functions = String::new();
+    if !ir_module.global_init_section.basic_blocks.is_empty() {
+        IRLLVMEmitter::new(ir_module, None, &declarations).emit_function(
+            &format!("internal void {}()", identifier('@', GLOBAL_INIT_FUNCTION)),
+            &mut functions,
+        )?;
+        let _ = writeln!(
+            output,
+            "@llvm.global_ctors = appending global [1 x {{ i32, ptr, ptr }}] \
+             [{{ i32, ptr, ptr }} {{ i32 65535, ptr {}, ptr null }}]",
+            identifier('@', GLOBAL_INIT_FUNCTION)
+        );
+    }
+    for ir_function in ir_module.functions.values() {
+        let parameters = ir_function
+            .fields
+            .iter()
+            .take(ir_function.arguments_count)
+            .enumerate()
+            .map(|(index, field)| format!("{} %a{}", llvm_type(field._type.as_ref()), index))
+            .collect::<Vec<_>>();
+        let signature = format!(
+            "{} {}({})",
+            llvm_type(ir_function.return_type.as_ref()),
+            identifier('@', &ir_function.name),
+            parameters.join(", ")
+        );
+        IRLLVMEmitter::new(ir_module, Some(ir_function), &declarations)
+            .emit_function(&signature, &mut functions)?;
+    }
+    emit_entry_point(ir_module, &mut functions);
+    for declaration in declarations.into_inner().into_values() {
+        let _ = writeln!(output, "{}", declaration);
+    }
+    output.push_str(&functions);
+    Ok(output)
+}
+
+/// A global (`@`) or local (`%`) name, quoted unless it only uses characters LLVM accepts bare.
+fn identifier(sigil: char, name: &str) -> String {
+    let bare = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"-$._".contains(&byte));
+    if bare {
+        return format!("{}{}", sigil, name);
+    }
+    format!("{}\"{}\"", sigil, escape(name.as_bytes()))
+}
+
+/// Escapes bytes for a quoted name or a `c"..."` string.
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &byte in bytes {
+        if byte.is_ascii_graphic() && byte != b'"' && byte != b'\\' || byte == b' ' {
+            escaped.push(byte as char);
+        } else {
+            let _ = write!(escaped, "\\{:02X}", byte);
+        }
+    }
+    escaped
+}
+
+fn llvm_type(_type: &dyn IRType) -> String {
+    match IRTypeKind::of(_type) {
+        IRTypeKind::Integer(integer) => format!("i{}", integer.size as u32),
+        IRTypeKind::Float(_) => "float".to_string(),
+        IRTypeKind::Double(_) => "double".to_string(),
+        IRTypeKind::Void(_) => "void".to_string(),
+        IRTypeKind::Pointer(_) => "ptr".to_string(),
+        IRTypeKind::Structure(structure) => identifier('%', &structure.name),
+        IRTypeKind::Array(array) => {
+            format!("[{} x {}]", array.length, llvm_type(array.element.as_ref()))
+        }
+        IRTypeKind::Function(function) => {
+            let mut params = function
+                .params
+                .iter()
+                .map(|param| llvm_type(param.as_ref()))
+                .collect::<Vec<_>>();
+            if function.variadic {
+                params.push("...".to_string());
+            }
+            format!(
+                "{} ({})",
+                llvm_type(function.return_type.as_ref()),
+                params.join(", ")
+            )
+        }
+    }
+}
+
+/// The type used to access a value atomically: LLVM has no atomic `i1`, and compare-exchange
+/// only works on integers and pointers.
+fn memory_type(value_type: IRValueType) -> String {
+    format!("i{}", value_type.size() * 8)
+}
+
+fn zero(_type: &dyn IRType) -> &'static str {
+    match IRTypeKind::of(_type) {
+        IRTypeKind::Integer(_) => "0",
+        IRTypeKind::Float(_) | IRTypeKind::Double(_) => "0.0",
+        IRTypeKind::Pointer(_) => "null",
+        IRTypeKind::Void(_) | IRTypeKind::Function(_) => "",
+        IRTypeKind::Structure(_) | IRTypeKind::Array(_) => "zeroinitializer",
+    }
+}
+
+fn constant_name(index: usize) -> String {
+    format!("@.str.{}", index)
+}
+
+fn emit_constants(ir_module: &IRModule, output: &mut String) {
+    for (index, entry) in ir_module.constant_pool.entries.iter().enumerate() {
+        if let Some(IRConstantData::String(mut bytes)) = IRConstantData::of(entry) {
+            bytes.push(0);
+            let _ = writeln!(
+                output,
+                "{} = private unnamed_addr constant [{} x i8] c\"{}\"",
+                constant_name(index),
+                bytes.len(),
+                escape(&bytes)
+            );
+        }
+    }
+}
+
+/// Declares a symbol that is neither global data nor a function of the module.
+fn declare_external(
+    ir_module: &IRModule,
+    declarations: &RefCell<IndexMap<String, String>>,
+    name: &str,
+    function: bool,
+) {
+    if ir_module.functions.contains_key(name)
+        || ir_module
+            .global_data_section
+            .data
+            .iter()
+            .any(|data| data.name == name)
+    {
+        return;
+    }
+    let declaration = if function {
+        format!("declare void {}()", identifier('@', name))
+    } else {
+        format!("{} = external global i8", identifier('@', name))
+    };
+    declarations
+        .borrow_mut()
+        .entry(name.to_string())
+        .or_insert(declaration);
+}
+
+/// Emits each global as a packed structure mirroring its layout, so that values of mixed widths
+/// and addresses sit exactly where the native backends put them.
+fn emit_global_data(
+    ir_module: &IRModule,
+    declarations: &RefCell<IndexMap<String, String>>,
+    output: &mut String,
+) -> IRGenerateResult<()> {
+    for ir_global_data in ir_module.global_data_section.data.iter() {
+        let layout = global_data_layout(ir_module, ir_global_data)?;
+        let name = identifier('@', &ir_global_data.name);
+        if !layout.initialized {
+            let _ = writeln!(
+                output,
+                "{} = global [{} x i8] zeroinitializer, align 8",
+                name, layout.size
+            );
+            continue;
+        }
+        let mut types = vec![];
+        let mut values = vec![];
+        for item in layout.items {
+            match item {
+                IRDataItem::Bytes(bytes) => {
+                    types.push(format!("[{} x i8]", bytes.len()));
+                    values.push(format!("[{} x i8] c\"{}\"", bytes.len(), escape(&bytes)));
+                }
+                IRDataItem::Symbol(symbol) => {
+                    let function = !ir_module
+                        .global_data_section
+                        .data
+                        .iter()
+                        .any(|data| data.name == symbol);
+                    declare_external(ir_module, declarations, &symbol, function);
+                    types.push("ptr".to_string());
+                    values.push(format!("ptr {}", identifier('@', &symbol)));
+                }
+                IRDataItem::Constant(index) => {
+                    types.push("ptr".to_string());
+                    values.push(format!("ptr {}", constant_name(index)));
+                }
+                IRDataItem::Zero(size) => {
+                    types.push(format!("[{} x i8]", size));
+                    values.push(format!("[{} x i8] zeroinitializer", size));
+                }
+            }
+        }
+        let _ = writeln!(
+            output,
+            "{} = global <{{ {} }}> <{{ {} }}>, align 8",
+            name,
+            types.join(", "),
+            values.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn emit_entry_point(ir_module: &IRModule, output: &mut String) {
+    let Some(ir_function) = ir_module
+        .entry_point
+        .as_ref()
+        .and_then(|entry_point| ir_module.functions.get(entry_point))
+    else {
+        return;
+    };
+    if ir_function.name == "main" || ir_module.functions.contains_key("main") {
+        return;
+    }
+    let arguments = ir_function
+        .fields
+        .iter()
+        .take(ir_function.arguments_count)
+        .map(|field| {
+            format!(
+                "{} {}",
+                llvm_type(field._type.as_ref()),
+                zero(field._type.as_ref())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let return_type = ir_function.return_type.as_ref();
+    let call = format!(
+        "call {} {}({})",
+        llvm_type(return_type),
+        identifier('@', &ir_function.name),
+        arguments
+    );
+    output.push_str("\ndefine i32 @main() {\n");
+    match IRTypeKind::of(return_type) {
+        IRTypeKind::Integer(integer) => {
+            let bits = integer.size as u32;
+            let _ = writeln!(output, "  %result = {}", call);
+            let result = match bits {
+                32 => "%result".to_string(),
+                bits => {
+                    let cast = if bits < 32 { "zext" } else { "trunc" };
+                    let _ = writeln!(output, "  %status = {} i{} %result to i32", cast, bits);
+                    "%status".to_string()
+                }
+            };
+            let _ = writeln!(output, "  ret i32 {}", result);
+        }
+        _ => {
+            let _ = writeln!(output, "  {}\n  ret i32 0", call);
+        }
+    }
+    output.push_str("}\n");
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IRArithmetic {
+    Binary(IRCalculateOperator),
+    Not,
+    Negate,
+}
+
+/// The incoming values of the phis of one basic block, one list entry per predecessor edge.
+type IRIncoming = Vec<(String, Vec<Option<String>>)>;
+
+struct IRLLVMEmitter<'a> {
+    ir_module: &'a IRModule,
+    ir_function: Option<&'a IRFunction>,
+    ir_control_flow_graph: &'a IRControlFlowGraph,
+    types: IRFunctionTypes,
+    declarations: &'a RefCell<IndexMap<String, String>>,
+    lines: RefCell<Vec<String>>,
+    /// Where the phis of each basic block go once all incoming edges are known.
+    phi_positions: RefCell<IndexMap<String, usize>>,
+    incoming: RefCell<IndexMap<String, IRIncoming>>,
+    current_label: RefCell<String>,
+    open: Cell<bool>,
+    values: Cell<usize>,
+    location: RefCell<IRLocation>,
+    error: RefCell<Option<IRGenerateError>>,
+}
+
+impl<'a> IRLLVMEmitter<'a> {
+    fn new(
+        ir_module: &'a IRModule,
+        ir_function: Option<&'a IRFunction>,
+        declarations: &'a RefCell<IndexMap<String, String>>,
+    ) -> Self {
+        let ir_control_flow_graph = match ir_function {
+            Some(ir_function) => &ir_function.control_flow_graph,
+            None => &ir_module.global_init_section,
+        };
+        Self {
+            ir_module,
+            ir_function,
+            ir_control_flow_graph,
+            types: infer_register_types(ir_module, ir_function),
+            declarations,
+            lines: RefCell::new(vec![]),
+            phi_positions: RefCell::new(IndexMap::new()),
+            incoming: RefCell::new(IndexMap::new()),
+            current_label: RefCell::new(String::new()),
+            open: Cell::new(false),
+            values: Cell::new(0),
+            location: RefCell::new(IRLocation::Module),
+            error: RefCell::new(None),
+        }
+    }
+
+    fn emit(&self, args: fmt::Arguments) {
+        if !self.open.get() {
+            // Code after a terminator is unreachable but still needs a block.
+            let label = self.fresh("dead");
+            self.start_block(&label);
+        }
+        self.lines.borrow_mut().push(format!("  {}", args));
+    }
+
+    fn terminate(&self, args: fmt::Arguments) {
+        self.emit(args);
+        self.open.set(false);
+    }
+
+    fn start_block(&self, label: &str) {
+        self.lines.borrow_mut().push(format!("{}:", label));
+        *self.current_label.borrow_mut() = label.to_string();
+        self.open.set(true);
+    }
+
+    fn fresh(&self, prefix: &str) -> String {
+        let index = self.values.get();
+        self.values.set(index + 1);
+        format!("{}{}", prefix, index)
+    }
+
+    /// Emits an instruction producing a value and returns the value's name.
+    fn value(&self, args: fmt::Arguments) -> String {
+        let name = format!("%{}", self.fresh("t"));
+        self.emit(format_args!("{} = {}", name, args));
+        name
+    }
+
+    fn unsupported(&self, message: String) -> IRGenerateError {
+        IRGenerateError::Unsupported {
+            location: self.location.borrow().clone(),
+            message,
+        }
+    }
+
+    fn finish(&self, result: IRGenerateResult<()>) {
+        if let Err(error) = result {
+            self.error.borrow_mut().get_or_insert(error);
+        }
+    }
+
+    fn label(&self, name: &str) -> String {
+        format!("L.{}", name)
+    }
+
+    fn label_reference(&self, label: &str) -> String {
+        identifier('%', label)
+    }
+
+    fn register(&self, name: &str) -> String {
+        identifier('%', &format!("r.{}", name))
+    }
+
+    fn field(&self, name: &str) -> String {
+        identifier('%', &format!("f.{}", name))
+    }
+
+    fn phi(&self, basic_block: &str, target: &str) -> String {
+        identifier('%', &format!("p.{}.{}", basic_block, target))
+    }
+
+    fn emit_function(&self, signature: &str, output: &mut String) -> IRGenerateResult<()> {
+        let first = self.ir_control_flow_graph.basic_blocks.keys().next();
+        self.start_block("entry");
+        if let Some(ir_function) = self.ir_function {
+            for (index, field) in ir_function.fields.iter().enumerate() {
+                let _type = llvm_type(field._type.as_ref());
+                self.emit(format_args!(
+                    "{} = alloca {}",
+                    self.field(&field.name),
+                    _type
+                ));
+                if index < ir_function.arguments_count {
+                    self.emit(format_args!(
+                        "store {} %a{}, ptr {}",
+                        _type,
+                        index,
+                        self.field(&field.name)
+                    ));
+                }
+            }
+        }
+        for (name, _type) in self.types.registers.iter() {
+            self.emit(format_args!(
+                "{} = alloca {}",
+                self.register(name),
+                self.register_type(_type.as_ref())
+            ));
+        }
+        match first {
+            Some(first) => self.emit_jump("", first)?,
+            None => self.emit_return(None),
+        }
+        let count = self.ir_control_flow_graph.basic_blocks.len();
+        for (position, (name, ir_basic_block)) in
+            self.ir_control_flow_graph.basic_blocks.iter().enumerate()
+        {
+            self.start_block(&self.label(name));
+            let position_of_phis = self.lines.borrow().len();
+            self.phi_positions
+                .borrow_mut()
+                .insert(name.clone(), position_of_phis);
+            for (index, ir_instruction) in ir_basic_block.instructions.iter().enumerate() {
+                self.location.replace(IRLocation::Instruction {
+                    function: self.ir_function.map(|ir_function| ir_function.name.clone()),
+                    basic_block: name.clone(),
+                    index,
+                });
+                ir_instruction.accept(self);
+            }
+            if self.open.get() {
+                match self
+                    .ir_control_flow_graph
+                    .basic_blocks
+                    .get_index(position + 1)
+                {
+                    Some((next, _)) if position + 1 < count => {
+                        let result = self.emit_jump(name, next);
+                        self.finish(result);
+                    }
+                    _ => self.emit_return(None),
+                }
+            }
+        }
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.insert_phis()?;
+        let _ = writeln!(output, "\ndefine {} {{", signature);
+        for line in self.lines.borrow().iter() {
+            let _ = writeln!(output, "{}", line);
+        }
+        output.push_str("}\n");
+        Ok(())
+    }
+
+    /// Inserts the `phi` instructions at the top of their blocks, now that every edge is known.
+    fn insert_phis(&self) -> IRGenerateResult<()> {
+        let positions = self.phi_positions.borrow();
+        let mut lines = self.lines.borrow_mut();
+        for (name, position) in positions.iter().rev() {
+            let ir_basic_block = &self.ir_control_flow_graph.basic_blocks[name];
+            let incoming = self.incoming.borrow();
+            let edges = incoming.get(name).map(Vec::as_slice).unwrap_or_default();
+            let mut phis = vec![];
+            for (index, (target, ir_phi)) in ir_basic_block.phis().into_iter().enumerate() {
+                let _type = ir_phi._type.as_ref();
+                let entries = edges
+                    .iter()
+                    .map(|(label, values)| {
+                        format!(
+                            "[ {}, {} ]",
+                            values[index].as_deref().unwrap_or("poison"),
+                            self.label_reference(label)
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                // A block without predecessors still defines its phis, for the copies that read them.
+                let phi = if entries.is_empty() {
+                    format!("freeze {} poison", llvm_type(_type))
+                } else {
+                    format!("phi {} {}", llvm_type(_type), entries.join(", "))
+                };
+                phis.push(format!("  {} = {}", self.phi(name, &target), phi));
+            }
+            for (offset, phi) in phis.into_iter().enumerate() {
+                lines.insert(position + offset, phi);
+            }
+        }
+        Ok(())
+    }
+
+    /// Type of the slot of a register; void-typed registers hold addresses.
+    fn register_type(&self, _type: &dyn IRType) -> String {
+        match IRTypeKind::of(_type) {
+            IRTypeKind::Void(_) => "ptr".to_string(),
+            _ => llvm_type(_type),
+        }
+    }
+
+    fn target_type(&self, target: &IRVirtualRegister) -> IRGenerateResult<&dyn IRType> {
+        self.types.register_type(&target.name).ok_or_else(|| {
+            self.unsupported(format!("register %{} without a known type", target.name))
+        })
+    }
+
+    /// Stores `value`, of type `_type`, into the register `target`.
+    fn store_register(
+        &self,
+        target: &IRVirtualRegister,
+        value: &str,
+        _type: &dyn IRType,
+    ) -> IRGenerateResult<()> {
+        let target_type = self.target_type(target)?;
+        let value = self.convert(value, _type, target_type)?;
+        self.emit(format_args!(
+            "store {} {}, ptr {}",
+            self.register_type(target_type),
+            value,
+            self.register(&target.name)
+        ));
+        Ok(())
+    }
+
+    /// Converts a value between types that hold the same thing in different widths or
+    /// representations, as the other backends do implicitly with their 64-bit registers.
+    fn convert(&self, value: &str, from: &dyn IRType, to: &dyn IRType) -> IRGenerateResult<String> {
+        let (from_type, to_type) = (self.register_type(from), self.register_type(to));
+        if from_type == to_type {
+            return Ok(value.to_string());
+        }
+        let converted = match (IRValueType::of(from), IRValueType::of(to)) {
+            _ if from_type == "ptr" => {
+                let address = self.value(format_args!("ptrtoint ptr {} to i64", value));
+                return self.convert(&address, &address_type(), to);
+            }
+            (_, _) if to_type == "ptr" => {
+                let address = self.convert(value, from, &address_type())?;
+                self.value(format_args!("inttoptr i64 {} to ptr", address))
+            }
+            (IRValueType::Integer { bits: a, unsigned }, IRValueType::Integer { bits: b, .. }) => {
+                let kind = if a > b {
+                    "trunc"
+                } else if unsigned || a == 1 {
+                    "zext"
+                } else {
+                    "sext"
+                };
+                self.value(format_args!(
+                    "{} {} {} to {}",
+                    kind, from_type, value, to_type
+                ))
+            }
+            (IRValueType::Float, IRValueType::Double) => {
+                self.value(format_args!("fpext float {} to double", value))
+            }
+            (IRValueType::Double, IRValueType::Float) => {
+                self.value(format_args!("fptrunc double {} to float", value))
+            }
+            (from_value, to_value) if from_value.size() == to_value.size() => self.value(
+                format_args!("bitcast {} {} to {}", from_type, value, to_type),
+            ),
+            _ => {
+                return Err(self.unsupported(format!("conversion from {} to {}", from, to)));
+            }
+        };
+        Ok(converted)
+    }
+
+    /// The value of an operand, converted to `_type`.
+    fn operand(&self, operand: &dyn IROperand, _type: &dyn IRType) -> IRGenerateResult<String> {
+        let own_type = operand_type(self.ir_module, &self.types, operand);
+        let from = own_type.as_deref().unwrap_or(_type);
+        let value = match IROperandValue::of(operand) {
+            IROperandValue::Register(name) => {
+                let register_type = self
+                    .types
+                    .register_type(&name)
+                    .ok_or_else(|| self.unsupported(format!("register %{}", name)))?;
+                self.value(format_args!(
+                    "load {}, ptr {}",
+                    self.register_type(register_type),
+                    self.register(&name)
+                ))
+            }
+            IROperandValue::Constant(index) => {
+                let (entry, data) = constant(self.ir_module, index)
+                    .ok_or_else(|| self.unsupported(format!("constant ${}", index)))?;
+                self.constant_literal(index as usize, entry._type.as_ref(), &data)
+            }
+            IROperandValue::FieldAddress(name) => {
+                let defined = self.ir_function.is_some_and(|ir_function| {
+                    ir_function.fields.iter().any(|field| field.name == name)
+                });
+                if !defined {
+                    return Err(self.unsupported(format!("field '{}'", name)));
+                }
+                self.field(&name)
+            }
+            IROperandValue::GlobalDataAddress(name) => {
+                declare_external(self.ir_module, self.declarations, &name, false);
+                identifier('@', &name)
+            }
+            IROperandValue::FunctionAddress(name) => {
+                declare_external(self.ir_module, self.declarations, &name, true);
+                identifier('@', &name)
+            }
+            IROperandValue::Phi(_) => {
+                return Err(
+                    self.unsupported(format!("phi outside of a register copy: {}", operand))
+                );
+            }
+            _ => return Err(self.unsupported(format!("operand {}", operand))),
+        };
+        self.convert(&value, from, _type)
+    }
+
+    fn constant_literal(&self, index: usize, _type: &dyn IRType, data: &IRConstantData) -> String {
+        match (IRTypeKind::of(_type), data) {
+            (_, IRConstantData::String(_)) => constant_name(index),
+            // The only constant a structure or an array holds is `zeroinitializer`.
+            (IRTypeKind::Structure(_) | IRTypeKind::Array(_), _) => "zeroinitializer".to_string(),
+            (IRTypeKind::Integer(integer), IRConstantData::Integer(value)) => {
+                let bits = integer.size as u32;
+                if bits == 1 {
+                    (if *value & 1 == 1 { "true" } else { "false" }).to_string()
+                } else {
+                    // Sign-extended from the type's width, which LLVM accepts for every iN.
+                    (((*value << (64 - bits)) as i64) >> (64 - bits)).to_string()
+                }
+            }
+            (IRTypeKind::Pointer(_), IRConstantData::Integer(0)) => "null".to_string(),
+            (IRTypeKind::Pointer(_), IRConstantData::Integer(value)) => {
+                format!("inttoptr (i64 {} to ptr)", value)
+            }
+            (_, IRConstantData::Float(value)) => format!("0x{:016X}", (*value as f64).to_bits()),
+            (_, IRConstantData::Double(value)) => format!("0x{:016X}", value.to_bits()),
+            (_, IRConstantData::Integer(value)) => value.to_string(),
+        }
+    }
+
+    fn address(&self, operand: &dyn IROperand) -> IRGenerateResult<String> {
+        self.operand(operand, &pointer())
+    }
+
+    fn current_block(&self) -> String {
+        match &*self.location.borrow() {
+            IRLocation::Instruction { basic_block, .. } => basic_block.clone(),
+            _ => String::new(),
+        }
+    }
+
+    /// Loads the values the phis of `to` receive on an edge from the current block, and records
+    /// them for the `phi` instructions.
+    fn emit_edge(&self, from: &str, to: &str) -> IRGenerateResult<()> {
+        if !self.ir_control_flow_graph.basic_blocks.contains_key(to) {
+            return Err(self.unsupported(format!("jump to missing block '{}'", to)));
+        }
+        let moves = phi_moves(self.ir_control_flow_graph, from, to);
+        let mut values = vec![];
+        for (target, ir_phi) in self.ir_control_flow_graph.basic_blocks[to].phis() {
+            let value = match moves.iter().find(|phi_move| phi_move.target == target) {
+                Some(phi_move) => {
+                    Some(self.operand(phi_move.operand.as_ref(), ir_phi._type.as_ref())?)
+                }
+                None => None,
+            };
+            values.push(value);
+        }
+        let label = self.current_label.borrow().clone();
+        self.incoming
+            .borrow_mut()
+            .entry(to.to_string())
+            .or_default()
+            .push((label, values));
+        Ok(())
+    }
+
+    fn emit_jump(&self, from: &str, to: &str) -> IRGenerateResult<()> {
+        self.emit_edge(from, to)?;
+        self.terminate(format_args!(
+            "br label {}",
+            self.label_reference(&self.label(to))
+        ));
+        Ok(())
+    }
+
+    fn emit_return(&self, value: Option<String>) {
+        let return_type = self
+            .ir_function
+            .map(|ir_function| ir_function.return_type.as_ref());
+        match return_type {
+            Some(_type) if !matches!(IRTypeKind::of(_type), IRTypeKind::Void(_)) => {
+                self.terminate(format_args!(
+                    "ret {} {}",
+                    llvm_type(_type),
+                    value.as_deref().unwrap_or(zero(_type))
+                ));
+            }
+            _ => self.terminate(format_args!("ret void")),
+        }
+    }
+
+    /// Emits `a op b` in `_type` and returns the result.
+    fn arithmetic(
+        &self,
+        arithmetic: IRArithmetic,
+        _type: &dyn IRType,
+        a: &str,
+        b: &str,
+    ) -> IRGenerateResult<String> {
+        use IRCalculateOperator::*;
+        let value_type = IRValueType::of(_type);
+        let t = llvm_type(_type);
+        let value = match value_type {
+            IRValueType::Integer { bits, unsigned } => {
+                let instruction = match arithmetic {
+                    IRArithmetic::Binary(ADD) => "add",
+                    IRArithmetic::Binary(SUB) => "sub",
+                    IRArithmetic::Binary(MUL) => "mul",
+                    IRArithmetic::Binary(DIV) if unsigned => "udiv",
+                    IRArithmetic::Binary(DIV) => "sdiv",
+                    IRArithmetic::Binary(MOD) if unsigned => "urem",
+                    IRArithmetic::Binary(MOD) => "srem",
+                    IRArithmetic::Binary(AND) => "and",
+                    IRArithmetic::Binary(OR) => "or",
+                    IRArithmetic::Binary(XOR) => "xor",
+                    IRArithmetic::Binary(SHL) => "shl",
+                    IRArithmetic::Binary(SHR) if !unsigned => "ashr",
+                    IRArithmetic::Binary(SHR | USHR) => "lshr",
+                    IRArithmetic::Not => {
+                        return Ok(self.value(format_args!("xor {} {}, -1", t, a)));
+                    }
+                    IRArithmetic::Negate => {
+                        return Ok(self.value(format_args!("sub {} 0, {}", t, a)));
+                    }
+                };
+                // Shift amounts wrap at the width, as on the native targets, instead of
+                // producing poison.
+                let b = if matches!(instruction, "shl" | "lshr" | "ashr") {
+                    self.value(format_args!("and {} {}, {}", t, b, bits - 1))
+                } else {
+                    b.to_string()
+                };
+                self.value(format_args!("{} {} {}, {}", instruction, t, a, b))
+            }
+            IRValueType::Float | IRValueType::Double => {
+                let instruction = match arithmetic {
+                    IRArithmetic::Binary(ADD) => "fadd",
+                    IRArithmetic::Binary(SUB) => "fsub",
+                    IRArithmetic::Binary(MUL) => "fmul",
+                    IRArithmetic::Binary(DIV) => "fdiv",
+                    IRArithmetic::Binary(MOD) => "frem",
+                    IRArithmetic::Negate => {
+                        return Ok(self.value(format_args!("fneg {} {}", t, a)));
+                    }
+                    arithmetic => {
+                        return Err(self.unsupported(format!("{:?} on {}", arithmetic, _type)));
+                    }
+                };
+                self.value(format_args!("{} {} {}, {}", instruction, t, a, b))
+            }
+            IRValueType::Void => {
+                return Err(self.unsupported(format!("arithmetic on {}", _type)));
+            }
+        };
+        Ok(value)
+    }
+
+    /// Atomically replaces `*address` with `*address op rhs` and returns the new value.
+    fn emit_atomic_update(
+        &self,
+        arithmetic: IRArithmetic,
+        _type: &dyn IRType,
+        address: &dyn IROperand,
+        rhs: Option<&str>,
+    ) -> IRGenerateResult<String> {
+        let value_type = IRValueType::of(_type);
+        let address = self.address(address)?;
+        let rhs = rhs.unwrap_or_default();
+        let t = llvm_type(_type);
+        let align = value_type.size();
+        let read_modify_write = match (arithmetic, value_type) {
+            (IRArithmetic::Binary(operator), IRValueType::Integer { bits, .. }) if bits > 1 => {
+                match operator {
+                    IRCalculateOperator::ADD => Some("add"),
+                    IRCalculateOperator::SUB => Some("sub"),
+                    IRCalculateOperator::AND => Some("and"),
+                    IRCalculateOperator::OR => Some("or"),
+                    IRCalculateOperator::XOR => Some("xor"),
+                    _ => None,
+                }
+            }
+            (IRArithmetic::Binary(IRCalculateOperator::ADD), _)
+                if value_type.is_floating_point() =>
+            {
+                Some("fadd")
+            }
+            (IRArithmetic::Binary(IRCalculateOperator::SUB), _)
+                if value_type.is_floating_point() =>
+            {
+                Some("fsub")
+            }
+            _ => None,
+        };
+        if let Some(operation) = read_modify_write {
+            let old = self.value(format_args!(
+                "atomicrmw {} ptr {}, {} {} seq_cst, align {}",
+                operation, address, t, rhs, align
+            ));
+            return self.arithmetic(arithmetic, _type, &old, rhs);
+        }
+        // Anything else is a compare-exchange loop on the integer holding the value's bits.
+        let memory = memory_type(value_type);
+        let memory_ir_type = integer_type(value_type.size() as u32 * 8);
+        let initial = self.value(format_args!(
+            "load atomic {}, ptr {} seq_cst, align {}",
+            memory, address, align
+        ));
+        let before = self.current_label.borrow().clone();
+        let repeat = self.fresh("atomic");
+        let done = self.fresh("atomic");
+        let seen = format!("%{}", self.fresh("t"));
+        self.terminate(format_args!("br label %{}", repeat));
+        self.start_block(&repeat);
+        let expected = self.value(format_args!(
+            "phi {} [ {}, {} ], [ {}, %{} ]",
+            memory,
+            initial,
+            self.label_reference(&before),
+            seen,
+            repeat
+        ));
+        let old = self.reinterpret_memory(&expected, &memory_ir_type, _type)?;
+        let new = self.arithmetic(arithmetic, _type, &old, rhs)?;
+        let desired = self.reinterpret_as_memory(&new, _type, &memory_ir_type)?;
+        let pair = self.value(format_args!(
+            "cmpxchg ptr {}, {} {}, {} {} seq_cst seq_cst, align {}",
+            address, memory, expected, memory, desired, align
+        ));
+        self.emit(format_args!(
+            "{} = extractvalue {{ {}, i1 }} {}, 0",
+            seen, memory, pair
+        ));
+        let success = self.value(format_args!(
+            "extractvalue {{ {}, i1 }} {}, 1",
+            memory, pair
+        ));
+        self.terminate(format_args!(
+            "br i1 {}, label %{}, label %{}",
+            success, done, repeat
+        ));
+        self.start_block(&done);
+        Ok(new)
+    }
+
+    /// Reinterprets the bits of an atomically accessed integer as a value of `_type`.
+    fn reinterpret_memory(
+        &self,
+        value: &str,
+        memory: &dyn IRType,
+        _type: &dyn IRType,
+    ) -> IRGenerateResult<String> {
+        match IRValueType::of(_type) {
+            IRValueType::Integer { bits: 1, .. } => {
+                Ok(self.value(format_args!("trunc {} {} to i1", llvm_type(memory), value)))
+            }
+            _ => self.convert(value, memory, _type),
+        }
+    }
+
+    fn reinterpret_as_memory(
+        &self,
+        value: &str,
+        _type: &dyn IRType,
+        memory: &dyn IRType,
+    ) -> IRGenerateResult<String> {
+        match IRValueType::of(_type) {
+            IRValueType::Integer { bits: 1, .. } => {
+                Ok(self.value(format_args!("zext i1 {} to {}", value, llvm_type(memory))))
+            }
+            _ => self.convert(value, _type, memory),
+        }
+    }
+
+    fn emit_unary(
+        &self,
+        arithmetic: IRArithmetic,
+        is_atomic: bool,
+        _type: &dyn IRType,
+        operand: &dyn IROperand,
+        target: &IRVirtualRegister,
+    ) -> IRGenerateResult<()> {
+        let value = if is_atomic {
+            self.emit_atomic_update(arithmetic, _type, operand, None)?
+        } else {
+            let value = self.operand(operand, _type)?;
+            self.arithmetic(arithmetic, _type, &value, "")?
+        };
+        self.store_register(target, &value, _type)
+    }
+
+    fn emit_step(
+        &self,
+        operator: IRCalculateOperator,
+        _type: &dyn IRType,
+        operand: &dyn IROperand,
+        target: Option<&IRVirtualRegister>,
+    ) -> IRGenerateResult<()> {
+        let one = match IRValueType::of(_type) {
+            IRValueType::Integer { bits: 1, .. } => "true",
+            value_type if value_type.is_floating_point() => "1.0",
+            _ => "1",
+        };
+        let arithmetic = IRArithmetic::Binary(operator);
+        match target {
+            Some(target) => {
+                let value = self.operand(operand, _type)?;
+                let value = self.arithmetic(arithmetic, _type, &value, one)?;
+                self.store_register(target, &value, _type)
+            }
+            None => self
+                .emit_atomic_update(arithmetic, _type, operand, Some(one))
+                .map(|_| ()),
+        }
+    }
+
+    fn emit_conditional_jump(
+        &self,
+        ir_conditional_jump: &IRConditionalJump,
+    ) -> IRGenerateResult<()> {
+        let _type = ir_conditional_jump._type.as_ref();
+        let value_type = IRValueType::of(_type);
+        let t = llvm_type(_type);
+        let operand1 = if ir_conditional_jump.is_atomic {
+            let address = self.address(ir_conditional_jump.operand1.as_ref())?;
+            let memory = integer_type(value_type.size() as u32 * 8);
+            let value = self.value(format_args!(
+                "load atomic {}, ptr {} seq_cst, align {}",
+                llvm_type(&memory),
+                address,
+                value_type.size()
+            ));
+            self.reinterpret_memory(&value, &memory, _type)?
+        } else {
+            self.operand(ir_conditional_jump.operand1.as_ref(), _type)?
+        };
+        let operand2 = match ir_conditional_jump.operand2.as_ref() {
+            Some(operand2) => self.operand(operand2.as_ref(), _type)?,
+            None => zero(_type).to_string(),
+        };
+        let floating_point = value_type.is_floating_point();
+        let unsigned =
+            value_type.is_unsigned() || matches!(IRTypeKind::of(_type), IRTypeKind::Pointer(_));
+        let predicate = match (ir_conditional_jump.condition, floating_point) {
+            (IRCondition::Equal | IRCondition::IfFalse, false) => "eq",
+            (IRCondition::NotEqual | IRCondition::IfTrue, false) => "ne",
+            (IRCondition::Less, false) if unsigned => "ult",
+            (IRCondition::LessEqual, false) if unsigned => "ule",
+            (IRCondition::Greater, false) if unsigned => "ugt",
+            (IRCondition::GreaterEqual, false) if unsigned => "uge",
+            (IRCondition::Less, false) => "slt",
+            (IRCondition::LessEqual, false) => "sle",
+            (IRCondition::Greater, false) => "sgt",
+            (IRCondition::GreaterEqual, false) => "sge",
+            (IRCondition::Equal | IRCondition::IfFalse, true) => "oeq",
+            (IRCondition::NotEqual | IRCondition::IfTrue, true) => "une",
+            (IRCondition::Less, true) => "olt",
+            (IRCondition::LessEqual, true) => "ole",
+            (IRCondition::Greater, true) => "ogt",
+            (IRCondition::GreaterEqual, true) => "oge",
+        };
+        let condition = self.value(format_args!(
+            "{} {} {} {}, {}",
+            if floating_point { "fcmp" } else { "icmp" },
+            predicate,
+            t,
+            operand1,
+            operand2
+        ));
+        let target = &ir_conditional_jump.target;
+        self.emit_edge(&self.current_block(), target)?;
+        let next = self.fresh("next");
+        self.terminate(format_args!(
+            "br i1 {}, label {}, label %{}",
+            condition,
+            self.label_reference(&self.label(target)),
+            next
+        ));
+        self.start_block(&next);
+        Ok(())
+    }
+
+    fn emit_invoke(&self, ir_invoke: &IRInvoke) -> IRGenerateResult<()> {
+        if ir_invoke.argument_types.len() != ir_invoke.arguments.len() {
+            return Err(self.unsupported("invoke with mismatched argument types".to_string()));
+        }
+        let name = match IROperandValue::of(ir_invoke.address.as_ref()) {
+            IROperandValue::FunctionAddress(name) => Some(name),
+            _ => None,
+        };
+        let direct = name
+            .as_ref()
+            .and_then(|name| self.ir_module.functions.get(name))
+            .filter(|ir_function| ir_function.arguments_count == ir_invoke.arguments.len());
+        let (return_type, parameter_types): (&dyn IRType, Vec<&dyn IRType>) = match direct {
+            Some(ir_function) => (
+                ir_function.return_type.as_ref(),
+                ir_function.fields[..ir_function.arguments_count]
+                    .iter()
+                    .map(|field| field._type.as_ref())
+                    .collect(),
+            ),
+            None => (
+                ir_invoke.return_type.as_ref(),
+                ir_invoke
+                    .argument_types
+                    .iter()
+                    .map(|_type| _type.as_ref())
+                    .collect(),
+            ),
+        };
+        let mut arguments = vec![];
+        for (argument, _type) in ir_invoke.arguments.iter().zip(parameter_types.iter()) {
+            let value = self.operand(argument.as_ref(), *_type)?;
+            arguments.push(format!("{} {}", llvm_type(*_type), value));
+        }
+        let callee = match name {
+            Some(name) if !self.ir_module.functions.contains_key(&name) => {
+                // The first call fixes the signature of an external function.
+                let parameters = parameter_types
+                    .iter()
+                    .map(|_type| llvm_type(*_type))
+                    .collect::<Vec<_>>();
+                let declaration = format!(
+                    "declare {} {}({})",
+                    llvm_type(return_type),
+                    identifier('@', &name),
+                    parameters.join(", ")
+                );
+                self.declarations
+                    .borrow_mut()
+                    .entry(name.clone())
+                    .or_insert(declaration);
+                identifier('@', &name)
+            }
+            Some(name) if direct.is_some() => identifier('@', &name),
+            _ => self.address(ir_invoke.address.as_ref())?,
+        };
+        let call = format!(
+            "call {} {}({})",
+            llvm_type(return_type),
+            callee,
+            arguments.join(", ")
+        );
+        match ir_invoke.target.as_ref() {
+            Some(target) if !matches!(IRTypeKind::of(return_type), IRTypeKind::Void(_)) => {
+                let value = self.value(format_args!("{}", call));
+                self.store_register(target, &value, return_type)?;
+            }
+            _ => self.emit(format_args!("{}", call)),
+        }
+        Ok(())
+    }
+
+    fn declare_library(&self, name: &str, declaration: &str) {
+        self.declarations
+            .borrow_mut()
+            .entry(name.to_string())
+            .or_insert_with(|| declaration.to_string());
+    }
+
+    fn emit_type_cast(&self, ir_type_cast: &IRTypeCast) -> IRGenerateResult<()> {
+        let from = ir_type_cast.original_type.as_ref();
+        let to = ir_type_cast.target_type.as_ref();
+        let source = self.operand(ir_type_cast.source.as_ref(), from)?;
+        let (from_type, to_type) = (llvm_type(from), llvm_type(to));
+        let value = match ir_type_cast.kind {
+            IRTypeCastKind::ZeroExtend | IRTypeCastKind::SignExtend | IRTypeCastKind::Truncate => {
+                // Pointers take part as 64-bit integers.
+                let (source, from) = if from_type == "ptr" {
+                    let address = self.value(format_args!("ptrtoint ptr {} to i64", source));
+                    (address, address_type())
+                } else {
+                    (source, integer(from))
+                };
+                let to_integer = if to_type == "ptr" {
+                    address_type()
+                } else {
+                    integer(to)
+                };
+                let (a, b) = (llvm_type(&from), llvm_type(&to_integer));
+                let bits = |_type: &str| _type[1..].parse::<u32>().unwrap_or(64);
+                let value = if a == b {
+                    source
+                } else if bits(&a) > bits(&b) {
+                    self.value(format_args!("trunc {} {} to {}", a, source, b))
+                } else if ir_type_cast.kind == IRTypeCastKind::SignExtend {
+                    self.value(format_args!("sext {} {} to {}", a, source, b))
+                } else {
+                    self.value(format_args!("zext {} {} to {}", a, source, b))
+                };
+                if to_type == "ptr" {
+                    self.value(format_args!("inttoptr i64 {} to ptr", value))
+                } else {
+                    value
+                }
+            }
+            IRTypeCastKind::IntToFloat => {
+                let instruction = if IRValueType::of(from).is_unsigned() {
+                    "uitofp"
+                } else {
+                    "sitofp"
+                };
+                let (source, from_type) = if from_type == "ptr" {
+                    (
+                        self.value(format_args!("ptrtoint ptr {} to i64", source)),
+                        "i64".to_string(),
+                    )
+                } else {
+                    (source, from_type)
+                };
+                self.value(format_args!(
+                    "{} {} {} to {}",
+                    instruction, from_type, source, to_type
+                ))
+            }
+            IRTypeCastKind::FloatToInt => {
+                let instruction = if IRValueType::of(to).is_unsigned() {
+                    "fptoui"
+                } else {
+                    "fptosi"
+                };
+                self.value(format_args!(
+                    "{} {} {} to {}",
+                    instruction, from_type, source, to_type
+                ))
+            }
+            IRTypeCastKind::FloatExtend | IRTypeCastKind::FloatTruncate if from_type == to_type => {
+                source
+            }
+            IRTypeCastKind::FloatExtend => self.value(format_args!(
+                "fpext {} {} to {}",
+                from_type, source, to_type
+            )),
+            IRTypeCastKind::FloatTruncate => self.value(format_args!(
+                "fptrunc {} {} to {}",
+                from_type, source, to_type
+            )),
+        };
+        self.store_register(&ir_type_cast.target, &value, to)
+    }
+
+    fn emit_asm(&self, ir_asm: &IRAsm) -> IRGenerateResult<()> {
+        let mut constraints = vec![];
+        let mut arguments = vec![];
+        for (index, (resource, name)) in
+            ir_asm.resources.iter().zip(ir_asm.names.iter()).enumerate()
+        {
+            let register = asm_register(name)
+                .ok_or_else(|| self.unsupported(format!("asm register '{}'", name)))?;
+            let _type = ir_asm
+                .types
+                .get(index)
+                .map(|_type| _type.as_ref())
+                .ok_or_else(|| {
+                    self.unsupported(format!("asm resource {} without a type", index))
+                })?;
+            let value = self.operand(resource.as_ref(), _type)?;
+            constraints.push(format!("{{{}}}", register));
+            arguments.push(format!("{} {}", llvm_type(_type), value));
+        }
+        constraints.extend(["~{memory}", "~{dirflag}", "~{fpsr}", "~{flags}"].map(String::from));
+        // `$` starts an operand reference in LLVM's asm strings.
+        let code = ir_asm.code.replace("\\n", "\n").replace('$', "$$");
+        self.emit(format_args!(
+            "call void asm sideeffect \"{}\", \"{}\"({})",
+            escape(code.as_bytes()),
+            constraints.join(","),
+            arguments.join(", ")
+        ));
+        Ok(())
+    }
+}
+
+fn pointer() -> IRPointerType {
+    IRPointerType::new(Box::new(IRVoidType::new()))
+}
+
+fn address_type() -> IRIntegerType {
+    integer_type(64)
+}
+
+fn integer_type(bits: u32) -> IRIntegerType {
+    let size = match bits {
+        1 => IRIntegerTypeSize::OneBit,
+        8 => IRIntegerTypeSize::OneByte,
+        16 => IRIntegerTypeSize::TwoBytes,
+        32 => IRIntegerTypeSize::FourBytes,
+        _ => IRIntegerTypeSize::EightBytes,
+    };
+    IRIntegerType::new(size, true)
+}
+
+/// The integer type of the same width as an integer or pointer type.
+fn integer(_type: &dyn IRType) -> IRIntegerType {
+    match IRTypeKind::of(_type) {
+        IRTypeKind::Integer(integer) => integer,
+        _ => address_type(),
+    }
+}
+
+impl IRVisitor for IRLLVMEmitter<'_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_goto(&self, ir_goto: &IRGoto) {
+        self.finish(self.emit_jump(&self.current_block(), &ir_goto.target));
+    }
+    fn visit_conditional_jump(&self, ir_conditional_jump: &IRConditionalJump) {
+        self.finish(self.emit_conditional_jump(ir_conditional_jump));
+    }
+    fn visit_return(&self, ir_return: &IRReturn) {
+        let value = match (ir_return.operand.as_ref(), self.ir_function) {
+            (Some(operand), Some(ir_function))
+                if !matches!(
+                    IRTypeKind::of(ir_function.return_type.as_ref()),
+                    IRTypeKind::Void(_)
+                ) =>
+            {
+                match self.operand(operand.as_ref(), ir_function.return_type.as_ref()) {
+                    Ok(value) => Some(value),
+                    Err(error) => return self.finish(Err(error)),
+                }
+            }
+            _ => None,
+        };
+        self.emit_return(value);
+    }
+    fn visit_calculate(&self, ir_calculate: &IRCalculate) {
+        let _type = ir_calculate._type.as_ref();
+        let arithmetic = IRArithmetic::Binary(ir_calculate.operator);
+        let result = (|| {
+            let operand2 = self.operand(ir_calculate.operand2.as_ref(), _type)?;
+            let value = if ir_calculate.is_atomic {
+                self.emit_atomic_update(
+                    arithmetic,
+                    _type,
+                    ir_calculate.operand1.as_ref(),
+                    Some(&operand2),
+                )?
+            } else {
+                let operand1 = self.operand(ir_calculate.operand1.as_ref(), _type)?;
+                self.arithmetic(arithmetic, _type, &operand1, &operand2)?
+            };
+            self.store_register(&ir_calculate.target, &value, _type)
+        })();
+        self.finish(result);
+    }
+    fn visit_not(&self, ir_not: &IRNot) {
+        self.finish(self.emit_unary(
+            IRArithmetic::Not,
+            ir_not.is_atomic,
+            ir_not._type.as_ref(),
+            ir_not.operand.as_ref(),
+            &ir_not.target,
+        ));
+    }
+    fn visit_negate(&self, ir_negate: &IRNegate) {
+        self.finish(self.emit_unary(
+            IRArithmetic::Negate,
+            ir_negate.is_atomic,
+            ir_negate._type.as_ref(),
+            ir_negate.operand.as_ref(),
+            &ir_negate.target,
+        ));
+    }
+    fn visit_malloc(&self, ir_malloc: &IRMalloc) {
+        let result = (|| {
+            let size = self.operand(ir_malloc.size.as_ref(), &address_type())?;
+            self.declare_library("malloc", "declare ptr @malloc(i64)");
+            let value = self.value(format_args!("call ptr @malloc(i64 {})", size));
+            self.store_register(&ir_malloc.target, &value, &pointer())
+        })();
+        self.finish(result);
+    }
+    fn visit_free(&self, ir_free: &IRFree) {
+        let result = (|| {
+            let address = self.address(ir_free.ptr.as_ref())?;
+            self.declare_library("free", "declare void @free(ptr)");
+            self.emit(format_args!("call void @free(ptr {})", address));
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_realloc(&self, ir_realloc: &IRRealloc) {
+        let result = (|| {
+            let address = self.address(ir_realloc.ptr.as_ref())?;
+            let size = self.operand(ir_realloc.size.as_ref(), &address_type())?;
+            self.declare_library("realloc", "declare ptr @realloc(ptr, i64)");
+            let value = self.value(format_args!(
+                "call ptr @realloc(ptr {}, i64 {})",
+                address, size
+            ));
+            self.store_register(&ir_realloc.target, &value, &pointer())
+        })();
+        self.finish(result);
+    }
+    fn visit_get(&self, ir_get: &IRGet) {
+        let result = (|| {
+            let _type = ir_get._type.as_ref();
+            let address = self.address(ir_get.address.as_ref())?;
+            let value = self.value(format_args!("load {}, ptr {}", llvm_type(_type), address));
+            self.store_register(&ir_get.target, &value, _type)
+        })();
+        self.finish(result);
+    }
+    fn visit_set(&self, ir_set: &IRSet) {
+        let result = (|| {
+            let _type = ir_set._type.as_ref();
+            let address = self.address(ir_set.address.as_ref())?;
+            let value = self.operand(ir_set.value.as_ref(), _type)?;
+            self.emit(format_args!(
+                "store {} {}, ptr {}",
+                llvm_type(_type),
+                value,
+                address
+            ));
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_set_virtual_register(&self, ir_set_virtual_register: &IRSetVirtualRegister) {
+        let result = (|| {
+            let target = &ir_set_virtual_register.target;
+            match IROperandValue::of(ir_set_virtual_register.source.as_ref()) {
+                IROperandValue::Phi(ir_phi) => {
+                    let value = self.phi(&self.current_block(), &target.name);
+                    self.store_register(target, &value, ir_phi._type.as_ref())
+                }
+                _ => {
+                    let _type = self.target_type(target)?;
+                    let value = self.operand(ir_set_virtual_register.source.as_ref(), _type)?;
+                    self.store_register(target, &value, _type)
+                }
+            }
+        })();
+        self.finish(result);
+    }
+    fn visit_invoke(&self, ir_invoke: &IRInvoke) {
+        self.finish(self.emit_invoke(ir_invoke));
+    }
+    fn visit_no_operate(&self, _ir_no_operate: &IRNoOperate) {}
+    fn visit_increase(&self, ir_increase: &IRIncrease) {
+        self.finish(self.emit_step(
+            IRCalculateOperator::ADD,
+            ir_increase._type.as_ref(),
+            ir_increase.operand.as_ref(),
+            ir_increase.target.as_deref(),
+        ));
+    }
+    fn visit_decrease(&self, ir_decrease: &IRDecrease) {
+        self.finish(self.emit_step(
+            IRCalculateOperator::SUB,
+            ir_decrease._type.as_ref(),
+            ir_decrease.operand.as_ref(),
+            ir_decrease.target.as_deref(),
+        ));
+    }
+    fn visit_stack_allocate(&self, ir_stack_allocate: &IRStackAllocate) {
+        let result = (|| {
+            let size = self.operand(ir_stack_allocate.size.as_ref(), &address_type())?;
+            let value = self.value(format_args!("alloca i8, i64 {}, align 16", size));
+            self.store_register(&ir_stack_allocate.target, &value, &pointer())
+        })();
+        self.finish(result);
+    }
+    fn visit_type_cast(&self, ir_type_cast: &IRTypeCast) {
+        self.finish(self.emit_type_cast(ir_type_cast));
+    }
+    fn visit_asm(&self, ir_asm: &IRAsm) {
+        self.finish(self.emit_asm(ir_asm));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parser::parse_module;
+
+    const LOOP: &str = "\
+constant $0 = i64 0
+constant $1 = i64 1
+function i64 sum(i64 n) {
+entry:
+    %pn = `field_address([n], [])
+    %n = get i64, %pn
+    goto loop
+loop:
+    %i = phi i64 [entry, $0], [loop, %next_i]
+    %total = phi i64 [entry, $0], [loop, %next_total]
+    %next_total = add i64 %total, %i
+    %next_i = add i64 %i, $1
+    conditional_jump i64 le, %next_i, %n, #loop
+done:
+    %r = invoke i64 `function_address([twice], []), [i64, %next_total]
+    return %r
+}
+function i64 twice(i64 x) {
+entry:
+    %px = `field_address([x], [])
+    %x = get i64, %px
+    %r = add i64 %x, %x
+    return %r
+}
+";
+
+    #[test]
+    fn phis_take_the_values_loaded_before_each_branch() {
+        assert_eq!(
+            generate_ir(&parse_module(LOOP).unwrap()).unwrap(),
+            "\n\
+define i64 @sum(i64 %a0) {
+entry:
+  %f.n = alloca i64
+  store i64 %a0, ptr %f.n
+  %r.pn = alloca ptr
+  %r.n = alloca i64
+  %r.i = alloca i64
+  %r.total = alloca i64
+  %r.next_total = alloca i64
+  %r.next_i = alloca i64
+  %r.r = alloca i64
+  br label %L.entry
+L.entry:
+  store ptr %f.n, ptr %r.pn
+  %t0 = load ptr, ptr %r.pn
+  %t1 = load i64, ptr %t0
+  store i64 %t1, ptr %r.n
+  br label %L.loop
+L.loop:
+  %p.loop.i = phi i64 [ 0, %L.entry ], [ %t10, %L.loop ]
+  %p.loop.total = phi i64 [ 0, %L.entry ], [ %t11, %L.loop ]
+  store i64 %p.loop.i, ptr %r.i
+  store i64 %p.loop.total, ptr %r.total
+  %t2 = load i64, ptr %r.i
+  %t3 = load i64, ptr %r.total
+  %t4 = add i64 %t3, %t2
+  store i64 %t4, ptr %r.next_total
+  %t5 = load i64, ptr %r.i
+  %t6 = add i64 %t5, 1
+  store i64 %t6, ptr %r.next_i
+  %t7 = load i64, ptr %r.next_i
+  %t8 = load i64, ptr %r.n
+  %t9 = icmp sle i64 %t7, %t8
+  %t10 = load i64, ptr %r.next_i
+  %t11 = load i64, ptr %r.next_total
+  br i1 %t9, label %L.loop, label %next12
+next12:
+  br label %L.done
+L.done:
+  %t13 = load i64, ptr %r.next_total
+  %t14 = call i64 @twice(i64 %t13)
+  store i64 %t14, ptr %r.r
+  %t15 = load i64, ptr %r.r
+  ret i64 %t15
+}
+
+define i64 @twice(i64 %a0) {
+entry:
+  %f.x = alloca i64
+  store i64 %a0, ptr %f.x
+  %r.px = alloca ptr
+  %r.x = alloca i64
+  %r.r = alloca i64
+  br label %L.entry
+L.entry:
+  store ptr %f.x, ptr %r.px
+  %t0 = load ptr, ptr %r.px
+  %t1 = load i64, ptr %t0
+  store i64 %t1, ptr %r.x
+  %t2 = load i64, ptr %r.x
+  %t3 = load i64, ptr %r.x
+  %t4 = add i64 %t3, %t2
+  store i64 %t4, ptr %r.r
+  %t5 = load i64, ptr %r.r
+  ret i64 %t5
+}
+"
+        );
+    }
+}
@@ -0,0 +1,1329 @@
+//! GNU as RV64GC assembly for the LP64D calling convention.
+//!
+//! The scheme is the one of the x86-64 backend: every virtual register and field lives in an
+//! 8-byte slot below the frame pointer `s0`, and each instruction loads its operands into
+//! `a0`/`a1`, computes and stores the result back. `a2`-`a7` and `t0`-`t4` are scratch registers
+//! and `t5`/`t6` address memory. Integers are kept extended to 64 bits according to their
+//! signedness; floats and doubles are kept as their bit patterns and moved to `fa0`/`fa1` only to
+//! compute. Atomic instructions use AMOs where one exists for the operation and width, and
+//! `lr`/`sc` compare-and-swap loops otherwise, on the containing word for 8- and 16-bit values.
+
+use crate::backend::x86_64::{constant_label, emit_constants, emit_global_data, emit_text_section};
+use crate::backend::{
+    IRConstantData, IRFrameLayout, IRGenerateError, IRGenerateResult, IROperandValue, IRTarget,
+    IRValueType, check_scalar_values, constant, lower_for_target, mangle_symbol, phi_moves,
+};
+use crate::ir::base::{IRCondition, IRControlFlowGraph, IRFunction, IRNode};
+use crate::ir::instruction::{
+    IRAsm, IRCalculate, IRCalculateOperator, IRConditionalJump, IRDecrease, IRFree, IRGet, IRGoto,
+    IRIncrease, IRInvoke, IRMalloc, IRNegate, IRNoOperate, IRNot, IRRealloc, IRReturn, IRSet,
+    IRSetVirtualRegister, IRStackAllocate, IRTypeCast, IRTypeCastKind,
+};
+use crate::ir::operand::{IROperand, IRVirtualRegister};
+use crate::ir::type_check::infer_register_types;
+use crate::ir::verify::IRLocation;
+use crate::ir::{IRModule, IRVisitor};
+use std::cell::{Cell, RefCell};
+use std::fmt::{self, Write};
+
+const GLOBAL_INIT_SYMBOL: &str = "__lg_global_init";
+const ARGUMENT_REGISTERS: usize = 8;
+const SCRATCH_SLOTS: usize = 3;
+/// The saved return address and frame pointer, right below the frame pointer.
+const SAVE_AREA: i64 = 16;
+
+pub fn generate_assembly(ir_module: &IRModule) -> IRGenerateResult<String> {
+    let ir_module = &*lower_for_target(ir_module, IRTarget::RiscV64)?;
+    check_scalar_values(ir_module)?;
+    let mut output = String::new();
+    emit_constants(ir_module, &mut output);
+    emit_global_data(ir_module, &mut output)?;
+    if !ir_module.global_init_section.basic_blocks.is_empty() {
+        emit_text_section(GLOBAL_INIT_SYMBOL, &mut output);
+        IRRISCVEmitter::new(ir_module, None).emit_function(GLOBAL_INIT_SYMBOL, &mut output)?;
+        output.push_str("\t.section .init_array,\"aw\"\n\t.balign 8\n");
+        let _ = writeln!(output, "\t.quad {}", GLOBAL_INIT_SYMBOL);
+    }
+    for ir_function in ir_module.functions.values() {
+        let symbol = mangle_symbol(&ir_function.name);
+        emit_text_section(&symbol, &mut output);
+        let _ = writeln!(output, "\t.globl {}", symbol);
+        IRRISCVEmitter::new(ir_module, Some(ir_function)).emit_function(&symbol, &mut output)?;
+    }
+    if let Some(entry_point) = ir_module.entry_point.as_ref()
+        && entry_point != "main"
+        && !ir_module.functions.contains_key("main")
+    {
+        emit_text_section("main", &mut output);
+        let _ = writeln!(
+            output,
+            "\t.globl main\n\t.type main, @function\nmain:\n\ttail {}\n\t.size main, .-main",
+            mangle_symbol(entry_point)
+        );
+    }
+    output.push_str("\t.section .note.GNU-stack,\"\",@progbits\n");
+    Ok(output)
+}
+
+/// The register an `asm` resource name refers to, by ABI name or as `xN`, except the registers
+/// the platform, the frame and the emitter own.
+fn asm_register(name: &str) -> Option<String> {
+    const ABI_NAMES: [&str; 32] = [
+        "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+        "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+        "t5", "t6",
+    ];
+    let number = match name
+        .strip_prefix('x')
+        .and_then(|number| number.parse().ok())
+    {
+        Some(number) if number < 32 => number,
+        _ => ABI_NAMES.iter().position(|abi_name| *abi_name == name)?,
+    };
+    match number {
+        5..=7 | 9..=29 => Some(ABI_NAMES[number].to_string()),
+        _ => None,
+    }
+}
+
+fn is_callee_saved(register: &str) -> bool {
+    register.starts_with('s') && register != "sp"
+}
+
+/// The suffix of AMOs and `lr`/`sc` for a width in bytes.
+fn atomic_suffix(size: u64) -> &'static str {
+    if size == 8 { "d" } else { "w" }
+}
+
+fn float_suffix(value_type: IRValueType) -> &'static str {
+    if value_type == IRValueType::Float {
+        "s"
+    } else {
+        "d"
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IRArithmetic {
+    Binary(IRCalculateOperator),
+    Not,
+    Negate,
+}
+
+struct IRRISCVEmitter<'a> {
+    ir_module: &'a IRModule,
+    ir_function: Option<&'a IRFunction>,
+    ir_control_flow_graph: &'a IRControlFlowGraph,
+    frame: IRFrameLayout,
+    output: RefCell<String>,
+    location: RefCell<IRLocation>,
+    label_prefix: RefCell<String>,
+    labels: Cell<usize>,
+    terminated: Cell<bool>,
+    error: RefCell<Option<IRGenerateError>>,
+}
+
+impl<'a> IRRISCVEmitter<'a> {
+    /// Creates an emitter for a function, or for the global init section when `ir_function` is
+    /// `None`.
+    fn new(ir_module: &'a IRModule, ir_function: Option<&'a IRFunction>) -> Self {
+        let ir_control_flow_graph = match ir_function {
+            Some(ir_function) => &ir_function.control_flow_graph,
+            None => &ir_module.global_init_section,
+        };
+        let fields = ir_function
+            .iter()
+            .flat_map(|ir_function| ir_function.fields.iter())
+            .map(|field| (field.name.clone(), IRValueType::of(field._type.as_ref())))
+            .collect::<Vec<_>>();
+        let types = infer_register_types(ir_module, ir_function);
+        Self {
+            ir_module,
+            ir_function,
+            ir_control_flow_graph,
+            frame: IRFrameLayout::new(
+                &fields,
+                &types,
+                ir_control_flow_graph,
+                SAVE_AREA,
+                SCRATCH_SLOTS,
+            ),
+            output: RefCell::new(String::new()),
+            location: RefCell::new(IRLocation::Module),
+            label_prefix: RefCell::new(String::new()),
+            labels: Cell::new(0),
+            terminated: Cell::new(false),
+            error: RefCell::new(None),
+        }
+    }
+
+    fn emit(&self, args: fmt::Arguments) {
+        let mut output = self.output.borrow_mut();
+        output.push('\t');
+        let _ = output.write_fmt(args);
+        output.push('\n');
+    }
+
+    fn emit_label(&self, label: &str) {
+        let _ = writeln!(self.output.borrow_mut(), "{}:", label);
+    }
+
+    fn unsupported(&self, message: String) -> IRGenerateError {
+        IRGenerateError::Unsupported {
+            location: self.location.borrow().clone(),
+            message,
+        }
+    }
+
+    fn finish(&self, result: IRGenerateResult<()>) {
+        if let Err(error) = result {
+            self.error.borrow_mut().get_or_insert(error);
+        }
+    }
+
+    fn block_label(&self, name: &str) -> String {
+        format!("{}.{}", self.label_prefix.borrow(), mangle_symbol(name))
+    }
+
+    fn fresh_label(&self) -> String {
+        self.labels.set(self.labels.get() + 1);
+        format!("{}..{}", self.label_prefix.borrow(), self.labels.get())
+    }
+
+    fn emit_function(&self, symbol: &str, output: &mut String) -> IRGenerateResult<()> {
+        self.label_prefix.replace(format!(".L{}", symbol));
+        self.emit(format_args!(".type {}, @function", symbol));
+        self.emit_label(symbol);
+        self.emit(format_args!("addi sp, sp, -{}", SAVE_AREA));
+        self.emit(format_args!("sd ra, 8(sp)"));
+        self.emit(format_args!("sd s0, 0(sp)"));
+        self.emit(format_args!("addi s0, sp, {}", SAVE_AREA));
+        if self.frame.size > 0 {
+            self.emit_adjust_stack(-self.frame.size);
+        }
+        self.emit_arguments();
+        for (position, (name, ir_basic_block)) in
+            self.ir_control_flow_graph.basic_blocks.iter().enumerate()
+        {
+            self.emit_label(&self.block_label(name));
+            self.terminated.set(false);
+            for (index, ir_instruction) in ir_basic_block.instructions.iter().enumerate() {
+                self.location.replace(IRLocation::Instruction {
+                    function: self.ir_function.map(|ir_function| ir_function.name.clone()),
+                    basic_block: name.clone(),
+                    index,
+                });
+                self.terminated.set(false);
+                ir_instruction.accept(self);
+            }
+            if !self.terminated.get() {
+                match self
+                    .ir_control_flow_graph
+                    .basic_blocks
+                    .get_index(position + 1)
+                {
+                    Some((next, _)) => {
+                        let result = self.emit_edge(name, next);
+                        self.finish(result);
+                    }
+                    None => self.emit_epilogue(),
+                }
+            }
+        }
+        self.emit(format_args!(".size {}, .-{}", symbol, symbol));
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        output.push_str(&self.output.borrow());
+        Ok(())
+    }
+
+    fn emit_epilogue(&self) {
+        self.emit(format_args!("addi sp, s0, -{}", SAVE_AREA));
+        self.emit(format_args!("ld ra, 8(sp)"));
+        self.emit(format_args!("ld s0, 0(sp)"));
+        self.emit(format_args!("addi sp, sp, {}", SAVE_AREA));
+        self.emit(format_args!("ret"));
+    }
+
+    /// `sp += amount`, for amounts beyond the 12-bit immediate too.
+    fn emit_adjust_stack(&self, amount: i64) {
+        if (-2048..2048).contains(&amount) {
+            self.emit(format_args!("addi sp, sp, {}", amount));
+        } else {
+            self.emit(format_args!("li t5, {}", amount));
+            self.emit(format_args!("add sp, sp, t5"));
+        }
+    }
+
+    /// A memory operand for a frame slot; offsets beyond the 12-bit immediate go through `t6`.
+    fn frame_slot(&self, offset: i64) -> String {
+        if (-2048..2048).contains(&offset) {
+            return format!("{}(s0)", offset);
+        }
+        self.emit(format_args!("li t6, {}", offset));
+        self.emit(format_args!("add t6, s0, t6"));
+        "0(t6)".to_string()
+    }
+
+    /// Emits `instruction` with a frame slot as its memory operand.
+    fn emit_slot(&self, instruction: &str, offset: i64) {
+        let memory = self.frame_slot(offset);
+        self.emit(format_args!("{}, {}", instruction, memory));
+    }
+
+    /// Stores incoming arguments into the slots of the leading fields. Floats and doubles come
+    /// in `fa0`-`fa7`, then in the integer registers left, then on the stack like integers.
+    fn emit_arguments(&self) {
+        let mut integers = 0;
+        let mut floats = 0;
+        let mut stack = 0;
+        for (offset, value_type) in self.frame.fields.values().take(
+            self.ir_function
+                .map_or(0, |ir_function| ir_function.arguments_count),
+        ) {
+            let register = if value_type.is_floating_point() && floats < ARGUMENT_REGISTERS {
+                self.move_from_float(*value_type, &format!("fa{}", floats), "t0");
+                floats += 1;
+                "t0".to_string()
+            } else if integers < ARGUMENT_REGISTERS {
+                integers += 1;
+                format!("a{}", integers - 1)
+            } else {
+                self.emit(format_args!("ld t0, {}(s0)", stack * 8));
+                stack += 1;
+                "t0".to_string()
+            };
+            let memory = self.frame_slot(*offset);
+            self.store_from(*value_type, &register, &memory);
+        }
+    }
+
+    /// Loads a value of the given type from memory into `a0`, extending it to 64 bits.
+    fn load(&self, value_type: IRValueType, memory: &str) {
+        let instruction = match value_type {
+            IRValueType::Integer { bits: 1, .. }
+            | IRValueType::Integer {
+                bits: 8,
+                unsigned: true,
+            } => "lbu",
+            IRValueType::Integer { bits: 8, .. } => "lb",
+            IRValueType::Integer {
+                bits: 16,
+                unsigned: true,
+            } => "lhu",
+            IRValueType::Integer { bits: 16, .. } => "lh",
+            IRValueType::Integer {
+                bits: 32,
+                unsigned: false,
+            } => "lw",
+            IRValueType::Integer { bits: 32, .. } | IRValueType::Float => "lwu",
+            _ => "ld",
+        };
+        self.emit(format_args!("{} a0, {}", instruction, memory));
+    }
+
+    /// Stores `a0` to memory with the width of the given type.
+    fn store(&self, value_type: IRValueType, memory: &str) {
+        self.store_from(value_type, "a0", memory);
+    }
+
+    fn store_from(&self, value_type: IRValueType, register: &str, memory: &str) {
+        let size = value_type.size();
+        if size == 0 {
+            return;
+        }
+        if let IRValueType::Integer { bits: 1, .. } = value_type {
+            self.emit(format_args!("andi {}, {}, 1", register, register));
+        }
+        let instruction = match size {
+            1 => "sb",
+            2 => "sh",
+            4 => "sw",
+            _ => "sd",
+        };
+        self.emit(format_args!("{} {}, {}", instruction, register, memory));
+    }
+
+    /// Re-extends the low bits of `register` according to the type.
+    fn extend_register(&self, value_type: IRValueType, register: &str) {
+        let shifts = |bits: u32, right: &str| {
+            self.emit(format_args!(
+                "slli {}, {}, {}",
+                register,
+                register,
+                64 - bits
+            ));
+            self.emit(format_args!(
+                "{} {}, {}, {}",
+                right,
+                register,
+                register,
+                64 - bits
+            ));
+        };
+        match value_type {
+            IRValueType::Integer { bits: 1, .. } => {
+                self.emit(format_args!("andi {}, {}, 1", register, register))
+            }
+            IRValueType::Integer {
+                bits: 8,
+                unsigned: true,
+            } => self.emit(format_args!("andi {}, {}, 255", register, register)),
+            IRValueType::Integer {
+                bits: 32,
+                unsigned: false,
+            } => self.emit(format_args!("sext.w {}, {}", register, register)),
+            IRValueType::Integer {
+                bits: bits @ (8 | 16 | 32),
+                unsigned,
+            } => shifts(bits, if unsigned { "srli" } else { "srai" }),
+            IRValueType::Float => shifts(32, "srli"),
+            _ => {}
+        }
+    }
+
+    fn extend(&self, value_type: IRValueType) {
+        self.extend_register(value_type, "a0");
+    }
+
+    fn zero_extend(&self, bits: u32) {
+        self.extend(IRValueType::Integer {
+            bits,
+            unsigned: true,
+        });
+    }
+
+    fn sign_extend(&self, bits: u32) {
+        self.extend(IRValueType::Integer {
+            bits,
+            unsigned: false,
+        });
+    }
+
+    fn slot(&self, name: &str) -> IRGenerateResult<(i64, IRValueType)> {
+        self.frame
+            .register(name)
+            .ok_or_else(|| self.unsupported(format!("register %{} without a known type", name)))
+    }
+
+    fn store_register(&self, target: &IRVirtualRegister) -> IRGenerateResult<()> {
+        let (offset, value_type) = self.slot(&target.name)?;
+        let memory = self.frame_slot(offset);
+        self.store(value_type, &memory);
+        Ok(())
+    }
+
+    fn symbol_address(&self, register: &str, symbol: &str, defined: bool) {
+        if defined {
+            self.emit(format_args!("lla {}, {}", register, symbol));
+        } else {
+            let label = self.fresh_label();
+            self.emit_label(&label);
+            self.emit(format_args!(
+                "auipc {}, %got_pcrel_hi({})",
+                register, symbol
+            ));
+            self.emit(format_args!(
+                "ld {}, %pcrel_lo({})({})",
+                register, label, register
+            ));
+        }
+    }
+
+    /// Loads an operand into `a0`; only `t5` and `t6` are used on the way.
+    fn load_operand(&self, operand: &dyn IROperand) -> IRGenerateResult<()> {
+        match IROperandValue::of(operand) {
+            IROperandValue::Register(name) => {
+                let (offset, value_type) = self.slot(&name)?;
+                let memory = self.frame_slot(offset);
+                self.load(value_type, &memory);
+            }
+            IROperandValue::Constant(index) => {
+                let (entry, data) = constant(self.ir_module, index)
+                    .ok_or_else(|| self.unsupported(format!("constant ${}", index)))?;
+                let label = constant_label(index as usize);
+                match data {
+                    IRConstantData::String(_) => self.symbol_address("a0", &label, true),
+                    _ => {
+                        self.symbol_address("t5", &label, true);
+                        self.load(IRValueType::of(entry._type.as_ref()), "0(t5)");
+                    }
+                }
+            }
+            IROperandValue::FieldAddress(name) => {
+                let (offset, _) = self
+                    .frame
+                    .fields
+                    .get(&name)
+                    .ok_or_else(|| self.unsupported(format!("field '{}'", name)))?;
+                if (-2048..2048).contains(offset) {
+                    self.emit(format_args!("addi a0, s0, {}", offset));
+                } else {
+                    self.emit(format_args!("li a0, {}", offset));
+                    self.emit(format_args!("add a0, s0, a0"));
+                }
+            }
+            IROperandValue::GlobalDataAddress(name) => {
+                let defined = self
+                    .ir_module
+                    .global_data_section
+                    .data
+                    .iter()
+                    .any(|data| data.name == name);
+                self.symbol_address("a0", &mangle_symbol(&name), defined);
+            }
+            IROperandValue::FunctionAddress(name) => {
+                let defined = self.ir_module.functions.contains_key(&name);
+                self.symbol_address("a0", &mangle_symbol(&name), defined);
+            }
+            _ => return Err(self.unsupported(format!("operand {}", operand))),
+        }
+        Ok(())
+    }
+
+    /// Loads `value` into `a1` and `operand` into `a0`.
+    fn load_pair(&self, operand: &dyn IROperand, value: &dyn IROperand) -> IRGenerateResult<()> {
+        self.load_operand(value)?;
+        self.emit(format_args!("mv a1, a0"));
+        self.load_operand(operand)
+    }
+
+    /// Copies the phi inputs of block `to` for the edge leaving block `from`.
+    fn emit_edge(&self, from: &str, to: &str) -> IRGenerateResult<()> {
+        for phi_move in phi_moves(self.ir_control_flow_graph, from, to) {
+            self.load_operand(phi_move.operand.as_ref())?;
+            let memory = self.frame_slot(self.frame.phi_shadows[&phi_move.target]);
+            self.store(phi_move.value_type, &memory);
+        }
+        Ok(())
+    }
+
+    fn current_block(&self) -> String {
+        match &*self.location.borrow() {
+            IRLocation::Instruction { basic_block, .. } => basic_block.clone(),
+            _ => String::new(),
+        }
+    }
+
+    fn emit_jump(&self, target: &str) -> IRGenerateResult<()> {
+        if !self.ir_control_flow_graph.basic_blocks.contains_key(target) {
+            return Err(self.unsupported(format!("jump to missing block '{}'", target)));
+        }
+        self.emit_edge(&self.current_block(), target)?;
+        self.emit(format_args!("j {}", self.block_label(target)));
+        Ok(())
+    }
+
+    /// Moves the bits of a float or double from a general purpose register to a float register.
+    fn move_to_float(&self, value_type: IRValueType, register: &str, float: &str) {
+        match value_type {
+            IRValueType::Float => self.emit(format_args!("fmv.w.x {}, {}", float, register)),
+            _ => self.emit(format_args!("fmv.d.x {}, {}", float, register)),
+        }
+    }
+
+    fn move_from_float(&self, value_type: IRValueType, float: &str, register: &str) {
+        match value_type {
+            IRValueType::Float => {
+                self.emit(format_args!("fmv.x.w {}, {}", register, float));
+                self.extend_register(value_type, register);
+            }
+            _ => self.emit(format_args!("fmv.x.d {}, {}", register, float)),
+        }
+    }
+
+    /// Computes `a0 op a1` into `a0`. Clobbers `a2`, `fa0`, `fa1` and, for floating point
+    /// remainders, every caller-saved register.
+    fn compute(&self, arithmetic: IRArithmetic, value_type: IRValueType) -> IRGenerateResult<()> {
+        use IRCalculateOperator::*;
+        match (value_type, arithmetic) {
+            (IRValueType::Integer { bits, unsigned }, IRArithmetic::Binary(operator)) => {
+                let u = if unsigned { "u" } else { "" };
+                match operator {
+                    ADD => self.emit(format_args!("add a0, a0, a1")),
+                    SUB => self.emit(format_args!("sub a0, a0, a1")),
+                    MUL => self.emit(format_args!("mul a0, a0, a1")),
+                    AND => self.emit(format_args!("and a0, a0, a1")),
+                    OR => self.emit(format_args!("or a0, a0, a1")),
+                    XOR => self.emit(format_args!("xor a0, a0, a1")),
+                    DIV => self.emit(format_args!("div{} a0, a0, a1", u)),
+                    MOD => self.emit(format_args!("rem{} a0, a0, a1", u)),
+                    SHL | SHR | USHR => {
+                        // Shift amounts are taken modulo the width of the type.
+                        self.emit(format_args!("andi a1, a1, {}", bits - 1));
+                        match operator {
+                            SHL => self.emit(format_args!("sll a0, a0, a1")),
+                            SHR if !unsigned => self.emit(format_args!("sra a0, a0, a1")),
+                            _ => {
+                                self.zero_extend(bits);
+                                self.emit(format_args!("srl a0, a0, a1"));
+                            }
+                        }
+                    }
+                }
+                self.extend(value_type);
+            }
+            (IRValueType::Integer { .. }, IRArithmetic::Not) => {
+                self.emit(format_args!("not a0, a0"));
+                self.extend(value_type);
+            }
+            (IRValueType::Integer { .. }, IRArithmetic::Negate) => {
+                self.emit(format_args!("neg a0, a0"));
+                self.extend(value_type);
+            }
+            (IRValueType::Float | IRValueType::Double, IRArithmetic::Negate) => {
+                let suffix = float_suffix(value_type);
+                self.move_to_float(value_type, "a0", "fa0");
+                self.emit(format_args!("fneg.{} fa0, fa0", suffix));
+                self.move_from_float(value_type, "fa0", "a0");
+            }
+            (IRValueType::Float | IRValueType::Double, IRArithmetic::Binary(operator)) => {
+                let suffix = float_suffix(value_type);
+                self.move_to_float(value_type, "a0", "fa0");
+                self.move_to_float(value_type, "a1", "fa1");
+                match operator {
+                    ADD => self.emit(format_args!("fadd.{} fa0, fa0, fa1", suffix)),
+                    SUB => self.emit(format_args!("fsub.{} fa0, fa0, fa1", suffix)),
+                    MUL => self.emit(format_args!("fmul.{} fa0, fa0, fa1", suffix)),
+                    DIV => self.emit(format_args!("fdiv.{} fa0, fa0, fa1", suffix)),
+                    MOD => {
+                        let function = if value_type == IRValueType::Float {
+                            "fmodf"
+                        } else {
+                            "fmod"
+                        };
+                        self.emit(format_args!("call {}", function));
+                    }
+                    _ => {
+                        return Err(self.unsupported(format!("{} on floating point", operator)));
+                    }
+                }
+                self.move_from_float(value_type, "fa0", "a0");
+            }
+            (value_type, arithmetic) => {
+                return Err(self.unsupported(format!("{:?} on {:?}", arithmetic, value_type)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Atomically replaces the value at `address` with `value op rhs`, leaving the new value in
+    /// `a0`. Additions and bitwise operations on words and double words are a single AMO;
+    /// everything else is a compare-and-swap loop whose operation runs outside of the `lr`/`sc`
+    /// pair since it may call `fmod`.
+    fn emit_atomic_update(
+        &self,
+        arithmetic: IRArithmetic,
+        value_type: IRValueType,
+        address: &dyn IROperand,
+        rhs: Option<&dyn IROperand>,
+        one: bool,
+    ) -> IRGenerateResult<()> {
+        use IRCalculateOperator::*;
+        let [address_slot, rhs_slot, expected_slot] = self.frame.scratch[..] else {
+            unreachable!()
+        };
+        let size = value_type.size();
+        self.load_operand(address)?;
+        self.emit_slot("sd a0", address_slot);
+        if let Some(rhs) = rhs {
+            self.load_operand(rhs)?;
+            self.emit_slot("sd a0", rhs_slot);
+        } else if one {
+            self.load_one(value_type, "a1");
+            self.emit_slot("sd a1", rhs_slot);
+        }
+        let amo = match arithmetic {
+            IRArithmetic::Binary(operator @ (ADD | SUB | AND | OR | XOR))
+                if matches!(value_type, IRValueType::Integer { bits: 32 | 64, .. }) =>
+            {
+                Some(operator)
+            }
+            _ => None,
+        };
+        if let Some(operator) = amo {
+            let suffix = atomic_suffix(size);
+            self.emit_slot("ld t0", address_slot);
+            self.emit_slot("ld a1", rhs_slot);
+            let instruction = match operator {
+                ADD | SUB => "amoadd",
+                AND => "amoand",
+                OR => "amoor",
+                _ => "amoxor",
+            };
+            if operator == SUB {
+                self.emit(format_args!("neg a2, a1"));
+                self.emit(format_args!("{}.{}.aqrl a0, a2, (t0)", instruction, suffix));
+            } else {
+                self.emit(format_args!("{}.{}.aqrl a0, a1, (t0)", instruction, suffix));
+            }
+            return self.compute(arithmetic, value_type);
+        }
+        self.emit_slot("ld t0", address_slot);
+        self.load(value_type, "0(t0)");
+        self.emit_label("1");
+        self.extend(value_type);
+        self.emit_slot("sd a0", expected_slot);
+        self.emit_slot("ld a1", rhs_slot);
+        self.compute(arithmetic, value_type)?;
+        self.emit(format_args!("mv a2, a0"));
+        self.emit_slot("ld a3", expected_slot);
+        self.emit_slot("ld a4", address_slot);
+        if size >= 4 {
+            let suffix = atomic_suffix(size);
+            if size == 4 {
+                // `lr.w` sign-extends, so the expected value is compared the same way.
+                self.emit(format_args!("sext.w a3, a3"));
+            }
+            self.emit_label("2");
+            self.emit(format_args!("lr.{}.aqrl a5, (a4)", suffix));
+            self.emit(format_args!("bne a5, a3, 3f"));
+            self.emit(format_args!("sc.{}.rl a6, a2, (a4)", suffix));
+            self.emit(format_args!("bnez a6, 2b"));
+            self.emit(format_args!("j 4f"));
+            self.emit_label("3");
+            self.emit(format_args!("mv a0, a5"));
+            self.emit(format_args!("j 1b"));
+        } else {
+            // Bytes and halves are swapped within their aligned word.
+            let width = IRValueType::Integer {
+                bits: size as u32 * 8,
+                unsigned: true,
+            };
+            self.emit(format_args!("andi a6, a4, 3"));
+            self.emit(format_args!("slli a6, a6, 3"));
+            self.emit(format_args!("andi a4, a4, -4"));
+            self.emit(format_args!("li a7, {}", (1u32 << (size * 8)) - 1));
+            self.emit(format_args!("sll a7, a7, a6"));
+            self.extend_register(width, "a3");
+            self.emit(format_args!("sll a3, a3, a6"));
+            self.emit(format_args!("mv t1, a2"));
+            self.extend_register(width, "t1");
+            self.emit(format_args!("sll t1, t1, a6"));
+            self.emit_label("2");
+            self.emit(format_args!("lr.w.aqrl a5, (a4)"));
+            self.emit(format_args!("and t2, a5, a7"));
+            self.emit(format_args!("bne t2, a3, 3f"));
+            self.emit(format_args!("xor t3, a5, t2"));
+            self.emit(format_args!("or t3, t3, t1"));
+            self.emit(format_args!("sc.w.rl t4, t3, (a4)"));
+            self.emit(format_args!("bnez t4, 2b"));
+            self.emit(format_args!("j 4f"));
+            self.emit_label("3");
+            self.emit(format_args!("srl a0, t2, a6"));
+            self.emit(format_args!("j 1b"));
+        }
+        self.emit_label("4");
+        self.emit(format_args!("mv a0, a2"));
+        Ok(())
+    }
+
+    fn load_one(&self, value_type: IRValueType, register: &str) {
+        match value_type {
+            IRValueType::Float => self.emit(format_args!("li {}, {:#x}", register, 1f32.to_bits())),
+            IRValueType::Double => {
+                self.emit(format_args!("li {}, {:#x}", register, 1f64.to_bits()))
+            }
+            _ => self.emit(format_args!("li {}, 1", register)),
+        }
+    }
+
+    fn emit_unary(
+        &self,
+        arithmetic: IRArithmetic,
+        is_atomic: bool,
+        value_type: IRValueType,
+        operand: &dyn IROperand,
+        target: &IRVirtualRegister,
+    ) -> IRGenerateResult<()> {
+        if is_atomic {
+            self.emit_atomic_update(arithmetic, value_type, operand, None, false)?;
+        } else {
+            self.load_operand(operand)?;
+            self.compute(arithmetic, value_type)?;
+        }
+        self.store_register(target)
+    }
+
+    fn emit_step(
+        &self,
+        operator: IRCalculateOperator,
+        value_type: IRValueType,
+        operand: &dyn IROperand,
+        target: Option<&IRVirtualRegister>,
+    ) -> IRGenerateResult<()> {
+        let arithmetic = IRArithmetic::Binary(operator);
+        match (target, value_type) {
+            (Some(target), _) => {
+                self.load_operand(operand)?;
+                self.load_one(value_type, "a1");
+                self.compute(arithmetic, value_type)?;
+                self.store_register(target)
+            }
+            (None, IRValueType::Integer { bits: 32 | 64, .. }) => {
+                let step = if operator == IRCalculateOperator::ADD {
+                    1
+                } else {
+                    -1
+                };
+                self.load_operand(operand)?;
+                self.emit(format_args!("li a1, {}", step));
+                self.emit(format_args!(
+                    "amoadd.{}.aqrl zero, a1, (a0)",
+                    atomic_suffix(value_type.size())
+                ));
+                Ok(())
+            }
+            (None, _) => self.emit_atomic_update(arithmetic, value_type, operand, None, true),
+        }
+    }
+
+    /// Branches to `label` if `a0 cond a1` holds. Conditional branches only reach 4 KiB, so they
+    /// skip over a `j` to the label instead.
+    fn emit_branch(&self, condition: IRCondition, value_type: IRValueType, label: &str) {
+        let skip = |branch: fmt::Arguments| {
+            self.emit(branch);
+            self.emit(format_args!("j {}", label));
+            self.emit_label("5");
+        };
+        if value_type.is_floating_point() {
+            let suffix = float_suffix(value_type);
+            self.move_to_float(value_type, "a0", "fa0");
+            match condition {
+                IRCondition::IfTrue | IRCondition::IfFalse => {
+                    self.move_to_float(value_type, "zero", "fa1")
+                }
+                _ => self.move_to_float(value_type, "a1", "fa1"),
+            }
+            // Every comparison is false when either side is NaN, which makes `ne` true.
+            let (comparison, left, right, negated) = match condition {
+                IRCondition::Less => ("flt", "fa0", "fa1", false),
+                IRCondition::LessEqual => ("fle", "fa0", "fa1", false),
+                IRCondition::Greater => ("flt", "fa1", "fa0", false),
+                IRCondition::GreaterEqual => ("fle", "fa1", "fa0", false),
+                IRCondition::Equal | IRCondition::IfFalse => ("feq", "fa0", "fa1", false),
+                IRCondition::NotEqual | IRCondition::IfTrue => ("feq", "fa0", "fa1", true),
+            };
+            self.emit(format_args!(
+                "{}.{} t0, {}, {}",
+                comparison, suffix, left, right
+            ));
+            if negated {
+                skip(format_args!("bnez t0, 5f"));
+            } else {
+                skip(format_args!("beqz t0, 5f"));
+            }
+            return;
+        }
+        let u = if value_type.is_unsigned() { "u" } else { "" };
+        // The branch taken when the condition does not hold.
+        match condition {
+            IRCondition::IfTrue => skip(format_args!("beqz a0, 5f")),
+            IRCondition::IfFalse => skip(format_args!("bnez a0, 5f")),
+            IRCondition::Equal => skip(format_args!("bne a0, a1, 5f")),
+            IRCondition::NotEqual => skip(format_args!("beq a0, a1, 5f")),
+            IRCondition::Less => skip(format_args!("bge{} a0, a1, 5f", u)),
+            IRCondition::LessEqual => skip(format_args!("blt{} a1, a0, 5f", u)),
+            IRCondition::Greater => skip(format_args!("bge{} a1, a0, 5f", u)),
+            IRCondition::GreaterEqual => skip(format_args!("blt{} a0, a1, 5f", u)),
+        }
+    }
+
+    fn emit_conditional_jump(
+        &self,
+        ir_conditional_jump: &IRConditionalJump,
+    ) -> IRGenerateResult<()> {
+        let value_type = IRValueType::of(ir_conditional_jump._type.as_ref());
+        if let Some(operand2) = ir_conditional_jump.operand2.as_ref() {
+            self.load_operand(operand2.as_ref())?;
+            self.emit(format_args!("mv a1, a0"));
+        }
+        self.load_operand(ir_conditional_jump.operand1.as_ref())?;
+        if ir_conditional_jump.is_atomic {
+            self.emit(format_args!("mv t0, a0"));
+            self.emit(format_args!("fence rw, rw"));
+            self.load(value_type, "0(t0)");
+            self.emit(format_args!("fence r, rw"));
+        }
+        let target = &ir_conditional_jump.target;
+        if !self.ir_control_flow_graph.basic_blocks.contains_key(target) {
+            return Err(self.unsupported(format!("jump to missing block '{}'", target)));
+        }
+        if phi_moves(self.ir_control_flow_graph, &self.current_block(), target).is_empty() {
+            self.emit_branch(
+                ir_conditional_jump.condition,
+                value_type,
+                &self.block_label(target),
+            );
+            return Ok(());
+        }
+        let taken = self.fresh_label();
+        let skipped = self.fresh_label();
+        self.emit_branch(ir_conditional_jump.condition, value_type, &taken);
+        self.emit(format_args!("j {}", skipped));
+        self.emit_label(&taken);
+        self.emit_jump(target)?;
+        self.emit_label(&skipped);
+        Ok(())
+    }
+
+    fn emit_invoke(&self, ir_invoke: &IRInvoke) -> IRGenerateResult<()> {
+        if ir_invoke.argument_types.len() != ir_invoke.arguments.len() {
+            return Err(self.unsupported("invoke with mismatched argument types".to_string()));
+        }
+        let mut registers = vec![];
+        let mut stack = vec![];
+        let mut floats = 0;
+        let mut integers = 0;
+        for (argument_type, argument) in ir_invoke
+            .argument_types
+            .iter()
+            .zip(ir_invoke.arguments.iter())
+        {
+            let value_type = IRValueType::of(argument_type.as_ref());
+            if value_type.is_floating_point() && floats < ARGUMENT_REGISTERS {
+                registers.push((value_type, format!("fa{}", floats), argument));
+                floats += 1;
+            } else if integers < ARGUMENT_REGISTERS {
+                registers.push((value_type, format!("a{}", integers), argument));
+                integers += 1;
+            } else {
+                stack.push((value_type, argument));
+            }
+        }
+        let callee = match IROperandValue::of(ir_invoke.address.as_ref()) {
+            IROperandValue::FunctionAddress(name) => Some(mangle_symbol(&name)),
+            _ => {
+                self.load_operand(ir_invoke.address.as_ref())?;
+                self.emit(format_args!("mv t1, a0"));
+                None
+            }
+        };
+        let stack_size = (stack.len() as i64 * 8 + 15) / 16 * 16;
+        if stack_size > 0 {
+            self.emit_adjust_stack(-stack_size);
+        }
+        for (index, (value_type, argument)) in stack.iter().enumerate() {
+            self.load_operand(argument.as_ref())?;
+            self.widen_argument(*value_type);
+            self.emit(format_args!("sd a0, {}(sp)", index * 8));
+        }
+        // Loading goes through `a0`, so `a0` itself is filled last.
+        for (value_type, register, argument) in registers.iter().rev() {
+            self.load_operand(argument.as_ref())?;
+            if register.starts_with('f') {
+                self.move_to_float(*value_type, "a0", register);
+            } else {
+                self.widen_argument(*value_type);
+                if register != "a0" {
+                    self.emit(format_args!("mv {}, a0", register));
+                }
+            }
+        }
+        match callee {
+            Some(symbol) => self.emit(format_args!("call {}", symbol)),
+            None => self.emit(format_args!("jalr t1")),
+        }
+        if stack_size > 0 {
+            self.emit_adjust_stack(stack_size);
+        }
+        if let Some(target) = ir_invoke.target.as_ref() {
+            let return_type = IRValueType::of(ir_invoke.return_type.as_ref());
+            if return_type.is_floating_point() {
+                self.move_from_float(return_type, "fa0", "a0");
+            }
+            self.store_register(target)?;
+        }
+        Ok(())
+    }
+
+    /// LP64 passes 32-bit integers sign-extended to 64 bits whatever their signedness.
+    fn widen_argument(&self, value_type: IRValueType) {
+        if let IRValueType::Integer {
+            bits: 32,
+            unsigned: true,
+        } = value_type
+        {
+            self.emit(format_args!("sext.w a0, a0"));
+        }
+    }
+
+    fn emit_type_cast(&self, ir_type_cast: &IRTypeCast) -> IRGenerateResult<()> {
+        let from = IRValueType::of(ir_type_cast.original_type.as_ref());
+        let to = IRValueType::of(ir_type_cast.target_type.as_ref());
+        self.load_operand(ir_type_cast.source.as_ref())?;
+        self.extend(from);
+        let bits = match from {
+            IRValueType::Integer { bits, .. } => bits,
+            _ => 64,
+        };
+        match ir_type_cast.kind {
+            IRTypeCastKind::ZeroExtend => self.zero_extend(bits),
+            IRTypeCastKind::SignExtend => self.sign_extend(bits),
+            IRTypeCastKind::Truncate => {}
+            IRTypeCastKind::IntToFloat => {
+                let u = if from.is_unsigned() { "u" } else { "" };
+                self.emit(format_args!("fcvt.{}.l{} fa0, a0", float_suffix(to), u));
+                self.move_from_float(to, "fa0", "a0");
+            }
+            IRTypeCastKind::FloatToInt => {
+                self.move_to_float(from, "a0", "fa0");
+                self.emit(format_args!("fcvt.l.{} a0, fa0, rtz", float_suffix(from)));
+            }
+            IRTypeCastKind::FloatExtend | IRTypeCastKind::FloatTruncate => {
+                if from != to {
+                    self.move_to_float(from, "a0", "fa0");
+                    self.emit(format_args!(
+                        "fcvt.{}.{} fa0, fa0",
+                        float_suffix(to),
+                        float_suffix(from)
+                    ));
+                    self.move_from_float(to, "fa0", "a0");
+                }
+            }
+        }
+        self.store_register(&ir_type_cast.target)
+    }
+
+    fn emit_asm(&self, ir_asm: &IRAsm) -> IRGenerateResult<()> {
+        let mut bindings = vec![];
+        for (resource, name) in ir_asm.resources.iter().zip(ir_asm.names.iter()) {
+            let register = asm_register(name)
+                .ok_or_else(|| self.unsupported(format!("asm register '{}'", name)))?;
+            bindings.push((register, resource));
+        }
+        // a0 is the scratch register for loading, so it is bound last.
+        bindings.sort_by_key(|(register, _)| register == "a0");
+        let saved = bindings
+            .iter()
+            .map(|(register, _)| register.clone())
+            .filter(|register| is_callee_saved(register))
+            .collect::<Vec<_>>();
+        for register in saved.iter() {
+            self.emit(format_args!("addi sp, sp, -16"));
+            self.emit(format_args!("sd {}, 0(sp)", register));
+        }
+        for (register, resource) in bindings.iter() {
+            self.load_operand(resource.as_ref())?;
+            if register != "a0" {
+                self.emit(format_args!("mv {}, a0", register));
+            }
+        }
+        // Front ends write multi-line code with `\n` escapes as often as with real newlines.
+        for line in ir_asm.code.replace("\\n", "\n").lines() {
+            self.emit(format_args!("{}", line.trim()));
+        }
+        for register in saved.iter().rev() {
+            self.emit(format_args!("ld {}, 0(sp)", register));
+            self.emit(format_args!("addi sp, sp, 16"));
+        }
+        Ok(())
+    }
+}
+
+impl IRVisitor for IRRISCVEmitter<'_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_goto(&self, ir_goto: &IRGoto) {
+        self.terminated.set(true);
+        self.finish(self.emit_jump(&ir_goto.target));
+    }
+    fn visit_conditional_jump(&self, ir_conditional_jump: &IRConditionalJump) {
+        self.finish(self.emit_conditional_jump(ir_conditional_jump));
+    }
+    fn visit_return(&self, ir_return: &IRReturn) {
+        self.terminated.set(true);
+        if let Some(operand) = ir_return.operand.as_ref() {
+            self.finish(self.load_operand(operand.as_ref()));
+            let return_type = self.ir_function.map_or(IRValueType::Void, |ir_function| {
+                IRValueType::of(ir_function.return_type.as_ref())
+            });
+            if return_type.is_floating_point() {
+                self.move_to_float(return_type, "a0", "fa0");
+            } else {
+                self.widen_argument(return_type);
+            }
+        }
+        self.emit_epilogue();
+    }
+    fn visit_calculate(&self, ir_calculate: &IRCalculate) {
+        let value_type = IRValueType::of(ir_calculate._type.as_ref());
+        let arithmetic = IRArithmetic::Binary(ir_calculate.operator);
+        let result = (|| {
+            if ir_calculate.is_atomic {
+                self.emit_atomic_update(
+                    arithmetic,
+                    value_type,
+                    ir_calculate.operand1.as_ref(),
+                    Some(ir_calculate.operand2.as_ref()),
+                    false,
+                )?;
+            } else {
+                self.load_pair(
+                    ir_calculate.operand1.as_ref(),
+                    ir_calculate.operand2.as_ref(),
+                )?;
+                self.compute(arithmetic, value_type)?;
+            }
+            self.store_register(&ir_calculate.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_not(&self, ir_not: &IRNot) {
+        self.finish(self.emit_unary(
+            IRArithmetic::Not,
+            ir_not.is_atomic,
+            IRValueType::of(ir_not._type.as_ref()),
+            ir_not.operand.as_ref(),
+            &ir_not.target,
+        ));
+    }
+    fn visit_negate(&self, ir_negate: &IRNegate) {
+        self.finish(self.emit_unary(
+            IRArithmetic::Negate,
+            ir_negate.is_atomic,
+            IRValueType::of(ir_negate._type.as_ref()),
+            ir_negate.operand.as_ref(),
+            &ir_negate.target,
+        ));
+    }
+    fn visit_malloc(&self, ir_malloc: &IRMalloc) {
+        let result = (|| {
+            self.load_operand(ir_malloc.size.as_ref())?;
+            self.emit(format_args!("call malloc"));
+            self.store_register(&ir_malloc.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_free(&self, ir_free: &IRFree) {
+        let result = self.load_operand(ir_free.ptr.as_ref());
+        self.emit(format_args!("call free"));
+        self.finish(result);
+    }
+    fn visit_realloc(&self, ir_realloc: &IRRealloc) {
+        let result = (|| {
+            self.load_pair(ir_realloc.ptr.as_ref(), ir_realloc.size.as_ref())?;
+            self.emit(format_args!("call realloc"));
+            self.store_register(&ir_realloc.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_get(&self, ir_get: &IRGet) {
+        let result = (|| {
+            self.load_operand(ir_get.address.as_ref())?;
+            self.emit(format_args!("mv t0, a0"));
+            self.load(IRValueType::of(ir_get._type.as_ref()), "0(t0)");
+            self.store_register(&ir_get.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_set(&self, ir_set: &IRSet) {
+        let result = (|| {
+            self.load_operand(ir_set.address.as_ref())?;
+            self.emit(format_args!("mv t0, a0"));
+            self.load_operand(ir_set.value.as_ref())?;
+            self.store(IRValueType::of(ir_set._type.as_ref()), "0(t0)");
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_set_virtual_register(&self, ir_set_virtual_register: &IRSetVirtualRegister) {
+        let target = &ir_set_virtual_register.target;
+        let result = (|| {
+            match IROperandValue::of(ir_set_virtual_register.source.as_ref()) {
+                IROperandValue::Phi(ir_phi) => {
+                    let memory = self.frame_slot(self.frame.phi_shadows[&target.name]);
+                    self.load(IRValueType::of(ir_phi._type.as_ref()), &memory)
+                }
+                _ => self.load_operand(ir_set_virtual_register.source.as_ref())?,
+            }
+            self.store_register(target)
+        })();
+        self.finish(result);
+    }
+    fn visit_invoke(&self, ir_invoke: &IRInvoke) {
+        self.finish(self.emit_invoke(ir_invoke));
+    }
+    fn visit_no_operate(&self, _ir_no_operate: &IRNoOperate) {
+        self.emit(format_args!("nop"));
+    }
+    fn visit_increase(&self, ir_increase: &IRIncrease) {
+        self.finish(self.emit_step(
+            IRCalculateOperator::ADD,
+            IRValueType::of(ir_increase._type.as_ref()),
+            ir_increase.operand.as_ref(),
+            ir_increase.target.as_deref(),
+        ));
+    }
+    fn visit_decrease(&self, ir_decrease: &IRDecrease) {
+        self.finish(self.emit_step(
+            IRCalculateOperator::SUB,
+            IRValueType::of(ir_decrease._type.as_ref()),
+            ir_decrease.operand.as_ref(),
+            ir_decrease.target.as_deref(),
+        ));
+    }
+    fn visit_stack_allocate(&self, ir_stack_allocate: &IRStackAllocate) {
+        let result = (|| {
+            self.load_operand(ir_stack_allocate.size.as_ref())?;
+            self.emit(format_args!("addi a0, a0, 15"));
+            self.emit(format_args!("andi a0, a0, -16"));
+            self.emit(format_args!("sub sp, sp, a0"));
+            self.emit(format_args!("mv a0, sp"));
+            self.store_register(&ir_stack_allocate.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_type_cast(&self, ir_type_cast: &IRTypeCast) {
+        self.finish(self.emit_type_cast(ir_type_cast));
+    }
+    fn visit_asm(&self, ir_asm: &IRAsm) {
+        self.finish(self.emit_asm(ir_asm));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parser::parse_module;
+
+    const LOOP: &str = "\
+constant $0 = i64 0
+constant $1 = i64 1
+function i64 sum(i64 n) {
+entry:
+    %pn = `field_address([n], [])
+    %n = get i64, %pn
+    goto loop
+loop:
+    %i = phi i64 [entry, $0], [loop, %next_i]
+    %total = phi i64 [entry, $0], [loop, %next_total]
+    %next_total = add i64 %total, %i
+    %next_i = add i64 %i, $1
+    conditional_jump i64 le, %next_i, %n, #loop
+done:
+    %r = invoke i64 `function_address([twice], []), [i64, %next_total]
+    return %r
+}
+function i64 twice(i64 x) {
+entry:
+    %px = `field_address([x], [])
+    %x = get i64, %px
+    %r = add i64 %x, %x
+    return %r
+}
+";
+
+    #[test]
+    fn phis_are_copied_on_each_edge_and_calls_use_call() {
+        assert_eq!(
+            generate_assembly(&parse_module(LOOP).unwrap()).unwrap(),
+            "\
+\t.section .rodata
+\t.balign 8
+.LC0:
+\t.quad 0
+\t.balign 8
+.LC1:
+\t.quad 1
+\t.section .text.sum,\"ax\",@progbits
+\t.globl sum
+\t.type sum, @function
+sum:
+\taddi sp, sp, -16
+\tsd ra, 8(sp)
+\tsd s0, 0(sp)
+\taddi s0, sp, 16
+\taddi sp, sp, -112
+\tsd a0, -24(s0)
+.Lsum.entry:
+\taddi a0, s0, -24
+\tsd a0, -32(s0)
+\tld a0, -32(s0)
+\tmv t0, a0
+\tld a0, 0(t0)
+\tsd a0, -40(s0)
+\tlla t5, .LC0
+\tld a0, 0(t5)
+\tsd a0, -88(s0)
+\tlla t5, .LC0
+\tld a0, 0(t5)
+\tsd a0, -96(s0)
+\tj .Lsum.loop
+.Lsum.loop:
+\tld a0, -88(s0)
+\tsd a0, -48(s0)
+\tld a0, -96(s0)
+\tsd a0, -56(s0)
+\tld a0, -48(s0)
+\tmv a1, a0
+\tld a0, -56(s0)
+\tadd a0, a0, a1
+\tsd a0, -64(s0)
+\tlla t5, .LC1
+\tld a0, 0(t5)
+\tmv a1, a0
+\tld a0, -48(s0)
+\tadd a0, a0, a1
+\tsd a0, -72(s0)
+\tld a0, -40(s0)
+\tmv a1, a0
+\tld a0, -72(s0)
+\tblt a1, a0, 5f
+\tj .Lsum..1
+5:
+\tj .Lsum..2
+.Lsum..1:
+\tld a0, -72(s0)
+\tsd a0, -88(s0)
+\tld a0, -64(s0)
+\tsd a0, -96(s0)
+\tj .Lsum.loop
+.Lsum..2:
+.Lsum.done:
+\tld a0, -64(s0)
+\tcall twice
+\tsd a0, -80(s0)
+\tld a0, -80(s0)
+\taddi sp, s0, -16
+\tld ra, 8(sp)
+\tld s0, 0(sp)
+\taddi sp, sp, 16
+\tret
+\t.size sum, .-sum
+\t.section .text.twice,\"ax\",@progbits
+\t.globl twice
+\t.type twice, @function
+twice:
+\taddi sp, sp, -16
+\tsd ra, 8(sp)
+\tsd s0, 0(sp)
+\taddi s0, sp, 16
+\taddi sp, sp, -64
+\tsd a0, -24(s0)
+.Ltwice.entry:
+\taddi a0, s0, -24
+\tsd a0, -32(s0)
+\tld a0, -32(s0)
+\tmv t0, a0
+\tld a0, 0(t0)
+\tsd a0, -40(s0)
+\tld a0, -40(s0)
+\tmv a1, a0
+\tld a0, -40(s0)
+\tadd a0, a0, a1
+\tsd a0, -48(s0)
+\tld a0, -48(s0)
+\taddi sp, s0, -16
+\tld ra, 8(sp)
+\tld s0, 0(sp)
+\taddi sp, sp, 16
+\tret
+\t.size twice, .-twice
+\t.section .note.GNU-stack,\"\",@progbits
+"
+        );
+    }
+}
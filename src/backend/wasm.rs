@@ -0,0 +1,2922 @@
+//! WebAssembly modules, as a `.wasm` binary or as its text format.
+//!
+//! Addresses are 64-bit values like on the native targets, so that global data keeps its layout,
+//! and are wrapped to 32 bits for every memory access. Linear memory holds, in order: a null page
+//! guard, string constants and global data, a shadow stack for fields and `stack_alloc`, and a
+//! heap managed by a first-fit free-list allocator that grows memory on demand. Function
+//! addresses are indices into a table holding every function, slot 0 being null.
+//!
+//! Each basic block is split at its conditional jumps, and the resulting graph is turned into
+//! nested `block`/`loop`/`if` following Ramsey's "Beyond Relooper" when it is reducible; an
+//! irreducible graph falls back to a `br_table` dispatch loop. Memory is not shared, so atomic
+//! instructions are plain loads and stores.
+
+use crate::backend::{
+    IRDataItem, IRGenerateError, IRGenerateResult, IROperandValue, IRTarget, IRValueType,
+    check_scalar_values, constant, global_data_layout, lower_for_target, phi_moves,
+};
+use crate::ir::base::{IRCondition, IRControlFlowGraph, IRFunction, IRNode};
+use crate::ir::instruction::{
+    IRAsm, IRCalculate, IRCalculateOperator, IRConditionalJump, IRDecrease, IRFree, IRGet, IRGoto,
+    IRIncrease, IRInvoke, IRMalloc, IRNegate, IRNoOperate, IRNot, IRRealloc, IRReturn, IRSet,
+    IRSetVirtualRegister, IRStackAllocate, IRTypeCast, IRTypeCastKind,
+};
+use crate::ir::operand::{IROperand, IRVirtualRegister};
+use crate::ir::type_check::{IRFunctionTypes, infer_register_types, operand_type};
+use crate::ir::verify::IRLocation;
+use crate::ir::{IRConstantData, IRModule, IRVisitor};
+use indexmap::IndexMap;
+use std::cell::{RefCell, RefMut};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const PAGE_SIZE: u32 = 65536;
+const DATA_START: u32 = 16;
+const STACK_SIZE: u32 = 1 << 20;
+const STACK_POINTER: u32 = 0;
+const HEAP_TOP: u32 = 1;
+const FREE_LIST: u32 = 2;
+/// Bytes in front of every heap block: its capacity and, while free, the next free block.
+const BLOCK_HEADER: i32 = 8;
+const SCRATCH_LOCALS: usize = 2;
+
+pub fn generate_binary(ir_module: &IRModule) -> IRGenerateResult<Vec<u8>> {
+    Ok(IRWasmModule::build(ir_module)?.encode())
+}
+
+pub fn generate_text(ir_module: &IRModule) -> IRGenerateResult<String> {
+    Ok(IRWasmModule::build(ir_module)?.text())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum IRWasmType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl IRWasmType {
+    fn of(value_type: IRValueType) -> Self {
+        match value_type {
+            IRValueType::Integer { bits, .. } if bits <= 32 => IRWasmType::I32,
+            IRValueType::Float => IRWasmType::F32,
+            IRValueType::Double => IRWasmType::F64,
+            _ => IRWasmType::I64,
+        }
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            IRWasmType::I32 => 0x7F,
+            IRWasmType::I64 => 0x7E,
+            IRWasmType::F32 => 0x7D,
+            IRWasmType::F64 => 0x7C,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            IRWasmType::I32 => "i32",
+            IRWasmType::I64 => "i64",
+            IRWasmType::F32 => "f32",
+            IRWasmType::F64 => "f64",
+        }
+    }
+}
+
+/// How a value of some type is held in a local: registers of void type hold addresses.
+fn value_type(_type: &dyn crate::ir::types::IRType) -> IRValueType {
+    match IRValueType::of(_type) {
+        IRValueType::Void => IRValueType::ADDRESS,
+        value_type => value_type,
+    }
+}
+
+fn write_unsigned(output: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            output.push(byte);
+            return;
+        }
+        output.push(byte | 0x80);
+    }
+}
+
+fn write_signed(output: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            output.push(byte);
+            return;
+        }
+        output.push(byte | 0x80);
+    }
+}
+
+fn write_name(output: &mut Vec<u8>, name: &str) {
+    write_unsigned(output, name.len() as u64);
+    output.extend_from_slice(name.as_bytes());
+}
+
+/// A string in the text format, with every byte that is not printable ASCII escaped.
+fn quote(bytes: &[u8]) -> String {
+    let mut quoted = String::from("\"");
+    for &byte in bytes {
+        if (byte.is_ascii_graphic() || byte == b' ') && byte != b'"' && byte != b'\\' {
+            quoted.push(byte as char);
+        } else {
+            let _ = write!(quoted, "\\{:02x}", byte);
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn float_text(value: f64, bits: u64, mantissa_bits: u32) -> String {
+    if value.is_nan() {
+        let sign = if bits >> (mantissa_bits + if mantissa_bits == 52 { 11 } else { 8 }) & 1 == 1 {
+            "-"
+        } else {
+            ""
+        };
+        format!("{}nan:0x{:x}", sign, bits & ((1 << mantissa_bits) - 1))
+    } else if value.is_infinite() {
+        (if value > 0.0 { "inf" } else { "-inf" }).to_string()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+/// Instructions of one function body, kept in the binary encoding and in the text format at the
+/// same time.
+#[derive(Default)]
+struct IRWasmCode {
+    bytes: Vec<u8>,
+    text: String,
+    depth: usize,
+}
+
+impl IRWasmCode {
+    fn instruction(&mut self, opcode: &[u8], name: &str, immediates: &[u8], text: &str) {
+        self.bytes.extend_from_slice(opcode);
+        self.bytes.extend_from_slice(immediates);
+        let _ = write!(self.text, "\n    {}{}", "  ".repeat(self.depth), name);
+        if !text.is_empty() {
+            let _ = write!(self.text, " {}", text);
+        }
+    }
+
+    fn op(&mut self, opcode: u8, name: &str) {
+        self.instruction(&[opcode], name, &[], "");
+    }
+
+    fn with_index(&mut self, opcode: u8, name: &str, index: u32) {
+        let mut immediates = vec![];
+        write_unsigned(&mut immediates, index as u64);
+        self.instruction(&[opcode], name, &immediates, &index.to_string());
+    }
+
+    /// Opens a `block`, `loop` or `if` without parameters or results.
+    fn open(&mut self, opcode: u8, name: &str) {
+        self.instruction(&[opcode, 0x40], name, &[], "");
+        self.depth += 1;
+    }
+
+    fn otherwise(&mut self) {
+        self.depth -= 1;
+        self.op(0x05, "else");
+        self.depth += 1;
+    }
+
+    fn end(&mut self) {
+        self.depth -= 1;
+        self.op(0x0B, "end");
+    }
+
+    fn i32_const(&mut self, value: i32) {
+        let mut immediates = vec![];
+        write_signed(&mut immediates, value as i64);
+        self.instruction(&[0x41], "i32.const", &immediates, &value.to_string());
+    }
+
+    fn i64_const(&mut self, value: i64) {
+        let mut immediates = vec![];
+        write_signed(&mut immediates, value);
+        self.instruction(&[0x42], "i64.const", &immediates, &value.to_string());
+    }
+
+    fn f32_const(&mut self, value: f32) {
+        let text = float_text(value as f64, value.to_bits() as u64, 23);
+        self.instruction(&[0x43], "f32.const", &value.to_bits().to_le_bytes(), &text);
+    }
+
+    fn f64_const(&mut self, value: f64) {
+        let text = float_text(value, value.to_bits(), 52);
+        self.instruction(&[0x44], "f64.const", &value.to_bits().to_le_bytes(), &text);
+    }
+
+    fn local_get(&mut self, index: u32) {
+        self.with_index(0x20, "local.get", index);
+    }
+
+    fn local_set(&mut self, index: u32) {
+        self.with_index(0x21, "local.set", index);
+    }
+
+    fn local_tee(&mut self, index: u32) {
+        self.with_index(0x22, "local.tee", index);
+    }
+
+    fn global_get(&mut self, index: u32) {
+        self.with_index(0x23, "global.get", index);
+    }
+
+    fn global_set(&mut self, index: u32) {
+        self.with_index(0x24, "global.set", index);
+    }
+
+    fn br(&mut self, depth: usize) {
+        self.with_index(0x0C, "br", depth as u32);
+    }
+
+    fn br_if(&mut self, depth: usize) {
+        self.with_index(0x0D, "br_if", depth as u32);
+    }
+
+    fn br_table(&mut self, targets: &[u32], default: u32) {
+        let mut immediates = vec![];
+        write_unsigned(&mut immediates, targets.len() as u64);
+        for target in targets {
+            write_unsigned(&mut immediates, *target as u64);
+        }
+        write_unsigned(&mut immediates, default as u64);
+        let text = targets
+            .iter()
+            .chain(std::iter::once(&default))
+            .map(|target| target.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.instruction(&[0x0E], "br_table", &immediates, &text);
+    }
+
+    fn call(&mut self, function: u32) {
+        self.with_index(0x10, "call", function);
+    }
+
+    fn call_indirect(&mut self, type_index: u32) {
+        let mut immediates = vec![];
+        write_unsigned(&mut immediates, type_index as u64);
+        immediates.push(0);
+        self.instruction(
+            &[0x11],
+            "call_indirect",
+            &immediates,
+            &format!("(type {})", type_index),
+        );
+    }
+
+    fn memory(&mut self, opcode: u8, name: &str, align: u32, offset: u32) {
+        let mut immediates = vec![];
+        write_unsigned(&mut immediates, align as u64);
+        write_unsigned(&mut immediates, offset as u64);
+        let text = if offset == 0 {
+            String::new()
+        } else {
+            format!("offset={}", offset)
+        };
+        self.instruction(&[opcode], name, &immediates, &text);
+    }
+
+    fn load(&mut self, value_type: IRValueType, offset: u32) {
+        let (opcode, name, align) = match value_type {
+            IRValueType::Integer { bits: 1, .. }
+            | IRValueType::Integer {
+                bits: 8,
+                unsigned: true,
+            } => (0x2D, "i32.load8_u", 0),
+            IRValueType::Integer { bits: 8, .. } => (0x2C, "i32.load8_s", 0),
+            IRValueType::Integer {
+                bits: 16,
+                unsigned: true,
+            } => (0x2F, "i32.load16_u", 1),
+            IRValueType::Integer { bits: 16, .. } => (0x2E, "i32.load16_s", 1),
+            IRValueType::Integer { bits: 32, .. } => (0x28, "i32.load", 2),
+            IRValueType::Float => (0x2A, "f32.load", 2),
+            IRValueType::Double => (0x2B, "f64.load", 3),
+            _ => (0x29, "i64.load", 3),
+        };
+        self.memory(opcode, name, align, offset);
+    }
+
+    fn store(&mut self, value_type: IRValueType, offset: u32) {
+        let (opcode, name, align) = match value_type {
+            IRValueType::Integer { bits: 1 | 8, .. } => (0x3A, "i32.store8", 0),
+            IRValueType::Integer { bits: 16, .. } => (0x3B, "i32.store16", 1),
+            IRValueType::Integer { bits: 32, .. } => (0x36, "i32.store", 2),
+            IRValueType::Float => (0x38, "f32.store", 2),
+            IRValueType::Double => (0x39, "f64.store", 3),
+            _ => (0x37, "i64.store", 3),
+        };
+        self.memory(opcode, name, align, offset);
+    }
+
+    /// A numeric instruction by its name in the text format.
+    fn numeric(&mut self, name: &str) {
+        let opcode: &[u8] = match name {
+            "i32.eqz" => &[0x45],
+            "i32.eq" => &[0x46],
+            "i32.ne" => &[0x47],
+            "i32.lt_s" => &[0x48],
+            "i32.lt_u" => &[0x49],
+            "i32.gt_s" => &[0x4A],
+            "i32.gt_u" => &[0x4B],
+            "i32.le_s" => &[0x4C],
+            "i32.le_u" => &[0x4D],
+            "i32.ge_s" => &[0x4E],
+            "i32.ge_u" => &[0x4F],
+            "i64.eqz" => &[0x50],
+            "i64.eq" => &[0x51],
+            "i64.ne" => &[0x52],
+            "i64.lt_s" => &[0x53],
+            "i64.lt_u" => &[0x54],
+            "i64.gt_s" => &[0x55],
+            "i64.gt_u" => &[0x56],
+            "i64.le_s" => &[0x57],
+            "i64.le_u" => &[0x58],
+            "i64.ge_s" => &[0x59],
+            "i64.ge_u" => &[0x5A],
+            "f32.eq" => &[0x5B],
+            "f32.ne" => &[0x5C],
+            "f32.lt" => &[0x5D],
+            "f32.gt" => &[0x5E],
+            "f32.le" => &[0x5F],
+            "f32.ge" => &[0x60],
+            "f64.eq" => &[0x61],
+            "f64.ne" => &[0x62],
+            "f64.lt" => &[0x63],
+            "f64.gt" => &[0x64],
+            "f64.le" => &[0x65],
+            "f64.ge" => &[0x66],
+            "i32.add" => &[0x6A],
+            "i32.sub" => &[0x6B],
+            "i32.mul" => &[0x6C],
+            "i32.div_s" => &[0x6D],
+            "i32.div_u" => &[0x6E],
+            "i32.rem_s" => &[0x6F],
+            "i32.rem_u" => &[0x70],
+            "i32.and" => &[0x71],
+            "i32.or" => &[0x72],
+            "i32.xor" => &[0x73],
+            "i32.shl" => &[0x74],
+            "i32.shr_s" => &[0x75],
+            "i32.shr_u" => &[0x76],
+            "i64.add" => &[0x7C],
+            "i64.sub" => &[0x7D],
+            "i64.mul" => &[0x7E],
+            "i64.div_s" => &[0x7F],
+            "i64.div_u" => &[0x80],
+            "i64.rem_s" => &[0x81],
+            "i64.rem_u" => &[0x82],
+            "i64.and" => &[0x83],
+            "i64.or" => &[0x84],
+            "i64.xor" => &[0x85],
+            "i64.shl" => &[0x86],
+            "i64.shr_s" => &[0x87],
+            "i64.shr_u" => &[0x88],
+            "f32.neg" => &[0x8C],
+            "f32.add" => &[0x92],
+            "f32.sub" => &[0x93],
+            "f32.mul" => &[0x94],
+            "f32.div" => &[0x95],
+            "f64.neg" => &[0x9A],
+            "f64.add" => &[0xA0],
+            "f64.sub" => &[0xA1],
+            "f64.mul" => &[0xA2],
+            "f64.div" => &[0xA3],
+            "i32.wrap_i64" => &[0xA7],
+            "i64.extend_i32_s" => &[0xAC],
+            "i64.extend_i32_u" => &[0xAD],
+            "f32.convert_i32_s" => &[0xB2],
+            "f32.convert_i32_u" => &[0xB3],
+            "f32.convert_i64_s" => &[0xB4],
+            "f32.convert_i64_u" => &[0xB5],
+            "f32.demote_f64" => &[0xB6],
+            "f64.convert_i32_s" => &[0xB7],
+            "f64.convert_i32_u" => &[0xB8],
+            "f64.convert_i64_s" => &[0xB9],
+            "f64.convert_i64_u" => &[0xBA],
+            "f64.promote_f32" => &[0xBB],
+            "i32.reinterpret_f32" => &[0xBC],
+            "i64.reinterpret_f64" => &[0xBD],
+            "f32.reinterpret_i32" => &[0xBE],
+            "f64.reinterpret_i64" => &[0xBF],
+            "i32.extend8_s" => &[0xC0],
+            "i32.extend16_s" => &[0xC1],
+            "i64.trunc_sat_f32_s" => &[0xFC, 0x04],
+            "i64.trunc_sat_f64_s" => &[0xFC, 0x06],
+            _ => unreachable!("numeric instruction {}", name),
+        };
+        self.instruction(opcode, name, &[], "");
+    }
+}
+
+/// One function of the module with its type, extra locals and body.
+struct IRWasmFunction {
+    type_index: u32,
+    locals: Vec<IRWasmType>,
+    code: IRWasmCode,
+}
+
+type IRWasmSignature = (Vec<IRWasmType>, Vec<IRWasmType>);
+
+/// Everything about the module a function body refers to.
+struct IRWasmLayout {
+    types: RefCell<Vec<IRWasmSignature>>,
+    imports: IndexMap<String, u32>,
+    /// Index of every function by name: imports first, then the allocator, then the module's.
+    functions: IndexMap<String, u32>,
+    malloc: u32,
+    free: u32,
+    realloc: u32,
+    global_addresses: IndexMap<String, u32>,
+    constant_addresses: HashMap<usize, u32>,
+}
+
+impl IRWasmLayout {
+    fn type_index(&self, signature: IRWasmSignature) -> u32 {
+        let mut types = self.types.borrow_mut();
+        match types.iter().position(|existing| *existing == signature) {
+            Some(index) => index as u32,
+            None => {
+                types.push(signature);
+                types.len() as u32 - 1
+            }
+        }
+    }
+
+    /// The table slot, and so the address, of a function.
+    fn function_address(&self, name: &str) -> Option<u32> {
+        self.functions.get(name).map(|index| index + 1)
+    }
+}
+
+fn signature(ir_function: &IRFunction) -> IRWasmSignature {
+    let parameters = ir_function.fields[..ir_function.arguments_count]
+        .iter()
+        .map(|field| IRWasmType::of(value_type(field._type.as_ref())))
+        .collect();
+    (parameters, results(ir_function.return_type.as_ref()))
+}
+
+fn results(return_type: &dyn crate::ir::types::IRType) -> Vec<IRWasmType> {
+    match IRValueType::of(return_type) {
+        IRValueType::Void => vec![],
+        value_type => vec![IRWasmType::of(value_type)],
+    }
+}
+
+struct IRWasmModule {
+    types: Vec<IRWasmSignature>,
+    imports: Vec<(String, u32)>,
+    functions: Vec<IRWasmFunction>,
+    exports: Vec<(String, u32)>,
+    data: Vec<(u32, Vec<u8>)>,
+    pages: u32,
+    stack_top: u32,
+    heap_base: u32,
+}
+
+impl IRWasmModule {
+    fn build(ir_module: &IRModule) -> IRGenerateResult<Self> {
+        let ir_module = &*lower_for_target(ir_module, IRTarget::Wasm32)?;
+        check_scalar_values(ir_module)?;
+        let collector = IRImportCollector {
+            ir_module,
+            imports: RefCell::new(IndexMap::new()),
+        };
+        for ir_control_flow_graph in control_flow_graphs(ir_module) {
+            for ir_basic_block in ir_control_flow_graph.basic_blocks.values() {
+                for ir_instruction in ir_basic_block.instructions.iter() {
+                    ir_instruction.accept(&collector);
+                }
+            }
+        }
+        let mut global_layouts = vec![];
+        for ir_global_data in ir_module.global_data_section.data.iter() {
+            let layout = global_data_layout(ir_module, ir_global_data)?;
+            for item in layout.items.iter() {
+                if let IRDataItem::Symbol(symbol) = item {
+                    collector.add(symbol, None);
+                }
+            }
+            global_layouts.push((ir_global_data.name.clone(), layout));
+        }
+        let layout_types = RefCell::new(vec![]);
+        let mut layout = IRWasmLayout {
+            types: layout_types,
+            imports: IndexMap::new(),
+            functions: IndexMap::new(),
+            malloc: 0,
+            free: 0,
+            realloc: 0,
+            global_addresses: IndexMap::new(),
+            constant_addresses: HashMap::new(),
+        };
+        for (name, signature) in collector.imports.into_inner() {
+            let type_index = layout.type_index(signature.unwrap_or_default());
+            layout
+                .functions
+                .insert(name.clone(), layout.functions.len() as u32);
+            layout.imports.insert(name, type_index);
+        }
+        let allocator_base = layout.functions.len() as u32;
+        layout.malloc = allocator_base;
+        layout.free = allocator_base + 1;
+        layout.realloc = allocator_base + 2;
+        let module_base = allocator_base + 3;
+        for (index, name) in ir_module.functions.keys().enumerate() {
+            layout
+                .functions
+                .insert(name.clone(), module_base + index as u32);
+        }
+
+        // Linear memory: constants, then global data, then the stack and the heap.
+        let mut address = DATA_START;
+        let mut data = vec![];
+        for (index, entry) in ir_module.constant_pool.entries.iter().enumerate() {
+            if let Some(IRConstantData::String(mut bytes)) = IRConstantData::of(entry) {
+                bytes.push(0);
+                layout.constant_addresses.insert(index, address);
+                address += bytes.len() as u32;
+                data.push((layout.constant_addresses[&index], bytes));
+            }
+        }
+        for (name, global_layout) in global_layouts.iter() {
+            address = address.next_multiple_of(8);
+            layout.global_addresses.insert(name.clone(), address);
+            address += global_layout.size as u32;
+        }
+        for (name, global_layout) in global_layouts.iter() {
+            if !global_layout.initialized {
+                continue;
+            }
+            let mut bytes = vec![];
+            for item in global_layout.items.iter() {
+                match item {
+                    IRDataItem::Bytes(value) => bytes.extend_from_slice(value),
+                    IRDataItem::Symbol(symbol) => {
+                        let value = layout
+                            .global_addresses
+                            .get(symbol)
+                            .copied()
+                            .or_else(|| layout.function_address(symbol))
+                            .unwrap_or_default();
+                        bytes.extend_from_slice(&(value as u64).to_le_bytes());
+                    }
+                    IRDataItem::Constant(index) => {
+                        let value = layout.constant_addresses[index] as u64;
+                        bytes.extend_from_slice(&value.to_le_bytes());
+                    }
+                    IRDataItem::Zero(size) => bytes.resize(bytes.len() + *size as usize, 0),
+                }
+            }
+            data.push((layout.global_addresses[name], bytes));
+        }
+        let stack_base = address.next_multiple_of(16);
+        let stack_top = stack_base + STACK_SIZE;
+
+        let mut functions = vec![
+            allocator_malloc(&layout),
+            allocator_free(&layout),
+            allocator_realloc(&layout),
+        ];
+        let mut exports = vec![];
+        for ir_function in ir_module.functions.values() {
+            let emitter = IRWasmEmitter::new(ir_module, Some(ir_function), &layout);
+            functions.push(emitter.emit_function()?);
+            exports.push((
+                ir_function.name.clone(),
+                layout.functions[&ir_function.name],
+            ));
+        }
+        let next_index = module_base + ir_module.functions.len() as u32;
+        let mut init = None;
+        if !ir_module.global_init_section.basic_blocks.is_empty() {
+            let emitter = IRWasmEmitter::new(ir_module, None, &layout);
+            functions.push(emitter.emit_function()?);
+            init = Some(next_index);
+            exports.push(("_initialize".to_string(), next_index));
+        }
+        let entry_point = ir_module
+            .entry_point
+            .as_ref()
+            .and_then(|entry_point| ir_module.functions.get(entry_point));
+        if let Some(ir_function) = entry_point {
+            let mut code = IRWasmCode::default();
+            if let Some(init) = init {
+                code.call(init);
+            }
+            for field in ir_function.fields[..ir_function.arguments_count].iter() {
+                match IRWasmType::of(value_type(field._type.as_ref())) {
+                    IRWasmType::I32 => code.i32_const(0),
+                    IRWasmType::I64 => code.i64_const(0),
+                    IRWasmType::F32 => code.f32_const(0.0),
+                    IRWasmType::F64 => code.f64_const(0.0),
+                }
+            }
+            code.call(layout.functions[&ir_function.name]);
+            if !results(ir_function.return_type.as_ref()).is_empty() {
+                code.op(0x1A, "drop");
+            }
+            functions.push(IRWasmFunction {
+                type_index: layout.type_index((vec![], vec![])),
+                locals: vec![],
+                code,
+            });
+            exports.push(("_start".to_string(), next_index + init.is_some() as u32));
+        }
+        // Names exported first win; a module function may not shadow a reserved export.
+        let mut seen = vec!["memory".to_string()];
+        exports.retain(|(name, _)| {
+            let duplicate = seen.contains(name);
+            seen.push(name.clone());
+            !duplicate
+        });
+        exports.sort_by_key(|(name, _)| !matches!(name.as_str(), "_start" | "_initialize"));
+        Ok(Self {
+            types: layout.types.into_inner(),
+            imports: layout.imports.into_iter().collect(),
+            functions,
+            exports,
+            data,
+            pages: stack_top.div_ceil(PAGE_SIZE).max(1),
+            stack_top,
+            heap_base: stack_top,
+        })
+    }
+
+    fn function_count(&self) -> u32 {
+        (self.imports.len() + self.functions.len()) as u32
+    }
+
+    fn globals(&self) -> [(&'static str, u32); 3] {
+        [
+            ("stack pointer", self.stack_top),
+            ("heap top", self.heap_base),
+            ("free list", 0),
+        ]
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        fn section(output: &mut Vec<u8>, id: u8, content: Vec<u8>) {
+            output.push(id);
+            write_unsigned(output, content.len() as u64);
+            output.extend(content);
+        }
+        fn vector(output: &mut Vec<u8>, length: usize) {
+            write_unsigned(output, length as u64);
+        }
+        let mut output = b"\0asm".to_vec();
+        output.extend_from_slice(&1u32.to_le_bytes());
+
+        let mut types = vec![];
+        vector(&mut types, self.types.len());
+        for (parameters, results) in self.types.iter() {
+            types.push(0x60);
+            vector(&mut types, parameters.len());
+            types.extend(parameters.iter().map(|_type| _type.code()));
+            vector(&mut types, results.len());
+            types.extend(results.iter().map(|_type| _type.code()));
+        }
+        section(&mut output, 1, types);
+
+        let mut imports = vec![];
+        vector(&mut imports, self.imports.len());
+        for (name, type_index) in self.imports.iter() {
+            write_name(&mut imports, "env");
+            write_name(&mut imports, name);
+            imports.push(0x00);
+            write_unsigned(&mut imports, *type_index as u64);
+        }
+        section(&mut output, 2, imports);
+
+        let mut functions = vec![];
+        vector(&mut functions, self.functions.len());
+        for function in self.functions.iter() {
+            write_unsigned(&mut functions, function.type_index as u64);
+        }
+        section(&mut output, 3, functions);
+
+        let mut table = vec![];
+        vector(&mut table, 1);
+        table.extend([0x70, 0x00]);
+        write_unsigned(&mut table, self.function_count() as u64 + 1);
+        section(&mut output, 4, table);
+
+        let mut memory = vec![];
+        vector(&mut memory, 1);
+        memory.push(0x00);
+        write_unsigned(&mut memory, self.pages as u64);
+        section(&mut output, 5, memory);
+
+        let mut globals = vec![];
+        vector(&mut globals, 3);
+        for (_, value) in self.globals() {
+            globals.extend([IRWasmType::I32.code(), 0x01, 0x41]);
+            write_signed(&mut globals, value as i32 as i64);
+            globals.push(0x0B);
+        }
+        section(&mut output, 6, globals);
+
+        let mut exports = vec![];
+        vector(&mut exports, self.exports.len() + 1);
+        write_name(&mut exports, "memory");
+        exports.extend([0x02, 0x00]);
+        for (name, index) in self.exports.iter() {
+            write_name(&mut exports, name);
+            exports.push(0x00);
+            write_unsigned(&mut exports, *index as u64);
+        }
+        section(&mut output, 7, exports);
+
+        let mut elements = vec![];
+        vector(&mut elements, 1);
+        elements.extend([0x00, 0x41, 0x01, 0x0B]);
+        vector(&mut elements, self.function_count() as usize);
+        for index in 0..self.function_count() {
+            write_unsigned(&mut elements, index as u64);
+        }
+        section(&mut output, 9, elements);
+
+        let mut code = vec![];
+        vector(&mut code, self.functions.len());
+        for function in self.functions.iter() {
+            let mut body = vec![];
+            let mut runs: Vec<(u32, IRWasmType)> = vec![];
+            for local in function.locals.iter() {
+                match runs.last_mut() {
+                    Some((count, _type)) if _type == local => *count += 1,
+                    _ => runs.push((1, *local)),
+                }
+            }
+            vector(&mut body, runs.len());
+            for (count, _type) in runs {
+                write_unsigned(&mut body, count as u64);
+                body.push(_type.code());
+            }
+            body.extend_from_slice(&function.code.bytes);
+            body.push(0x0B);
+            write_unsigned(&mut code, body.len() as u64);
+            code.extend(body);
+        }
+        section(&mut output, 10, code);
+
+        let mut data = vec![];
+        vector(&mut data, self.data.len());
+        for (address, bytes) in self.data.iter() {
+            data.extend([0x00, 0x41]);
+            write_signed(&mut data, *address as i32 as i64);
+            data.push(0x0B);
+            vector(&mut data, bytes.len());
+            data.extend_from_slice(bytes);
+        }
+        section(&mut output, 11, data);
+        output
+    }
+
+    fn text(&self) -> String {
+        let list = |types: &[IRWasmType]| {
+            types
+                .iter()
+                .map(|_type| _type.name())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let mut output = String::from("(module");
+        for (index, (parameters, results)) in self.types.iter().enumerate() {
+            let _ = write!(output, "\n  (type (;{};) (func", index);
+            if !parameters.is_empty() {
+                let _ = write!(output, " (param {})", list(parameters));
+            }
+            if !results.is_empty() {
+                let _ = write!(output, " (result {})", list(results));
+            }
+            output.push_str("))");
+        }
+        for (index, (name, type_index)) in self.imports.iter().enumerate() {
+            let _ = write!(
+                output,
+                "\n  (import \"env\" {} (func (;{};) (type {})))",
+                quote(name.as_bytes()),
+                index,
+                type_index
+            );
+        }
+        for (index, function) in self.functions.iter().enumerate() {
+            let _ = write!(
+                output,
+                "\n  (func (;{};) (type {})",
+                self.imports.len() + index,
+                function.type_index
+            );
+            if !function.locals.is_empty() {
+                let _ = write!(output, " (local {})", list(&function.locals));
+            }
+            output.push_str(&function.code.text);
+            output.push(')');
+        }
+        let _ = write!(
+            output,
+            "\n  (table (;0;) {} funcref)",
+            self.function_count() + 1
+        );
+        let _ = write!(output, "\n  (memory (;0;) {})", self.pages);
+        for (index, (name, value)) in self.globals().iter().enumerate() {
+            let _ = write!(
+                output,
+                "\n  (global (;{};) (mut i32) (i32.const {})) ;; {}",
+                index, *value as i32, name
+            );
+        }
+        output.push_str("\n  (export \"memory\" (memory 0))");
+        for (name, index) in self.exports.iter() {
+            let _ = write!(
+                output,
+                "\n  (export {} (func {}))",
+                quote(name.as_bytes()),
+                index
+            );
+        }
+        let _ = write!(output, "\n  (elem (;0;) (i32.const 1) func");
+        for index in 0..self.function_count() {
+            let _ = write!(output, " {}", index);
+        }
+        output.push(')');
+        for (address, bytes) in self.data.iter() {
+            let _ = write!(
+                output,
+                "\n  (data (i32.const {}) {})",
+                address,
+                quote(bytes)
+            );
+        }
+        output.push_str(")\n");
+        output
+    }
+}
+
+fn control_flow_graphs(ir_module: &IRModule) -> impl Iterator<Item = &IRControlFlowGraph> {
+    std::iter::once(ir_module.global_init_section.as_ref()).chain(
+        ir_module
+            .functions
+            .values()
+            .map(|ir_function| ir_function.control_flow_graph.as_ref()),
+    )
+}
+
+/// `malloc(size)`: first fit from the free list, else a new block at the heap top, growing
+/// memory when needed. Capacities are rounded up to multiples of 8.
+fn allocator_malloc(layout: &IRWasmLayout) -> IRWasmFunction {
+    let (size, capacity, previous, block, top) = (0, 1, 2, 3, 4);
+    let mut code = IRWasmCode::default();
+    code.local_get(size);
+    code.numeric("i32.wrap_i64");
+    code.i32_const(7);
+    code.numeric("i32.add");
+    code.i32_const(-8);
+    code.numeric("i32.and");
+    code.local_tee(capacity);
+    code.numeric("i32.eqz");
+    code.open(0x04, "if");
+    code.i32_const(8);
+    code.local_set(capacity);
+    code.end();
+    code.global_get(FREE_LIST);
+    code.local_set(block);
+    code.open(0x02, "block");
+    code.open(0x03, "loop");
+    code.local_get(block);
+    code.numeric("i32.eqz");
+    code.br_if(1);
+    code.local_get(block);
+    code.load(
+        IRValueType::Integer {
+            bits: 32,
+            unsigned: true,
+        },
+        0,
+    );
+    code.local_get(capacity);
+    code.numeric("i32.ge_u");
+    code.open(0x04, "if");
+    code.local_get(previous);
+    code.numeric("i32.eqz");
+    code.open(0x04, "if");
+    code.local_get(block);
+    code.load(
+        IRValueType::Integer {
+            bits: 32,
+            unsigned: true,
+        },
+        4,
+    );
+    code.global_set(FREE_LIST);
+    code.otherwise();
+    code.local_get(previous);
+    code.local_get(block);
+    code.load(
+        IRValueType::Integer {
+            bits: 32,
+            unsigned: true,
+        },
+        4,
+    );
+    code.store(
+        IRValueType::Integer {
+            bits: 32,
+            unsigned: true,
+        },
+        4,
+    );
+    code.end();
+    code.local_get(block);
+    code.i32_const(BLOCK_HEADER);
+    code.numeric("i32.add");
+    code.numeric("i64.extend_i32_u");
+    code.op(0x0F, "return");
+    code.end();
+    code.local_get(block);
+    code.local_set(previous);
+    code.local_get(block);
+    code.load(
+        IRValueType::Integer {
+            bits: 32,
+            unsigned: true,
+        },
+        4,
+    );
+    code.local_set(block);
+    code.br(0);
+    code.end();
+    code.end();
+    code.global_get(HEAP_TOP);
+    code.local_tee(block);
+    code.i32_const(BLOCK_HEADER);
+    code.numeric("i32.add");
+    code.local_get(capacity);
+    code.numeric("i32.add");
+    code.local_tee(top);
+    code.instruction(&[0x3F, 0x00], "memory.size", &[], "");
+    code.i32_const(16);
+    code.numeric("i32.shl");
+    code.numeric("i32.gt_u");
+    code.open(0x04, "if");
+    code.local_get(top);
+    code.instruction(&[0x3F, 0x00], "memory.size", &[], "");
+    code.i32_const(16);
+    code.numeric("i32.shl");
+    code.numeric("i32.sub");
+    code.i32_const(PAGE_SIZE as i32 - 1);
+    code.numeric("i32.add");
+    code.i32_const(16);
+    code.numeric("i32.shr_u");
+    code.instruction(&[0x40, 0x00], "memory.grow", &[], "");
+    code.i32_const(-1);
+    code.numeric("i32.eq");
+    code.open(0x04, "if");
+    code.i64_const(0);
+    code.op(0x0F, "return");
+    code.end();
+    code.end();
+    code.local_get(block);
+    code.local_get(capacity);
+    code.store(
+        IRValueType::Integer {
+            bits: 32,
+            unsigned: true,
+        },
+        0,
+    );
+    code.local_get(top);
+    code.global_set(HEAP_TOP);
+    code.local_get(block);
+    code.i32_const(BLOCK_HEADER);
+    code.numeric("i32.add");
+    code.numeric("i64.extend_i32_u");
+    IRWasmFunction {
+        type_index: layout.type_index((vec![IRWasmType::I64], vec![IRWasmType::I64])),
+        locals: vec![IRWasmType::I32; 4],
+        code,
+    }
+}
+
+/// `free(address)`: pushes the block on the free list.
+fn allocator_free(layout: &IRWasmLayout) -> IRWasmFunction {
+    let (address, block) = (0, 1);
+    let mut code = IRWasmCode::default();
+    code.local_get(address);
+    code.numeric("i64.eqz");
+    code.open(0x04, "if");
+    code.op(0x0F, "return");
+    code.end();
+    code.local_get(address);
+    code.numeric("i32.wrap_i64");
+    code.i32_const(BLOCK_HEADER);
+    code.numeric("i32.sub");
+    code.local_tee(block);
+    code.global_get(FREE_LIST);
+    code.store(
+        IRValueType::Integer {
+            bits: 32,
+            unsigned: true,
+        },
+        4,
+    );
+    code.local_get(block);
+    code.global_set(FREE_LIST);
+    IRWasmFunction {
+        type_index: layout.type_index((vec![IRWasmType::I64], vec![])),
+        locals: vec![IRWasmType::I32],
+        code,
+    }
+}
+
+/// `realloc(address, size)`: keeps the block when it is large enough, else moves the contents to
+/// a new one.
+fn allocator_realloc(layout: &IRWasmLayout) -> IRWasmFunction {
+    let (address, size, capacity, moved) = (0, 1, 2, 3);
+    let mut code = IRWasmCode::default();
+    code.local_get(address);
+    code.numeric("i64.eqz");
+    code.open(0x04, "if");
+    code.local_get(size);
+    code.call(layout.malloc);
+    code.op(0x0F, "return");
+    code.end();
+    code.local_get(address);
+    code.numeric("i32.wrap_i64");
+    code.i32_const(BLOCK_HEADER);
+    code.numeric("i32.sub");
+    code.load(
+        IRValueType::Integer {
+            bits: 32,
+            unsigned: true,
+        },
+        0,
+    );
+    code.local_tee(capacity);
+    code.numeric("i64.extend_i32_u");
+    code.local_get(size);
+    code.numeric("i64.ge_u");
+    code.open(0x04, "if");
+    code.local_get(address);
+    code.op(0x0F, "return");
+    code.end();
+    code.local_get(size);
+    code.call(layout.malloc);
+    code.local_tee(moved);
+    code.numeric("i64.eqz");
+    code.open(0x04, "if");
+    code.i64_const(0);
+    code.op(0x0F, "return");
+    code.end();
+    code.local_get(moved);
+    code.numeric("i32.wrap_i64");
+    code.local_get(address);
+    code.numeric("i32.wrap_i64");
+    code.local_get(capacity);
+    code.instruction(&[0xFC, 0x0A, 0x00, 0x00], "memory.copy", &[], "");
+    code.local_get(address);
+    code.call(layout.free);
+    code.local_get(moved);
+    IRWasmFunction {
+        type_index: layout.type_index((
+            vec![IRWasmType::I64, IRWasmType::I64],
+            vec![IRWasmType::I64],
+        )),
+        locals: vec![IRWasmType::I32, IRWasmType::I64],
+        code,
+    }
+}
+
+/// Collects the functions the module calls or refers to without defining them, with the
+/// signature of the first direct call, and whether `fmod`/`fmodf` are needed.
+struct IRImportCollector<'a> {
+    ir_module: &'a IRModule,
+    imports: RefCell<IndexMap<String, Option<IRWasmSignature>>>,
+}
+
+impl IRImportCollector<'_> {
+    fn add(&self, name: &str, signature: Option<IRWasmSignature>) {
+        let defined = self.ir_module.functions.contains_key(name)
+            || self
+                .ir_module
+                .global_data_section
+                .data
+                .iter()
+                .any(|data| data.name == name);
+        if defined {
+            return;
+        }
+        let mut imports = self.imports.borrow_mut();
+        let entry = imports.entry(name.to_string()).or_default();
+        if entry.is_none() {
+            *entry = signature;
+        }
+    }
+
+    fn collect(&self, operand: &dyn IROperand) {
+        if let IROperandValue::FunctionAddress(name) = IROperandValue::of(operand) {
+            self.add(&name, None);
+        }
+    }
+}
+
+impl IRVisitor for IRImportCollector<'_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_invoke(&self, ir_invoke: &IRInvoke) {
+        if let IROperandValue::FunctionAddress(name) =
+            IROperandValue::of(ir_invoke.address.as_ref())
+        {
+            let parameters = ir_invoke
+                .argument_types
+                .iter()
+                .map(|_type| IRWasmType::of(value_type(_type.as_ref())))
+                .collect();
+            self.add(
+                &name,
+                Some((parameters, results(ir_invoke.return_type.as_ref()))),
+            );
+        }
+        for argument in ir_invoke.arguments.iter() {
+            self.collect(argument.as_ref());
+        }
+    }
+    fn visit_set_virtual_register(&self, ir_set_virtual_register: &IRSetVirtualRegister) {
+        self.collect(ir_set_virtual_register.source.as_ref());
+    }
+    fn visit_set(&self, ir_set: &IRSet) {
+        self.collect(ir_set.value.as_ref());
+    }
+    fn visit_calculate(&self, ir_calculate: &IRCalculate) {
+        if ir_calculate.operator == IRCalculateOperator::MOD {
+            let signature = |_type| (vec![_type, _type], vec![_type]);
+            match IRValueType::of(ir_calculate._type.as_ref()) {
+                IRValueType::Float => self.add("fmodf", Some(signature(IRWasmType::F32))),
+                IRValueType::Double => self.add("fmod", Some(signature(IRWasmType::F64))),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// How a piece of a basic block is left.
+enum IRWasmExit {
+    Return(Option<Box<dyn IROperand>>),
+    Jump(usize),
+    Branch(IRConditionalJump, usize, usize),
+}
+
+/// A run of instructions of one basic block that is only entered at its start: a basic block
+/// is split after each conditional jump, `goto` and `return`.
+struct IRWasmNode {
+    basic_block: String,
+    start: usize,
+    end: usize,
+    exit: IRWasmExit,
+}
+
+impl IRWasmNode {
+    fn successors(&self) -> Vec<usize> {
+        match self.exit {
+            IRWasmExit::Return(_) => vec![],
+            IRWasmExit::Jump(target) => vec![target],
+            IRWasmExit::Branch(_, taken, not_taken) => vec![taken, not_taken],
+        }
+    }
+}
+
+/// Classifies the instruction that ends a node.
+#[derive(Default)]
+struct IRExitClassifier {
+    exit: RefCell<Option<IRWasmRawExit>>,
+}
+
+enum IRWasmRawExit {
+    Return(Option<Box<dyn IROperand>>),
+    Goto(String),
+    Branch(IRConditionalJump),
+}
+
+impl IRVisitor for IRExitClassifier {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_goto(&self, ir_goto: &IRGoto) {
+        *self.exit.borrow_mut() = Some(IRWasmRawExit::Goto(ir_goto.target.clone()));
+    }
+    fn visit_conditional_jump(&self, ir_conditional_jump: &IRConditionalJump) {
+        *self.exit.borrow_mut() = Some(IRWasmRawExit::Branch(ir_conditional_jump.clone()));
+    }
+    fn visit_return(&self, ir_return: &IRReturn) {
+        *self.exit.borrow_mut() = Some(IRWasmRawExit::Return(ir_return.operand.clone()));
+    }
+}
+
+/// The node graph of a function with what the structuring needs: reverse postorder, dominator
+/// tree, loop headers and merge nodes.
+struct IRWasmGraph {
+    nodes: Vec<IRWasmNode>,
+    /// Reachable nodes in reverse postorder, and each node's position in it.
+    order: Vec<usize>,
+    position: Vec<Option<usize>>,
+    dominator: Vec<usize>,
+    children: Vec<Vec<usize>>,
+    loop_header: Vec<bool>,
+    merge: Vec<bool>,
+    reducible: bool,
+}
+
+impl IRWasmGraph {
+    fn new(nodes: Vec<IRWasmNode>) -> Self {
+        let count = nodes.len();
+        let mut order = vec![];
+        if count > 0 {
+            let mut visited = vec![false; count];
+            let mut stack = vec![(0, 0)];
+            visited[0] = true;
+            while let Some((node, next)) = stack.pop() {
+                let successors = nodes[node].successors();
+                if next < successors.len() {
+                    stack.push((node, next + 1));
+                    let successor = successors[next];
+                    if !visited[successor] {
+                        visited[successor] = true;
+                        stack.push((successor, 0));
+                    }
+                } else {
+                    order.push(node);
+                }
+            }
+            order.reverse();
+        }
+        let mut position = vec![None; count];
+        for (index, node) in order.iter().enumerate() {
+            position[*node] = Some(index);
+        }
+        let mut predecessors = vec![vec![]; count];
+        for &node in order.iter() {
+            for successor in nodes[node].successors() {
+                predecessors[successor].push(node);
+            }
+        }
+        // Cooper, Harvey and Kennedy's iterative dominator algorithm.
+        let mut dominator = vec![usize::MAX; count];
+        if count > 0 {
+            dominator[0] = 0;
+        }
+        let intersect = |dominator: &[usize], mut a: usize, mut b: usize| {
+            while a != b {
+                while position[a] > position[b] {
+                    a = dominator[a];
+                }
+                while position[b] > position[a] {
+                    b = dominator[b];
+                }
+            }
+            a
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in order.iter().skip(1) {
+                let mut new = usize::MAX;
+                for &predecessor in predecessors[node].iter() {
+                    if dominator[predecessor] == usize::MAX {
+                        continue;
+                    }
+                    new = if new == usize::MAX {
+                        predecessor
+                    } else {
+                        intersect(&dominator, predecessor, new)
+                    };
+                }
+                if dominator[node] != new {
+                    dominator[node] = new;
+                    changed = true;
+                }
+            }
+        }
+        let dominates = |a: usize, mut b: usize| loop {
+            if a == b {
+                return true;
+            }
+            if b == dominator[b] {
+                return false;
+            }
+            b = dominator[b];
+        };
+        let mut children = vec![vec![]; count];
+        let mut loop_header = vec![false; count];
+        let mut forward_edges = vec![0; count];
+        let mut reducible = true;
+        for &node in order.iter() {
+            if node != 0 {
+                children[dominator[node]].push(node);
+            }
+            for successor in nodes[node].successors() {
+                if position[successor] <= position[node] {
+                    loop_header[successor] = true;
+                    reducible &= dominates(successor, node);
+                } else {
+                    forward_edges[successor] += 1;
+                }
+            }
+        }
+        Self {
+            nodes,
+            order,
+            position,
+            dominator,
+            children,
+            loop_header,
+            merge: forward_edges.into_iter().map(|count| count >= 2).collect(),
+            reducible,
+        }
+    }
+
+    fn is_backward(&self, from: usize, to: usize) -> bool {
+        self.position[to] <= self.position[from]
+    }
+}
+
+/// What encloses the code being emitted, innermost last, to compute branch depths.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IRWasmFrame {
+    If,
+    Loop(usize),
+    Block(usize),
+    Dispatch,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IRArithmetic {
+    Binary(IRCalculateOperator),
+    Not,
+    Negate,
+}
+
+struct IRWasmEmitter<'a> {
+    ir_module: &'a IRModule,
+    ir_function: Option<&'a IRFunction>,
+    ir_control_flow_graph: &'a IRControlFlowGraph,
+    layout: &'a IRWasmLayout,
+    types: IRFunctionTypes,
+    locals: RefCell<Vec<IRWasmType>>,
+    registers: IndexMap<String, (u32, IRValueType)>,
+    phi_shadows: IndexMap<String, (u32, IRValueType)>,
+    scratch: HashMap<IRWasmType, [u32; SCRATCH_LOCALS]>,
+    frame_pointer: u32,
+    address: u32,
+    label: u32,
+    frame_size: u32,
+    code: RefCell<IRWasmCode>,
+    location: RefCell<IRLocation>,
+    error: RefCell<Option<IRGenerateError>>,
+}
+
+impl<'a> IRWasmEmitter<'a> {
+    fn new(
+        ir_module: &'a IRModule,
+        ir_function: Option<&'a IRFunction>,
+        layout: &'a IRWasmLayout,
+    ) -> Self {
+        let ir_control_flow_graph = match ir_function {
+            Some(ir_function) => &ir_function.control_flow_graph,
+            None => &ir_module.global_init_section,
+        };
+        let types = infer_register_types(ir_module, ir_function);
+        let parameters = ir_function.map_or(0, |ir_function| ir_function.arguments_count as u32);
+        let mut locals = vec![];
+        let mut local = |_type: IRWasmType| {
+            locals.push(_type);
+            parameters + locals.len() as u32 - 1
+        };
+        let frame_pointer = local(IRWasmType::I32);
+        let address = local(IRWasmType::I32);
+        let label = local(IRWasmType::I32);
+        let registers = types
+            .registers
+            .iter()
+            .map(|(name, _type)| {
+                let value_type = value_type(_type.as_ref());
+                (
+                    name.clone(),
+                    (local(IRWasmType::of(value_type)), value_type),
+                )
+            })
+            .collect();
+        let mut phi_shadows = IndexMap::new();
+        for ir_basic_block in ir_control_flow_graph.basic_blocks.values() {
+            for (target, ir_phi) in ir_basic_block.phis() {
+                let value_type = value_type(ir_phi._type.as_ref());
+                phi_shadows
+                    .entry(target)
+                    .or_insert_with(|| (local(IRWasmType::of(value_type)), value_type));
+            }
+        }
+        let mut scratch = HashMap::new();
+        for _type in [
+            IRWasmType::I32,
+            IRWasmType::I64,
+            IRWasmType::F32,
+            IRWasmType::F64,
+        ] {
+            scratch.insert(_type, [local(_type), local(_type)]);
+        }
+        let fields = ir_function.map_or(0, |ir_function| ir_function.fields.len() as u32);
+        Self {
+            ir_module,
+            ir_function,
+            ir_control_flow_graph,
+            layout,
+            types,
+            locals: RefCell::new(locals),
+            registers,
+            phi_shadows,
+            scratch,
+            frame_pointer,
+            address,
+            label,
+            frame_size: (fields * 8).next_multiple_of(16),
+            code: RefCell::new(IRWasmCode::default()),
+            location: RefCell::new(IRLocation::Module),
+            error: RefCell::new(None),
+        }
+    }
+
+    fn code(&self) -> RefMut<'_, IRWasmCode> {
+        self.code.borrow_mut()
+    }
+
+    fn unsupported(&self, message: String) -> IRGenerateError {
+        IRGenerateError::Unsupported {
+            location: self.location.borrow().clone(),
+            message,
+        }
+    }
+
+    fn finish(&self, result: IRGenerateResult<()>) {
+        if let Err(error) = result {
+            self.error.borrow_mut().get_or_insert(error);
+        }
+    }
+
+    fn emit_function(self) -> IRGenerateResult<IRWasmFunction> {
+        let signature = match self.ir_function {
+            Some(ir_function) => signature(ir_function),
+            None => (vec![], vec![]),
+        };
+        // The frame holds the fields; the stack pointer on entry is restored on return, which
+        // also releases `stack_alloc` memory.
+        {
+            let mut code = self.code();
+            code.global_get(STACK_POINTER);
+            code.local_tee(self.frame_pointer);
+            code.i32_const(self.frame_size as i32);
+            code.numeric("i32.sub");
+            code.global_set(STACK_POINTER);
+        }
+        if let Some(ir_function) = self.ir_function {
+            for (index, field) in ir_function.fields[..ir_function.arguments_count]
+                .iter()
+                .enumerate()
+            {
+                let mut code = self.code();
+                code.global_get(STACK_POINTER);
+                code.local_get(index as u32);
+                code.store(value_type(field._type.as_ref()), index as u32 * 8);
+            }
+        }
+        let graph = IRWasmGraph::new(self.nodes()?);
+        if graph.nodes.is_empty() {
+            self.emit_return(None)?;
+        } else if graph.reducible {
+            self.emit_tree(&graph, 0, &mut vec![])?;
+        } else {
+            self.emit_dispatch(&graph)?;
+        }
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        // Every path has returned or branched; this keeps the validator's stack typing happy.
+        self.code().op(0x00, "unreachable");
+        Ok(IRWasmFunction {
+            type_index: self.layout.type_index(signature),
+            locals: self.locals.into_inner(),
+            code: self.code.into_inner(),
+        })
+    }
+
+    fn nodes(&self) -> IRGenerateResult<Vec<IRWasmNode>> {
+        let mut raw = vec![];
+        let mut first = IndexMap::new();
+        for (name, ir_basic_block) in self.ir_control_flow_graph.basic_blocks.iter() {
+            first.insert(name.clone(), raw.len());
+            let mut start = 0;
+            for (index, ir_instruction) in ir_basic_block.instructions.iter().enumerate() {
+                let classifier = IRExitClassifier::default();
+                ir_instruction.accept(&classifier);
+                if let Some(exit) = classifier.exit.into_inner() {
+                    raw.push((name.clone(), start, index + 1, Some(exit)));
+                    start = index + 1;
+                }
+            }
+            let ends_with_jump = raw.last().is_some_and(|(block, _, end, exit)| {
+                block == name && *end == start && matches!(exit, Some(IRWasmRawExit::Branch(_)))
+            });
+            if start < ir_basic_block.instructions.len() || start == 0 || ends_with_jump {
+                raw.push((name.clone(), start, ir_basic_block.instructions.len(), None));
+            }
+        }
+        let target = |name: &str| {
+            first
+                .get(name)
+                .copied()
+                .ok_or_else(|| self.unsupported(format!("jump to missing block '{}'", name)))
+        };
+        let mut nodes = vec![];
+        for (index, (basic_block, start, end, exit)) in raw.iter().enumerate() {
+            let exit = match exit {
+                Some(IRWasmRawExit::Return(operand)) => IRWasmExit::Return(operand.clone()),
+                Some(IRWasmRawExit::Goto(name)) => IRWasmExit::Jump(target(name)?),
+                Some(IRWasmRawExit::Branch(ir_conditional_jump)) => IRWasmExit::Branch(
+                    ir_conditional_jump.clone(),
+                    target(&ir_conditional_jump.target)?,
+                    index + 1,
+                ),
+                None => {
+                    let next = first.get_index_of(basic_block).unwrap() + 1;
+                    match first.get_index(next) {
+                        Some((_, node)) => IRWasmExit::Jump(*node),
+                        None => IRWasmExit::Return(None),
+                    }
+                }
+            };
+            nodes.push(IRWasmNode {
+                basic_block: basic_block.clone(),
+                start: *start,
+                end: *end,
+                exit,
+            });
+        }
+        Ok(nodes)
+    }
+
+    fn depth(&self, context: &[IRWasmFrame], frame: IRWasmFrame) -> IRGenerateResult<usize> {
+        context
+            .iter()
+            .rev()
+            .position(|existing| *existing == frame)
+            .ok_or_else(|| self.unsupported(format!("branch to {:?} outside of it", frame)))
+    }
+
+    /// Ramsey's `doTree`: a node, wrapped in a loop if it heads one, followed by the dominated
+    /// nodes that are reached from more than one place.
+    fn emit_tree(
+        &self,
+        graph: &IRWasmGraph,
+        node: usize,
+        context: &mut Vec<IRWasmFrame>,
+    ) -> IRGenerateResult<()> {
+        let mut merges = graph.children[node]
+            .iter()
+            .copied()
+            .filter(|child| graph.merge[*child])
+            .collect::<Vec<_>>();
+        merges.sort_by_key(|child| std::cmp::Reverse(graph.position[*child]));
+        if graph.loop_header[node] {
+            self.code().open(0x03, "loop");
+            context.push(IRWasmFrame::Loop(node));
+            self.emit_within(graph, node, &merges, context)?;
+            context.pop();
+            self.code().end();
+            Ok(())
+        } else {
+            self.emit_within(graph, node, &merges, context)
+        }
+    }
+
+    fn emit_within(
+        &self,
+        graph: &IRWasmGraph,
+        node: usize,
+        merges: &[usize],
+        context: &mut Vec<IRWasmFrame>,
+    ) -> IRGenerateResult<()> {
+        match merges {
+            [merge, rest @ ..] => {
+                self.code().open(0x02, "block");
+                context.push(IRWasmFrame::Block(*merge));
+                self.emit_within(graph, node, rest, context)?;
+                context.pop();
+                self.code().end();
+                self.emit_tree(graph, *merge, context)
+            }
+            [] => self.emit_node(graph, node, context),
+        }
+    }
+
+    /// The fallback for irreducible control flow: a loop around a `br_table` on the number of
+    /// the next node.
+    fn emit_dispatch(&self, graph: &IRWasmGraph) -> IRGenerateResult<()> {
+        let count = graph.order.len();
+        let mut context = vec![IRWasmFrame::Dispatch];
+        self.code().open(0x03, "loop");
+        for &node in graph.order.iter().rev() {
+            self.code().open(0x02, "block");
+            context.push(IRWasmFrame::Block(node));
+        }
+        {
+            let mut code = self.code();
+            code.local_get(self.label);
+            let targets = (0..count as u32).collect::<Vec<_>>();
+            code.br_table(&targets, count as u32 - 1);
+        }
+        for &node in graph.order.iter() {
+            self.code().end();
+            context.pop();
+            self.emit_node(graph, node, &mut context)?;
+        }
+        self.code().end();
+        Ok(())
+    }
+
+    fn emit_node(
+        &self,
+        graph: &IRWasmGraph,
+        node: usize,
+        context: &mut Vec<IRWasmFrame>,
+    ) -> IRGenerateResult<()> {
+        let ir_node = &graph.nodes[node];
+        let ir_basic_block = &self.ir_control_flow_graph.basic_blocks[&ir_node.basic_block];
+        let terminated = self.ends_with_exit(ir_node);
+        let end = if terminated {
+            ir_node.end - 1
+        } else {
+            ir_node.end
+        };
+        for index in ir_node.start..end {
+            self.location.replace(IRLocation::Instruction {
+                function: self.ir_function.map(|ir_function| ir_function.name.clone()),
+                basic_block: ir_node.basic_block.clone(),
+                index,
+            });
+            ir_basic_block.instructions[index].accept(self);
+            if let Some(error) = self.error.take() {
+                return Err(error);
+            }
+        }
+        if terminated {
+            self.location.replace(IRLocation::Instruction {
+                function: self.ir_function.map(|ir_function| ir_function.name.clone()),
+                basic_block: ir_node.basic_block.clone(),
+                index: end,
+            });
+        }
+        match &ir_node.exit {
+            IRWasmExit::Return(operand) => self.emit_return(operand.as_deref()),
+            IRWasmExit::Jump(target) => self.emit_branch(graph, node, *target, context),
+            IRWasmExit::Branch(ir_conditional_jump, taken, not_taken) => {
+                self.emit_condition(ir_conditional_jump)?;
+                self.code().open(0x04, "if");
+                context.push(IRWasmFrame::If);
+                self.emit_branch(graph, node, *taken, context)?;
+                self.code().otherwise();
+                self.emit_branch(graph, node, *not_taken, context)?;
+                context.pop();
+                self.code().end();
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether the last instruction of a node is the `goto` or `return` its exit comes from.
+    fn ends_with_exit(&self, ir_node: &IRWasmNode) -> bool {
+        if ir_node.end == ir_node.start {
+            return false;
+        }
+        let ir_basic_block = &self.ir_control_flow_graph.basic_blocks[&ir_node.basic_block];
+        let classifier = IRExitClassifier::default();
+        ir_basic_block.instructions[ir_node.end - 1].accept(&classifier);
+        classifier.exit.into_inner().is_some()
+    }
+
+    /// Ramsey's `doBranch`, after the copies feeding the phis of the target.
+    fn emit_branch(
+        &self,
+        graph: &IRWasmGraph,
+        from: usize,
+        to: usize,
+        context: &mut Vec<IRWasmFrame>,
+    ) -> IRGenerateResult<()> {
+        let target = &graph.nodes[to];
+        if target.start == 0 {
+            self.emit_phi_copies(&graph.nodes[from].basic_block, &target.basic_block)?;
+        }
+        if context.contains(&IRWasmFrame::Dispatch) {
+            let depth = self.depth(context, IRWasmFrame::Dispatch)?;
+            let mut code = self.code();
+            code.i32_const(graph.position[to].unwrap() as i32);
+            code.local_set(self.label);
+            code.br(depth);
+            return Ok(());
+        }
+        if graph.is_backward(from, to) {
+            let depth = self.depth(context, IRWasmFrame::Loop(to))?;
+            self.code().br(depth);
+        } else if graph.merge[to] {
+            let depth = self.depth(context, IRWasmFrame::Block(to))?;
+            self.code().br(depth);
+        } else {
+            debug_assert_eq!(graph.dominator[to], from);
+            self.emit_tree(graph, to, context)?;
+        }
+        Ok(())
+    }
+
+    fn emit_phi_copies(&self, from: &str, to: &str) -> IRGenerateResult<()> {
+        for phi_move in phi_moves(self.ir_control_flow_graph, from, to) {
+            let (local, value_type) = self.phi_shadows[&phi_move.target];
+            self.emit_operand(phi_move.operand.as_ref(), value_type)?;
+            self.code().local_set(local);
+        }
+        Ok(())
+    }
+
+    fn emit_return(&self, operand: Option<&dyn IROperand>) -> IRGenerateResult<()> {
+        if let Some(ir_function) = self.ir_function {
+            let return_type = IRValueType::of(ir_function.return_type.as_ref());
+            match (operand, return_type) {
+                (_, IRValueType::Void) => {}
+                (Some(operand), return_type) => self.emit_operand(operand, return_type)?,
+                (None, return_type) => self.emit_zero(return_type),
+            }
+        }
+        let mut code = self.code();
+        code.local_get(self.frame_pointer);
+        code.global_set(STACK_POINTER);
+        code.op(0x0F, "return");
+        Ok(())
+    }
+
+    fn emit_zero(&self, value_type: IRValueType) {
+        let mut code = self.code();
+        match IRWasmType::of(value_type) {
+            IRWasmType::I32 => code.i32_const(0),
+            IRWasmType::I64 => code.i64_const(0),
+            IRWasmType::F32 => code.f32_const(0.0),
+            IRWasmType::F64 => code.f64_const(0.0),
+        }
+    }
+
+    fn scratch(&self, value_type: IRValueType, index: usize) -> u32 {
+        self.scratch[&IRWasmType::of(value_type)][index]
+    }
+
+    /// Brings an integer held in 32 bits back to its type's range, sign- or zero-extended.
+    fn normalize(&self, value_type: IRValueType) {
+        let mut code = self.code();
+        match value_type {
+            IRValueType::Integer { bits: 1, .. } => {
+                code.i32_const(1);
+                code.numeric("i32.and");
+            }
+            IRValueType::Integer {
+                bits: 8,
+                unsigned: false,
+            } => code.numeric("i32.extend8_s"),
+            IRValueType::Integer {
+                bits: 16,
+                unsigned: false,
+            } => code.numeric("i32.extend16_s"),
+            IRValueType::Integer {
+                bits: bits @ (8 | 16),
+                unsigned: true,
+            } => {
+                code.i32_const(((1u32 << bits) - 1) as i32);
+                code.numeric("i32.and");
+            }
+            _ => {}
+        }
+    }
+
+    /// Converts the value on top of the stack between types that hold the same thing in
+    /// different widths or representations.
+    fn emit_convert(&self, from: IRValueType, to: IRValueType) -> IRGenerateResult<()> {
+        if from == to {
+            return Ok(());
+        }
+        let (from_wasm, to_wasm) = (IRWasmType::of(from), IRWasmType::of(to));
+        match (from, to) {
+            (IRValueType::Integer { bits: a, unsigned }, IRValueType::Integer { bits: b, .. }) => {
+                match (from_wasm, to_wasm) {
+                    (IRWasmType::I32, IRWasmType::I64) if unsigned || a == 1 => {
+                        self.code().numeric("i64.extend_i32_u")
+                    }
+                    (IRWasmType::I32, IRWasmType::I64) => self.code().numeric("i64.extend_i32_s"),
+                    (IRWasmType::I64, IRWasmType::I32) => {
+                        self.code().numeric("i32.wrap_i64");
+                        self.normalize(to);
+                    }
+                    _ if b < 32 => self.normalize(to),
+                    _ => {}
+                }
+            }
+            (IRValueType::Float, IRValueType::Double) => self.code().numeric("f64.promote_f32"),
+            (IRValueType::Double, IRValueType::Float) => self.code().numeric("f32.demote_f64"),
+            (IRValueType::Integer { bits: 32, .. }, IRValueType::Float) => {
+                self.code().numeric("f32.reinterpret_i32")
+            }
+            (IRValueType::Float, IRValueType::Integer { bits: 32, .. }) => {
+                self.code().numeric("i32.reinterpret_f32")
+            }
+            (IRValueType::Integer { bits: 64, .. }, IRValueType::Double) => {
+                self.code().numeric("f64.reinterpret_i64")
+            }
+            (IRValueType::Double, IRValueType::Integer { bits: 64, .. }) => {
+                self.code().numeric("i64.reinterpret_f64")
+            }
+            _ => {
+                return Err(self.unsupported(format!("conversion from {:?} to {:?}", from, to)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Pushes the value of an operand as a `value_type`.
+    fn emit_operand(
+        &self,
+        operand: &dyn IROperand,
+        value_type: IRValueType,
+    ) -> IRGenerateResult<()> {
+        let own_type = operand_type(self.ir_module, &self.types, operand)
+            .map(|_type| self::value_type(_type.as_ref()));
+        let from = match IROperandValue::of(operand) {
+            IROperandValue::Register(name) => {
+                let (local, register_type) = *self
+                    .registers
+                    .get(&name)
+                    .ok_or_else(|| self.unsupported(format!("register %{}", name)))?;
+                self.code().local_get(local);
+                register_type
+            }
+            IROperandValue::Constant(index) => {
+                let (entry, data) = constant(self.ir_module, index)
+                    .ok_or_else(|| self.unsupported(format!("constant ${}", index)))?;
+                let constant_type = self::value_type(entry._type.as_ref());
+                let mut code = self.code();
+                match (data, IRWasmType::of(constant_type)) {
+                    (IRConstantData::String(_), _) => {
+                        code.i64_const(self.layout.constant_addresses[&(index as usize)] as i64);
+                        IRValueType::ADDRESS
+                    }
+                    (IRConstantData::Integer(bits), IRWasmType::I32) => {
+                        let value = match constant_type {
+                            IRValueType::Integer {
+                                bits: width,
+                                unsigned: false,
+                            } if width > 1 => {
+                                ((bits << (64 - width)) as i64 >> (64 - width)) as i32
+                            }
+                            _ => bits as u32 as i32,
+                        };
+                        code.i32_const(value);
+                        constant_type
+                    }
+                    (IRConstantData::Integer(bits), _) => {
+                        code.i64_const(bits as i64);
+                        constant_type
+                    }
+                    (IRConstantData::Float(value), _) => {
+                        code.f32_const(value);
+                        IRValueType::Float
+                    }
+                    (IRConstantData::Double(value), _) => {
+                        code.f64_const(value);
+                        IRValueType::Double
+                    }
+                }
+            }
+            IROperandValue::FieldAddress(name) => {
+                let index = self
+                    .ir_function
+                    .and_then(|ir_function| {
+                        ir_function
+                            .fields
+                            .iter()
+                            .position(|field| field.name == name)
+                    })
+                    .ok_or_else(|| self.unsupported(format!("field '{}'", name)))?;
+                let mut code = self.code();
+                code.local_get(self.frame_pointer);
+                code.i32_const(self.frame_size as i32 - index as i32 * 8);
+                code.numeric("i32.sub");
+                code.numeric("i64.extend_i32_u");
+                IRValueType::ADDRESS
+            }
+            IROperandValue::GlobalDataAddress(name) => {
+                let address =
+                    self.layout.global_addresses.get(&name).ok_or_else(|| {
+                        self.unsupported(format!("external global data '{}'", name))
+                    })?;
+                self.code().i64_const(*address as i64);
+                IRValueType::ADDRESS
+            }
+            IROperandValue::FunctionAddress(name) => {
+                let address = self
+                    .layout
+                    .function_address(&name)
+                    .ok_or_else(|| self.unsupported(format!("function '{}'", name)))?;
+                self.code().i64_const(address as i64);
+                IRValueType::ADDRESS
+            }
+            IROperandValue::Phi(_) => {
+                return Err(
+                    self.unsupported(format!("phi outside of a register copy: {}", operand))
+                );
+            }
+            _ => return Err(self.unsupported(format!("operand {}", operand))),
+        };
+        // The operand's own type says how the pushed value is to be read, as long as it is held
+        // the same way.
+        let from = match own_type {
+            Some(own_type) if IRWasmType::of(own_type) == IRWasmType::of(from) => own_type,
+            _ => from,
+        };
+        self.emit_convert(from, value_type)
+    }
+
+    /// Pushes an operand as a 32-bit memory address.
+    fn emit_address(&self, operand: &dyn IROperand) -> IRGenerateResult<()> {
+        self.emit_operand(operand, IRValueType::ADDRESS)?;
+        self.code().numeric("i32.wrap_i64");
+        Ok(())
+    }
+
+    fn store_register(
+        &self,
+        target: &IRVirtualRegister,
+        from: IRValueType,
+    ) -> IRGenerateResult<()> {
+        let (local, register_type) = *self.registers.get(&target.name).ok_or_else(|| {
+            self.unsupported(format!("register %{} without a known type", target.name))
+        })?;
+        self.emit_convert(from, register_type)?;
+        self.code().local_set(local);
+        Ok(())
+    }
+
+    /// Pushes `a op b` where `a` and `b` are in the first and second scratch locals.
+    fn emit_arithmetic(
+        &self,
+        arithmetic: IRArithmetic,
+        value_type: IRValueType,
+    ) -> IRGenerateResult<()> {
+        use IRCalculateOperator::*;
+        let (a, b) = (self.scratch(value_type, 0), self.scratch(value_type, 1));
+        let wasm = IRWasmType::of(value_type).name();
+        match value_type {
+            IRValueType::Integer { bits, unsigned } => {
+                let operation = match arithmetic {
+                    IRArithmetic::Binary(ADD) => "add",
+                    IRArithmetic::Binary(SUB) => "sub",
+                    IRArithmetic::Binary(MUL) => "mul",
+                    IRArithmetic::Binary(DIV) if unsigned => "div_u",
+                    IRArithmetic::Binary(DIV) => "div_s",
+                    IRArithmetic::Binary(MOD) if unsigned => "rem_u",
+                    IRArithmetic::Binary(MOD) => "rem_s",
+                    IRArithmetic::Binary(AND) => "and",
+                    IRArithmetic::Binary(OR) => "or",
+                    IRArithmetic::Binary(XOR) => "xor",
+                    IRArithmetic::Binary(SHL) => "shl",
+                    IRArithmetic::Binary(SHR) if !unsigned => "shr_s",
+                    IRArithmetic::Binary(SHR | USHR) => "shr_u",
+                    IRArithmetic::Not => "xor",
+                    IRArithmetic::Negate => "sub",
+                };
+                let wide = bits == 64;
+                let constant = |code: &mut IRWasmCode, value: i64| {
+                    if wide {
+                        code.i64_const(value)
+                    } else {
+                        code.i32_const(value as i32)
+                    }
+                };
+                let mut code = self.code();
+                match arithmetic {
+                    IRArithmetic::Not => {
+                        code.local_get(a);
+                        constant(&mut code, -1);
+                    }
+                    IRArithmetic::Negate => {
+                        constant(&mut code, 0);
+                        code.local_get(a);
+                    }
+                    IRArithmetic::Binary(SHL | SHR | USHR) => {
+                        code.local_get(a);
+                        if operation == "shr_u" && bits < 32 {
+                            constant(&mut code, (1i64 << bits) - 1);
+                            code.numeric(&format!("{}.and", wasm));
+                        }
+                        code.local_get(b);
+                        constant(&mut code, bits as i64 - 1);
+                        code.numeric(&format!("{}.and", wasm));
+                    }
+                    IRArithmetic::Binary(_) => {
+                        code.local_get(a);
+                        code.local_get(b);
+                    }
+                }
+                code.numeric(&format!("{}.{}", wasm, operation));
+                drop(code);
+                self.normalize(value_type);
+            }
+            IRValueType::Float | IRValueType::Double => {
+                let operation = match arithmetic {
+                    IRArithmetic::Binary(ADD) => "add",
+                    IRArithmetic::Binary(SUB) => "sub",
+                    IRArithmetic::Binary(MUL) => "mul",
+                    IRArithmetic::Binary(DIV) => "div",
+                    IRArithmetic::Binary(MOD) => {
+                        let name = if value_type == IRValueType::Float {
+                            "fmodf"
+                        } else {
+                            "fmod"
+                        };
+                        let mut code = self.code();
+                        code.local_get(a);
+                        code.local_get(b);
+                        code.call(self.layout.functions[name]);
+                        return Ok(());
+                    }
+                    IRArithmetic::Negate => {
+                        let mut code = self.code();
+                        code.local_get(a);
+                        code.numeric(&format!("{}.neg", wasm));
+                        return Ok(());
+                    }
+                    arithmetic => {
+                        return Err(
+                            self.unsupported(format!("{:?} on {:?}", arithmetic, value_type))
+                        );
+                    }
+                };
+                let mut code = self.code();
+                code.local_get(a);
+                code.local_get(b);
+                code.numeric(&format!("{}.{}", wasm, operation));
+            }
+            IRValueType::Void => {
+                return Err(self.unsupported("arithmetic on void".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// `*address = *address op rhs`, leaving the new value in the first scratch local.
+    fn emit_update(
+        &self,
+        arithmetic: IRArithmetic,
+        value_type: IRValueType,
+        address: &dyn IROperand,
+        rhs: Option<&dyn IROperand>,
+        one: bool,
+    ) -> IRGenerateResult<()> {
+        self.emit_address(address)?;
+        self.code().local_set(self.address);
+        {
+            let mut code = self.code();
+            code.local_get(self.address);
+            code.load(value_type, 0);
+            code.local_set(self.scratch(value_type, 0));
+        }
+        if let Some(rhs) = rhs {
+            self.emit_operand(rhs, value_type)?;
+            self.code().local_set(self.scratch(value_type, 1));
+        } else if one {
+            self.emit_one(value_type);
+            self.code().local_set(self.scratch(value_type, 1));
+        }
+        self.emit_arithmetic(arithmetic, value_type)?;
+        let mut code = self.code();
+        code.local_set(self.scratch(value_type, 0));
+        code.local_get(self.address);
+        code.local_get(self.scratch(value_type, 0));
+        code.store(value_type, 0);
+        Ok(())
+    }
+
+    fn emit_one(&self, value_type: IRValueType) {
+        let mut code = self.code();
+        match IRWasmType::of(value_type) {
+            IRWasmType::I32 => code.i32_const(1),
+            IRWasmType::I64 => code.i64_const(1),
+            IRWasmType::F32 => code.f32_const(1.0),
+            IRWasmType::F64 => code.f64_const(1.0),
+        }
+    }
+
+    fn emit_unary(
+        &self,
+        arithmetic: IRArithmetic,
+        is_atomic: bool,
+        _type: &dyn crate::ir::types::IRType,
+        operand: &dyn IROperand,
+        target: &IRVirtualRegister,
+    ) -> IRGenerateResult<()> {
+        let value_type = value_type(_type);
+        if is_atomic {
+            self.emit_update(arithmetic, value_type, operand, None, false)?;
+            self.code().local_get(self.scratch(value_type, 0));
+        } else {
+            self.emit_operand(operand, value_type)?;
+            self.code().local_set(self.scratch(value_type, 0));
+            self.emit_arithmetic(arithmetic, value_type)?;
+        }
+        self.store_register(target, value_type)
+    }
+
+    fn emit_step(
+        &self,
+        operator: IRCalculateOperator,
+        _type: &dyn crate::ir::types::IRType,
+        operand: &dyn IROperand,
+        target: Option<&IRVirtualRegister>,
+    ) -> IRGenerateResult<()> {
+        let value_type = value_type(_type);
+        let arithmetic = IRArithmetic::Binary(operator);
+        match target {
+            Some(target) => {
+                self.emit_operand(operand, value_type)?;
+                self.code().local_set(self.scratch(value_type, 0));
+                self.emit_one(value_type);
+                self.code().local_set(self.scratch(value_type, 1));
+                self.emit_arithmetic(arithmetic, value_type)?;
+                self.store_register(target, value_type)
+            }
+            None => self.emit_update(arithmetic, value_type, operand, None, true),
+        }
+    }
+
+    /// Pushes the `i32` condition of a conditional jump.
+    fn emit_condition(&self, ir_conditional_jump: &IRConditionalJump) -> IRGenerateResult<()> {
+        let value_type = value_type(ir_conditional_jump._type.as_ref());
+        let (a, b) = (self.scratch(value_type, 0), self.scratch(value_type, 1));
+        if ir_conditional_jump.is_atomic {
+            self.emit_address(ir_conditional_jump.operand1.as_ref())?;
+            self.code().load(value_type, 0);
+        } else {
+            self.emit_operand(ir_conditional_jump.operand1.as_ref(), value_type)?;
+        }
+        self.code().local_set(a);
+        match ir_conditional_jump.operand2.as_ref() {
+            Some(operand2) => self.emit_operand(operand2.as_ref(), value_type)?,
+            None => self.emit_zero(value_type),
+        }
+        self.code().local_set(b);
+        let wasm = IRWasmType::of(value_type).name();
+        let floating_point = value_type.is_floating_point();
+        let suffix = if floating_point {
+            ""
+        } else if value_type.is_unsigned() {
+            "_u"
+        } else {
+            "_s"
+        };
+        let comparison = match ir_conditional_jump.condition {
+            IRCondition::Equal | IRCondition::IfFalse => "eq".to_string(),
+            IRCondition::NotEqual | IRCondition::IfTrue => "ne".to_string(),
+            IRCondition::Less => format!("lt{}", suffix),
+            IRCondition::LessEqual => format!("le{}", suffix),
+            IRCondition::Greater => format!("gt{}", suffix),
+            IRCondition::GreaterEqual => format!("ge{}", suffix),
+        };
+        let mut code = self.code();
+        code.local_get(a);
+        code.local_get(b);
+        code.numeric(&format!("{}.{}", wasm, comparison));
+        Ok(())
+    }
+
+    fn emit_invoke(&self, ir_invoke: &IRInvoke) -> IRGenerateResult<()> {
+        if ir_invoke.argument_types.len() != ir_invoke.arguments.len() {
+            return Err(self.unsupported("invoke with mismatched argument types".to_string()));
+        }
+        let callee = match IROperandValue::of(ir_invoke.address.as_ref()) {
+            IROperandValue::FunctionAddress(name) => self.layout.functions.get(&name).copied(),
+            _ => None,
+        };
+        let signature = match callee {
+            Some(index) => {
+                let type_index = match self.layout.imports.get_index(index as usize) {
+                    Some((_, type_index)) => *type_index,
+                    None => {
+                        let ir_function = &self.ir_module.functions
+                            [index as usize - self.layout.imports.len() - 3];
+                        self.layout.type_index(signature(ir_function))
+                    }
+                };
+                self.layout.types.borrow()[type_index as usize].clone()
+            }
+            None => (
+                ir_invoke
+                    .argument_types
+                    .iter()
+                    .map(|_type| IRWasmType::of(value_type(_type.as_ref())))
+                    .collect(),
+                results(ir_invoke.return_type.as_ref()),
+            ),
+        };
+        if signature.0.len() != ir_invoke.arguments.len() {
+            return Err(self.unsupported(format!(
+                "call with {} arguments to a function taking {}",
+                ir_invoke.arguments.len(),
+                signature.0.len()
+            )));
+        }
+        let module_function = callee.and_then(|index| {
+            let position = (index as usize).checked_sub(self.layout.imports.len() + 3)?;
+            self.ir_module.functions.get_index(position).map(|(_, f)| f)
+        });
+        for (index, argument) in ir_invoke.arguments.iter().enumerate() {
+            let value_type = match module_function {
+                Some(ir_function) => value_type(ir_function.fields[index]._type.as_ref()),
+                None => value_type(ir_invoke.argument_types[index].as_ref()),
+            };
+            self.emit_operand(argument.as_ref(), value_type)?;
+        }
+        match callee {
+            Some(index) => self.code().call(index),
+            None => {
+                self.emit_address(ir_invoke.address.as_ref())?;
+                let type_index = self.layout.type_index(signature.clone());
+                self.code().call_indirect(type_index);
+            }
+        }
+        let return_type = match module_function {
+            Some(ir_function) => IRValueType::of(ir_function.return_type.as_ref()),
+            None => IRValueType::of(ir_invoke.return_type.as_ref()),
+        };
+        if signature.1.is_empty() {
+            return Ok(());
+        }
+        match ir_invoke.target.as_ref() {
+            Some(target) => self.store_register(target, return_type),
+            None => {
+                self.code().op(0x1A, "drop");
+                Ok(())
+            }
+        }
+    }
+
+    fn emit_type_cast(&self, ir_type_cast: &IRTypeCast) -> IRGenerateResult<()> {
+        let from = value_type(ir_type_cast.original_type.as_ref());
+        let to = value_type(ir_type_cast.target_type.as_ref());
+        self.emit_operand(ir_type_cast.source.as_ref(), from)?;
+        match (ir_type_cast.kind, from) {
+            (
+                IRTypeCastKind::ZeroExtend | IRTypeCastKind::SignExtend,
+                IRValueType::Integer { bits, .. },
+            ) => {
+                // Reinterpret the source with the signedness the extension implies first.
+                let extended = IRValueType::Integer {
+                    bits,
+                    unsigned: ir_type_cast.kind == IRTypeCastKind::ZeroExtend,
+                };
+                if extended != from && bits < 32 {
+                    self.normalize(extended);
+                }
+                self.emit_convert(extended, to)?;
+            }
+            (IRTypeCastKind::Truncate, _) => self.emit_convert(from, to)?,
+            (IRTypeCastKind::IntToFloat, IRValueType::Integer { bits, unsigned }) => {
+                let source = if bits == 64 { "i64" } else { "i32" };
+                let target = if to == IRValueType::Float {
+                    "f32"
+                } else {
+                    "f64"
+                };
+                let sign = if unsigned || bits == 1 { "u" } else { "s" };
+                self.code()
+                    .numeric(&format!("{}.convert_{}_{}", target, source, sign));
+            }
+            (IRTypeCastKind::FloatToInt, IRValueType::Float | IRValueType::Double) => {
+                // Like the native targets: a signed 64-bit conversion, then truncation.
+                let source = if from == IRValueType::Float {
+                    "f32"
+                } else {
+                    "f64"
+                };
+                self.code().numeric(&format!("i64.trunc_sat_{}_s", source));
+                self.emit_convert(
+                    IRValueType::Integer {
+                        bits: 64,
+                        unsigned: false,
+                    },
+                    to,
+                )?;
+            }
+            (IRTypeCastKind::FloatExtend | IRTypeCastKind::FloatTruncate, _) => {
+                self.emit_convert(from, to)?
+            }
+            (kind, from) => {
+                return Err(self.unsupported(format!("{} from {:?}", kind, from)));
+            }
+        }
+        self.store_register(&ir_type_cast.target, to)
+    }
+}
+
+impl IRVisitor for IRWasmEmitter<'_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_goto(&self, _ir_goto: &IRGoto) {}
+    fn visit_conditional_jump(&self, _ir_conditional_jump: &IRConditionalJump) {}
+    fn visit_return(&self, _ir_return: &IRReturn) {}
+    fn visit_calculate(&self, ir_calculate: &IRCalculate) {
+        let value_type = value_type(ir_calculate._type.as_ref());
+        let arithmetic = IRArithmetic::Binary(ir_calculate.operator);
+        let result = (|| {
+            if ir_calculate.is_atomic {
+                self.emit_update(
+                    arithmetic,
+                    value_type,
+                    ir_calculate.operand1.as_ref(),
+                    Some(ir_calculate.operand2.as_ref()),
+                    false,
+                )?;
+                self.code().local_get(self.scratch(value_type, 0));
+            } else {
+                self.emit_operand(ir_calculate.operand1.as_ref(), value_type)?;
+                self.code().local_set(self.scratch(value_type, 0));
+                self.emit_operand(ir_calculate.operand2.as_ref(), value_type)?;
+                self.code().local_set(self.scratch(value_type, 1));
+                self.emit_arithmetic(arithmetic, value_type)?;
+            }
+            self.store_register(&ir_calculate.target, value_type)
+        })();
+        self.finish(result);
+    }
+    fn visit_not(&self, ir_not: &IRNot) {
+        self.finish(self.emit_unary(
+            IRArithmetic::Not,
+            ir_not.is_atomic,
+            ir_not._type.as_ref(),
+            ir_not.operand.as_ref(),
+            &ir_not.target,
+        ));
+    }
+    fn visit_negate(&self, ir_negate: &IRNegate) {
+        self.finish(self.emit_unary(
+            IRArithmetic::Negate,
+            ir_negate.is_atomic,
+            ir_negate._type.as_ref(),
+            ir_negate.operand.as_ref(),
+            &ir_negate.target,
+        ));
+    }
+    fn visit_malloc(&self, ir_malloc: &IRMalloc) {
+        let result = (|| {
+            self.emit_operand(ir_malloc.size.as_ref(), IRValueType::ADDRESS)?;
+            self.code().call(self.layout.malloc);
+            self.store_register(&ir_malloc.target, IRValueType::ADDRESS)
+        })();
+        self.finish(result);
+    }
+    fn visit_free(&self, ir_free: &IRFree) {
+        let result = (|| {
+            self.emit_operand(ir_free.ptr.as_ref(), IRValueType::ADDRESS)?;
+            self.code().call(self.layout.free);
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_realloc(&self, ir_realloc: &IRRealloc) {
+        let result = (|| {
+            self.emit_operand(ir_realloc.ptr.as_ref(), IRValueType::ADDRESS)?;
+            self.emit_operand(ir_realloc.size.as_ref(), IRValueType::ADDRESS)?;
+            self.code().call(self.layout.realloc);
+            self.store_register(&ir_realloc.target, IRValueType::ADDRESS)
+        })();
+        self.finish(result);
+    }
+    fn visit_get(&self, ir_get: &IRGet) {
+        let result = (|| {
+            let value_type = value_type(ir_get._type.as_ref());
+            self.emit_address(ir_get.address.as_ref())?;
+            self.code().load(value_type, 0);
+            self.store_register(&ir_get.target, value_type)
+        })();
+        self.finish(result);
+    }
+    fn visit_set(&self, ir_set: &IRSet) {
+        let result = (|| {
+            let value_type = value_type(ir_set._type.as_ref());
+            self.emit_address(ir_set.address.as_ref())?;
+            self.emit_operand(ir_set.value.as_ref(), value_type)?;
+            self.code().store(value_type, 0);
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_set_virtual_register(&self, ir_set_virtual_register: &IRSetVirtualRegister) {
+        let result = (|| {
+            let target = &ir_set_virtual_register.target;
+            match IROperandValue::of(ir_set_virtual_register.source.as_ref()) {
+                IROperandValue::Phi(_) => {
+                    let (local, value_type) = self.phi_shadows[&target.name];
+                    self.code().local_get(local);
+                    self.store_register(target, value_type)
+                }
+                _ => {
+                    let (_, register_type) =
+                        *self.registers.get(&target.name).ok_or_else(|| {
+                            self.unsupported(format!(
+                                "register %{} without a known type",
+                                target.name
+                            ))
+                        })?;
+                    self.emit_operand(ir_set_virtual_register.source.as_ref(), register_type)?;
+                    self.store_register(target, register_type)
+                }
+            }
+        })();
+        self.finish(result);
+    }
+    fn visit_invoke(&self, ir_invoke: &IRInvoke) {
+        self.finish(self.emit_invoke(ir_invoke));
+    }
+    fn visit_no_operate(&self, _ir_no_operate: &IRNoOperate) {}
+    fn visit_increase(&self, ir_increase: &IRIncrease) {
+        self.finish(self.emit_step(
+            IRCalculateOperator::ADD,
+            ir_increase._type.as_ref(),
+            ir_increase.operand.as_ref(),
+            ir_increase.target.as_deref(),
+        ));
+    }
+    fn visit_decrease(&self, ir_decrease: &IRDecrease) {
+        self.finish(self.emit_step(
+            IRCalculateOperator::SUB,
+            ir_decrease._type.as_ref(),
+            ir_decrease.operand.as_ref(),
+            ir_decrease.target.as_deref(),
+        ));
+    }
+    fn visit_stack_allocate(&self, ir_stack_allocate: &IRStackAllocate) {
+        let result = (|| {
+            {
+                let mut code = self.code();
+                code.global_get(STACK_POINTER);
+            }
+            self.emit_operand(ir_stack_allocate.size.as_ref(), IRValueType::ADDRESS)?;
+            {
+                let mut code = self.code();
+                code.numeric("i32.wrap_i64");
+                code.numeric("i32.sub");
+                code.i32_const(-16);
+                code.numeric("i32.and");
+                code.global_set(STACK_POINTER);
+                code.global_get(STACK_POINTER);
+                code.numeric("i64.extend_i32_u");
+            }
+            self.store_register(&ir_stack_allocate.target, IRValueType::ADDRESS)
+        })();
+        self.finish(result);
+    }
+    fn visit_type_cast(&self, ir_type_cast: &IRTypeCast) {
+        self.finish(self.emit_type_cast(ir_type_cast));
+    }
+    fn visit_asm(&self, _ir_asm: &IRAsm) {
+        self.finish(Err(
+            self.unsupported("inline assembly in WebAssembly".to_string())
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parser::parse_module;
+
+    const LOOP: &str = "\
+constant $0 = i64 0
+constant $1 = i64 1
+function i64 sum(i64 n) {
+entry:
+    %pn = `field_address([n], [])
+    %n = get i64, %pn
+    goto loop
+loop:
+    %i = phi i64 [entry, $0], [loop, %next_i]
+    %total = phi i64 [entry, $0], [loop, %next_total]
+    %next_total = add i64 %total, %i
+    %next_i = add i64 %i, $1
+    conditional_jump i64 le, %next_i, %n, #loop
+done:
+    %r = invoke i64 `function_address([twice], []), [i64, %next_total]
+    return %r
+}
+function i64 twice(i64 x) {
+entry:
+    %px = `field_address([x], [])
+    %x = get i64, %px
+    %r = add i64 %x, %x
+    return %r
+}
+";
+
+    /// Reads an unsigned LEB128 number at `position`, moving past it.
+    fn read_unsigned(bytes: &[u8], position: &mut usize) -> u64 {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = bytes[*position];
+            *position += 1;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return value;
+            }
+            shift += 7;
+        }
+    }
+
+    fn read_name(bytes: &[u8], position: &mut usize) -> String {
+        let length = read_unsigned(bytes, position) as usize;
+        let name = String::from_utf8(bytes[*position..*position + length].to_vec()).unwrap();
+        *position += length;
+        name
+    }
+
+    /// The id and contents of each section, checking that they exactly fill the module.
+    fn sections(binary: &[u8]) -> Vec<(u8, &[u8])> {
+        assert_eq!(&binary[..8], b"\0asm\x01\0\0\0");
+        let mut sections = vec![];
+        let mut position = 8;
+        while position < binary.len() {
+            let id = binary[position];
+            position += 1;
+            let size = read_unsigned(binary, &mut position) as usize;
+            sections.push((id, &binary[position..position + size]));
+            position += size;
+        }
+        assert_eq!(position, binary.len());
+        sections
+    }
+
+    #[test]
+    fn binaries_hold_each_section_once_in_order() {
+        let source = format!(
+            "\
+{}constant $2 = i8* \"hi\"
+global counter, size=$1
+global table, values=[`global_data_address([counter], [])]
+function void main() {{
+entry:
+    %r = invoke i64 `function_address([puts], []), [i8*, $2]
+    return
+}}
+",
+            LOOP
+        );
+        let binary = generate_binary(&parse_module(&source).unwrap()).unwrap();
+        let sections = sections(&binary);
+        let ids = sections.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        // Type, import, function, table, memory, global, export, element, code and data.
+        assert_eq!(ids, [1, 2, 3, 4, 5, 6, 7, 9, 10, 11]);
+        let section = |id: u8| {
+            sections
+                .iter()
+                .find(|(section, _)| *section == id)
+                .unwrap()
+                .1
+        };
+
+        let imports = section(2);
+        let mut position = 0;
+        assert_eq!(read_unsigned(imports, &mut position), 1);
+        assert_eq!(read_name(imports, &mut position), "env");
+        assert_eq!(read_name(imports, &mut position), "puts");
+
+        // The runtime's malloc, free and realloc come before the module's functions.
+        let functions = read_unsigned(section(3), &mut 0);
+        assert_eq!(functions, 3 + 3);
+        assert_eq!(read_unsigned(section(10), &mut 0), functions);
+
+        let exports = section(7);
+        let mut position = 0;
+        let count = read_unsigned(exports, &mut position);
+        let mut names = vec![];
+        for _ in 0..count {
+            names.push(read_name(exports, &mut position));
+            position += 1;
+            read_unsigned(exports, &mut position);
+        }
+        assert_eq!(names, ["memory", "sum", "twice", "main"]);
+        assert_eq!(position, exports.len());
+
+        // The string constant and the initialized global are one segment each; the global
+        // holds the address of the zeroed one in 8 bytes.
+        let data = section(11);
+        let mut position = 0;
+        assert_eq!(read_unsigned(data, &mut position), 2);
+        let mut segments = vec![];
+        for _ in 0..2 {
+            assert_eq!(&data[position..position + 2], [0x00, 0x41]);
+            position += 2;
+            let address = read_unsigned(data, &mut position);
+            assert_eq!(data[position], 0x0B);
+            position += 1;
+            let length = read_unsigned(data, &mut position) as usize;
+            segments.push((address, &data[position..position + length]));
+            position += length;
+        }
+        assert_eq!(position, data.len());
+        assert_eq!(segments[0].1, b"hi\0");
+        let table = segments[1].1;
+        assert_eq!(table.len(), 8);
+        let counter = u64::from_le_bytes(table.try_into().unwrap());
+        assert!(counter > segments[0].0 && counter < segments[1].0);
+    }
+
+    #[test]
+    fn text_matches_the_binary_it_describes() {
+        assert_eq!(
+            generate_text(&parse_module(LOOP).unwrap()).unwrap(),
+            "\
+(module
+  (type (;0;) (func (param i64) (result i64)))
+  (type (;1;) (func (param i64)))
+  (type (;2;) (func (param i64 i64) (result i64)))
+  (func (;0;) (type 0) (local i32 i32 i32 i32)
+    local.get 0
+    i32.wrap_i64
+    i32.const 7
+    i32.add
+    i32.const -8
+    i32.and
+    local.tee 1
+    i32.eqz
+    if
+      i32.const 8
+      local.set 1
+    end
+    global.get 2
+    local.set 3
+    block
+      loop
+        local.get 3
+        i32.eqz
+        br_if 1
+        local.get 3
+        i32.load
+        local.get 1
+        i32.ge_u
+        if
+          local.get 2
+          i32.eqz
+          if
+            local.get 3
+            i32.load offset=4
+            global.set 2
+          else
+            local.get 2
+            local.get 3
+            i32.load offset=4
+            i32.store offset=4
+          end
+          local.get 3
+          i32.const 8
+          i32.add
+          i64.extend_i32_u
+          return
+        end
+        local.get 3
+        local.set 2
+        local.get 3
+        i32.load offset=4
+        local.set 3
+        br 0
+      end
+    end
+    global.get 1
+    local.tee 3
+    i32.const 8
+    i32.add
+    local.get 1
+    i32.add
+    local.tee 4
+    memory.size
+    i32.const 16
+    i32.shl
+    i32.gt_u
+    if
+      local.get 4
+      memory.size
+      i32.const 16
+      i32.shl
+      i32.sub
+      i32.const 65535
+      i32.add
+      i32.const 16
+      i32.shr_u
+      memory.grow
+      i32.const -1
+      i32.eq
+      if
+        i64.const 0
+        return
+      end
+    end
+    local.get 3
+    local.get 1
+    i32.store
+    local.get 4
+    global.set 1
+    local.get 3
+    i32.const 8
+    i32.add
+    i64.extend_i32_u)
+  (func (;1;) (type 1) (local i32)
+    local.get 0
+    i64.eqz
+    if
+      return
+    end
+    local.get 0
+    i32.wrap_i64
+    i32.const 8
+    i32.sub
+    local.tee 1
+    global.get 2
+    i32.store offset=4
+    local.get 1
+    global.set 2)
+  (func (;2;) (type 2) (local i32 i64)
+    local.get 0
+    i64.eqz
+    if
+      local.get 1
+      call 0
+      return
+    end
+    local.get 0
+    i32.wrap_i64
+    i32.const 8
+    i32.sub
+    i32.load
+    local.tee 2
+    i64.extend_i32_u
+    local.get 1
+    i64.ge_u
+    if
+      local.get 0
+      return
+    end
+    local.get 1
+    call 0
+    local.tee 3
+    i64.eqz
+    if
+      i64.const 0
+      return
+    end
+    local.get 3
+    i32.wrap_i64
+    local.get 0
+    i32.wrap_i64
+    local.get 2
+    memory.copy
+    local.get 0
+    call 1
+    local.get 3)
+  (func (;3;) (type 0) (local i32 i32 i32 i64 i64 i64 i64 i64 i64 i64 i64 i64 i32 i32 i64 i64 f32 f32 f64 f64)
+    global.get 0
+    local.tee 1
+    i32.const 16
+    i32.sub
+    global.set 0
+    global.get 0
+    local.get 0
+    i64.store
+    local.get 1
+    i32.const 16
+    i32.sub
+    i64.extend_i32_u
+    local.set 4
+    local.get 4
+    i32.wrap_i64
+    i64.load
+    local.set 5
+    i64.const 0
+    local.set 11
+    i64.const 0
+    local.set 12
+    loop
+      local.get 11
+      local.set 6
+      local.get 12
+      local.set 7
+      local.get 7
+      local.set 15
+      local.get 6
+      local.set 16
+      local.get 15
+      local.get 16
+      i64.add
+      local.set 8
+      local.get 6
+      local.set 15
+      i64.const 1
+      local.set 16
+      local.get 15
+      local.get 16
+      i64.add
+      local.set 9
+      local.get 9
+      local.set 15
+      local.get 5
+      local.set 16
+      local.get 15
+      local.get 16
+      i64.le_s
+      if
+        local.get 9
+        local.set 11
+        local.get 8
+        local.set 12
+        br 1
+      else
+        local.get 8
+        call 4
+        local.set 10
+        local.get 10
+        local.get 1
+        global.set 0
+        return
+      end
+    end
+    unreachable)
+  (func (;4;) (type 0) (local i32 i32 i32 i64 i64 i64 i32 i32 i64 i64 f32 f32 f64 f64)
+    global.get 0
+    local.tee 1
+    i32.const 16
+    i32.sub
+    global.set 0
+    global.get 0
+    local.get 0
+    i64.store
+    local.get 1
+    i32.const 16
+    i32.sub
+    i64.extend_i32_u
+    local.set 4
+    local.get 4
+    i32.wrap_i64
+    i64.load
+    local.set 5
+    local.get 5
+    local.set 9
+    local.get 5
+    local.set 10
+    local.get 9
+    local.get 10
+    i64.add
+    local.set 6
+    local.get 6
+    local.get 1
+    global.set 0
+    return
+    unreachable)
+  (table (;0;) 6 funcref)
+  (memory (;0;) 17)
+  (global (;0;) (mut i32) (i32.const 1048592)) ;; stack pointer
+  (global (;1;) (mut i32) (i32.const 1048592)) ;; heap top
+  (global (;2;) (mut i32) (i32.const 0)) ;; free list
+  (export \"memory\" (memory 0))
+  (export \"sum\" (func 3))
+  (export \"twice\" (func 4))
+  (elem (;0;) (i32.const 1) func 0 1 2 3 4))
+"
+        );
+    }
+}
@@ -0,0 +1,1342 @@
+//! GNU as x86-64 assembly for the System V ABI.
+//!
+//! Code generation is deliberately simple: every virtual register and field lives in an 8-byte
+//! slot of the frame, and each instruction loads its operands into scratch registers (`%rax`,
+//! `%rcx`, `%rdx`, `%r10`, `%r11`), computes and stores the result back. Integers are kept in
+//! registers extended to 64 bits according to their signedness; floats and doubles are kept as
+//! their bit patterns and moved to `%xmm0`/`%xmm1` only to compute.
+
+use crate::backend::{
+    IRConstantData, IRDataItem, IRFrameLayout, IRGenerateError, IRGenerateResult, IROperandValue,
+    IRTarget, IRValueType, check_scalar_values, constant, global_data_layout, lower_for_target,
+    mangle_symbol, phi_moves,
+};
+use crate::ir::base::{IRCondition, IRControlFlowGraph, IRFunction, IRNode};
+use crate::ir::instruction::{
+    IRAsm, IRCalculate, IRCalculateOperator, IRConditionalJump, IRDecrease, IRFree, IRGet, IRGoto,
+    IRIncrease, IRInvoke, IRMalloc, IRNegate, IRNoOperate, IRNot, IRRealloc, IRReturn, IRSet,
+    IRSetVirtualRegister, IRStackAllocate, IRTypeCast, IRTypeCastKind,
+};
+use crate::ir::operand::{IROperand, IRVirtualRegister};
+use crate::ir::type_check::infer_register_types;
+use crate::ir::verify::IRLocation;
+use crate::ir::{IRModule, IRVisitor};
+use std::cell::{Cell, RefCell};
+use std::fmt::{self, Write};
+
+const GLOBAL_INIT_SYMBOL: &str = "__lg_global_init";
+const INTEGER_ARGUMENT_REGISTERS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+const FLOAT_ARGUMENT_REGISTERS: usize = 8;
+const SCRATCH_SLOTS: usize = 3;
+
+pub fn generate_assembly(ir_module: &IRModule) -> IRGenerateResult<String> {
+    let ir_module = &*lower_for_target(ir_module, IRTarget::X86_64)?;
+    check_scalar_values(ir_module)?;
+    let mut output = String::new();
+    emit_constants(ir_module, &mut output);
+    emit_global_data(ir_module, &mut output)?;
+    if !ir_module.global_init_section.basic_blocks.is_empty() {
+        emit_text_section(GLOBAL_INIT_SYMBOL, &mut output);
+        IRX86Emitter::new(ir_module, None).emit_function(GLOBAL_INIT_SYMBOL, &mut output)?;
+        output.push_str("\t.section .init_array,\"aw\"\n\t.balign 8\n");
+        let _ = writeln!(output, "\t.quad {}", GLOBAL_INIT_SYMBOL);
+    }
+    for ir_function in ir_module.functions.values() {
+        let symbol = mangle_symbol(&ir_function.name);
+        emit_text_section(&symbol, &mut output);
+        let _ = writeln!(output, "\t.globl {}", symbol);
+        IRX86Emitter::new(ir_module, Some(ir_function)).emit_function(&symbol, &mut output)?;
+    }
+    if let Some(entry_point) = ir_module.entry_point.as_ref()
+        && entry_point != "main"
+        && !ir_module.functions.contains_key("main")
+    {
+        emit_text_section("main", &mut output);
+        let _ = writeln!(
+            output,
+            "\t.globl main\n\t.type main, @function\nmain:\n\tjmp {}\n\t.size main, .-main",
+            mangle_symbol(entry_point)
+        );
+    }
+    output.push_str("\t.section .note.GNU-stack,\"\",@progbits\n");
+    Ok(output)
+}
+
+/// Switches to the section of one function, so each function is in a section of its own.
+pub(crate) fn emit_text_section(symbol: &str, output: &mut String) {
+    let _ = writeln!(output, "\t.section .text.{},\"ax\",@progbits", symbol);
+}
+
+pub(crate) fn constant_label(index: usize) -> String {
+    format!(".LC{}", index)
+}
+
+fn data_directive(size: u64) -> &'static str {
+    match size {
+        1 => ".byte",
+        2 => ".short",
+        4 => ".long",
+        _ => ".quad",
+    }
+}
+
+fn byte_list(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| byte.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+pub(crate) fn emit_constants(ir_module: &IRModule, output: &mut String) {
+    if ir_module.constant_pool.entries.is_empty() {
+        return;
+    }
+    output.push_str("\t.section .rodata\n");
+    for (index, entry) in ir_module.constant_pool.entries.iter().enumerate() {
+        let Some(data) = IRConstantData::of(entry) else {
+            continue;
+        };
+        output.push_str("\t.balign 8\n");
+        let _ = writeln!(output, "{}:", constant_label(index));
+        let _ = match data {
+            IRConstantData::String(mut bytes) => {
+                bytes.push(0);
+                writeln!(output, "\t.byte {}", byte_list(&bytes))
+            }
+            data => {
+                let size = IRValueType::of(entry._type.as_ref()).size();
+                writeln!(
+                    output,
+                    "\t{} {}",
+                    data_directive(size),
+                    data.bits().unwrap()
+                )
+            }
+        };
+    }
+}
+
+pub(crate) fn emit_global_data(ir_module: &IRModule, output: &mut String) -> IRGenerateResult<()> {
+    for ir_global_data in ir_module.global_data_section.data.iter() {
+        let layout = global_data_layout(ir_module, ir_global_data)?;
+        let symbol = mangle_symbol(&ir_global_data.name);
+        let section = if layout.initialized { ".data" } else { ".bss" };
+        let _ = writeln!(
+            output,
+            "\t{}\n\t.globl {}\n\t.type {}, @object\n\t.balign 8\n{}:",
+            section, symbol, symbol, symbol
+        );
+        for item in layout.items {
+            let _ = match item {
+                IRDataItem::Bytes(bytes) => writeln!(output, "\t.byte {}", byte_list(&bytes)),
+                IRDataItem::Symbol(name) => writeln!(output, "\t.quad {}", mangle_symbol(&name)),
+                IRDataItem::Constant(index) => {
+                    writeln!(output, "\t.quad {}", constant_label(index))
+                }
+                IRDataItem::Zero(size) => writeln!(output, "\t.zero {}", size),
+            };
+        }
+        if layout.size == 0 {
+            output.push_str("\t.zero 1\n");
+        }
+        let _ = writeln!(output, "\t.size {}, {}", symbol, layout.size.max(1));
+    }
+    Ok(())
+}
+
+/// The name of a 64-bit register when accessed with the given width in bytes.
+fn sized_register(register: &str, size: u64) -> String {
+    let legacy = match register {
+        "rax" => Some(("al", "ax", "eax")),
+        "rbx" => Some(("bl", "bx", "ebx")),
+        "rcx" => Some(("cl", "cx", "ecx")),
+        "rdx" => Some(("dl", "dx", "edx")),
+        "rsi" => Some(("sil", "si", "esi")),
+        "rdi" => Some(("dil", "di", "edi")),
+        _ => None,
+    };
+    match (legacy, size) {
+        (_, 8) => register.to_string(),
+        (Some((byte, _, _)), 1) => byte.to_string(),
+        (Some((_, word, _)), 2) => word.to_string(),
+        (Some((_, _, double)), 4) => double.to_string(),
+        (None, 1) => format!("{}b", register),
+        (None, 2) => format!("{}w", register),
+        (None, _) => format!("{}d", register),
+        (Some(_), _) => register.to_string(),
+    }
+}
+
+fn size_suffix(size: u64) -> char {
+    match size {
+        1 => 'b',
+        2 => 'w',
+        4 => 'l',
+        _ => 'q',
+    }
+}
+
+/// The register an `asm` resource name refers to: a GCC-style constraint letter or a register
+/// name.
+pub(crate) fn asm_register(name: &str) -> Option<&str> {
+    let name = name.trim_start_matches('%');
+    match name {
+        "a" => Some("rax"),
+        "b" => Some("rbx"),
+        "c" => Some("rcx"),
+        "d" => Some("rdx"),
+        "S" => Some("rsi"),
+        "D" => Some("rdi"),
+        "rax" | "rbx" | "rcx" | "rdx" | "rsi" | "rdi" | "r8" | "r9" | "r10" | "r11" | "r12"
+        | "r13" | "r14" | "r15" => Some(name),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IRArithmetic {
+    Binary(IRCalculateOperator),
+    Not,
+    Negate,
+}
+
+struct IRX86Emitter<'a> {
+    ir_module: &'a IRModule,
+    ir_function: Option<&'a IRFunction>,
+    ir_control_flow_graph: &'a IRControlFlowGraph,
+    frame: IRFrameLayout,
+    output: RefCell<String>,
+    location: RefCell<IRLocation>,
+    label_prefix: RefCell<String>,
+    labels: Cell<usize>,
+    terminated: Cell<bool>,
+    error: RefCell<Option<IRGenerateError>>,
+}
+
+impl<'a> IRX86Emitter<'a> {
+    /// Creates an emitter for a function, or for the global init section when `ir_function` is
+    /// `None`.
+    fn new(ir_module: &'a IRModule, ir_function: Option<&'a IRFunction>) -> Self {
+        let ir_control_flow_graph = match ir_function {
+            Some(ir_function) => &ir_function.control_flow_graph,
+            None => &ir_module.global_init_section,
+        };
+        let fields = ir_function
+            .iter()
+            .flat_map(|ir_function| ir_function.fields.iter())
+            .map(|field| (field.name.clone(), IRValueType::of(field._type.as_ref())))
+            .collect::<Vec<_>>();
+        let types = infer_register_types(ir_module, ir_function);
+        Self {
+            ir_module,
+            ir_function,
+            ir_control_flow_graph,
+            frame: IRFrameLayout::new(&fields, &types, ir_control_flow_graph, 0, SCRATCH_SLOTS),
+            output: RefCell::new(String::new()),
+            location: RefCell::new(IRLocation::Module),
+            label_prefix: RefCell::new(String::new()),
+            labels: Cell::new(0),
+            terminated: Cell::new(false),
+            error: RefCell::new(None),
+        }
+    }
+
+    fn emit(&self, args: fmt::Arguments) {
+        let mut output = self.output.borrow_mut();
+        output.push('\t');
+        let _ = output.write_fmt(args);
+        output.push('\n');
+    }
+
+    fn emit_label(&self, label: &str) {
+        let _ = writeln!(self.output.borrow_mut(), "{}:", label);
+    }
+
+    fn unsupported(&self, message: String) -> IRGenerateError {
+        IRGenerateError::Unsupported {
+            location: self.location.borrow().clone(),
+            message,
+        }
+    }
+
+    fn finish(&self, result: IRGenerateResult<()>) {
+        if let Err(error) = result {
+            self.error.borrow_mut().get_or_insert(error);
+        }
+    }
+
+    fn block_label(&self, name: &str) -> String {
+        format!("{}.{}", self.label_prefix.borrow(), mangle_symbol(name))
+    }
+
+    fn fresh_label(&self) -> String {
+        self.labels.set(self.labels.get() + 1);
+        format!("{}..{}", self.label_prefix.borrow(), self.labels.get())
+    }
+
+    fn emit_function(&self, symbol: &str, output: &mut String) -> IRGenerateResult<()> {
+        self.label_prefix.replace(format!(".L{}", symbol));
+        self.emit(format_args!(".type {}, @function", symbol));
+        self.emit_label(symbol);
+        self.emit(format_args!("pushq %rbp"));
+        self.emit(format_args!("movq %rsp, %rbp"));
+        if self.frame.size > 0 {
+            self.emit(format_args!("subq ${}, %rsp", self.frame.size));
+        }
+        self.emit_arguments();
+        for (position, (name, ir_basic_block)) in
+            self.ir_control_flow_graph.basic_blocks.iter().enumerate()
+        {
+            self.emit_label(&self.block_label(name));
+            self.terminated.set(false);
+            for (index, ir_instruction) in ir_basic_block.instructions.iter().enumerate() {
+                self.location.replace(IRLocation::Instruction {
+                    function: self.ir_function.map(|ir_function| ir_function.name.clone()),
+                    basic_block: name.clone(),
+                    index,
+                });
+                self.terminated.set(false);
+                ir_instruction.accept(self);
+            }
+            if !self.terminated.get() {
+                match self
+                    .ir_control_flow_graph
+                    .basic_blocks
+                    .get_index(position + 1)
+                {
+                    Some((next, _)) => {
+                        let result = self.emit_edge(name, next);
+                        self.finish(result);
+                    }
+                    None => {
+                        self.emit(format_args!("leave"));
+                        self.emit(format_args!("ret"));
+                    }
+                }
+            }
+        }
+        self.emit(format_args!(".size {}, .-{}", symbol, symbol));
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        output.push_str(&self.output.borrow());
+        Ok(())
+    }
+
+    /// Stores incoming arguments into the slots of the leading fields.
+    fn emit_arguments(&self) {
+        let mut integers = 0;
+        let mut floats = 0;
+        let mut stack = 0;
+        for (offset, value_type) in self.frame.fields.values().take(
+            self.ir_function
+                .map_or(0, |ir_function| ir_function.arguments_count),
+        ) {
+            if value_type.is_floating_point() && floats < FLOAT_ARGUMENT_REGISTERS {
+                self.emit(format_args!("movq %xmm{}, %rax", floats));
+                floats += 1;
+            } else if !value_type.is_floating_point() && integers < INTEGER_ARGUMENT_REGISTERS.len()
+            {
+                self.emit(format_args!(
+                    "movq %{}, %rax",
+                    INTEGER_ARGUMENT_REGISTERS[integers]
+                ));
+                integers += 1;
+            } else {
+                self.emit(format_args!("movq {}(%rbp), %rax", 16 + stack * 8));
+                stack += 1;
+            }
+            self.store(*value_type, &format!("{}(%rbp)", offset));
+        }
+    }
+
+    /// Loads a value of the given type from memory into `%rax`, extending it to 64 bits.
+    fn load(&self, value_type: IRValueType, memory: &str) {
+        let instruction = match value_type {
+            IRValueType::Integer { bits: 1, .. }
+            | IRValueType::Integer {
+                bits: 8,
+                unsigned: true,
+            } => "movzbq",
+            IRValueType::Integer { bits: 8, .. } => "movsbq",
+            IRValueType::Integer {
+                bits: 16,
+                unsigned: true,
+            } => "movzwq",
+            IRValueType::Integer { bits: 16, .. } => "movswq",
+            IRValueType::Integer {
+                bits: 32,
+                unsigned: false,
+            } => "movslq",
+            IRValueType::Integer { bits: 32, .. } | IRValueType::Float => {
+                self.emit(format_args!("movl {}, %eax", memory));
+                return;
+            }
+            _ => "movq",
+        };
+        self.emit(format_args!("{} {}, %rax", instruction, memory));
+    }
+
+    /// Stores `%rax` to memory with the width of the given type.
+    fn store(&self, value_type: IRValueType, memory: &str) {
+        let size = value_type.size();
+        if size == 0 {
+            return;
+        }
+        if let IRValueType::Integer { bits: 1, .. } = value_type {
+            self.emit(format_args!("andl $1, %eax"));
+        }
+        self.emit(format_args!(
+            "mov{} %{}, {}",
+            size_suffix(size),
+            sized_register("rax", size),
+            memory
+        ));
+    }
+
+    /// Re-extends the low bits of `%rax` according to the type.
+    fn extend(&self, value_type: IRValueType) {
+        match value_type {
+            IRValueType::Integer { bits: 1, .. } => self.emit(format_args!("andl $1, %eax")),
+            IRValueType::Integer { bits: 8, unsigned } => self.emit(format_args!(
+                "{} %al, %rax",
+                if unsigned { "movzbq" } else { "movsbq" }
+            )),
+            IRValueType::Integer { bits: 16, unsigned } => self.emit(format_args!(
+                "{} %ax, %rax",
+                if unsigned { "movzwq" } else { "movswq" }
+            )),
+            IRValueType::Integer {
+                bits: 32,
+                unsigned: true,
+            }
+            | IRValueType::Float => self.emit(format_args!("movl %eax, %eax")),
+            IRValueType::Integer {
+                bits: 32,
+                unsigned: false,
+            } => self.emit(format_args!("movslq %eax, %rax")),
+            _ => {}
+        }
+    }
+
+    fn zero_extend(&self, bits: u32) {
+        self.extend(IRValueType::Integer {
+            bits,
+            unsigned: true,
+        });
+    }
+
+    fn sign_extend(&self, bits: u32) {
+        self.extend(IRValueType::Integer {
+            bits,
+            unsigned: false,
+        });
+    }
+
+    fn slot(&self, name: &str) -> IRGenerateResult<(i64, IRValueType)> {
+        self.frame
+            .register(name)
+            .ok_or_else(|| self.unsupported(format!("register %{} without a known type", name)))
+    }
+
+    fn store_register(&self, target: &IRVirtualRegister) -> IRGenerateResult<()> {
+        let (offset, value_type) = self.slot(&target.name)?;
+        self.store(value_type, &format!("{}(%rbp)", offset));
+        Ok(())
+    }
+
+    fn symbol_address(&self, name: &str, defined: bool) {
+        if defined {
+            self.emit(format_args!("leaq {}(%rip), %rax", mangle_symbol(name)));
+        } else {
+            self.emit(format_args!(
+                "movq {}@GOTPCREL(%rip), %rax",
+                mangle_symbol(name)
+            ));
+        }
+    }
+
+    /// Loads an operand into `%rax`; no other register is touched.
+    fn load_operand(&self, operand: &dyn IROperand) -> IRGenerateResult<()> {
+        match IROperandValue::of(operand) {
+            IROperandValue::Register(name) => {
+                let (offset, value_type) = self.slot(&name)?;
+                self.load(value_type, &format!("{}(%rbp)", offset));
+            }
+            IROperandValue::Constant(index) => {
+                let (entry, data) = constant(self.ir_module, index)
+                    .ok_or_else(|| self.unsupported(format!("constant ${}", index)))?;
+                let label = constant_label(index as usize);
+                match data {
+                    IRConstantData::String(_) => {
+                        self.emit(format_args!("leaq {}(%rip), %rax", label))
+                    }
+                    _ => self.load(
+                        IRValueType::of(entry._type.as_ref()),
+                        &format!("{}(%rip)", label),
+                    ),
+                }
+            }
+            IROperandValue::FieldAddress(name) => {
+                let (offset, _) = self
+                    .frame
+                    .fields
+                    .get(&name)
+                    .ok_or_else(|| self.unsupported(format!("field '{}'", name)))?;
+                self.emit(format_args!("leaq {}(%rbp), %rax", offset));
+            }
+            IROperandValue::GlobalDataAddress(name) => {
+                let defined = self
+                    .ir_module
+                    .global_data_section
+                    .data
+                    .iter()
+                    .any(|data| data.name == name);
+                self.symbol_address(&name, defined);
+            }
+            IROperandValue::FunctionAddress(name) => {
+                self.symbol_address(&name, self.ir_module.functions.contains_key(&name));
+            }
+            _ => return Err(self.unsupported(format!("operand {}", operand))),
+        }
+        Ok(())
+    }
+
+    /// Loads `value` into `%rcx` and `operand` into `%rax`.
+    fn load_pair(&self, operand: &dyn IROperand, value: &dyn IROperand) -> IRGenerateResult<()> {
+        self.load_operand(value)?;
+        self.emit(format_args!("movq %rax, %rcx"));
+        self.load_operand(operand)
+    }
+
+    /// Copies the phi inputs of block `to` for the edge leaving block `from`.
+    fn emit_edge(&self, from: &str, to: &str) -> IRGenerateResult<()> {
+        for phi_move in phi_moves(self.ir_control_flow_graph, from, to) {
+            self.load_operand(phi_move.operand.as_ref())?;
+            self.store(
+                phi_move.value_type,
+                &format!("{}(%rbp)", self.frame.phi_shadows[&phi_move.target]),
+            );
+        }
+        Ok(())
+    }
+
+    fn current_block(&self) -> String {
+        match &*self.location.borrow() {
+            IRLocation::Instruction { basic_block, .. } => basic_block.clone(),
+            _ => String::new(),
+        }
+    }
+
+    fn emit_jump(&self, target: &str) -> IRGenerateResult<()> {
+        if !self.ir_control_flow_graph.basic_blocks.contains_key(target) {
+            return Err(self.unsupported(format!("jump to missing block '{}'", target)));
+        }
+        self.emit_edge(&self.current_block(), target)?;
+        self.emit(format_args!("jmp {}", self.block_label(target)));
+        Ok(())
+    }
+
+    fn move_to_xmm(&self, value_type: IRValueType, register: &str, xmm: u32) {
+        match value_type {
+            IRValueType::Float => self.emit(format_args!(
+                "movd %{}, %xmm{}",
+                sized_register(register, 4),
+                xmm
+            )),
+            _ => self.emit(format_args!("movq %{}, %xmm{}", register, xmm)),
+        }
+    }
+
+    fn move_from_xmm(&self, value_type: IRValueType) {
+        match value_type {
+            IRValueType::Float => self.emit(format_args!("movd %xmm0, %eax")),
+            _ => self.emit(format_args!("movq %xmm0, %rax")),
+        }
+    }
+
+    /// Computes `%rax op %rcx` into `%rax`. Clobbers `%rdx`, `%xmm0`, `%xmm1` and, for floating
+    /// point remainders, every caller-saved register.
+    fn compute(&self, arithmetic: IRArithmetic, value_type: IRValueType) -> IRGenerateResult<()> {
+        use IRCalculateOperator::*;
+        match (value_type, arithmetic) {
+            (IRValueType::Integer { bits, unsigned }, IRArithmetic::Binary(operator)) => {
+                match operator {
+                    ADD => self.emit(format_args!("addq %rcx, %rax")),
+                    SUB => self.emit(format_args!("subq %rcx, %rax")),
+                    MUL => self.emit(format_args!("imulq %rcx, %rax")),
+                    AND => self.emit(format_args!("andq %rcx, %rax")),
+                    OR => self.emit(format_args!("orq %rcx, %rax")),
+                    XOR => self.emit(format_args!("xorq %rcx, %rax")),
+                    DIV | MOD => {
+                        if unsigned {
+                            self.emit(format_args!("xorl %edx, %edx"));
+                            self.emit(format_args!("divq %rcx"));
+                        } else {
+                            self.emit(format_args!("cqto"));
+                            self.emit(format_args!("idivq %rcx"));
+                        }
+                        if operator == MOD {
+                            self.emit(format_args!("movq %rdx, %rax"));
+                        }
+                    }
+                    SHL => self.emit(format_args!("shlq %cl, %rax")),
+                    SHR if !unsigned => self.emit(format_args!("sarq %cl, %rax")),
+                    SHR | USHR => {
+                        self.zero_extend(bits);
+                        self.emit(format_args!("shrq %cl, %rax"));
+                    }
+                }
+                self.extend(value_type);
+            }
+            (IRValueType::Integer { .. }, IRArithmetic::Not) => {
+                self.emit(format_args!("notq %rax"));
+                self.extend(value_type);
+            }
+            (IRValueType::Integer { .. }, IRArithmetic::Negate) => {
+                self.emit(format_args!("negq %rax"));
+                self.extend(value_type);
+            }
+            (IRValueType::Float, IRArithmetic::Negate) => {
+                self.emit(format_args!("xorl $0x80000000, %eax"))
+            }
+            (IRValueType::Double, IRArithmetic::Negate) => {
+                self.emit(format_args!("btcq $63, %rax"))
+            }
+            (IRValueType::Float | IRValueType::Double, IRArithmetic::Binary(operator)) => {
+                let suffix = if value_type == IRValueType::Float {
+                    "ss"
+                } else {
+                    "sd"
+                };
+                self.move_to_xmm(value_type, "rax", 0);
+                self.move_to_xmm(value_type, "rcx", 1);
+                match operator {
+                    ADD => self.emit(format_args!("add{} %xmm1, %xmm0", suffix)),
+                    SUB => self.emit(format_args!("sub{} %xmm1, %xmm0", suffix)),
+                    MUL => self.emit(format_args!("mul{} %xmm1, %xmm0", suffix)),
+                    DIV => self.emit(format_args!("div{} %xmm1, %xmm0", suffix)),
+                    MOD => {
+                        let function = if value_type == IRValueType::Float {
+                            "fmodf"
+                        } else {
+                            "fmod"
+                        };
+                        self.emit(format_args!("call {}@PLT", function));
+                    }
+                    _ => {
+                        return Err(self.unsupported(format!("{} on floating point", operator)));
+                    }
+                }
+                self.move_from_xmm(value_type);
+            }
+            (value_type, arithmetic) => {
+                return Err(self.unsupported(format!("{:?} on {:?}", arithmetic, value_type)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Atomically replaces the value at `address` with `value op rhs` through a compare-exchange
+    /// loop, leaving the new value in `%rax`.
+    fn emit_atomic_update(
+        &self,
+        arithmetic: IRArithmetic,
+        value_type: IRValueType,
+        address: &dyn IROperand,
+        rhs: Option<&dyn IROperand>,
+        one: bool,
+    ) -> IRGenerateResult<()> {
+        let [address_slot, rhs_slot, expected_slot] = self.frame.scratch[..] else {
+            unreachable!()
+        };
+        let size = value_type.size();
+        self.load_operand(address)?;
+        self.emit(format_args!("movq %rax, {}(%rbp)", address_slot));
+        if let Some(rhs) = rhs {
+            self.load_operand(rhs)?;
+            self.emit(format_args!("movq %rax, {}(%rbp)", rhs_slot));
+        } else if one {
+            self.load_one(value_type, "rcx");
+            self.emit(format_args!("movq %rcx, {}(%rbp)", rhs_slot));
+        }
+        self.emit(format_args!("movq {}(%rbp), %r11", address_slot));
+        self.load(value_type, "(%r11)");
+        self.emit_label("1");
+        self.extend(value_type);
+        self.emit(format_args!("movq %rax, {}(%rbp)", expected_slot));
+        self.emit(format_args!("movq {}(%rbp), %rcx", rhs_slot));
+        self.compute(arithmetic, value_type)?;
+        self.emit(format_args!("movq %rax, %r10"));
+        self.emit(format_args!("movq {}(%rbp), %rax", expected_slot));
+        self.emit(format_args!("movq {}(%rbp), %r11", address_slot));
+        self.emit(format_args!(
+            "lock cmpxchg{} %{}, (%r11)",
+            size_suffix(size),
+            sized_register("r10", size)
+        ));
+        self.emit(format_args!("jne 1b"));
+        self.emit(format_args!("movq %r10, %rax"));
+        Ok(())
+    }
+
+    fn load_one(&self, value_type: IRValueType, register: &str) {
+        match value_type {
+            IRValueType::Float => self.emit(format_args!(
+                "movl ${:#x}, %{}",
+                1f32.to_bits(),
+                sized_register(register, 4)
+            )),
+            IRValueType::Double => self.emit(format_args!(
+                "movabsq ${:#x}, %{}",
+                1f64.to_bits(),
+                register
+            )),
+            _ => self.emit(format_args!("movl $1, %{}", sized_register(register, 4))),
+        }
+    }
+
+    fn emit_unary(
+        &self,
+        arithmetic: IRArithmetic,
+        is_atomic: bool,
+        value_type: IRValueType,
+        operand: &dyn IROperand,
+        target: &IRVirtualRegister,
+    ) -> IRGenerateResult<()> {
+        if is_atomic {
+            self.emit_atomic_update(arithmetic, value_type, operand, None, false)?;
+        } else {
+            self.load_operand(operand)?;
+            self.compute(arithmetic, value_type)?;
+        }
+        self.store_register(target)
+    }
+
+    fn emit_step(
+        &self,
+        operator: IRCalculateOperator,
+        value_type: IRValueType,
+        operand: &dyn IROperand,
+        target: Option<&IRVirtualRegister>,
+    ) -> IRGenerateResult<()> {
+        let arithmetic = IRArithmetic::Binary(operator);
+        match (target, value_type) {
+            (Some(target), _) => {
+                self.load_operand(operand)?;
+                self.load_one(value_type, "rcx");
+                self.compute(arithmetic, value_type)?;
+                self.store_register(target)
+            }
+            (None, IRValueType::Integer { .. }) => {
+                let size = value_type.size();
+                self.load_operand(operand)?;
+                let mnemonic = if operator == IRCalculateOperator::ADD {
+                    "add"
+                } else {
+                    "sub"
+                };
+                self.emit(format_args!(
+                    "lock {}{} $1, (%rax)",
+                    mnemonic,
+                    size_suffix(size)
+                ));
+                Ok(())
+            }
+            (None, _) => self.emit_atomic_update(arithmetic, value_type, operand, None, true),
+        }
+    }
+
+    /// Jumps to `label` if `%rax cond %rcx` holds.
+    fn emit_branch(&self, condition: IRCondition, value_type: IRValueType, label: &str) {
+        if value_type.is_floating_point() {
+            let suffix = if value_type == IRValueType::Float {
+                "ss"
+            } else {
+                "sd"
+            };
+            self.move_to_xmm(value_type, "rax", 0);
+            match condition {
+                IRCondition::IfTrue | IRCondition::IfFalse => {
+                    self.emit(format_args!("xorps %xmm1, %xmm1"))
+                }
+                _ => self.move_to_xmm(value_type, "rcx", 1),
+            }
+            let swapped = matches!(condition, IRCondition::Less | IRCondition::LessEqual);
+            if swapped {
+                self.emit(format_args!("ucomi{} %xmm0, %xmm1", suffix));
+            } else {
+                self.emit(format_args!("ucomi{} %xmm1, %xmm0", suffix));
+            }
+            match condition {
+                IRCondition::Less | IRCondition::Greater => self.emit(format_args!("ja {}", label)),
+                IRCondition::LessEqual | IRCondition::GreaterEqual => {
+                    self.emit(format_args!("jae {}", label))
+                }
+                IRCondition::Equal | IRCondition::IfFalse => {
+                    self.emit(format_args!("jp 2f"));
+                    self.emit(format_args!("je {}", label));
+                    self.emit_label("2");
+                }
+                IRCondition::NotEqual | IRCondition::IfTrue => {
+                    self.emit(format_args!("jp {}", label));
+                    self.emit(format_args!("jne {}", label));
+                }
+            }
+            return;
+        }
+        let code = match condition {
+            IRCondition::IfTrue | IRCondition::IfFalse => {
+                self.emit(format_args!("testq %rax, %rax"));
+                if condition == IRCondition::IfTrue {
+                    "ne"
+                } else {
+                    "e"
+                }
+            }
+            condition => {
+                self.emit(format_args!("cmpq %rcx, %rax"));
+                match (condition, value_type.is_unsigned()) {
+                    (IRCondition::Equal, _) => "e",
+                    (IRCondition::NotEqual, _) => "ne",
+                    (IRCondition::Less, false) => "l",
+                    (IRCondition::LessEqual, false) => "le",
+                    (IRCondition::Greater, false) => "g",
+                    (IRCondition::GreaterEqual, false) => "ge",
+                    (IRCondition::Less, true) => "b",
+                    (IRCondition::LessEqual, true) => "be",
+                    (IRCondition::Greater, true) => "a",
+                    _ => "ae",
+                }
+            }
+        };
+        self.emit(format_args!("j{} {}", code, label));
+    }
+
+    fn emit_conditional_jump(
+        &self,
+        ir_conditional_jump: &IRConditionalJump,
+    ) -> IRGenerateResult<()> {
+        let value_type = IRValueType::of(ir_conditional_jump._type.as_ref());
+        if let Some(operand2) = ir_conditional_jump.operand2.as_ref() {
+            self.load_operand(operand2.as_ref())?;
+            self.emit(format_args!("movq %rax, %rcx"));
+        }
+        self.load_operand(ir_conditional_jump.operand1.as_ref())?;
+        if ir_conditional_jump.is_atomic {
+            self.emit(format_args!("movq %rax, %r11"));
+            self.load(value_type, "(%r11)");
+        }
+        let target = &ir_conditional_jump.target;
+        if !self.ir_control_flow_graph.basic_blocks.contains_key(target) {
+            return Err(self.unsupported(format!("jump to missing block '{}'", target)));
+        }
+        if phi_moves(self.ir_control_flow_graph, &self.current_block(), target).is_empty() {
+            self.emit_branch(
+                ir_conditional_jump.condition,
+                value_type,
+                &self.block_label(target),
+            );
+            return Ok(());
+        }
+        let taken = self.fresh_label();
+        let skipped = self.fresh_label();
+        self.emit_branch(ir_conditional_jump.condition, value_type, &taken);
+        self.emit(format_args!("jmp {}", skipped));
+        self.emit_label(&taken);
+        self.emit_jump(target)?;
+        self.emit_label(&skipped);
+        Ok(())
+    }
+
+    fn emit_invoke(&self, ir_invoke: &IRInvoke) -> IRGenerateResult<()> {
+        if ir_invoke.argument_types.len() != ir_invoke.arguments.len() {
+            return Err(self.unsupported("invoke with mismatched argument types".to_string()));
+        }
+        let mut registers = vec![];
+        let mut stack = vec![];
+        let mut floats = 0;
+        let mut integers = 0;
+        for (argument_type, argument) in ir_invoke
+            .argument_types
+            .iter()
+            .zip(ir_invoke.arguments.iter())
+        {
+            let value_type = IRValueType::of(argument_type.as_ref());
+            if value_type.is_floating_point() && floats < FLOAT_ARGUMENT_REGISTERS {
+                registers.push((format!("xmm{}", floats), argument));
+                floats += 1;
+            } else if !value_type.is_floating_point() && integers < INTEGER_ARGUMENT_REGISTERS.len()
+            {
+                registers.push((INTEGER_ARGUMENT_REGISTERS[integers].to_string(), argument));
+                integers += 1;
+            } else {
+                stack.push(argument);
+            }
+        }
+        let stack_size = (stack.len() as i64 * 8 + 15) / 16 * 16;
+        if stack_size > 0 {
+            self.emit(format_args!("subq ${}, %rsp", stack_size));
+        }
+        for (index, argument) in stack.iter().enumerate() {
+            self.load_operand(argument.as_ref())?;
+            self.emit(format_args!("movq %rax, {}(%rsp)", index * 8));
+        }
+        for (register, argument) in registers.iter() {
+            self.load_operand(argument.as_ref())?;
+            self.emit(format_args!("movq %rax, %{}", register));
+        }
+        let callee = match IROperandValue::of(ir_invoke.address.as_ref()) {
+            IROperandValue::FunctionAddress(name)
+                if self.ir_module.functions.contains_key(&name) =>
+            {
+                mangle_symbol(&name)
+            }
+            IROperandValue::FunctionAddress(name) => format!("{}@PLT", mangle_symbol(&name)),
+            _ => {
+                self.load_operand(ir_invoke.address.as_ref())?;
+                self.emit(format_args!("movq %rax, %r11"));
+                "*%r11".to_string()
+            }
+        };
+        self.emit(format_args!("movl ${}, %eax", floats));
+        self.emit(format_args!("call {}", callee));
+        if stack_size > 0 {
+            self.emit(format_args!("addq ${}, %rsp", stack_size));
+        }
+        if let Some(target) = ir_invoke.target.as_ref() {
+            let return_type = IRValueType::of(ir_invoke.return_type.as_ref());
+            if return_type.is_floating_point() {
+                self.move_from_xmm(return_type);
+            }
+            self.store_register(target)?;
+        }
+        Ok(())
+    }
+
+    fn emit_type_cast(&self, ir_type_cast: &IRTypeCast) -> IRGenerateResult<()> {
+        let from = IRValueType::of(ir_type_cast.original_type.as_ref());
+        let to = IRValueType::of(ir_type_cast.target_type.as_ref());
+        let suffix = |value_type| {
+            if value_type == IRValueType::Float {
+                "ss"
+            } else {
+                "sd"
+            }
+        };
+        self.load_operand(ir_type_cast.source.as_ref())?;
+        self.extend(from);
+        let bits = match from {
+            IRValueType::Integer { bits, .. } => bits,
+            _ => 64,
+        };
+        match ir_type_cast.kind {
+            IRTypeCastKind::ZeroExtend => self.zero_extend(bits),
+            IRTypeCastKind::SignExtend => self.sign_extend(bits),
+            IRTypeCastKind::Truncate => {}
+            IRTypeCastKind::IntToFloat if from.is_unsigned() && bits == 64 => {
+                let large = self.fresh_label();
+                let done = self.fresh_label();
+                self.emit(format_args!("testq %rax, %rax"));
+                self.emit(format_args!("js {}", large));
+                self.emit(format_args!("cvtsi2{}q %rax, %xmm0", suffix(to)));
+                self.emit(format_args!("jmp {}", done));
+                self.emit_label(&large);
+                self.emit(format_args!("movq %rax, %rcx"));
+                self.emit(format_args!("shrq %rcx"));
+                self.emit(format_args!("andl $1, %eax"));
+                self.emit(format_args!("orq %rax, %rcx"));
+                self.emit(format_args!("cvtsi2{}q %rcx, %xmm0", suffix(to)));
+                self.emit(format_args!("add{} %xmm0, %xmm0", suffix(to)));
+                self.emit_label(&done);
+                self.move_from_xmm(to);
+            }
+            IRTypeCastKind::IntToFloat => {
+                self.emit(format_args!("cvtsi2{}q %rax, %xmm0", suffix(to)));
+                self.move_from_xmm(to);
+            }
+            IRTypeCastKind::FloatToInt => {
+                self.move_to_xmm(from, "rax", 0);
+                self.emit(format_args!("cvtt{}2siq %xmm0, %rax", suffix(from)));
+            }
+            IRTypeCastKind::FloatExtend | IRTypeCastKind::FloatTruncate => {
+                if from != to {
+                    self.move_to_xmm(from, "rax", 0);
+                    self.emit(format_args!(
+                        "cvt{}2{} %xmm0, %xmm0",
+                        suffix(from),
+                        suffix(to)
+                    ));
+                    self.move_from_xmm(to);
+                }
+            }
+        }
+        self.store_register(&ir_type_cast.target)
+    }
+
+    fn emit_asm(&self, ir_asm: &IRAsm) -> IRGenerateResult<()> {
+        let mut bindings = vec![];
+        for (resource, name) in ir_asm.resources.iter().zip(ir_asm.names.iter()) {
+            let register = asm_register(name)
+                .ok_or_else(|| self.unsupported(format!("asm register '{}'", name)))?;
+            bindings.push((register, resource));
+        }
+        // %rax is the scratch register for loading, so it is bound last.
+        bindings.sort_by_key(|(register, _)| *register == "rax");
+        let saved = bindings
+            .iter()
+            .map(|(register, _)| *register)
+            .filter(|register| matches!(*register, "rbx" | "r12" | "r13" | "r14" | "r15"))
+            .collect::<Vec<_>>();
+        for register in saved.iter() {
+            self.emit(format_args!("pushq %{}", register));
+        }
+        for (register, resource) in bindings.iter() {
+            self.load_operand(resource.as_ref())?;
+            if *register != "rax" {
+                self.emit(format_args!("movq %rax, %{}", register));
+            }
+        }
+        // Front ends write multi-line code with `\n` escapes as often as with real newlines.
+        for line in ir_asm.code.replace("\\n", "\n").lines() {
+            self.emit(format_args!("{}", line.trim()));
+        }
+        for register in saved.iter().rev() {
+            self.emit(format_args!("popq %{}", register));
+        }
+        Ok(())
+    }
+}
+
+impl IRVisitor for IRX86Emitter<'_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_goto(&self, ir_goto: &IRGoto) {
+        self.terminated.set(true);
+        self.finish(self.emit_jump(&ir_goto.target));
+    }
+    fn visit_conditional_jump(&self, ir_conditional_jump: &IRConditionalJump) {
+        self.finish(self.emit_conditional_jump(ir_conditional_jump));
+    }
+    fn visit_return(&self, ir_return: &IRReturn) {
+        self.terminated.set(true);
+        if let Some(operand) = ir_return.operand.as_ref() {
+            self.finish(self.load_operand(operand.as_ref()));
+            let return_type = self.ir_function.map_or(IRValueType::Void, |ir_function| {
+                IRValueType::of(ir_function.return_type.as_ref())
+            });
+            if return_type.is_floating_point() {
+                self.move_to_xmm(return_type, "rax", 0);
+            }
+        }
+        self.emit(format_args!("leave"));
+        self.emit(format_args!("ret"));
+    }
+    fn visit_calculate(&self, ir_calculate: &IRCalculate) {
+        let value_type = IRValueType::of(ir_calculate._type.as_ref());
+        let arithmetic = IRArithmetic::Binary(ir_calculate.operator);
+        let result = (|| {
+            if ir_calculate.is_atomic {
+                self.emit_atomic_update(
+                    arithmetic,
+                    value_type,
+                    ir_calculate.operand1.as_ref(),
+                    Some(ir_calculate.operand2.as_ref()),
+                    false,
+                )?;
+            } else {
+                self.load_pair(
+                    ir_calculate.operand1.as_ref(),
+                    ir_calculate.operand2.as_ref(),
+                )?;
+                self.compute(arithmetic, value_type)?;
+            }
+            self.store_register(&ir_calculate.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_not(&self, ir_not: &IRNot) {
+        self.finish(self.emit_unary(
+            IRArithmetic::Not,
+            ir_not.is_atomic,
+            IRValueType::of(ir_not._type.as_ref()),
+            ir_not.operand.as_ref(),
+            &ir_not.target,
+        ));
+    }
+    fn visit_negate(&self, ir_negate: &IRNegate) {
+        self.finish(self.emit_unary(
+            IRArithmetic::Negate,
+            ir_negate.is_atomic,
+            IRValueType::of(ir_negate._type.as_ref()),
+            ir_negate.operand.as_ref(),
+            &ir_negate.target,
+        ));
+    }
+    fn visit_malloc(&self, ir_malloc: &IRMalloc) {
+        let result = (|| {
+            self.load_operand(ir_malloc.size.as_ref())?;
+            self.emit(format_args!("movq %rax, %rdi"));
+            self.emit(format_args!("call malloc@PLT"));
+            self.store_register(&ir_malloc.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_free(&self, ir_free: &IRFree) {
+        let result = self.load_operand(ir_free.ptr.as_ref());
+        self.emit(format_args!("movq %rax, %rdi"));
+        self.emit(format_args!("call free@PLT"));
+        self.finish(result);
+    }
+    fn visit_realloc(&self, ir_realloc: &IRRealloc) {
+        let result = (|| {
+            self.load_operand(ir_realloc.ptr.as_ref())?;
+            self.emit(format_args!("movq %rax, %rdi"));
+            self.load_operand(ir_realloc.size.as_ref())?;
+            self.emit(format_args!("movq %rax, %rsi"));
+            self.emit(format_args!("call realloc@PLT"));
+            self.store_register(&ir_realloc.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_get(&self, ir_get: &IRGet) {
+        let result = (|| {
+            self.load_operand(ir_get.address.as_ref())?;
+            self.emit(format_args!("movq %rax, %r11"));
+            self.load(IRValueType::of(ir_get._type.as_ref()), "(%r11)");
+            self.store_register(&ir_get.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_set(&self, ir_set: &IRSet) {
+        let result = (|| {
+            self.load_operand(ir_set.address.as_ref())?;
+            self.emit(format_args!("movq %rax, %r11"));
+            self.load_operand(ir_set.value.as_ref())?;
+            self.store(IRValueType::of(ir_set._type.as_ref()), "(%r11)");
+            Ok(())
+        })();
+        self.finish(result);
+    }
+    fn visit_set_virtual_register(&self, ir_set_virtual_register: &IRSetVirtualRegister) {
+        let target = &ir_set_virtual_register.target;
+        let result = (|| {
+            match IROperandValue::of(ir_set_virtual_register.source.as_ref()) {
+                IROperandValue::Phi(ir_phi) => self.load(
+                    IRValueType::of(ir_phi._type.as_ref()),
+                    &format!("{}(%rbp)", self.frame.phi_shadows[&target.name]),
+                ),
+                _ => self.load_operand(ir_set_virtual_register.source.as_ref())?,
+            }
+            self.store_register(target)
+        })();
+        self.finish(result);
+    }
+    fn visit_invoke(&self, ir_invoke: &IRInvoke) {
+        self.finish(self.emit_invoke(ir_invoke));
+    }
+    fn visit_no_operate(&self, _ir_no_operate: &IRNoOperate) {
+        self.emit(format_args!("nop"));
+    }
+    fn visit_increase(&self, ir_increase: &IRIncrease) {
+        self.finish(self.emit_step(
+            IRCalculateOperator::ADD,
+            IRValueType::of(ir_increase._type.as_ref()),
+            ir_increase.operand.as_ref(),
+            ir_increase.target.as_deref(),
+        ));
+    }
+    fn visit_decrease(&self, ir_decrease: &IRDecrease) {
+        self.finish(self.emit_step(
+            IRCalculateOperator::SUB,
+            IRValueType::of(ir_decrease._type.as_ref()),
+            ir_decrease.operand.as_ref(),
+            ir_decrease.target.as_deref(),
+        ));
+    }
+    fn visit_stack_allocate(&self, ir_stack_allocate: &IRStackAllocate) {
+        let result = (|| {
+            self.load_operand(ir_stack_allocate.size.as_ref())?;
+            self.emit(format_args!("addq $15, %rax"));
+            self.emit(format_args!("andq $-16, %rax"));
+            self.emit(format_args!("subq %rax, %rsp"));
+            self.emit(format_args!("movq %rsp, %rax"));
+            self.store_register(&ir_stack_allocate.target)
+        })();
+        self.finish(result);
+    }
+    fn visit_type_cast(&self, ir_type_cast: &IRTypeCast) {
+        self.finish(self.emit_type_cast(ir_type_cast));
+    }
+    fn visit_asm(&self, ir_asm: &IRAsm) {
+        self.finish(self.emit_asm(ir_asm));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parser::parse_module;
+
+    const LOOP: &str = "\
+constant $0 = i64 0
+constant $1 = i64 1
+function i64 add(i64 a, i64 b) {
+entry:
+    %pa = `field_address([a], [])
+    %a = get i64, %pa
+    %pb = `field_address([b], [])
+    %b = get i64, %pb
+    %r = add i64 %a, %b
+    return %r
+}
+function i64 sum(i64 n) {
+entry:
+    %pn = `field_address([n], [])
+    %n = get i64, %pn
+    goto loop
+loop:
+    %i = phi i64 [entry, $0], [loop, %next_i]
+    %total = phi i64 [entry, $0], [loop, %next_total]
+    %next_total = invoke i64 `function_address([add], []), [i64, %total], [i64, %i]
+    %next_i = add i64 %i, $1
+    conditional_jump i64 l, %next_i, %n, #loop
+done:
+    return %next_total
+}
+";
+
+    fn assembly(source: &str) -> String {
+        generate_assembly(&parse_module(source).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn functions_set_up_a_frame_and_spill_their_arguments() {
+        let assembly = assembly(LOOP);
+        assert!(assembly.contains(
+            "\
+\t.section .text.add,\"ax\",@progbits
+\t.globl add
+\t.type add, @function
+add:
+\tpushq %rbp
+\tmovq %rsp, %rbp
+\tsubq $80, %rsp
+\tmovq %rdi, %rax
+\tmovq %rax, -8(%rbp)
+\tmovq %rsi, %rax
+\tmovq %rax, -16(%rbp)
+.Ladd.entry:
+"
+        ));
+        assert!(assembly.contains(
+            "\
+\tmovq -56(%rbp), %rax
+\tleave
+\tret
+\t.size add, .-add
+"
+        ));
+        assert!(assembly.ends_with("\t.section .note.GNU-stack,\"\",@progbits\n"));
+    }
+
+    #[test]
+    fn phis_are_copied_through_shadow_slots_on_every_edge() {
+        let assembly = assembly(LOOP);
+        // Entering the loop stores the initial values in the shadow slots of %i and %total...
+        assert!(assembly.contains(
+            "\
+\tmovq .LC0(%rip), %rax
+\tmovq %rax, -64(%rbp)
+\tmovq .LC0(%rip), %rax
+\tmovq %rax, -72(%rbp)
+\tjmp .Lsum.loop
+.Lsum.loop:
+\tmovq -64(%rbp), %rax
+\tmovq %rax, -32(%rbp)
+\tmovq -72(%rbp), %rax
+\tmovq %rax, -40(%rbp)
+"
+        ));
+        // ...and the back edge stores the next ones, so the phis never read each other's update.
+        assert!(assembly.contains(
+            "\
+\tcmpq %rcx, %rax
+\tjl .Lsum..1
+\tjmp .Lsum..2
+.Lsum..1:
+\tmovq -56(%rbp), %rax
+\tmovq %rax, -64(%rbp)
+\tmovq -48(%rbp), %rax
+\tmovq %rax, -72(%rbp)
+\tjmp .Lsum.loop
+.Lsum..2:
+.Lsum.done:
+"
+        ));
+    }
+
+    #[test]
+    fn calls_follow_the_system_v_abi() {
+        // Module functions are called directly.
+        assert!(assembly(LOOP).contains(
+            "\
+\tmovq -40(%rbp), %rax
+\tmovq %rax, %rdi
+\tmovq -32(%rbp), %rax
+\tmovq %rax, %rsi
+\tmovl $0, %eax
+\tcall add
+\tmovq %rax, -48(%rbp)
+"
+        ));
+        // Others go through the PLT. Integers past the sixth go on the stack, and %al holds the
+        // number of vector registers used.
+        let assembly = assembly(
+            "\
+constant $0 = i64 0
+constant $1 = i64 1
+constant $2 = i64 2
+constant $3 = i64 3
+constant $4 = i64 4
+constant $5 = i64 5
+constant $6 = i64 6
+constant $7 = double 0.5
+function double f() {
+entry:
+    %r = invoke double `function_address([g], []), [i64, $0], [double, $7], [i64, $1], [i64, $2], [i64, $3], [i64, $4], [i64, $5], [i64, $6]
+    return %r
+}
+",
+        );
+        assert!(assembly.contains(
+            "\
+.Lf.entry:
+\tsubq $16, %rsp
+\tmovq .LC6(%rip), %rax
+\tmovq %rax, 0(%rsp)
+\tmovq .LC0(%rip), %rax
+\tmovq %rax, %rdi
+\tmovq .LC7(%rip), %rax
+\tmovq %rax, %xmm0
+\tmovq .LC1(%rip), %rax
+\tmovq %rax, %rsi
+\tmovq .LC2(%rip), %rax
+\tmovq %rax, %rdx
+\tmovq .LC3(%rip), %rax
+\tmovq %rax, %rcx
+\tmovq .LC4(%rip), %rax
+\tmovq %rax, %r8
+\tmovq .LC5(%rip), %rax
+\tmovq %rax, %r9
+\tmovl $1, %eax
+\tcall g@PLT
+\taddq $16, %rsp
+\tmovq %xmm0, %rax
+\tmovq %rax, -8(%rbp)
+"
+        ));
+    }
+}
@@ -0,0 +1,1404 @@
+//! The C API, declared in `include/lg_rust_binding.h`.
+//!
+//! Modules, functions and basic blocks are handed out as pointers: an `IRModule` is owned by the
+//! caller and released with `lg_module_free`, while `IRFunction` and `IRBasicBlock` pointers
+//! point into their module and stay valid until it is freed. Types, operands and instructions
+//! are boxed into `IRTypeHandle`, `IROperandHandle` and `IRInstructionHandle`, each released
+//! with its own `*_free` function.
+//!
+//! Functions only read their handle arguments and copy what they keep, so the caller still owns
+//! them afterwards. The one exception is `lg_basic_block_append`, which takes the instruction.
+//!
+//! Strings are NUL-terminated UTF-8 and arrays are a pointer and a length; a null pointer is
+//! allowed for an empty array. Functions that create something return null when an argument is
+//! null, not UTF-8 or out of range; functions that can fail otherwise return an `LG_*` status
+//! and, if `error` is not null, store a message there for `lg_string_free`. A panic never
+//! unwinds into C: the former return null or false instead, the latter `LG_ERROR_PANIC`.
+//!
+//! `ctest/run.sh` compiles a smoke test of this API against the static library and runs it.
+#![allow(clippy::missing_safety_doc)]
+
+use crate::IRGenerator;
+use crate::backend::IRGenerateError;
+use crate::ir::base::{IRBasicBlock, IRCondition, IRControlFlowGraph, IRFunction, IRGlobalData};
+use crate::ir::binary::{read_module, write_module};
+use crate::ir::instruction::{
+    IRAsm, IRCalculate, IRCalculateOperator, IRConditionalJump, IRDecrease, IRElementAddress,
+    IRElementIndex, IRFree, IRGet, IRGoto, IRIncrease, IRInstruction, IRInvoke, IRMalloc, IRNegate,
+    IRNoOperate, IRNot, IRRealloc, IRReturn, IRSet, IRSetVirtualRegister, IRStackAllocate,
+    IRTypeCast, IRTypeCastKind,
+};
+use crate::ir::operand::{
+    IRConstant, IRInterfaceTable, IRInterfaceTableEntry, IRMacro, IROperand, IRPhi,
+    IRVirtualRegister, IRVirtualTable,
+};
+use crate::ir::parser::parse_module;
+use crate::ir::structure::{IRField, IRStructure};
+use crate::ir::types::{
+    IRArrayType, IRDoubleType, IRFloatType, IRFunctionType, IRIntegerType, IRIntegerTypeSize,
+    IRPointerType, IRStructureType, IRType, IRTypeKind, IRVoidType,
+};
+use crate::ir::verify::verify_module;
+use crate::ir::{IRConstantValue, IRDumper, IRModule};
+use std::ffi::{CStr, CString, c_char};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+pub type LGStatus = u32;
+
+pub const LG_OK: LGStatus = 0;
+/// A required pointer was null.
+pub const LG_ERROR_NULL_ARGUMENT: LGStatus = 1;
+/// A string was not UTF-8.
+pub const LG_ERROR_INVALID_UTF8: LGStatus = 2;
+/// An option passed to `lg_generate` was not recognized.
+pub const LG_ERROR_INVALID_OPTION: LGStatus = 3;
+/// The backend cannot generate code for something in the module.
+pub const LG_ERROR_UNSUPPORTED: LGStatus = 4;
+/// The text passed to `lg_module_parse` is not a valid module.
+pub const LG_ERROR_PARSE: LGStatus = 5;
+/// `lg_module_verify` found problems.
+pub const LG_ERROR_VERIFY: LGStatus = 6;
+/// The library panicked; the module may be left half-changed.
+pub const LG_ERROR_PANIC: LGStatus = 7;
+/// The data passed to `lg_module_read` is not a valid encoded module.
+pub const LG_ERROR_DECODE: LGStatus = 8;
+
+pub const LG_CONDITION_EQUAL: u32 = 0;
+pub const LG_CONDITION_NOT_EQUAL: u32 = 1;
+pub const LG_CONDITION_LESS: u32 = 2;
+pub const LG_CONDITION_LESS_EQUAL: u32 = 3;
+pub const LG_CONDITION_GREATER: u32 = 4;
+pub const LG_CONDITION_GREATER_EQUAL: u32 = 5;
+pub const LG_CONDITION_IF_TRUE: u32 = 6;
+pub const LG_CONDITION_IF_FALSE: u32 = 7;
+
+pub const LG_OPERATOR_ADD: u32 = 0;
+pub const LG_OPERATOR_SUB: u32 = 1;
+pub const LG_OPERATOR_MUL: u32 = 2;
+pub const LG_OPERATOR_DIV: u32 = 3;
+pub const LG_OPERATOR_MOD: u32 = 4;
+pub const LG_OPERATOR_AND: u32 = 5;
+pub const LG_OPERATOR_OR: u32 = 6;
+pub const LG_OPERATOR_XOR: u32 = 7;
+pub const LG_OPERATOR_SHL: u32 = 8;
+pub const LG_OPERATOR_SHR: u32 = 9;
+pub const LG_OPERATOR_USHR: u32 = 10;
+
+pub const LG_CAST_ZERO_EXTEND: u32 = 0;
+pub const LG_CAST_SIGN_EXTEND: u32 = 1;
+pub const LG_CAST_TRUNCATE: u32 = 2;
+pub const LG_CAST_INT_TO_FLOAT: u32 = 3;
+pub const LG_CAST_FLOAT_TO_INT: u32 = 4;
+pub const LG_CAST_FLOAT_EXTEND: u32 = 5;
+pub const LG_CAST_FLOAT_TRUNCATE: u32 = 6;
+
+pub struct IRTypeHandle(Box<dyn IRType>);
+
+pub struct IROperandHandle(Box<dyn IROperand>);
+
+pub struct IRInstructionHandle(Box<dyn IRInstruction>);
+
+fn condition(value: u32) -> Option<IRCondition> {
+    Some(match value {
+        LG_CONDITION_EQUAL => IRCondition::Equal,
+        LG_CONDITION_NOT_EQUAL => IRCondition::NotEqual,
+        LG_CONDITION_LESS => IRCondition::Less,
+        LG_CONDITION_LESS_EQUAL => IRCondition::LessEqual,
+        LG_CONDITION_GREATER => IRCondition::Greater,
+        LG_CONDITION_GREATER_EQUAL => IRCondition::GreaterEqual,
+        LG_CONDITION_IF_TRUE => IRCondition::IfTrue,
+        LG_CONDITION_IF_FALSE => IRCondition::IfFalse,
+        _ => return None,
+    })
+}
+
+fn operator(value: u32) -> Option<IRCalculateOperator> {
+    Some(match value {
+        LG_OPERATOR_ADD => IRCalculateOperator::ADD,
+        LG_OPERATOR_SUB => IRCalculateOperator::SUB,
+        LG_OPERATOR_MUL => IRCalculateOperator::MUL,
+        LG_OPERATOR_DIV => IRCalculateOperator::DIV,
+        LG_OPERATOR_MOD => IRCalculateOperator::MOD,
+        LG_OPERATOR_AND => IRCalculateOperator::AND,
+        LG_OPERATOR_OR => IRCalculateOperator::OR,
+        LG_OPERATOR_XOR => IRCalculateOperator::XOR,
+        LG_OPERATOR_SHL => IRCalculateOperator::SHL,
+        LG_OPERATOR_SHR => IRCalculateOperator::SHR,
+        LG_OPERATOR_USHR => IRCalculateOperator::USHR,
+        _ => return None,
+    })
+}
+
+fn cast_kind(value: u32) -> Option<IRTypeCastKind> {
+    Some(match value {
+        LG_CAST_ZERO_EXTEND => IRTypeCastKind::ZeroExtend,
+        LG_CAST_SIGN_EXTEND => IRTypeCastKind::SignExtend,
+        LG_CAST_TRUNCATE => IRTypeCastKind::Truncate,
+        LG_CAST_INT_TO_FLOAT => IRTypeCastKind::IntToFloat,
+        LG_CAST_FLOAT_TO_INT => IRTypeCastKind::FloatToInt,
+        LG_CAST_FLOAT_EXTEND => IRTypeCastKind::FloatExtend,
+        LG_CAST_FLOAT_TRUNCATE => IRTypeCastKind::FloatTruncate,
+        _ => return None,
+    })
+}
+
+unsafe fn string<'a>(value: *const c_char) -> Option<&'a str> {
+    if value.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(value) }.to_str().ok()
+}
+
+/// Like `string`, but a null pointer is `Some(None)`.
+unsafe fn optional_string<'a>(value: *const c_char) -> Option<Option<&'a str>> {
+    if value.is_null() {
+        Some(None)
+    } else {
+        unsafe { string(value) }.map(Some)
+    }
+}
+
+unsafe fn slice<'a, T>(values: *const T, count: usize) -> Option<&'a [T]> {
+    if count == 0 {
+        Some(&[])
+    } else if values.is_null() {
+        None
+    } else {
+        Some(unsafe { std::slice::from_raw_parts(values, count) })
+    }
+}
+
+unsafe fn strings(values: *const *const c_char, count: usize) -> Option<Vec<String>> {
+    unsafe { slice(values, count) }?
+        .iter()
+        .map(|value| unsafe { string(*value) }.map(str::to_string))
+        .collect()
+}
+
+unsafe fn ir_type(_type: *const IRTypeHandle) -> Option<Box<dyn IRType>> {
+    unsafe { _type.as_ref() }.map(|_type| _type.0.clone())
+}
+
+unsafe fn ir_types(
+    types: *const *const IRTypeHandle,
+    count: usize,
+) -> Option<Vec<Box<dyn IRType>>> {
+    unsafe { slice(types, count) }?
+        .iter()
+        .map(|_type| unsafe { ir_type(*_type) })
+        .collect()
+}
+
+unsafe fn ir_operand(operand: *const IROperandHandle) -> Option<Box<dyn IROperand>> {
+    unsafe { operand.as_ref() }.map(|operand| operand.0.clone())
+}
+
+/// Like `ir_operand`, but a null pointer is `Some(None)`.
+unsafe fn optional_ir_operand(
+    operand: *const IROperandHandle,
+) -> Option<Option<Box<dyn IROperand>>> {
+    if operand.is_null() {
+        Some(None)
+    } else {
+        unsafe { ir_operand(operand) }.map(Some)
+    }
+}
+
+unsafe fn ir_operands(
+    operands: *const *const IROperandHandle,
+    count: usize,
+) -> Option<Vec<Box<dyn IROperand>>> {
+    unsafe { slice(operands, count) }?
+        .iter()
+        .map(|operand| unsafe { ir_operand(*operand) })
+        .collect()
+}
+
+unsafe fn register(name: *const c_char) -> Option<Box<IRVirtualRegister>> {
+    unsafe { string(name) }.map(|name| Box::new(IRVirtualRegister::new(name.to_string())))
+}
+
+/// Like `register`, but a null pointer is `Some(None)`.
+unsafe fn optional_register(name: *const c_char) -> Option<Option<Box<IRVirtualRegister>>> {
+    if name.is_null() {
+        Some(None)
+    } else {
+        unsafe { register(name) }.map(Some)
+    }
+}
+
+fn new_type(_type: Option<impl IRType + 'static>) -> *mut IRTypeHandle {
+    _type.map_or(ptr::null_mut(), |_type| {
+        Box::into_raw(Box::new(IRTypeHandle(Box::new(_type))))
+    })
+}
+
+fn new_operand(operand: Option<impl IROperand>) -> *mut IROperandHandle {
+    operand.map_or(ptr::null_mut(), |operand| {
+        Box::into_raw(Box::new(IROperandHandle(Box::new(operand))))
+    })
+}
+
+fn new_instruction(instruction: Option<impl IRInstruction>) -> *mut IRInstructionHandle {
+    instruction.map_or(ptr::null_mut(), |instruction| {
+        Box::into_raw(Box::new(IRInstructionHandle(Box::new(instruction))))
+    })
+}
+
+fn new_string(value: String) -> *mut c_char {
+    // Interior NULs cannot be passed on, so the string is cut at the first one.
+    let value = match value.find('\0') {
+        Some(end) => value[..end].to_string(),
+        None => value,
+    };
+    CString::new(value).unwrap().into_raw()
+}
+
+unsafe fn set_error(error: *mut *mut c_char, message: String) {
+    if let Some(error) = unsafe { error.as_mut() } {
+        *error = new_string(message);
+    }
+}
+
+/// Runs `f`, turning a panic into `LG_ERROR_PANIC` so that it does not cross into C.
+unsafe fn guard(
+    error: *mut *mut c_char,
+    f: impl FnOnce() -> Result<(), (LGStatus, String)>,
+) -> LGStatus {
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err((LG_ERROR_PANIC, format!("panic: {}", message)))
+    });
+    match result {
+        Ok(()) => LG_OK,
+        Err((status, message)) => {
+            unsafe { set_error(error, message) };
+            status
+        }
+    }
+}
+
+/// Runs `f`, returning `fallback` instead if it panics, for the functions that report failure
+/// with null or false.
+fn guard_or<T>(fallback: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(fallback)
+}
+
+fn control_flow_graphs_mut(
+    ir_module: &mut IRModule,
+) -> impl Iterator<Item = &mut IRControlFlowGraph> {
+    std::iter::once(ir_module.global_init_section.as_mut()).chain(
+        ir_module
+            .functions
+            .values_mut()
+            .map(|ir_function| ir_function.control_flow_graph.as_mut()),
+    )
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(unsafe { CString::from_raw(value) });
+    }
+}
+
+/// Releases the output of `lg_generate` and `lg_module_write`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_bytes_free(data: *mut u8, length: usize) {
+    if !data.is_null() {
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(data, length)) });
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn lg_module_new() -> *mut IRModule {
+    guard_or(ptr::null_mut(), || Box::into_raw(Box::new(IRModule::new())))
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_free(module: *mut IRModule) {
+    if !module.is_null() {
+        drop(unsafe { Box::from_raw(module) });
+    }
+}
+
+/// Parses the textual form printed by `lg_module_dump` into a new module stored in `module`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_parse(
+    source: *const c_char,
+    module: *mut *mut IRModule,
+    error: *mut *mut c_char,
+) -> LGStatus {
+    unsafe {
+        guard(error, || {
+            let module = module
+                .as_mut()
+                .ok_or((LG_ERROR_NULL_ARGUMENT, "module is null".to_string()))?;
+            if source.is_null() {
+                return Err((LG_ERROR_NULL_ARGUMENT, "source is null".to_string()));
+            }
+            let source =
+                string(source).ok_or((LG_ERROR_INVALID_UTF8, "source is not UTF-8".to_string()))?;
+            let ir_module = parse_module(source)
+                .map_err(|parse_error| (LG_ERROR_PARSE, parse_error.to_string()))?;
+            *module = Box::into_raw(Box::new(ir_module));
+            Ok(())
+        })
+    }
+}
+
+/// The textual form of the module, to be released with `lg_string_free`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_dump(module: *const IRModule) -> *mut c_char {
+    guard_or(ptr::null_mut(), || match unsafe { module.as_ref() } {
+        Some(ir_module) => new_string(IRDumper::dump_to_string(ir_module)),
+        None => ptr::null_mut(),
+    })
+}
+
+/// Decodes a module encoded by `lg_module_write` into a new module stored in `module`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_read(
+    data: *const u8,
+    length: usize,
+    module: *mut *mut IRModule,
+    error: *mut *mut c_char,
+) -> LGStatus {
+    unsafe {
+        guard(error, || {
+            let module = module
+                .as_mut()
+                .ok_or((LG_ERROR_NULL_ARGUMENT, "module is null".to_string()))?;
+            let data =
+                slice(data, length).ok_or((LG_ERROR_NULL_ARGUMENT, "data is null".to_string()))?;
+            let ir_module = read_module(data)
+                .map_err(|binary_error| (LG_ERROR_DECODE, binary_error.to_string()))?;
+            *module = Box::into_raw(Box::new(ir_module));
+            Ok(())
+        })
+    }
+}
+
+/// The binary encoding of the module, to be released with `lg_bytes_free`. Its size is stored in
+/// `length`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_write(module: *const IRModule, length: *mut usize) -> *mut u8 {
+    guard_or(ptr::null_mut(), || {
+        match unsafe { (module.as_ref(), length.as_mut()) } {
+            (Some(ir_module), Some(length)) => {
+                let output = write_module(ir_module);
+                *length = output.len();
+                Box::into_raw(output.into_boxed_slice()) as *mut u8
+            }
+            _ => ptr::null_mut(),
+        }
+    })
+}
+
+/// Checks the module, storing one problem per line in `error` if there are any.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_verify(
+    module: *mut IRModule,
+    error: *mut *mut c_char,
+) -> LGStatus {
+    unsafe {
+        guard(error, || {
+            let ir_module = module
+                .as_mut()
+                .ok_or((LG_ERROR_NULL_ARGUMENT, "module is null".to_string()))?;
+            control_flow_graphs_mut(ir_module).for_each(IRControlFlowGraph::build_edges);
+            verify_module(ir_module).map_err(|errors| {
+                let messages = errors
+                    .iter()
+                    .map(|error| error.to_string())
+                    .collect::<Vec<_>>();
+                (LG_ERROR_VERIFY, messages.join("\n"))
+            })
+        })
+    }
+}
+
+/// Generates code for the module with the same options as `IRGenerator::generate`, e.g.
+/// `--target=c`. On success the output is stored in `data` and `length`, to be released with
+/// `lg_bytes_free`.
+///
+/// Instructions appended through this API do not keep the control flow edges up to date, so
+/// they are recomputed first.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_generate(
+    module: *mut IRModule,
+    options: *const *const c_char,
+    options_count: usize,
+    data: *mut *mut u8,
+    length: *mut usize,
+    error: *mut *mut c_char,
+) -> LGStatus {
+    unsafe {
+        guard(error, || {
+            let null = |name: &str| (LG_ERROR_NULL_ARGUMENT, format!("{} is null", name));
+            let ir_module = module.as_mut().ok_or_else(|| null("module"))?;
+            let data = data.as_mut().ok_or_else(|| null("data"))?;
+            let length = length.as_mut().ok_or_else(|| null("length"))?;
+            let options = slice(options, options_count).ok_or_else(|| null("options"))?;
+            let options = options
+                .iter()
+                .map(|option| {
+                    if option.is_null() {
+                        Err(null("option"))
+                    } else {
+                        string(*option)
+                            .map(str::to_string)
+                            .ok_or((LG_ERROR_INVALID_UTF8, "option is not UTF-8".to_string()))
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            control_flow_graphs_mut(ir_module).for_each(IRControlFlowGraph::build_edges);
+            let output = IRGenerator::generate(ir_module, &options).map_err(|generate_error| {
+                let status = match generate_error {
+                    IRGenerateError::InvalidOption(_) => LG_ERROR_INVALID_OPTION,
+                    IRGenerateError::Unsupported { .. } => LG_ERROR_UNSUPPORTED,
+                };
+                (status, generate_error.to_string())
+            })?;
+            *length = output.len();
+            *data = Box::into_raw(output.into_boxed_slice()) as *mut u8;
+            Ok(())
+        })
+    }
+}
+
+/// Sets the function the program starts at, or clears it when `name` is null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_set_entry_point(
+    module: *mut IRModule,
+    name: *const c_char,
+) -> bool {
+    guard_or(false, || {
+        let (Some(ir_module), Some(name)) =
+            (unsafe { module.as_mut() }, unsafe { optional_string(name) })
+        else {
+            return false;
+        };
+        ir_module.entry_point = name.map(str::to_string);
+        true
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_add_structure(
+    module: *mut IRModule,
+    name: *const c_char,
+    field_names: *const *const c_char,
+    field_types: *const *const IRTypeHandle,
+    fields_count: usize,
+) -> bool {
+    guard_or(false, || {
+        let add = || {
+            let ir_module = unsafe { module.as_mut() }?;
+            let name = unsafe { string(name) }?;
+            let names = unsafe { strings(field_names, fields_count) }?;
+            let types = unsafe { ir_types(field_types, fields_count) }?;
+            let fields = names
+                .into_iter()
+                .zip(types)
+                .map(|(name, _type)| IRField::new(name, _type))
+                .collect();
+            ir_module.push_struct(IRStructure::new(name.to_string(), fields));
+            Some(())
+        };
+        add().is_some()
+    })
+}
+
+/// Adds a global; `size` and `values` are optional, and `values` is only used when
+/// `has_values` is set, so that an empty list can be told from no list.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_add_global_data(
+    module: *mut IRModule,
+    name: *const c_char,
+    size: *const IROperandHandle,
+    has_values: bool,
+    values: *const *const IROperandHandle,
+    values_count: usize,
+) -> bool {
+    guard_or(false, || {
+        let add = || {
+            let ir_module = unsafe { module.as_mut() }?;
+            let name = unsafe { string(name) }?;
+            let size = unsafe { optional_ir_operand(size) }?;
+            let values = if has_values {
+                Some(unsafe { ir_operands(values, values_count) }?)
+            } else {
+                None
+            };
+            ir_module.global_data_section.data.push(IRGlobalData::new(
+                name.to_string(),
+                size,
+                values,
+            ));
+            Some(())
+        };
+        add().is_some()
+    })
+}
+
+/// Adds a function whose fields are its arguments. Returns null if the module already has a
+/// function of that name.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_add_function(
+    module: *mut IRModule,
+    return_type: *const IRTypeHandle,
+    name: *const c_char,
+    argument_names: *const *const c_char,
+    argument_types: *const *const IRTypeHandle,
+    arguments_count: usize,
+) -> *mut IRFunction {
+    guard_or(ptr::null_mut(), || {
+        let add = || {
+            let ir_module = unsafe { module.as_mut() }?;
+            let return_type = unsafe { ir_type(return_type) }?;
+            let name = unsafe { string(name) }?;
+            let names = unsafe { strings(argument_names, arguments_count) }?;
+            let types = unsafe { ir_types(argument_types, arguments_count) }?;
+            if ir_module.functions.contains_key(name) {
+                return None;
+            }
+            let fields = names
+                .into_iter()
+                .zip(types)
+                .map(|(name, _type)| Box::new(IRField::new(name, _type)))
+                .collect();
+            ir_module.push_function(IRFunction::new(
+                return_type,
+                name.to_string(),
+                arguments_count,
+                fields,
+                Box::default(),
+            ));
+            Some(ptr::from_mut(ir_module.functions[name].as_mut()))
+        };
+        add().unwrap_or(ptr::null_mut())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_get_function(
+    module: *mut IRModule,
+    name: *const c_char,
+) -> *mut IRFunction {
+    guard_or(ptr::null_mut(), || {
+        let get = || {
+            let ir_module = unsafe { module.as_mut() }?;
+            let ir_function = ir_module.functions.get_mut(unsafe { string(name) }?)?;
+            Some(ptr::from_mut(ir_function.as_mut()))
+        };
+        get().unwrap_or(ptr::null_mut())
+    })
+}
+
+/// Adds a local variable after the arguments and other fields.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_function_add_local(
+    function: *mut IRFunction,
+    name: *const c_char,
+    _type: *const IRTypeHandle,
+) -> bool {
+    guard_or(false, || {
+        let add = || {
+            let ir_function = unsafe { function.as_mut() }?;
+            let field = IRField::new(unsafe { string(name) }?.to_string(), unsafe {
+                ir_type(_type)
+            }?);
+            ir_function.fields.push(Box::new(field));
+            Some(())
+        };
+        add().is_some()
+    })
+}
+
+unsafe fn append_basic_block(
+    ir_control_flow_graph: &mut IRControlFlowGraph,
+    name: *const c_char,
+) -> *mut IRBasicBlock {
+    let Some(name) = (unsafe { string(name) }) else {
+        return ptr::null_mut();
+    };
+    if ir_control_flow_graph.basic_blocks.contains_key(name) {
+        return ptr::null_mut();
+    }
+    ir_control_flow_graph.add_basic_block(Box::new(IRBasicBlock::new(name.to_string())));
+    ptr::from_mut(ir_control_flow_graph.basic_blocks[name].as_mut())
+}
+
+/// Appends an empty block to the function. Returns null if it already has a block of that
+/// name.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_function_append_basic_block(
+    function: *mut IRFunction,
+    name: *const c_char,
+) -> *mut IRBasicBlock {
+    guard_or(ptr::null_mut(), || match unsafe { function.as_mut() } {
+        Some(ir_function) => unsafe {
+            append_basic_block(&mut ir_function.control_flow_graph, name)
+        },
+        None => ptr::null_mut(),
+    })
+}
+
+/// Like `lg_function_append_basic_block`, for the global init section.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_append_global_init_block(
+    module: *mut IRModule,
+    name: *const c_char,
+) -> *mut IRBasicBlock {
+    guard_or(ptr::null_mut(), || match unsafe { module.as_mut() } {
+        Some(ir_module) => unsafe { append_basic_block(&mut ir_module.global_init_section, name) },
+        None => ptr::null_mut(),
+    })
+}
+
+/// Moves `instruction` to the end of the block; the handle must not be used afterwards, even
+/// when this returns false because `block` is null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_basic_block_append(
+    block: *mut IRBasicBlock,
+    instruction: *mut IRInstructionHandle,
+) -> bool {
+    guard_or(false, || {
+        if instruction.is_null() {
+            return false;
+        }
+        let instruction = unsafe { Box::from_raw(instruction) };
+        match unsafe { block.as_mut() } {
+            Some(ir_basic_block) => {
+                ir_basic_block.instructions.push(instruction.0);
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_type_free(_type: *mut IRTypeHandle) {
+    if !_type.is_null() {
+        drop(unsafe { Box::from_raw(_type) });
+    }
+}
+
+/// An integer type of 1, 8, 16, 32 or 64 bits.
+#[unsafe(no_mangle)]
+pub extern "C" fn lg_type_integer(bits: u32, unsigned: bool) -> *mut IRTypeHandle {
+    guard_or(ptr::null_mut(), || {
+        let size = match bits {
+            1 => IRIntegerTypeSize::OneBit,
+            8 => IRIntegerTypeSize::OneByte,
+            16 => IRIntegerTypeSize::TwoBytes,
+            32 => IRIntegerTypeSize::FourBytes,
+            64 => IRIntegerTypeSize::EightBytes,
+            _ => return ptr::null_mut(),
+        };
+        new_type(Some(IRIntegerType::new(size, unsigned)))
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn lg_type_float() -> *mut IRTypeHandle {
+    guard_or(ptr::null_mut(), || new_type(Some(IRFloatType::new())))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn lg_type_double() -> *mut IRTypeHandle {
+    guard_or(ptr::null_mut(), || new_type(Some(IRDoubleType::new())))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn lg_type_void() -> *mut IRTypeHandle {
+    guard_or(ptr::null_mut(), || new_type(Some(IRVoidType::new())))
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_type_pointer(base: *const IRTypeHandle) -> *mut IRTypeHandle {
+    guard_or(ptr::null_mut(), || {
+        new_type(unsafe { ir_type(base) }.map(IRPointerType::new))
+    })
+}
+
+/// The structure of that name in the module the type is used in.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_type_structure(name: *const c_char) -> *mut IRTypeHandle {
+    guard_or(ptr::null_mut(), || {
+        new_type(unsafe { string(name) }.map(|name| IRStructureType::new(name.to_string())))
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_type_array(
+    element: *const IRTypeHandle,
+    length: u64,
+) -> *mut IRTypeHandle {
+    guard_or(ptr::null_mut(), || {
+        new_type(unsafe { ir_type(element) }.map(|element| IRArrayType::new(element, length)))
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_type_function(
+    return_type: *const IRTypeHandle,
+    params: *const *const IRTypeHandle,
+    params_count: usize,
+    variadic: bool,
+) -> *mut IRTypeHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRFunctionType::new(
+                unsafe { ir_type(return_type) }?,
+                unsafe { ir_types(params, params_count) }?,
+                variadic,
+            ))
+        };
+        new_type(new())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_operand_free(operand: *mut IROperandHandle) {
+    if !operand.is_null() {
+        drop(unsafe { Box::from_raw(operand) });
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_operand_register(name: *const c_char) -> *mut IROperandHandle {
+    guard_or(ptr::null_mut(), || {
+        new_operand(unsafe { register(name) }.map(|register| *register))
+    })
+}
+
+/// Refers to the constant pool entry at `index`; see `lg_module_constant_*` for adding entries.
+#[unsafe(no_mangle)]
+pub extern "C" fn lg_operand_constant(index: u32) -> *mut IROperandHandle {
+    guard_or(ptr::null_mut(), || {
+        new_operand(Some(IRConstant::new(index)))
+    })
+}
+
+unsafe fn constant(
+    module: *mut IRModule,
+    _type: Option<Box<dyn IRType>>,
+    value: IRConstantValue,
+) -> *mut IROperandHandle {
+    let (Some(ir_module), Some(_type)) = (unsafe { module.as_mut() }, _type) else {
+        return ptr::null_mut();
+    };
+    if !value.matches_in(ir_module, _type.as_ref()) {
+        return ptr::null_mut();
+    }
+    new_operand(Some(ir_module.constant_pool.intern(_type, value)))
+}
+
+/// An integer constant of an integer type, holding the low bits of `value`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_constant_integer(
+    module: *mut IRModule,
+    _type: *const IRTypeHandle,
+    value: i64,
+) -> *mut IROperandHandle {
+    guard_or(ptr::null_mut(), || {
+        let _type = unsafe { ir_type(_type) };
+        let Some(IRTypeKind::Integer(integer_type)) = _type.as_deref().map(IRTypeKind::of) else {
+            return ptr::null_mut();
+        };
+        unsafe {
+            constant(
+                module,
+                _type,
+                IRConstantValue::integer(&integer_type.size, value),
+            )
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_constant_float(
+    module: *mut IRModule,
+    value: f32,
+) -> *mut IROperandHandle {
+    guard_or(ptr::null_mut(), || unsafe {
+        constant(
+            module,
+            Some(Box::new(IRFloatType::new())),
+            IRConstantValue::F32(value),
+        )
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_constant_double(
+    module: *mut IRModule,
+    value: f64,
+) -> *mut IROperandHandle {
+    guard_or(ptr::null_mut(), || unsafe {
+        constant(
+            module,
+            Some(Box::new(IRDoubleType::new())),
+            IRConstantValue::F64(value),
+        )
+    })
+}
+
+/// The null pointer of a pointer type.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_constant_null(
+    module: *mut IRModule,
+    _type: *const IRTypeHandle,
+) -> *mut IROperandHandle {
+    guard_or(ptr::null_mut(), || unsafe {
+        constant(module, ir_type(_type), IRConstantValue::Null)
+    })
+}
+
+/// The address of a NUL-terminated copy of `bytes`, typed as a pointer to `i8`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_constant_string(
+    module: *mut IRModule,
+    bytes: *const u8,
+    length: usize,
+) -> *mut IROperandHandle {
+    guard_or(ptr::null_mut(), || {
+        let Some(bytes) = (unsafe { slice(bytes, length) }) else {
+            return ptr::null_mut();
+        };
+        let _type = IRPointerType::new(Box::new(IRIntegerType::new(
+            IRIntegerTypeSize::OneByte,
+            false,
+        )));
+        unsafe {
+            constant(
+                module,
+                Some(Box::new(_type)),
+                IRConstantValue::Bytes(bytes.to_vec()),
+            )
+        }
+    })
+}
+
+/// A value of `_type` with all bits zero.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_constant_zero(
+    module: *mut IRModule,
+    _type: *const IRTypeHandle,
+) -> *mut IROperandHandle {
+    guard_or(ptr::null_mut(), || unsafe {
+        constant(module, ir_type(_type), IRConstantValue::ZeroInitializer)
+    })
+}
+
+/// An array or structure constant of `_type` whose elements are the values of the constants in
+/// `elements`, which must belong to `module`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_module_constant_aggregate(
+    module: *mut IRModule,
+    _type: *const IRTypeHandle,
+    elements: *const *const IROperandHandle,
+    elements_count: usize,
+) -> *mut IROperandHandle {
+    guard_or(ptr::null_mut(), || {
+        let values = || {
+            let ir_module = unsafe { module.as_ref() }?;
+            unsafe { ir_operands(elements, elements_count) }?
+                .iter()
+                .map(|element| {
+                    let ir_constant = element.downcast_ref::<IRConstant>()?;
+                    let entry = ir_module
+                        .constant_pool
+                        .entries
+                        .get(ir_constant.index as usize)?;
+                    Some(entry.value.clone())
+                })
+                .collect::<Option<_>>()
+        };
+        match values() {
+            Some(values) => unsafe {
+                constant(module, ir_type(_type), IRConstantValue::Aggregate(values))
+            },
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+/// A macro such as `field_address` or `function_address`, written `` `name([args], [operands]) ``
+/// in the textual form.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_operand_macro(
+    name: *const c_char,
+    args: *const *const c_char,
+    args_count: usize,
+    operands: *const *const IROperandHandle,
+    operands_count: usize,
+) -> *mut IROperandHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRMacro::new(
+                unsafe { string(name) }?.to_string(),
+                unsafe { strings(args, args_count) }?,
+                unsafe { ir_operands(operands, operands_count) }?,
+            ))
+        };
+        new_operand(new())
+    })
+}
+
+/// A phi taking `operands[i]` when control comes from the block `labels[i]`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_operand_phi(
+    _type: *const IRTypeHandle,
+    labels: *const *const c_char,
+    operands: *const *const IROperandHandle,
+    count: usize,
+) -> *mut IROperandHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRPhi::new(
+                unsafe { ir_type(_type) }?,
+                unsafe { strings(labels, count) }?,
+                unsafe { ir_operands(operands, count) }?,
+            ))
+        };
+        new_operand(new())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_operand_virtual_table(
+    functions: *const *const c_char,
+    functions_count: usize,
+) -> *mut IROperandHandle {
+    guard_or(ptr::null_mut(), || {
+        new_operand(unsafe { strings(functions, functions_count) }.map(IRVirtualTable::new))
+    })
+}
+
+/// An interface table whose entry `i` is named `names[i]` and lists `functions_counts[i]`
+/// functions, taken in order from `functions`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_operand_interface_table(
+    names: *const *const c_char,
+    functions_counts: *const usize,
+    entries_count: usize,
+    functions: *const *const c_char,
+    functions_count: usize,
+) -> *mut IROperandHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            let names = unsafe { strings(names, entries_count) }?;
+            let counts = unsafe { slice(functions_counts, entries_count) }?;
+            let mut functions = unsafe { strings(functions, functions_count) }?.into_iter();
+            let entries = names
+                .into_iter()
+                .zip(counts)
+                .map(|(name, count)| {
+                    let entry_functions = functions.by_ref().take(*count).collect::<Vec<_>>();
+                    (entry_functions.len() == *count)
+                        .then(|| IRInterfaceTableEntry::new(name, entry_functions))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            functions
+                .next()
+                .is_none()
+                .then(|| IRInterfaceTable::new(entries))
+        };
+        new_operand(new())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_free(instruction: *mut IRInstructionHandle) {
+    if !instruction.is_null() {
+        drop(unsafe { Box::from_raw(instruction) });
+    }
+}
+
+/// The textual form of the instruction, to be released with `lg_string_free`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_to_string(
+    instruction: *const IRInstructionHandle,
+) -> *mut c_char {
+    guard_or(ptr::null_mut(), || match unsafe { instruction.as_ref() } {
+        Some(instruction) => new_string(instruction.0.to_string()),
+        None => ptr::null_mut(),
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_goto(target: *const c_char) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        new_instruction(unsafe { string(target) }.map(|target| IRGoto::new(target.to_string())))
+    })
+}
+
+/// Jumps to the block `target` when the condition holds. `operand2` is null for the
+/// `LG_CONDITION_IF_TRUE` and `LG_CONDITION_IF_FALSE` conditions.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_conditional_jump(
+    is_atomic: bool,
+    _type: *const IRTypeHandle,
+    condition: u32,
+    operand1: *const IROperandHandle,
+    operand2: *const IROperandHandle,
+    target: *const c_char,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRConditionalJump::new(
+                is_atomic,
+                unsafe { ir_type(_type) }?,
+                self::condition(condition)?,
+                unsafe { ir_operand(operand1) }?,
+                unsafe { optional_ir_operand(operand2) }?,
+                unsafe { string(target) }?.to_string(),
+            ))
+        };
+        new_instruction(new())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn lg_instruction_no_operate() -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        new_instruction(Some(IRNoOperate::new()))
+    })
+}
+
+/// Returns `operand`, or nothing when it is null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_return(
+    operand: *const IROperandHandle,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        new_instruction(unsafe { optional_ir_operand(operand) }.map(IRReturn::new))
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_malloc(
+    size: *const IROperandHandle,
+    target: *const c_char,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRMalloc::new(unsafe { ir_operand(size) }?, unsafe {
+                register(target)
+            }?))
+        };
+        new_instruction(new())
+    })
+}
+
+/// The `free` instruction, releasing memory from `malloc`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_free_memory(
+    ptr: *const IROperandHandle,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        new_instruction(unsafe { ir_operand(ptr) }.map(IRFree::new))
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_realloc(
+    ptr: *const IROperandHandle,
+    size: *const IROperandHandle,
+    target: *const c_char,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRRealloc::new(
+                unsafe { ir_operand(ptr) }?,
+                unsafe { ir_operand(size) }?,
+                unsafe { register(target) }?,
+            ))
+        };
+        new_instruction(new())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_set(
+    _type: *const IRTypeHandle,
+    address: *const IROperandHandle,
+    value: *const IROperandHandle,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRSet::new(
+                unsafe { ir_type(_type) }?,
+                unsafe { ir_operand(address) }?,
+                unsafe { ir_operand(value) }?,
+            ))
+        };
+        new_instruction(new())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_get(
+    _type: *const IRTypeHandle,
+    address: *const IROperandHandle,
+    target: *const c_char,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRGet::new(
+                unsafe { ir_type(_type) }?,
+                unsafe { ir_operand(address) }?,
+                unsafe { register(target) }?,
+            ))
+        };
+        new_instruction(new())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_set_virtual_register(
+    source: *const IROperandHandle,
+    target: *const c_char,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRSetVirtualRegister::new(
+                unsafe { ir_operand(source) }?,
+                unsafe { register(target) }?,
+            ))
+        };
+        new_instruction(new())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_type_cast(
+    kind: u32,
+    original_type: *const IRTypeHandle,
+    source: *const IROperandHandle,
+    target_type: *const IRTypeHandle,
+    target: *const c_char,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRTypeCast::new(
+                cast_kind(kind)?,
+                unsafe { ir_type(original_type) }?,
+                unsafe { ir_operand(source) }?,
+                unsafe { ir_type(target_type) }?,
+                unsafe { register(target) }?,
+            ))
+        };
+        new_instruction(new())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_stack_allocate(
+    size: *const IROperandHandle,
+    target: *const c_char,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRStackAllocate::new(unsafe { ir_operand(size) }?, unsafe {
+                register(target)
+            }?))
+        };
+        new_instruction(new())
+    })
+}
+
+/// The address of an element of the `_type` value at `base`. Step `i` of the path is the field
+/// `fields[i]` of a structure or, when that is null, the element `indices[i]` of an array or
+/// pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_element_address(
+    _type: *const IRTypeHandle,
+    base: *const IROperandHandle,
+    fields: *const *const c_char,
+    indices: *const *const IROperandHandle,
+    path_length: usize,
+    target: *const c_char,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            let fields = unsafe { slice(fields, path_length) }?;
+            let indices = unsafe { slice(indices, path_length) }?;
+            let path = fields
+                .iter()
+                .zip(indices)
+                .map(|(field, index)| match unsafe { optional_string(*field) }? {
+                    Some(field) => Some(IRElementIndex::Field(field.to_string())),
+                    None => unsafe { ir_operand(*index) }.map(IRElementIndex::Index),
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(IRElementAddress::new(
+                unsafe { ir_type(_type) }?,
+                unsafe { ir_operand(base) }?,
+                path,
+                unsafe { register(target) }?,
+            ))
+        };
+        new_instruction(new())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_calculate(
+    is_atomic: bool,
+    operator: u32,
+    _type: *const IRTypeHandle,
+    operand1: *const IROperandHandle,
+    operand2: *const IROperandHandle,
+    target: *const c_char,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRCalculate::new(
+                is_atomic,
+                self::operator(operator)?,
+                unsafe { ir_type(_type) }?,
+                unsafe { ir_operand(operand1) }?,
+                unsafe { ir_operand(operand2) }?,
+                unsafe { register(target) }?,
+            ))
+        };
+        new_instruction(new())
+    })
+}
+
+/// Increments a value of `_type`. When `target` is null, the value at the address `operand` is
+/// incremented in place, atomically. Otherwise `operand` is the value itself: it is left
+/// unchanged and the register `target` is set to it plus one.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_increase(
+    _type: *const IRTypeHandle,
+    operand: *const IROperandHandle,
+    target: *const c_char,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRIncrease::new(
+                unsafe { ir_type(_type) }?,
+                unsafe { ir_operand(operand) }?,
+                unsafe { optional_register(target) }?,
+            ))
+        };
+        new_instruction(new())
+    })
+}
+
+/// Like `lg_instruction_increase`, decrementing.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_decrease(
+    _type: *const IRTypeHandle,
+    operand: *const IROperandHandle,
+    target: *const c_char,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRDecrease::new(
+                unsafe { ir_type(_type) }?,
+                unsafe { ir_operand(operand) }?,
+                unsafe { optional_register(target) }?,
+            ))
+        };
+        new_instruction(new())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_not(
+    is_atomic: bool,
+    _type: *const IRTypeHandle,
+    operand: *const IROperandHandle,
+    target: *const c_char,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRNot::new(
+                is_atomic,
+                unsafe { ir_type(_type) }?,
+                unsafe { ir_operand(operand) }?,
+                unsafe { register(target) }?,
+            ))
+        };
+        new_instruction(new())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_negate(
+    is_atomic: bool,
+    _type: *const IRTypeHandle,
+    operand: *const IROperandHandle,
+    target: *const c_char,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRNegate::new(
+                is_atomic,
+                unsafe { ir_type(_type) }?,
+                unsafe { ir_operand(operand) }?,
+                unsafe { register(target) }?,
+            ))
+        };
+        new_instruction(new())
+    })
+}
+
+/// Calls `address` with `arguments[i]` of type `argument_types[i]`; `target` is null when the
+/// result is not kept.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_invoke(
+    return_type: *const IRTypeHandle,
+    address: *const IROperandHandle,
+    argument_types: *const *const IRTypeHandle,
+    arguments: *const *const IROperandHandle,
+    arguments_count: usize,
+    target: *const c_char,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRInvoke::new(
+                unsafe { ir_type(return_type) }?,
+                unsafe { ir_operand(address) }?,
+                unsafe { ir_types(argument_types, arguments_count) }?,
+                unsafe { ir_operands(arguments, arguments_count) }?,
+                unsafe { optional_register(target) }?,
+            ))
+        };
+        new_instruction(new())
+    })
+}
+
+/// Inline assembly, with `resources[i]` of type `types[i]` bound to the name `names[i]`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lg_instruction_asm(
+    code: *const c_char,
+    types: *const *const IRTypeHandle,
+    resources: *const *const IROperandHandle,
+    names: *const *const c_char,
+    resources_count: usize,
+) -> *mut IRInstructionHandle {
+    guard_or(ptr::null_mut(), || {
+        let new = || {
+            Some(IRAsm::new(
+                unsafe { string(code) }?.to_string(),
+                unsafe { ir_types(types, resources_count) }?,
+                unsafe { ir_operands(resources, resources_count) }?,
+                unsafe { strings(names, resources_count) }?,
+            ))
+        };
+        new_instruction(new())
+    })
+}
@@ -1,36 +1,229 @@
 use crate::ir::base::{IRControlFlowGraph, IRFunction, IRGlobalData, IRGlobalDataSection, IRNode};
 use crate::ir::instruction::{
-    IRAsm, IRCalculate, IRConditionalJump, IRDecrease, IRFree, IRGet, IRGoto, IRIncrease, IRInvoke,
-    IRMalloc, IRNegate, IRNoOperate, IRNot, IRRealloc, IRReturn, IRSet, IRSetVirtualRegister,
-    IRStackAllocate, IRTypeCast,
+    IRAsm, IRCalculate, IRConditionalJump, IRDecrease, IRElementAddress, IRElementIndex, IRFree,
+    IRGet, IRGoto, IRIncrease, IRInvoke, IRMalloc, IRNegate, IRNoOperate, IRNot, IRRealloc,
+    IRReturn, IRSet, IRSetVirtualRegister, IRStackAllocate, IRTypeCast,
+};
+use crate::ir::operand::{
+    IRConstant, IRInterfaceTable, IRMacro, IRPhi, IRVirtualRegister, IRVirtualTable,
 };
-use crate::ir::operand::{IRConstant, IRMacro, IRPhi, IRVirtualRegister};
 use crate::ir::structure::{IRField, IRStructure};
 use crate::ir::types::{
-    IRDoubleType, IRFloatType, IRIntegerType, IRPointerType, IRType, IRVoidType,
+    IRArrayType, IRDoubleType, IRFloatType, IRFunctionType, IRIntegerType, IRIntegerTypeSize,
+    IRPointerType, IRStructureType, IRType, IRTypeKind, IRVoidType,
 };
 use indexmap::IndexMap;
-use std::fmt::{Debug, Display};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Display};
+use std::io;
+
+/// How deeply constants, operands and types may nest in parsed or decoded input, so that a
+/// hostile module cannot overflow the stack of the recursive code reading it.
+pub(crate) const MAX_NESTING_DEPTH: usize = 256;
 
 pub mod base;
+pub mod binary;
+pub mod builder;
+pub mod compact;
 pub mod instruction;
+pub mod interp;
+pub mod layout;
+pub mod lower;
 pub mod operand;
+pub mod parser;
+pub mod rewrite;
 pub mod structure;
+pub mod type_check;
 pub mod types;
+pub mod verify;
+/// The value of a constant pool entry. Integers keep the two's complement bits of their width,
+/// whether or not their type is unsigned.
+#[derive(Clone, Debug, PartialEq)]
+pub enum IRConstantValue {
+    I1(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Null,
+    /// A string literal; the constant itself is the address of its NUL-terminated bytes.
+    Bytes(Vec<u8>),
+    /// All bits zero, whatever the type.
+    ZeroInitializer,
+    Aggregate(Vec<IRConstantValue>),
+}
+
+impl IRConstantValue {
+    /// An integer of `size` holding the low bits of `value`.
+    pub fn integer(size: &IRIntegerTypeSize, value: i64) -> Self {
+        match size {
+            IRIntegerTypeSize::OneBit => IRConstantValue::I1(value & 1 != 0),
+            IRIntegerTypeSize::OneByte => IRConstantValue::I8(value as i8),
+            IRIntegerTypeSize::TwoBytes => IRConstantValue::I16(value as i16),
+            IRIntegerTypeSize::FourBytes => IRConstantValue::I32(value as i32),
+            IRIntegerTypeSize::EightBytes => IRConstantValue::I64(value),
+        }
+    }
+
+    /// The bits of a scalar value, zero-extended to 64 bits.
+    pub fn bits(&self) -> Option<u64> {
+        match self {
+            IRConstantValue::I1(value) => Some(*value as u64),
+            IRConstantValue::I8(value) => Some(*value as u8 as u64),
+            IRConstantValue::I16(value) => Some(*value as u16 as u64),
+            IRConstantValue::I32(value) => Some(*value as u32 as u64),
+            IRConstantValue::I64(value) => Some(*value as u64),
+            IRConstantValue::F32(value) => Some(value.to_bits() as u64),
+            IRConstantValue::F64(value) => Some(value.to_bits()),
+            IRConstantValue::Null | IRConstantValue::ZeroInitializer => Some(0),
+            IRConstantValue::Bytes(_) | IRConstantValue::Aggregate(_) => None,
+        }
+    }
+
+    /// The value of an integer, sign-extended to 64 bits.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            IRConstantValue::I1(value) => Some(*value as i64),
+            IRConstantValue::I8(value) => Some(*value as i64),
+            IRConstantValue::I16(value) => Some(*value as i64),
+            IRConstantValue::I32(value) => Some(*value as i64),
+            IRConstantValue::I64(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn integer_size(&self) -> Option<IRIntegerTypeSize> {
+        match self {
+            IRConstantValue::I1(_) => Some(IRIntegerTypeSize::OneBit),
+            IRConstantValue::I8(_) => Some(IRIntegerTypeSize::OneByte),
+            IRConstantValue::I16(_) => Some(IRIntegerTypeSize::TwoBytes),
+            IRConstantValue::I32(_) => Some(IRIntegerTypeSize::FourBytes),
+            IRConstantValue::I64(_) => Some(IRIntegerTypeSize::EightBytes),
+            _ => None,
+        }
+    }
+
+    /// Whether this value can be the value of a constant of `_type`. Aggregates only match arrays
+    /// here, since the fields of a structure are not known; see `matches_in`.
+    pub fn matches(&self, _type: &dyn IRType) -> bool {
+        self.matches_with(_type, None)
+    }
+
+    /// Like `matches`, checking aggregates of structures against the fields declared in
+    /// `ir_module`.
+    pub fn matches_in(&self, ir_module: &IRModule, _type: &dyn IRType) -> bool {
+        self.matches_with(_type, Some(&ir_module.structures))
+    }
+
+    fn matches_with(
+        &self,
+        _type: &dyn IRType,
+        structures: Option<&IndexMap<String, Box<IRStructure>>>,
+    ) -> bool {
+        let kind = IRTypeKind::of(_type);
+        match self {
+            IRConstantValue::ZeroInitializer => {
+                !matches!(kind, IRTypeKind::Void(_) | IRTypeKind::Function(_))
+            }
+            IRConstantValue::F32(_) => matches!(kind, IRTypeKind::Float(_)),
+            IRConstantValue::F64(_) => matches!(kind, IRTypeKind::Double(_)),
+            IRConstantValue::Null | IRConstantValue::Bytes(_) => kind.is_pointer(),
+            IRConstantValue::Aggregate(values) => match kind {
+                IRTypeKind::Array(array_type) => {
+                    values.len() as u64 == array_type.length
+                        && values.iter().all(|value| {
+                            value.matches_with(array_type.element.as_ref(), structures)
+                        })
+                }
+                IRTypeKind::Structure(structure_type) => structures
+                    .and_then(|structures| structures.get(&structure_type.name))
+                    .is_some_and(|ir_structure| {
+                        values.len() == ir_structure.fields.len()
+                            && values.iter().zip(ir_structure.fields.iter()).all(
+                                |(value, ir_field)| {
+                                    value.matches_with(ir_field._type.as_ref(), structures)
+                                },
+                            )
+                    }),
+                _ => false,
+            },
+            integer => match kind {
+                IRTypeKind::Integer(integer_type) => {
+                    integer.integer_size() == Some(integer_type.size)
+                }
+                // An address given as a number.
+                IRTypeKind::Pointer(_) => matches!(integer, IRConstantValue::I64(_)),
+                _ => false,
+            },
+        }
+    }
+}
+
+impl Display for IRConstantValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IRConstantValue::I1(value) => write!(f, "{}", value),
+            IRConstantValue::I8(value) => write!(f, "{}", value),
+            IRConstantValue::I16(value) => write!(f, "{}", value),
+            IRConstantValue::I32(value) => write!(f, "{}", value),
+            IRConstantValue::I64(value) => write!(f, "{}", value),
+            IRConstantValue::F32(value) => write!(f, "{:?}", value),
+            IRConstantValue::F64(value) => write!(f, "{:?}", value),
+            IRConstantValue::Null => write!(f, "null"),
+            IRConstantValue::Bytes(bytes) => {
+                write!(f, "\"")?;
+                for byte in bytes {
+                    match byte {
+                        b'\n' => write!(f, "\\n")?,
+                        b'\t' => write!(f, "\\t")?,
+                        b'\r' => write!(f, "\\r")?,
+                        0 => write!(f, "\\0")?,
+                        b'"' | b'\\' => write!(f, "\\{}", *byte as char)?,
+                        0x20..0x7F => write!(f, "{}", *byte as char)?,
+                        _ => write!(f, "\\x{:02x}", byte)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            IRConstantValue::ZeroInitializer => write!(f, "zeroinitializer"),
+            IRConstantValue::Aggregate(values) => {
+                let values = values
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "{{{}}}", values)
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct IRConstantPoolEntry {
     pub _type: Box<dyn IRType>,
-    pub value: Box<dyn Display>,
+    pub value: IRConstantValue,
 }
 
 impl IRConstantPoolEntry {
-    pub fn new(_type: Box<dyn IRType>, value: Box<dyn Display>) -> Self {
+    pub fn new(_type: Box<dyn IRType>, value: IRConstantValue) -> Self {
         Self { _type, value }
     }
+
+    /// The value as the text format writes it, with the integers of unsigned types unsigned.
+    pub fn literal(&self) -> String {
+        match (IRTypeKind::of(self._type.as_ref()), self.value.bits()) {
+            (IRTypeKind::Integer(integer), Some(bits)) if integer.unsigned => bits.to_string(),
+            _ => self.value.to_string(),
+        }
+    }
 }
 
 impl Display for IRConstantPoolEntry {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Entry{{type={}, value={}}}", self._type, self.value)
+        write!(f, "Entry{{type={}, value={}}}", self._type, self.literal())
     }
 }
 
@@ -39,31 +232,154 @@ impl Debug for IRConstantPoolEntry {
         let mut debug_struct = f.debug_struct("IRConstantPoolEntry");
 
         debug_struct.field("type", &self._type);
-        debug_struct.field("value", &format!("{}", self.value));
-        
+        debug_struct.field("value", &self.value);
+
         debug_struct.finish()
     }
 }
 
+/// A scalar constant pool entry reduced to the bits code generators load.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum IRConstantData {
+    Integer(u64),
+    Float(f32),
+    Double(f64),
+    /// A string literal; the constant itself is the address of its NUL-terminated bytes.
+    String(Vec<u8>),
+}
+
+impl IRConstantData {
+    pub(crate) fn of(entry: &IRConstantPoolEntry) -> Option<Self> {
+        match (&entry.value, IRTypeKind::of(entry._type.as_ref())) {
+            (IRConstantValue::Bytes(bytes), _) => Some(IRConstantData::String(bytes.clone())),
+            (IRConstantValue::F32(value), _) => Some(IRConstantData::Float(*value)),
+            (IRConstantValue::F64(value), _) => Some(IRConstantData::Double(*value)),
+            (IRConstantValue::ZeroInitializer, IRTypeKind::Float(_)) => {
+                Some(IRConstantData::Float(0.0))
+            }
+            (IRConstantValue::ZeroInitializer, IRTypeKind::Double(_)) => {
+                Some(IRConstantData::Double(0.0))
+            }
+            (value, _) => value.bits().map(IRConstantData::Integer),
+        }
+    }
+
+    /// The raw bits of a scalar constant.
+    pub(crate) fn bits(&self) -> Option<u64> {
+        match self {
+            IRConstantData::Integer(value) => Some(*value),
+            IRConstantData::Float(value) => Some(value.to_bits() as u64),
+            IRConstantData::Double(value) => Some(value.to_bits()),
+            IRConstantData::String(_) => None,
+        }
+    }
+}
+
 impl IRNode for IRConstantPoolEntry {
     fn accept(&self, visitor: &dyn IRVisitor) {
         visitor.visit_constant_pool_entry(self)
     }
 }
-#[derive(Debug)]
+/// What makes two constants the same for interning: floats compare by their bits, so `0.0` and
+/// `-0.0` stay apart and a NaN matches itself.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum IRConstantKey {
+    Scalar(std::mem::Discriminant<IRConstantValue>, u64),
+    Bytes(Vec<u8>),
+    Aggregate(Vec<IRConstantKey>),
+}
+
+impl IRConstantKey {
+    fn of_value(value: &IRConstantValue) -> Self {
+        match value {
+            IRConstantValue::Bytes(bytes) => IRConstantKey::Bytes(bytes.clone()),
+            IRConstantValue::Aggregate(values) => {
+                IRConstantKey::Aggregate(values.iter().map(Self::of_value).collect())
+            }
+            scalar => IRConstantKey::Scalar(
+                std::mem::discriminant(scalar),
+                scalar.bits().unwrap_or_default(),
+            ),
+        }
+    }
+
+    pub(crate) fn of(_type: &dyn IRType, value: &IRConstantValue) -> (String, Self) {
+        (_type.to_string(), Self::of_value(value))
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct IRConstantPool {
     pub entries: Vec<Box<IRConstantPoolEntry>>,
+    /// The first index of each distinct constant among the first `interned` entries.
+    indices: HashMap<(String, IRConstantKey), usize>,
+    interned: usize,
 }
 impl IRConstantPool {
     pub fn new() -> Self {
-        Self { entries: vec![] }
+        Self {
+            entries: vec![],
+            indices: HashMap::new(),
+            interned: 0,
+        }
     }
 
+    /// Appends an entry even if an equal one exists; see `intern`.
     pub fn push(&mut self, entry: Box<IRConstantPoolEntry>) -> usize {
         self.entries.push(entry);
 
         self.entries.len() - 1
-    } 
+    }
+
+    /// The constant for `value` of type `_type`, reusing an equal entry if there is one.
+    ///
+    /// # Panics
+    ///
+    /// If a new entry would get an index past `u32::MAX`, the last one an `IRConstant` can name.
+    pub fn intern(&mut self, _type: Box<dyn IRType>, value: IRConstantValue) -> IRConstant {
+        let key = IRConstantKey::of(_type.as_ref(), &value);
+        let index = match self.lookup(&key) {
+            Some(index) => index,
+            None => {
+                let index = self.push(Box::new(IRConstantPoolEntry::new(_type, value)));
+                self.indices.insert(key, index);
+                self.interned = self.entries.len();
+                index
+            }
+        };
+        IRConstant::new(u32::try_from(index).expect("constant pool index overflows u32"))
+    }
+
+    fn lookup(&mut self, key: &(String, IRConstantKey)) -> Option<usize> {
+        // `entries` is public, so entries pushed, replaced or removed behind the pool's back are
+        // caught up with here.
+        let stale = |pool: &Self, index: usize| {
+            pool.entries
+                .get(index)
+                .is_none_or(|entry| IRConstantKey::of(entry._type.as_ref(), &entry.value) != *key)
+        };
+        if self.interned > self.entries.len()
+            || self
+                .indices
+                .get(key)
+                .is_some_and(|index| stale(self, *index))
+        {
+            self.indices.clear();
+            self.interned = 0;
+        }
+        for (index, entry) in self.entries.iter().enumerate().skip(self.interned) {
+            self.indices
+                .entry(IRConstantKey::of(entry._type.as_ref(), &entry.value))
+                .or_insert(index);
+        }
+        self.interned = self.entries.len();
+        self.indices.get(key).copied()
+    }
+}
+impl Default for IRConstantPool {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl Display for IRConstantPool {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -82,7 +398,7 @@ impl IRNode for IRConstantPool {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct IRModule {
     pub structures: IndexMap<String, Box<IRStructure>>,
     pub constant_pool: Box<IRConstantPool>,
@@ -98,7 +414,7 @@ impl IRModule {
     pub fn new() -> Self {
         Self {
             structures: IndexMap::new(),
-            constant_pool: Box::new(IRConstantPool { entries: vec![] }),
+            constant_pool: Box::new(IRConstantPool::new()),
             global_data_section: Box::new(IRGlobalDataSection::new()),
             global_init_section: Box::new(IRControlFlowGraph::new()),
             functions: IndexMap::new(),
@@ -115,7 +431,27 @@ impl IRModule {
 
     #[inline]
     pub fn push_struct(&mut self, structure: IRStructure) {
-        self.structures.insert(structure.name.clone(), Box::new(structure));
+        self.structures
+            .insert(structure.name.clone(), Box::new(structure));
+    }
+
+    #[inline]
+    pub fn get_struct(&self, name: &str) -> Option<&IRStructure> {
+        self.structures
+            .get(name)
+            .map(|structure| structure.as_ref())
+    }
+
+    #[inline]
+    pub fn function_type(&self, name: &str) -> Option<IRFunctionType> {
+        self.functions
+            .get(name)
+            .map(|ir_function| ir_function.function_type())
+    }
+}
+impl Default for IRModule {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -226,14 +562,24 @@ pub trait IRVisitor {
             }
         }
     }
-    fn visit_integer_type(&self, ir_integer_type: &IRIntegerType) {}
-    fn visit_float_type(&self, ir_float_type: &IRFloatType) {}
-    fn visit_double_type(&self, ir_double_type: &IRDoubleType) {}
+    fn visit_integer_type(&self, _ir_integer_type: &IRIntegerType) {}
+    fn visit_float_type(&self, _ir_float_type: &IRFloatType) {}
+    fn visit_double_type(&self, _ir_double_type: &IRDoubleType) {}
     fn visit_pointer_type(&self, ir_pointer_type: &IRPointerType) {
         self.visit_dyn(ir_pointer_type.base.as_ref());
     }
-    fn visit_void_type(&self, ir_void_type: &IRVoidType) {}
-    fn visit_goto(&self, ir_goto: &IRGoto) {}
+    fn visit_void_type(&self, _ir_void_type: &IRVoidType) {}
+    fn visit_structure_type(&self, _ir_structure_type: &IRStructureType) {}
+    fn visit_array_type(&self, ir_array_type: &IRArrayType) {
+        self.visit_dyn(ir_array_type.element.as_ref());
+    }
+    fn visit_function_type(&self, ir_function_type: &IRFunctionType) {
+        self.visit_dyn(ir_function_type.return_type.as_ref());
+        for param in ir_function_type.params.iter() {
+            self.visit_dyn(param.as_ref());
+        }
+    }
+    fn visit_goto(&self, _ir_goto: &IRGoto) {}
     fn visit_conditional_jump(&self, ir_conditional_jump: &IRConditionalJump) {
         self.visit_dyn(ir_conditional_jump._type.as_ref());
         self.visit_dyn(ir_conditional_jump.operand1.as_ref());
@@ -303,7 +649,7 @@ pub trait IRVisitor {
             self.visit_dyn(target.as_ref());
         }
     }
-    fn visit_no_operate(&self, ir_no_operate: &IRNoOperate) {}
+    fn visit_no_operate(&self, _ir_no_operate: &IRNoOperate) {}
     fn visit_increase(&self, ir_increase: &IRIncrease) {
         self.visit_dyn(ir_increase._type.as_ref());
         self.visit_dyn(ir_increase.operand.as_ref());
@@ -322,6 +668,16 @@ pub trait IRVisitor {
         self.visit_dyn(ir_stack_allocate.size.as_ref());
         self.visit_virtual_register(ir_stack_allocate.target.as_ref());
     }
+    fn visit_element_address(&self, ir_element_address: &IRElementAddress) {
+        self.visit_dyn(ir_element_address._type.as_ref());
+        self.visit_dyn(ir_element_address.base.as_ref());
+        for index in ir_element_address.path.iter() {
+            if let IRElementIndex::Index(operand) = index {
+                self.visit_dyn(operand.as_ref());
+            }
+        }
+        self.visit_virtual_register(ir_element_address.target.as_ref());
+    }
     fn visit_type_cast(&self, ir_type_cast: &IRTypeCast) {
         self.visit_dyn(ir_type_cast.original_type.as_ref());
         self.visit_dyn(ir_type_cast.source.as_ref());
@@ -334,15 +690,17 @@ pub trait IRVisitor {
             self.visit_dyn(resource.as_ref());
         }
     }
-    fn visit_constant(&self, ir_constant: &IRConstant) {}
-    fn visit_virtual_register(&self, ir_virtual_register: &IRVirtualRegister) {}
+    fn visit_constant(&self, _ir_constant: &IRConstant) {}
+    fn visit_virtual_register(&self, _ir_virtual_register: &IRVirtualRegister) {}
     fn visit_phi(&self, ir_phi: &IRPhi) {
         self.visit_dyn(ir_phi._type.as_ref());
         for operand in ir_phi.operands.iter() {
             self.visit_dyn(operand.as_ref());
         }
     }
-    fn visit_macro(&self, ir_macro: &IRMacro) {}
+    fn visit_macro(&self, _ir_macro: &IRMacro) {}
+    fn visit_virtual_table(&self, _ir_virtual_table: &IRVirtualTable) {}
+    fn visit_interface_table(&self, _ir_interface_table: &IRInterfaceTable) {}
 }
 
 pub trait IRVisitorImpl: IRVisitor {
@@ -355,7 +713,398 @@ impl<T: IRVisitorImpl + Sized> IRVisitor for T {
     }
 }
 
-pub struct IRDumper {}
-impl IRVisitorImpl for IRDumper {
-    fn visit_function(&self, ir_function: &IRFunction) {}
+/// Prints a module as an indented, assembly-like listing that `parser::parse_module` reads back.
+pub struct IRDumper<W: fmt::Write> {
+    output: RefCell<W>,
+    result: Cell<fmt::Result>,
+}
+
+impl<W: fmt::Write> IRDumper<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output: RefCell::new(output),
+            result: Cell::new(Ok(())),
+        }
+    }
+
+    pub fn dump_module(&self, ir_module: &IRModule) -> fmt::Result {
+        self.visit_module(ir_module);
+        self.result.replace(Ok(()))
+    }
+
+    pub fn dump_function(&self, ir_function: &IRFunction) -> fmt::Result {
+        self.visit_function(ir_function);
+        self.result.replace(Ok(()))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output.into_inner()
+    }
+
+    fn write_line(&self, indent: usize, line: fmt::Arguments) {
+        if self.result.get().is_err() {
+            return;
+        }
+        let mut output = self.output.borrow_mut();
+        let result = write!(output, "{:1$}", "", indent * 4)
+            .and_then(|_| output.write_fmt(line))
+            .and_then(|_| output.write_char('\n'));
+        self.result.set(result);
+    }
+
+    fn write_control_flow_graph(&self, ir_control_flow_graph: &IRControlFlowGraph) {
+        for ir_basic_block in ir_control_flow_graph.basic_blocks.values() {
+            self.write_line(0, format_args!("{}:", ir_basic_block.name));
+            for ir_instruction in ir_basic_block.instructions.iter() {
+                self.write_line(1, format_args!("{}", ir_instruction));
+            }
+        }
+    }
+}
+
+impl IRDumper<String> {
+    pub fn dump_to_string(ir_module: &IRModule) -> String {
+        let dumper = IRDumper::new(String::new());
+        // Writing into a String cannot fail.
+        let _ = dumper.dump_module(ir_module);
+        dumper.into_inner()
+    }
+}
+
+impl<W: io::Write> IRDumper<IRIoWriter<W>> {
+    pub fn dump_to_io(ir_module: &IRModule, output: W) -> io::Result<()> {
+        let dumper = IRDumper::new(IRIoWriter::new(output));
+        let result = dumper.dump_module(ir_module);
+        let writer = dumper.into_inner();
+        match writer.error {
+            Some(error) => Err(error),
+            None => result.map_err(io::Error::other),
+        }
+    }
+}
+
+impl<W: fmt::Write> IRVisitor for IRDumper<W> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_module(&self, ir_module: &IRModule) {
+        let mut sections = 0;
+        let mut separate = |count: usize| {
+            if count > 0 {
+                if sections > 0 {
+                    self.write_line(0, format_args!(""));
+                }
+                sections += 1;
+            }
+        };
+        for ir_structure in ir_module.structures.values() {
+            separate(1);
+            self.visit_structure(ir_structure);
+        }
+        separate(ir_module.constant_pool.entries.len());
+        self.visit_constant_pool(&ir_module.constant_pool);
+        separate(ir_module.global_data_section.data.len());
+        self.visit_global_data_section(&ir_module.global_data_section);
+        if !ir_module.global_init_section.basic_blocks.is_empty() {
+            separate(1);
+            self.write_line(0, format_args!("init {{"));
+            self.write_control_flow_graph(&ir_module.global_init_section);
+            self.write_line(0, format_args!("}}"));
+        }
+        for ir_function in ir_module.functions.values() {
+            separate(1);
+            self.visit_function(ir_function);
+        }
+        separate(ir_module.name2vtable_keys.len() + ir_module.name2itable_keys.len());
+        for (name, keys) in ir_module.name2vtable_keys.iter() {
+            self.write_line(0, format_args!("vtable {} = [{}]", name, keys.join(", ")));
+        }
+        for (name, keys) in ir_module.name2itable_keys.iter() {
+            self.write_line(0, format_args!("itable {} = [{}]", name, keys.join(", ")));
+        }
+        if let Some(entry_point) = ir_module.entry_point.as_ref() {
+            separate(1);
+            self.write_line(0, format_args!("entry_point {}", entry_point));
+        }
+    }
+    fn visit_constant_pool(&self, ir_constant_pool: &IRConstantPool) {
+        for (index, entry) in ir_constant_pool.entries.iter().enumerate() {
+            self.write_line(
+                0,
+                format_args!("constant ${} = {} {}", index, entry._type, entry.literal()),
+            );
+        }
+    }
+    fn visit_function(&self, ir_function: &IRFunction) {
+        let (arguments, locals) = ir_function
+            .fields
+            .split_at(ir_function.arguments_count.min(ir_function.fields.len()));
+        self.write_line(
+            0,
+            format_args!(
+                "function {} {}({}) {{",
+                ir_function.return_type,
+                ir_function.name,
+                arguments
+                    .iter()
+                    .map(|field| format!("{} {}", field._type, field.name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        );
+        for field in locals {
+            self.write_line(1, format_args!("local {} {}", field._type, field.name));
+        }
+        self.write_control_flow_graph(&ir_function.control_flow_graph);
+        self.write_line(0, format_args!("}}"));
+    }
+    fn visit_structure(&self, ir_structure: &IRStructure) {
+        self.write_line(0, format_args!("structure {} {{", ir_structure.name));
+        for ir_field in ir_structure.fields.iter() {
+            self.write_line(1, format_args!("{} {}", ir_field._type, ir_field.name));
+        }
+        self.write_line(0, format_args!("}}"));
+    }
+    fn visit_global_data(&self, ir_global_data: &IRGlobalData) {
+        self.write_line(0, format_args!("global {}", ir_global_data));
+    }
+}
+
+/// Adapts an `io::Write` sink for `IRDumper`, keeping the underlying I/O error.
+pub struct IRIoWriter<W: io::Write> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> IRIoWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, error: None }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: io::Write> fmt::Write for IRIoWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|error| {
+            self.error = Some(error);
+            fmt::Error
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn i64_type() -> Box<dyn IRType> {
+        Box::new(IRIntegerType::new(IRIntegerTypeSize::EightBytes, false))
+    }
+
+    fn double_type() -> Box<dyn IRType> {
+        Box::new(IRDoubleType::new())
+    }
+
+    fn intern(pool: &mut IRConstantPool, _type: Box<dyn IRType>, value: IRConstantValue) -> u32 {
+        pool.intern(_type, value).index
+    }
+
+    /// Every kind of item, separated into sections the way the dumper writes them.
+    const MODULE: &str = "\
+structure Node {
+    i32 value
+    %Node* next
+}
+
+structure Empty {
+}
+
+constant $0 = i32 1
+constant $1 = i8* \"hi\\n\"
+
+global counter, size=$0
+global table, values=[IRVirtualTable{functions={main}}]
+
+init {
+entry:
+    return
+}
+
+function i32 main(i32 argc, i8** argv) {
+    local i64 tmp
+entry:
+    %t = add i32 %argc, $0
+    goto exit
+exit:
+    return %t
+}
+
+function void nothing() {
+}
+
+vtable Node = [main]
+itable Node = [main, nothing]
+
+entry_point main
+";
+
+    /// Fails every write after the first `budget` bytes.
+    struct IRFailingWriter {
+        budget: usize,
+        written: String,
+    }
+
+    impl fmt::Write for IRFailingWriter {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            if self.written.len() + s.len() > self.budget {
+                return Err(fmt::Error);
+            }
+            self.written.push_str(s);
+            Ok(())
+        }
+    }
+
+    impl io::Write for IRFailingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            match fmt::Write::write_str(self, std::str::from_utf8(buf).unwrap()) {
+                Ok(()) => Ok(buf.len()),
+                Err(_) => Err(io::Error::new(io::ErrorKind::WriteZero, "out of budget")),
+            }
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn modules_dump_to_the_text_they_were_parsed_from() {
+        let ir_module = parser::parse_module(MODULE).unwrap();
+        let dumped = IRDumper::dump_to_string(&ir_module);
+        assert_eq!(dumped, MODULE);
+        let reparsed = parser::parse_module(&dumped).unwrap();
+        assert_eq!(IRDumper::dump_to_string(&reparsed), dumped);
+    }
+
+    #[test]
+    fn empty_sections_are_left_out() {
+        assert_eq!(IRDumper::dump_to_string(&IRModule::new()), "");
+        let ir_module = parser::parse_module("entry_point main\n").unwrap();
+        assert_eq!(IRDumper::dump_to_string(&ir_module), "entry_point main\n");
+    }
+
+    #[test]
+    fn functions_dump_on_their_own() {
+        let ir_module = parser::parse_module(MODULE).unwrap();
+        let dumper = IRDumper::new(String::new());
+        dumper
+            .dump_function(&ir_module.functions["nothing"])
+            .unwrap();
+        assert_eq!(dumper.into_inner(), "function void nothing() {\n}\n");
+    }
+
+    #[test]
+    fn dumping_stops_at_the_first_failed_write() {
+        let ir_module = parser::parse_module(MODULE).unwrap();
+        let dumper = IRDumper::new(IRFailingWriter {
+            budget: 17,
+            written: String::new(),
+        });
+        assert_eq!(dumper.dump_module(&ir_module), Err(fmt::Error));
+        assert_eq!(dumper.into_inner().written, "structure Node {\n");
+
+        // The error does not carry over to the next dump.
+        let dumper = IRDumper::new(String::new());
+        dumper.dump_module(&IRModule::new()).unwrap();
+    }
+
+    #[test]
+    fn io_dumps_keep_the_io_error() {
+        let ir_module = parser::parse_module(MODULE).unwrap();
+        let mut output = vec![];
+        IRDumper::dump_to_io(&ir_module, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), MODULE);
+
+        let writer = IRFailingWriter {
+            budget: 20,
+            written: String::new(),
+        };
+        let error = IRDumper::dump_to_io(&ir_module, writer).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WriteZero);
+        assert_eq!(error.to_string(), "out of budget");
+    }
+
+    #[test]
+    fn intern_reuses_equal_constants() {
+        let mut pool = IRConstantPool::new();
+        assert_eq!(intern(&mut pool, i64_type(), IRConstantValue::I64(1)), 0);
+        assert_eq!(intern(&mut pool, i64_type(), IRConstantValue::I64(2)), 1);
+        assert_eq!(intern(&mut pool, i64_type(), IRConstantValue::I64(1)), 0);
+        // The type is part of the constant.
+        let u64_type = Box::new(IRIntegerType::new(IRIntegerTypeSize::EightBytes, true));
+        assert_eq!(intern(&mut pool, u64_type, IRConstantValue::I64(1)), 2);
+        assert_eq!(pool.entries.len(), 3);
+    }
+
+    #[test]
+    fn intern_compares_floats_by_their_bits() {
+        let mut pool = IRConstantPool::new();
+        assert_eq!(
+            intern(&mut pool, double_type(), IRConstantValue::F64(0.0)),
+            0
+        );
+        assert_eq!(
+            intern(&mut pool, double_type(), IRConstantValue::F64(-0.0)),
+            1
+        );
+        assert_eq!(
+            intern(&mut pool, double_type(), IRConstantValue::F64(f64::NAN)),
+            2
+        );
+        assert_eq!(
+            intern(&mut pool, double_type(), IRConstantValue::F64(f64::NAN)),
+            2
+        );
+        assert_eq!(
+            intern(&mut pool, double_type(), IRConstantValue::F64(-0.0)),
+            1
+        );
+    }
+
+    #[test]
+    fn intern_finds_entries_pushed_directly() {
+        let mut pool = IRConstantPool::new();
+        for _ in 0..2 {
+            pool.push(Box::new(IRConstantPoolEntry::new(
+                i64_type(),
+                IRConstantValue::I64(7),
+            )));
+        }
+        assert_eq!(intern(&mut pool, i64_type(), IRConstantValue::I64(7)), 0);
+        pool.entries.push(Box::new(IRConstantPoolEntry::new(
+            i64_type(),
+            IRConstantValue::I64(8),
+        )));
+        assert_eq!(intern(&mut pool, i64_type(), IRConstantValue::I64(8)), 2);
+        assert_eq!(pool.entries.len(), 3);
+    }
+
+    #[test]
+    fn intern_rebuilds_its_index_after_entries_change() {
+        let mut pool = IRConstantPool::new();
+        intern(&mut pool, i64_type(), IRConstantValue::I64(1));
+        intern(&mut pool, i64_type(), IRConstantValue::I64(2));
+
+        // A replaced entry is neither found under its old value nor missed under its new one.
+        *pool.entries[0] = IRConstantPoolEntry::new(i64_type(), IRConstantValue::I64(3));
+        assert_eq!(intern(&mut pool, i64_type(), IRConstantValue::I64(1)), 2);
+        assert_eq!(intern(&mut pool, i64_type(), IRConstantValue::I64(3)), 0);
+
+        // Removed entries are forgotten.
+        pool.entries.truncate(1);
+        assert_eq!(intern(&mut pool, i64_type(), IRConstantValue::I64(2)), 1);
+        assert_eq!(intern(&mut pool, i64_type(), IRConstantValue::I64(3)), 0);
+        assert_eq!(pool.entries.len(), 2);
+    }
 }
@@ -1,17 +1,20 @@
-use crate::ir::instruction::IRInstruction;
-use crate::ir::operand::IROperand;
-use crate::ir::structure::IRField;
-use crate::ir::types::IRType;
 use crate::ir::IRVisitor;
+use crate::ir::instruction::{
+    IRConditionalJump, IRGoto, IRInstruction, IRReturn, IRSetVirtualRegister,
+};
+use crate::ir::operand::{IROperand, IRPhi};
+use crate::ir::structure::IRField;
+use crate::ir::types::{IRFunctionType, IRType};
 use indexmap::IndexMap;
-use std::collections::BTreeMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashSet};
 use std::fmt;
 
 pub trait IRNode: fmt::Display {
     fn accept(&self, visitor: &dyn IRVisitor);
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum IRCondition {
     Equal,
     NotEqual,
@@ -38,7 +41,7 @@ impl fmt::Display for IRCondition {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct IRGlobalData {
     pub name: String,
     pub size: Option<Box<dyn IROperand>>,
@@ -79,7 +82,7 @@ impl IRNode for IRGlobalData {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct IRGlobalDataSection {
     pub data: Vec<IRGlobalData>,
 }
@@ -89,6 +92,11 @@ impl IRGlobalDataSection {
         Self { data: vec![] }
     }
 }
+impl Default for IRGlobalDataSection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl fmt::Display for IRGlobalDataSection {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -121,6 +129,18 @@ impl IRBasicBlock {
             instructions: vec![],
         }
     }
+
+    /// The phis assigned to registers in this block, with the register each one defines.
+    pub fn phis(&self) -> Vec<(String, IRPhi)> {
+        let collector = IRPhiCollector {
+            target: RefCell::new(None),
+            phis: RefCell::new(vec![]),
+        };
+        for instruction in &self.instructions {
+            instruction.accept(&collector);
+        }
+        collector.phis.into_inner()
+    }
 }
 impl fmt::Display for IRBasicBlock {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -146,8 +166,8 @@ impl IRNode for IRBasicBlock {
 #[derive(Debug, Clone)]
 pub struct IRControlFlowGraph {
     pub basic_blocks: IndexMap<String, Box<IRBasicBlock>>,
-    pub out_edges: BTreeMap<Box<IRBasicBlock>, Vec<Box<IRBasicBlock>>>,
-    pub in_edges: BTreeMap<Box<IRBasicBlock>, Vec<Box<IRBasicBlock>>>,
+    pub out_edges: BTreeMap<String, Vec<String>>,
+    pub in_edges: BTreeMap<String, Vec<String>>,
 }
 
 impl IRControlFlowGraph {
@@ -161,24 +181,203 @@ impl IRControlFlowGraph {
             in_edges,
         }
     }
+    /// Appends a block (or replaces the block of the same name in place) and updates the edges
+    /// of it and of the block that may now fall through into it.
     pub fn add_basic_block(&mut self, basic_block: Box<IRBasicBlock>) {
+        let name = basic_block.name.clone();
+        let (index, _) = self.basic_blocks.insert_full(name.clone(), basic_block);
+        self.update_edges(&name);
+        if index > 0 {
+            self.update_edges_at(index - 1);
+        }
+    }
+    /// Inserts a block at `index` in layout order, shifting the following blocks.
+    pub fn insert_basic_block(&mut self, index: usize, basic_block: Box<IRBasicBlock>) {
+        let name = basic_block.name.clone();
+        self.remove_basic_block(&name);
+        let index = index.min(self.basic_blocks.len());
         self.basic_blocks
-            .insert(basic_block.name.clone(), basic_block);
+            .shift_insert(index, name.clone(), basic_block);
+        self.update_edges(&name);
+        if index > 0 {
+            self.update_edges_at(index - 1);
+        }
+    }
+    /// Removes a block. Jumps to it from other blocks are left untouched and keep their edges.
+    pub fn remove_basic_block(&mut self, name: &str) -> Option<Box<IRBasicBlock>> {
+        let (index, _, basic_block) = self.basic_blocks.shift_remove_full(name)?;
+        // Its former successors must no longer report it as a predecessor.
+        self.out_edges.remove(name);
+        self.in_edges.retain(|_, predecessors| {
+            predecessors.retain(|predecessor| predecessor != name);
+            !predecessors.is_empty()
+        });
+        if index > 0 {
+            self.update_edges_at(index - 1);
+        }
+        Some(basic_block)
+    }
+    /// Moves the instructions of `name` from `at` onwards into a new block placed right after it,
+    /// so that the original block falls through into the new one.
+    pub fn split_basic_block(&mut self, name: &str, at: usize, new_name: String) -> bool {
+        if self.basic_blocks.contains_key(&new_name) {
+            return false;
+        }
+        let Some((index, _, basic_block)) = self.basic_blocks.get_full_mut(name) else {
+            return false;
+        };
+        if at > basic_block.instructions.len() {
+            return false;
+        }
+        let mut new_basic_block = IRBasicBlock::new(new_name.clone());
+        new_basic_block.instructions = basic_block.instructions.split_off(at);
+        self.basic_blocks
+            .shift_insert(index + 1, new_name.clone(), Box::new(new_basic_block));
+        self.update_edges(name);
+        self.update_edges(&new_name);
+        true
+    }
+    pub fn successors(&self, name: &str) -> &[String] {
+        self.out_edges
+            .get(name)
+            .map_or(&[], |edges| edges.as_slice())
+    }
+    pub fn predecessors(&self, name: &str) -> &[String] {
+        self.in_edges
+            .get(name)
+            .map_or(&[], |edges| edges.as_slice())
+    }
+    /// Recomputes every edge, for use after instructions were edited through `basic_blocks`.
+    pub fn build_edges(&mut self) {
+        self.out_edges.clear();
+        self.in_edges.clear();
+        for index in 0..self.basic_blocks.len() {
+            self.update_edges_at(index);
+        }
+    }
+    /// Recomputes the out edges of one block from its jumps and fall-through.
+    pub fn update_edges(&mut self, name: &str) {
+        if let Some(index) = self.basic_blocks.get_index_of(name) {
+            self.update_edges_at(index);
+        }
+    }
+    fn update_edges_at(&mut self, index: usize) {
+        let successors = self.compute_successors_at(index);
+        let name = self.basic_blocks.get_index(index).unwrap().0.clone();
+        self.set_out_edges(&name, successors);
+    }
+    /// Derives the successors of the block at `index` from its instructions, ignoring the
+    /// stored edges.
+    pub(crate) fn compute_successors_at(&self, index: usize) -> Vec<String> {
+        let (_, basic_block) = self.basic_blocks.get_index(index).unwrap();
+        let collector = IRBranchCollector::default();
+        for instruction in basic_block.instructions.iter() {
+            collector.terminates.set(false);
+            instruction.accept(&collector);
+        }
+        let mut successors = collector.targets.into_inner();
+        if !collector.terminates.get()
+            && let Some((next, _)) = self.basic_blocks.get_index(index + 1)
+        {
+            successors.push(next.clone());
+        }
+        let mut seen = HashSet::new();
+        successors.retain(|successor| seen.insert(successor.clone()));
+        successors
+    }
+    fn set_out_edges(&mut self, name: &str, successors: Vec<String>) {
+        if let Some(old) = self.out_edges.remove(name) {
+            for successor in old {
+                if let Some(predecessors) = self.in_edges.get_mut(&successor) {
+                    predecessors.retain(|predecessor| predecessor != name);
+                    if predecessors.is_empty() {
+                        self.in_edges.remove(&successor);
+                    }
+                }
+            }
+        }
+        for successor in successors.iter() {
+            self.in_edges
+                .entry(successor.clone())
+                .or_default()
+                .push(name.to_string());
+        }
+        self.out_edges.insert(name.to_string(), successors);
+    }
+}
+impl Default for IRControlFlowGraph {
+    fn default() -> Self {
+        Self::new()
     }
 }
 impl fmt::Display for IRControlFlowGraph {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let edges = |edges: &BTreeMap<String, Vec<String>>| {
+            edges
+                .iter()
+                .map(|(name, names)| format!("{}=[{}]", name, names.join(", ")))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
         write!(
             f,
-            "IRControlFlowGraph{{basicBlocks=[{}], outEdges=[], inEdges=[]}}",
+            "IRControlFlowGraph{{basicBlocks=[{}], outEdges={{{}}}, inEdges={{{}}}}}",
             self.basic_blocks
                 .values()
                 .map(|b| b.to_string())
                 .collect::<Vec<_>>()
-                .join(", ")
+                .join(", "),
+            edges(&self.out_edges),
+            edges(&self.in_edges)
         )
     }
 }
+
+/// Collects the jump targets of a block and whether its last instruction ends control flow.
+#[derive(Default)]
+struct IRBranchCollector {
+    targets: RefCell<Vec<String>>,
+    terminates: Cell<bool>,
+}
+
+impl IRVisitor for IRBranchCollector {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_goto(&self, ir_goto: &IRGoto) {
+        self.targets.borrow_mut().push(ir_goto.target.clone());
+        self.terminates.set(true);
+    }
+    fn visit_conditional_jump(&self, ir_conditional_jump: &IRConditionalJump) {
+        self.targets
+            .borrow_mut()
+            .push(ir_conditional_jump.target.clone());
+    }
+    fn visit_return(&self, _ir_return: &IRReturn) {
+        self.terminates.set(true);
+    }
+}
+struct IRPhiCollector {
+    target: RefCell<Option<String>>,
+    phis: RefCell<Vec<(String, IRPhi)>>,
+}
+
+impl IRVisitor for IRPhiCollector {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_set_virtual_register(&self, ir_set_virtual_register: &IRSetVirtualRegister) {
+        self.target
+            .replace(Some(ir_set_virtual_register.target.name.clone()));
+        ir_set_virtual_register.source.accept(self);
+        self.target.replace(None);
+    }
+    fn visit_phi(&self, ir_phi: &IRPhi) {
+        if let Some(target) = self.target.borrow().clone() {
+            self.phis.borrow_mut().push((target, ir_phi.clone()));
+        }
+    }
+}
 #[derive(Debug, Clone)]
 pub struct IRFunction {
     pub return_type: Box<dyn IRType>,
@@ -203,6 +402,19 @@ impl IRFunction {
             control_flow_graph,
         }
     }
+
+    /// The signature callers see: the return type and the types of the leading argument fields.
+    pub fn function_type(&self) -> IRFunctionType {
+        IRFunctionType::new(
+            self.return_type.clone(),
+            self.fields
+                .iter()
+                .take(self.arguments_count)
+                .map(|field| field._type.clone())
+                .collect(),
+            false,
+        )
+    }
 }
 impl fmt::Display for IRFunction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -226,3 +438,176 @@ impl IRNode for IRFunction {
         visitor.visit_function(self);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::instruction::IRNoOperate;
+    use crate::ir::operand::IRVirtualRegister;
+    use crate::ir::types::{IRIntegerType, IRIntegerTypeSize};
+
+    fn goto(target: &str) -> Box<dyn IRInstruction> {
+        Box::new(IRGoto::new(target.to_string()))
+    }
+
+    fn branch(target: &str) -> Box<dyn IRInstruction> {
+        Box::new(IRConditionalJump::new(
+            false,
+            Box::new(IRIntegerType::new(IRIntegerTypeSize::OneByte, false)),
+            IRCondition::IfTrue,
+            Box::new(IRVirtualRegister::new("c".to_string())),
+            None,
+            target.to_string(),
+        ))
+    }
+
+    fn ret() -> Box<dyn IRInstruction> {
+        Box::new(IRReturn::new(None))
+    }
+
+    fn nop() -> Box<dyn IRInstruction> {
+        Box::new(IRNoOperate::new())
+    }
+
+    fn block(name: &str, instructions: Vec<Box<dyn IRInstruction>>) -> Box<IRBasicBlock> {
+        let mut basic_block = IRBasicBlock::new(name.to_string());
+        basic_block.instructions = instructions;
+        Box::new(basic_block)
+    }
+
+    /// `entry` branches to `exit` or falls into `loop`, which jumps back to itself or falls into
+    /// `exit`.
+    fn looping_graph() -> IRControlFlowGraph {
+        let mut graph = IRControlFlowGraph::new();
+        graph.add_basic_block(block("entry", vec![nop(), branch("exit")]));
+        graph.add_basic_block(block("loop", vec![branch("loop")]));
+        graph.add_basic_block(block("exit", vec![ret()]));
+        graph
+    }
+
+    /// Checks the edges against `expected` successors, and that they match a full rebuild.
+    fn assert_edges(graph: &IRControlFlowGraph, expected: &[(&str, &[&str])]) {
+        let names = graph.basic_blocks.keys().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            expected.iter().map(|(name, _)| *name).collect::<Vec<_>>()
+        );
+        for (name, successors) in expected {
+            assert_eq!(
+                graph.successors(name),
+                *successors,
+                "successors of {}",
+                name
+            );
+            for successor in successors.iter() {
+                assert!(graph.predecessors(successor).contains(&name.to_string()));
+            }
+        }
+        let mut rebuilt = graph.clone();
+        rebuilt.build_edges();
+        assert_eq!(graph.out_edges, rebuilt.out_edges);
+        // Predecessors are kept in the order the edges were added in.
+        let sorted = |edges: &BTreeMap<String, Vec<String>>| {
+            let mut edges = edges.clone();
+            edges.values_mut().for_each(|names| names.sort());
+            edges
+        };
+        assert_eq!(sorted(&graph.in_edges), sorted(&rebuilt.in_edges));
+    }
+
+    #[test]
+    fn edges_follow_jumps_and_fall_through() {
+        let graph = looping_graph();
+        assert_edges(
+            &graph,
+            &[
+                ("entry", &["exit", "loop"]),
+                ("loop", &["loop", "exit"]),
+                ("exit", &[]),
+            ],
+        );
+        assert_eq!(graph.predecessors("entry"), &[] as &[String]);
+        assert_eq!(graph.predecessors("loop"), ["entry", "loop"]);
+        assert_eq!(graph.predecessors("exit"), ["entry", "loop"]);
+        assert_eq!(graph.successors("missing"), &[] as &[String]);
+    }
+
+    #[test]
+    fn added_blocks_update_the_block_falling_into_them() {
+        let mut graph = IRControlFlowGraph::new();
+        graph.add_basic_block(block("entry", vec![nop()]));
+        assert_edges(&graph, &[("entry", &[])]);
+        graph.add_basic_block(block("next", vec![ret()]));
+        assert_edges(&graph, &[("entry", &["next"]), ("next", &[])]);
+        // Replacing a block keeps its place.
+        graph.add_basic_block(block("entry", vec![goto("entry")]));
+        assert_edges(&graph, &[("entry", &["entry"]), ("next", &[])]);
+    }
+
+    #[test]
+    fn inserted_blocks_take_their_place_in_layout_order() {
+        let mut graph = looping_graph();
+        graph.insert_basic_block(1, block("middle", vec![nop()]));
+        assert_edges(
+            &graph,
+            &[
+                ("entry", &["exit", "middle"]),
+                ("middle", &["loop"]),
+                ("loop", &["loop", "exit"]),
+                ("exit", &[]),
+            ],
+        );
+        // Inserting an existing block moves it.
+        graph.insert_basic_block(0, block("exit", vec![goto("middle")]));
+        assert_edges(
+            &graph,
+            &[
+                ("exit", &["middle"]),
+                ("entry", &["exit", "middle"]),
+                ("middle", &["loop"]),
+                ("loop", &["loop"]),
+            ],
+        );
+    }
+
+    #[test]
+    fn removed_blocks_leave_no_back_edges() {
+        let mut graph = looping_graph();
+        let removed = graph.remove_basic_block("loop").unwrap();
+        assert_eq!(removed.name, "loop");
+        assert_eq!(graph.predecessors("exit"), ["entry"]);
+        assert!(!graph.in_edges.values().flatten().any(|name| name == "loop"));
+        assert!(!graph.out_edges.contains_key("loop"));
+        assert_eq!(graph.successors("entry"), ["exit"]);
+        assert!(graph.remove_basic_block("loop").is_none());
+    }
+
+    #[test]
+    fn removed_jump_targets_keep_the_jumps_to_them() {
+        let mut graph = looping_graph();
+        graph.remove_basic_block("exit");
+        assert_eq!(graph.successors("entry"), ["exit", "loop"]);
+        assert_eq!(graph.successors("loop"), ["loop"]);
+        assert_eq!(graph.predecessors("exit"), ["entry"]);
+    }
+
+    #[test]
+    fn split_blocks_fall_through_into_their_tail() {
+        let mut graph = looping_graph();
+        assert!(graph.split_basic_block("entry", 1, "tail".to_string()));
+        assert_edges(
+            &graph,
+            &[
+                ("entry", &["tail"]),
+                ("tail", &["exit", "loop"]),
+                ("loop", &["loop", "exit"]),
+                ("exit", &[]),
+            ],
+        );
+        assert_eq!(graph.basic_blocks["entry"].instructions.len(), 1);
+        assert_eq!(graph.basic_blocks["tail"].instructions.len(), 1);
+        assert!(!graph.split_basic_block("entry", 0, "loop".to_string()));
+        assert!(!graph.split_basic_block("entry", 2, "after".to_string()));
+        assert!(!graph.split_basic_block("missing", 0, "after".to_string()));
+    }
+}
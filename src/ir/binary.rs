@@ -0,0 +1,1371 @@
+//! A compact binary encoding of `IRModule`, for handing modules over and caching them when the
+//! textual form is too slow to parse.
+//!
+//! A module is encoded as the magic bytes `LGIR` and a little-endian `u32` version, followed by:
+//!
+//! 1. the string table, every name in the module once;
+//! 2. the type table, every distinct type once, each referring only to strings and to types
+//!    earlier in the table;
+//! 3. the structures, the constant pool, the global data and the global init section;
+//! 4. the functions, each with its fields and basic blocks;
+//! 5. the vtable and itable keys and the entry point.
+//!
+//! Counts, indices and lengths are unsigned LEB128, signed integers are zigzag LEB128 and floats
+//! are their little-endian bits. Names and types are written as indices into their tables.
+//! Instructions, operands, types and constant values each start with a tag; the instruction tags
+//! follow the order of `IRInstructionKind`.
+//!
+//! The reader treats its input as untrusted: nesting is limited, as is how much a module may grow
+//! over its encoding by referring to large strings and types many times.
+
+use crate::ir::base::{IRBasicBlock, IRCondition, IRControlFlowGraph, IRFunction, IRGlobalData};
+use crate::ir::instruction::{
+    IRAsm, IRCalculate, IRCalculateOperator, IRConditionalJump, IRDecrease, IRElementAddress,
+    IRElementIndex, IRFree, IRGet, IRGoto, IRIncrease, IRInstruction, IRInstructionKind, IRInvoke,
+    IRMalloc, IRNegate, IRNoOperate, IRNot, IRRealloc, IRReturn, IRSet, IRSetVirtualRegister,
+    IRStackAllocate, IRTypeCast, IRTypeCastKind,
+};
+use crate::ir::operand::{
+    IRConstant, IRInterfaceTable, IRInterfaceTableEntry, IRMacro, IROperand, IROperandKind, IRPhi,
+    IRVirtualRegister, IRVirtualTable,
+};
+use crate::ir::structure::{IRField, IRStructure};
+use crate::ir::types::{
+    IRArrayType, IRDoubleType, IRFloatType, IRFunctionType, IRIntegerType, IRIntegerTypeSize,
+    IRPointerType, IRStructureType, IRType, IRTypeKind, IRVoidType,
+};
+use crate::ir::{IRConstantPoolEntry, IRConstantValue, IRModule, MAX_NESTING_DEPTH};
+use indexmap::{IndexMap, IndexSet};
+use std::fmt;
+
+pub const MAGIC: [u8; 4] = *b"LGIR";
+/// Bumped whenever the encoding changes; readers reject every other version.
+pub const VERSION: u32 = 1;
+/// How many string bytes and type nodes the reader may copy out of its tables per byte of input,
+/// so that a small input referring to a large entry over and over cannot exhaust memory.
+const EXPANSION_PER_BYTE: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IRBinaryErrorKind {
+    BadMagic,
+    UnsupportedVersion(u32),
+    /// The input ends in the middle of something.
+    Truncated,
+    UnknownTag {
+        what: &'static str,
+        tag: u64,
+    },
+    /// An index into the string table, the type table or a forward reference in the type table.
+    InvalidIndex {
+        what: &'static str,
+        index: u64,
+    },
+    InvalidUtf8,
+    /// Two lists that must be parallel, such as the labels and operands of a phi, differ in length.
+    LengthMismatch(&'static str),
+    /// A number does not fit the value it encodes.
+    Overflow,
+    /// Constants, operands or types nest deeper than the reader allows.
+    TooDeep,
+    /// The module would be far larger than its encoding, through repeated references to large
+    /// strings or types.
+    TooLarge,
+    TrailingBytes,
+}
+
+impl fmt::Display for IRBinaryErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IRBinaryErrorKind::BadMagic => write!(f, "not an encoded module"),
+            IRBinaryErrorKind::UnsupportedVersion(version) => {
+                write!(f, "unsupported version {}, expected {}", version, VERSION)
+            }
+            IRBinaryErrorKind::Truncated => write!(f, "unexpected end of input"),
+            IRBinaryErrorKind::UnknownTag { what, tag } => {
+                write!(f, "unknown {} tag {}", what, tag)
+            }
+            IRBinaryErrorKind::InvalidIndex { what, index } => {
+                write!(f, "invalid {} index {}", what, index)
+            }
+            IRBinaryErrorKind::InvalidUtf8 => write!(f, "string is not UTF-8"),
+            IRBinaryErrorKind::LengthMismatch(what) => write!(f, "mismatched {} lengths", what),
+            IRBinaryErrorKind::Overflow => write!(f, "number out of range"),
+            IRBinaryErrorKind::TooDeep => write!(f, "nested too deeply"),
+            IRBinaryErrorKind::TooLarge => write!(f, "module too large for its encoding"),
+            IRBinaryErrorKind::TrailingBytes => write!(f, "unexpected bytes after the module"),
+        }
+    }
+}
+
+/// Error produced when an encoded module cannot be read, located by byte offset.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IRBinaryError {
+    pub offset: usize,
+    pub kind: IRBinaryErrorKind,
+}
+
+impl fmt::Display for IRBinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "offset {}: {}", self.offset, self.kind)
+    }
+}
+
+impl std::error::Error for IRBinaryError {}
+
+pub type IRBinaryResult<T> = Result<T, IRBinaryError>;
+
+const TYPE_INTEGER: u8 = 0;
+const TYPE_FLOAT: u8 = 1;
+const TYPE_DOUBLE: u8 = 2;
+const TYPE_VOID: u8 = 3;
+const TYPE_POINTER: u8 = 4;
+const TYPE_STRUCTURE: u8 = 5;
+const TYPE_ARRAY: u8 = 6;
+const TYPE_FUNCTION: u8 = 7;
+
+const VALUE_I1: u8 = 0;
+const VALUE_I8: u8 = 1;
+const VALUE_I16: u8 = 2;
+const VALUE_I32: u8 = 3;
+const VALUE_I64: u8 = 4;
+const VALUE_F32: u8 = 5;
+const VALUE_F64: u8 = 6;
+const VALUE_NULL: u8 = 7;
+const VALUE_BYTES: u8 = 8;
+const VALUE_ZERO_INITIALIZER: u8 = 9;
+const VALUE_AGGREGATE: u8 = 10;
+
+const OPERAND_VIRTUAL_REGISTER: u8 = 0;
+const OPERAND_CONSTANT: u8 = 1;
+const OPERAND_MACRO: u8 = 2;
+const OPERAND_PHI: u8 = 3;
+const OPERAND_VIRTUAL_TABLE: u8 = 4;
+const OPERAND_INTERFACE_TABLE: u8 = 5;
+
+const INSTRUCTION_GOTO: u8 = 0;
+const INSTRUCTION_CONDITIONAL_JUMP: u8 = 1;
+const INSTRUCTION_NO_OPERATE: u8 = 2;
+const INSTRUCTION_RETURN: u8 = 3;
+const INSTRUCTION_MALLOC: u8 = 4;
+const INSTRUCTION_FREE: u8 = 5;
+const INSTRUCTION_REALLOC: u8 = 6;
+const INSTRUCTION_SET: u8 = 7;
+const INSTRUCTION_GET: u8 = 8;
+const INSTRUCTION_SET_VIRTUAL_REGISTER: u8 = 9;
+const INSTRUCTION_TYPE_CAST: u8 = 10;
+const INSTRUCTION_STACK_ALLOCATE: u8 = 11;
+const INSTRUCTION_ELEMENT_ADDRESS: u8 = 12;
+const INSTRUCTION_CALCULATE: u8 = 13;
+const INSTRUCTION_INCREASE: u8 = 14;
+const INSTRUCTION_DECREASE: u8 = 15;
+const INSTRUCTION_NOT: u8 = 16;
+const INSTRUCTION_NEGATE: u8 = 17;
+const INSTRUCTION_INVOKE: u8 = 18;
+const INSTRUCTION_ASM: u8 = 19;
+
+/// Every condition, indexed by its tag.
+pub(crate) const CONDITIONS: [IRCondition; 8] = [
+    IRCondition::Equal,
+    IRCondition::NotEqual,
+    IRCondition::Less,
+    IRCondition::LessEqual,
+    IRCondition::Greater,
+    IRCondition::GreaterEqual,
+    IRCondition::IfTrue,
+    IRCondition::IfFalse,
+];
+
+/// Every operator, indexed by its tag.
+pub(crate) const OPERATORS: [IRCalculateOperator; 11] = [
+    IRCalculateOperator::ADD,
+    IRCalculateOperator::SUB,
+    IRCalculateOperator::MUL,
+    IRCalculateOperator::DIV,
+    IRCalculateOperator::MOD,
+    IRCalculateOperator::AND,
+    IRCalculateOperator::OR,
+    IRCalculateOperator::XOR,
+    IRCalculateOperator::SHL,
+    IRCalculateOperator::SHR,
+    IRCalculateOperator::USHR,
+];
+
+/// Every type cast kind, indexed by its tag.
+pub(crate) const CAST_KINDS: [IRTypeCastKind; 7] = [
+    IRTypeCastKind::ZeroExtend,
+    IRTypeCastKind::SignExtend,
+    IRTypeCastKind::Truncate,
+    IRTypeCastKind::IntToFloat,
+    IRTypeCastKind::FloatToInt,
+    IRTypeCastKind::FloatExtend,
+    IRTypeCastKind::FloatTruncate,
+];
+
+fn write_unsigned(output: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            output.push(byte);
+            return;
+        }
+        output.push(byte | 0x80);
+    }
+}
+
+fn write_signed(output: &mut Vec<u8>, value: i64) {
+    write_unsigned(output, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+/// Encodes a module; see the module documentation for the layout.
+pub fn write_module(ir_module: &IRModule) -> Vec<u8> {
+    let mut writer = IRBinaryWriter {
+        strings: IndexSet::new(),
+        types: IndexMap::new(),
+        body: vec![],
+    };
+    writer.module(ir_module);
+
+    let mut output = MAGIC.to_vec();
+    output.extend(VERSION.to_le_bytes());
+    write_unsigned(&mut output, writer.strings.len() as u64);
+    for string in writer.strings.iter() {
+        write_unsigned(&mut output, string.len() as u64);
+        output.extend(string.as_bytes());
+    }
+    write_unsigned(&mut output, writer.types.len() as u64);
+    for encoded in writer.types.values() {
+        output.extend(encoded);
+    }
+    output.extend(writer.body);
+    output
+}
+
+struct IRBinaryWriter {
+    strings: IndexSet<String>,
+    /// Each distinct type, keyed by its textual form, with its encoded table entry.
+    types: IndexMap<String, Vec<u8>>,
+    body: Vec<u8>,
+}
+
+impl IRBinaryWriter {
+    fn string_index(&mut self, string: &str) -> u64 {
+        match self.strings.get_index_of(string) {
+            Some(index) => index as u64,
+            None => self.strings.insert_full(string.to_string()).0 as u64,
+        }
+    }
+
+    /// The index of `_type` in the type table, adding it after the types it refers to.
+    fn type_index(&mut self, _type: &dyn IRType) -> u64 {
+        let key = _type.to_string();
+        if let Some(index) = self.types.get_index_of(&key) {
+            return index as u64;
+        }
+        let mut entry = vec![];
+        match IRTypeKind::of(_type) {
+            IRTypeKind::Integer(integer_type) => {
+                entry.push(TYPE_INTEGER);
+                entry.push(integer_type.size as u8);
+                entry.push(integer_type.unsigned as u8);
+            }
+            IRTypeKind::Float(_) => entry.push(TYPE_FLOAT),
+            IRTypeKind::Double(_) => entry.push(TYPE_DOUBLE),
+            IRTypeKind::Void(_) => entry.push(TYPE_VOID),
+            IRTypeKind::Pointer(pointer_type) => {
+                entry.push(TYPE_POINTER);
+                write_unsigned(&mut entry, self.type_index(pointer_type.base.as_ref()));
+            }
+            IRTypeKind::Structure(structure_type) => {
+                entry.push(TYPE_STRUCTURE);
+                write_unsigned(&mut entry, self.string_index(&structure_type.name));
+            }
+            IRTypeKind::Array(array_type) => {
+                entry.push(TYPE_ARRAY);
+                write_unsigned(&mut entry, self.type_index(array_type.element.as_ref()));
+                write_unsigned(&mut entry, array_type.length);
+            }
+            IRTypeKind::Function(function_type) => {
+                entry.push(TYPE_FUNCTION);
+                write_unsigned(
+                    &mut entry,
+                    self.type_index(function_type.return_type.as_ref()),
+                );
+                write_unsigned(&mut entry, function_type.params.len() as u64);
+                for param in function_type.params.iter() {
+                    write_unsigned(&mut entry, self.type_index(param.as_ref()));
+                }
+                entry.push(function_type.variadic as u8);
+            }
+        }
+        self.types.insert_full(key, entry).0 as u64
+    }
+
+    fn byte(&mut self, value: u8) {
+        self.body.push(value);
+    }
+
+    fn unsigned(&mut self, value: u64) {
+        write_unsigned(&mut self.body, value);
+    }
+
+    fn signed(&mut self, value: i64) {
+        write_signed(&mut self.body, value);
+    }
+
+    fn string(&mut self, string: &str) {
+        let index = self.string_index(string);
+        self.unsigned(index);
+    }
+
+    fn strings(&mut self, strings: &[String]) {
+        self.unsigned(strings.len() as u64);
+        for string in strings {
+            self.string(string);
+        }
+    }
+
+    fn _type(&mut self, _type: &dyn IRType) {
+        let index = self.type_index(_type);
+        self.unsigned(index);
+    }
+
+    fn types(&mut self, types: &[Box<dyn IRType>]) {
+        self.unsigned(types.len() as u64);
+        for _type in types {
+            self._type(_type.as_ref());
+        }
+    }
+
+    fn module(&mut self, ir_module: &IRModule) {
+        self.unsigned(ir_module.structures.len() as u64);
+        for ir_structure in ir_module.structures.values() {
+            self.string(&ir_structure.name);
+            self.unsigned(ir_structure.fields.len() as u64);
+            for ir_field in ir_structure.fields.iter() {
+                self.field(ir_field);
+            }
+        }
+
+        self.unsigned(ir_module.constant_pool.entries.len() as u64);
+        for entry in ir_module.constant_pool.entries.iter() {
+            self._type(entry._type.as_ref());
+            self.value(&entry.value);
+        }
+
+        self.unsigned(ir_module.global_data_section.data.len() as u64);
+        for ir_global_data in ir_module.global_data_section.data.iter() {
+            self.string(&ir_global_data.name);
+            self.optional_operand(ir_global_data.size.as_deref());
+            match ir_global_data.values.as_ref() {
+                Some(values) => {
+                    self.byte(1);
+                    self.operands(values);
+                }
+                None => self.byte(0),
+            }
+        }
+
+        self.control_flow_graph(&ir_module.global_init_section);
+
+        self.unsigned(ir_module.functions.len() as u64);
+        for ir_function in ir_module.functions.values() {
+            self._type(ir_function.return_type.as_ref());
+            self.string(&ir_function.name);
+            self.unsigned(ir_function.arguments_count as u64);
+            self.unsigned(ir_function.fields.len() as u64);
+            for ir_field in ir_function.fields.iter() {
+                self.field(ir_field);
+            }
+            self.control_flow_graph(&ir_function.control_flow_graph);
+        }
+
+        for keys in [&ir_module.name2vtable_keys, &ir_module.name2itable_keys] {
+            self.unsigned(keys.len() as u64);
+            for (name, functions) in keys.iter() {
+                self.string(name);
+                self.strings(functions);
+            }
+        }
+
+        match ir_module.entry_point.as_ref() {
+            Some(entry_point) => {
+                self.byte(1);
+                self.string(entry_point);
+            }
+            None => self.byte(0),
+        }
+    }
+
+    fn field(&mut self, ir_field: &IRField) {
+        self.string(&ir_field.name);
+        self._type(ir_field._type.as_ref());
+    }
+
+    fn value(&mut self, value: &IRConstantValue) {
+        match value {
+            IRConstantValue::I1(value) => {
+                self.byte(VALUE_I1);
+                self.byte(*value as u8);
+            }
+            IRConstantValue::I8(value) => {
+                self.byte(VALUE_I8);
+                self.signed(*value as i64);
+            }
+            IRConstantValue::I16(value) => {
+                self.byte(VALUE_I16);
+                self.signed(*value as i64);
+            }
+            IRConstantValue::I32(value) => {
+                self.byte(VALUE_I32);
+                self.signed(*value as i64);
+            }
+            IRConstantValue::I64(value) => {
+                self.byte(VALUE_I64);
+                self.signed(*value);
+            }
+            IRConstantValue::F32(value) => {
+                self.byte(VALUE_F32);
+                self.body.extend(value.to_bits().to_le_bytes());
+            }
+            IRConstantValue::F64(value) => {
+                self.byte(VALUE_F64);
+                self.body.extend(value.to_bits().to_le_bytes());
+            }
+            IRConstantValue::Null => self.byte(VALUE_NULL),
+            IRConstantValue::Bytes(bytes) => {
+                self.byte(VALUE_BYTES);
+                self.unsigned(bytes.len() as u64);
+                self.body.extend(bytes);
+            }
+            IRConstantValue::ZeroInitializer => self.byte(VALUE_ZERO_INITIALIZER),
+            IRConstantValue::Aggregate(values) => {
+                self.byte(VALUE_AGGREGATE);
+                self.unsigned(values.len() as u64);
+                for value in values {
+                    self.value(value);
+                }
+            }
+        }
+    }
+
+    fn operand(&mut self, ir_operand: &dyn IROperand) {
+        match ir_operand.kind() {
+            IROperandKind::VirtualRegister(ir_virtual_register) => {
+                self.byte(OPERAND_VIRTUAL_REGISTER);
+                self.string(&ir_virtual_register.name);
+            }
+            IROperandKind::Constant(ir_constant) => {
+                self.byte(OPERAND_CONSTANT);
+                self.unsigned(ir_constant.index as u64);
+            }
+            IROperandKind::Macro(ir_macro) => {
+                self.byte(OPERAND_MACRO);
+                self.string(&ir_macro.name);
+                self.strings(&ir_macro.args);
+                self.operands(&ir_macro.additional_operands);
+            }
+            IROperandKind::Phi(ir_phi) => {
+                self.byte(OPERAND_PHI);
+                self._type(ir_phi._type.as_ref());
+                self.strings(&ir_phi.labels);
+                self.operands(&ir_phi.operands);
+            }
+            IROperandKind::VirtualTable(ir_virtual_table) => {
+                self.byte(OPERAND_VIRTUAL_TABLE);
+                self.strings(&ir_virtual_table.functions);
+            }
+            IROperandKind::InterfaceTable(ir_interface_table) => {
+                self.byte(OPERAND_INTERFACE_TABLE);
+                self.unsigned(ir_interface_table.entries.len() as u64);
+                for entry in ir_interface_table.entries.iter() {
+                    self.string(&entry.name);
+                    self.strings(&entry.functions);
+                }
+            }
+        }
+    }
+
+    fn optional_operand(&mut self, ir_operand: Option<&dyn IROperand>) {
+        match ir_operand {
+            Some(ir_operand) => {
+                self.byte(1);
+                self.operand(ir_operand);
+            }
+            None => self.byte(0),
+        }
+    }
+
+    fn operands(&mut self, ir_operands: &[Box<dyn IROperand>]) {
+        self.unsigned(ir_operands.len() as u64);
+        for ir_operand in ir_operands {
+            self.operand(ir_operand.as_ref());
+        }
+    }
+
+    fn register(&mut self, ir_virtual_register: &IRVirtualRegister) {
+        self.string(&ir_virtual_register.name);
+    }
+
+    fn optional_register(&mut self, ir_virtual_register: Option<&IRVirtualRegister>) {
+        match ir_virtual_register {
+            Some(ir_virtual_register) => {
+                self.byte(1);
+                self.register(ir_virtual_register);
+            }
+            None => self.byte(0),
+        }
+    }
+
+    fn control_flow_graph(&mut self, ir_control_flow_graph: &IRControlFlowGraph) {
+        self.unsigned(ir_control_flow_graph.basic_blocks.len() as u64);
+        for ir_basic_block in ir_control_flow_graph.basic_blocks.values() {
+            self.string(&ir_basic_block.name);
+            self.unsigned(ir_basic_block.instructions.len() as u64);
+            for ir_instruction in ir_basic_block.instructions.iter() {
+                self.instruction(ir_instruction.as_ref());
+            }
+        }
+    }
+
+    fn instruction(&mut self, ir_instruction: &dyn IRInstruction) {
+        match ir_instruction.kind() {
+            IRInstructionKind::Goto(ir_goto) => {
+                self.byte(INSTRUCTION_GOTO);
+                self.string(&ir_goto.target);
+            }
+            IRInstructionKind::ConditionalJump(ir_conditional_jump) => {
+                self.byte(INSTRUCTION_CONDITIONAL_JUMP);
+                self.byte(ir_conditional_jump.is_atomic as u8);
+                self._type(ir_conditional_jump._type.as_ref());
+                let condition = CONDITIONS
+                    .iter()
+                    .position(|condition| *condition == ir_conditional_jump.condition)
+                    .unwrap();
+                self.byte(condition as u8);
+                self.operand(ir_conditional_jump.operand1.as_ref());
+                self.optional_operand(ir_conditional_jump.operand2.as_deref());
+                self.string(&ir_conditional_jump.target);
+            }
+            IRInstructionKind::NoOperate(_) => self.byte(INSTRUCTION_NO_OPERATE),
+            IRInstructionKind::Return(ir_return) => {
+                self.byte(INSTRUCTION_RETURN);
+                self.optional_operand(ir_return.operand.as_deref());
+            }
+            IRInstructionKind::Malloc(ir_malloc) => {
+                self.byte(INSTRUCTION_MALLOC);
+                self.operand(ir_malloc.size.as_ref());
+                self.register(&ir_malloc.target);
+            }
+            IRInstructionKind::Free(ir_free) => {
+                self.byte(INSTRUCTION_FREE);
+                self.operand(ir_free.ptr.as_ref());
+            }
+            IRInstructionKind::Realloc(ir_realloc) => {
+                self.byte(INSTRUCTION_REALLOC);
+                self.operand(ir_realloc.ptr.as_ref());
+                self.operand(ir_realloc.size.as_ref());
+                self.register(&ir_realloc.target);
+            }
+            IRInstructionKind::Set(ir_set) => {
+                self.byte(INSTRUCTION_SET);
+                self._type(ir_set._type.as_ref());
+                self.operand(ir_set.address.as_ref());
+                self.operand(ir_set.value.as_ref());
+            }
+            IRInstructionKind::Get(ir_get) => {
+                self.byte(INSTRUCTION_GET);
+                self._type(ir_get._type.as_ref());
+                self.operand(ir_get.address.as_ref());
+                self.register(&ir_get.target);
+            }
+            IRInstructionKind::SetVirtualRegister(ir_set_virtual_register) => {
+                self.byte(INSTRUCTION_SET_VIRTUAL_REGISTER);
+                self.operand(ir_set_virtual_register.source.as_ref());
+                self.register(&ir_set_virtual_register.target);
+            }
+            IRInstructionKind::TypeCast(ir_type_cast) => {
+                self.byte(INSTRUCTION_TYPE_CAST);
+                let kind = CAST_KINDS
+                    .iter()
+                    .position(|kind| *kind == ir_type_cast.kind)
+                    .unwrap();
+                self.byte(kind as u8);
+                self._type(ir_type_cast.original_type.as_ref());
+                self.operand(ir_type_cast.source.as_ref());
+                self._type(ir_type_cast.target_type.as_ref());
+                self.register(&ir_type_cast.target);
+            }
+            IRInstructionKind::StackAllocate(ir_stack_allocate) => {
+                self.byte(INSTRUCTION_STACK_ALLOCATE);
+                self.operand(ir_stack_allocate.size.as_ref());
+                self.register(&ir_stack_allocate.target);
+            }
+            IRInstructionKind::ElementAddress(ir_element_address) => {
+                self.byte(INSTRUCTION_ELEMENT_ADDRESS);
+                self._type(ir_element_address._type.as_ref());
+                self.operand(ir_element_address.base.as_ref());
+                self.unsigned(ir_element_address.path.len() as u64);
+                for index in ir_element_address.path.iter() {
+                    match index {
+                        IRElementIndex::Field(field) => {
+                            self.byte(0);
+                            self.string(field);
+                        }
+                        IRElementIndex::Index(index) => {
+                            self.byte(1);
+                            self.operand(index.as_ref());
+                        }
+                    }
+                }
+                self.register(&ir_element_address.target);
+            }
+            IRInstructionKind::Calculate(ir_calculate) => {
+                self.byte(INSTRUCTION_CALCULATE);
+                self.byte(ir_calculate.is_atomic as u8);
+                let operator = OPERATORS
+                    .iter()
+                    .position(|operator| *operator == ir_calculate.operator)
+                    .unwrap();
+                self.byte(operator as u8);
+                self._type(ir_calculate._type.as_ref());
+                self.operand(ir_calculate.operand1.as_ref());
+                self.operand(ir_calculate.operand2.as_ref());
+                self.register(&ir_calculate.target);
+            }
+            IRInstructionKind::Increase(ir_increase) => {
+                self.byte(INSTRUCTION_INCREASE);
+                self._type(ir_increase._type.as_ref());
+                self.operand(ir_increase.operand.as_ref());
+                self.optional_register(ir_increase.target.as_deref());
+            }
+            IRInstructionKind::Decrease(ir_decrease) => {
+                self.byte(INSTRUCTION_DECREASE);
+                self._type(ir_decrease._type.as_ref());
+                self.operand(ir_decrease.operand.as_ref());
+                self.optional_register(ir_decrease.target.as_deref());
+            }
+            IRInstructionKind::Not(ir_not) => {
+                self.byte(INSTRUCTION_NOT);
+                self.byte(ir_not.is_atomic as u8);
+                self._type(ir_not._type.as_ref());
+                self.operand(ir_not.operand.as_ref());
+                self.register(&ir_not.target);
+            }
+            IRInstructionKind::Negate(ir_negate) => {
+                self.byte(INSTRUCTION_NEGATE);
+                self.byte(ir_negate.is_atomic as u8);
+                self._type(ir_negate._type.as_ref());
+                self.operand(ir_negate.operand.as_ref());
+                self.register(&ir_negate.target);
+            }
+            IRInstructionKind::Invoke(ir_invoke) => {
+                self.byte(INSTRUCTION_INVOKE);
+                self._type(ir_invoke.return_type.as_ref());
+                self.operand(ir_invoke.address.as_ref());
+                self.types(&ir_invoke.argument_types);
+                self.operands(&ir_invoke.arguments);
+                self.optional_register(ir_invoke.target.as_deref());
+            }
+            IRInstructionKind::Asm(ir_asm) => {
+                self.byte(INSTRUCTION_ASM);
+                self.string(&ir_asm.code);
+                self.types(&ir_asm.types);
+                self.operands(&ir_asm.resources);
+                self.strings(&ir_asm.names);
+            }
+        }
+    }
+}
+
+/// Decodes a module written by `write_module`.
+pub fn read_module(bytes: &[u8]) -> IRBinaryResult<IRModule> {
+    let mut reader = IRBinaryReader {
+        bytes,
+        offset: 0,
+        strings: vec![],
+        types: vec![],
+        type_shapes: vec![],
+        expansion: bytes.len().saturating_mul(EXPANSION_PER_BYTE),
+        depth: 0,
+    };
+    let magic = reader.take(MAGIC.len())?;
+    if magic != MAGIC {
+        return Err(reader.error_at(0, IRBinaryErrorKind::BadMagic));
+    }
+    let version = u32::from_le_bytes(reader.take(4)?.try_into().unwrap());
+    if version != VERSION {
+        return Err(reader.error_at(4, IRBinaryErrorKind::UnsupportedVersion(version)));
+    }
+    let ir_module = reader.module()?;
+    if reader.offset != bytes.len() {
+        return Err(reader.error(IRBinaryErrorKind::TrailingBytes));
+    }
+    Ok(ir_module)
+}
+
+struct IRBinaryReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    strings: Vec<String>,
+    types: Vec<Box<dyn IRType>>,
+    /// The nesting depth and node count of each type in `types`.
+    type_shapes: Vec<(usize, usize)>,
+    /// How many more string bytes and type nodes may be copied out of the tables.
+    expansion: usize,
+    /// How deeply the constant value or operand being read is nested.
+    depth: usize,
+}
+
+impl<'a> IRBinaryReader<'a> {
+    fn error(&self, kind: IRBinaryErrorKind) -> IRBinaryError {
+        self.error_at(self.offset, kind)
+    }
+
+    fn error_at(&self, offset: usize, kind: IRBinaryErrorKind) -> IRBinaryError {
+        IRBinaryError { offset, kind }
+    }
+
+    fn take(&mut self, length: usize) -> IRBinaryResult<&'a [u8]> {
+        let end = self
+            .offset
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| self.error_at(self.bytes.len(), IRBinaryErrorKind::Truncated))?;
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> IRBinaryResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn tag(&mut self, what: &'static str, count: u8) -> IRBinaryResult<u8> {
+        let start = self.offset;
+        let tag = self.byte()?;
+        if tag >= count {
+            return Err(self.error_at(
+                start,
+                IRBinaryErrorKind::UnknownTag {
+                    what,
+                    tag: tag as u64,
+                },
+            ));
+        }
+        Ok(tag)
+    }
+
+    fn bool(&mut self) -> IRBinaryResult<bool> {
+        Ok(self.tag("boolean", 2)? == 1)
+    }
+
+    fn unsigned(&mut self) -> IRBinaryResult<u64> {
+        let start = self.offset;
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7f) as u64;
+            if shift == 63 && bits > 1 {
+                break;
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(self.error_at(start, IRBinaryErrorKind::Overflow))
+    }
+
+    fn signed(&mut self) -> IRBinaryResult<i64> {
+        let value = self.unsigned()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    /// A signed value that must fit `T`.
+    fn signed_as<T: TryFrom<i64>>(&mut self) -> IRBinaryResult<T> {
+        let start = self.offset;
+        let value = self.signed()?;
+        T::try_from(value).map_err(|_| self.error_at(start, IRBinaryErrorKind::Overflow))
+    }
+
+    /// A count of items that each take at least one byte, so a corrupt count fails as truncated
+    /// input instead of allocating.
+    fn count(&mut self) -> IRBinaryResult<usize> {
+        let count = self.unsigned()?;
+        if count > (self.bytes.len() - self.offset) as u64 {
+            return Err(self.error_at(self.bytes.len(), IRBinaryErrorKind::Truncated));
+        }
+        Ok(count as usize)
+    }
+
+    fn index(&mut self, what: &'static str, length: usize) -> IRBinaryResult<usize> {
+        let start = self.offset;
+        let index = self.unsigned()?;
+        if index >= length as u64 {
+            return Err(self.error_at(start, IRBinaryErrorKind::InvalidIndex { what, index }));
+        }
+        Ok(index as usize)
+    }
+
+    /// Accounts for copying `size` string bytes or type nodes out of a table.
+    fn expand(&mut self, start: usize, size: usize) -> IRBinaryResult<()> {
+        self.expansion = self
+            .expansion
+            .checked_sub(size)
+            .ok_or_else(|| self.error_at(start, IRBinaryErrorKind::TooLarge))?;
+        Ok(())
+    }
+
+    /// Reads something that may contain itself, such as an aggregate or a macro operand.
+    fn nested<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> IRBinaryResult<T>,
+    ) -> IRBinaryResult<T> {
+        if self.depth == MAX_NESTING_DEPTH {
+            return Err(self.error(IRBinaryErrorKind::TooDeep));
+        }
+        self.depth += 1;
+        let result = read(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn string(&mut self) -> IRBinaryResult<String> {
+        let start = self.offset;
+        let index = self.index("string", self.strings.len())?;
+        self.expand(start, self.strings[index].len())?;
+        Ok(self.strings[index].clone())
+    }
+
+    fn strings(&mut self) -> IRBinaryResult<Vec<String>> {
+        (0..self.count()?).map(|_| self.string()).collect()
+    }
+
+    fn type_index(&mut self) -> IRBinaryResult<usize> {
+        let start = self.offset;
+        let index = self.index("type", self.types.len())?;
+        self.expand(start, self.type_shapes[index].1)?;
+        Ok(index)
+    }
+
+    fn _type(&mut self) -> IRBinaryResult<Box<dyn IRType>> {
+        let index = self.type_index()?;
+        Ok(self.types[index].clone())
+    }
+
+    fn types(&mut self) -> IRBinaryResult<Vec<Box<dyn IRType>>> {
+        (0..self.count()?).map(|_| self._type()).collect()
+    }
+
+    fn string_table(&mut self) -> IRBinaryResult<()> {
+        for _ in 0..self.count()? {
+            let length = self.count()?;
+            let start = self.offset;
+            let string = std::str::from_utf8(self.take(length)?)
+                .map_err(|_| self.error_at(start, IRBinaryErrorKind::InvalidUtf8))?;
+            self.strings.push(string.to_string());
+        }
+        Ok(())
+    }
+
+    fn type_table(&mut self) -> IRBinaryResult<()> {
+        for _ in 0..self.count()? {
+            let start = self.offset;
+            let (mut depth, mut size) = (0, 1);
+            let mut child = |reader: &mut Self| {
+                let index = reader.type_index()?;
+                let (child_depth, child_size) = reader.type_shapes[index];
+                depth = depth.max(child_depth);
+                size += child_size;
+                Ok::<_, IRBinaryError>(reader.types[index].clone())
+            };
+            let _type: Box<dyn IRType> = match self.tag("type", TYPE_FUNCTION + 1)? {
+                TYPE_INTEGER => {
+                    let start = self.offset;
+                    let size = match self.byte()? {
+                        1 => IRIntegerTypeSize::OneBit,
+                        8 => IRIntegerTypeSize::OneByte,
+                        16 => IRIntegerTypeSize::TwoBytes,
+                        32 => IRIntegerTypeSize::FourBytes,
+                        64 => IRIntegerTypeSize::EightBytes,
+                        bits => {
+                            return Err(self.error_at(
+                                start,
+                                IRBinaryErrorKind::UnknownTag {
+                                    what: "integer size",
+                                    tag: bits as u64,
+                                },
+                            ));
+                        }
+                    };
+                    Box::new(IRIntegerType::new(size, self.bool()?))
+                }
+                TYPE_FLOAT => Box::new(IRFloatType::new()),
+                TYPE_DOUBLE => Box::new(IRDoubleType::new()),
+                TYPE_VOID => Box::new(IRVoidType::new()),
+                TYPE_POINTER => Box::new(IRPointerType::new(child(self)?)),
+                TYPE_STRUCTURE => Box::new(IRStructureType::new(self.string()?)),
+                TYPE_ARRAY => Box::new(IRArrayType::new(child(self)?, self.unsigned()?)),
+                _ => Box::new(IRFunctionType::new(
+                    child(self)?,
+                    (0..self.count()?)
+                        .map(|_| child(self))
+                        .collect::<IRBinaryResult<_>>()?,
+                    self.bool()?,
+                )),
+            };
+            if depth == MAX_NESTING_DEPTH {
+                return Err(self.error_at(start, IRBinaryErrorKind::TooDeep));
+            }
+            self.types.push(_type);
+            self.type_shapes.push((depth + 1, size));
+        }
+        Ok(())
+    }
+
+    fn module(&mut self) -> IRBinaryResult<IRModule> {
+        self.string_table()?;
+        self.type_table()?;
+        let mut ir_module = IRModule::new();
+
+        for _ in 0..self.count()? {
+            let name = self.string()?;
+            let fields = (0..self.count()?)
+                .map(|_| self.field())
+                .collect::<IRBinaryResult<_>>()?;
+            ir_module.push_struct(IRStructure::new(name, fields));
+        }
+
+        for _ in 0..self.count()? {
+            let _type = self._type()?;
+            let value = self.value()?;
+            ir_module
+                .constant_pool
+                .push(Box::new(IRConstantPoolEntry::new(_type, value)));
+        }
+
+        for _ in 0..self.count()? {
+            let name = self.string()?;
+            let size = self.optional_operand()?;
+            let values = if self.bool()? {
+                Some(self.operands()?)
+            } else {
+                None
+            };
+            ir_module
+                .global_data_section
+                .data
+                .push(IRGlobalData::new(name, size, values));
+        }
+
+        ir_module.global_init_section = Box::new(self.control_flow_graph()?);
+
+        for _ in 0..self.count()? {
+            let return_type = self._type()?;
+            let name = self.string()?;
+            let start = self.offset;
+            let arguments_count = usize::try_from(self.unsigned()?)
+                .map_err(|_| self.error_at(start, IRBinaryErrorKind::Overflow))?;
+            let fields = (0..self.count()?)
+                .map(|_| self.field().map(Box::new))
+                .collect::<IRBinaryResult<Vec<_>>>()?;
+            if arguments_count > fields.len() {
+                return Err(self.error_at(start, IRBinaryErrorKind::LengthMismatch("argument")));
+            }
+            let control_flow_graph = Box::new(self.control_flow_graph()?);
+            ir_module.push_function(IRFunction::new(
+                return_type,
+                name,
+                arguments_count,
+                fields,
+                control_flow_graph,
+            ));
+        }
+
+        for keys in [
+            &mut ir_module.name2vtable_keys,
+            &mut ir_module.name2itable_keys,
+        ] {
+            for _ in 0..self.count()? {
+                let name = self.string()?;
+                keys.insert(name, self.strings()?);
+            }
+        }
+
+        if self.bool()? {
+            ir_module.entry_point = Some(self.string()?);
+        }
+        Ok(ir_module)
+    }
+
+    fn field(&mut self) -> IRBinaryResult<IRField> {
+        Ok(IRField::new(self.string()?, self._type()?))
+    }
+
+    fn value(&mut self) -> IRBinaryResult<IRConstantValue> {
+        Ok(match self.tag("constant value", VALUE_AGGREGATE + 1)? {
+            VALUE_I1 => IRConstantValue::I1(self.bool()?),
+            VALUE_I8 => IRConstantValue::I8(self.signed_as()?),
+            VALUE_I16 => IRConstantValue::I16(self.signed_as()?),
+            VALUE_I32 => IRConstantValue::I32(self.signed_as()?),
+            VALUE_I64 => IRConstantValue::I64(self.signed()?),
+            VALUE_F32 => IRConstantValue::F32(f32::from_bits(u32::from_le_bytes(
+                self.take(4)?.try_into().unwrap(),
+            ))),
+            VALUE_F64 => IRConstantValue::F64(f64::from_bits(u64::from_le_bytes(
+                self.take(8)?.try_into().unwrap(),
+            ))),
+            VALUE_NULL => IRConstantValue::Null,
+            VALUE_BYTES => {
+                let length = self.count()?;
+                IRConstantValue::Bytes(self.take(length)?.to_vec())
+            }
+            VALUE_ZERO_INITIALIZER => IRConstantValue::ZeroInitializer,
+            _ => IRConstantValue::Aggregate(
+                self.nested(|reader| (0..reader.count()?).map(|_| reader.value()).collect())?,
+            ),
+        })
+    }
+
+    fn operand(&mut self) -> IRBinaryResult<Box<dyn IROperand>> {
+        Ok(match self.tag("operand", OPERAND_INTERFACE_TABLE + 1)? {
+            OPERAND_VIRTUAL_REGISTER => Box::new(IRVirtualRegister::new(self.string()?)),
+            OPERAND_CONSTANT => {
+                let start = self.offset;
+                let index = u32::try_from(self.unsigned()?)
+                    .map_err(|_| self.error_at(start, IRBinaryErrorKind::Overflow))?;
+                Box::new(IRConstant::new(index))
+            }
+            OPERAND_MACRO => Box::new(IRMacro::new(
+                self.string()?,
+                self.strings()?,
+                self.nested(Self::operands)?,
+            )),
+            OPERAND_PHI => {
+                let _type = self._type()?;
+                let start = self.offset;
+                let labels = self.strings()?;
+                let operands = self.nested(Self::operands)?;
+                if labels.len() != operands.len() {
+                    return Err(self.error_at(start, IRBinaryErrorKind::LengthMismatch("phi")));
+                }
+                Box::new(IRPhi::new(_type, labels, operands))
+            }
+            OPERAND_VIRTUAL_TABLE => Box::new(IRVirtualTable::new(self.strings()?)),
+            _ => Box::new(IRInterfaceTable::new(
+                (0..self.count()?)
+                    .map(|_| Ok(IRInterfaceTableEntry::new(self.string()?, self.strings()?)))
+                    .collect::<IRBinaryResult<_>>()?,
+            )),
+        })
+    }
+
+    fn optional_operand(&mut self) -> IRBinaryResult<Option<Box<dyn IROperand>>> {
+        if self.bool()? {
+            Ok(Some(self.operand()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn operands(&mut self) -> IRBinaryResult<Vec<Box<dyn IROperand>>> {
+        (0..self.count()?).map(|_| self.operand()).collect()
+    }
+
+    fn register(&mut self) -> IRBinaryResult<Box<IRVirtualRegister>> {
+        Ok(Box::new(IRVirtualRegister::new(self.string()?)))
+    }
+
+    fn optional_register(&mut self) -> IRBinaryResult<Option<Box<IRVirtualRegister>>> {
+        if self.bool()? {
+            Ok(Some(self.register()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn control_flow_graph(&mut self) -> IRBinaryResult<IRControlFlowGraph> {
+        let mut ir_control_flow_graph = IRControlFlowGraph::new();
+        for _ in 0..self.count()? {
+            let mut ir_basic_block = IRBasicBlock::new(self.string()?);
+            ir_basic_block.instructions = (0..self.count()?)
+                .map(|_| self.instruction())
+                .collect::<IRBinaryResult<_>>()?;
+            ir_control_flow_graph.add_basic_block(Box::new(ir_basic_block));
+        }
+        Ok(ir_control_flow_graph)
+    }
+
+    fn instruction(&mut self) -> IRBinaryResult<Box<dyn IRInstruction>> {
+        Ok(match self.tag("instruction", INSTRUCTION_ASM + 1)? {
+            INSTRUCTION_GOTO => Box::new(IRGoto::new(self.string()?)),
+            INSTRUCTION_CONDITIONAL_JUMP => Box::new(IRConditionalJump::new(
+                self.bool()?,
+                self._type()?,
+                CONDITIONS[self.tag("condition", CONDITIONS.len() as u8)? as usize],
+                self.operand()?,
+                self.optional_operand()?,
+                self.string()?,
+            )),
+            INSTRUCTION_NO_OPERATE => Box::new(IRNoOperate::new()),
+            INSTRUCTION_RETURN => Box::new(IRReturn::new(self.optional_operand()?)),
+            INSTRUCTION_MALLOC => Box::new(IRMalloc::new(self.operand()?, self.register()?)),
+            INSTRUCTION_FREE => Box::new(IRFree::new(self.operand()?)),
+            INSTRUCTION_REALLOC => Box::new(IRRealloc::new(
+                self.operand()?,
+                self.operand()?,
+                self.register()?,
+            )),
+            INSTRUCTION_SET => {
+                Box::new(IRSet::new(self._type()?, self.operand()?, self.operand()?))
+            }
+            INSTRUCTION_GET => {
+                Box::new(IRGet::new(self._type()?, self.operand()?, self.register()?))
+            }
+            INSTRUCTION_SET_VIRTUAL_REGISTER => {
+                Box::new(IRSetVirtualRegister::new(self.operand()?, self.register()?))
+            }
+            INSTRUCTION_TYPE_CAST => Box::new(IRTypeCast::new(
+                CAST_KINDS[self.tag("type cast kind", CAST_KINDS.len() as u8)? as usize],
+                self._type()?,
+                self.operand()?,
+                self._type()?,
+                self.register()?,
+            )),
+            INSTRUCTION_STACK_ALLOCATE => {
+                Box::new(IRStackAllocate::new(self.operand()?, self.register()?))
+            }
+            INSTRUCTION_ELEMENT_ADDRESS => {
+                let _type = self._type()?;
+                let base = self.operand()?;
+                let path = (0..self.count()?)
+                    .map(|_| match self.tag("element index", 2)? {
+                        0 => Ok(IRElementIndex::Field(self.string()?)),
+                        _ => Ok(IRElementIndex::Index(self.operand()?)),
+                    })
+                    .collect::<IRBinaryResult<_>>()?;
+                Box::new(IRElementAddress::new(_type, base, path, self.register()?))
+            }
+            INSTRUCTION_CALCULATE => Box::new(IRCalculate::new(
+                self.bool()?,
+                OPERATORS[self.tag("operator", OPERATORS.len() as u8)? as usize],
+                self._type()?,
+                self.operand()?,
+                self.operand()?,
+                self.register()?,
+            )),
+            INSTRUCTION_INCREASE => Box::new(IRIncrease::new(
+                self._type()?,
+                self.operand()?,
+                self.optional_register()?,
+            )),
+            INSTRUCTION_DECREASE => Box::new(IRDecrease::new(
+                self._type()?,
+                self.operand()?,
+                self.optional_register()?,
+            )),
+            INSTRUCTION_NOT => Box::new(IRNot::new(
+                self.bool()?,
+                self._type()?,
+                self.operand()?,
+                self.register()?,
+            )),
+            INSTRUCTION_NEGATE => Box::new(IRNegate::new(
+                self.bool()?,
+                self._type()?,
+                self.operand()?,
+                self.register()?,
+            )),
+            INSTRUCTION_INVOKE => {
+                let return_type = self._type()?;
+                let address = self.operand()?;
+                let start = self.offset;
+                let argument_types = self.types()?;
+                let arguments = self.operands()?;
+                if argument_types.len() != arguments.len() {
+                    return Err(self.error_at(start, IRBinaryErrorKind::LengthMismatch("argument")));
+                }
+                Box::new(IRInvoke::new(
+                    return_type,
+                    address,
+                    argument_types,
+                    arguments,
+                    self.optional_register()?,
+                ))
+            }
+            _ => Box::new(IRAsm::new(
+                self.string()?,
+                self.types()?,
+                self.operands()?,
+                self.strings()?,
+            )),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::IRDumper;
+    use crate::ir::parser::parse_module;
+
+    const MODULE: &str = "\
+structure Node {
+    i64 value
+    %Node* next
+}
+
+constant $0 = i64 0
+constant $1 = i64 1
+constant $2 = [2 x i64] {1, -1}
+constant $3 = i8* \"hello\\n\"
+
+global head, size=$1
+global table, values=[IRVirtualTable{functions={sum}}]
+
+function i64 sum(%Node* node) {
+entry:
+    %p = `field_address([node], [])
+    %node = get %Node*, %p
+    goto loop
+loop:
+    %total = phi i64 [entry, $0], [body, %next_total]
+    %cursor = phi %Node* [entry, %node], [body, %next]
+    conditional_jump %Node* if_false, %cursor, #done
+body:
+    %value_address = element_address %Node, %cursor, [value]
+    %value = get i64, %value_address
+    %next_total = add i64 %total, %value
+    %next_address = element_address %Node, %cursor, [next]
+    %next = get %Node*, %next_address
+    goto loop
+done:
+    %r = invoke i32 `function_address([puts], []), [i8*, $3]
+    return %total
+}
+
+vtable Node = [sum]
+";
+
+    fn header() -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn round_trips_a_module() {
+        let ir_module = parse_module(MODULE).unwrap();
+        let bytes = write_module(&ir_module);
+        let decoded = read_module(&bytes).unwrap();
+        assert_eq!(
+            IRDumper::dump_to_string(&decoded),
+            IRDumper::dump_to_string(&ir_module)
+        );
+        assert_eq!(write_module(&decoded), bytes);
+        for function in ir_module.functions.values() {
+            let control_flow_graph = &decoded.functions[&function.name].control_flow_graph;
+            assert_eq!(
+                control_flow_graph.in_edges,
+                function.control_flow_graph.in_edges
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_and_unknown_input() {
+        let bytes = write_module(&parse_module(MODULE).unwrap());
+        for length in MAGIC.len()..bytes.len() {
+            let error = read_module(&bytes[..length]).unwrap_err();
+            assert_eq!(error.kind, IRBinaryErrorKind::Truncated, "{}", length);
+        }
+        assert_eq!(
+            read_module(b"ELF\x7f\x01\x00\x00\x00").unwrap_err().kind,
+            IRBinaryErrorKind::BadMagic
+        );
+
+        let mut bytes = header();
+        // No strings, one type whose tag is unknown.
+        bytes.extend([0, 1, 42]);
+        assert_eq!(
+            read_module(&bytes).unwrap_err(),
+            IRBinaryError {
+                offset: 10,
+                kind: IRBinaryErrorKind::UnknownTag {
+                    what: "type",
+                    tag: 42
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_deeply_nested_constants() {
+        let mut bytes = header();
+        // No strings, the type i8, no structures and one constant of type 0.
+        bytes.extend([0, 1, TYPE_INTEGER, 8, 0, 0, 1, 0]);
+        for _ in 0..100_000 {
+            bytes.extend([VALUE_AGGREGATE, 1]);
+        }
+        bytes.extend([VALUE_I8, 0]);
+        let error = read_module(&bytes).unwrap_err();
+        assert_eq!(error.kind, IRBinaryErrorKind::TooDeep);
+    }
+
+    #[test]
+    fn rejects_deeply_nested_operands() {
+        let mut bytes = header();
+        // The string "m", no types, structures or constants, and one global data whose size is
+        // a chain of macros.
+        bytes.extend([1, 1, b'm', 0, 0, 0, 1, 0, 1]);
+        for _ in 0..100_000 {
+            bytes.extend([OPERAND_MACRO, 0, 0, 1]);
+        }
+        bytes.extend([OPERAND_VIRTUAL_REGISTER, 0]);
+        let error = read_module(&bytes).unwrap_err();
+        assert_eq!(error.kind, IRBinaryErrorKind::TooDeep);
+    }
+
+    #[test]
+    fn rejects_deeply_nested_types() {
+        let mut bytes = header();
+        bytes.push(0);
+        write_unsigned(&mut bytes, 100_000);
+        bytes.extend([TYPE_INTEGER, 8, 0]);
+        for index in 1..100_000 {
+            bytes.push(TYPE_POINTER);
+            write_unsigned(&mut bytes, index - 1);
+        }
+        let error = read_module(&bytes).unwrap_err();
+        assert_eq!(error.kind, IRBinaryErrorKind::TooDeep);
+    }
+
+    #[test]
+    fn rejects_inputs_that_expand_too_far() {
+        let mut bytes = header();
+        // One string of 64 KiB, then a type table whose function types each take the previous
+        // one twice, doubling in size.
+        bytes.push(1);
+        write_unsigned(&mut bytes, 1 << 16);
+        bytes.extend([b'a'; 1 << 16]);
+        bytes.extend([64, TYPE_VOID]);
+        for index in 1..64 {
+            bytes.extend([TYPE_FUNCTION, index - 1, 2, index - 1, index - 1, 0]);
+        }
+        let error = read_module(&bytes).unwrap_err();
+        assert_eq!(error.kind, IRBinaryErrorKind::TooLarge);
+
+        let mut bytes = header();
+        // The same string referenced as a structure name over and over.
+        bytes.push(1);
+        write_unsigned(&mut bytes, 1 << 16);
+        bytes.extend([b'a'; 1 << 16]);
+        write_unsigned(&mut bytes, 100_000);
+        for _ in 0..100_000 {
+            bytes.extend([TYPE_STRUCTURE, 0]);
+        }
+        let error = read_module(&bytes).unwrap_err();
+        assert_eq!(error.kind, IRBinaryErrorKind::TooLarge);
+    }
+}
@@ -0,0 +1,898 @@
+//! Building functions instruction by instruction.
+//!
+//! An `IRBuilder` borrows a module and keeps an insertion point: a basic block of a function (or
+//! of the global init section) and a position in it. Each `build_*` method inserts one
+//! instruction there and returns the register it defines, named after the hint it was given.
+
+use crate::ir::base::{IRBasicBlock, IRCondition, IRControlFlowGraph, IRFunction, IRNode};
+use crate::ir::instruction::{
+    IRCalculate, IRCalculateOperator, IRConditionalJump, IRElementAddress, IRElementIndex, IRFree,
+    IRGet, IRGoto, IRInstruction, IRInvoke, IRMalloc, IRNegate, IRNot, IRRealloc, IRReturn, IRSet,
+    IRSetVirtualRegister, IRStackAllocate, IRTypeCast, IRTypeCastKind,
+};
+use crate::ir::operand::{IRConstant, IROperand, IRPhi, IRVirtualRegister};
+use crate::ir::structure::IRField;
+use crate::ir::types::{IRIntegerType, IRType, IRTypeKind};
+use crate::ir::{IRConstantValue, IRModule, IRVisitor};
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+/// Collects every register name a control flow graph mentions, so that new ones do not clash.
+struct IRRegisterNameCollector {
+    names: RefCell<HashSet<String>>,
+}
+
+impl IRVisitor for IRRegisterNameCollector {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_virtual_register(&self, ir_virtual_register: &IRVirtualRegister) {
+        self.names
+            .borrow_mut()
+            .insert(ir_virtual_register.name.clone());
+    }
+}
+
+/// The register names used by a function, its fields included, or by the global init section
+/// when `function` is `None`.
+pub(crate) fn register_names(ir_module: &IRModule, function: Option<&str>) -> HashSet<String> {
+    let collector = IRRegisterNameCollector {
+        names: RefCell::new(HashSet::new()),
+    };
+    let ir_control_flow_graph = match function.and_then(|name| ir_module.functions.get(name)) {
+        Some(ir_function) => {
+            collector.names.borrow_mut().extend(
+                ir_function
+                    .fields
+                    .iter()
+                    .map(|ir_field| ir_field.name.clone()),
+            );
+            ir_function.control_flow_graph.as_ref()
+        }
+        None => ir_module.global_init_section.as_ref(),
+    };
+    for ir_basic_block in ir_control_flow_graph.basic_blocks.values() {
+        for ir_instruction in ir_basic_block.instructions.iter() {
+            ir_instruction.accept(&collector);
+        }
+    }
+    collector.names.into_inner()
+}
+
+/// `hint` itself if it is free, otherwise the first free `hint.0`, `hint.1`, ...; an empty hint
+/// gives `0`, `1`, ...
+fn fresh_name(hint: &str, used: impl Fn(&str) -> bool) -> String {
+    let mut candidates: Box<dyn Iterator<Item = String>> = if hint.is_empty() {
+        Box::new((0..).map(|n: u64| n.to_string()))
+    } else {
+        Box::new(
+            std::iter::once(hint.to_string()).chain((0..).map(move |n| format!("{}.{}", hint, n))),
+        )
+    };
+    candidates.find(|name| !used(name)).unwrap()
+}
+
+/// Does the instruction decide where control goes after its block?
+fn is_branch(ir_instruction: &dyn IRInstruction) -> bool {
+    ir_instruction.is::<IRGoto>()
+        || ir_instruction.is::<IRConditionalJump>()
+        || ir_instruction.is::<IRReturn>()
+}
+
+pub struct IRBuilder<'a> {
+    ir_module: &'a mut IRModule,
+    /// The function being built, or `None` for the global init section.
+    function: Option<String>,
+    basic_block: Option<String>,
+    /// Where the next instruction goes in the block; `None` appends it.
+    index: Option<usize>,
+    register_names: HashSet<String>,
+}
+
+impl<'a> IRBuilder<'a> {
+    /// A builder positioned in the global init section, with no basic block yet.
+    pub fn new(ir_module: &'a mut IRModule) -> Self {
+        let register_names = register_names(ir_module, None);
+        Self {
+            ir_module,
+            function: None,
+            basic_block: None,
+            index: None,
+            register_names,
+        }
+    }
+
+    pub fn ir_module(&mut self) -> &mut IRModule {
+        self.ir_module
+    }
+
+    pub fn function(&self) -> Option<&str> {
+        self.function.as_deref()
+    }
+
+    pub fn basic_block(&self) -> Option<&str> {
+        self.basic_block.as_deref()
+    }
+
+    /// Adds an empty function whose fields are its arguments and moves into it. A function of
+    /// the same name is replaced.
+    pub fn add_function(
+        &mut self,
+        return_type: Box<dyn IRType>,
+        name: String,
+        arguments: Vec<Box<IRField>>,
+    ) {
+        let arguments_count = arguments.len();
+        self.ir_module.push_function(IRFunction::new(
+            return_type,
+            name.clone(),
+            arguments_count,
+            arguments,
+            Box::default(),
+        ));
+        self.position_at_function(&name);
+    }
+
+    /// Adds a local variable to the current function and returns the name it got.
+    ///
+    /// # Panics
+    ///
+    /// In the global init section, which has no fields.
+    pub fn add_local(&mut self, name: &str, _type: Box<dyn IRType>) -> String {
+        let name = self.fresh_register_name(name);
+        self.function_mut()
+            .fields
+            .push(Box::new(IRField::new(name.clone(), _type)));
+        name
+    }
+
+    /// Moves into an existing function, with no basic block selected.
+    ///
+    /// # Panics
+    ///
+    /// If the module has no function `name`.
+    pub fn position_at_function(&mut self, name: &str) {
+        assert!(
+            self.ir_module.functions.contains_key(name),
+            "no function named {}",
+            name
+        );
+        self.register_names = register_names(self.ir_module, Some(name));
+        self.function = Some(name.to_string());
+        self.basic_block = None;
+        self.index = None;
+    }
+
+    /// Moves into the global init section, with no basic block selected.
+    pub fn position_at_global_init(&mut self) {
+        self.register_names = register_names(self.ir_module, None);
+        self.function = None;
+        self.basic_block = None;
+        self.index = None;
+    }
+
+    /// Appends new instructions to the end of `basic_block` in the current function.
+    ///
+    /// # Panics
+    ///
+    /// If the current function has no such block.
+    pub fn position_at_end(&mut self, basic_block: &str) {
+        self.position(basic_block, None);
+    }
+
+    /// Inserts new instructions before the one at `index` of `basic_block`, keeping them in the
+    /// order they are built.
+    ///
+    /// # Panics
+    ///
+    /// If the current function has no such block or the block is shorter than `index`.
+    pub fn position_before(&mut self, basic_block: &str, index: usize) {
+        self.position(basic_block, Some(index));
+    }
+
+    fn position(&mut self, basic_block: &str, index: Option<usize>) {
+        let Some(ir_basic_block) = self.control_flow_graph().basic_blocks.get(basic_block) else {
+            panic!("no basic block named {}", basic_block);
+        };
+        if let Some(index) = index {
+            assert!(
+                index <= ir_basic_block.instructions.len(),
+                "basic block {} has fewer than {} instructions",
+                basic_block,
+                index
+            );
+        }
+        self.basic_block = Some(basic_block.to_string());
+        self.index = index;
+    }
+
+    /// Appends an empty block to the current function and returns the name it got, without
+    /// moving the insertion point.
+    pub fn append_basic_block(&mut self, name: &str) -> String {
+        let name = self.fresh_basic_block_name(name);
+        self.control_flow_graph_mut()
+            .add_basic_block(Box::new(IRBasicBlock::new(name.clone())));
+        name
+    }
+
+    /// Like `append_basic_block`, but places the block right after the current one.
+    pub fn insert_basic_block(&mut self, name: &str) -> String {
+        let name = self.fresh_basic_block_name(name);
+        let index = self
+            .basic_block
+            .as_ref()
+            .and_then(|basic_block| {
+                self.control_flow_graph()
+                    .basic_blocks
+                    .get_index_of(basic_block)
+            })
+            .map_or(usize::MAX, |index| index + 1);
+        self.control_flow_graph_mut()
+            .insert_basic_block(index, Box::new(IRBasicBlock::new(name.clone())));
+        name
+    }
+
+    pub fn fresh_basic_block_name(&self, hint: &str) -> String {
+        let ir_control_flow_graph = self.control_flow_graph();
+        fresh_name(hint, |name| {
+            ir_control_flow_graph.basic_blocks.contains_key(name)
+        })
+    }
+
+    /// A register name not used anywhere in the current function, reserved from now on.
+    pub fn fresh_register_name(&mut self, hint: &str) -> String {
+        let name = fresh_name(hint, |name| self.register_names.contains(name));
+        self.register_names.insert(name.clone());
+        name
+    }
+
+    pub fn fresh_register(&mut self, hint: &str) -> Box<IRVirtualRegister> {
+        Box::new(IRVirtualRegister::new(self.fresh_register_name(hint)))
+    }
+
+    /// The constant for `value` of type `_type`, interned into the module's constant pool.
+    pub fn constant(&mut self, _type: Box<dyn IRType>, value: IRConstantValue) -> Box<IRConstant> {
+        Box::new(self.ir_module.constant_pool.intern(_type, value))
+    }
+
+    /// The integer constant of type `_type` holding the low bits of `value`.
+    pub fn integer_constant(&mut self, _type: IRIntegerType, value: i64) -> Box<IRConstant> {
+        let value = IRConstantValue::integer(&_type.size, value);
+        self.constant(Box::new(_type), value)
+    }
+
+    /// Inserts an instruction at the insertion point and moves past it.
+    ///
+    /// # Panics
+    ///
+    /// If no basic block is selected.
+    pub fn insert(&mut self, ir_instruction: Box<dyn IRInstruction>) {
+        let basic_block = self
+            .basic_block
+            .clone()
+            .expect("IRBuilder has no basic block to insert into");
+        let collector = IRRegisterNameCollector {
+            names: RefCell::new(HashSet::new()),
+        };
+        ir_instruction.accept(&collector);
+        self.register_names.extend(collector.names.into_inner());
+
+        let index = self.index;
+        let ir_control_flow_graph = self.control_flow_graph_mut();
+        let instructions = &mut ir_control_flow_graph.basic_blocks[&basic_block].instructions;
+        let index = index.unwrap_or(instructions.len()).min(instructions.len());
+        // Only a branch, or an instruction placed after one, can change the edges of the block.
+        let update_edges = is_branch(ir_instruction.as_ref())
+            || (index == instructions.len()
+                && instructions
+                    .last()
+                    .is_some_and(|last| is_branch(last.as_ref())));
+        instructions.insert(index, ir_instruction);
+        if update_edges {
+            ir_control_flow_graph.update_edges(&basic_block);
+        }
+        if let Some(index) = self.index.as_mut() {
+            *index += 1;
+        }
+    }
+
+    pub fn build_calculate(
+        &mut self,
+        operator: IRCalculateOperator,
+        _type: Box<dyn IRType>,
+        operand1: Box<dyn IROperand>,
+        operand2: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        let target = self.fresh_register(name);
+        self.insert(Box::new(IRCalculate::new(
+            false,
+            operator,
+            _type,
+            operand1,
+            operand2,
+            target.clone(),
+        )));
+        target
+    }
+
+    pub fn build_add(
+        &mut self,
+        _type: Box<dyn IRType>,
+        operand1: Box<dyn IROperand>,
+        operand2: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        self.build_calculate(IRCalculateOperator::ADD, _type, operand1, operand2, name)
+    }
+
+    pub fn build_sub(
+        &mut self,
+        _type: Box<dyn IRType>,
+        operand1: Box<dyn IROperand>,
+        operand2: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        self.build_calculate(IRCalculateOperator::SUB, _type, operand1, operand2, name)
+    }
+
+    pub fn build_mul(
+        &mut self,
+        _type: Box<dyn IRType>,
+        operand1: Box<dyn IROperand>,
+        operand2: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        self.build_calculate(IRCalculateOperator::MUL, _type, operand1, operand2, name)
+    }
+
+    pub fn build_div(
+        &mut self,
+        _type: Box<dyn IRType>,
+        operand1: Box<dyn IROperand>,
+        operand2: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        self.build_calculate(IRCalculateOperator::DIV, _type, operand1, operand2, name)
+    }
+
+    pub fn build_mod(
+        &mut self,
+        _type: Box<dyn IRType>,
+        operand1: Box<dyn IROperand>,
+        operand2: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        self.build_calculate(IRCalculateOperator::MOD, _type, operand1, operand2, name)
+    }
+
+    pub fn build_and(
+        &mut self,
+        _type: Box<dyn IRType>,
+        operand1: Box<dyn IROperand>,
+        operand2: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        self.build_calculate(IRCalculateOperator::AND, _type, operand1, operand2, name)
+    }
+
+    pub fn build_or(
+        &mut self,
+        _type: Box<dyn IRType>,
+        operand1: Box<dyn IROperand>,
+        operand2: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        self.build_calculate(IRCalculateOperator::OR, _type, operand1, operand2, name)
+    }
+
+    pub fn build_xor(
+        &mut self,
+        _type: Box<dyn IRType>,
+        operand1: Box<dyn IROperand>,
+        operand2: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        self.build_calculate(IRCalculateOperator::XOR, _type, operand1, operand2, name)
+    }
+
+    pub fn build_shl(
+        &mut self,
+        _type: Box<dyn IRType>,
+        operand1: Box<dyn IROperand>,
+        operand2: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        self.build_calculate(IRCalculateOperator::SHL, _type, operand1, operand2, name)
+    }
+
+    pub fn build_shr(
+        &mut self,
+        _type: Box<dyn IRType>,
+        operand1: Box<dyn IROperand>,
+        operand2: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        self.build_calculate(IRCalculateOperator::SHR, _type, operand1, operand2, name)
+    }
+
+    pub fn build_ushr(
+        &mut self,
+        _type: Box<dyn IRType>,
+        operand1: Box<dyn IROperand>,
+        operand2: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        self.build_calculate(IRCalculateOperator::USHR, _type, operand1, operand2, name)
+    }
+
+    pub fn build_not(
+        &mut self,
+        _type: Box<dyn IRType>,
+        operand: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        let target = self.fresh_register(name);
+        self.insert(Box::new(IRNot::new(false, _type, operand, target.clone())));
+        target
+    }
+
+    pub fn build_negate(
+        &mut self,
+        _type: Box<dyn IRType>,
+        operand: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        let target = self.fresh_register(name);
+        self.insert(Box::new(IRNegate::new(
+            false,
+            _type,
+            operand,
+            target.clone(),
+        )));
+        target
+    }
+
+    pub fn build_goto(&mut self, target: &str) {
+        self.insert(Box::new(IRGoto::new(target.to_string())));
+    }
+
+    /// Jumps to `target` if the condition holds and falls through to the next block otherwise.
+    pub fn build_cond_jump(
+        &mut self,
+        _type: Box<dyn IRType>,
+        condition: IRCondition,
+        operand1: Box<dyn IROperand>,
+        operand2: Option<Box<dyn IROperand>>,
+        target: &str,
+    ) {
+        self.insert(Box::new(IRConditionalJump::new(
+            false,
+            _type,
+            condition,
+            operand1,
+            operand2,
+            target.to_string(),
+        )));
+    }
+
+    pub fn build_return(&mut self, operand: Option<Box<dyn IROperand>>) {
+        self.insert(Box::new(IRReturn::new(operand)));
+    }
+
+    /// Calls `address` with `arguments` given as type and value pairs. Returns the register that
+    /// holds the result, or `None` when `return_type` is void.
+    pub fn build_invoke(
+        &mut self,
+        return_type: Box<dyn IRType>,
+        address: Box<dyn IROperand>,
+        arguments: Vec<(Box<dyn IRType>, Box<dyn IROperand>)>,
+        name: &str,
+    ) -> Option<Box<IRVirtualRegister>> {
+        let target = match IRTypeKind::of(return_type.as_ref()) {
+            IRTypeKind::Void(_) => None,
+            _ => Some(self.fresh_register(name)),
+        };
+        let (argument_types, arguments) = arguments.into_iter().unzip();
+        self.insert(Box::new(IRInvoke::new(
+            return_type,
+            address,
+            argument_types,
+            arguments,
+            target.clone(),
+        )));
+        target
+    }
+
+    /// Assigns a phi of `incoming` (predecessor block and value pairs) to a new register.
+    pub fn build_phi(
+        &mut self,
+        _type: Box<dyn IRType>,
+        incoming: Vec<(String, Box<dyn IROperand>)>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        let (labels, operands) = incoming.into_iter().unzip();
+        self.build_copy(Box::new(IRPhi::new(_type, labels, operands)), name)
+    }
+
+    /// Adds an incoming value to the phi assigned to `phi` in the current function, for values
+    /// that are only built after the phi, such as those coming round a loop.
+    ///
+    /// # Panics
+    ///
+    /// If no phi in the current function is assigned to `phi`.
+    pub fn add_phi_incoming(
+        &mut self,
+        phi: &IRVirtualRegister,
+        basic_block: &str,
+        value: Box<dyn IROperand>,
+    ) {
+        let collector = IRRegisterNameCollector {
+            names: RefCell::new(HashSet::new()),
+        };
+        value.accept(&collector);
+        self.register_names.extend(collector.names.into_inner());
+
+        let ir_phi = self
+            .control_flow_graph_mut()
+            .basic_blocks
+            .values_mut()
+            .flat_map(|ir_basic_block| ir_basic_block.instructions.iter_mut())
+            .filter_map(|ir_instruction| ir_instruction.downcast_mut::<IRSetVirtualRegister>())
+            .filter(|ir_set_virtual_register| ir_set_virtual_register.target.name == phi.name)
+            .find_map(|ir_set_virtual_register| {
+                ir_set_virtual_register.source.downcast_mut::<IRPhi>()
+            });
+        let Some(ir_phi) = ir_phi else {
+            panic!("no phi is assigned to {}", phi);
+        };
+        ir_phi.labels.push(basic_block.to_string());
+        ir_phi.operands.push(value);
+    }
+
+    /// Assigns `source` to a new register.
+    pub fn build_copy(&mut self, source: Box<dyn IROperand>, name: &str) -> Box<IRVirtualRegister> {
+        let target = self.fresh_register(name);
+        self.insert(Box::new(IRSetVirtualRegister::new(source, target.clone())));
+        target
+    }
+
+    pub fn build_malloc(&mut self, size: Box<dyn IROperand>, name: &str) -> Box<IRVirtualRegister> {
+        let target = self.fresh_register(name);
+        self.insert(Box::new(IRMalloc::new(size, target.clone())));
+        target
+    }
+
+    pub fn build_free(&mut self, ptr: Box<dyn IROperand>) {
+        self.insert(Box::new(IRFree::new(ptr)));
+    }
+
+    pub fn build_realloc(
+        &mut self,
+        ptr: Box<dyn IROperand>,
+        size: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        let target = self.fresh_register(name);
+        self.insert(Box::new(IRRealloc::new(ptr, size, target.clone())));
+        target
+    }
+
+    pub fn build_stack_alloc(
+        &mut self,
+        size: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        let target = self.fresh_register(name);
+        self.insert(Box::new(IRStackAllocate::new(size, target.clone())));
+        target
+    }
+
+    pub fn build_get(
+        &mut self,
+        _type: Box<dyn IRType>,
+        address: Box<dyn IROperand>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        let target = self.fresh_register(name);
+        self.insert(Box::new(IRGet::new(_type, address, target.clone())));
+        target
+    }
+
+    pub fn build_set(
+        &mut self,
+        _type: Box<dyn IRType>,
+        address: Box<dyn IROperand>,
+        value: Box<dyn IROperand>,
+    ) {
+        self.insert(Box::new(IRSet::new(_type, address, value)));
+    }
+
+    pub fn build_element_address(
+        &mut self,
+        _type: Box<dyn IRType>,
+        base: Box<dyn IROperand>,
+        path: Vec<IRElementIndex>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        let target = self.fresh_register(name);
+        self.insert(Box::new(IRElementAddress::new(
+            _type,
+            base,
+            path,
+            target.clone(),
+        )));
+        target
+    }
+
+    pub fn build_type_cast(
+        &mut self,
+        kind: IRTypeCastKind,
+        original_type: Box<dyn IRType>,
+        source: Box<dyn IROperand>,
+        target_type: Box<dyn IRType>,
+        name: &str,
+    ) -> Box<IRVirtualRegister> {
+        let target = self.fresh_register(name);
+        self.insert(Box::new(IRTypeCast::new(
+            kind,
+            original_type,
+            source,
+            target_type,
+            target.clone(),
+        )));
+        target
+    }
+
+    fn function_mut(&mut self) -> &mut IRFunction {
+        let function = self
+            .function
+            .as_ref()
+            .expect("IRBuilder is in the global init section, not a function");
+        &mut self.ir_module.functions[function]
+    }
+
+    fn control_flow_graph(&self) -> &IRControlFlowGraph {
+        match self.function.as_ref() {
+            Some(function) => &self.ir_module.functions[function].control_flow_graph,
+            None => &self.ir_module.global_init_section,
+        }
+    }
+
+    fn control_flow_graph_mut(&mut self) -> &mut IRControlFlowGraph {
+        match self.function.as_ref() {
+            Some(function) => &mut self.ir_module.functions[function].control_flow_graph,
+            None => &mut self.ir_module.global_init_section,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::instruction::IRNoOperate;
+    use crate::ir::interp::{IRInterpreter, IRValue};
+    use crate::ir::operand::IRMacro;
+    use crate::ir::types::{IRIntegerTypeSize, IRVoidType};
+    use crate::ir::verify::verify_module;
+
+    fn i64_type() -> IRIntegerType {
+        IRIntegerType::new(IRIntegerTypeSize::EightBytes, false)
+    }
+
+    fn field_address(name: &str) -> Box<dyn IROperand> {
+        Box::new(IRMacro::new(
+            "field_address".to_string(),
+            vec![name.to_string()],
+            vec![],
+        ))
+    }
+
+    fn function_address(name: &str) -> Box<dyn IROperand> {
+        Box::new(IRMacro::new(
+            "function_address".to_string(),
+            vec![name.to_string()],
+            vec![],
+        ))
+    }
+
+    fn instructions(ir_module: &IRModule, function: &str, basic_block: &str) -> Vec<String> {
+        ir_module.functions[function]
+            .control_flow_graph
+            .basic_blocks[basic_block]
+            .instructions
+            .iter()
+            .map(|ir_instruction| ir_instruction.to_string())
+            .collect()
+    }
+
+    /// Adds `f(n)` with an empty `entry` block and positions the builder at its end.
+    fn function_with_entry(builder: &mut IRBuilder) {
+        builder.add_function(
+            Box::new(i64_type()),
+            "f".to_string(),
+            vec![Box::new(IRField::new(
+                "n".to_string(),
+                Box::new(i64_type()),
+            ))],
+        );
+        let entry = builder.append_basic_block("entry");
+        builder.position_at_end(&entry);
+    }
+
+    /// `twice(x)` returns `x + x`; `sum(n)` adds up `0..=n` in a loop and returns twice that.
+    fn build_sum(ir_module: &mut IRModule) {
+        let mut builder = IRBuilder::new(ir_module);
+        builder.add_function(
+            Box::new(i64_type()),
+            "twice".to_string(),
+            vec![Box::new(IRField::new(
+                "x".to_string(),
+                Box::new(i64_type()),
+            ))],
+        );
+        let entry = builder.append_basic_block("entry");
+        builder.position_at_end(&entry);
+        let px = builder.build_copy(field_address("x"), "px");
+        let x = builder.build_get(Box::new(i64_type()), px, "x");
+        let r = builder.build_add(Box::new(i64_type()), x.clone(), x, "r");
+        builder.build_return(Some(r));
+
+        builder.add_function(
+            Box::new(i64_type()),
+            "sum".to_string(),
+            vec![Box::new(IRField::new(
+                "n".to_string(),
+                Box::new(i64_type()),
+            ))],
+        );
+        let entry = builder.append_basic_block("entry");
+        let body = builder.append_basic_block("loop");
+        let done = builder.append_basic_block("done");
+        builder.position_at_end(&entry);
+        let pn = builder.build_copy(field_address("n"), "pn");
+        let n = builder.build_get(Box::new(i64_type()), pn, "n");
+        let zero = builder.integer_constant(i64_type(), 0);
+        let one = builder.integer_constant(i64_type(), 1);
+        builder.build_goto(&body);
+
+        builder.position_at_end(&body);
+        let i = builder.build_phi(
+            Box::new(i64_type()),
+            vec![(entry.clone(), zero.clone())],
+            "i",
+        );
+        let total = builder.build_phi(Box::new(i64_type()), vec![(entry, zero)], "total");
+        let next_total = builder.build_add(Box::new(i64_type()), total.clone(), i.clone(), "total");
+        let next_i = builder.build_add(Box::new(i64_type()), i.clone(), one, "i");
+        builder.add_phi_incoming(&i, &body, next_i.clone());
+        builder.add_phi_incoming(&total, &body, next_total.clone());
+        builder.build_cond_jump(
+            Box::new(i64_type()),
+            IRCondition::LessEqual,
+            next_i,
+            Some(n),
+            &body,
+        );
+
+        builder.position_at_end(&done);
+        let r = builder
+            .build_invoke(
+                Box::new(i64_type()),
+                function_address("twice"),
+                vec![(Box::new(i64_type()), next_total)],
+                "r",
+            )
+            .unwrap();
+        builder.build_return(Some(r));
+    }
+
+    #[test]
+    fn built_functions_verify_and_run() {
+        let mut ir_module = IRModule::new();
+        build_sum(&mut ir_module);
+        assert_eq!(
+            instructions(&ir_module, "sum", "loop"),
+            [
+                "%i = phi i64 [entry, $0], [loop, %i.0]",
+                "%total = phi i64 [entry, $0], [loop, %total.0]",
+                "%total.0 = add i64 %total, %i",
+                "%i.0 = add i64 %i, $1",
+                // The argument field already goes by `n`.
+                "conditional_jump i64 le, %i.0, %n.0, #loop",
+            ]
+        );
+        let graph = &ir_module.functions["sum"].control_flow_graph;
+        assert_eq!(graph.successors("entry"), ["loop"]);
+        assert_eq!(graph.successors("loop"), ["loop", "done"]);
+        assert_eq!(ir_module.constant_pool.entries.len(), 2);
+
+        verify_module(&ir_module).unwrap();
+        let result = IRInterpreter::new(&ir_module)
+            .with_step_limit(10_000)
+            .call("sum", &[IRValue::Integer(10)])
+            .unwrap();
+        assert_eq!(result, Some(IRValue::Integer(110)));
+    }
+
+    #[test]
+    fn names_avoid_fields_registers_and_blocks_in_use() {
+        let mut ir_module = IRModule::new();
+        let mut builder = IRBuilder::new(&mut ir_module);
+        function_with_entry(&mut builder);
+        assert_eq!(builder.fresh_register_name("n"), "n.0");
+        assert_eq!(builder.fresh_register_name("n"), "n.1");
+        assert_eq!(builder.fresh_register_name(""), "0");
+        assert_eq!(builder.append_basic_block("entry"), "entry.0");
+        assert_eq!(builder.add_local("n", Box::new(i64_type())), "n.2");
+
+        // Moving back into the function picks up the names already used in it.
+        let pn = builder.build_copy(field_address("n"), "p");
+        builder.position_at_global_init();
+        builder.position_at_function("f");
+        builder.position_at_end("entry");
+        assert_eq!(builder.fresh_register_name(&pn.name), "p.0");
+    }
+
+    #[test]
+    fn instructions_built_before_an_index_keep_their_order() {
+        let mut ir_module = IRModule::new();
+        let mut builder = IRBuilder::new(&mut ir_module);
+        function_with_entry(&mut builder);
+        builder.build_return(None);
+        builder.position_before("entry", 0);
+        builder.insert(Box::new(IRNoOperate::new()));
+        let one = builder.integer_constant(i64_type(), 1);
+        builder.build_copy(one, "one");
+        assert_eq!(
+            instructions(&ir_module, "f", "entry"),
+            ["nop", "%one = $0", "return"]
+        );
+    }
+
+    #[test]
+    fn inserted_blocks_follow_the_current_one_and_jumps_update_the_edges() {
+        let mut ir_module = IRModule::new();
+        let mut builder = IRBuilder::new(&mut ir_module);
+        function_with_entry(&mut builder);
+        let exit = builder.append_basic_block("exit");
+        let middle = builder.insert_basic_block("middle");
+        builder.build_goto(&exit);
+        let graph = &builder.ir_module().functions["f"].control_flow_graph;
+        assert_eq!(
+            graph.basic_blocks.keys().collect::<Vec<_>>(),
+            ["entry", "middle", "exit"]
+        );
+        assert_eq!(graph.successors("entry"), ["exit"]);
+        assert!(graph.predecessors(&middle).is_empty());
+    }
+
+    #[test]
+    fn void_calls_define_no_register() {
+        let mut ir_module = IRModule::new();
+        let mut builder = IRBuilder::new(&mut ir_module);
+        function_with_entry(&mut builder);
+        let result = builder.build_invoke(
+            Box::new(IRVoidType::new()),
+            function_address("f"),
+            vec![],
+            "r",
+        );
+        assert!(result.is_none());
+        assert_eq!(
+            instructions(&ir_module, "f", "entry"),
+            ["invoke void `function_address([f], [])"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no phi is assigned to %n")]
+    fn adding_incoming_values_needs_a_phi() {
+        let mut ir_module = IRModule::new();
+        let mut builder = IRBuilder::new(&mut ir_module);
+        function_with_entry(&mut builder);
+        let pn = builder.build_copy(field_address("n"), "pn");
+        let n = builder.build_get(Box::new(i64_type()), pn, "n");
+        builder.add_phi_incoming(&n, "entry", n.clone());
+    }
+}
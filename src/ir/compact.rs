@@ -0,0 +1,130 @@
+use crate::ir::operand::{IRConstant, IROperand};
+use crate::ir::rewrite::{IRVisitorMut, walk_operand_mut};
+use crate::ir::{IRConstantKey, IRModule};
+use std::collections::HashMap;
+
+/// Hands the index of every constant in the module to `f`, nested operands included, and
+/// renumbers the constant to whatever `f` returns.
+struct IRConstantRenumberer<F: FnMut(u32) -> Option<u32>> {
+    f: F,
+}
+
+impl<F: FnMut(u32) -> Option<u32>> IRVisitorMut for IRConstantRenumberer<F> {
+    fn visit_operand_mut(&mut self, ir_operand: &mut Box<dyn IROperand>) {
+        match ir_operand.downcast_ref::<IRConstant>() {
+            Some(&IRConstant { index }) => {
+                if let Some(new_index) = (self.f)(index) {
+                    *ir_operand = Box::new(IRConstant::new(new_index));
+                }
+            }
+            None => walk_operand_mut(self, ir_operand),
+        }
+    }
+}
+
+fn renumber_constants(ir_module: &mut IRModule, f: impl FnMut(u32) -> Option<u32>) {
+    IRConstantRenumberer { f }.visit_module_mut(ir_module);
+}
+
+/// Removes the constant pool entries no operand refers to, merges equal entries, and renumbers
+/// every `IRConstant` in the module to match. Returns the number of entries removed.
+///
+/// Constants whose index is out of range are left as they are for the verifier to report.
+pub fn compact_constant_pool(ir_module: &mut IRModule) -> usize {
+    let count = ir_module.constant_pool.entries.len();
+
+    let mut used = vec![false; count];
+    renumber_constants(ir_module, |index| {
+        if let Some(used) = used.get_mut(index as usize) {
+            *used = true;
+        }
+        None
+    });
+
+    let mut kept = vec![false; count];
+    let mut new_indices: Vec<Option<u32>> = vec![None; count];
+    let mut first_of: HashMap<(String, IRConstantKey), u32> = HashMap::new();
+    let mut next_index = 0;
+    for (index, entry) in ir_module.constant_pool.entries.iter().enumerate() {
+        if !used[index] {
+            continue;
+        }
+        let key = IRConstantKey::of(entry._type.as_ref(), &entry.value);
+        let new_index = *first_of.entry(key).or_insert_with(|| {
+            kept[index] = true;
+            next_index += 1;
+            next_index - 1
+        });
+        new_indices[index] = Some(new_index);
+    }
+
+    renumber_constants(ir_module, |index| {
+        new_indices.get(index as usize).copied().flatten()
+    });
+
+    let mut kept = kept.into_iter();
+    ir_module
+        .constant_pool
+        .entries
+        .retain(|_| kept.next().unwrap_or(false));
+    count - ir_module.constant_pool.entries.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parser::parse_module;
+
+    const MODULE: &str = "\
+constant $0 = i64 1
+constant $1 = i64 2
+constant $2 = i64 1
+constant $3 = i32 1
+constant $4 = i64 2
+global counter, size=$4
+function i64 f() {
+entry:
+    %a = add i64 $2, $0
+    %b = add i64 %a, $4
+    return %b
+}
+";
+
+    fn instructions(ir_module: &IRModule) -> Vec<String> {
+        ir_module.functions["f"].control_flow_graph.basic_blocks["entry"]
+            .instructions
+            .iter()
+            .map(|ir_instruction| ir_instruction.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn unused_constants_are_removed_and_equal_ones_merged() {
+        let mut ir_module = parse_module(MODULE).unwrap();
+        assert_eq!(compact_constant_pool(&mut ir_module), 3);
+        let entries = &ir_module.constant_pool.entries;
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| entry.to_string())
+                .collect::<Vec<_>>(),
+            ["Entry{type=i64, value=1}", "Entry{type=i64, value=2}"]
+        );
+        assert_eq!(
+            instructions(&ir_module),
+            ["%a = add i64 $0, $0", "%b = add i64 %a, $1", "return %b"]
+        );
+        let size = ir_module.global_data_section.data[0].size.as_ref().unwrap();
+        assert_eq!(size.downcast_ref::<IRConstant>().unwrap().index, 1);
+    }
+
+    #[test]
+    fn a_compact_pool_is_left_alone() {
+        let mut ir_module = parse_module(MODULE).unwrap();
+        compact_constant_pool(&mut ir_module);
+        let before = instructions(&ir_module);
+        assert_eq!(compact_constant_pool(&mut ir_module), 0);
+        assert_eq!(ir_module.constant_pool.entries.len(), 2);
+        assert_eq!(instructions(&ir_module), before);
+    }
+}
@@ -1,15 +1,331 @@
 use clone_dyn::clone_dyn;
 
-use crate::ir::IRVisitor;
 use crate::ir::base::{IRCondition, IRNode};
 use crate::ir::operand::{IROperand, IRVirtualRegister};
-use crate::ir::types::IRType;
+use crate::ir::types::{IRType, IRTypeKind};
+use crate::ir::{IRModule, IRVisitor};
 
+use std::any::Any;
+use std::cell::RefCell;
 use std::fmt::{self, Debug};
 use std::fmt::{Display, Formatter};
 
 #[clone_dyn]
-pub trait IRInstruction: IRNode + Debug {}
+pub trait IRInstruction: IRNode + Debug + Any {
+    /// The operands of the instruction, for passes that rewrite them in place.
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![]
+    }
+}
+
+impl dyn IRInstruction {
+    pub fn is<T: IRInstruction>(&self) -> bool {
+        (self as &dyn Any).is::<T>()
+    }
+
+    pub fn downcast_ref<T: IRInstruction>(&self) -> Option<&T> {
+        (self as &dyn Any).downcast_ref()
+    }
+
+    pub fn downcast_mut<T: IRInstruction>(&mut self) -> Option<&mut T> {
+        (self as &mut dyn Any).downcast_mut()
+    }
+
+    /// See `IRInstructionKind::of` for what this costs.
+    pub fn kind(&self) -> IRInstructionKind {
+        IRInstructionKind::of(self)
+    }
+}
+
+/// A closed view of an `IRInstruction` trait object, for passes that match on instructions.
+#[derive(Clone, Debug)]
+pub enum IRInstructionKind {
+    Goto(IRGoto),
+    ConditionalJump(IRConditionalJump),
+    NoOperate(IRNoOperate),
+    Return(IRReturn),
+    Malloc(IRMalloc),
+    Free(IRFree),
+    Realloc(IRRealloc),
+    Set(IRSet),
+    Get(IRGet),
+    SetVirtualRegister(IRSetVirtualRegister),
+    TypeCast(IRTypeCast),
+    StackAllocate(IRStackAllocate),
+    ElementAddress(IRElementAddress),
+    Calculate(IRCalculate),
+    Increase(IRIncrease),
+    Decrease(IRDecrease),
+    Not(IRNot),
+    Negate(IRNegate),
+    Invoke(IRInvoke),
+    Asm(IRAsm),
+}
+
+impl IRInstructionKind {
+    /// Clones the instruction with everything it holds, types and nested operands included. To
+    /// test for or borrow a single kind, `downcast_ref` does so without copying.
+    pub fn of(ir_instruction: &dyn IRInstruction) -> Self {
+        let collector = IRInstructionKindCollector {
+            kind: RefCell::new(None),
+        };
+        ir_instruction.accept(&collector);
+        collector
+            .kind
+            .into_inner()
+            .expect("every IRInstruction is visited through one of the instruction visitor methods")
+    }
+
+    pub fn into_instruction(self) -> Box<dyn IRInstruction> {
+        match self {
+            IRInstructionKind::Goto(ir_goto) => Box::new(ir_goto),
+            IRInstructionKind::ConditionalJump(ir_conditional_jump) => {
+                Box::new(ir_conditional_jump)
+            }
+            IRInstructionKind::NoOperate(ir_no_operate) => Box::new(ir_no_operate),
+            IRInstructionKind::Return(ir_return) => Box::new(ir_return),
+            IRInstructionKind::Malloc(ir_malloc) => Box::new(ir_malloc),
+            IRInstructionKind::Free(ir_free) => Box::new(ir_free),
+            IRInstructionKind::Realloc(ir_realloc) => Box::new(ir_realloc),
+            IRInstructionKind::Set(ir_set) => Box::new(ir_set),
+            IRInstructionKind::Get(ir_get) => Box::new(ir_get),
+            IRInstructionKind::SetVirtualRegister(ir_set_virtual_register) => {
+                Box::new(ir_set_virtual_register)
+            }
+            IRInstructionKind::TypeCast(ir_type_cast) => Box::new(ir_type_cast),
+            IRInstructionKind::StackAllocate(ir_stack_allocate) => Box::new(ir_stack_allocate),
+            IRInstructionKind::ElementAddress(ir_element_address) => Box::new(ir_element_address),
+            IRInstructionKind::Calculate(ir_calculate) => Box::new(ir_calculate),
+            IRInstructionKind::Increase(ir_increase) => Box::new(ir_increase),
+            IRInstructionKind::Decrease(ir_decrease) => Box::new(ir_decrease),
+            IRInstructionKind::Not(ir_not) => Box::new(ir_not),
+            IRInstructionKind::Negate(ir_negate) => Box::new(ir_negate),
+            IRInstructionKind::Invoke(ir_invoke) => Box::new(ir_invoke),
+            IRInstructionKind::Asm(ir_asm) => Box::new(ir_asm),
+        }
+    }
+}
+
+impl From<IRInstructionKind> for Box<dyn IRInstruction> {
+    fn from(ir_instruction_kind: IRInstructionKind) -> Self {
+        ir_instruction_kind.into_instruction()
+    }
+}
+
+impl From<IRGoto> for IRInstructionKind {
+    fn from(ir_goto: IRGoto) -> Self {
+        IRInstructionKind::Goto(ir_goto)
+    }
+}
+
+impl From<IRConditionalJump> for IRInstructionKind {
+    fn from(ir_conditional_jump: IRConditionalJump) -> Self {
+        IRInstructionKind::ConditionalJump(ir_conditional_jump)
+    }
+}
+
+impl From<IRNoOperate> for IRInstructionKind {
+    fn from(ir_no_operate: IRNoOperate) -> Self {
+        IRInstructionKind::NoOperate(ir_no_operate)
+    }
+}
+
+impl From<IRReturn> for IRInstructionKind {
+    fn from(ir_return: IRReturn) -> Self {
+        IRInstructionKind::Return(ir_return)
+    }
+}
+
+impl From<IRMalloc> for IRInstructionKind {
+    fn from(ir_malloc: IRMalloc) -> Self {
+        IRInstructionKind::Malloc(ir_malloc)
+    }
+}
+
+impl From<IRFree> for IRInstructionKind {
+    fn from(ir_free: IRFree) -> Self {
+        IRInstructionKind::Free(ir_free)
+    }
+}
+
+impl From<IRRealloc> for IRInstructionKind {
+    fn from(ir_realloc: IRRealloc) -> Self {
+        IRInstructionKind::Realloc(ir_realloc)
+    }
+}
+
+impl From<IRSet> for IRInstructionKind {
+    fn from(ir_set: IRSet) -> Self {
+        IRInstructionKind::Set(ir_set)
+    }
+}
+
+impl From<IRGet> for IRInstructionKind {
+    fn from(ir_get: IRGet) -> Self {
+        IRInstructionKind::Get(ir_get)
+    }
+}
+
+impl From<IRSetVirtualRegister> for IRInstructionKind {
+    fn from(ir_set_virtual_register: IRSetVirtualRegister) -> Self {
+        IRInstructionKind::SetVirtualRegister(ir_set_virtual_register)
+    }
+}
+
+impl From<IRTypeCast> for IRInstructionKind {
+    fn from(ir_type_cast: IRTypeCast) -> Self {
+        IRInstructionKind::TypeCast(ir_type_cast)
+    }
+}
+
+impl From<IRStackAllocate> for IRInstructionKind {
+    fn from(ir_stack_allocate: IRStackAllocate) -> Self {
+        IRInstructionKind::StackAllocate(ir_stack_allocate)
+    }
+}
+
+impl From<IRElementAddress> for IRInstructionKind {
+    fn from(ir_element_address: IRElementAddress) -> Self {
+        IRInstructionKind::ElementAddress(ir_element_address)
+    }
+}
+
+impl From<IRCalculate> for IRInstructionKind {
+    fn from(ir_calculate: IRCalculate) -> Self {
+        IRInstructionKind::Calculate(ir_calculate)
+    }
+}
+
+impl From<IRIncrease> for IRInstructionKind {
+    fn from(ir_increase: IRIncrease) -> Self {
+        IRInstructionKind::Increase(ir_increase)
+    }
+}
+
+impl From<IRDecrease> for IRInstructionKind {
+    fn from(ir_decrease: IRDecrease) -> Self {
+        IRInstructionKind::Decrease(ir_decrease)
+    }
+}
+
+impl From<IRNot> for IRInstructionKind {
+    fn from(ir_not: IRNot) -> Self {
+        IRInstructionKind::Not(ir_not)
+    }
+}
+
+impl From<IRNegate> for IRInstructionKind {
+    fn from(ir_negate: IRNegate) -> Self {
+        IRInstructionKind::Negate(ir_negate)
+    }
+}
+
+impl From<IRInvoke> for IRInstructionKind {
+    fn from(ir_invoke: IRInvoke) -> Self {
+        IRInstructionKind::Invoke(ir_invoke)
+    }
+}
+
+impl From<IRAsm> for IRInstructionKind {
+    fn from(ir_asm: IRAsm) -> Self {
+        IRInstructionKind::Asm(ir_asm)
+    }
+}
+
+struct IRInstructionKindCollector {
+    kind: RefCell<Option<IRInstructionKind>>,
+}
+
+impl IRVisitor for IRInstructionKindCollector {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_goto(&self, ir_goto: &IRGoto) {
+        self.kind
+            .replace(Some(IRInstructionKind::Goto(ir_goto.clone())));
+    }
+    fn visit_conditional_jump(&self, ir_conditional_jump: &IRConditionalJump) {
+        self.kind.replace(Some(IRInstructionKind::ConditionalJump(
+            ir_conditional_jump.clone(),
+        )));
+    }
+    fn visit_no_operate(&self, ir_no_operate: &IRNoOperate) {
+        self.kind
+            .replace(Some(IRInstructionKind::NoOperate(ir_no_operate.clone())));
+    }
+    fn visit_return(&self, ir_return: &IRReturn) {
+        self.kind
+            .replace(Some(IRInstructionKind::Return(ir_return.clone())));
+    }
+    fn visit_malloc(&self, ir_malloc: &IRMalloc) {
+        self.kind
+            .replace(Some(IRInstructionKind::Malloc(ir_malloc.clone())));
+    }
+    fn visit_free(&self, ir_free: &IRFree) {
+        self.kind
+            .replace(Some(IRInstructionKind::Free(ir_free.clone())));
+    }
+    fn visit_realloc(&self, ir_realloc: &IRRealloc) {
+        self.kind
+            .replace(Some(IRInstructionKind::Realloc(ir_realloc.clone())));
+    }
+    fn visit_set(&self, ir_set: &IRSet) {
+        self.kind
+            .replace(Some(IRInstructionKind::Set(ir_set.clone())));
+    }
+    fn visit_get(&self, ir_get: &IRGet) {
+        self.kind
+            .replace(Some(IRInstructionKind::Get(ir_get.clone())));
+    }
+    fn visit_set_virtual_register(&self, ir_set_virtual_register: &IRSetVirtualRegister) {
+        self.kind
+            .replace(Some(IRInstructionKind::SetVirtualRegister(
+                ir_set_virtual_register.clone(),
+            )));
+    }
+    fn visit_type_cast(&self, ir_type_cast: &IRTypeCast) {
+        self.kind
+            .replace(Some(IRInstructionKind::TypeCast(ir_type_cast.clone())));
+    }
+    fn visit_stack_allocate(&self, ir_stack_allocate: &IRStackAllocate) {
+        self.kind.replace(Some(IRInstructionKind::StackAllocate(
+            ir_stack_allocate.clone(),
+        )));
+    }
+    fn visit_element_address(&self, ir_element_address: &IRElementAddress) {
+        self.kind.replace(Some(IRInstructionKind::ElementAddress(
+            ir_element_address.clone(),
+        )));
+    }
+    fn visit_calculate(&self, ir_calculate: &IRCalculate) {
+        self.kind
+            .replace(Some(IRInstructionKind::Calculate(ir_calculate.clone())));
+    }
+    fn visit_increase(&self, ir_increase: &IRIncrease) {
+        self.kind
+            .replace(Some(IRInstructionKind::Increase(ir_increase.clone())));
+    }
+    fn visit_decrease(&self, ir_decrease: &IRDecrease) {
+        self.kind
+            .replace(Some(IRInstructionKind::Decrease(ir_decrease.clone())));
+    }
+    fn visit_not(&self, ir_not: &IRNot) {
+        self.kind
+            .replace(Some(IRInstructionKind::Not(ir_not.clone())));
+    }
+    fn visit_negate(&self, ir_negate: &IRNegate) {
+        self.kind
+            .replace(Some(IRInstructionKind::Negate(ir_negate.clone())));
+    }
+    fn visit_invoke(&self, ir_invoke: &IRInvoke) {
+        self.kind
+            .replace(Some(IRInstructionKind::Invoke(ir_invoke.clone())));
+    }
+    fn visit_asm(&self, ir_asm: &IRAsm) {
+        self.kind
+            .replace(Some(IRInstructionKind::Asm(ir_asm.clone())));
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct IRGoto {
@@ -71,20 +387,13 @@ impl Display for IRConditionalJump {
             (if self.is_atomic { "atomic_" } else { "" }).to_string()
                 + &format!(
                     "conditional_jump {} {}, {}, {}, #{}",
-                    self._type.to_string(),
-                    self.condition,
-                    self.operand1.to_string(),
-                    op2.to_string(),
-                    self.target
+                    self._type, self.condition, self.operand1, op2, self.target
                 )
         } else {
             (if self.is_atomic { "atomic_" } else { "" }).to_string()
                 + &format!(
                     "conditional_jump {} {}, {}, #{}",
-                    self._type.to_string(),
-                    self.condition,
-                    self.operand1.to_string(),
-                    self.target
+                    self._type, self.condition, self.operand1, self.target
                 )
         };
         write!(f, "{}", s)
@@ -97,7 +406,13 @@ impl IRNode for IRConditionalJump {
     }
 }
 
-impl IRInstruction for IRConditionalJump {}
+impl IRInstruction for IRConditionalJump {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        let mut operands = vec![&mut self.operand1];
+        operands.extend(self.operand2.as_mut());
+        operands
+    }
+}
 #[derive(Clone, Debug)]
 pub struct IRNoOperate {}
 
@@ -107,6 +422,12 @@ impl IRNoOperate {
     }
 }
 
+impl Default for IRNoOperate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Display for IRNoOperate {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "nop")
@@ -144,7 +465,11 @@ impl IRNode for IRReturn {
         visitor.visit_return(self);
     }
 }
-impl IRInstruction for IRReturn {}
+impl IRInstruction for IRReturn {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        self.operand.iter_mut().collect()
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct IRMalloc {
@@ -168,7 +493,11 @@ impl IRNode for IRMalloc {
         visitor.visit_malloc(self);
     }
 }
-impl IRInstruction for IRMalloc {}
+impl IRInstruction for IRMalloc {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![&mut self.size]
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct IRFree {
@@ -190,7 +519,11 @@ impl IRNode for IRFree {
         visitor.visit_free(self);
     }
 }
-impl IRInstruction for IRFree {}
+impl IRInstruction for IRFree {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![&mut self.ptr]
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct IRRealloc {
@@ -219,7 +552,11 @@ impl IRNode for IRRealloc {
         visitor.visit_realloc(self);
     }
 }
-impl IRInstruction for IRRealloc {}
+impl IRInstruction for IRRealloc {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![&mut self.ptr, &mut self.size]
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct IRSet {
@@ -252,7 +589,11 @@ impl IRNode for IRSet {
     }
 }
 
-impl IRInstruction for IRSet {}
+impl IRInstruction for IRSet {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![&mut self.address, &mut self.value]
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct IRGet {
@@ -283,7 +624,11 @@ impl IRNode for IRGet {
         visitor.visit_get(self);
     }
 }
-impl IRInstruction for IRGet {}
+impl IRInstruction for IRGet {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![&mut self.address]
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct IRSetVirtualRegister {
@@ -307,9 +652,13 @@ impl IRNode for IRSetVirtualRegister {
     }
 }
 
-impl IRInstruction for IRSetVirtualRegister {}
+impl IRInstruction for IRSetVirtualRegister {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![&mut self.source]
+    }
+}
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum IRTypeCastKind {
     ZeroExtend,
     SignExtend,
@@ -372,7 +721,11 @@ impl IRNode for IRTypeCast {
         visitor.visit_type_cast(self);
     }
 }
-impl IRInstruction for IRTypeCast {}
+impl IRInstruction for IRTypeCast {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![&mut self.source]
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct IRStackAllocate {
@@ -394,8 +747,169 @@ impl IRNode for IRStackAllocate {
         visitor.visit_stack_allocate(self);
     }
 }
-impl IRInstruction for IRStackAllocate {}
-#[derive(Clone, Copy, Debug)]
+impl IRInstruction for IRStackAllocate {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![&mut self.size]
+    }
+}
+/// One step of an `element_address` path.
+#[derive(Clone, Debug)]
+pub enum IRElementIndex {
+    /// A field of a structure, by name.
+    Field(String),
+    /// An element of an array, by an `i64` operand.
+    Index(Box<dyn IROperand>),
+}
+impl Display for IRElementIndex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            IRElementIndex::Field(name) => write!(f, "{}", name),
+            IRElementIndex::Index(operand) => write!(f, "{}", operand),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IRElementPathError {
+    /// Only structures and arrays have elements.
+    NotAggregate(String),
+    UnknownStructure(String),
+    UnknownField {
+        structure: String,
+        field: String,
+    },
+    /// A structure indexed by an operand, or an array by a field name.
+    MismatchedIndex {
+        _type: String,
+        index: String,
+    },
+}
+impl Display for IRElementPathError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            IRElementPathError::NotAggregate(_type) => {
+                write!(f, "type {} has no elements", _type)
+            }
+            IRElementPathError::UnknownStructure(name) => {
+                write!(f, "structure '{}' is not defined", name)
+            }
+            IRElementPathError::UnknownField { structure, field } => {
+                write!(f, "structure '{}' has no field '{}'", structure, field)
+            }
+            IRElementPathError::MismatchedIndex { _type, index } => {
+                write!(f, "type {} cannot be indexed by {}", _type, index)
+            }
+        }
+    }
+}
+impl std::error::Error for IRElementPathError {}
+
+/// The address of a field or element inside the structure or array `_type` that `base` points
+/// to, reached by following `path`; the target is a pointer to the element's type.
+#[derive(Clone, Debug)]
+pub struct IRElementAddress {
+    pub _type: Box<dyn IRType>,
+    pub base: Box<dyn IROperand>,
+    pub path: Vec<IRElementIndex>,
+    pub target: Box<IRVirtualRegister>,
+}
+impl IRElementAddress {
+    pub fn new(
+        _type: Box<dyn IRType>,
+        base: Box<dyn IROperand>,
+        path: Vec<IRElementIndex>,
+        target: Box<IRVirtualRegister>,
+    ) -> Self {
+        Self {
+            _type,
+            base,
+            path,
+            target,
+        }
+    }
+
+    /// The type of the element at the end of the path.
+    pub fn element_type(
+        &self,
+        ir_module: &IRModule,
+    ) -> Result<Box<dyn IRType>, IRElementPathError> {
+        let kind = IRTypeKind::of(self._type.as_ref());
+        if !kind.is_structure() && !kind.is_array() {
+            return Err(IRElementPathError::NotAggregate(self._type.to_string()));
+        }
+        let mut _type = self._type.clone();
+        for index in self.path.iter() {
+            _type = element_step(ir_module, _type.as_ref(), index)?;
+        }
+        Ok(_type)
+    }
+}
+impl Display for IRElementAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} = element_address {}, {}, [{}]",
+            self.target,
+            self._type,
+            self.base,
+            self.path
+                .iter()
+                .map(|index| index.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+impl IRNode for IRElementAddress {
+    fn accept(&self, visitor: &dyn IRVisitor) {
+        visitor.visit_element_address(self);
+    }
+}
+impl IRInstruction for IRElementAddress {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        let mut operands = vec![&mut self.base];
+        for index in self.path.iter_mut() {
+            if let IRElementIndex::Index(operand) = index {
+                operands.push(operand);
+            }
+        }
+        operands
+    }
+}
+
+/// The type of the field or element that `index` selects inside a value of `_type`.
+pub(crate) fn element_step(
+    ir_module: &IRModule,
+    _type: &dyn IRType,
+    index: &IRElementIndex,
+) -> Result<Box<dyn IRType>, IRElementPathError> {
+    match (IRTypeKind::of(_type), index) {
+        (IRTypeKind::Structure(structure), IRElementIndex::Field(field)) => {
+            let ir_structure = structure
+                .structure(ir_module)
+                .ok_or_else(|| IRElementPathError::UnknownStructure(structure.name.clone()))?;
+            ir_structure
+                .fields
+                .iter()
+                .find(|ir_field| ir_field.name == *field)
+                .map(|ir_field| ir_field._type.clone())
+                .ok_or_else(|| IRElementPathError::UnknownField {
+                    structure: structure.name,
+                    field: field.clone(),
+                })
+        }
+        (IRTypeKind::Array(array), IRElementIndex::Index(_)) => Ok(array.element),
+        (IRTypeKind::Structure(_) | IRTypeKind::Array(_), index) => {
+            Err(IRElementPathError::MismatchedIndex {
+                _type: _type.to_string(),
+                index: index.to_string(),
+            })
+        }
+        _ => Err(IRElementPathError::NotAggregate(_type.to_string())),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum IRCalculateOperator {
     ADD,
     SUB,
@@ -475,7 +989,11 @@ impl IRNode for IRCalculate {
         visitor.visit_calculate(self);
     }
 }
-impl IRInstruction for IRCalculate {}
+impl IRInstruction for IRCalculate {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![&mut self.operand1, &mut self.operand2]
+    }
+}
 #[derive(Clone, Debug)]
 pub struct IRIncrease {
     pub _type: Box<dyn IRType>,
@@ -509,7 +1027,11 @@ impl IRNode for IRIncrease {
         visitor.visit_increase(self);
     }
 }
-impl IRInstruction for IRIncrease {}
+impl IRInstruction for IRIncrease {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![&mut self.operand]
+    }
+}
 #[derive(Clone, Debug)]
 pub struct IRDecrease {
     pub _type: Box<dyn IRType>,
@@ -543,7 +1065,11 @@ impl IRNode for IRDecrease {
         visitor.visit_decrease(self);
     }
 }
-impl IRInstruction for IRDecrease {}
+impl IRInstruction for IRDecrease {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![&mut self.operand]
+    }
+}
 #[derive(Clone, Debug)]
 pub struct IRNot {
     pub is_atomic: bool,
@@ -583,7 +1109,11 @@ impl IRNode for IRNot {
         visitor.visit_not(self);
     }
 }
-impl IRInstruction for IRNot {}
+impl IRInstruction for IRNot {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![&mut self.operand]
+    }
+}
 #[derive(Clone, Debug)]
 pub struct IRNegate {
     pub is_atomic: bool,
@@ -623,7 +1153,11 @@ impl IRNode for IRNegate {
         visitor.visit_negate(self);
     }
 }
-impl IRInstruction for IRNegate {}
+impl IRInstruction for IRNegate {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![&mut self.operand]
+    }
+}
 #[derive(Clone, Debug)]
 pub struct IRInvoke {
     pub return_type: Box<dyn IRType>,
@@ -652,24 +1186,20 @@ impl IRInvoke {
 }
 impl Display for IRInvoke {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let s = if self.argument_types.len() == self.arguments.len() {
-            self.argument_types
-                .iter()
-                .zip(self.arguments.iter())
-                .map(|(t, a)| format!(", [{}, {}]", t, a))
-                .collect::<String>()
-        } else {
-            panic!("argument types and arguments length mismatch")
-        };
         if let Some(target) = &self.target {
-            write!(
-                f,
-                "{} = invoke {} {}{}",
-                target, self.return_type, self.address, s
-            )
-        } else {
-            write!(f, "invoke {} {}{}", self.return_type, self.address, s)
+            write!(f, "{} = ", target)?;
         }
+        write!(f, "invoke {} {}", self.return_type, self.address)?;
+        // A length mismatch is reported by the verifier, so print what is there rather than fail.
+        for index in 0..self.argument_types.len().max(self.arguments.len()) {
+            match (self.argument_types.get(index), self.arguments.get(index)) {
+                (Some(t), Some(a)) => write!(f, ", [{}, {}]", t, a)?,
+                (Some(t), None) => write!(f, ", [{}, <error: missing argument>]", t)?,
+                (None, Some(a)) => write!(f, ", [<error: missing type>, {}]", a)?,
+                (None, None) => unreachable!(),
+            }
+        }
+        Ok(())
     }
 }
 
@@ -678,7 +1208,13 @@ impl IRNode for IRInvoke {
         visitor.visit_invoke(self);
     }
 }
-impl IRInstruction for IRInvoke {}
+impl IRInstruction for IRInvoke {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        let mut operands = vec![&mut self.address];
+        operands.extend(self.arguments.iter_mut());
+        operands
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct IRAsm {
@@ -719,4 +1255,110 @@ impl IRNode for IRAsm {
         visitor.visit_asm(self);
     }
 }
-impl IRInstruction for IRAsm {}
+impl IRInstruction for IRAsm {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        self.resources.iter_mut().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parser::parse_module;
+
+    /// One instruction of every kind, in the order of `IRInstructionKind`.
+    const EVERY_KIND: &str = "\
+constant $0 = i64 8
+function void f() {
+entry:
+    goto next
+next:
+    conditional_jump i64 l, %a, %b, #next
+    nop
+    return
+    %p = malloc $0
+    free %p
+    %q = realloc %p, $0
+    set i64, %p, %a
+    %v = get i64, %p
+    %w = %v
+    %x = sext i32 %v to i64
+    %s = stack_alloc $0
+    %e = element_address %Node, %p, [value]
+    %sum = add i64 %a, %b
+    %i = increase i64 %p
+    %d = decrease i64 %p
+    %n = not i64 %a
+    %m = negate i64 %a
+    invoke void `function_address([f], [])
+    asm \"nop\"
+}
+";
+
+    fn every_kind() -> Vec<Box<dyn IRInstruction>> {
+        let ir_module = parse_module(EVERY_KIND).unwrap();
+        let ir_function = ir_module.functions.into_values().next().unwrap();
+        ir_function
+            .control_flow_graph
+            .basic_blocks
+            .into_values()
+            .flat_map(|ir_basic_block| ir_basic_block.instructions)
+            .collect()
+    }
+
+    fn variant(kind: &IRInstructionKind) -> String {
+        let debug = format!("{:?}", kind);
+        debug[..debug.find('(').unwrap()].to_string()
+    }
+
+    #[test]
+    fn every_instruction_has_its_own_kind() {
+        let kinds = every_kind()
+            .iter()
+            .map(|ir_instruction| variant(&ir_instruction.kind()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                "Goto",
+                "ConditionalJump",
+                "NoOperate",
+                "Return",
+                "Malloc",
+                "Free",
+                "Realloc",
+                "Set",
+                "Get",
+                "SetVirtualRegister",
+                "TypeCast",
+                "StackAllocate",
+                "ElementAddress",
+                "Calculate",
+                "Increase",
+                "Decrease",
+                "Not",
+                "Negate",
+                "Invoke",
+                "Asm",
+            ]
+        );
+    }
+
+    #[test]
+    fn kinds_turn_back_into_the_same_instructions() {
+        for ir_instruction in every_kind() {
+            let kind = ir_instruction.kind();
+            let name = variant(&kind);
+            let round_tripped: Box<dyn IRInstruction> = kind.into();
+            assert_eq!(round_tripped.to_string(), ir_instruction.to_string());
+            assert_eq!(variant(&round_tripped.kind()), name);
+        }
+    }
+
+    #[test]
+    fn instructions_convert_into_their_kind() {
+        let kind = IRInstructionKind::from(IRGoto::new("loop".to_string()));
+        assert!(matches!(&kind, IRInstructionKind::Goto(ir_goto) if ir_goto.target == "loop"));
+        assert!(kind.into_instruction().is::<IRGoto>());
+    }
+}
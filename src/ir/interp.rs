@@ -0,0 +1,1815 @@
+//! A reference interpreter for `IRModule`, used as a golden model for front-end output.
+//!
+//! Values are untyped bit patterns; every instruction interprets its operands through the type
+//! it declares. Memory is simulated: each global, field, stack allocation and heap block is a
+//! separate allocation, and any access outside a live allocation is reported as an error. Live
+//! allocations are limited to 1 GiB in total: past that `malloc` and `realloc` return null and
+//! other allocations fail.
+//!
+//! Atomic instructions are executed sequentially with read-modify-write semantics: the first
+//! operand of an `atomic_` calculation, `not`, `negate` or conditional jump is the address of the
+//! value it operates on, and the new value is written back before it is assigned to the target.
+//! `increase`/`decrease` without a target step the value stored at their operand in place; with
+//! a target they step the operand value itself.
+//!
+//! The phis of a block are evaluated together on entry, so that phis reading each other see the
+//! values from the predecessor; a phi with no label for the predecessor is an error.
+//!
+//! The address macros `field_address([name], [])`, `global_data_address([name], [])` and
+//! `function_address([name], [])` are understood; functions that are not part of the module can
+//! be provided by the host through `IRInterpreter::define_native`.
+//!
+//! `element_address` uses the offsets of `DataLayout::default()`, the layout of the native
+//! backends.
+
+use crate::ir::base::{IRCondition, IRControlFlowGraph, IRFunction, IRGlobalData, IRNode};
+use crate::ir::instruction::{
+    IRAsm, IRCalculate, IRCalculateOperator, IRConditionalJump, IRDecrease, IRElementAddress,
+    IRFree, IRGet, IRGoto, IRIncrease, IRInstruction, IRInvoke, IRMalloc, IRNegate, IRNoOperate,
+    IRNot, IRRealloc, IRReturn, IRSet, IRSetVirtualRegister, IRStackAllocate, IRTypeCast,
+    IRTypeCastKind,
+};
+use crate::ir::layout::DataLayout;
+use crate::ir::operand::{
+    IRConstant, IRInterfaceTable, IRMacro, IROperand, IRPhi, IRVirtualRegister, IRVirtualTable,
+};
+use crate::ir::type_check::find_aggregate_value;
+use crate::ir::types::{IRType, IRTypeKind};
+use crate::ir::verify::IRLocation;
+use crate::ir::{IRConstantData, IRModule, IRVisitor};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+const FUNCTION_BASE: u64 = 0x1000;
+const HEAP_BASE: u64 = 0x10_0000;
+const ALLOCATION_ALIGNMENT: u64 = 16;
+/// The most bytes a program may have allocated at a time, so that it cannot exhaust the host.
+const MEMORY_LIMIT: u64 = 1 << 30;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IRValue {
+    Integer(u64),
+    Float(f32),
+    Double(f64),
+}
+
+impl IRValue {
+    /// The raw bits of the value, as stored in memory.
+    pub fn bits(self) -> u64 {
+        match self {
+            IRValue::Integer(value) => value,
+            IRValue::Float(value) => value.to_bits() as u64,
+            IRValue::Double(value) => value.to_bits(),
+        }
+    }
+}
+
+impl fmt::Display for IRValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IRValue::Integer(value) => write!(f, "{}", value),
+            IRValue::Float(value) => write!(f, "{}", value),
+            IRValue::Double(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IRInterpErrorKind {
+    MissingEntryPoint,
+    UnknownFunction(String),
+    UnknownBasicBlock(String),
+    UnknownGlobalData(String),
+    UnknownField(String),
+    UndefinedRegister(String),
+    InvalidConstant(u32),
+    InvalidFunctionAddress(u64),
+    InvalidAccess {
+        address: u64,
+        size: u64,
+    },
+    InvalidFree(u64),
+    DivisionByZero,
+    ArgumentCountMismatch {
+        expected: usize,
+        found: usize,
+    },
+    MissingPhiLabel {
+        phi: String,
+        predecessor: Option<String>,
+    },
+    FellThroughEnd,
+    StepLimitExceeded,
+    OutOfMemory(u64),
+    Unsupported(String),
+}
+
+impl fmt::Display for IRInterpErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IRInterpErrorKind::MissingEntryPoint => write!(f, "module has no entry point"),
+            IRInterpErrorKind::UnknownFunction(name) => write!(f, "unknown function '{}'", name),
+            IRInterpErrorKind::UnknownBasicBlock(name) => {
+                write!(f, "unknown basic block '{}'", name)
+            }
+            IRInterpErrorKind::UnknownGlobalData(name) => {
+                write!(f, "unknown global data '{}'", name)
+            }
+            IRInterpErrorKind::UnknownField(name) => write!(f, "unknown field '{}'", name),
+            IRInterpErrorKind::UndefinedRegister(name) => {
+                write!(f, "register %{} is read before it is defined", name)
+            }
+            IRInterpErrorKind::InvalidConstant(index) => {
+                write!(f, "constant ${} has no usable value", index)
+            }
+            IRInterpErrorKind::InvalidFunctionAddress(address) => {
+                write!(f, "{:#x} is not a function address", address)
+            }
+            IRInterpErrorKind::InvalidAccess { address, size } => write!(
+                f,
+                "access of {} bytes at {:#x} is outside any live allocation",
+                size, address
+            ),
+            IRInterpErrorKind::InvalidFree(address) => {
+                write!(
+                    f,
+                    "{:#x} is not the start of a live heap allocation",
+                    address
+                )
+            }
+            IRInterpErrorKind::DivisionByZero => write!(f, "division by zero"),
+            IRInterpErrorKind::ArgumentCountMismatch { expected, found } => {
+                write!(f, "function expects {} arguments, got {}", expected, found)
+            }
+            IRInterpErrorKind::MissingPhiLabel { phi, predecessor } => match predecessor {
+                Some(predecessor) => {
+                    write!(f, "{} has no value for predecessor '{}'", phi, predecessor)
+                }
+                None => write!(f, "{} evaluated without a predecessor block", phi),
+            },
+            IRInterpErrorKind::FellThroughEnd => {
+                write!(f, "control fell through the last basic block")
+            }
+            IRInterpErrorKind::StepLimitExceeded => write!(f, "step limit exceeded"),
+            IRInterpErrorKind::OutOfMemory(size) => write!(f, "cannot allocate {} bytes", size),
+            IRInterpErrorKind::Unsupported(what) => write!(f, "unsupported: {}", what),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IRInterpError {
+    pub location: IRLocation,
+    pub kind: IRInterpErrorKind,
+}
+
+impl fmt::Display for IRInterpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.kind)
+    }
+}
+
+impl std::error::Error for IRInterpError {}
+
+type IRInterpResult<T> = Result<T, IRInterpErrorKind>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IRAllocationKind {
+    Global,
+    Stack,
+    Heap,
+}
+
+#[derive(Debug)]
+struct IRAllocation {
+    kind: IRAllocationKind,
+    bytes: Vec<u8>,
+}
+
+/// The simulated address space of the interpreter.
+#[derive(Debug)]
+pub struct IRMemory {
+    allocations: BTreeMap<u64, IRAllocation>,
+    next_address: u64,
+    allocated: u64,
+}
+
+impl IRMemory {
+    fn new() -> Self {
+        Self {
+            allocations: BTreeMap::new(),
+            next_address: HEAP_BASE,
+            allocated: 0,
+        }
+    }
+
+    fn allocate(&mut self, kind: IRAllocationKind, size: u64) -> IRInterpResult<u64> {
+        if size > MEMORY_LIMIT - self.allocated {
+            return Err(IRInterpErrorKind::OutOfMemory(size));
+        }
+        let address = self.next_address;
+        // Leave a gap after every allocation so that overruns never land in a neighbour.
+        self.next_address = address
+            .checked_add(size.max(1) + ALLOCATION_ALIGNMENT)
+            .and_then(|end| end.checked_next_multiple_of(ALLOCATION_ALIGNMENT))
+            .ok_or(IRInterpErrorKind::OutOfMemory(size))?;
+        self.allocated += size;
+        self.allocations.insert(
+            address,
+            IRAllocation {
+                kind,
+                bytes: vec![0; size as usize],
+            },
+        );
+        Ok(address)
+    }
+
+    fn release(&mut self, kind: IRAllocationKind, address: u64) -> IRInterpResult<Vec<u8>> {
+        match self.allocations.get(&address) {
+            Some(allocation) if allocation.kind == kind => {
+                let bytes = self.allocations.remove(&address).unwrap().bytes;
+                self.allocated -= bytes.len() as u64;
+                Ok(bytes)
+            }
+            _ => Err(IRInterpErrorKind::InvalidFree(address)),
+        }
+    }
+
+    /// Allocates `size` zeroed bytes on the heap, as `malloc` does, returning null when the
+    /// memory limit would be exceeded.
+    pub fn malloc(&mut self, size: u64) -> u64 {
+        self.allocate(IRAllocationKind::Heap, size).unwrap_or(0)
+    }
+
+    pub fn free(&mut self, address: u64) -> Result<(), IRInterpErrorKind> {
+        if address == 0 {
+            return Ok(());
+        }
+        self.release(IRAllocationKind::Heap, address).map(|_| ())
+    }
+
+    pub fn realloc(&mut self, address: u64, size: u64) -> Result<u64, IRInterpErrorKind> {
+        let bytes = if address == 0 {
+            vec![]
+        } else {
+            self.release(IRAllocationKind::Heap, address)?
+        };
+        let new_address = self.malloc(size);
+        if new_address == 0 {
+            // Like `realloc`, leave the original block in place when the new one cannot be had.
+            if address != 0 {
+                self.allocated += bytes.len() as u64;
+                let kind = IRAllocationKind::Heap;
+                self.allocations
+                    .insert(address, IRAllocation { kind, bytes });
+            }
+            return Ok(0);
+        }
+        let length = bytes.len().min(size as usize);
+        self.write(new_address, &bytes[..length])?;
+        Ok(new_address)
+    }
+
+    fn locate(&self, address: u64, size: u64) -> IRInterpResult<(u64, usize)> {
+        let invalid = IRInterpErrorKind::InvalidAccess { address, size };
+        let (base, allocation) = self
+            .allocations
+            .range(..=address)
+            .next_back()
+            .ok_or(invalid.clone())?;
+        let offset = address - base;
+        if offset
+            .checked_add(size)
+            .is_none_or(|end| end > allocation.bytes.len() as u64)
+        {
+            return Err(invalid);
+        }
+        Ok((*base, offset as usize))
+    }
+
+    pub fn read(&self, address: u64, size: u64) -> Result<&[u8], IRInterpErrorKind> {
+        let (base, offset) = self.locate(address, size)?;
+        Ok(&self.allocations[&base].bytes[offset..offset + size as usize])
+    }
+
+    pub fn write(&mut self, address: u64, bytes: &[u8]) -> Result<(), IRInterpErrorKind> {
+        let (base, offset) = self.locate(address, bytes.len() as u64)?;
+        let allocation = self.allocations.get_mut(&base).unwrap();
+        allocation.bytes[offset..offset + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Reads a NUL-terminated string, for natives that take C strings.
+    pub fn read_c_string(&self, address: u64) -> Result<Vec<u8>, IRInterpErrorKind> {
+        let (base, offset) = self.locate(address, 1)?;
+        let bytes = &self.allocations[&base].bytes[offset..];
+        match bytes.iter().position(|&byte| byte == 0) {
+            Some(length) => Ok(bytes[..length].to_vec()),
+            None => Err(IRInterpErrorKind::InvalidAccess {
+                address,
+                size: bytes.len() as u64 + 1,
+            }),
+        }
+    }
+
+    /// Number of heap allocations that have not been freed.
+    pub fn live_heap_allocations(&self) -> usize {
+        self.allocations
+            .values()
+            .filter(|allocation| allocation.kind == IRAllocationKind::Heap)
+            .count()
+    }
+}
+
+/// The runtime view of a type: how many bytes it occupies and how its bits are interpreted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IRScalar {
+    Integer { bits: u32, unsigned: bool },
+    Float,
+    Double,
+    Void,
+}
+
+impl IRScalar {
+    fn of(_type: &dyn IRType) -> Self {
+        match IRTypeKind::of(_type) {
+            IRTypeKind::Integer(integer) => IRScalar::Integer {
+                bits: integer.size as u32,
+                unsigned: integer.unsigned,
+            },
+            IRTypeKind::Float(_) => IRScalar::Float,
+            IRTypeKind::Double(_) => IRScalar::Double,
+            IRTypeKind::Pointer(_) => IRScalar::Integer {
+                bits: 64,
+                unsigned: true,
+            },
+            IRTypeKind::Void(_) => IRScalar::Void,
+            // Rejected up front by `initialize`.
+            IRTypeKind::Structure(_) | IRTypeKind::Array(_) | IRTypeKind::Function(_) => {
+                IRScalar::Void
+            }
+        }
+    }
+
+    fn size(self) -> u64 {
+        match self {
+            IRScalar::Integer { bits, .. } => (bits as u64).div_ceil(8),
+            IRScalar::Float => 4,
+            IRScalar::Double => 8,
+            IRScalar::Void => 0,
+        }
+    }
+
+    fn mask(bits: u32, value: u64) -> u64 {
+        if bits >= 64 {
+            value
+        } else {
+            value & ((1u64 << bits) - 1)
+        }
+    }
+
+    fn sign_extend(bits: u32, value: u64) -> i64 {
+        if bits >= 64 {
+            value as i64
+        } else {
+            let shift = 64 - bits;
+            ((value << shift) as i64) >> shift
+        }
+    }
+
+    /// Reinterprets a value as this type, truncating integers to their width.
+    fn normalize(self, value: IRValue) -> IRValue {
+        match (self, value) {
+            (IRScalar::Integer { bits, .. }, value) => {
+                IRValue::Integer(Self::mask(bits, value.bits()))
+            }
+            (IRScalar::Float, IRValue::Float(_)) | (IRScalar::Double, IRValue::Double(_)) => value,
+            (IRScalar::Float, value) => IRValue::Float(f32::from_bits(value.bits() as u32)),
+            (IRScalar::Double, value) => IRValue::Double(f64::from_bits(value.bits())),
+            (IRScalar::Void, value) => value,
+        }
+    }
+
+    fn load(self, bytes: &[u8]) -> IRValue {
+        let mut buffer = [0u8; 8];
+        buffer[..bytes.len()].copy_from_slice(bytes);
+        self.normalize(IRValue::Integer(u64::from_le_bytes(buffer)))
+    }
+
+    fn store(self, value: IRValue) -> Vec<u8> {
+        let bits = self.normalize(value).bits();
+        bits.to_le_bytes()[..self.size() as usize].to_vec()
+    }
+}
+
+fn calculate(
+    scalar: IRScalar,
+    operator: IRCalculateOperator,
+    a: IRValue,
+    b: IRValue,
+) -> IRInterpResult<IRValue> {
+    use IRCalculateOperator::*;
+    match (scalar, a, b) {
+        (IRScalar::Integer { bits, unsigned }, a, b) => {
+            let (a, b) = (a.bits(), b.bits());
+            let (signed_a, signed_b) = (
+                IRScalar::sign_extend(bits, a),
+                IRScalar::sign_extend(bits, b),
+            );
+            let (unsigned_a, unsigned_b) = (IRScalar::mask(bits, a), IRScalar::mask(bits, b));
+            let shift = (unsigned_b % bits as u64) as u32;
+            let value = match operator {
+                ADD => a.wrapping_add(b),
+                SUB => a.wrapping_sub(b),
+                MUL => a.wrapping_mul(b),
+                DIV | MOD if unsigned_b == 0 => return Err(IRInterpErrorKind::DivisionByZero),
+                DIV if unsigned => unsigned_a / unsigned_b,
+                DIV => signed_a.wrapping_div(signed_b) as u64,
+                MOD if unsigned => unsigned_a % unsigned_b,
+                MOD => signed_a.wrapping_rem(signed_b) as u64,
+                AND => a & b,
+                OR => a | b,
+                XOR => a ^ b,
+                SHL => unsigned_a << shift,
+                SHR if !unsigned => (signed_a >> shift) as u64,
+                SHR | USHR => unsigned_a >> shift,
+            };
+            Ok(IRValue::Integer(IRScalar::mask(bits, value)))
+        }
+        (IRScalar::Float, IRValue::Float(a), IRValue::Float(b)) => match operator {
+            ADD => Ok(IRValue::Float(a + b)),
+            SUB => Ok(IRValue::Float(a - b)),
+            MUL => Ok(IRValue::Float(a * b)),
+            DIV => Ok(IRValue::Float(a / b)),
+            MOD => Ok(IRValue::Float(a % b)),
+            _ => Err(IRInterpErrorKind::Unsupported(format!(
+                "{} on float",
+                operator
+            ))),
+        },
+        (IRScalar::Double, IRValue::Double(a), IRValue::Double(b)) => match operator {
+            ADD => Ok(IRValue::Double(a + b)),
+            SUB => Ok(IRValue::Double(a - b)),
+            MUL => Ok(IRValue::Double(a * b)),
+            DIV => Ok(IRValue::Double(a / b)),
+            MOD => Ok(IRValue::Double(a % b)),
+            _ => Err(IRInterpErrorKind::Unsupported(format!(
+                "{} on double",
+                operator
+            ))),
+        },
+        (IRScalar::Float | IRScalar::Double, a, b) => {
+            calculate(scalar, operator, scalar.normalize(a), scalar.normalize(b))
+        }
+        (IRScalar::Void, _, _) => Err(IRInterpErrorKind::Unsupported(format!(
+            "{} on void",
+            operator
+        ))),
+    }
+}
+
+fn compare(scalar: IRScalar, condition: IRCondition, a: IRValue, b: Option<IRValue>) -> bool {
+    use std::cmp::Ordering;
+    let truthy = match scalar.normalize(a) {
+        IRValue::Integer(value) => value != 0,
+        IRValue::Float(value) => value != 0.0,
+        IRValue::Double(value) => value != 0.0,
+    };
+    let ordering = b.and_then(
+        |b| match (scalar, scalar.normalize(a), scalar.normalize(b)) {
+            (
+                IRScalar::Integer {
+                    bits,
+                    unsigned: false,
+                },
+                a,
+                b,
+            ) => Some(
+                IRScalar::sign_extend(bits, a.bits()).cmp(&IRScalar::sign_extend(bits, b.bits())),
+            ),
+            (_, IRValue::Float(a), IRValue::Float(b)) => a.partial_cmp(&b),
+            (_, IRValue::Double(a), IRValue::Double(b)) => a.partial_cmp(&b),
+            (_, a, b) => Some(a.bits().cmp(&b.bits())),
+        },
+    );
+    match condition {
+        IRCondition::IfTrue => truthy,
+        IRCondition::IfFalse => !truthy,
+        IRCondition::Equal => ordering == Some(Ordering::Equal),
+        IRCondition::NotEqual => ordering != Some(Ordering::Equal),
+        IRCondition::Less => ordering == Some(Ordering::Less),
+        IRCondition::LessEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        IRCondition::Greater => ordering == Some(Ordering::Greater),
+        IRCondition::GreaterEqual => {
+            matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+        }
+    }
+}
+
+fn cast(kind: IRTypeCastKind, from: IRScalar, to: IRScalar, value: IRValue) -> IRValue {
+    let value = from.normalize(value);
+    let signed = |value: IRValue| match from {
+        IRScalar::Integer { bits, .. } => IRScalar::sign_extend(bits, value.bits()),
+        _ => value.bits() as i64,
+    };
+    let unsigned_source = matches!(from, IRScalar::Integer { unsigned: true, .. });
+    let unsigned_target = matches!(to, IRScalar::Integer { unsigned: true, .. });
+    let floating = |value: IRValue| match value {
+        IRValue::Float(value) => value as f64,
+        IRValue::Double(value) => value,
+        IRValue::Integer(value) => value as f64,
+    };
+    match kind {
+        IRTypeCastKind::ZeroExtend | IRTypeCastKind::Truncate => to.normalize(value),
+        IRTypeCastKind::SignExtend => to.normalize(IRValue::Integer(signed(value) as u64)),
+        IRTypeCastKind::IntToFloat => {
+            let value = if unsigned_source {
+                value.bits() as f64
+            } else {
+                signed(value) as f64
+            };
+            match to {
+                IRScalar::Float => IRValue::Float(value as f32),
+                _ => IRValue::Double(value),
+            }
+        }
+        IRTypeCastKind::FloatToInt => {
+            let value = floating(value);
+            let bits = if unsigned_target {
+                value as u64
+            } else {
+                value as i64 as u64
+            };
+            to.normalize(IRValue::Integer(bits))
+        }
+        IRTypeCastKind::FloatExtend | IRTypeCastKind::FloatTruncate => match to {
+            IRScalar::Float => IRValue::Float(floating(value) as f32),
+            _ => IRValue::Double(floating(value)),
+        },
+    }
+}
+
+/// A host function callable from IR through `invoke`.
+pub type IRNativeFunction =
+    Box<dyn Fn(&mut IRMemory, &[IRValue]) -> Result<Option<IRValue>, IRInterpErrorKind>>;
+
+struct IRFrame<'a> {
+    function: Option<&'a IRFunction>,
+    control_flow_graph: &'a IRControlFlowGraph,
+    basic_block: usize,
+    instruction: usize,
+    previous_basic_block: Option<&'a str>,
+    /// Values of the phis of the current block, evaluated together on entry so that phis
+    /// reading each other see the values from the predecessor.
+    phi_values: HashMap<String, IRValue>,
+    registers: HashMap<String, IRValue>,
+    fields: HashMap<String, u64>,
+    stack_allocations: Vec<u64>,
+    return_target: Option<String>,
+}
+
+enum IRControl {
+    Next,
+    Jump(String),
+    Call {
+        address: u64,
+        arguments: Vec<IRValue>,
+        target: Option<String>,
+    },
+    Return(Option<IRValue>),
+}
+
+struct IRMachine<'a> {
+    memory: IRMemory,
+    frames: Vec<IRFrame<'a>>,
+    globals: HashMap<String, u64>,
+    steps: u64,
+}
+
+pub struct IRInterpreter<'a> {
+    ir_module: &'a IRModule,
+    machine: RefCell<IRMachine<'a>>,
+    constants: Vec<Option<IRValue>>,
+    natives: Vec<(String, IRNativeFunction)>,
+    step_limit: Option<u64>,
+    initialized: bool,
+}
+
+impl<'a> IRInterpreter<'a> {
+    pub fn new(ir_module: &'a IRModule) -> Self {
+        let mut memory = IRMemory::new();
+        let constants = ir_module
+            .constant_pool
+            .entries
+            .iter()
+            .map(|entry| match IRConstantData::of(entry)? {
+                IRConstantData::String(mut bytes) => {
+                    bytes.push(0);
+                    let address = memory
+                        .allocate(IRAllocationKind::Global, bytes.len() as u64)
+                        .ok()?;
+                    memory.write(address, &bytes).unwrap();
+                    Some(IRValue::Integer(address))
+                }
+                data => Some(
+                    IRScalar::of(entry._type.as_ref()).normalize(IRValue::Integer(data.bits()?)),
+                ),
+            })
+            .collect();
+        Self {
+            ir_module,
+            machine: RefCell::new(IRMachine {
+                memory,
+                frames: vec![],
+                globals: HashMap::new(),
+                steps: 0,
+            }),
+            constants,
+            natives: vec![],
+            step_limit: None,
+            initialized: false,
+        }
+    }
+
+    /// Aborts execution after `limit` instructions, to catch runaway loops.
+    pub fn with_step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    /// Makes `name` callable through `function_address([name], [])` when the module does not
+    /// define a function of that name.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        function: impl Fn(&mut IRMemory, &[IRValue]) -> Result<Option<IRValue>, IRInterpErrorKind>
+        + 'static,
+    ) {
+        self.natives.push((name.to_string(), Box::new(function)));
+    }
+
+    pub fn memory(&self) -> std::cell::Ref<'_, IRMemory> {
+        std::cell::Ref::map(self.machine.borrow(), |machine| &machine.memory)
+    }
+
+    pub fn global_data_address(&self, name: &str) -> Option<u64> {
+        self.machine.borrow().globals.get(name).copied()
+    }
+
+    pub fn function_address(&self, name: &str) -> Option<u64> {
+        self.ir_module
+            .functions
+            .get_index_of(name)
+            .or_else(|| {
+                self.natives
+                    .iter()
+                    .position(|(native, _)| native == name)
+                    .map(|index| self.ir_module.functions.len() + index)
+            })
+            .map(|index| FUNCTION_BASE + index as u64 * ALLOCATION_ALIGNMENT)
+    }
+
+    /// Runs the global init section followed by the entry point.
+    pub fn run(&mut self) -> Result<Option<IRValue>, IRInterpError> {
+        let entry_point = self.ir_module.entry_point.as_ref().ok_or(IRInterpError {
+            location: IRLocation::Module,
+            kind: IRInterpErrorKind::MissingEntryPoint,
+        })?;
+        self.call(entry_point, &[])
+    }
+
+    /// Lays out global data and runs the global init section, once.
+    pub fn initialize(&mut self) -> Result<(), IRInterpError> {
+        if self.initialized {
+            return Ok(());
+        }
+        if let Some((location, _type)) = find_aggregate_value(self.ir_module) {
+            return Err(IRInterpError {
+                location,
+                kind: IRInterpErrorKind::Unsupported(format!("value of type {}", _type)),
+            });
+        }
+        // Globals may hold each other's addresses, so all of them are allocated before any is
+        // filled in.
+        for pass in 0..2 {
+            for ir_global_data in self.ir_module.global_data_section.data.iter() {
+                let bytes =
+                    self.encode_global_data(ir_global_data)
+                        .map_err(|kind| IRInterpError {
+                            location: IRLocation::GlobalData(ir_global_data.name.clone()),
+                            kind,
+                        })?;
+                let mut machine = self.machine.borrow_mut();
+                if pass == 0 {
+                    let address = machine
+                        .memory
+                        .allocate(IRAllocationKind::Global, bytes.len() as u64)
+                        .map_err(|kind| IRInterpError {
+                            location: IRLocation::GlobalData(ir_global_data.name.clone()),
+                            kind,
+                        })?;
+                    machine.globals.insert(ir_global_data.name.clone(), address);
+                } else {
+                    let address = machine.globals[&ir_global_data.name];
+                    machine.memory.write(address, &bytes).unwrap();
+                }
+            }
+        }
+        self.initialized = true;
+        let control_flow_graph = &self.ir_module.global_init_section;
+        if control_flow_graph.basic_blocks.is_empty() {
+            return Ok(());
+        }
+        self.machine.borrow_mut().frames.push(IRFrame {
+            function: None,
+            control_flow_graph,
+            basic_block: 0,
+            instruction: 0,
+            previous_basic_block: None,
+            phi_values: HashMap::new(),
+            registers: HashMap::new(),
+            fields: HashMap::new(),
+            stack_allocations: vec![],
+            return_target: None,
+        });
+        self.execute().map(|_| ())
+    }
+
+    /// Calls a module function with the given arguments, initializing the module first.
+    pub fn call(
+        &mut self,
+        name: &str,
+        arguments: &[IRValue],
+    ) -> Result<Option<IRValue>, IRInterpError> {
+        self.initialize()?;
+        let ir_function = self.ir_module.functions.get(name).ok_or(IRInterpError {
+            location: IRLocation::Module,
+            kind: IRInterpErrorKind::UnknownFunction(name.to_string()),
+        })?;
+        self.push_frame(ir_function, arguments.to_vec(), None)
+            .map_err(|kind| IRInterpError {
+                location: IRLocation::Module,
+                kind,
+            })?;
+        self.execute()
+    }
+
+    /// The initial bytes of a global: its values back to back, padded to its declared size.
+    /// Addresses of globals that are not allocated yet encode as zero.
+    fn encode_global_data(&self, ir_global_data: &IRGlobalData) -> IRInterpResult<Vec<u8>> {
+        let mut bytes = vec![];
+        for value in ir_global_data.values.iter().flatten() {
+            let encoder = IRGlobalValueEncoder {
+                interpreter: self,
+                bytes: RefCell::new(Err(IRInterpErrorKind::Unsupported(value.to_string()))),
+            };
+            value.accept(&encoder);
+            bytes.extend(encoder.bytes.into_inner()?);
+        }
+        if let Some(size) = ir_global_data.size.as_ref() {
+            let size = self.evaluate_operand(size.as_ref())?.bits();
+            if size > MEMORY_LIMIT {
+                return Err(IRInterpErrorKind::OutOfMemory(size));
+            }
+            bytes.resize(bytes.len().max(size as usize), 0);
+        }
+        Ok(bytes)
+    }
+
+    /// Evaluates an operand in the current frame, if there is one.
+    fn evaluate_operand(&self, operand: &dyn IROperand) -> IRInterpResult<IRValue> {
+        let evaluator = IROperandEvaluator {
+            interpreter: self,
+            value: RefCell::new(Err(IRInterpErrorKind::Unsupported(operand.to_string()))),
+        };
+        operand.accept(&evaluator);
+        evaluator.value.into_inner()
+    }
+
+    fn push_frame(
+        &self,
+        ir_function: &'a IRFunction,
+        arguments: Vec<IRValue>,
+        return_target: Option<String>,
+    ) -> IRInterpResult<()> {
+        if arguments.len() != ir_function.arguments_count {
+            return Err(IRInterpErrorKind::ArgumentCountMismatch {
+                expected: ir_function.arguments_count,
+                found: arguments.len(),
+            });
+        }
+        let mut machine = self.machine.borrow_mut();
+        let mut fields = HashMap::new();
+        let mut stack_allocations = vec![];
+        for (index, field) in ir_function.fields.iter().enumerate() {
+            let scalar = IRScalar::of(field._type.as_ref());
+            let address = machine
+                .memory
+                .allocate(IRAllocationKind::Stack, scalar.size().max(1))?;
+            if let Some(argument) = arguments.get(index) {
+                machine.memory.write(address, &scalar.store(*argument))?;
+            }
+            fields.insert(field.name.clone(), address);
+            stack_allocations.push(address);
+        }
+        machine.frames.push(IRFrame {
+            function: Some(ir_function),
+            control_flow_graph: &ir_function.control_flow_graph,
+            basic_block: 0,
+            instruction: 0,
+            previous_basic_block: None,
+            phi_values: HashMap::new(),
+            registers: HashMap::new(),
+            fields,
+            stack_allocations,
+            return_target,
+        });
+        Ok(())
+    }
+
+    fn location(&self) -> IRLocation {
+        let machine = self.machine.borrow();
+        match machine.frames.last() {
+            Some(frame) => IRLocation::Instruction {
+                function: frame.function.map(|function| function.name.clone()),
+                basic_block: frame
+                    .control_flow_graph
+                    .basic_blocks
+                    .get_index(frame.basic_block)
+                    .map_or(String::new(), |(name, _)| name.clone()),
+                index: frame.instruction.saturating_sub(1),
+            },
+            None => IRLocation::Module,
+        }
+    }
+
+    /// Runs until the frame that was on top when called returns.
+    fn execute(&self) -> Result<Option<IRValue>, IRInterpError> {
+        let depth = self.machine.borrow().frames.len();
+        let result = self.execute_until(depth);
+        if result.is_err() {
+            let mut machine = self.machine.borrow_mut();
+            while machine.frames.len() >= depth {
+                let frame = machine.frames.pop().unwrap();
+                for address in frame.stack_allocations {
+                    let _ = machine.memory.release(IRAllocationKind::Stack, address);
+                }
+            }
+        }
+        result
+    }
+
+    fn execute_until(&self, depth: usize) -> Result<Option<IRValue>, IRInterpError> {
+        loop {
+            let instruction = self.fetch().map_err(|kind| IRInterpError {
+                location: self.location(),
+                kind,
+            })?;
+            let executor = IRExecutor {
+                interpreter: self,
+                control: RefCell::new(Ok(IRControl::Next)),
+            };
+            instruction.accept(&executor);
+            let control = executor.control.into_inner();
+            let result = control.and_then(|control| self.apply(control, depth));
+            match result {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => {}
+                Err(kind) => {
+                    return Err(IRInterpError {
+                        location: self.location(),
+                        kind,
+                    });
+                }
+            }
+        }
+    }
+
+    fn fetch(&self) -> IRInterpResult<&'a dyn IRInstruction> {
+        let mut machine = self.machine.borrow_mut();
+        machine.steps += 1;
+        if self.step_limit.is_some_and(|limit| machine.steps > limit) {
+            return Err(IRInterpErrorKind::StepLimitExceeded);
+        }
+        drop(machine);
+        loop {
+            let mut machine = self.machine.borrow_mut();
+            let frame = machine.frames.last_mut().unwrap();
+            let (name, basic_block) = frame
+                .control_flow_graph
+                .basic_blocks
+                .get_index(frame.basic_block)
+                .ok_or(IRInterpErrorKind::FellThroughEnd)?;
+            if let Some(instruction) = basic_block.instructions.get(frame.instruction) {
+                frame.instruction += 1;
+                return Ok(instruction.as_ref());
+            }
+            frame.previous_basic_block = Some(name.as_str());
+            frame.basic_block += 1;
+            frame.instruction = 0;
+            drop(machine);
+            self.enter_basic_block()?;
+        }
+    }
+
+    fn enter_basic_block(&self) -> IRInterpResult<()> {
+        let machine = self.machine.borrow();
+        let frame = machine.frames.last().unwrap();
+        let previous = frame.previous_basic_block;
+        let Some((_, basic_block)) = frame
+            .control_flow_graph
+            .basic_blocks
+            .get_index(frame.basic_block)
+        else {
+            return Ok(());
+        };
+        drop(machine);
+        let mut phi_values = HashMap::new();
+        for (target, ir_phi) in basic_block.phis() {
+            let value = self.evaluate_phi(&format!("%{}", target), &ir_phi, previous)?;
+            phi_values.insert(target, value);
+        }
+        self.machine
+            .borrow_mut()
+            .frames
+            .last_mut()
+            .unwrap()
+            .phi_values = phi_values;
+        Ok(())
+    }
+
+    /// The value of the operand a phi pairs with `predecessor`.
+    fn evaluate_phi(
+        &self,
+        phi: &str,
+        ir_phi: &IRPhi,
+        predecessor: Option<&str>,
+    ) -> IRInterpResult<IRValue> {
+        let (_, operand) = ir_phi
+            .labels
+            .iter()
+            .zip(ir_phi.operands.iter())
+            .find(|(label, _)| Some(label.as_str()) == predecessor)
+            .ok_or_else(|| IRInterpErrorKind::MissingPhiLabel {
+                phi: phi.to_string(),
+                predecessor: predecessor.map(str::to_string),
+            })?;
+        let value = self.evaluate_operand(operand.as_ref())?;
+        Ok(IRScalar::of(ir_phi._type.as_ref()).normalize(value))
+    }
+
+    /// Applies the effect of one instruction on control flow. Returns the result of the
+    /// outermost call once the frame at `depth` returns.
+    fn apply(&self, control: IRControl, depth: usize) -> IRInterpResult<Option<Option<IRValue>>> {
+        match control {
+            IRControl::Next => Ok(None),
+            IRControl::Jump(target) => {
+                let mut machine = self.machine.borrow_mut();
+                let frame = machine.frames.last_mut().unwrap();
+                let control_flow_graph = frame.control_flow_graph;
+                let index = control_flow_graph
+                    .basic_blocks
+                    .get_index_of(&target)
+                    .ok_or(IRInterpErrorKind::UnknownBasicBlock(target))?;
+                frame.previous_basic_block = control_flow_graph
+                    .basic_blocks
+                    .get_index(frame.basic_block)
+                    .map(|(name, _)| name.as_str());
+                frame.basic_block = index;
+                frame.instruction = 0;
+                drop(machine);
+                self.enter_basic_block().map(|_| None)
+            }
+            IRControl::Call {
+                address,
+                arguments,
+                target,
+            } => {
+                let index = address
+                    .checked_sub(FUNCTION_BASE)
+                    .filter(|offset| offset % ALLOCATION_ALIGNMENT == 0)
+                    .map(|offset| (offset / ALLOCATION_ALIGNMENT) as usize)
+                    .ok_or(IRInterpErrorKind::InvalidFunctionAddress(address))?;
+                if let Some((_, ir_function)) = self.ir_module.functions.get_index(index) {
+                    return self
+                        .push_frame(ir_function, arguments, target)
+                        .map(|_| None);
+                }
+                let (_, native) = self
+                    .natives
+                    .get(index - self.ir_module.functions.len())
+                    .ok_or(IRInterpErrorKind::InvalidFunctionAddress(address))?;
+                let mut machine = self.machine.borrow_mut();
+                let value = native(&mut machine.memory, &arguments)?;
+                if let (Some(target), Some(value)) = (target, value) {
+                    machine
+                        .frames
+                        .last_mut()
+                        .unwrap()
+                        .registers
+                        .insert(target, value);
+                }
+                Ok(None)
+            }
+            IRControl::Return(value) => {
+                let mut machine = self.machine.borrow_mut();
+                let frame = machine.frames.pop().unwrap();
+                for address in frame.stack_allocations {
+                    machine.memory.release(IRAllocationKind::Stack, address)?;
+                }
+                if machine.frames.len() < depth {
+                    return Ok(Some(value));
+                }
+                if let (Some(target), Some(value)) = (frame.return_target, value) {
+                    machine
+                        .frames
+                        .last_mut()
+                        .unwrap()
+                        .registers
+                        .insert(target, value);
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+struct IROperandEvaluator<'i, 'a> {
+    interpreter: &'i IRInterpreter<'a>,
+    value: RefCell<IRInterpResult<IRValue>>,
+}
+
+impl IROperandEvaluator<'_, '_> {
+    fn macro_address(&self, ir_macro: &IRMacro) -> IRInterpResult<u64> {
+        let name = ir_macro.args.first().cloned().unwrap_or_default();
+        let machine = self.interpreter.machine.borrow();
+        match ir_macro.name.as_str() {
+            "field_address" => machine
+                .frames
+                .last()
+                .and_then(|frame| frame.fields.get(&name).copied())
+                .ok_or(IRInterpErrorKind::UnknownField(name)),
+            "global_data_address" => machine
+                .globals
+                .get(&name)
+                .copied()
+                .ok_or(IRInterpErrorKind::UnknownGlobalData(name)),
+            "function_address" => self
+                .interpreter
+                .function_address(&name)
+                .ok_or(IRInterpErrorKind::UnknownFunction(name)),
+            _ => Err(IRInterpErrorKind::Unsupported(ir_macro.to_string())),
+        }
+    }
+}
+
+impl IRVisitor for IROperandEvaluator<'_, '_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_virtual_register(&self, ir_virtual_register: &IRVirtualRegister) {
+        let machine = self.interpreter.machine.borrow();
+        let value = machine
+            .frames
+            .last()
+            .and_then(|frame| frame.registers.get(&ir_virtual_register.name).copied())
+            .ok_or(IRInterpErrorKind::UndefinedRegister(
+                ir_virtual_register.name.clone(),
+            ));
+        *self.value.borrow_mut() = value;
+    }
+    fn visit_constant(&self, ir_constant: &IRConstant) {
+        let value = self
+            .interpreter
+            .constants
+            .get(ir_constant.index as usize)
+            .copied()
+            .flatten()
+            .ok_or(IRInterpErrorKind::InvalidConstant(ir_constant.index));
+        *self.value.borrow_mut() = value;
+    }
+    fn visit_phi(&self, ir_phi: &IRPhi) {
+        let previous = self
+            .interpreter
+            .machine
+            .borrow()
+            .frames
+            .last()
+            .and_then(|frame| frame.previous_basic_block);
+        let value = self
+            .interpreter
+            .evaluate_phi(&ir_phi.to_string(), ir_phi, previous);
+        *self.value.borrow_mut() = value;
+    }
+    fn visit_macro(&self, ir_macro: &IRMacro) {
+        *self.value.borrow_mut() = self.macro_address(ir_macro).map(IRValue::Integer);
+    }
+}
+
+/// Encodes the initial value of global data into bytes.
+struct IRGlobalValueEncoder<'i, 'a> {
+    interpreter: &'i IRInterpreter<'a>,
+    bytes: RefCell<IRInterpResult<Vec<u8>>>,
+}
+
+impl IRGlobalValueEncoder<'_, '_> {
+    fn function_addresses(&self, functions: &[String]) -> IRInterpResult<Vec<u8>> {
+        let mut bytes = vec![];
+        for function in functions {
+            let address = self
+                .interpreter
+                .function_address(function)
+                .ok_or(IRInterpErrorKind::UnknownFunction(function.clone()))?;
+            bytes.extend(address.to_le_bytes());
+        }
+        Ok(bytes)
+    }
+}
+
+impl IRVisitor for IRGlobalValueEncoder<'_, '_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_constant(&self, ir_constant: &IRConstant) {
+        let index = ir_constant.index as usize;
+        let bytes = self
+            .interpreter
+            .ir_module
+            .constant_pool
+            .entries
+            .get(index)
+            .and_then(|entry| {
+                let value = self.interpreter.constants.get(index).copied().flatten()?;
+                Some(IRScalar::of(entry._type.as_ref()).store(value))
+            })
+            .ok_or(IRInterpErrorKind::InvalidConstant(ir_constant.index));
+        *self.bytes.borrow_mut() = bytes;
+    }
+    fn visit_macro(&self, ir_macro: &IRMacro) {
+        let name = ir_macro.args.first().cloned().unwrap_or_default();
+        let bytes = match ir_macro.name.as_str() {
+            "function_address" => self.function_addresses(&ir_macro.args),
+            "global_data_address" => {
+                let machine = self.interpreter.machine.borrow();
+                match machine.globals.get(&name) {
+                    Some(address) => Ok(address.to_le_bytes().to_vec()),
+                    None if self
+                        .interpreter
+                        .ir_module
+                        .global_data_section
+                        .data
+                        .iter()
+                        .any(|data| data.name == name) =>
+                    {
+                        Ok(vec![0; 8])
+                    }
+                    None => Err(IRInterpErrorKind::UnknownGlobalData(name)),
+                }
+            }
+            _ => Err(IRInterpErrorKind::Unsupported(ir_macro.to_string())),
+        };
+        *self.bytes.borrow_mut() = bytes;
+    }
+    fn visit_virtual_table(&self, ir_virtual_table: &IRVirtualTable) {
+        *self.bytes.borrow_mut() = self.function_addresses(&ir_virtual_table.functions);
+    }
+    fn visit_interface_table(&self, ir_interface_table: &IRInterfaceTable) {
+        let functions = ir_interface_table
+            .entries
+            .iter()
+            .flat_map(|entry| entry.functions.iter().cloned())
+            .collect::<Vec<_>>();
+        *self.bytes.borrow_mut() = self.function_addresses(&functions);
+    }
+}
+
+/// Executes a single instruction against the top frame.
+struct IRExecutor<'i, 'a> {
+    interpreter: &'i IRInterpreter<'a>,
+    control: RefCell<IRInterpResult<IRControl>>,
+}
+
+impl IRExecutor<'_, '_> {
+    fn evaluate(&self, operand: &dyn IROperand) -> IRInterpResult<IRValue> {
+        let evaluator = IROperandEvaluator {
+            interpreter: self.interpreter,
+            value: RefCell::new(Err(IRInterpErrorKind::Unsupported(operand.to_string()))),
+        };
+        operand.accept(&evaluator);
+        evaluator.value.into_inner()
+    }
+
+    fn evaluate_typed(&self, operand: &dyn IROperand, scalar: IRScalar) -> IRInterpResult<IRValue> {
+        self.evaluate(operand).map(|value| scalar.normalize(value))
+    }
+
+    fn define(&self, target: &IRVirtualRegister, value: IRValue) {
+        let mut machine = self.interpreter.machine.borrow_mut();
+        let frame = machine.frames.last_mut().unwrap();
+        frame.registers.insert(target.name.clone(), value);
+    }
+
+    fn load(&self, address: u64, scalar: IRScalar) -> IRInterpResult<IRValue> {
+        let machine = self.interpreter.machine.borrow();
+        let bytes = machine.memory.read(address, scalar.size())?;
+        Ok(scalar.load(bytes))
+    }
+
+    fn store(&self, address: u64, scalar: IRScalar, value: IRValue) -> IRInterpResult<()> {
+        let mut machine = self.interpreter.machine.borrow_mut();
+        machine.memory.write(address, &scalar.store(value))
+    }
+
+    fn finish(&self, control: IRInterpResult<IRControl>) {
+        *self.control.borrow_mut() = control;
+    }
+
+    /// Applies `update` to a value, either held in memory at `operand` (atomic) or passed in
+    /// the operand itself, returning the updated value.
+    fn read_modify_write(
+        &self,
+        is_atomic: bool,
+        operand: &dyn IROperand,
+        scalar: IRScalar,
+        update: impl FnOnce(IRValue) -> IRInterpResult<IRValue>,
+    ) -> IRInterpResult<IRValue> {
+        if is_atomic {
+            let address = self.evaluate(operand)?.bits();
+            let value = update(self.load(address, scalar)?)?;
+            self.store(address, scalar, value)?;
+            Ok(value)
+        } else {
+            update(self.evaluate_typed(operand, scalar)?)
+        }
+    }
+
+    fn step(
+        &self,
+        _type: &dyn IRType,
+        operand: &dyn IROperand,
+        target: Option<&IRVirtualRegister>,
+        operator: IRCalculateOperator,
+    ) -> IRInterpResult<IRControl> {
+        let scalar = IRScalar::of(_type);
+        let one = match scalar {
+            IRScalar::Float => IRValue::Float(1.0),
+            IRScalar::Double => IRValue::Double(1.0),
+            _ => IRValue::Integer(1),
+        };
+        let value = self.read_modify_write(target.is_none(), operand, scalar, |value| {
+            calculate(scalar, operator, value, one)
+        })?;
+        if let Some(target) = target {
+            self.define(target, value);
+        }
+        Ok(IRControl::Next)
+    }
+}
+
+impl IRVisitor for IRExecutor<'_, '_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_goto(&self, ir_goto: &IRGoto) {
+        self.finish(Ok(IRControl::Jump(ir_goto.target.clone())));
+    }
+    fn visit_conditional_jump(&self, ir_conditional_jump: &IRConditionalJump) {
+        let scalar = IRScalar::of(ir_conditional_jump._type.as_ref());
+        let control = (|| {
+            let operand1 = if ir_conditional_jump.is_atomic {
+                let address = self.evaluate(ir_conditional_jump.operand1.as_ref())?.bits();
+                self.load(address, scalar)?
+            } else {
+                self.evaluate(ir_conditional_jump.operand1.as_ref())?
+            };
+            let operand2 = match ir_conditional_jump.operand2.as_ref() {
+                Some(operand2) => Some(self.evaluate(operand2.as_ref())?),
+                None => None,
+            };
+            if compare(scalar, ir_conditional_jump.condition, operand1, operand2) {
+                Ok(IRControl::Jump(ir_conditional_jump.target.clone()))
+            } else {
+                Ok(IRControl::Next)
+            }
+        })();
+        self.finish(control);
+    }
+    fn visit_return(&self, ir_return: &IRReturn) {
+        let control = match ir_return.operand.as_ref() {
+            Some(operand) => self
+                .evaluate(operand.as_ref())
+                .map(|value| IRControl::Return(Some(value))),
+            None => Ok(IRControl::Return(None)),
+        };
+        self.finish(control);
+    }
+    fn visit_calculate(&self, ir_calculate: &IRCalculate) {
+        let scalar = IRScalar::of(ir_calculate._type.as_ref());
+        let control = (|| {
+            let operand2 = self.evaluate_typed(ir_calculate.operand2.as_ref(), scalar)?;
+            let value = self.read_modify_write(
+                ir_calculate.is_atomic,
+                ir_calculate.operand1.as_ref(),
+                scalar,
+                |operand1| calculate(scalar, ir_calculate.operator, operand1, operand2),
+            )?;
+            self.define(&ir_calculate.target, value);
+            Ok(IRControl::Next)
+        })();
+        self.finish(control);
+    }
+    fn visit_not(&self, ir_not: &IRNot) {
+        let scalar = IRScalar::of(ir_not._type.as_ref());
+        let control = (|| {
+            let value = self.read_modify_write(
+                ir_not.is_atomic,
+                ir_not.operand.as_ref(),
+                scalar,
+                |value| match scalar {
+                    IRScalar::Integer { .. } => {
+                        Ok(scalar.normalize(IRValue::Integer(!value.bits())))
+                    }
+                    _ => Err(IRInterpErrorKind::Unsupported(format!(
+                        "not on {}",
+                        ir_not._type
+                    ))),
+                },
+            )?;
+            self.define(&ir_not.target, value);
+            Ok(IRControl::Next)
+        })();
+        self.finish(control);
+    }
+    fn visit_negate(&self, ir_negate: &IRNegate) {
+        let scalar = IRScalar::of(ir_negate._type.as_ref());
+        let control = (|| {
+            let value = self.read_modify_write(
+                ir_negate.is_atomic,
+                ir_negate.operand.as_ref(),
+                scalar,
+                |value| match value {
+                    IRValue::Float(value) => Ok(IRValue::Float(-value)),
+                    IRValue::Double(value) => Ok(IRValue::Double(-value)),
+                    IRValue::Integer(value) => {
+                        Ok(scalar.normalize(IRValue::Integer(value.wrapping_neg())))
+                    }
+                },
+            )?;
+            self.define(&ir_negate.target, value);
+            Ok(IRControl::Next)
+        })();
+        self.finish(control);
+    }
+    fn visit_malloc(&self, ir_malloc: &IRMalloc) {
+        let control = (|| {
+            let size = self.evaluate(ir_malloc.size.as_ref())?.bits();
+            let address = self.interpreter.machine.borrow_mut().memory.malloc(size);
+            self.define(&ir_malloc.target, IRValue::Integer(address));
+            Ok(IRControl::Next)
+        })();
+        self.finish(control);
+    }
+    fn visit_free(&self, ir_free: &IRFree) {
+        let control = (|| {
+            let address = self.evaluate(ir_free.ptr.as_ref())?.bits();
+            self.interpreter.machine.borrow_mut().memory.free(address)?;
+            Ok(IRControl::Next)
+        })();
+        self.finish(control);
+    }
+    fn visit_realloc(&self, ir_realloc: &IRRealloc) {
+        let control = (|| {
+            let address = self.evaluate(ir_realloc.ptr.as_ref())?.bits();
+            let size = self.evaluate(ir_realloc.size.as_ref())?.bits();
+            let address = self
+                .interpreter
+                .machine
+                .borrow_mut()
+                .memory
+                .realloc(address, size)?;
+            self.define(&ir_realloc.target, IRValue::Integer(address));
+            Ok(IRControl::Next)
+        })();
+        self.finish(control);
+    }
+    fn visit_get(&self, ir_get: &IRGet) {
+        let control = (|| {
+            let address = self.evaluate(ir_get.address.as_ref())?.bits();
+            let value = self.load(address, IRScalar::of(ir_get._type.as_ref()))?;
+            self.define(&ir_get.target, value);
+            Ok(IRControl::Next)
+        })();
+        self.finish(control);
+    }
+    fn visit_set(&self, ir_set: &IRSet) {
+        let control = (|| {
+            let address = self.evaluate(ir_set.address.as_ref())?.bits();
+            let value = self.evaluate(ir_set.value.as_ref())?;
+            self.store(address, IRScalar::of(ir_set._type.as_ref()), value)?;
+            Ok(IRControl::Next)
+        })();
+        self.finish(control);
+    }
+    fn visit_set_virtual_register(&self, ir_set_virtual_register: &IRSetVirtualRegister) {
+        let target = &ir_set_virtual_register.target;
+        let mut machine = self.interpreter.machine.borrow_mut();
+        let frame = machine.frames.last_mut().unwrap();
+        let phi_value = frame.phi_values.remove(&target.name);
+        let previous = frame.previous_basic_block;
+        drop(machine);
+        let source = ir_set_virtual_register.source.as_ref();
+        let value = match (phi_value, source.downcast_ref::<IRPhi>()) {
+            (Some(value), _) => Ok(value),
+            // Phis of the entry block are not evaluated on entry, as it has no predecessor.
+            (None, Some(ir_phi)) => {
+                self.interpreter
+                    .evaluate_phi(&format!("%{}", target.name), ir_phi, previous)
+            }
+            (None, None) => self.evaluate(source),
+        };
+        let control = value.map(|value| {
+            self.define(&ir_set_virtual_register.target, value);
+            IRControl::Next
+        });
+        self.finish(control);
+    }
+    fn visit_invoke(&self, ir_invoke: &IRInvoke) {
+        let control = (|| {
+            if ir_invoke.argument_types.len() != ir_invoke.arguments.len() {
+                return Err(IRInterpErrorKind::ArgumentCountMismatch {
+                    expected: ir_invoke.argument_types.len(),
+                    found: ir_invoke.arguments.len(),
+                });
+            }
+            let address = self.evaluate(ir_invoke.address.as_ref())?.bits();
+            let mut arguments = vec![];
+            for (argument_type, argument) in ir_invoke
+                .argument_types
+                .iter()
+                .zip(ir_invoke.arguments.iter())
+            {
+                arguments.push(
+                    self.evaluate_typed(argument.as_ref(), IRScalar::of(argument_type.as_ref()))?,
+                );
+            }
+            Ok(IRControl::Call {
+                address,
+                arguments,
+                target: ir_invoke.target.as_ref().map(|target| target.name.clone()),
+            })
+        })();
+        self.finish(control);
+    }
+    fn visit_no_operate(&self, _ir_no_operate: &IRNoOperate) {
+        self.finish(Ok(IRControl::Next));
+    }
+    fn visit_increase(&self, ir_increase: &IRIncrease) {
+        self.finish(self.step(
+            ir_increase._type.as_ref(),
+            ir_increase.operand.as_ref(),
+            ir_increase.target.as_deref(),
+            IRCalculateOperator::ADD,
+        ));
+    }
+    fn visit_decrease(&self, ir_decrease: &IRDecrease) {
+        self.finish(self.step(
+            ir_decrease._type.as_ref(),
+            ir_decrease.operand.as_ref(),
+            ir_decrease.target.as_deref(),
+            IRCalculateOperator::SUB,
+        ));
+    }
+    fn visit_stack_allocate(&self, ir_stack_allocate: &IRStackAllocate) {
+        let control = (|| {
+            let size = self.evaluate(ir_stack_allocate.size.as_ref())?.bits();
+            let mut machine = self.interpreter.machine.borrow_mut();
+            let address = machine.memory.allocate(IRAllocationKind::Stack, size)?;
+            machine
+                .frames
+                .last_mut()
+                .unwrap()
+                .stack_allocations
+                .push(address);
+            drop(machine);
+            self.define(&ir_stack_allocate.target, IRValue::Integer(address));
+            Ok(IRControl::Next)
+        })();
+        self.finish(control);
+    }
+    fn visit_element_address(&self, ir_element_address: &IRElementAddress) {
+        let control = (|| {
+            let offset = DataLayout::default()
+                .element_offset(self.interpreter.ir_module, ir_element_address)
+                .map_err(|error| IRInterpErrorKind::Unsupported(error.to_string()))?;
+            let mut address = self
+                .evaluate(ir_element_address.base.as_ref())?
+                .bits()
+                .wrapping_add(offset.constant);
+            for (stride, index) in offset.scaled.iter() {
+                let index = self.evaluate(index.as_ref())?.bits();
+                address = address.wrapping_add(index.wrapping_mul(*stride));
+            }
+            self.define(&ir_element_address.target, IRValue::Integer(address));
+            Ok(IRControl::Next)
+        })();
+        self.finish(control);
+    }
+    fn visit_type_cast(&self, ir_type_cast: &IRTypeCast) {
+        let control = self.evaluate(ir_type_cast.source.as_ref()).map(|value| {
+            let value = cast(
+                ir_type_cast.kind,
+                IRScalar::of(ir_type_cast.original_type.as_ref()),
+                IRScalar::of(ir_type_cast.target_type.as_ref()),
+                value,
+            );
+            self.define(&ir_type_cast.target, value);
+            IRControl::Next
+        });
+        self.finish(control);
+    }
+    fn visit_asm(&self, ir_asm: &IRAsm) {
+        self.finish(Err(IRInterpErrorKind::Unsupported(format!(
+            "asm \"{}\"",
+            ir_asm.code
+        ))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parser::parse_module;
+
+    fn call(source: &str, arguments: &[u64]) -> Result<Option<IRValue>, IRInterpError> {
+        let ir_module = parse_module(source).unwrap();
+        let arguments = arguments
+            .iter()
+            .map(|&argument| IRValue::Integer(argument))
+            .collect::<Vec<_>>();
+        IRInterpreter::new(&ir_module)
+            .with_step_limit(10_000)
+            .call("f", &arguments)
+    }
+
+    /// A function `f(a, b)` returning `a <operator> b` computed in `_type`.
+    fn calculate(operator: &str, _type: &str, a: u64, b: u64) -> Result<u64, IRInterpErrorKind> {
+        let source = format!(
+            "\
+function {_type} f({_type} a, {_type} b) {{
+entry:
+    %pa = `field_address([a], [])
+    %a = get {_type}, %pa
+    %pb = `field_address([b], [])
+    %b = get {_type}, %pb
+    %r = {operator} {_type} %a, %b
+    return %r
+}}
+"
+        );
+        match call(&source, &[a, b]) {
+            Ok(value) => Ok(value.unwrap().bits()),
+            Err(error) => Err(error.kind),
+        }
+    }
+
+    #[test]
+    fn calculates_through_the_declared_type() {
+        assert_eq!(calculate("add", "i32", 40, 2), Ok(42));
+        assert_eq!(calculate("sub", "i32", 2, 3), Ok(0xffff_ffff));
+        assert_eq!(calculate("mul", "i64", 6, 7), Ok(42));
+        assert_eq!(
+            calculate("div", "i32", -7i32 as u32 as u64, 2),
+            Ok(-3i32 as u32 as u64)
+        );
+        assert_eq!(
+            calculate("div", "u32", -7i32 as u32 as u64, 2),
+            Ok(0x7fff_fffc)
+        );
+        assert_eq!(
+            calculate("mod", "i32", -7i32 as u32 as u64, 2),
+            Ok(0xffff_ffff)
+        );
+        assert_eq!(calculate("shr", "i8", 0x80, 1), Ok(0xc0));
+        assert_eq!(calculate("ushr", "i8", 0x80, 1), Ok(0x40));
+        assert_eq!(calculate("xor", "u16", 0xff00, 0x0ff0), Ok(0xf0f0));
+    }
+
+    #[test]
+    fn integers_wrap_at_their_width() {
+        assert_eq!(calculate("add", "i8", 127, 1), Ok(0x80));
+        assert_eq!(calculate("add", "u8", 255, 1), Ok(0));
+        assert_eq!(calculate("mul", "u16", 0x100, 0x100), Ok(0));
+        assert_eq!(calculate("sub", "u64", 0, 1), Ok(u64::MAX));
+        assert_eq!(calculate("shl", "i32", 1, 31), Ok(0x8000_0000));
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        assert_eq!(
+            calculate("div", "i32", 1, 0),
+            Err(IRInterpErrorKind::DivisionByZero)
+        );
+        assert_eq!(
+            calculate("mod", "u64", 1, 0),
+            Err(IRInterpErrorKind::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn loops_carry_values_through_phis() {
+        let source = "\
+constant $0 = i32 0
+constant $1 = i32 1
+function i32 f(i32 n) {
+entry:
+    %pn = `field_address([n], [])
+    %n = get i32, %pn
+    goto loop
+loop:
+    %i = phi i32 [entry, $1], [loop, %next_i]
+    %sum = phi i32 [entry, $0], [loop, %next_sum]
+    %next_sum = add i32 %sum, %i
+    %next_i = add i32 %i, $1
+    conditional_jump i32 le, %next_i, %n, #loop
+done:
+    return %next_sum
+}
+";
+        assert_eq!(call(source, &[10]), Ok(Some(IRValue::Integer(55))));
+        assert_eq!(call(source, &[1]), Ok(Some(IRValue::Integer(1))));
+    }
+
+    #[test]
+    fn phis_of_a_block_read_the_values_of_the_predecessor() {
+        // Every pass back into the loop swaps a and b.
+        let source = "\
+constant $0 = i32 0
+constant $1 = i32 1
+constant $2 = i32 2
+constant $3 = i32 3
+function i32 f() {
+entry:
+    goto loop
+loop:
+    %a = phi i32 [entry, $1], [loop, %b]
+    %b = phi i32 [entry, $2], [loop, %a]
+    %round = phi i32 [entry, $0], [loop, %next]
+    %next = add i32 %round, $1
+    conditional_jump i32 l, %next, $3, #loop
+done:
+    %r = mul i32 %a, $3
+    %s = add i32 %r, %b
+    return %s
+}
+";
+        // Two swaps leave a = 1 and b = 2, where copying one phi at a time leaves both at 2.
+        assert_eq!(call(source, &[]), Ok(Some(IRValue::Integer(5))));
+    }
+
+    #[test]
+    fn phis_without_a_value_for_the_predecessor_are_an_error() {
+        let source = "\
+constant $0 = i32 0
+function i32 f() {
+entry:
+    goto other
+other:
+    goto join
+join:
+    %x = phi i32 [entry, $0]
+    return %x
+}
+";
+        let error = call(source, &[]).unwrap_err();
+        assert_eq!(
+            error.kind,
+            IRInterpErrorKind::MissingPhiLabel {
+                phi: "%x".to_string(),
+                predecessor: Some("other".to_string()),
+            }
+        );
+        assert_eq!(
+            error.kind.to_string(),
+            "%x has no value for predecessor 'other'"
+        );
+
+        let source = "\
+constant $0 = i32 0
+function i32 f() {
+entry:
+    %x = phi i32 [entry, $0]
+    return %x
+}
+";
+        assert_eq!(
+            call(source, &[]).unwrap_err().kind,
+            IRInterpErrorKind::MissingPhiLabel {
+                phi: "%x".to_string(),
+                predecessor: None,
+            }
+        );
+    }
+
+    #[test]
+    fn accesses_outside_an_allocation_are_an_error() {
+        let source = "\
+constant $0 = i64 4
+function i64 f() {
+entry:
+    %p = malloc $0
+    %x = get i64, %p
+    return %x
+}
+";
+        let error = call(source, &[]).unwrap_err();
+        assert!(matches!(
+            error.kind,
+            IRInterpErrorKind::InvalidAccess { size: 8, .. }
+        ));
+        assert_eq!(
+            error.location,
+            IRLocation::Instruction {
+                function: Some("f".to_string()),
+                basic_block: "entry".to_string(),
+                index: 1,
+            }
+        );
+
+        let source = "\
+constant $0 = i64 4
+function void f() {
+entry:
+    %p = malloc $0
+    free %p
+    free %p
+    return
+}
+";
+        assert!(matches!(
+            call(source, &[]).unwrap_err().kind,
+            IRInterpErrorKind::InvalidFree(_)
+        ));
+    }
+
+    #[test]
+    fn atomic_instructions_return_the_stored_value() {
+        let source = "\
+constant $0 = i64 4
+constant $1 = i32 10
+constant $2 = i32 5
+function i32 f() {
+entry:
+    %p = malloc $0
+    set i32, %p, $1
+    %sum = atomic_add i32 %p, $2
+    atomic_increase i32 %p
+    %stored = get i32, %p
+    %r = mul i32 %sum, $1
+    %s = add i32 %r, %stored
+    free %p
+    return %s
+}
+";
+        // atomic_add yields 15 and leaves 15 stored, which atomic_increase steps to 16.
+        assert_eq!(call(source, &[]), Ok(Some(IRValue::Integer(166))));
+    }
+
+    #[test]
+    fn globals_may_hold_each_other_addresses() {
+        let ir_module = parse_module(
+            "\
+global a, values=[`global_data_address([b], [])]
+global b, values=[`global_data_address([a], [])]
+",
+        )
+        .unwrap();
+        let mut interpreter = IRInterpreter::new(&ir_module);
+        interpreter.initialize().unwrap();
+        let a = interpreter.global_data_address("a").unwrap();
+        let b = interpreter.global_data_address("b").unwrap();
+        let memory = interpreter.memory();
+        assert_eq!(memory.read(a, 8).unwrap(), b.to_le_bytes());
+        assert_eq!(memory.read(b, 8).unwrap(), a.to_le_bytes());
+    }
+
+    #[test]
+    fn string_constants_are_nul_terminated_globals() {
+        let ir_module = parse_module(
+            "\
+constant $0 = i8* \"hi\\n\"
+function i8* f() {
+entry:
+    return $0
+}
+",
+        )
+        .unwrap();
+        let mut interpreter = IRInterpreter::new(&ir_module);
+        let address = interpreter.call("f", &[]).unwrap().unwrap().bits();
+        assert_eq!(
+            interpreter.memory().read_c_string(address).unwrap(),
+            b"hi\n"
+        );
+    }
+}
@@ -0,0 +1,569 @@
+//! Sizes, alignments and field offsets of IR types on a target.
+//!
+//! Structures are laid out like C structures: each field at the next offset aligned for it, the
+//! whole padded to a multiple of its largest field alignment. Arrays hold their elements back to
+//! back. Global data holds its values back to back, as the backends emit them, padded to its
+//! declared size.
+
+use crate::ir::base::{IRGlobalData, IRNode};
+use crate::ir::instruction::{IRElementAddress, IRElementIndex, IRElementPathError, element_step};
+use crate::ir::operand::{IRConstant, IRInterfaceTable, IRMacro, IROperand, IRVirtualTable};
+use crate::ir::structure::IRStructure;
+use crate::ir::types::{IRIntegerTypeSize, IRType, IRTypeKind};
+use crate::ir::{IRConstantValue, IRModule, IRVisitor};
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IREndianness {
+    Little,
+    Big,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IRLayoutError {
+    /// `void` and function types have no size.
+    Unsized(String),
+    UnknownStructure(String),
+    RecursiveStructure(String),
+    TooLarge(String),
+    UnknownConstant(u32),
+    Unsupported(String),
+    InvalidElementPath(IRElementPathError),
+}
+
+impl fmt::Display for IRLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IRLayoutError::Unsized(_type) => write!(f, "type {} has no size", _type),
+            IRLayoutError::UnknownStructure(name) => {
+                write!(f, "structure '{}' is not defined", name)
+            }
+            IRLayoutError::RecursiveStructure(name) => {
+                write!(f, "structure '{}' contains itself", name)
+            }
+            IRLayoutError::TooLarge(_type) => write!(f, "type {} is too large", _type),
+            IRLayoutError::UnknownConstant(index) => {
+                write!(f, "constant ${} is outside the constant pool", index)
+            }
+            IRLayoutError::Unsupported(value) => write!(f, "global value {} has no size", value),
+            IRLayoutError::InvalidElementPath(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for IRLayoutError {}
+
+impl From<IRElementPathError> for IRLayoutError {
+    fn from(error: IRElementPathError) -> Self {
+        match error {
+            IRElementPathError::UnknownStructure(name) => IRLayoutError::UnknownStructure(name),
+            error => IRLayoutError::InvalidElementPath(error),
+        }
+    }
+}
+
+pub type IRLayoutResult<T> = Result<T, IRLayoutError>;
+
+/// Where each field of a structure lives, in bytes from its start.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IRStructureLayout {
+    pub size: u64,
+    pub alignment: u64,
+    pub offsets: IndexMap<String, u64>,
+}
+
+impl IRStructureLayout {
+    pub fn offset_of(&self, field: &str) -> Option<u64> {
+        self.offsets.get(field).copied()
+    }
+}
+
+/// Where an `element_address` points relative to its base: a constant number of bytes plus each
+/// array index register times the size of the elements it selects. Constant indices are folded
+/// into `constant`, and all of it wraps around like address arithmetic does.
+#[derive(Clone, Debug)]
+pub struct IRElementOffset {
+    pub constant: u64,
+    pub scaled: Vec<(u64, Box<dyn IROperand>)>,
+}
+
+/// The sizes and alignments, in bytes, of the scalar types of a target.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataLayout {
+    pub pointer_size: u64,
+    pub pointer_alignment: u64,
+    pub endianness: IREndianness,
+    /// Alignments of `i1`, `i8`, `i16`, `i32` and `i64`, in that order.
+    pub integer_alignments: [u64; 5],
+    pub float_alignment: u64,
+    pub double_alignment: u64,
+}
+
+impl DataLayout {
+    /// A layout where every scalar is aligned to its own size.
+    pub fn new(pointer_size: u64, endianness: IREndianness) -> Self {
+        Self {
+            pointer_size,
+            pointer_alignment: pointer_size,
+            endianness,
+            integer_alignments: [1, 1, 2, 4, 8],
+            float_alignment: 4,
+            double_alignment: 8,
+        }
+    }
+
+    pub fn integer_alignment(&self, size: &IRIntegerTypeSize) -> u64 {
+        let index = match size {
+            IRIntegerTypeSize::OneBit => 0,
+            IRIntegerTypeSize::OneByte => 1,
+            IRIntegerTypeSize::TwoBytes => 2,
+            IRIntegerTypeSize::FourBytes => 3,
+            IRIntegerTypeSize::EightBytes => 4,
+        };
+        self.integer_alignments[index]
+    }
+
+    pub fn size_of(&self, ir_module: &IRModule, _type: &dyn IRType) -> IRLayoutResult<u64> {
+        self.layout_of(ir_module, _type, &mut vec![])
+            .map(|(size, _)| size)
+    }
+
+    pub fn align_of(&self, ir_module: &IRModule, _type: &dyn IRType) -> IRLayoutResult<u64> {
+        self.layout_of(ir_module, _type, &mut vec![])
+            .map(|(_, alignment)| alignment)
+    }
+
+    pub fn structure_layout(
+        &self,
+        ir_module: &IRModule,
+        ir_structure: &IRStructure,
+    ) -> IRLayoutResult<IRStructureLayout> {
+        self.structure_layout_in(
+            ir_module,
+            ir_structure,
+            &mut vec![ir_structure.name.clone()],
+        )
+    }
+
+    pub fn element_offset(
+        &self,
+        ir_module: &IRModule,
+        ir_element_address: &IRElementAddress,
+    ) -> IRLayoutResult<IRElementOffset> {
+        ir_element_address.element_type(ir_module)?;
+        let mut offset = IRElementOffset {
+            constant: 0,
+            scaled: vec![],
+        };
+        let mut _type = ir_element_address._type.clone();
+        for index in ir_element_address.path.iter() {
+            let element = element_step(ir_module, _type.as_ref(), index)?;
+            match (IRTypeKind::of(_type.as_ref()), index) {
+                (IRTypeKind::Structure(structure), IRElementIndex::Field(field)) => {
+                    let ir_structure = structure
+                        .structure(ir_module)
+                        .ok_or(IRLayoutError::UnknownStructure(structure.name))?;
+                    let field_offset = self
+                        .structure_layout(ir_module, ir_structure)?
+                        .offset_of(field)
+                        .unwrap_or(0);
+                    offset.constant = offset.constant.wrapping_add(field_offset);
+                }
+                (IRTypeKind::Array(_), IRElementIndex::Index(operand)) => {
+                    let stride = self.size_of(ir_module, element.as_ref())?;
+                    match constant_bits(ir_module, operand.as_ref()) {
+                        Some(index) => {
+                            offset.constant =
+                                offset.constant.wrapping_add(index.wrapping_mul(stride));
+                        }
+                        None => offset.scaled.push((stride, operand.clone())),
+                    }
+                }
+                // `element_step` accepts no other combination.
+                _ => {}
+            }
+            _type = element;
+        }
+        Ok(offset)
+    }
+
+    /// The bytes a global occupies: its values back to back, constants in the size of their
+    /// type, strings, addresses and table entries as pointers, padded to its declared size.
+    pub fn global_data_size(
+        &self,
+        ir_module: &IRModule,
+        ir_global_data: &IRGlobalData,
+    ) -> IRLayoutResult<u64> {
+        let sizer = IRGlobalValueSizer {
+            data_layout: self,
+            ir_module,
+            size: RefCell::new(None),
+        };
+        let mut size = 0u64;
+        for value in ir_global_data.values.iter().flatten() {
+            size = size
+                .checked_add(sizer.size_of(value.as_ref())?)
+                .ok_or_else(|| IRLayoutError::TooLarge(ir_global_data.name.clone()))?;
+        }
+        if let Some(declared) = ir_global_data.size.as_ref() {
+            size = size.max(declared_size(ir_module, declared.as_ref())?);
+        }
+        Ok(size)
+    }
+
+    /// `visiting` holds the structures being laid out, innermost last, so that a structure
+    /// holding itself by value is reported instead of recursing forever.
+    fn layout_of(
+        &self,
+        ir_module: &IRModule,
+        _type: &dyn IRType,
+        visiting: &mut Vec<String>,
+    ) -> IRLayoutResult<(u64, u64)> {
+        match IRTypeKind::of(_type) {
+            IRTypeKind::Integer(integer) => Ok((
+                (integer.size.clone() as u64).div_ceil(8),
+                self.integer_alignment(&integer.size),
+            )),
+            IRTypeKind::Float(_) => Ok((4, self.float_alignment)),
+            IRTypeKind::Double(_) => Ok((8, self.double_alignment)),
+            IRTypeKind::Pointer(_) => Ok((self.pointer_size, self.pointer_alignment)),
+            IRTypeKind::Void(_) | IRTypeKind::Function(_) => {
+                Err(IRLayoutError::Unsized(_type.to_string()))
+            }
+            IRTypeKind::Structure(structure) => {
+                if visiting.contains(&structure.name) {
+                    return Err(IRLayoutError::RecursiveStructure(structure.name));
+                }
+                let ir_structure = structure
+                    .structure(ir_module)
+                    .ok_or_else(|| IRLayoutError::UnknownStructure(structure.name.clone()))?;
+                visiting.push(structure.name);
+                let layout = self.structure_layout_in(ir_module, ir_structure, visiting);
+                visiting.pop();
+                layout.map(|layout| (layout.size, layout.alignment))
+            }
+            IRTypeKind::Array(array) => {
+                let (size, alignment) =
+                    self.layout_of(ir_module, array.element.as_ref(), visiting)?;
+                let size = size
+                    .checked_mul(array.length)
+                    .ok_or_else(|| IRLayoutError::TooLarge(_type.to_string()))?;
+                Ok((size, alignment))
+            }
+        }
+    }
+
+    fn structure_layout_in(
+        &self,
+        ir_module: &IRModule,
+        ir_structure: &IRStructure,
+        visiting: &mut Vec<String>,
+    ) -> IRLayoutResult<IRStructureLayout> {
+        let too_large = || IRLayoutError::TooLarge(format!("%{}", ir_structure.name));
+        let mut offsets = IndexMap::new();
+        let mut offset = 0u64;
+        let mut alignment = 1;
+        for field in ir_structure.fields.iter() {
+            let (field_size, field_alignment) =
+                self.layout_of(ir_module, field._type.as_ref(), visiting)?;
+            offset = align_to(offset, field_alignment).ok_or_else(too_large)?;
+            offsets.insert(field.name.clone(), offset);
+            offset = offset.checked_add(field_size).ok_or_else(too_large)?;
+            alignment = alignment.max(field_alignment);
+        }
+        Ok(IRStructureLayout {
+            size: align_to(offset, alignment).ok_or_else(too_large)?,
+            alignment,
+            offsets,
+        })
+    }
+}
+
+impl Default for DataLayout {
+    /// The layout of the 64-bit little-endian targets the backends generate code for.
+    fn default() -> Self {
+        Self::new(8, IREndianness::Little)
+    }
+}
+
+fn align_to(offset: u64, alignment: u64) -> Option<u64> {
+    offset.checked_next_multiple_of(alignment.max(1))
+}
+
+/// The declared size of a global, which must be an integer constant.
+fn declared_size(ir_module: &IRModule, declared: &dyn IROperand) -> IRLayoutResult<u64> {
+    constant_bits(ir_module, declared)
+        .ok_or_else(|| IRLayoutError::Unsupported(declared.to_string()))
+}
+
+/// The bits of an operand that is a scalar constant.
+fn constant_bits(ir_module: &IRModule, operand: &dyn IROperand) -> Option<u64> {
+    let reader = IRConstantBitsReader {
+        ir_module,
+        bits: RefCell::new(None),
+    };
+    operand.accept(&reader);
+    reader.bits.into_inner()
+}
+
+struct IRConstantBitsReader<'a> {
+    ir_module: &'a IRModule,
+    bits: RefCell<Option<u64>>,
+}
+
+impl IRVisitor for IRConstantBitsReader<'_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_constant(&self, ir_constant: &IRConstant) {
+        let entry = self
+            .ir_module
+            .constant_pool
+            .entries
+            .get(ir_constant.index as usize);
+        self.bits
+            .replace(entry.and_then(|entry| entry.value.bits()));
+    }
+}
+
+struct IRGlobalValueSizer<'a> {
+    data_layout: &'a DataLayout,
+    ir_module: &'a IRModule,
+    size: RefCell<Option<IRLayoutResult<u64>>>,
+}
+
+impl IRGlobalValueSizer<'_> {
+    fn size_of(&self, value: &dyn IROperand) -> IRLayoutResult<u64> {
+        value.accept(self);
+        self.size
+            .take()
+            .unwrap_or_else(|| Err(IRLayoutError::Unsupported(value.to_string())))
+    }
+
+    fn pointers(&self, count: usize) -> IRLayoutResult<u64> {
+        (count as u64)
+            .checked_mul(self.data_layout.pointer_size)
+            .ok_or_else(|| IRLayoutError::TooLarge(format!("{} pointers", count)))
+    }
+}
+
+impl IRVisitor for IRGlobalValueSizer<'_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_constant(&self, ir_constant: &IRConstant) {
+        let size = match self
+            .ir_module
+            .constant_pool
+            .entries
+            .get(ir_constant.index as usize)
+        {
+            // A string is stored elsewhere and referred to by its address.
+            Some(entry) if matches!(entry.value, IRConstantValue::Bytes(_)) => {
+                Ok(self.data_layout.pointer_size)
+            }
+            Some(entry) => self
+                .data_layout
+                .size_of(self.ir_module, entry._type.as_ref()),
+            None => Err(IRLayoutError::UnknownConstant(ir_constant.index)),
+        };
+        self.size.replace(Some(size));
+    }
+    fn visit_macro(&self, ir_macro: &IRMacro) {
+        if matches!(
+            ir_macro.name.as_str(),
+            "global_data_address" | "function_address"
+        ) {
+            self.size.replace(Some(self.pointers(1)));
+        }
+    }
+    fn visit_virtual_table(&self, ir_virtual_table: &IRVirtualTable) {
+        self.size
+            .replace(Some(self.pointers(ir_virtual_table.functions.len())));
+    }
+    fn visit_interface_table(&self, ir_interface_table: &IRInterfaceTable) {
+        let count = ir_interface_table
+            .entries
+            .iter()
+            .map(|entry| entry.functions.len())
+            .sum();
+        self.size.replace(Some(self.pointers(count)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parser::{parse_module, parse_type};
+
+    const MODULE: &str = "\
+structure Pair {
+    i8 tag
+    i64 value
+    i16 count
+}
+structure Grid {
+    i32 width
+    [3 x %Pair] cells
+}
+structure Loop {
+    i32 value
+    %Loop next
+}
+constant $0 = i64 2
+constant $1 = i8* \"hello\"
+constant $2 = i64 64
+constant $3 = i32 7
+global table, values=[$3, $1, IRVirtualTable{functions={f, f}}]
+global padded, size=$2, values=[$3]
+global dangling, values=[$9]
+function void f(%Grid* grid, i64 k) {
+entry:
+    %a = element_address %Grid, %grid, [cells, $0, count]
+    %b = element_address %Grid, %grid, [cells, %k, value]
+    return
+}
+";
+
+    /// Pointers and `i64` in 4 bytes, as on 32-bit x86.
+    fn narrow() -> DataLayout {
+        DataLayout {
+            integer_alignments: [1, 1, 2, 4, 4],
+            double_alignment: 4,
+            ..DataLayout::new(4, IREndianness::Little)
+        }
+    }
+
+    fn size_and_alignment(data_layout: &DataLayout, _type: &str) -> IRLayoutResult<(u64, u64)> {
+        let ir_module = parse_module(MODULE).unwrap();
+        let _type = parse_type(_type).unwrap();
+        Ok((
+            data_layout.size_of(&ir_module, _type.as_ref())?,
+            data_layout.align_of(&ir_module, _type.as_ref())?,
+        ))
+    }
+
+    fn structure_layout(data_layout: &DataLayout, name: &str) -> IRStructureLayout {
+        let ir_module = parse_module(MODULE).unwrap();
+        data_layout
+            .structure_layout(&ir_module, &ir_module.structures[name])
+            .unwrap()
+    }
+
+    fn offsets(layout: &IRStructureLayout) -> Vec<(&str, u64)> {
+        layout
+            .offsets
+            .iter()
+            .map(|(name, offset)| (name.as_str(), *offset))
+            .collect()
+    }
+
+    #[test]
+    fn scalars_follow_the_data_layout() {
+        let default = DataLayout::default();
+        assert_eq!(size_and_alignment(&default, "i1"), Ok((1, 1)));
+        assert_eq!(size_and_alignment(&default, "u16"), Ok((2, 2)));
+        assert_eq!(size_and_alignment(&default, "i64"), Ok((8, 8)));
+        assert_eq!(size_and_alignment(&default, "float"), Ok((4, 4)));
+        assert_eq!(size_and_alignment(&default, "double"), Ok((8, 8)));
+        assert_eq!(size_and_alignment(&default, "i8*"), Ok((8, 8)));
+        assert_eq!(size_and_alignment(&narrow(), "i64"), Ok((8, 4)));
+        assert_eq!(size_and_alignment(&narrow(), "double"), Ok((8, 4)));
+        assert_eq!(size_and_alignment(&narrow(), "%Pair*"), Ok((4, 4)));
+    }
+
+    #[test]
+    fn structures_pad_each_field_and_their_end() {
+        let pair = structure_layout(&DataLayout::default(), "Pair");
+        assert_eq!(offsets(&pair), [("tag", 0), ("value", 8), ("count", 16)]);
+        assert_eq!((pair.size, pair.alignment), (24, 8));
+        assert_eq!(pair.offset_of("count"), Some(16));
+        assert_eq!(pair.offset_of("missing"), None);
+
+        let pair = structure_layout(&narrow(), "Pair");
+        assert_eq!(offsets(&pair), [("tag", 0), ("value", 4), ("count", 12)]);
+        assert_eq!((pair.size, pair.alignment), (16, 4));
+    }
+
+    #[test]
+    fn arrays_hold_their_elements_back_to_back() {
+        let default = DataLayout::default();
+        assert_eq!(size_and_alignment(&default, "[3 x i16]"), Ok((6, 2)));
+        assert_eq!(size_and_alignment(&default, "[0 x i64]"), Ok((0, 8)));
+        let grid = structure_layout(&default, "Grid");
+        assert_eq!(offsets(&grid), [("width", 0), ("cells", 8)]);
+        assert_eq!((grid.size, grid.alignment), (80, 8));
+    }
+
+    #[test]
+    fn types_without_a_layout_are_reported() {
+        let default = DataLayout::default();
+        assert_eq!(
+            size_and_alignment(&default, "void"),
+            Err(IRLayoutError::Unsized("void".to_string()))
+        );
+        assert_eq!(
+            size_and_alignment(&default, "i32(i32)"),
+            Err(IRLayoutError::Unsized("i32 (i32)".to_string()))
+        );
+        assert_eq!(
+            size_and_alignment(&default, "%Missing"),
+            Err(IRLayoutError::UnknownStructure("Missing".to_string()))
+        );
+        assert_eq!(
+            size_and_alignment(&default, "%Loop"),
+            Err(IRLayoutError::RecursiveStructure("Loop".to_string()))
+        );
+        // A pointer to itself is fine.
+        assert_eq!(size_and_alignment(&default, "%Loop*"), Ok((8, 8)));
+        assert!(matches!(
+            size_and_alignment(&default, "[4294967296 x [4294967296 x i64]]"),
+            Err(IRLayoutError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn element_offsets_fold_constants_and_scale_registers() {
+        let ir_module = parse_module(MODULE).unwrap();
+        let instructions =
+            &ir_module.functions["f"].control_flow_graph.basic_blocks["entry"].instructions;
+        let offset = |index: usize| {
+            let ir_element_address = instructions[index]
+                .downcast_ref::<IRElementAddress>()
+                .unwrap();
+            DataLayout::default()
+                .element_offset(&ir_module, ir_element_address)
+                .unwrap()
+        };
+
+        // cells (8) + 2 * sizeof(Pair) (48) + count (16)
+        let a = offset(0);
+        assert_eq!(a.constant, 72);
+        assert!(a.scaled.is_empty());
+
+        // cells (8) + value (8), plus %k times sizeof(Pair)
+        let b = offset(1);
+        assert_eq!(b.constant, 16);
+        assert_eq!(b.scaled.len(), 1);
+        assert_eq!(b.scaled[0].0, 24);
+        assert_eq!(b.scaled[0].1.to_string(), "%k");
+    }
+
+    #[test]
+    fn globals_hold_their_values_padded_to_the_declared_size() {
+        let ir_module = parse_module(MODULE).unwrap();
+        let size = |data_layout: &DataLayout, index: usize| {
+            data_layout.global_data_size(&ir_module, &ir_module.global_data_section.data[index])
+        };
+        // An i32, the address of the string and two function pointers.
+        assert_eq!(size(&DataLayout::default(), 0), Ok(4 + 8 + 16));
+        assert_eq!(size(&narrow(), 0), Ok(4 + 4 + 8));
+        assert_eq!(size(&DataLayout::default(), 1), Ok(64));
+        assert_eq!(
+            size(&DataLayout::default(), 2),
+            Err(IRLayoutError::UnknownConstant(9))
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! Rewrites `element_address` instructions into the `i64` arithmetic they stand for, so that code
+//! generators only have to deal with flat addresses.
+
+use crate::ir::base::IRControlFlowGraph;
+use crate::ir::builder::register_names;
+use crate::ir::instruction::{
+    IRCalculate, IRCalculateOperator, IRElementAddress, IRInstruction, IRSetVirtualRegister,
+};
+use crate::ir::layout::{DataLayout, IRLayoutError};
+use crate::ir::operand::{IROperand, IRVirtualRegister};
+use crate::ir::types::{IRIntegerType, IRIntegerTypeSize};
+use crate::ir::verify::IRLocation;
+use crate::ir::{IRConstantValue, IRModule};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IRLowerError {
+    pub location: IRLocation,
+    pub kind: IRLayoutError,
+}
+
+impl fmt::Display for IRLowerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.kind)
+    }
+}
+
+impl std::error::Error for IRLowerError {}
+
+/// An `element_address` resolved against the data layout, waiting to be replaced.
+struct IRElementAddressPlan {
+    function: Option<String>,
+    basic_block: String,
+    index: usize,
+    base: Box<dyn IROperand>,
+    constant: u64,
+    scaled: Vec<(u64, Box<dyn IROperand>)>,
+    target: Box<IRVirtualRegister>,
+}
+
+/// Does the module contain an `element_address` anywhere?
+pub fn has_element_addresses(ir_module: &IRModule) -> bool {
+    control_flow_graphs(ir_module).any(|(_, ir_control_flow_graph)| {
+        ir_control_flow_graph
+            .basic_blocks
+            .values()
+            .flat_map(|ir_basic_block| ir_basic_block.instructions.iter())
+            .any(|ir_instruction| ir_instruction.is::<IRElementAddress>())
+    })
+}
+
+/// Replaces every `element_address` in the module with `mul` and `add` instructions on `i64`
+/// using the offsets of `data_layout`, and returns how many were replaced.
+///
+/// The target keeps its name; intermediate results get fresh registers named after it. The
+/// target is an `i64` afterwards rather than a typed pointer, which the type checker accepts
+/// wherever an address is expected.
+pub fn lower_element_addresses(
+    ir_module: &mut IRModule,
+    data_layout: &DataLayout,
+) -> Result<usize, IRLowerError> {
+    let plans = plan(ir_module, data_layout)?;
+    let count = plans.len();
+    let mut names_of: HashMap<Option<String>, HashSet<String>> = HashMap::new();
+    let i64_type = || Box::new(IRIntegerType::new(IRIntegerTypeSize::EightBytes, false));
+
+    // Later instructions are replaced first, so that the indices of earlier ones stay valid.
+    for plan in plans.into_iter().rev() {
+        let names = names_of
+            .entry(plan.function.clone())
+            .or_insert_with(|| register_names(ir_module, plan.function.as_deref()));
+        let mut constant = |value: u64| -> Box<dyn IROperand> {
+            Box::new(
+                ir_module
+                    .constant_pool
+                    .intern(i64_type(), IRConstantValue::I64(value as i64)),
+            )
+        };
+        let mut terms = vec![];
+        let mut instructions: Vec<Box<dyn IRInstruction>> = vec![];
+        let mut fresh = || {
+            let name = (0..)
+                .map(|n| format!("{}.{}", plan.target.name, n))
+                .find(|name| !names.contains(name))
+                .unwrap();
+            names.insert(name.clone());
+            Box::new(IRVirtualRegister::new(name))
+        };
+        for (stride, operand) in plan.scaled {
+            if stride == 1 {
+                terms.push(operand);
+                continue;
+            }
+            let product = fresh();
+            instructions.push(Box::new(IRCalculate::new(
+                false,
+                IRCalculateOperator::MUL,
+                i64_type(),
+                operand,
+                constant(stride),
+                product.clone(),
+            )));
+            terms.push(product);
+        }
+        if plan.constant != 0 {
+            terms.push(constant(plan.constant));
+        }
+        let mut sum = plan.base;
+        let last = terms.len();
+        for (position, term) in terms.into_iter().enumerate() {
+            let target = if position + 1 == last {
+                plan.target.clone()
+            } else {
+                fresh()
+            };
+            instructions.push(Box::new(IRCalculate::new(
+                false,
+                IRCalculateOperator::ADD,
+                i64_type(),
+                sum,
+                term,
+                target.clone(),
+            )));
+            sum = target;
+        }
+        if instructions.is_empty() {
+            instructions.push(Box::new(IRSetVirtualRegister::new(sum, plan.target)));
+        }
+
+        let ir_control_flow_graph = match plan.function.as_ref() {
+            Some(function) => &mut ir_module.functions[function].control_flow_graph,
+            None => &mut ir_module.global_init_section,
+        };
+        ir_control_flow_graph.basic_blocks[&plan.basic_block]
+            .instructions
+            .splice(plan.index..=plan.index, instructions);
+    }
+    Ok(count)
+}
+
+fn control_flow_graphs(
+    ir_module: &IRModule,
+) -> impl Iterator<Item = (Option<&str>, &IRControlFlowGraph)> {
+    std::iter::once((None, ir_module.global_init_section.as_ref())).chain(
+        ir_module.functions.values().map(|ir_function| {
+            (
+                Some(ir_function.name.as_str()),
+                ir_function.control_flow_graph.as_ref(),
+            )
+        }),
+    )
+}
+
+fn plan(
+    ir_module: &IRModule,
+    data_layout: &DataLayout,
+) -> Result<Vec<IRElementAddressPlan>, IRLowerError> {
+    let mut plans = vec![];
+    for (function, ir_control_flow_graph) in control_flow_graphs(ir_module) {
+        for (name, ir_basic_block) in ir_control_flow_graph.basic_blocks.iter() {
+            for (index, ir_instruction) in ir_basic_block.instructions.iter().enumerate() {
+                let Some(ir_element_address) = ir_instruction.downcast_ref::<IRElementAddress>()
+                else {
+                    continue;
+                };
+                let offset = data_layout
+                    .element_offset(ir_module, ir_element_address)
+                    .map_err(|kind| IRLowerError {
+                        location: IRLocation::Instruction {
+                            function: function.map(|function| function.to_string()),
+                            basic_block: name.clone(),
+                            index,
+                        },
+                        kind,
+                    })?;
+                plans.push(IRElementAddressPlan {
+                    function: function.map(|function| function.to_string()),
+                    basic_block: name.clone(),
+                    index,
+                    base: ir_element_address.base.clone(),
+                    constant: offset.constant,
+                    scaled: offset.scaled,
+                    target: ir_element_address.target.clone(),
+                });
+            }
+        }
+    }
+    Ok(plans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::layout::IREndianness;
+    use crate::ir::parser::parse_module;
+    use crate::ir::verify::verify_module;
+
+    const MODULE: &str = "\
+structure Pair {
+    i8 tag
+    i64 value
+    i16 count
+}
+structure Grid {
+    i32 width
+    [3 x %Pair] cells
+    [4 x i8] bytes
+}
+constant $0 = i64 2
+function void f(%Grid* grid, i64 k) {
+entry:
+    %pgrid = `field_address([grid], [])
+    %g = get %Grid*, %pgrid
+    %pk = `field_address([k], [])
+    %k = get i64, %pk
+    %width = element_address %Grid, %g, [width]
+    %count = element_address %Grid, %g, [cells, $0, count]
+    %value = element_address %Grid, %g, [cells, %k, value]
+    %byte = element_address %Grid, %g, [bytes, %k]
+    %value.0 = add i64 %k, %k
+    return
+}
+";
+
+    fn lowered() -> IRModule {
+        let mut ir_module = parse_module(MODULE).unwrap();
+        assert!(has_element_addresses(&ir_module));
+        assert_eq!(
+            lower_element_addresses(&mut ir_module, &DataLayout::default()),
+            Ok(4)
+        );
+        ir_module
+    }
+
+    fn instructions(ir_module: &IRModule) -> Vec<String> {
+        ir_module.functions["f"].control_flow_graph.basic_blocks["entry"]
+            .instructions
+            .iter()
+            .map(|ir_instruction| ir_instruction.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn element_addresses_become_i64_arithmetic() {
+        let ir_module = lowered();
+        assert!(!has_element_addresses(&ir_module));
+        assert_eq!(
+            instructions(&ir_module)[4..],
+            [
+                // Nothing to add for the first field.
+                "%width = %g",
+                // cells (8) + 2 * sizeof(Pair) (48) + count (16), folded into one constant.
+                "%count = add i64 %g, $4",
+                // %k times sizeof(Pair), then cells (8) + value (8); `%value.0` is taken.
+                "%value.1 = mul i64 %k, $2",
+                "%value.2 = add i64 %g, %value.1",
+                "%value = add i64 %value.2, $3",
+                // Bytes need no multiplication.
+                "%byte.0 = add i64 %g, %k",
+                "%byte = add i64 %byte.0, $1",
+                "%value.0 = add i64 %k, %k",
+                "return",
+            ]
+        );
+        let constants = ir_module
+            .constant_pool
+            .entries
+            .iter()
+            .map(|entry| entry.literal())
+            .collect::<Vec<_>>();
+        // Later element addresses are lowered first.
+        assert_eq!(constants, ["2", "80", "24", "16", "72"]);
+        verify_module(&ir_module).unwrap();
+    }
+
+    #[test]
+    fn the_data_layout_decides_the_offsets() {
+        let mut ir_module = parse_module(MODULE).unwrap();
+        let narrow = DataLayout {
+            integer_alignments: [1, 1, 2, 4, 4],
+            ..DataLayout::new(4, IREndianness::Little)
+        };
+        lower_element_addresses(&mut ir_module, &narrow).unwrap();
+        // Pair is 16 bytes with `count` at 12, and `cells` starts at 4: 4 + 2 * 16 + 12.
+        assert_eq!(instructions(&ir_module)[5], "%count = add i64 %g, $4");
+        assert_eq!(ir_module.constant_pool.entries[4].literal(), "48");
+        assert_eq!(instructions(&ir_module)[6], "%value.1 = mul i64 %k, $2");
+        assert_eq!(ir_module.constant_pool.entries[2].literal(), "16");
+    }
+
+    #[test]
+    fn layout_errors_name_the_instruction() {
+        let mut ir_module = parse_module(
+            "\
+function void f(%Missing* p) {
+entry:
+    %pp = `field_address([p], [])
+    %p = get %Missing*, %pp
+    %q = element_address %Missing, %p, [value]
+    return
+}
+",
+        )
+        .unwrap();
+        let error = lower_element_addresses(&mut ir_module, &DataLayout::default()).unwrap_err();
+        assert_eq!(
+            error,
+            IRLowerError {
+                location: IRLocation::Instruction {
+                    function: Some("f".to_string()),
+                    basic_block: "entry".to_string(),
+                    index: 2,
+                },
+                kind: IRLayoutError::UnknownStructure("Missing".to_string()),
+            }
+        );
+        // Nothing was rewritten.
+        assert!(has_element_addresses(&ir_module));
+    }
+}
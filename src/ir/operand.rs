@@ -1,13 +1,151 @@
 use clone_dyn::clone_dyn;
 
-use crate::ir::IRVisitor;
 use crate::ir::base::IRNode;
-use crate::ir::types::IRType;
+use crate::ir::types::{IRFunctionType, IRType};
+use crate::ir::{IRModule, IRVisitor};
+use std::any::Any;
+use std::cell::RefCell;
 use std::fmt::{self, Debug};
 use std::fmt::{Display, Formatter};
 
 #[clone_dyn]
-pub trait IROperand: IRNode + Debug {}
+pub trait IROperand: IRNode + Debug + Any {
+    /// The operands nested in this one, for passes that rewrite them in place.
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        vec![]
+    }
+}
+
+impl dyn IROperand {
+    pub fn is<T: IROperand>(&self) -> bool {
+        (self as &dyn Any).is::<T>()
+    }
+
+    pub fn downcast_ref<T: IROperand>(&self) -> Option<&T> {
+        (self as &dyn Any).downcast_ref()
+    }
+
+    pub fn downcast_mut<T: IROperand>(&mut self) -> Option<&mut T> {
+        (self as &mut dyn Any).downcast_mut()
+    }
+
+    pub fn kind(&self) -> IROperandKind {
+        IROperandKind::of(self)
+    }
+}
+
+/// A closed view of an `IROperand` trait object, for passes that match on operands.
+#[derive(Clone, Debug)]
+pub enum IROperandKind {
+    VirtualRegister(IRVirtualRegister),
+    Constant(IRConstant),
+    Macro(IRMacro),
+    Phi(IRPhi),
+    VirtualTable(IRVirtualTable),
+    InterfaceTable(IRInterfaceTable),
+}
+
+impl IROperandKind {
+    pub fn of(ir_operand: &dyn IROperand) -> Self {
+        let collector = IROperandKindCollector {
+            kind: RefCell::new(None),
+        };
+        ir_operand.accept(&collector);
+        collector
+            .kind
+            .into_inner()
+            .expect("every IROperand is visited through one of the operand visitor methods")
+    }
+
+    pub fn into_operand(self) -> Box<dyn IROperand> {
+        match self {
+            IROperandKind::VirtualRegister(ir_virtual_register) => Box::new(ir_virtual_register),
+            IROperandKind::Constant(ir_constant) => Box::new(ir_constant),
+            IROperandKind::Macro(ir_macro) => Box::new(ir_macro),
+            IROperandKind::Phi(ir_phi) => Box::new(ir_phi),
+            IROperandKind::VirtualTable(ir_virtual_table) => Box::new(ir_virtual_table),
+            IROperandKind::InterfaceTable(ir_interface_table) => Box::new(ir_interface_table),
+        }
+    }
+}
+
+impl From<IROperandKind> for Box<dyn IROperand> {
+    fn from(ir_operand_kind: IROperandKind) -> Self {
+        ir_operand_kind.into_operand()
+    }
+}
+
+impl From<IRVirtualRegister> for IROperandKind {
+    fn from(ir_virtual_register: IRVirtualRegister) -> Self {
+        IROperandKind::VirtualRegister(ir_virtual_register)
+    }
+}
+
+impl From<IRConstant> for IROperandKind {
+    fn from(ir_constant: IRConstant) -> Self {
+        IROperandKind::Constant(ir_constant)
+    }
+}
+
+impl From<IRMacro> for IROperandKind {
+    fn from(ir_macro: IRMacro) -> Self {
+        IROperandKind::Macro(ir_macro)
+    }
+}
+
+impl From<IRPhi> for IROperandKind {
+    fn from(ir_phi: IRPhi) -> Self {
+        IROperandKind::Phi(ir_phi)
+    }
+}
+
+impl From<IRVirtualTable> for IROperandKind {
+    fn from(ir_virtual_table: IRVirtualTable) -> Self {
+        IROperandKind::VirtualTable(ir_virtual_table)
+    }
+}
+
+impl From<IRInterfaceTable> for IROperandKind {
+    fn from(ir_interface_table: IRInterfaceTable) -> Self {
+        IROperandKind::InterfaceTable(ir_interface_table)
+    }
+}
+
+struct IROperandKindCollector {
+    kind: RefCell<Option<IROperandKind>>,
+}
+
+impl IRVisitor for IROperandKindCollector {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_virtual_register(&self, ir_virtual_register: &IRVirtualRegister) {
+        self.kind.replace(Some(IROperandKind::VirtualRegister(
+            ir_virtual_register.clone(),
+        )));
+    }
+    fn visit_constant(&self, ir_constant: &IRConstant) {
+        self.kind
+            .replace(Some(IROperandKind::Constant(ir_constant.clone())));
+    }
+    fn visit_macro(&self, ir_macro: &IRMacro) {
+        self.kind
+            .replace(Some(IROperandKind::Macro(ir_macro.clone())));
+    }
+    fn visit_phi(&self, ir_phi: &IRPhi) {
+        self.kind.replace(Some(IROperandKind::Phi(ir_phi.clone())));
+    }
+    fn visit_virtual_table(&self, ir_virtual_table: &IRVirtualTable) {
+        self.kind
+            .replace(Some(IROperandKind::VirtualTable(ir_virtual_table.clone())));
+    }
+    fn visit_interface_table(&self, ir_interface_table: &IRInterfaceTable) {
+        self.kind.replace(Some(IROperandKind::InterfaceTable(
+            ir_interface_table.clone(),
+        )));
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct IRVirtualRegister {
     pub name: String,
@@ -35,10 +173,10 @@ impl IROperand for IRVirtualRegister {}
 
 #[derive(Clone, Debug)]
 pub struct IRConstant {
-    pub index: i32,
+    pub index: u32,
 }
 impl IRConstant {
-    pub fn new(index: i32) -> Self {
+    pub fn new(index: u32) -> Self {
         Self { index }
     }
 }
@@ -95,7 +233,11 @@ impl IRNode for IRMacro {
         visitor.visit_macro(self);
     }
 }
-impl IROperand for IRMacro {}
+impl IROperand for IRMacro {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        self.additional_operands.iter_mut().collect()
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct IRPhi {
@@ -118,7 +260,7 @@ impl IRPhi {
 }
 impl Display for IRPhi {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        let s = format!("phi {} ", self._type.to_string())
+        let s = format!("phi {} ", self._type)
             + &self
                 .labels
                 .iter()
@@ -136,7 +278,11 @@ impl IRNode for IRPhi {
     }
 }
 
-impl IROperand for IRPhi {}
+impl IROperand for IRPhi {
+    fn operands_mut(&mut self) -> Vec<&mut Box<dyn IROperand>> {
+        self.operands.iter_mut().collect()
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct IRVirtualTable {
@@ -147,6 +293,14 @@ impl IRVirtualTable {
     pub fn new(functions: Vec<String>) -> Self {
         Self { functions }
     }
+
+    /// The signature of each entry, or `None` for a function the module does not define.
+    pub fn function_types(&self, ir_module: &IRModule) -> Vec<Option<IRFunctionType>> {
+        self.functions
+            .iter()
+            .map(|name| ir_module.function_type(name))
+            .collect()
+    }
 }
 
 impl Display for IRVirtualTable {
@@ -160,7 +314,7 @@ impl Display for IRVirtualTable {
 }
 impl IRNode for IRVirtualTable {
     fn accept(&self, visitor: &dyn IRVisitor) {
-        todo!()
+        visitor.visit_virtual_table(self);
     }
 }
 impl IROperand for IRVirtualTable {}
@@ -211,7 +365,7 @@ impl Display for IRInterfaceTable {
 }
 impl IRNode for IRInterfaceTable {
     fn accept(&self, visitor: &dyn IRVisitor) {
-        todo!()
+        visitor.visit_interface_table(self);
     }
 }
 impl IROperand for IRInterfaceTable {}
@@ -0,0 +1,1437 @@
+use crate::ir::base::{IRBasicBlock, IRCondition, IRControlFlowGraph, IRFunction, IRGlobalData};
+use crate::ir::instruction::{
+    IRAsm, IRCalculate, IRCalculateOperator, IRConditionalJump, IRDecrease, IRElementAddress,
+    IRElementIndex, IRFree, IRGet, IRGoto, IRIncrease, IRInstruction, IRInvoke, IRMalloc, IRNegate,
+    IRNoOperate, IRNot, IRRealloc, IRReturn, IRSet, IRSetVirtualRegister, IRStackAllocate,
+    IRTypeCast, IRTypeCastKind,
+};
+use crate::ir::operand::{
+    IRConstant, IRInterfaceTable, IRInterfaceTableEntry, IRMacro, IROperand, IRPhi,
+    IRVirtualRegister, IRVirtualTable,
+};
+use crate::ir::structure::{IRField, IRStructure};
+use crate::ir::types::{
+    IRArrayType, IRDoubleType, IRFloatType, IRFunctionType, IRIntegerType, IRIntegerTypeSize,
+    IRPointerType, IRStructureType, IRType, IRTypeKind, IRVoidType,
+};
+use crate::ir::{IRConstantPoolEntry, IRConstantValue, IRModule, MAX_NESTING_DEPTH};
+use indexmap::IndexMap;
+use std::fmt;
+
+/// Error produced when textual IR cannot be parsed, located by 1-based line and column.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IRParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for IRParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for IRParseError {}
+
+pub type IRParseResult<T> = Result<T, IRParseError>;
+
+/// Parses a whole module.
+///
+/// The module text is a sequence of top-level items, one per line or block:
+///
+/// ```text
+/// structure Node {
+///     i32 value
+///     %Node* next
+/// }
+/// constant $0 = i32 42
+/// global counter, size=$0
+/// init {
+/// entry:
+///     return
+/// }
+/// function i32 main(i32 argc) {
+///     local i64 tmp
+/// entry:
+///     %t = add i32 %argc, $0
+///     return %t
+/// }
+/// vtable Node = [main]
+/// itable Node = [main]
+/// entry_point main
+/// ```
+///
+/// Instructions and operands use exactly the form produced by their `Display` implementations.
+/// Comments start with `;` and run to the end of the line.
+pub fn parse_module(source: &str) -> IRParseResult<IRModule> {
+    let mut parser = IRParser::new(source);
+    let module = parser.parse_module()?;
+    parser.expect_eof()?;
+    Ok(module)
+}
+
+/// Parses a single `function ... { ... }` item.
+pub fn parse_function(source: &str) -> IRParseResult<IRFunction> {
+    let mut parser = IRParser::new(source);
+    parser.skip_trivia();
+    parser.expect_keyword("function")?;
+    let function = parser.parse_function()?;
+    parser.expect_eof()?;
+    Ok(function)
+}
+
+/// Parses a single instruction in its `Display` form.
+pub fn parse_instruction(source: &str) -> IRParseResult<Box<dyn IRInstruction>> {
+    let mut parser = IRParser::new(source);
+    parser.skip_trivia();
+    let instruction = parser.parse_instruction()?;
+    parser.expect_eof()?;
+    Ok(instruction)
+}
+
+/// Parses a single operand in its `Display` form.
+pub fn parse_operand(source: &str) -> IRParseResult<Box<dyn IROperand>> {
+    let mut parser = IRParser::new(source);
+    parser.skip_trivia();
+    let operand = parser.parse_operand()?;
+    parser.expect_eof()?;
+    Ok(operand)
+}
+
+/// Parses a single type in its `Display` form.
+pub fn parse_type(source: &str) -> IRParseResult<Box<dyn IRType>> {
+    let mut parser = IRParser::new(source);
+    parser.skip_trivia();
+    let _type = parser.parse_type()?;
+    parser.expect_eof()?;
+    Ok(_type)
+}
+
+fn is_identifier_char(c: char) -> bool {
+    !c.is_whitespace() && !",[](){}=:;\"%$#`*".contains(c)
+}
+
+struct IRParser<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl<'a> IRParser<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            position: 0,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn error_at<T>(&self, position: usize, message: String) -> IRParseResult<T> {
+        let before = &self.source[..position];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rfind('\n')
+            .map_or(before.chars().count(), |i| before[i + 1..].chars().count())
+            + 1;
+        Err(IRParseError {
+            line,
+            column,
+            message,
+        })
+    }
+
+    fn error<T>(&self, message: String) -> IRParseResult<T> {
+        self.error_at(self.position, message)
+    }
+
+    fn skip_spaces(&mut self) {
+        let rest = self.rest();
+        let trimmed = rest.trim_start_matches([' ', '\t']);
+        self.position += rest.len() - trimmed.len();
+    }
+
+    fn skip_comment(&mut self) {
+        if self.rest().starts_with(';') {
+            self.position += self.rest().find('\n').unwrap_or(self.rest().len());
+        }
+    }
+
+    fn skip_trivia(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek_char() {
+                Some('\n') | Some('\r') => self.position += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn at_line_end(&mut self) -> bool {
+        self.skip_spaces();
+        matches!(self.peek_char(), None | Some('\n') | Some('\r') | Some(';'))
+    }
+
+    fn expect_line_end(&mut self) -> IRParseResult<()> {
+        if !self.at_line_end() {
+            return self.error(format!("unexpected '{}'", self.peek_token()));
+        }
+        self.skip_comment();
+        self.skip_trivia();
+        Ok(())
+    }
+
+    fn expect_eof(&mut self) -> IRParseResult<()> {
+        self.skip_trivia();
+        if self.position < self.source.len() {
+            return self.error(format!("unexpected '{}'", self.peek_token()));
+        }
+        Ok(())
+    }
+
+    fn peek_token(&self) -> &'a str {
+        let rest = self.rest();
+        if rest.is_empty() || rest.starts_with(['\n', '\r']) {
+            return "end of line";
+        }
+        let end = rest
+            .char_indices()
+            .find(|&(i, c)| c.is_whitespace() || (i > 0 && !is_identifier_char(c)))
+            .map_or(rest.len(), |(i, _)| i);
+        if end == 0 {
+            rest.get(..rest.chars().next().map_or(0, char::len_utf8))
+                .unwrap_or("")
+        } else {
+            &rest[..end]
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_spaces();
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> IRParseResult<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            self.error(format!(
+                "expected '{}', found '{}'",
+                token,
+                self.peek_token()
+            ))
+        }
+    }
+
+    fn peek_identifier(&mut self) -> &'a str {
+        self.skip_spaces();
+        let rest = self.rest();
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| !is_identifier_char(c))
+            .map_or(rest.len(), |(i, _)| i);
+        &rest[..end]
+    }
+
+    fn identifier(&mut self) -> IRParseResult<String> {
+        let identifier = self.peek_identifier();
+        if identifier.is_empty() {
+            return self.error(format!(
+                "expected identifier, found '{}'",
+                self.peek_token()
+            ));
+        }
+        self.position += identifier.len();
+        Ok(identifier.to_string())
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_identifier() == keyword {
+            self.position += keyword.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> IRParseResult<()> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            self.error(format!(
+                "expected '{}', found '{}'",
+                keyword,
+                self.peek_token()
+            ))
+        }
+    }
+
+    fn index(&mut self) -> IRParseResult<usize> {
+        self.skip_spaces();
+        let rest = self.rest();
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| !c.is_ascii_digit())
+            .map_or(rest.len(), |(i, _)| i);
+        match rest[..end].parse() {
+            Ok(value) => {
+                self.position += end;
+                Ok(value)
+            }
+            Err(_) => self.error(format!("expected number, found '{}'", self.peek_token())),
+        }
+    }
+
+    /// Reads raw text up to (not including) the first of `terminators` or the end of the line.
+    fn raw_until(&mut self, terminators: &[char]) -> &'a str {
+        self.skip_spaces();
+        let rest = self.rest();
+        let end = rest
+            .find(|c| terminators.contains(&c) || c == '\n' || c == '\r')
+            .unwrap_or(rest.len());
+        self.position += end;
+        rest[..end].trim_end()
+    }
+
+    fn comma_separated<T>(
+        &mut self,
+        close: &str,
+        mut item: impl FnMut(&mut Self) -> IRParseResult<T>,
+    ) -> IRParseResult<Vec<T>> {
+        let mut items = vec![];
+        if self.eat(close) {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.eat(close) {
+                return Ok(items);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn parse_module(&mut self) -> IRParseResult<IRModule> {
+        let mut module = IRModule::new();
+        self.skip_trivia();
+        while self.position < self.source.len() {
+            let start = self.position;
+            let keyword = self.identifier()?;
+            match keyword.as_str() {
+                "structure" => {
+                    let structure = self.parse_structure()?;
+                    if module.structures.contains_key(&structure.name) {
+                        return self
+                            .error_at(start, format!("duplicate structure '{}'", structure.name));
+                    }
+                    module.push_struct(structure);
+                }
+                "constant" => {
+                    self.expect("$")?;
+                    let index = self.index()?;
+                    if index != module.constant_pool.entries.len() {
+                        return self.error_at(
+                            start,
+                            format!(
+                                "constant ${} out of order, expected ${}",
+                                index,
+                                module.constant_pool.entries.len()
+                            ),
+                        );
+                    }
+                    self.expect("=")?;
+                    let entry = self.parse_constant_pool_entry(&module.structures)?;
+                    module.constant_pool.push(Box::new(entry));
+                }
+                "global" => {
+                    let global_data = self.parse_global_data()?;
+                    module.global_data_section.data.push(global_data);
+                }
+                "init" => {
+                    self.expect("{")?;
+                    self.expect_line_end()?;
+                    let control_flow_graph = self.parse_control_flow_graph()?;
+                    module.global_init_section = Box::new(control_flow_graph);
+                }
+                "function" => {
+                    let function = self.parse_function()?;
+                    if module.functions.contains_key(&function.name) {
+                        return self
+                            .error_at(start, format!("duplicate function '{}'", function.name));
+                    }
+                    module.push_function(function);
+                }
+                "vtable" | "itable" => {
+                    let name = self.identifier()?;
+                    self.expect("=")?;
+                    self.expect("[")?;
+                    let keys = self.comma_separated("]", |parser| parser.identifier())?;
+                    if keyword == "vtable" {
+                        module.name2vtable_keys.insert(name, keys);
+                    } else {
+                        module.name2itable_keys.insert(name, keys);
+                    }
+                }
+                "entry_point" => {
+                    module.entry_point = Some(self.identifier()?);
+                }
+                _ => {
+                    return self.error_at(start, format!("unknown item '{}'", keyword));
+                }
+            }
+            self.expect_line_end()?;
+        }
+        Ok(module)
+    }
+
+    fn parse_structure(&mut self) -> IRParseResult<IRStructure> {
+        let name = self.identifier()?;
+        self.expect("{")?;
+        self.expect_line_end()?;
+        let mut fields = vec![];
+        while !self.eat("}") {
+            fields.push(self.parse_field()?);
+            self.expect_line_end()?;
+        }
+        Ok(IRStructure::new(name, fields))
+    }
+
+    fn parse_field(&mut self) -> IRParseResult<IRField> {
+        let _type = self.parse_type()?;
+        let name = self.identifier()?;
+        Ok(IRField::new(name, _type))
+    }
+
+    fn parse_constant_pool_entry(
+        &mut self,
+        structures: &IndexMap<String, Box<IRStructure>>,
+    ) -> IRParseResult<IRConstantPoolEntry> {
+        let _type = self.parse_type()?;
+        self.skip_spaces();
+        let start = self.position;
+        let text = strip_comment(self.raw_until(&[]));
+        if text.is_empty() {
+            return self.error("expected constant value".to_string());
+        }
+        match constant_value(text, _type.as_ref(), structures) {
+            Some(value) => Ok(IRConstantPoolEntry::new(_type, value)),
+            None => self.error_at(start, format!("invalid {} constant '{}'", _type, text)),
+        }
+    }
+
+    fn parse_global_data(&mut self) -> IRParseResult<IRGlobalData> {
+        let name = self.identifier()?;
+        let mut size = None;
+        let mut values = None;
+        while self.eat(",") {
+            if self.eat_keyword("size") {
+                self.expect("=")?;
+                size = Some(self.parse_operand()?);
+            } else if self.eat_keyword("values") {
+                self.expect("=")?;
+                self.expect("[")?;
+                values = Some(self.comma_separated("]", |parser| parser.parse_operand())?);
+            } else {
+                return self.error(format!(
+                    "expected 'size' or 'values', found '{}'",
+                    self.peek_token()
+                ));
+            }
+        }
+        Ok(IRGlobalData::new(name, size, values))
+    }
+
+    fn parse_function(&mut self) -> IRParseResult<IRFunction> {
+        let return_type = self.parse_type()?;
+        let name = self.identifier()?;
+        self.expect("(")?;
+        let mut fields = self
+            .comma_separated(")", |parser| parser.parse_field())?
+            .into_iter()
+            .map(Box::new)
+            .collect::<Vec<_>>();
+        let arguments_count = fields.len();
+        self.expect("{")?;
+        self.expect_line_end()?;
+        while self.eat_keyword("local") {
+            fields.push(Box::new(self.parse_field()?));
+            self.expect_line_end()?;
+        }
+        let control_flow_graph = self.parse_control_flow_graph()?;
+        Ok(IRFunction::new(
+            return_type,
+            name,
+            arguments_count,
+            fields,
+            Box::new(control_flow_graph),
+        ))
+    }
+
+    /// Parses labelled basic blocks up to and including the closing `}`.
+    fn parse_control_flow_graph(&mut self) -> IRParseResult<IRControlFlowGraph> {
+        let mut control_flow_graph = IRControlFlowGraph::new();
+        let mut current: Option<IRBasicBlock> = None;
+        while !self.eat("}") {
+            if self.position >= self.source.len() {
+                return self.error("expected '}'".to_string());
+            }
+            let start = self.position;
+            let label = self.peek_identifier();
+            if !label.is_empty() && self.rest()[label.len()..].starts_with(':') {
+                self.position += label.len() + 1;
+                if control_flow_graph.basic_blocks.contains_key(label)
+                    || current.as_ref().is_some_and(|block| block.name == label)
+                {
+                    return self.error_at(start, format!("duplicate basic block '{}'", label));
+                }
+                if let Some(block) = current.take() {
+                    control_flow_graph.add_basic_block(Box::new(block));
+                }
+                current = Some(IRBasicBlock::new(label.to_string()));
+            } else {
+                let instruction = self.parse_instruction()?;
+                match current.as_mut() {
+                    Some(block) => block.instructions.push(instruction),
+                    None => {
+                        return self
+                            .error_at(start, "instruction outside of a basic block".to_string());
+                    }
+                }
+            }
+            self.expect_line_end()?;
+        }
+        if let Some(block) = current.take() {
+            control_flow_graph.add_basic_block(Box::new(block));
+        }
+        Ok(control_flow_graph)
+    }
+
+    fn parse_type(&mut self) -> IRParseResult<Box<dyn IRType>> {
+        let mut _type = self.parse_base_type()?;
+        loop {
+            if self.rest().starts_with('*') {
+                self.position += 1;
+                _type = Box::new(IRPointerType::new(_type));
+            } else if self.eat("(") {
+                _type = Box::new(self.parse_function_type(_type)?);
+            } else {
+                return Ok(_type);
+            }
+        }
+    }
+
+    fn parse_function_type(
+        &mut self,
+        return_type: Box<dyn IRType>,
+    ) -> IRParseResult<IRFunctionType> {
+        let mut params = vec![];
+        let mut variadic = false;
+        if !self.eat(")") {
+            loop {
+                if self.eat("...") {
+                    variadic = true;
+                    self.expect(")")?;
+                    break;
+                }
+                params.push(self.parse_type()?);
+                if !self.eat(",") {
+                    self.expect(")")?;
+                    break;
+                }
+            }
+        }
+        Ok(IRFunctionType::new(return_type, params, variadic))
+    }
+
+    fn parse_base_type(&mut self) -> IRParseResult<Box<dyn IRType>> {
+        if self.eat("[") {
+            let length = self.index()?;
+            self.expect_keyword("x")?;
+            let element = self.parse_type()?;
+            self.expect("]")?;
+            return Ok(Box::new(IRArrayType::new(element, length as u64)));
+        }
+        let structure = self.eat("%");
+        let start = self.position;
+        let name = self.identifier()?;
+        let _type: Box<dyn IRType> = match name.as_str() {
+            _ if structure => Box::new(IRStructureType::new(name)),
+            "float" => Box::new(IRFloatType::new()),
+            "double" => Box::new(IRDoubleType::new()),
+            "void" => Box::new(IRVoidType::new()),
+            _ => {
+                let unsigned = match name.chars().next() {
+                    Some('i') => false,
+                    Some('u') => true,
+                    _ => return self.error_at(start, format!("unknown type '{}'", name)),
+                };
+                let size = match &name[1..] {
+                    "1" => IRIntegerTypeSize::OneBit,
+                    "8" => IRIntegerTypeSize::OneByte,
+                    "16" => IRIntegerTypeSize::TwoBytes,
+                    "32" => IRIntegerTypeSize::FourBytes,
+                    "64" => IRIntegerTypeSize::EightBytes,
+                    _ => return self.error_at(start, format!("unknown type '{}'", name)),
+                };
+                Box::new(IRIntegerType::new(size, unsigned))
+            }
+        };
+        Ok(_type)
+    }
+
+    fn parse_operand(&mut self) -> IRParseResult<Box<dyn IROperand>> {
+        self.skip_spaces();
+        if self.eat("%") {
+            return Ok(Box::new(IRVirtualRegister::new(self.identifier()?)));
+        }
+        if self.eat("$") {
+            let index = self.index()?;
+            return match u32::try_from(index) {
+                Ok(index) => Ok(Box::new(IRConstant::new(index))),
+                Err(_) => self.error(format!("constant index {} out of range", index)),
+            };
+        }
+        if self.eat("`") {
+            let name = self.identifier()?;
+            self.expect("(")?;
+            self.expect("[")?;
+            let args =
+                self.comma_separated("]", |parser| Ok(parser.raw_until(&[',', ']']).to_string()))?;
+            self.expect(",")?;
+            self.expect("[")?;
+            let additional_operands = self.comma_separated("]", |parser| parser.parse_operand())?;
+            self.expect(")")?;
+            return Ok(Box::new(IRMacro::new(name, args, additional_operands)));
+        }
+        let start = self.position;
+        let keyword = self.identifier()?;
+        match keyword.as_str() {
+            "phi" => {
+                let _type = self.parse_type()?;
+                let mut labels = vec![];
+                let mut operands = vec![];
+                if self.eat("[") {
+                    loop {
+                        labels.push(self.identifier()?);
+                        self.expect(",")?;
+                        operands.push(self.parse_operand()?);
+                        self.expect("]")?;
+                        let checkpoint = self.position;
+                        if !(self.eat(",") && self.eat("[")) {
+                            self.position = checkpoint;
+                            break;
+                        }
+                    }
+                }
+                Ok(Box::new(IRPhi::new(_type, labels, operands)))
+            }
+            "IRVirtualTable" => {
+                self.expect("{")?;
+                self.expect_keyword("functions")?;
+                self.expect("=")?;
+                self.expect("{")?;
+                let functions = self.comma_separated("}", |parser| parser.identifier())?;
+                self.expect("}")?;
+                Ok(Box::new(IRVirtualTable::new(functions)))
+            }
+            "IRInterfaceTable" => {
+                self.expect("{")?;
+                self.expect_keyword("entries")?;
+                self.expect("=")?;
+                self.expect("{")?;
+                let entries = self.comma_separated("}", |parser| {
+                    parser.expect_keyword("Entry")?;
+                    parser.expect("[")?;
+                    parser.expect_keyword("name")?;
+                    parser.expect("=")?;
+                    let name = parser.identifier()?;
+                    parser.expect(",")?;
+                    parser.expect_keyword("functions")?;
+                    parser.expect("=")?;
+                    parser.expect("{")?;
+                    let functions = parser.comma_separated("}", |parser| parser.identifier())?;
+                    parser.expect("]")?;
+                    Ok(IRInterfaceTableEntry::new(name, functions))
+                })?;
+                self.expect("}")?;
+                Ok(Box::new(IRInterfaceTable::new(entries)))
+            }
+            _ => self.error_at(start, format!("expected operand, found '{}'", keyword)),
+        }
+    }
+
+    fn parse_target(&mut self) -> IRParseResult<Box<IRVirtualRegister>> {
+        self.expect("%")?;
+        Ok(Box::new(IRVirtualRegister::new(self.identifier()?)))
+    }
+
+    fn parse_invoke(
+        &mut self,
+        target: Option<Box<IRVirtualRegister>>,
+    ) -> IRParseResult<Box<dyn IRInstruction>> {
+        let return_type = self.parse_type()?;
+        let address = self.parse_operand()?;
+        let mut argument_types = vec![];
+        let mut arguments = vec![];
+        while self.eat(",") {
+            self.expect("[")?;
+            argument_types.push(self.parse_type()?);
+            self.expect(",")?;
+            arguments.push(self.parse_operand()?);
+            self.expect("]")?;
+        }
+        Ok(Box::new(IRInvoke::new(
+            return_type,
+            address,
+            argument_types,
+            arguments,
+            target,
+        )))
+    }
+
+    fn parse_instruction(&mut self) -> IRParseResult<Box<dyn IRInstruction>> {
+        self.skip_spaces();
+        if self.rest().starts_with('%') {
+            let target = self.parse_target()?;
+            self.expect("=")?;
+            return self.parse_assignment(target);
+        }
+        let start = self.position;
+        let keyword = self.identifier()?;
+        let (is_atomic, name) = match keyword.strip_prefix("atomic_") {
+            Some(name) => (true, name),
+            None => (false, keyword.as_str()),
+        };
+        let instruction: Box<dyn IRInstruction> = match (is_atomic, name) {
+            (false, "goto") => Box::new(IRGoto::new(self.identifier()?)),
+            (_, "conditional_jump") => {
+                let _type = self.parse_type()?;
+                let condition = self.parse_condition()?;
+                self.expect(",")?;
+                let operand1 = self.parse_operand()?;
+                self.expect(",")?;
+                let operand2 = if self.eat("#") {
+                    None
+                } else {
+                    let operand2 = self.parse_operand()?;
+                    self.expect(",")?;
+                    self.expect("#")?;
+                    Some(operand2)
+                };
+                let target = self.identifier()?;
+                Box::new(IRConditionalJump::new(
+                    is_atomic, _type, condition, operand1, operand2, target,
+                ))
+            }
+            (false, "nop") => Box::new(IRNoOperate::new()),
+            (false, "return") => {
+                if self.at_line_end() {
+                    Box::new(IRReturn::new(None))
+                } else {
+                    Box::new(IRReturn::new(Some(self.parse_operand()?)))
+                }
+            }
+            (false, "free") => Box::new(IRFree::new(self.parse_operand()?)),
+            (false, "set") => {
+                let _type = self.parse_type()?;
+                self.expect(",")?;
+                let address = self.parse_operand()?;
+                self.expect(",")?;
+                let value = self.parse_operand()?;
+                Box::new(IRSet::new(_type, address, value))
+            }
+            (false, "invoke") => self.parse_invoke(None)?,
+            (true, "increase") => {
+                let _type = self.parse_type()?;
+                Box::new(IRIncrease::new(_type, self.parse_operand()?, None))
+            }
+            (true, "decrease") => {
+                let _type = self.parse_type()?;
+                Box::new(IRDecrease::new(_type, self.parse_operand()?, None))
+            }
+            (false, "asm") => {
+                let code = self.parse_asm_code()?;
+                let mut types = vec![];
+                let mut resources = vec![];
+                let mut names = vec![];
+                while self.eat(",") {
+                    self.expect("[")?;
+                    types.push(self.parse_type()?);
+                    self.expect(",")?;
+                    resources.push(self.parse_operand()?);
+                    self.expect(",")?;
+                    names.push(self.raw_until(&[']']).to_string());
+                    self.expect("]")?;
+                }
+                Box::new(IRAsm::new(code, types, resources, names))
+            }
+            _ => return self.error_at(start, format!("unknown instruction '{}'", keyword)),
+        };
+        Ok(instruction)
+    }
+
+    fn parse_assignment(
+        &mut self,
+        target: Box<IRVirtualRegister>,
+    ) -> IRParseResult<Box<dyn IRInstruction>> {
+        self.skip_spaces();
+        let checkpoint = self.position;
+        let keyword = self.peek_identifier();
+        let (is_atomic, name) = match keyword.strip_prefix("atomic_") {
+            Some(name) => (true, name),
+            None => (false, keyword),
+        };
+        if let Some(operator) = Self::calculate_operator(name) {
+            self.position += keyword.len();
+            let _type = self.parse_type()?;
+            let operand1 = self.parse_operand()?;
+            self.expect(",")?;
+            let operand2 = self.parse_operand()?;
+            return Ok(Box::new(IRCalculate::new(
+                is_atomic, operator, _type, operand1, operand2, target,
+            )));
+        }
+        if let Some(kind) = Self::type_cast_kind(keyword) {
+            self.position += keyword.len();
+            let original_type = self.parse_type()?;
+            let source = self.parse_operand()?;
+            self.expect_keyword("to")?;
+            let target_type = self.parse_type()?;
+            return Ok(Box::new(IRTypeCast::new(
+                kind,
+                original_type,
+                source,
+                target_type,
+                target,
+            )));
+        }
+        self.position += keyword.len();
+        let instruction: Box<dyn IRInstruction> = match (is_atomic, name) {
+            (_, "not") => {
+                let _type = self.parse_type()?;
+                Box::new(IRNot::new(is_atomic, _type, self.parse_operand()?, target))
+            }
+            (_, "negate") => {
+                let _type = self.parse_type()?;
+                Box::new(IRNegate::new(
+                    is_atomic,
+                    _type,
+                    self.parse_operand()?,
+                    target,
+                ))
+            }
+            (false, "malloc") => Box::new(IRMalloc::new(self.parse_operand()?, target)),
+            (false, "realloc") => {
+                let ptr = self.parse_operand()?;
+                self.expect(",")?;
+                Box::new(IRRealloc::new(ptr, self.parse_operand()?, target))
+            }
+            (false, "get") => {
+                let _type = self.parse_type()?;
+                self.expect(",")?;
+                Box::new(IRGet::new(_type, self.parse_operand()?, target))
+            }
+            (false, "element_address") => {
+                let _type = self.parse_type()?;
+                self.expect(",")?;
+                let base = self.parse_operand()?;
+                self.expect(",")?;
+                self.expect("[")?;
+                let path = self.comma_separated("]", |parser| parser.parse_element_index())?;
+                Box::new(IRElementAddress::new(_type, base, path, target))
+            }
+            (false, "stack_alloc") => Box::new(IRStackAllocate::new(self.parse_operand()?, target)),
+            (false, "increase") => {
+                let _type = self.parse_type()?;
+                Box::new(IRIncrease::new(_type, self.parse_operand()?, Some(target)))
+            }
+            (false, "decrease") => {
+                let _type = self.parse_type()?;
+                Box::new(IRDecrease::new(_type, self.parse_operand()?, Some(target)))
+            }
+            (false, "invoke") => self.parse_invoke(Some(target))?,
+            _ => {
+                self.position = checkpoint;
+                Box::new(IRSetVirtualRegister::new(self.parse_operand()?, target))
+            }
+        };
+        Ok(instruction)
+    }
+
+    /// Operands index arrays; anything else names a structure field.
+    fn parse_element_index(&mut self) -> IRParseResult<IRElementIndex> {
+        self.skip_spaces();
+        if self.rest().starts_with(['%', '$', '`']) {
+            Ok(IRElementIndex::Index(self.parse_operand()?))
+        } else {
+            Ok(IRElementIndex::Field(self.identifier()?))
+        }
+    }
+
+    /// `IRAsm` prints its code without escaping, so the code ends at the first quote that is
+    /// followed by the end of the line or by a resource list.
+    fn parse_asm_code(&mut self) -> IRParseResult<String> {
+        self.expect("\"")?;
+        let rest = self.rest();
+        let line = &rest[..rest.find(['\n', '\r']).unwrap_or(rest.len())];
+        for (i, _) in line.match_indices('"') {
+            let after = line[i + 1..].trim_start_matches([' ', '\t']);
+            if after.is_empty() || after.starts_with(';') || after.starts_with(", [") {
+                self.position += i + 1;
+                return Ok(line[..i].to_string());
+            }
+        }
+        self.error("unterminated asm code".to_string())
+    }
+
+    fn parse_condition(&mut self) -> IRParseResult<IRCondition> {
+        let start = self.position;
+        let name = self.identifier()?;
+        let condition = match name.as_str() {
+            "e" => IRCondition::Equal,
+            "ne" => IRCondition::NotEqual,
+            "l" => IRCondition::Less,
+            "le" => IRCondition::LessEqual,
+            "g" => IRCondition::Greater,
+            "ge" => IRCondition::GreaterEqual,
+            "if_true" => IRCondition::IfTrue,
+            "if_false" => IRCondition::IfFalse,
+            _ => return self.error_at(start, format!("unknown condition '{}'", name)),
+        };
+        Ok(condition)
+    }
+
+    fn calculate_operator(name: &str) -> Option<IRCalculateOperator> {
+        let operator = match name {
+            "add" => IRCalculateOperator::ADD,
+            "sub" => IRCalculateOperator::SUB,
+            "mul" => IRCalculateOperator::MUL,
+            "div" => IRCalculateOperator::DIV,
+            "mod" => IRCalculateOperator::MOD,
+            "and" => IRCalculateOperator::AND,
+            "or" => IRCalculateOperator::OR,
+            "xor" => IRCalculateOperator::XOR,
+            "shl" => IRCalculateOperator::SHL,
+            "shr" => IRCalculateOperator::SHR,
+            "ushr" => IRCalculateOperator::USHR,
+            _ => return None,
+        };
+        Some(operator)
+    }
+
+    fn type_cast_kind(name: &str) -> Option<IRTypeCastKind> {
+        let kind = match name {
+            "zext" => IRTypeCastKind::ZeroExtend,
+            "sext" => IRTypeCastKind::SignExtend,
+            "trunc" => IRTypeCastKind::Truncate,
+            "itof" => IRTypeCastKind::IntToFloat,
+            "ftoi" => IRTypeCastKind::FloatToInt,
+            "fext" => IRTypeCastKind::FloatExtend,
+            "ftrunc" => IRTypeCastKind::FloatTruncate,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+/// Reads the value of a constant of `_type`. Integers may be written signed or unsigned, as long
+/// as they fit the width of the type. Arrays and structures are written `{value, ...}`, with the
+/// structure declared before the constant.
+fn constant_value(
+    text: &str,
+    _type: &dyn IRType,
+    structures: &IndexMap<String, Box<IRStructure>>,
+) -> Option<IRConstantValue> {
+    if text == "zeroinitializer" {
+        return Some(IRConstantValue::ZeroInitializer);
+    }
+    if let Some(elements) = text
+        .strip_prefix('{')
+        .and_then(|text| text.strip_suffix('}'))
+    {
+        let elements = aggregate_elements(elements)?;
+        let values = match IRTypeKind::of(_type) {
+            IRTypeKind::Array(array_type) => {
+                if elements.len() as u64 != array_type.length {
+                    return None;
+                }
+                elements
+                    .iter()
+                    .map(|element| constant_value(element, array_type.element.as_ref(), structures))
+                    .collect::<Option<_>>()?
+            }
+            IRTypeKind::Structure(structure_type) => {
+                let fields = &structures.get(&structure_type.name)?.fields;
+                if elements.len() != fields.len() {
+                    return None;
+                }
+                elements
+                    .iter()
+                    .zip(fields.iter())
+                    .map(|(element, field)| {
+                        constant_value(element, field._type.as_ref(), structures)
+                    })
+                    .collect::<Option<_>>()?
+            }
+            _ => return None,
+        };
+        return Some(IRConstantValue::Aggregate(values));
+    }
+    let value = match IRTypeKind::of(_type) {
+        IRTypeKind::Integer(integer) => {
+            let value = match text {
+                "true" => 1,
+                "false" => 0,
+                _ => integer_literal(text)?,
+            };
+            let bits = integer.size.clone() as u32;
+            if bits < 64 && !(-(1 << (bits - 1))..1 << bits).contains(&value) {
+                return None;
+            }
+            IRConstantValue::integer(&integer.size, value as i64)
+        }
+        IRTypeKind::Float(_) => IRConstantValue::F32(text.parse().ok()?),
+        IRTypeKind::Double(_) => IRConstantValue::F64(text.parse().ok()?),
+        IRTypeKind::Pointer(_) => match text {
+            "null" => IRConstantValue::Null,
+            _ => match text
+                .strip_prefix('"')
+                .and_then(|text| text.strip_suffix('"'))
+            {
+                Some(literal) => IRConstantValue::Bytes(unescape(literal)?),
+                None => IRConstantValue::I64(integer_literal(text)? as i64),
+            },
+        },
+        IRTypeKind::Void(_)
+        | IRTypeKind::Structure(_)
+        | IRTypeKind::Array(_)
+        | IRTypeKind::Function(_) => return None,
+    };
+    Some(value)
+}
+
+/// Cuts a constant value at the first `;` that is not inside a string literal.
+fn strip_comment(text: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return text[..index].trim_end(),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Splits the inside of an aggregate literal at the commas that are not nested in another
+/// aggregate or in a string.
+fn aggregate_elements(text: &str) -> Option<Vec<&str>> {
+    if text.trim().is_empty() {
+        return Some(vec![]);
+    }
+    let mut elements = vec![];
+    let mut start = 0;
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' if depth == MAX_NESTING_DEPTH => return None,
+            '{' => depth += 1,
+            '}' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                elements.push(text[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    if in_string || depth != 0 {
+        return None;
+    }
+    elements.push(text[start..].trim());
+    Some(elements)
+}
+
+/// A decimal or `0x` hexadecimal integer, in the range of `i64` and `u64` together.
+fn integer_literal(text: &str) -> Option<i128> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<u64>().ok()?,
+    } as i128;
+    let value = if negative { -value } else { value };
+    (value >= i64::MIN as i128).then_some(value)
+}
+
+fn unescape(literal: &str) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut chars = literal.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend(c.encode_utf8(&mut buffer).bytes());
+            continue;
+        }
+        match chars.next()? {
+            'n' => bytes.push(b'\n'),
+            't' => bytes.push(b'\t'),
+            'r' => bytes.push(b'\r'),
+            '0' => bytes.push(0),
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+            }
+            c => bytes.push(c as u8),
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::IRDumper;
+    use crate::ir::binary::{CAST_KINDS, CONDITIONS, OPERATORS};
+    use crate::ir::verify::{IRVerifyErrorKind, verify_module};
+    use std::mem;
+
+    fn i32() -> Box<dyn IRType> {
+        Box::new(IRIntegerType::new(IRIntegerTypeSize::FourBytes, false))
+    }
+
+    fn pointer(base: Box<dyn IRType>) -> Box<dyn IRType> {
+        Box::new(IRPointerType::new(base))
+    }
+
+    fn register(name: &str) -> Box<IRVirtualRegister> {
+        Box::new(IRVirtualRegister::new(name.to_string()))
+    }
+
+    fn constant(index: u32) -> Box<dyn IROperand> {
+        Box::new(IRConstant::new(index))
+    }
+
+    fn operands() -> Vec<Box<dyn IROperand>> {
+        vec![
+            register("x"),
+            constant(7),
+            Box::new(IRMacro::new(
+                "field_address".to_string(),
+                vec!["node".to_string(), "next".to_string()],
+                vec![register("i"), constant(0)],
+            )),
+            Box::new(IRMacro::new("function_address".to_string(), vec![], vec![])),
+            Box::new(IRPhi::new(
+                i32(),
+                vec!["entry".to_string(), "loop".to_string()],
+                vec![constant(0), register("next")],
+            )),
+            Box::new(IRVirtualTable::new(vec!["f".to_string(), "g".to_string()])),
+            Box::new(IRInterfaceTable::new(vec![
+                IRInterfaceTableEntry::new("A".to_string(), vec!["f".to_string()]),
+                IRInterfaceTableEntry::new("B".to_string(), vec![]),
+            ])),
+        ]
+    }
+
+    fn instructions() -> Vec<Box<dyn IRInstruction>> {
+        let byte = || -> Box<dyn IRType> {
+            Box::new(IRIntegerType::new(IRIntegerTypeSize::OneByte, true))
+        };
+        let mut instructions: Vec<Box<dyn IRInstruction>> = vec![
+            Box::new(IRGoto::new("loop".to_string())),
+            Box::new(IRNoOperate::new()),
+            Box::new(IRReturn::new(None)),
+            Box::new(IRReturn::new(Some(register("x")))),
+            Box::new(IRMalloc::new(constant(1), register("p"))),
+            Box::new(IRFree::new(register("p"))),
+            Box::new(IRRealloc::new(register("p"), constant(2), register("q"))),
+            Box::new(IRSet::new(i32(), register("p"), constant(0))),
+            Box::new(IRGet::new(pointer(i32()), register("p"), register("v"))),
+            Box::new(IRSetVirtualRegister::new(register("v"), register("w"))),
+            Box::new(IRStackAllocate::new(constant(3), register("s"))),
+            Box::new(IRElementAddress::new(
+                Box::new(IRStructureType::new("Node".to_string())),
+                register("node"),
+                vec![
+                    IRElementIndex::Field("items".to_string()),
+                    IRElementIndex::Index(register("i")),
+                    IRElementIndex::Index(constant(0)),
+                ],
+                register("a"),
+            )),
+            Box::new(IRIncrease::new(i32(), register("p"), None)),
+            Box::new(IRIncrease::new(i32(), register("x"), Some(register("y")))),
+            Box::new(IRDecrease::new(i32(), register("p"), None)),
+            Box::new(IRDecrease::new(i32(), register("x"), Some(register("y")))),
+            Box::new(IRInvoke::new(
+                Box::new(IRVoidType::new()),
+                register("f"),
+                vec![],
+                vec![],
+                None,
+            )),
+            Box::new(IRInvoke::new(
+                i32(),
+                Box::new(IRMacro::new(
+                    "function_address".to_string(),
+                    vec!["puts".to_string()],
+                    vec![],
+                )),
+                vec![pointer(byte()), Box::new(IRDoubleType::new())],
+                vec![constant(0), register("d")],
+                Some(register("r")),
+            )),
+            Box::new(IRAsm::new("nop".to_string(), vec![], vec![], vec![])),
+            Box::new(IRAsm::new(
+                "mov %0, \"x\"".to_string(),
+                vec![i32(), Box::new(IRFloatType::new())],
+                vec![register("a"), constant(1)],
+                vec!["r".to_string(), "m".to_string()],
+            )),
+        ];
+        for is_atomic in [false, true] {
+            for condition in CONDITIONS {
+                instructions.push(Box::new(IRConditionalJump::new(
+                    is_atomic,
+                    i32(),
+                    condition,
+                    register("x"),
+                    Some(constant(0)),
+                    "done".to_string(),
+                )));
+            }
+            instructions.push(Box::new(IRConditionalJump::new(
+                is_atomic,
+                byte(),
+                IRCondition::IfTrue,
+                register("flag"),
+                None,
+                "done".to_string(),
+            )));
+            for operator in OPERATORS {
+                instructions.push(Box::new(IRCalculate::new(
+                    is_atomic,
+                    operator,
+                    i32(),
+                    register("x"),
+                    constant(1),
+                    register("y"),
+                )));
+            }
+            instructions.push(Box::new(IRNot::new(
+                is_atomic,
+                i32(),
+                register("x"),
+                register("y"),
+            )));
+            instructions.push(Box::new(IRNegate::new(
+                is_atomic,
+                i32(),
+                register("x"),
+                register("y"),
+            )));
+        }
+        for kind in CAST_KINDS {
+            instructions.push(Box::new(IRTypeCast::new(
+                kind,
+                i32(),
+                register("x"),
+                Box::new(IRIntegerType::new(IRIntegerTypeSize::EightBytes, false)),
+                register("y"),
+            )));
+        }
+        instructions
+    }
+
+    #[test]
+    fn operands_round_trip_through_display() {
+        for operand in operands() {
+            let text = operand.to_string();
+            let parsed = parse_operand(&text).unwrap_or_else(|error| panic!("{}: {}", text, error));
+            assert_eq!(parsed.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn instructions_round_trip_through_display() {
+        for instruction in instructions() {
+            let text = instruction.to_string();
+            let parsed =
+                parse_instruction(&text).unwrap_or_else(|error| panic!("{}: {}", text, error));
+            assert_eq!(parsed.to_string(), text);
+            assert_eq!(
+                mem::discriminant(&parsed.kind()),
+                mem::discriminant(&instruction.kind()),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn types_round_trip_through_display() {
+        for text in [
+            "i1",
+            "u8",
+            "i64*",
+            "float",
+            "double**",
+            "void",
+            "%Node*",
+            "[4 x [2 x u16]]",
+            "i32 (i8*, ...)*",
+        ] {
+            let parsed = parse_type(text).unwrap_or_else(|error| panic!("{}: {}", text, error));
+            assert_eq!(parsed.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn modules_round_trip_through_the_dumper() {
+        let source = "\
+structure Node {
+    i32 value
+    %Node* next
+}
+constant $0 = i32 42 ; the answer
+constant $1 = i8* \"a;\\\"b\" ; not \"part\" of it
+constant $2 = %Node* null
+global counter, size=$0
+global table, values=[IRVirtualTable{functions={main}}, $1]
+init {
+entry:
+    return
+}
+function i32 main(i32 argc) {
+    local i64 tmp
+entry:
+    %t = add i32 %argc, $0
+    conditional_jump i32 l, %t, $0, #done
+more:
+    goto done
+done:
+    return %t
+}
+vtable Node = [main]
+itable Node = [main]
+entry_point main
+";
+        let ir_module = parse_module(source).unwrap();
+        assert_eq!(
+            ir_module.constant_pool.entries[0].value,
+            IRConstantValue::I32(42)
+        );
+        assert_eq!(
+            ir_module.constant_pool.entries[1].value,
+            IRConstantValue::Bytes(b"a;\"b".to_vec())
+        );
+        assert_eq!(ir_module.functions["main"].arguments_count, 1);
+        assert_eq!(ir_module.functions["main"].fields.len(), 2);
+        assert_eq!(ir_module.entry_point.as_deref(), Some("main"));
+        let text = IRDumper::dump_to_string(&ir_module);
+        assert_eq!(
+            IRDumper::dump_to_string(&parse_module(&text).unwrap()),
+            text
+        );
+    }
+
+    #[test]
+    fn reports_the_line_and_column_of_errors() {
+        let error_at = |source: &str| {
+            let error = parse_module(source).unwrap_err();
+            (error.line, error.column)
+        };
+        assert_eq!(
+            error_at("function i32 main() {\nentry:\n    %x = add i32 $0 $1\n}\n"),
+            (3, 21)
+        );
+        assert_eq!(
+            error_at("function void main() {\nentry:\n    frobnicate %x\n}\n"),
+            (3, 5)
+        );
+        assert_eq!(error_at("constant $0 = i32 4294967296\n"), (1, 19));
+        assert_eq!(error_at("structure S {\n    i32 a\n    q17 b\n}\n"), (3, 5));
+        assert_eq!(error_at("\n\n  nonsense\n"), (3, 3));
+
+        let error = parse_instruction("%x = get i32, ").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 15));
+        assert_eq!(error.to_string(), format!("1:15: {}", error.message));
+    }
+
+    const TABLE: &str = "\
+structure Entry {
+    i32 key
+    i8* name
+}
+
+constant $0 = [3 x i32] {1, -2, 3}
+constant $1 = [2 x %Entry] {{1, \"one\"}, zeroinitializer}
+constant $2 = [1 x [2 x double]] {{1.5, -0.0}}
+
+global table, values=[$0]
+";
+
+    #[test]
+    fn array_constants_round_trip_and_verify() {
+        let ir_module = parse_module(TABLE).unwrap();
+        assert_eq!(
+            ir_module.constant_pool.entries[0].value,
+            IRConstantValue::Aggregate(vec![
+                IRConstantValue::I32(1),
+                IRConstantValue::I32(-2),
+                IRConstantValue::I32(3),
+            ])
+        );
+        assert_eq!(verify_module(&ir_module), Ok(()));
+        let text = IRDumper::dump_to_string(&ir_module);
+        assert_eq!(
+            IRDumper::dump_to_string(&parse_module(&text).unwrap()),
+            text
+        );
+    }
+
+    #[test]
+    fn array_constants_must_fit_their_type() {
+        for value in ["{1, 2}", "{1, 2, 3, 4}", "{1, {2}, 3}", "{1, 2, 3"] {
+            let source = format!("constant $0 = [3 x i32] {}\n", value);
+            assert!(parse_module(&source).is_err(), "{}", value);
+        }
+
+        let mut ir_module = parse_module(TABLE).unwrap();
+        ir_module.constant_pool.entries[0].value =
+            IRConstantValue::Aggregate(vec![IRConstantValue::I32(1)]);
+        let errors = verify_module(&ir_module).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, IRVerifyErrorKind::ConstantTypeMismatch(0));
+    }
+}
@@ -0,0 +1,340 @@
+//! Walking a module by `&mut` for passes that transform it.
+//!
+//! `IRVisitorMut` mirrors `IRVisitor` down to the level of instructions and operands. Each
+//! `visit_*_mut` method defaults to the matching `walk_*_mut` function, so a pass overrides the
+//! nodes it cares about and calls the walk function to keep descending. Instructions and basic
+//! blocks are replaced through `IRRewrite`; operands are replaced by assigning to the slot.
+//!
+//! To find out what an instruction or operand is, a pass accepts an `IRVisitor` on it.
+
+use crate::ir::IRModule;
+use crate::ir::base::{IRBasicBlock, IRControlFlowGraph, IRFunction, IRGlobalData};
+use crate::ir::instruction::IRInstruction;
+use crate::ir::operand::IROperand;
+
+/// What becomes of a visited instruction or basic block.
+#[derive(Debug)]
+pub enum IRRewrite<T> {
+    Keep,
+    /// Replaces the node with these, in order; none removes it.
+    Replace(Vec<T>),
+}
+
+impl<T> IRRewrite<T> {
+    pub fn remove() -> Self {
+        IRRewrite::Replace(vec![])
+    }
+}
+
+pub trait IRVisitorMut {
+    fn visit_module_mut(&mut self, ir_module: &mut IRModule) {
+        walk_module_mut(self, ir_module);
+    }
+    fn visit_global_data_mut(&mut self, ir_global_data: &mut IRGlobalData) {
+        walk_global_data_mut(self, ir_global_data);
+    }
+    fn visit_function_mut(&mut self, ir_function: &mut IRFunction) {
+        walk_function_mut(self, ir_function);
+    }
+    fn visit_control_flow_graph_mut(&mut self, ir_control_flow_graph: &mut IRControlFlowGraph) {
+        walk_control_flow_graph_mut(self, ir_control_flow_graph);
+    }
+    /// Blocks put in place of this one are not visited. Their names must not be used by other
+    /// blocks of the graph.
+    fn visit_basic_block_mut(
+        &mut self,
+        ir_basic_block: &mut IRBasicBlock,
+    ) -> IRRewrite<Box<IRBasicBlock>> {
+        walk_basic_block_mut(self, ir_basic_block);
+        IRRewrite::Keep
+    }
+    /// Instructions put in place of this one are not visited.
+    fn visit_instruction_mut(
+        &mut self,
+        ir_instruction: &mut Box<dyn IRInstruction>,
+    ) -> IRRewrite<Box<dyn IRInstruction>> {
+        walk_instruction_mut(self, ir_instruction);
+        IRRewrite::Keep
+    }
+    fn visit_operand_mut(&mut self, ir_operand: &mut Box<dyn IROperand>) {
+        walk_operand_mut(self, ir_operand);
+    }
+}
+
+/// Visits the global data, the global init section and then every function.
+pub fn walk_module_mut<V: IRVisitorMut + ?Sized>(visitor: &mut V, ir_module: &mut IRModule) {
+    for ir_global_data in ir_module.global_data_section.data.iter_mut() {
+        visitor.visit_global_data_mut(ir_global_data);
+    }
+    visitor.visit_control_flow_graph_mut(&mut ir_module.global_init_section);
+    for ir_function in ir_module.functions.values_mut() {
+        visitor.visit_function_mut(ir_function);
+    }
+}
+
+pub fn walk_global_data_mut<V: IRVisitorMut + ?Sized>(
+    visitor: &mut V,
+    ir_global_data: &mut IRGlobalData,
+) {
+    if let Some(size) = ir_global_data.size.as_mut() {
+        visitor.visit_operand_mut(size);
+    }
+    for value in ir_global_data.values.iter_mut().flatten() {
+        visitor.visit_operand_mut(value);
+    }
+}
+
+pub fn walk_function_mut<V: IRVisitorMut + ?Sized>(visitor: &mut V, ir_function: &mut IRFunction) {
+    visitor.visit_control_flow_graph_mut(&mut ir_function.control_flow_graph);
+}
+
+/// Visits the blocks in layout order, applies their rewrites, and then recomputes every edge
+/// since any jump may have changed.
+pub fn walk_control_flow_graph_mut<V: IRVisitorMut + ?Sized>(
+    visitor: &mut V,
+    ir_control_flow_graph: &mut IRControlFlowGraph,
+) {
+    let mut index = 0;
+    while index < ir_control_flow_graph.basic_blocks.len() {
+        let (_, ir_basic_block) = ir_control_flow_graph
+            .basic_blocks
+            .get_index_mut(index)
+            .unwrap();
+        match visitor.visit_basic_block_mut(ir_basic_block) {
+            IRRewrite::Keep => index += 1,
+            IRRewrite::Replace(ir_basic_blocks) => {
+                ir_control_flow_graph.basic_blocks.shift_remove_index(index);
+                for ir_basic_block in ir_basic_blocks {
+                    ir_control_flow_graph.basic_blocks.shift_insert(
+                        index,
+                        ir_basic_block.name.clone(),
+                        ir_basic_block,
+                    );
+                    index += 1;
+                }
+            }
+        }
+    }
+    ir_control_flow_graph.build_edges();
+}
+
+pub fn walk_basic_block_mut<V: IRVisitorMut + ?Sized>(
+    visitor: &mut V,
+    ir_basic_block: &mut IRBasicBlock,
+) {
+    let mut index = 0;
+    while index < ir_basic_block.instructions.len() {
+        match visitor.visit_instruction_mut(&mut ir_basic_block.instructions[index]) {
+            IRRewrite::Keep => index += 1,
+            IRRewrite::Replace(ir_instructions) => {
+                let count = ir_instructions.len();
+                ir_basic_block
+                    .instructions
+                    .splice(index..=index, ir_instructions);
+                index += count;
+            }
+        }
+    }
+}
+
+pub fn walk_instruction_mut<V: IRVisitorMut + ?Sized>(
+    visitor: &mut V,
+    ir_instruction: &mut Box<dyn IRInstruction>,
+) {
+    for ir_operand in ir_instruction.operands_mut() {
+        visitor.visit_operand_mut(ir_operand);
+    }
+}
+
+/// Visits the operands nested in this one, such as the values of a phi.
+pub fn walk_operand_mut<V: IRVisitorMut + ?Sized>(
+    visitor: &mut V,
+    ir_operand: &mut Box<dyn IROperand>,
+) {
+    for ir_operand in ir_operand.operands_mut() {
+        visitor.visit_operand_mut(ir_operand);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::instruction::{IRGoto, IRNoOperate};
+    use crate::ir::parser::parse_module;
+
+    const MODULE: &str = "\
+constant $0 = i64 1
+global counter, size=$0
+global table, values=[`global_data_address([counter], [])]
+init {
+entry:
+    %c = add i64 $0, $0
+    return
+}
+function i64 f(i64 n) {
+entry:
+    %pn = `field_address([n], [])
+    %n = get i64, %pn
+    nop
+    goto loop
+loop:
+    %i = phi i64 [entry, $0], [loop, %next]
+    %next = add i64 %i, %n
+    conditional_jump i64 l, %next, %n, #loop
+done:
+    return %next
+}
+";
+
+    fn instructions(ir_basic_block: &IRBasicBlock) -> Vec<String> {
+        ir_basic_block
+            .instructions
+            .iter()
+            .map(|ir_instruction| ir_instruction.to_string())
+            .collect()
+    }
+
+    struct IROperandCollector {
+        operands: Vec<String>,
+    }
+
+    impl IRVisitorMut for IROperandCollector {
+        fn visit_operand_mut(&mut self, ir_operand: &mut Box<dyn IROperand>) {
+            self.operands.push(ir_operand.to_string());
+            walk_operand_mut(self, ir_operand);
+        }
+    }
+
+    /// Replaces every `nop` with `replacement` copies of it, counting the instructions visited.
+    struct IRNoOperateRewriter {
+        replacement: usize,
+        visited: usize,
+    }
+
+    impl IRVisitorMut for IRNoOperateRewriter {
+        fn visit_instruction_mut(
+            &mut self,
+            ir_instruction: &mut Box<dyn IRInstruction>,
+        ) -> IRRewrite<Box<dyn IRInstruction>> {
+            self.visited += 1;
+            if ir_instruction.downcast_ref::<IRNoOperate>().is_none() {
+                return IRRewrite::Keep;
+            }
+            IRRewrite::Replace(
+                (0..self.replacement)
+                    .map(|_| Box::new(IRNoOperate::new()) as Box<dyn IRInstruction>)
+                    .collect(),
+            )
+        }
+    }
+
+    #[test]
+    fn operands_are_visited_everywhere_nested_ones_included() {
+        let mut ir_module = parse_module(MODULE).unwrap();
+        let mut collector = IROperandCollector { operands: vec![] };
+        collector.visit_module_mut(&mut ir_module);
+        assert_eq!(
+            collector.operands,
+            [
+                // The global data, then the init section...
+                "$0",
+                "`global_data_address([counter], [])",
+                "$0",
+                "$0",
+                // ...then the function, with a phi's values after the phi.
+                "`field_address([n], [])",
+                "%pn",
+                "phi i64 [entry, $0], [loop, %next]",
+                "$0",
+                "%next",
+                "%i",
+                "%n",
+                "%next",
+                "%n",
+                "%next",
+            ]
+        );
+    }
+
+    #[test]
+    fn replaced_instructions_are_not_visited_again() {
+        let mut ir_module = parse_module(MODULE).unwrap();
+        let mut rewriter = IRNoOperateRewriter {
+            replacement: 2,
+            visited: 0,
+        };
+        rewriter.visit_module_mut(&mut ir_module);
+        assert_eq!(rewriter.visited, 10);
+        let entry = &ir_module.functions["f"].control_flow_graph.basic_blocks["entry"];
+        assert_eq!(
+            instructions(entry),
+            [
+                "%pn = `field_address([n], [])",
+                "%n = get i64, %pn",
+                "nop",
+                "nop",
+                "goto loop"
+            ]
+        );
+    }
+
+    #[test]
+    fn removed_instructions_leave_the_rest_in_order() {
+        let mut ir_module = parse_module(MODULE).unwrap();
+        IRNoOperateRewriter {
+            replacement: 0,
+            visited: 0,
+        }
+        .visit_module_mut(&mut ir_module);
+        let entry = &ir_module.functions["f"].control_flow_graph.basic_blocks["entry"];
+        assert_eq!(
+            instructions(entry),
+            [
+                "%pn = `field_address([n], [])",
+                "%n = get i64, %pn",
+                "goto loop"
+            ]
+        );
+    }
+
+    /// Splits the jump at the end of `entry` off into a block of its own.
+    struct IREntrySplitter;
+
+    impl IRVisitorMut for IREntrySplitter {
+        fn visit_basic_block_mut(
+            &mut self,
+            ir_basic_block: &mut IRBasicBlock,
+        ) -> IRRewrite<Box<IRBasicBlock>> {
+            if ir_basic_block.name != "entry" {
+                return IRRewrite::Keep;
+            }
+            let mut head = IRBasicBlock::new("entry".to_string());
+            head.instructions = std::mem::take(&mut ir_basic_block.instructions);
+            let jump = head.instructions.pop().unwrap();
+            head.instructions
+                .push(Box::new(IRGoto::new("jump".to_string())));
+            let mut tail = IRBasicBlock::new("jump".to_string());
+            tail.instructions.push(jump);
+            IRRewrite::Replace(vec![Box::new(head), Box::new(tail)])
+        }
+    }
+
+    #[test]
+    fn replaced_blocks_take_their_place_and_the_edges_are_rebuilt() {
+        let mut ir_module = parse_module(MODULE).unwrap();
+        IREntrySplitter.visit_module_mut(&mut ir_module);
+        let graph = &ir_module.functions["f"].control_flow_graph;
+        assert_eq!(
+            graph.basic_blocks.keys().collect::<Vec<_>>(),
+            ["entry", "jump", "loop", "done"]
+        );
+        assert_eq!(graph.successors("entry"), ["jump"]);
+        assert_eq!(graph.successors("jump"), ["loop"]);
+        assert_eq!(graph.predecessors("loop"), ["jump", "loop"]);
+        assert_eq!(instructions(&graph.basic_blocks["jump"]), ["goto loop"]);
+        // The global init section was split as well, ending in a block of its own.
+        let init = &ir_module.global_init_section;
+        assert_eq!(init.successors("entry"), ["jump"]);
+        assert_eq!(instructions(&init.basic_blocks["jump"]), ["return"]);
+    }
+}
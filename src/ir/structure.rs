@@ -27,7 +27,7 @@ impl IRNode for IRField {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IRStructure {
     pub name: String,
     pub fields: Vec<IRField>,
@@ -56,4 +56,4 @@ impl IRNode for IRStructure {
     fn accept(&self, visitor: &dyn IRVisitor) {
         visitor.visit_structure(self);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,1159 @@
+use crate::ir::base::{IRCondition, IRControlFlowGraph, IRFunction, IRNode};
+use crate::ir::instruction::{
+    IRAsm, IRCalculate, IRCalculateOperator, IRConditionalJump, IRDecrease, IRElementAddress,
+    IRElementIndex, IRFree, IRGet, IRIncrease, IRInvoke, IRMalloc, IRNegate, IRNot, IRRealloc,
+    IRReturn, IRSet, IRSetVirtualRegister, IRStackAllocate, IRTypeCast, IRTypeCastKind,
+};
+use crate::ir::operand::{IRConstant, IRMacro, IROperand, IRPhi, IRVirtualRegister};
+use crate::ir::types::{
+    IRArrayType, IRFunctionType, IRIntegerType, IRIntegerTypeSize, IRPointerType, IRStructureType,
+    IRType, IRTypeKind, IRVoidType,
+};
+use crate::ir::verify::IRLocation;
+use crate::ir::{IRModule, IRVisitor};
+use indexmap::IndexMap;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+/// The type of every virtual register defined in one control flow graph, and of the fields of
+/// its function that `field_address` points to.
+#[derive(Clone, Debug, Default)]
+pub struct IRFunctionTypes {
+    pub fields: IndexMap<String, Box<dyn IRType>>,
+    pub registers: IndexMap<String, Box<dyn IRType>>,
+}
+
+impl IRFunctionTypes {
+    pub fn register_type(&self, name: &str) -> Option<&dyn IRType> {
+        self.registers.get(name).map(|_type| _type.as_ref())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct IRModuleTypes {
+    pub global_init: IRFunctionTypes,
+    pub functions: IndexMap<String, IRFunctionTypes>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IRTypeErrorKind {
+    OperandMismatch {
+        operand: String,
+        expected: String,
+        found: String,
+    },
+    ExpectedAddress {
+        operand: String,
+        found: String,
+    },
+    ExpectedInteger {
+        operand: String,
+        found: String,
+    },
+    InvalidOperator {
+        operator: String,
+        _type: String,
+    },
+    InvalidCast {
+        kind: IRTypeCastKind,
+        from: String,
+        to: String,
+    },
+    ConflictingDefinition {
+        register: String,
+        first: String,
+        second: String,
+    },
+    ReturnMismatch {
+        expected: String,
+        found: Option<String>,
+    },
+    VoidTarget(String),
+    SignatureMismatch {
+        expected: String,
+        found: String,
+    },
+}
+
+impl fmt::Display for IRTypeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IRTypeErrorKind::OperandMismatch {
+                operand,
+                expected,
+                found,
+            } => write!(
+                f,
+                "operand {} has type {}, expected {}",
+                operand, found, expected
+            ),
+            IRTypeErrorKind::ExpectedAddress { operand, found } => {
+                write!(
+                    f,
+                    "operand {} has type {}, expected an address",
+                    operand, found
+                )
+            }
+            IRTypeErrorKind::ExpectedInteger { operand, found } => {
+                write!(
+                    f,
+                    "operand {} has type {}, expected an integer",
+                    operand, found
+                )
+            }
+            IRTypeErrorKind::InvalidOperator { operator, _type } => {
+                write!(f, "operator {} cannot be applied to {}", operator, _type)
+            }
+            IRTypeErrorKind::InvalidCast { kind, from, to } => {
+                write!(f, "{} cannot convert {} to {}", kind, from, to)
+            }
+            IRTypeErrorKind::ConflictingDefinition {
+                register,
+                first,
+                second,
+            } => write!(
+                f,
+                "register %{} is defined as both {} and {}",
+                register, first, second
+            ),
+            IRTypeErrorKind::ReturnMismatch { expected, found } => match found {
+                Some(found) => write!(f, "returns {}, expected {}", found, expected),
+                None => write!(f, "returns nothing, expected {}", expected),
+            },
+            IRTypeErrorKind::VoidTarget(register) => {
+                write!(f, "register %{} is assigned a void value", register)
+            }
+            IRTypeErrorKind::SignatureMismatch { expected, found } => {
+                write!(f, "invoke as {} does not match {}", found, expected)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IRTypeError {
+    pub location: IRLocation,
+    pub kind: IRTypeErrorKind,
+}
+
+impl fmt::Display for IRTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.kind)
+    }
+}
+
+impl std::error::Error for IRTypeError {}
+
+/// Infers the type of each virtual register of a function, or of the global init section when
+/// `ir_function` is `None`, from the instruction that defines it.
+///
+/// Registers copied from other registers are resolved iteratively, so definitions may appear in
+/// any block order. Registers defined with conflicting types keep their first type.
+pub fn infer_register_types(
+    ir_module: &IRModule,
+    ir_function: Option<&IRFunction>,
+) -> IRFunctionTypes {
+    let ir_control_flow_graph = match ir_function {
+        Some(ir_function) => &ir_function.control_flow_graph,
+        None => &ir_module.global_init_section,
+    };
+    let fields = ir_function
+        .iter()
+        .flat_map(|ir_function| ir_function.fields.iter())
+        .map(|ir_field| (ir_field.name.clone(), ir_field._type.clone()))
+        .collect();
+    let inference = IRTypeInference {
+        ir_module,
+        fields: &fields,
+        registers: RefCell::new(IndexMap::new()),
+        changed: Cell::new(false),
+    };
+    loop {
+        inference.changed.set(false);
+        for ir_basic_block in ir_control_flow_graph.basic_blocks.values() {
+            for ir_instruction in ir_basic_block.instructions.iter() {
+                ir_instruction.accept(&inference);
+            }
+        }
+        if !inference.changed.get() {
+            break;
+        }
+    }
+    let registers = inference.registers.into_inner();
+    IRFunctionTypes { fields, registers }
+}
+
+/// Returns the type of an operand, if it can be determined: registers through `types`,
+/// constants through their pool entry, phis through their declared type, `field_address` as a
+/// pointer to the field and the other address macros as untyped pointers.
+pub fn operand_type(
+    ir_module: &IRModule,
+    types: &IRFunctionTypes,
+    operand: &dyn IROperand,
+) -> Option<Box<dyn IRType>> {
+    operand_type_in(ir_module, &types.fields, &types.registers, operand)
+}
+
+/// Infers register types for every function and checks that operands match the types their
+/// instructions declare.
+///
+/// Integer types must match exactly, including signedness. Pointers are interchangeable with one
+/// another and with 64-bit integers, since addresses are routinely computed with integer
+/// arithmetic.
+pub fn type_check_module(ir_module: &IRModule) -> Result<IRModuleTypes, Vec<IRTypeError>> {
+    let mut errors = vec![];
+    let global_init = infer_register_types(ir_module, None);
+    errors.extend(check_control_flow_graph(
+        ir_module,
+        None,
+        &IRVoidType::new(),
+        &ir_module.global_init_section,
+        &global_init,
+    ));
+    let mut functions = IndexMap::new();
+    for ir_function in ir_module.functions.values() {
+        let types = infer_register_types(ir_module, Some(ir_function));
+        errors.extend(check_function(ir_module, ir_function, &types));
+        functions.insert(ir_function.name.clone(), types);
+    }
+    if errors.is_empty() {
+        Ok(IRModuleTypes {
+            global_init,
+            functions,
+        })
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks one function against register types previously inferred for it.
+pub fn check_function(
+    ir_module: &IRModule,
+    ir_function: &IRFunction,
+    types: &IRFunctionTypes,
+) -> Vec<IRTypeError> {
+    check_control_flow_graph(
+        ir_module,
+        Some(&ir_function.name),
+        ir_function.return_type.as_ref(),
+        &ir_function.control_flow_graph,
+        types,
+    )
+}
+
+fn check_control_flow_graph(
+    ir_module: &IRModule,
+    function: Option<&str>,
+    return_type: &dyn IRType,
+    ir_control_flow_graph: &IRControlFlowGraph,
+    types: &IRFunctionTypes,
+) -> Vec<IRTypeError> {
+    let checker = IRTypeChecker {
+        ir_module,
+        types,
+        return_type,
+        location: RefCell::new(IRLocation::Module),
+        errors: RefCell::new(vec![]),
+    };
+    for (name, ir_basic_block) in ir_control_flow_graph.basic_blocks.iter() {
+        for (index, ir_instruction) in ir_basic_block.instructions.iter().enumerate() {
+            checker.location.replace(IRLocation::Instruction {
+                function: function.map(|function| function.to_string()),
+                basic_block: name.clone(),
+                index,
+            });
+            ir_instruction.accept(&checker);
+        }
+    }
+    checker.errors.into_inner()
+}
+
+/// Finds the first structure, array or function type used by value rather than behind a pointer,
+/// for the targets that keep every value in a register or an 8-byte slot and so cannot hold one.
+pub(crate) fn find_aggregate_value(ir_module: &IRModule) -> Option<(IRLocation, String)> {
+    let finder = IRAggregateValueFinder {
+        found: RefCell::new(None),
+    };
+    let found = |location: IRLocation| {
+        finder
+            .found
+            .take()
+            .map(|_type: Box<dyn IRType>| (location, _type.to_string()))
+    };
+    for entry in ir_module.constant_pool.entries.iter() {
+        entry._type.accept(&finder);
+        if let Some(found) = found(IRLocation::Module) {
+            return Some(found);
+        }
+    }
+    let control_flow_graphs = std::iter::once((None, ir_module.global_init_section.as_ref()))
+        .chain(ir_module.functions.values().map(|ir_function| {
+            (
+                Some(ir_function.as_ref()),
+                ir_function.control_flow_graph.as_ref(),
+            )
+        }));
+    for (ir_function, ir_control_flow_graph) in control_flow_graphs {
+        if let Some(ir_function) = ir_function {
+            ir_function.return_type.accept(&finder);
+            for ir_field in ir_function.fields.iter() {
+                ir_field._type.accept(&finder);
+            }
+            if let Some(found) = found(IRLocation::Function(ir_function.name.clone())) {
+                return Some(found);
+            }
+        }
+        for (name, ir_basic_block) in ir_control_flow_graph.basic_blocks.iter() {
+            for (index, ir_instruction) in ir_basic_block.instructions.iter().enumerate() {
+                ir_instruction.accept(&finder);
+                let location = IRLocation::Instruction {
+                    function: ir_function.map(|ir_function| ir_function.name.clone()),
+                    basic_block: name.clone(),
+                    index,
+                };
+                if let Some(found) = found(location) {
+                    return Some(found);
+                }
+            }
+        }
+    }
+    None
+}
+
+struct IRAggregateValueFinder {
+    found: RefCell<Option<Box<dyn IRType>>>,
+}
+
+impl IRVisitor for IRAggregateValueFinder {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_pointer_type(&self, _ir_pointer_type: &IRPointerType) {}
+    /// The type an element address points into is not a value.
+    fn visit_element_address(&self, _ir_element_address: &IRElementAddress) {}
+    fn visit_structure_type(&self, ir_structure_type: &IRStructureType) {
+        self.found
+            .borrow_mut()
+            .get_or_insert_with(|| Box::new(ir_structure_type.clone()));
+    }
+    fn visit_array_type(&self, ir_array_type: &IRArrayType) {
+        self.found
+            .borrow_mut()
+            .get_or_insert_with(|| Box::new(ir_array_type.clone()));
+    }
+    fn visit_function_type(&self, ir_function_type: &IRFunctionType) {
+        self.found
+            .borrow_mut()
+            .get_or_insert_with(|| Box::new(ir_function_type.clone()));
+    }
+}
+
+fn untyped_pointer() -> Box<dyn IRType> {
+    Box::new(IRPointerType::new(Box::new(IRVoidType::new())))
+}
+
+/// A pointer to the element the path leads to; an invalid path is left to the verifier.
+fn element_address_type(
+    ir_module: &IRModule,
+    ir_element_address: &IRElementAddress,
+) -> Box<dyn IRType> {
+    ir_element_address.element_type(ir_module).map_or_else(
+        |_| untyped_pointer(),
+        |_type| Box::new(IRPointerType::new(_type)),
+    )
+}
+
+fn is_address(_type: &dyn IRType) -> bool {
+    match IRTypeKind::of(_type) {
+        IRTypeKind::Pointer(_) => true,
+        IRTypeKind::Integer(integer) => integer.size == IRIntegerTypeSize::EightBytes,
+        _ => false,
+    }
+}
+
+fn is_compatible(expected: &dyn IRType, found: &dyn IRType) -> bool {
+    if expected == found {
+        return true;
+    }
+    let expected_kind = IRTypeKind::of(expected);
+    let found_kind = IRTypeKind::of(found);
+    (expected_kind.is_pointer() && is_address(found))
+        || (found_kind.is_pointer() && is_address(expected))
+}
+
+fn integer_bits(_type: &dyn IRType) -> Option<usize> {
+    match IRTypeKind::of(_type) {
+        IRTypeKind::Integer(integer) => Some(integer.size as usize),
+        IRTypeKind::Pointer(_) => Some(64),
+        _ => None,
+    }
+}
+
+fn floating_point_bits(_type: &dyn IRType) -> Option<usize> {
+    match IRTypeKind::of(_type) {
+        IRTypeKind::Float(_) => Some(32),
+        IRTypeKind::Double(_) => Some(64),
+        _ => None,
+    }
+}
+
+fn operand_type_in(
+    ir_module: &IRModule,
+    fields: &IndexMap<String, Box<dyn IRType>>,
+    registers: &IndexMap<String, Box<dyn IRType>>,
+    operand: &dyn IROperand,
+) -> Option<Box<dyn IRType>> {
+    let typer = IROperandTyper {
+        ir_module,
+        fields,
+        registers,
+        _type: RefCell::new(None),
+    };
+    operand.accept(&typer);
+    typer._type.into_inner()
+}
+
+struct IROperandTyper<'a> {
+    ir_module: &'a IRModule,
+    fields: &'a IndexMap<String, Box<dyn IRType>>,
+    registers: &'a IndexMap<String, Box<dyn IRType>>,
+    _type: RefCell<Option<Box<dyn IRType>>>,
+}
+
+impl IRVisitor for IROperandTyper<'_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_virtual_register(&self, ir_virtual_register: &IRVirtualRegister) {
+        self._type
+            .replace(self.registers.get(&ir_virtual_register.name).cloned());
+    }
+    fn visit_constant(&self, ir_constant: &IRConstant) {
+        let entry = self
+            .ir_module
+            .constant_pool
+            .entries
+            .get(ir_constant.index as usize);
+        self._type.replace(entry.map(|entry| entry._type.clone()));
+    }
+    fn visit_phi(&self, ir_phi: &IRPhi) {
+        self._type.replace(Some(ir_phi._type.clone()));
+    }
+    fn visit_macro(&self, ir_macro: &IRMacro) {
+        let _type = match ir_macro.name.as_str() {
+            // Functions of the module are typed by their signature, native ones are not known.
+            "function_address" => ir_macro
+                .args
+                .first()
+                .and_then(|name| self.ir_module.function_type(name))
+                .map_or_else(untyped_pointer, |function_type| {
+                    Box::new(IRPointerType::new(Box::new(function_type)))
+                }),
+            "field_address" => ir_macro
+                .args
+                .first()
+                .and_then(|name| self.fields.get(name))
+                .map_or_else(untyped_pointer, |_type| {
+                    Box::new(IRPointerType::new(_type.clone()))
+                }),
+            // Global data is only sized, not typed.
+            "global_data_address" => untyped_pointer(),
+            _ => return,
+        };
+        self._type.replace(Some(_type));
+    }
+}
+
+struct IRTypeInference<'a> {
+    ir_module: &'a IRModule,
+    fields: &'a IndexMap<String, Box<dyn IRType>>,
+    registers: RefCell<IndexMap<String, Box<dyn IRType>>>,
+    changed: Cell<bool>,
+}
+
+impl IRTypeInference<'_> {
+    fn define(&self, target: &IRVirtualRegister, _type: Box<dyn IRType>) {
+        let mut registers = self.registers.borrow_mut();
+        if !registers.contains_key(&target.name) {
+            registers.insert(target.name.clone(), _type);
+            self.changed.set(true);
+        }
+    }
+}
+
+impl IRVisitor for IRTypeInference<'_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_calculate(&self, ir_calculate: &IRCalculate) {
+        self.define(&ir_calculate.target, ir_calculate._type.clone());
+    }
+    fn visit_not(&self, ir_not: &IRNot) {
+        self.define(&ir_not.target, ir_not._type.clone());
+    }
+    fn visit_negate(&self, ir_negate: &IRNegate) {
+        self.define(&ir_negate.target, ir_negate._type.clone());
+    }
+    fn visit_malloc(&self, ir_malloc: &IRMalloc) {
+        self.define(&ir_malloc.target, untyped_pointer());
+    }
+    fn visit_realloc(&self, ir_realloc: &IRRealloc) {
+        self.define(&ir_realloc.target, untyped_pointer());
+    }
+    fn visit_get(&self, ir_get: &IRGet) {
+        self.define(&ir_get.target, ir_get._type.clone());
+    }
+    fn visit_set_virtual_register(&self, ir_set_virtual_register: &IRSetVirtualRegister) {
+        let source = operand_type_in(
+            self.ir_module,
+            self.fields,
+            &self.registers.borrow(),
+            ir_set_virtual_register.source.as_ref(),
+        );
+        if let Some(source) = source {
+            self.define(&ir_set_virtual_register.target, source);
+        }
+    }
+    fn visit_invoke(&self, ir_invoke: &IRInvoke) {
+        if let Some(target) = ir_invoke.target.as_ref() {
+            self.define(target, ir_invoke.return_type.clone());
+        }
+    }
+    fn visit_increase(&self, ir_increase: &IRIncrease) {
+        if let Some(target) = ir_increase.target.as_ref() {
+            self.define(target, ir_increase._type.clone());
+        }
+    }
+    fn visit_decrease(&self, ir_decrease: &IRDecrease) {
+        if let Some(target) = ir_decrease.target.as_ref() {
+            self.define(target, ir_decrease._type.clone());
+        }
+    }
+    fn visit_stack_allocate(&self, ir_stack_allocate: &IRStackAllocate) {
+        self.define(&ir_stack_allocate.target, untyped_pointer());
+    }
+    fn visit_element_address(&self, ir_element_address: &IRElementAddress) {
+        self.define(
+            &ir_element_address.target,
+            element_address_type(self.ir_module, ir_element_address),
+        );
+    }
+    fn visit_type_cast(&self, ir_type_cast: &IRTypeCast) {
+        self.define(&ir_type_cast.target, ir_type_cast.target_type.clone());
+    }
+}
+
+struct IRTypeChecker<'a> {
+    ir_module: &'a IRModule,
+    types: &'a IRFunctionTypes,
+    return_type: &'a dyn IRType,
+    location: RefCell<IRLocation>,
+    errors: RefCell<Vec<IRTypeError>>,
+}
+
+impl IRTypeChecker<'_> {
+    fn report(&self, kind: IRTypeErrorKind) {
+        self.errors.borrow_mut().push(IRTypeError {
+            location: self.location.borrow().clone(),
+            kind,
+        });
+    }
+
+    fn type_of(&self, operand: &dyn IROperand) -> Option<Box<dyn IRType>> {
+        operand.accept(self);
+        operand_type_in(
+            self.ir_module,
+            &self.types.fields,
+            &self.types.registers,
+            operand,
+        )
+    }
+
+    fn expect_type(&self, operand: &dyn IROperand, expected: &dyn IRType) {
+        if let Some(found) = self.type_of(operand)
+            && !is_compatible(expected, found.as_ref())
+        {
+            self.report(IRTypeErrorKind::OperandMismatch {
+                operand: operand.to_string(),
+                expected: expected.to_string(),
+                found: found.to_string(),
+            });
+        }
+    }
+
+    fn expect_address(&self, operand: &dyn IROperand) {
+        if let Some(found) = self.type_of(operand)
+            && !is_address(found.as_ref())
+        {
+            self.report(IRTypeErrorKind::ExpectedAddress {
+                operand: operand.to_string(),
+                found: found.to_string(),
+            });
+        }
+    }
+
+    /// An address through which a value of `_type` is read or written. Untyped pointers and
+    /// integers may point to anything.
+    fn expect_address_of(&self, operand: &dyn IROperand, _type: &dyn IRType) {
+        let Some(found) = self.type_of(operand) else {
+            return;
+        };
+        let points_to = match IRTypeKind::of(found.as_ref()) {
+            IRTypeKind::Pointer(pointer) => match IRTypeKind::of(pointer.base.as_ref()) {
+                IRTypeKind::Void(_) => true,
+                _ => is_compatible(_type, pointer.base.as_ref()),
+            },
+            _ => is_address(found.as_ref()),
+        };
+        if !points_to {
+            self.report(IRTypeErrorKind::OperandMismatch {
+                operand: operand.to_string(),
+                expected: format!("{}*", _type),
+                found: found.to_string(),
+            });
+        }
+    }
+
+    fn expect_integer(&self, operand: &dyn IROperand) {
+        if let Some(found) = self.type_of(operand)
+            && !IRTypeKind::of(found.as_ref()).is_integer()
+        {
+            self.report(IRTypeErrorKind::ExpectedInteger {
+                operand: operand.to_string(),
+                found: found.to_string(),
+            });
+        }
+    }
+
+    /// Atomic instructions read and write their first operand through memory.
+    fn expect_value(&self, is_atomic: bool, operand: &dyn IROperand, expected: &dyn IRType) {
+        if is_atomic {
+            self.expect_address_of(operand, expected);
+        } else {
+            self.expect_type(operand, expected);
+        }
+    }
+
+    fn check_definition(&self, target: &IRVirtualRegister, _type: &dyn IRType) {
+        if matches!(IRTypeKind::of(_type), IRTypeKind::Void(_)) {
+            self.report(IRTypeErrorKind::VoidTarget(target.name.clone()));
+            return;
+        }
+        if let Some(first) = self.types.register_type(&target.name)
+            && first != _type
+        {
+            self.report(IRTypeErrorKind::ConflictingDefinition {
+                register: target.name.clone(),
+                first: first.to_string(),
+                second: _type.to_string(),
+            });
+        }
+    }
+
+    /// Calls through a pointer to a function type must pass what the function takes.
+    fn check_signature(&self, ir_invoke: &IRInvoke) {
+        let address = ir_invoke.address.as_ref();
+        let Some(IRTypeKind::Pointer(pointer)) = operand_type_in(
+            self.ir_module,
+            &self.types.fields,
+            &self.types.registers,
+            address,
+        )
+        .map(|_type| IRTypeKind::of(_type.as_ref())) else {
+            return;
+        };
+        let IRTypeKind::Function(function) = IRTypeKind::of(pointer.base.as_ref()) else {
+            return;
+        };
+        let count_fits = if function.variadic {
+            ir_invoke.argument_types.len() >= function.params.len()
+        } else {
+            ir_invoke.argument_types.len() == function.params.len()
+        };
+        let fits = count_fits
+            && is_compatible(
+                function.return_type.as_ref(),
+                ir_invoke.return_type.as_ref(),
+            )
+            && function
+                .params
+                .iter()
+                .zip(ir_invoke.argument_types.iter())
+                .all(|(param, argument_type)| {
+                    is_compatible(param.as_ref(), argument_type.as_ref())
+                });
+        if !fits {
+            let found = IRFunctionType::new(
+                ir_invoke.return_type.clone(),
+                ir_invoke.argument_types.clone(),
+                false,
+            );
+            self.report(IRTypeErrorKind::SignatureMismatch {
+                expected: function.to_string(),
+                found: found.to_string(),
+            });
+        }
+    }
+
+    fn check_operator(&self, operator: String, _type: &dyn IRType, allow_floating_point: bool) {
+        let valid = match IRTypeKind::of(_type) {
+            IRTypeKind::Integer(_) => true,
+            IRTypeKind::Float(_) | IRTypeKind::Double(_) => allow_floating_point,
+            _ => false,
+        };
+        if !valid {
+            self.report(IRTypeErrorKind::InvalidOperator {
+                operator,
+                _type: _type.to_string(),
+            });
+        }
+    }
+
+    fn check_cast(&self, ir_type_cast: &IRTypeCast) {
+        let from = ir_type_cast.original_type.as_ref();
+        let to = ir_type_cast.target_type.as_ref();
+        let valid = match ir_type_cast.kind {
+            IRTypeCastKind::ZeroExtend | IRTypeCastKind::SignExtend => {
+                matches!((integer_bits(from), integer_bits(to)), (Some(f), Some(t)) if f <= t)
+            }
+            IRTypeCastKind::Truncate => {
+                matches!((integer_bits(from), integer_bits(to)), (Some(f), Some(t)) if f >= t)
+            }
+            IRTypeCastKind::IntToFloat => {
+                integer_bits(from).is_some() && floating_point_bits(to).is_some()
+            }
+            IRTypeCastKind::FloatToInt => {
+                floating_point_bits(from).is_some() && integer_bits(to).is_some()
+            }
+            IRTypeCastKind::FloatExtend => matches!(
+                (floating_point_bits(from), floating_point_bits(to)),
+                (Some(f), Some(t)) if f <= t
+            ),
+            IRTypeCastKind::FloatTruncate => matches!(
+                (floating_point_bits(from), floating_point_bits(to)),
+                (Some(f), Some(t)) if f >= t
+            ),
+        };
+        if !valid {
+            self.report(IRTypeErrorKind::InvalidCast {
+                kind: ir_type_cast.kind,
+                from: from.to_string(),
+                to: to.to_string(),
+            });
+        }
+    }
+}
+
+impl IRVisitor for IRTypeChecker<'_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_conditional_jump(&self, ir_conditional_jump: &IRConditionalJump) {
+        let _type = ir_conditional_jump._type.as_ref();
+        self.expect_value(
+            ir_conditional_jump.is_atomic,
+            ir_conditional_jump.operand1.as_ref(),
+            _type,
+        );
+        if let Some(operand2) = ir_conditional_jump.operand2.as_ref() {
+            self.expect_type(operand2.as_ref(), _type);
+        }
+        if !matches!(
+            ir_conditional_jump.condition,
+            IRCondition::IfTrue | IRCondition::IfFalse
+        ) && matches!(IRTypeKind::of(_type), IRTypeKind::Void(_))
+        {
+            self.report(IRTypeErrorKind::InvalidOperator {
+                operator: ir_conditional_jump.condition.to_string(),
+                _type: _type.to_string(),
+            });
+        }
+    }
+    fn visit_return(&self, ir_return: &IRReturn) {
+        let returns_void = matches!(IRTypeKind::of(self.return_type), IRTypeKind::Void(_));
+        match ir_return.operand.as_ref() {
+            Some(operand) if returns_void => self.report(IRTypeErrorKind::ReturnMismatch {
+                expected: self.return_type.to_string(),
+                found: Some(
+                    self.type_of(operand.as_ref())
+                        .map_or("unknown".to_string(), |found| found.to_string()),
+                ),
+            }),
+            Some(operand) => self.expect_type(operand.as_ref(), self.return_type),
+            None if !returns_void => self.report(IRTypeErrorKind::ReturnMismatch {
+                expected: self.return_type.to_string(),
+                found: None,
+            }),
+            None => {}
+        }
+    }
+    fn visit_calculate(&self, ir_calculate: &IRCalculate) {
+        let _type = ir_calculate._type.as_ref();
+        let allow_floating_point = matches!(
+            ir_calculate.operator,
+            IRCalculateOperator::ADD
+                | IRCalculateOperator::SUB
+                | IRCalculateOperator::MUL
+                | IRCalculateOperator::DIV
+                | IRCalculateOperator::MOD
+        );
+        self.check_operator(
+            ir_calculate.operator.to_string(),
+            _type,
+            allow_floating_point,
+        );
+        self.expect_value(
+            ir_calculate.is_atomic,
+            ir_calculate.operand1.as_ref(),
+            _type,
+        );
+        self.expect_type(ir_calculate.operand2.as_ref(), _type);
+        self.check_definition(&ir_calculate.target, _type);
+    }
+    fn visit_not(&self, ir_not: &IRNot) {
+        self.check_operator("not".to_string(), ir_not._type.as_ref(), false);
+        self.expect_value(
+            ir_not.is_atomic,
+            ir_not.operand.as_ref(),
+            ir_not._type.as_ref(),
+        );
+        self.check_definition(&ir_not.target, ir_not._type.as_ref());
+    }
+    fn visit_negate(&self, ir_negate: &IRNegate) {
+        self.check_operator("negate".to_string(), ir_negate._type.as_ref(), true);
+        self.expect_value(
+            ir_negate.is_atomic,
+            ir_negate.operand.as_ref(),
+            ir_negate._type.as_ref(),
+        );
+        self.check_definition(&ir_negate.target, ir_negate._type.as_ref());
+    }
+    fn visit_malloc(&self, ir_malloc: &IRMalloc) {
+        self.expect_integer(ir_malloc.size.as_ref());
+        self.check_definition(&ir_malloc.target, untyped_pointer().as_ref());
+    }
+    fn visit_free(&self, ir_free: &IRFree) {
+        self.expect_address(ir_free.ptr.as_ref());
+    }
+    fn visit_realloc(&self, ir_realloc: &IRRealloc) {
+        self.expect_address(ir_realloc.ptr.as_ref());
+        self.expect_integer(ir_realloc.size.as_ref());
+        self.check_definition(&ir_realloc.target, untyped_pointer().as_ref());
+    }
+    fn visit_get(&self, ir_get: &IRGet) {
+        self.expect_address_of(ir_get.address.as_ref(), ir_get._type.as_ref());
+        self.check_definition(&ir_get.target, ir_get._type.as_ref());
+    }
+    fn visit_set(&self, ir_set: &IRSet) {
+        self.expect_address_of(ir_set.address.as_ref(), ir_set._type.as_ref());
+        self.expect_type(ir_set.value.as_ref(), ir_set._type.as_ref());
+    }
+    fn visit_set_virtual_register(&self, ir_set_virtual_register: &IRSetVirtualRegister) {
+        if let Some(source) = self.type_of(ir_set_virtual_register.source.as_ref()) {
+            self.check_definition(&ir_set_virtual_register.target, source.as_ref());
+        }
+    }
+    fn visit_invoke(&self, ir_invoke: &IRInvoke) {
+        self.expect_address(ir_invoke.address.as_ref());
+        self.check_signature(ir_invoke);
+        for (argument_type, argument) in ir_invoke
+            .argument_types
+            .iter()
+            .zip(ir_invoke.arguments.iter())
+        {
+            self.expect_type(argument.as_ref(), argument_type.as_ref());
+        }
+        if let Some(target) = ir_invoke.target.as_ref() {
+            self.check_definition(target, ir_invoke.return_type.as_ref());
+        }
+    }
+    fn visit_increase(&self, ir_increase: &IRIncrease) {
+        self.check_step(
+            ir_increase._type.as_ref(),
+            ir_increase.operand.as_ref(),
+            ir_increase.target.as_deref(),
+            "increase",
+        );
+    }
+    fn visit_decrease(&self, ir_decrease: &IRDecrease) {
+        self.check_step(
+            ir_decrease._type.as_ref(),
+            ir_decrease.operand.as_ref(),
+            ir_decrease.target.as_deref(),
+            "decrease",
+        );
+    }
+    fn visit_stack_allocate(&self, ir_stack_allocate: &IRStackAllocate) {
+        self.expect_integer(ir_stack_allocate.size.as_ref());
+        self.check_definition(&ir_stack_allocate.target, untyped_pointer().as_ref());
+    }
+    fn visit_element_address(&self, ir_element_address: &IRElementAddress) {
+        self.expect_address(ir_element_address.base.as_ref());
+        let index_type = IRIntegerType::new(IRIntegerTypeSize::EightBytes, false);
+        for index in ir_element_address.path.iter() {
+            if let IRElementIndex::Index(operand) = index {
+                self.expect_type(operand.as_ref(), &index_type);
+            }
+        }
+        self.check_definition(
+            &ir_element_address.target,
+            element_address_type(self.ir_module, ir_element_address).as_ref(),
+        );
+    }
+    fn visit_type_cast(&self, ir_type_cast: &IRTypeCast) {
+        self.expect_type(
+            ir_type_cast.source.as_ref(),
+            ir_type_cast.original_type.as_ref(),
+        );
+        self.check_cast(ir_type_cast);
+        self.check_definition(&ir_type_cast.target, ir_type_cast.target_type.as_ref());
+    }
+    fn visit_asm(&self, ir_asm: &IRAsm) {
+        for (_type, resource) in ir_asm.types.iter().zip(ir_asm.resources.iter()) {
+            self.expect_type(resource.as_ref(), _type.as_ref());
+        }
+    }
+    fn visit_phi(&self, ir_phi: &IRPhi) {
+        for operand in ir_phi.operands.iter() {
+            self.expect_type(operand.as_ref(), ir_phi._type.as_ref());
+        }
+    }
+    fn visit_macro(&self, ir_macro: &IRMacro) {
+        for operand in ir_macro.additional_operands.iter() {
+            self.visit_dyn(operand.as_ref());
+        }
+    }
+}
+
+impl IRTypeChecker<'_> {
+    /// `increase`/`decrease` with a target step a value; without one they step memory in place.
+    fn check_step(
+        &self,
+        _type: &dyn IRType,
+        operand: &dyn IROperand,
+        target: Option<&IRVirtualRegister>,
+        operator: &str,
+    ) {
+        let kind = IRTypeKind::of(_type);
+        if !kind.is_integer() && !kind.is_pointer() {
+            self.report(IRTypeErrorKind::InvalidOperator {
+                operator: operator.to_string(),
+                _type: _type.to_string(),
+            });
+        }
+        match target {
+            Some(target) => {
+                self.expect_type(operand, _type);
+                self.check_definition(target, _type);
+            }
+            None => self.expect_address_of(operand, _type),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parser::parse_module;
+
+    fn errors(source: &str) -> Vec<IRTypeErrorKind> {
+        match type_check_module(&parse_module(source).unwrap()) {
+            Ok(_) => vec![],
+            Err(errors) => errors.into_iter().map(|error| error.kind).collect(),
+        }
+    }
+
+    /// A function `f` taking an `i64 a` and a `double d`, with `body` after loading both.
+    fn function(body: &str) -> String {
+        format!(
+            "\
+constant $0 = i32 0
+constant $1 = u32 1
+constant $2 = i64 8
+constant $3 = double 0.5
+function i64 f(i64 a, double d) {{
+entry:
+    %pa = `field_address([a], [])
+    %a = get i64, %pa
+    %pd = `field_address([d], [])
+    %d = get double, %pd
+{body}
+    return %a
+}}
+"
+        )
+    }
+
+    fn mismatch(operand: &str, expected: &str, found: &str) -> IRTypeErrorKind {
+        IRTypeErrorKind::OperandMismatch {
+            operand: operand.to_string(),
+            expected: expected.to_string(),
+            found: found.to_string(),
+        }
+    }
+
+    fn cast(kind: IRTypeCastKind, from: &str, to: &str) -> IRTypeErrorKind {
+        IRTypeErrorKind::InvalidCast {
+            kind,
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    #[test]
+    fn field_addresses_point_to_the_field_type() {
+        let ir_module = parse_module(&function("    %g = `global_data_address([a], [])")).unwrap();
+        let types = infer_register_types(&ir_module, Some(&ir_module.functions["f"]));
+        let register = |name: &str| types.register_type(name).map(|_type| _type.to_string());
+        assert_eq!(register("pa").as_deref(), Some("i64*"));
+        assert_eq!(register("pd").as_deref(), Some("double*"));
+        assert_eq!(register("d").as_deref(), Some("double"));
+        assert_eq!(register("g").as_deref(), Some("void*"));
+        // The global init section has no fields.
+        let types = infer_register_types(&ir_module, None);
+        assert!(types.fields.is_empty());
+    }
+
+    #[test]
+    fn loads_and_stores_follow_the_field_type() {
+        assert_eq!(errors(&function("    set double, %pd, $3")), []);
+        assert_eq!(
+            errors(&function("    %x = get i32, %pa")),
+            [mismatch("%pa", "i32*", "i64*")]
+        );
+        assert_eq!(
+            errors(&function("    set i64, %pd, %a")),
+            [mismatch("%pd", "i64*", "double*")]
+        );
+        assert_eq!(
+            errors(&function("    atomic_increase i32 %pa")),
+            [mismatch("%pa", "i32*", "i64*")]
+        );
+        // Untyped pointers and integers may point to anything.
+        assert_eq!(
+            errors(&function(
+                "    %p = malloc $2\n    set i32, %p, $0\n    %x = get double, %a"
+            )),
+            []
+        );
+    }
+
+    #[test]
+    fn operands_must_match_the_declared_type() {
+        assert_eq!(errors(&function("    %x = add i32 $0, $0")), []);
+        // Pointers and 64-bit integers are interchangeable.
+        assert_eq!(errors(&function("    %x = add i64 %a, %pa")), []);
+        assert_eq!(
+            errors(&function("    %x = add i32 $0, $1")),
+            [mismatch("$1", "i32", "u32")]
+        );
+        assert_eq!(
+            errors(&function("    %x = add i32 %pa, $0")),
+            [mismatch("%pa", "i32", "i64*")]
+        );
+        assert_eq!(
+            errors(&function("    %p = malloc %d")),
+            [IRTypeErrorKind::ExpectedInteger {
+                operand: "%d".to_string(),
+                found: "double".to_string(),
+            }]
+        );
+        assert_eq!(
+            errors(&function("    free $0")),
+            [IRTypeErrorKind::ExpectedAddress {
+                operand: "$0".to_string(),
+                found: "i32".to_string(),
+            }]
+        );
+        assert_eq!(
+            errors(&function("    %x = shl double %d, %d")),
+            [IRTypeErrorKind::InvalidOperator {
+                operator: "shl".to_string(),
+                _type: "double".to_string(),
+            }]
+        );
+        assert_eq!(
+            errors(&function("    %a = add i32 $0, $0")),
+            [IRTypeErrorKind::ConflictingDefinition {
+                register: "a".to_string(),
+                first: "i64".to_string(),
+                second: "i32".to_string(),
+            }]
+        );
+        assert_eq!(
+            errors(&function("    return %d")),
+            [mismatch("%d", "i64", "double")]
+        );
+    }
+
+    #[test]
+    fn invokes_follow_the_signature_of_the_function() {
+        let invoke = |arguments: &str| {
+            function(&format!(
+                "    %r = invoke i64 `function_address([f], []){}",
+                arguments
+            ))
+        };
+        assert_eq!(errors(&invoke(", [i64, %a], [double, %d]")), []);
+        assert_eq!(
+            errors(&invoke(", [i64, %a]")),
+            [IRTypeErrorKind::SignatureMismatch {
+                expected: "i64 (i64, double)".to_string(),
+                found: "i64 (i64)".to_string(),
+            }]
+        );
+        assert_eq!(
+            errors(&function(
+                "    %r = invoke void `function_address([puts], []), [i64, %a]"
+            )),
+            [IRTypeErrorKind::VoidTarget("r".to_string())]
+        );
+    }
+
+    #[test]
+    fn casts_convert_between_compatible_kinds() {
+        let valid = "\
+    %b = sext i64 %a to i64
+    %c = zext i32 $0 to i64
+    %e = trunc i64 %a to i32
+    %g = trunc i64* %pa to i64
+    %h = itof i64 %a to double
+    %i = ftoi double %d to i32
+    %j = ftrunc double %d to float
+    %k = fext float %j to double";
+        assert_eq!(errors(&function(valid)), []);
+        assert_eq!(
+            errors(&function("    %x = sext i64 %a to i32")),
+            [cast(IRTypeCastKind::SignExtend, "i64", "i32")]
+        );
+        assert_eq!(
+            errors(&function("    %x = zext double %d to i64")),
+            [cast(IRTypeCastKind::ZeroExtend, "double", "i64")]
+        );
+        assert_eq!(
+            errors(&function("    %x = trunc i32 $0 to i64")),
+            [cast(IRTypeCastKind::Truncate, "i32", "i64")]
+        );
+        assert_eq!(
+            errors(&function("    %x = itof double %d to double")),
+            [cast(IRTypeCastKind::IntToFloat, "double", "double")]
+        );
+        assert_eq!(
+            errors(&function("    %x = ftoi i64 %a to i32")),
+            [cast(IRTypeCastKind::FloatToInt, "i64", "i32")]
+        );
+        assert_eq!(
+            errors(&function("    %x = fext double %d to float")),
+            [cast(IRTypeCastKind::FloatExtend, "double", "float")]
+        );
+        assert_eq!(
+            errors(&function("    %x = ftrunc i64 %a to float")),
+            [cast(IRTypeCastKind::FloatTruncate, "i64", "float")]
+        );
+        // The source must also have the type the cast converts from.
+        assert_eq!(
+            errors(&function("    %x = sext i32 %a to i64")),
+            [mismatch("%a", "i32", "i64")]
+        );
+    }
+}
@@ -1,7 +1,9 @@
 use clone_dyn::clone_dyn;
 
-use crate::ir::IRVisitor;
 use crate::ir::base::IRNode;
+use crate::ir::structure::IRStructure;
+use crate::ir::{IRModule, IRVisitor};
+use std::cell::RefCell;
 use std::fmt::{self, Debug};
 use std::fmt::{Display, Formatter};
 
@@ -18,6 +20,105 @@ pub enum IRIntegerTypeSize {
 #[clone_dyn]
 pub trait IRType: IRNode + Debug {}
 
+/// Types are compared structurally through their canonical textual form.
+impl<'a> PartialEq for dyn IRType + 'a {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+/// A closed view of an `IRType` trait object, for code that needs to branch on the kind of type.
+#[derive(Clone, Debug)]
+pub enum IRTypeKind {
+    Integer(IRIntegerType),
+    Float(IRFloatType),
+    Double(IRDoubleType),
+    Void(IRVoidType),
+    Pointer(IRPointerType),
+    Structure(IRStructureType),
+    Array(IRArrayType),
+    Function(IRFunctionType),
+}
+
+impl IRTypeKind {
+    pub fn of(_type: &dyn IRType) -> Self {
+        let collector = IRTypeKindCollector {
+            kind: RefCell::new(None),
+        };
+        _type.accept(&collector);
+        collector
+            .kind
+            .into_inner()
+            .expect("every IRType is visited through one of the type visitor methods")
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, IRTypeKind::Integer(_))
+    }
+
+    pub fn is_floating_point(&self) -> bool {
+        matches!(self, IRTypeKind::Float(_) | IRTypeKind::Double(_))
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, IRTypeKind::Pointer(_))
+    }
+
+    pub fn is_structure(&self) -> bool {
+        matches!(self, IRTypeKind::Structure(_))
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self, IRTypeKind::Array(_))
+    }
+
+    pub fn is_function(&self) -> bool {
+        matches!(self, IRTypeKind::Function(_))
+    }
+}
+
+struct IRTypeKindCollector {
+    kind: RefCell<Option<IRTypeKind>>,
+}
+
+impl IRVisitor for IRTypeKindCollector {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_integer_type(&self, ir_integer_type: &IRIntegerType) {
+        self.kind
+            .replace(Some(IRTypeKind::Integer(ir_integer_type.clone())));
+    }
+    fn visit_float_type(&self, ir_float_type: &IRFloatType) {
+        self.kind
+            .replace(Some(IRTypeKind::Float(ir_float_type.clone())));
+    }
+    fn visit_double_type(&self, ir_double_type: &IRDoubleType) {
+        self.kind
+            .replace(Some(IRTypeKind::Double(ir_double_type.clone())));
+    }
+    fn visit_pointer_type(&self, ir_pointer_type: &IRPointerType) {
+        self.kind
+            .replace(Some(IRTypeKind::Pointer(ir_pointer_type.clone())));
+    }
+    fn visit_void_type(&self, ir_void_type: &IRVoidType) {
+        self.kind
+            .replace(Some(IRTypeKind::Void(ir_void_type.clone())));
+    }
+    fn visit_structure_type(&self, ir_structure_type: &IRStructureType) {
+        self.kind
+            .replace(Some(IRTypeKind::Structure(ir_structure_type.clone())));
+    }
+    fn visit_array_type(&self, ir_array_type: &IRArrayType) {
+        self.kind
+            .replace(Some(IRTypeKind::Array(ir_array_type.clone())));
+    }
+    fn visit_function_type(&self, ir_function_type: &IRFunctionType) {
+        self.kind
+            .replace(Some(IRTypeKind::Function(ir_function_type.clone())));
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct IRIntegerType {
     pub size: IRIntegerTypeSize,
@@ -50,6 +151,11 @@ impl IRFloatType {
         Self {}
     }
 }
+impl Default for IRFloatType {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Display for IRFloatType {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
@@ -69,6 +175,11 @@ impl IRDoubleType {
         Self {}
     }
 }
+impl Default for IRDoubleType {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Display for IRDoubleType {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "double")
@@ -88,6 +199,11 @@ impl IRVoidType {
         Self {}
     }
 }
+impl Default for IRVoidType {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Display for IRVoidType {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "void")
@@ -121,3 +237,90 @@ impl IRNode for IRPointerType {
     }
 }
 impl IRType for IRPointerType {}
+/// A structure used by value, referring to `IRModule::structures` by name.
+#[derive(Clone, Debug)]
+pub struct IRStructureType {
+    pub name: String,
+}
+
+impl IRStructureType {
+    pub fn new(name: String) -> Self {
+        Self { name }
+    }
+
+    /// The structure this type refers to, if the module defines it.
+    pub fn structure<'a>(&self, ir_module: &'a IRModule) -> Option<&'a IRStructure> {
+        ir_module.get_struct(&self.name)
+    }
+}
+impl Display for IRStructureType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "%{}", self.name)
+    }
+}
+impl IRNode for IRStructureType {
+    fn accept(&self, visitor: &dyn IRVisitor) {
+        visitor.visit_structure_type(self);
+    }
+}
+impl IRType for IRStructureType {}
+/// A fixed number of elements laid out back to back.
+#[derive(Clone, Debug)]
+pub struct IRArrayType {
+    pub element: Box<dyn IRType>,
+    pub length: u64,
+}
+
+impl IRArrayType {
+    pub fn new(element: Box<dyn IRType>, length: u64) -> Self {
+        Self { element, length }
+    }
+}
+impl Display for IRArrayType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "[{} x {}]", self.length, self.element)
+    }
+}
+impl IRNode for IRArrayType {
+    fn accept(&self, visitor: &dyn IRVisitor) {
+        visitor.visit_array_type(self);
+    }
+}
+impl IRType for IRArrayType {}
+/// The signature of a function. It has no values of its own; code refers to functions through
+/// pointers to it.
+#[derive(Clone, Debug)]
+pub struct IRFunctionType {
+    pub return_type: Box<dyn IRType>,
+    pub params: Vec<Box<dyn IRType>>,
+    pub variadic: bool,
+}
+
+impl IRFunctionType {
+    pub fn new(return_type: Box<dyn IRType>, params: Vec<Box<dyn IRType>>, variadic: bool) -> Self {
+        Self {
+            return_type,
+            params,
+            variadic,
+        }
+    }
+}
+impl Display for IRFunctionType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut params = self
+            .params
+            .iter()
+            .map(|param| param.to_string())
+            .collect::<Vec<_>>();
+        if self.variadic {
+            params.push("...".to_string());
+        }
+        write!(f, "{} ({})", self.return_type, params.join(", "))
+    }
+}
+impl IRNode for IRFunctionType {
+    fn accept(&self, visitor: &dyn IRVisitor) {
+        visitor.visit_function_type(self);
+    }
+}
+impl IRType for IRFunctionType {}
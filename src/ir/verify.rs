@@ -0,0 +1,686 @@
+use crate::ir::base::{IRControlFlowGraph, IRFunction, IRNode};
+use crate::ir::instruction::{
+    IRConditionalJump, IRElementAddress, IRElementIndex, IRElementPathError, IRGoto, IRInvoke,
+    IRReturn,
+};
+use crate::ir::operand::{IRConstant, IRMacro, IRPhi};
+use crate::ir::types::IRStructureType;
+use crate::ir::{IRModule, IRVisitor};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IRLocation {
+    Module,
+    Structure(String),
+    Function(String),
+    GlobalData(String),
+    BasicBlock {
+        function: Option<String>,
+        basic_block: String,
+    },
+    Instruction {
+        function: Option<String>,
+        basic_block: String,
+        index: usize,
+    },
+}
+
+impl fmt::Display for IRLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let write_function = |f: &mut fmt::Formatter<'_>, function: &Option<String>| match function
+        {
+            Some(function) => write!(f, "function '{}'", function),
+            None => write!(f, "global init section"),
+        };
+        match self {
+            IRLocation::Module => write!(f, "module"),
+            IRLocation::Structure(name) => write!(f, "structure '{}'", name),
+            IRLocation::Function(name) => write!(f, "function '{}'", name),
+            IRLocation::GlobalData(name) => write!(f, "global data '{}'", name),
+            IRLocation::BasicBlock {
+                function,
+                basic_block,
+            } => {
+                write_function(f, function)?;
+                write!(f, ", block '{}'", basic_block)
+            }
+            IRLocation::Instruction {
+                function,
+                basic_block,
+                index,
+            } => {
+                write_function(f, function)?;
+                write!(f, ", block '{}', instruction {}", basic_block, index)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IRVerifyErrorKind {
+    MissingJumpTarget(String),
+    PhiLabelNotPredecessor(String),
+    ConstantOutOfRange(u32),
+    ConstantTypeMismatch(usize),
+    ArgumentCountMismatch { types: usize, arguments: usize },
+    PhiOperandCountMismatch { labels: usize, operands: usize },
+    MissingTerminator,
+    MissingEntryPoint(String),
+    UnknownStructure(String),
+    InvalidElementPath(IRElementPathError),
+}
+
+impl fmt::Display for IRVerifyErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IRVerifyErrorKind::MissingJumpTarget(target) => {
+                write!(f, "jump target '{}' is not a basic block", target)
+            }
+            IRVerifyErrorKind::PhiLabelNotPredecessor(label) => {
+                write!(f, "phi label '{}' is not a predecessor", label)
+            }
+            IRVerifyErrorKind::ConstantOutOfRange(index) => {
+                write!(f, "constant ${} is outside the constant pool", index)
+            }
+            IRVerifyErrorKind::ConstantTypeMismatch(index) => {
+                write!(f, "constant ${} does not hold a value of its type", index)
+            }
+            IRVerifyErrorKind::ArgumentCountMismatch { types, arguments } => write!(
+                f,
+                "invoke has {} argument types but {} arguments",
+                types, arguments
+            ),
+            IRVerifyErrorKind::PhiOperandCountMismatch { labels, operands } => {
+                write!(f, "phi has {} labels but {} operands", labels, operands)
+            }
+            IRVerifyErrorKind::MissingTerminator => write!(f, "block has no terminator"),
+            IRVerifyErrorKind::MissingEntryPoint(name) => {
+                write!(f, "entry point '{}' is not a function", name)
+            }
+            IRVerifyErrorKind::UnknownStructure(name) => {
+                write!(f, "structure '{}' is not defined", name)
+            }
+            IRVerifyErrorKind::InvalidElementPath(error) => {
+                write!(f, "invalid element path: {}", error)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IRVerifyError {
+    pub location: IRLocation,
+    pub kind: IRVerifyErrorKind,
+}
+
+impl fmt::Display for IRVerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.kind)
+    }
+}
+
+impl std::error::Error for IRVerifyError {}
+
+/// Checks that a module is well formed, reporting every problem found rather than the first.
+pub fn verify_module(ir_module: &IRModule) -> Result<(), Vec<IRVerifyError>> {
+    let verifier = IRVerifier::new(ir_module);
+    for (index, entry) in ir_module.constant_pool.entries.iter().enumerate() {
+        if !entry.value.matches_in(ir_module, entry._type.as_ref()) {
+            verifier.report(IRVerifyErrorKind::ConstantTypeMismatch(index));
+        }
+        verifier.visit_dyn(entry._type.as_ref());
+    }
+    for ir_structure in ir_module.structures.values() {
+        verifier.set_location(IRLocation::Structure(ir_structure.name.clone()));
+        verifier.visit_structure(ir_structure);
+    }
+    for ir_global_data in ir_module.global_data_section.data.iter() {
+        verifier.set_location(IRLocation::GlobalData(ir_global_data.name.clone()));
+        verifier.visit_global_data(ir_global_data);
+    }
+    verifier.verify_control_flow_graph(None, &ir_module.global_init_section);
+    for ir_function in ir_module.functions.values() {
+        verifier.visit_function(ir_function);
+    }
+    if let Some(entry_point) = ir_module.entry_point.as_ref()
+        && !ir_module.functions.contains_key(entry_point)
+    {
+        verifier.set_location(IRLocation::Module);
+        verifier.report(IRVerifyErrorKind::MissingEntryPoint(entry_point.clone()));
+    }
+    verifier.finish()
+}
+
+/// Checks a single function against the constant pool of its module.
+pub fn verify_function(
+    ir_module: &IRModule,
+    ir_function: &IRFunction,
+) -> Result<(), Vec<IRVerifyError>> {
+    let verifier = IRVerifier::new(ir_module);
+    verifier.visit_function(ir_function);
+    verifier.finish()
+}
+
+struct IRVerifier<'a> {
+    ir_module: &'a IRModule,
+    constant_count: usize,
+    structures: HashSet<String>,
+    location: RefCell<IRLocation>,
+    basic_blocks: RefCell<HashSet<String>>,
+    predecessors: RefCell<Vec<String>>,
+    last_is_jump: Cell<bool>,
+    last_is_terminator: Cell<bool>,
+    errors: RefCell<Vec<IRVerifyError>>,
+}
+
+impl<'a> IRVerifier<'a> {
+    fn new(ir_module: &'a IRModule) -> Self {
+        Self {
+            ir_module,
+            constant_count: ir_module.constant_pool.entries.len(),
+            structures: ir_module.structures.keys().cloned().collect(),
+            location: RefCell::new(IRLocation::Module),
+            basic_blocks: RefCell::new(HashSet::new()),
+            predecessors: RefCell::new(vec![]),
+            last_is_jump: Cell::new(false),
+            last_is_terminator: Cell::new(false),
+            errors: RefCell::new(vec![]),
+        }
+    }
+
+    fn finish(self) -> Result<(), Vec<IRVerifyError>> {
+        let errors = self.errors.into_inner();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn set_location(&self, location: IRLocation) {
+        self.location.replace(location);
+    }
+
+    fn report(&self, kind: IRVerifyErrorKind) {
+        self.errors.borrow_mut().push(IRVerifyError {
+            location: self.location.borrow().clone(),
+            kind,
+        });
+    }
+
+    fn check_jump_target(&self, target: &str) {
+        if !self.basic_blocks.borrow().contains(target) {
+            self.report(IRVerifyErrorKind::MissingJumpTarget(target.to_string()));
+        }
+    }
+
+    fn verify_control_flow_graph(
+        &self,
+        function: Option<&str>,
+        ir_control_flow_graph: &IRControlFlowGraph,
+    ) {
+        let function = function.map(|function| function.to_string());
+        let mut predecessors: HashMap<String, Vec<String>> = HashMap::new();
+        for (index, name) in ir_control_flow_graph.basic_blocks.keys().enumerate() {
+            for successor in ir_control_flow_graph.compute_successors_at(index) {
+                predecessors
+                    .entry(successor)
+                    .or_default()
+                    .push(name.clone());
+            }
+        }
+        self.basic_blocks
+            .replace(ir_control_flow_graph.basic_blocks.keys().cloned().collect());
+        let count = ir_control_flow_graph.basic_blocks.len();
+        for (position, (name, ir_basic_block)) in
+            ir_control_flow_graph.basic_blocks.iter().enumerate()
+        {
+            self.predecessors
+                .replace(predecessors.get(name).cloned().unwrap_or_default());
+            self.last_is_jump.set(false);
+            self.last_is_terminator.set(false);
+            for (index, ir_instruction) in ir_basic_block.instructions.iter().enumerate() {
+                self.set_location(IRLocation::Instruction {
+                    function: function.clone(),
+                    basic_block: name.clone(),
+                    index,
+                });
+                self.last_is_jump.set(false);
+                self.last_is_terminator.set(false);
+                ir_instruction.accept(self);
+            }
+            let falls_into_next_block = self.last_is_jump.get() && position + 1 < count;
+            if !self.last_is_terminator.get() && !falls_into_next_block {
+                self.set_location(IRLocation::BasicBlock {
+                    function: function.clone(),
+                    basic_block: name.clone(),
+                });
+                self.report(IRVerifyErrorKind::MissingTerminator);
+            }
+        }
+    }
+}
+
+impl IRVisitor for IRVerifier<'_> {
+    fn visit_dyn(&self, ir_node: &dyn IRNode) {
+        ir_node.accept(self);
+    }
+    fn visit_function(&self, ir_function: &IRFunction) {
+        self.set_location(IRLocation::Function(ir_function.name.clone()));
+        self.visit_dyn(ir_function.return_type.as_ref());
+        for ir_field in ir_function.fields.iter() {
+            self.visit_field(ir_field);
+        }
+        self.verify_control_flow_graph(Some(&ir_function.name), &ir_function.control_flow_graph);
+    }
+    fn visit_goto(&self, ir_goto: &IRGoto) {
+        self.check_jump_target(&ir_goto.target);
+        self.last_is_terminator.set(true);
+    }
+    fn visit_conditional_jump(&self, ir_conditional_jump: &IRConditionalJump) {
+        self.visit_dyn(ir_conditional_jump._type.as_ref());
+        self.visit_dyn(ir_conditional_jump.operand1.as_ref());
+        if let Some(operand2) = ir_conditional_jump.operand2.as_ref() {
+            self.visit_dyn(operand2.as_ref());
+        }
+        self.check_jump_target(&ir_conditional_jump.target);
+        self.last_is_jump.set(true);
+    }
+    fn visit_return(&self, ir_return: &IRReturn) {
+        if let Some(operand) = ir_return.operand.as_ref() {
+            self.visit_dyn(operand.as_ref());
+        }
+        self.last_is_terminator.set(true);
+    }
+    fn visit_invoke(&self, ir_invoke: &IRInvoke) {
+        if ir_invoke.argument_types.len() != ir_invoke.arguments.len() {
+            self.report(IRVerifyErrorKind::ArgumentCountMismatch {
+                types: ir_invoke.argument_types.len(),
+                arguments: ir_invoke.arguments.len(),
+            });
+        }
+        self.visit_dyn(ir_invoke.return_type.as_ref());
+        self.visit_dyn(ir_invoke.address.as_ref());
+        for argument_type in ir_invoke.argument_types.iter() {
+            self.visit_dyn(argument_type.as_ref());
+        }
+        for argument in ir_invoke.arguments.iter() {
+            self.visit_dyn(argument.as_ref());
+        }
+    }
+    fn visit_element_address(&self, ir_element_address: &IRElementAddress) {
+        self.visit_dyn(ir_element_address._type.as_ref());
+        self.visit_dyn(ir_element_address.base.as_ref());
+        for index in ir_element_address.path.iter() {
+            if let IRElementIndex::Index(operand) = index {
+                self.visit_dyn(operand.as_ref());
+            }
+        }
+        match ir_element_address.element_type(self.ir_module) {
+            // Already reported where the structure type is named.
+            Ok(_) | Err(IRElementPathError::UnknownStructure(_)) => {}
+            Err(error) => self.report(IRVerifyErrorKind::InvalidElementPath(error)),
+        }
+    }
+    fn visit_constant(&self, ir_constant: &IRConstant) {
+        if ir_constant.index as usize >= self.constant_count {
+            self.report(IRVerifyErrorKind::ConstantOutOfRange(ir_constant.index));
+        }
+    }
+    fn visit_phi(&self, ir_phi: &IRPhi) {
+        if ir_phi.labels.len() != ir_phi.operands.len() {
+            self.report(IRVerifyErrorKind::PhiOperandCountMismatch {
+                labels: ir_phi.labels.len(),
+                operands: ir_phi.operands.len(),
+            });
+        }
+        self.visit_dyn(ir_phi._type.as_ref());
+        for label in ir_phi.labels.iter() {
+            if !self.predecessors.borrow().contains(label) {
+                self.report(IRVerifyErrorKind::PhiLabelNotPredecessor(label.clone()));
+            }
+        }
+        for operand in ir_phi.operands.iter() {
+            self.visit_dyn(operand.as_ref());
+        }
+    }
+    fn visit_structure_type(&self, ir_structure_type: &IRStructureType) {
+        if !self.structures.contains(&ir_structure_type.name) {
+            self.report(IRVerifyErrorKind::UnknownStructure(
+                ir_structure_type.name.clone(),
+            ));
+        }
+    }
+    fn visit_macro(&self, ir_macro: &IRMacro) {
+        for operand in ir_macro.additional_operands.iter() {
+            self.visit_dyn(operand.as_ref());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::instruction::{IRInstruction, IRSetVirtualRegister};
+    use crate::ir::parser::parse_module;
+    use crate::ir::types::{IRIntegerType, IRIntegerTypeSize, IRType};
+
+    const VALID: &str = "\
+structure Node {
+    i32 value
+    %Node* next
+}
+constant $0 = i32 0
+constant $1 = i32 1
+global head, size=$1
+init {
+entry:
+    return
+}
+function i32 sum(%Node* node) {
+entry:
+    %pnode = `field_address([node], [])
+    %first = get %Node*, %pnode
+    goto loop
+loop:
+    %n = phi %Node* [entry, %first], [body, %next]
+    %total = phi i32 [entry, $0], [body, %added]
+    conditional_jump %Node* e, %n, $0, #done
+body:
+    %pvalue = element_address %Node, %n, [value]
+    %value = get i32, %pvalue
+    %added = add i32 %total, %value
+    %pnext = element_address %Node, %n, [next]
+    %next = get %Node*, %pnext
+    goto loop
+done:
+    return %total
+}
+function i32 main() {
+entry:
+    %r = invoke i32 `function_address([sum], []), [%Node*, $0]
+    return %r
+}
+entry_point main
+";
+
+    fn i32() -> Box<dyn IRType> {
+        Box::new(IRIntegerType::new(IRIntegerTypeSize::FourBytes, false))
+    }
+
+    fn kinds(ir_module: &IRModule) -> Vec<IRVerifyErrorKind> {
+        verify_module(ir_module)
+            .unwrap_err()
+            .into_iter()
+            .map(|error| error.kind)
+            .collect()
+    }
+
+    fn errors(source: &str) -> Vec<IRVerifyErrorKind> {
+        kinds(&parse_module(source).unwrap())
+    }
+
+    /// The instruction `index` of block `basic_block` in function `f`.
+    fn instruction_mut<'a>(
+        ir_module: &'a mut IRModule,
+        basic_block: &str,
+        index: usize,
+    ) -> &'a mut dyn IRInstruction {
+        let ir_function = ir_module.functions.get_mut("f").unwrap();
+        let ir_basic_block = ir_function
+            .control_flow_graph
+            .basic_blocks
+            .get_mut(basic_block)
+            .unwrap();
+        ir_basic_block.instructions[index].as_mut()
+    }
+
+    #[test]
+    fn well_formed_modules_verify() {
+        let ir_module = parse_module(VALID).unwrap();
+        assert_eq!(verify_module(&ir_module), Ok(()));
+        let ir_function = &ir_module.functions["sum"];
+        assert_eq!(verify_function(&ir_module, ir_function), Ok(()));
+    }
+
+    #[test]
+    fn jumps_must_target_a_block() {
+        let source = "\
+function void f() {
+entry:
+    goto exit
+}
+";
+        assert_eq!(
+            verify_module(&parse_module(source).unwrap()),
+            Err(vec![IRVerifyError {
+                location: IRLocation::Instruction {
+                    function: Some("f".to_string()),
+                    basic_block: "entry".to_string(),
+                    index: 0,
+                },
+                kind: IRVerifyErrorKind::MissingJumpTarget("exit".to_string()),
+            }])
+        );
+    }
+
+    #[test]
+    fn phi_labels_must_be_predecessors() {
+        let source = "\
+constant $0 = i32 0
+function i32 f() {
+entry:
+    goto next
+other:
+    goto next
+next:
+    %x = phi i32 [entry, $0], [elsewhere, $0]
+    return %x
+}
+";
+        assert_eq!(
+            errors(source),
+            [IRVerifyErrorKind::PhiLabelNotPredecessor(
+                "elsewhere".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn constants_must_be_in_the_pool() {
+        let source = "\
+constant $0 = i32 0
+function i32 f() {
+entry:
+    return $1
+}
+";
+        assert_eq!(errors(source), [IRVerifyErrorKind::ConstantOutOfRange(1)]);
+    }
+
+    #[test]
+    fn constants_must_hold_a_value_of_their_type() {
+        let mut ir_module =
+            parse_module("constant $0 = i32 0\nconstant $1 = [2 x i32] {1, 2}\n").unwrap();
+        ir_module.constant_pool.entries[1].value = ir_module.constant_pool.entries[0].value.clone();
+        assert_eq!(
+            verify_module(&ir_module),
+            Err(vec![IRVerifyError {
+                location: IRLocation::Module,
+                kind: IRVerifyErrorKind::ConstantTypeMismatch(1),
+            }])
+        );
+    }
+
+    #[test]
+    fn invokes_need_a_type_for_each_argument() {
+        let source = "\
+constant $0 = i32 0
+function void f() {
+entry:
+    invoke void `function_address([f], []), [i32, $0]
+    return
+}
+";
+        let mut ir_module = parse_module(source).unwrap();
+        instruction_mut(&mut ir_module, "entry", 0)
+            .downcast_mut::<IRInvoke>()
+            .unwrap()
+            .argument_types
+            .push(i32());
+        assert_eq!(
+            kinds(&ir_module),
+            [IRVerifyErrorKind::ArgumentCountMismatch {
+                types: 2,
+                arguments: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn phis_need_an_operand_for_each_label() {
+        let source = "\
+constant $0 = i32 0
+function i32 f() {
+entry:
+    goto next
+next:
+    %x = phi i32 [entry, $0]
+    return %x
+}
+";
+        let mut ir_module = parse_module(source).unwrap();
+        instruction_mut(&mut ir_module, "next", 0)
+            .downcast_mut::<IRSetVirtualRegister>()
+            .unwrap()
+            .source
+            .downcast_mut::<IRPhi>()
+            .unwrap()
+            .operands
+            .clear();
+        assert_eq!(
+            kinds(&ir_module),
+            [IRVerifyErrorKind::PhiOperandCountMismatch {
+                labels: 1,
+                operands: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn blocks_must_end_with_a_terminator() {
+        let source = "\
+constant $0 = i32 0
+function i32 f() {
+entry:
+    conditional_jump i32 e, $0, $0, #entry
+}
+";
+        assert_eq!(
+            verify_module(&parse_module(source).unwrap()),
+            Err(vec![IRVerifyError {
+                location: IRLocation::BasicBlock {
+                    function: Some("f".to_string()),
+                    basic_block: "entry".to_string(),
+                },
+                kind: IRVerifyErrorKind::MissingTerminator,
+            }])
+        );
+        // A conditional jump may fall through into the next block.
+        let source = "\
+constant $0 = i32 0
+function i32 f() {
+entry:
+    conditional_jump i32 e, $0, $0, #entry
+exit:
+    return $0
+}
+";
+        assert_eq!(verify_module(&parse_module(source).unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn the_entry_point_must_be_a_function() {
+        assert_eq!(
+            verify_module(&parse_module("entry_point main\n").unwrap()),
+            Err(vec![IRVerifyError {
+                location: IRLocation::Module,
+                kind: IRVerifyErrorKind::MissingEntryPoint("main".to_string()),
+            }])
+        );
+    }
+
+    #[test]
+    fn structure_types_must_be_defined() {
+        let source = "\
+structure Node {
+    %Leaf* leaf
+}
+";
+        assert_eq!(
+            verify_module(&parse_module(source).unwrap()),
+            Err(vec![IRVerifyError {
+                location: IRLocation::Structure("Node".to_string()),
+                kind: IRVerifyErrorKind::UnknownStructure("Leaf".to_string()),
+            }])
+        );
+    }
+
+    #[test]
+    fn element_paths_must_follow_the_type() {
+        let source = "\
+structure Node {
+    i32 value
+}
+constant $0 = i32 0
+function void f(%Node* node) {
+entry:
+    %pnode = `field_address([node], [])
+    %node = get %Node*, %pnode
+    %a = element_address %Node, %node, [next]
+    %b = element_address %Node, %node, [$0]
+    %c = element_address i32, %node, [value]
+    return
+}
+";
+        assert_eq!(
+            errors(source),
+            [
+                IRVerifyErrorKind::InvalidElementPath(IRElementPathError::UnknownField {
+                    structure: "Node".to_string(),
+                    field: "next".to_string(),
+                }),
+                IRVerifyErrorKind::InvalidElementPath(IRElementPathError::MismatchedIndex {
+                    _type: "%Node".to_string(),
+                    index: "$0".to_string(),
+                }),
+                IRVerifyErrorKind::InvalidElementPath(IRElementPathError::NotAggregate(
+                    "i32".to_string()
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let source = "\
+function void f() {
+entry:
+    goto exit
+other:
+    return $3
+}
+entry_point main
+";
+        assert_eq!(
+            errors(source),
+            [
+                IRVerifyErrorKind::MissingJumpTarget("exit".to_string()),
+                IRVerifyErrorKind::ConstantOutOfRange(3),
+                IRVerifyErrorKind::MissingEntryPoint("main".to_string()),
+            ]
+        );
+    }
+}
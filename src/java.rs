@@ -0,0 +1,194 @@
+//! The JNI entry points behind the `jni` feature, for a JVM compiler that hands its IR to this
+//! library in-process.
+//!
+//! The Java side passes a module either as its own IR objects, or marshalled as the textual form
+//! read by `parse_module` (what `IRDumper` prints) or as the binary encoding read by
+//! `read_module`, which is the fastest for large modules. The methods are bound to this class:
+//!
+//! ```java
+//! package lg.rust.binding;
+//!
+//! public final class IRGenerator {
+//!     /** Generates code for a module; see IRGenerator::generate for the options. */
+//!     public static native byte[] generate(String module, String[] options);
+//!     /** Same as generate, for a module in the binary encoding. */
+//!     public static native byte[] generateBinary(byte[] module, String[] options);
+//!     /** Same as generate, for a module given as an IRModule object. */
+//!     public static native byte[] generateModule(Object module, String[] options);
+//! }
+//! ```
+//!
+//! `generateModule` reads the objects reflectively, so the compiler's IR classes may live in any
+//! package, but they must mirror the Rust types:
+//!
+//! - each class is public and has the simple name of the Rust type it stands for (`IRModule`,
+//!   `IRControlFlowGraph`, `IRCalculate`, `IRPointerType`, ...), which is how instructions,
+//!   operands and types are told apart;
+//! - each Rust field is a public field, possibly inherited, named in camel case (`isAtomic`,
+//!   `basicBlocks`, `name2vtableKeys`) except that `_type` is `type`; the edges of a control flow
+//!   graph are not read but recomputed;
+//! - an `Option` is null when absent, numbers are any `Number` and flags are `Boolean`;
+//! - a `Vec` or `IndexMap` is any `Collection`, `Map` or object array, and for a `Map` only the
+//!   values are read, except for `name2vtableKeys` and `name2itableKeys` which are a `Map` from
+//!   each name to its functions;
+//! - an `IRCondition`, `IRCalculateOperator` or `IRTypeCastKind` is an enum constant or a string
+//!   named like the Rust variant, ignoring case and underscores (`NOT_EQUAL`); an
+//!   `IRIntegerTypeSize` may also be its width in bits;
+//! - an element address path holds a `String` for each field and an operand for each index;
+//! - a constant pool entry's `value` follows its `type`: a `Number` for integers and floating
+//!   point, or a `Boolean` for integers; a `String` (its UTF-8 bytes), a `byte[]` or an address
+//!   for pointers; and a collection of element values for arrays and structures. A null value is
+//!   the null pointer or `zeroinitializer`.
+//!
+//! Errors are thrown as Java exceptions: `IllegalArgumentException` for a module that does not
+//! parse, decode or follow the contract above, or an unknown option, `NullPointerException` for a
+//! missing value, `UnsupportedOperationException` when a backend cannot handle the module, and
+//! `RuntimeException` if the library panics.
+
+use crate::IRGenerator;
+use crate::backend::IRGenerateError;
+use crate::ir::IRModule;
+use crate::ir::binary::read_module;
+use crate::ir::parser::parse_module;
+use jni::JNIEnv;
+use jni::objects::{JByteArray, JClass, JObject, JObjectArray, JString};
+use jni::sys::jbyteArray;
+use mirror::IRJavaReader;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+mod mirror;
+
+const ILLEGAL_ARGUMENT: &str = "java/lang/IllegalArgumentException";
+const NULL_POINTER: &str = "java/lang/NullPointerException";
+const RUNTIME: &str = "java/lang/RuntimeException";
+const UNSUPPORTED_OPERATION: &str = "java/lang/UnsupportedOperationException";
+
+/// Why a call failed: either a Java exception is already pending, or one is to be thrown.
+enum IRJavaError {
+    Pending,
+    Throw(&'static str, String),
+}
+
+impl From<jni::errors::Error> for IRJavaError {
+    fn from(error: jni::errors::Error) -> Self {
+        match error {
+            jni::errors::Error::JavaException => IRJavaError::Pending,
+            error => IRJavaError::Throw(RUNTIME, error.to_string()),
+        }
+    }
+}
+
+type IRJavaResult<T> = Result<T, IRJavaError>;
+
+fn read_string(env: &mut JNIEnv, string: &JString, name: &str) -> IRJavaResult<String> {
+    if string.is_null() {
+        return Err(IRJavaError::Throw(
+            NULL_POINTER,
+            format!("{} is null", name),
+        ));
+    }
+    Ok(env.get_string(string)?.into())
+}
+
+/// The strings of a `String[]`, where a null array is empty.
+fn read_strings(env: &mut JNIEnv, strings: &JObjectArray, name: &str) -> IRJavaResult<Vec<String>> {
+    if strings.is_null() {
+        return Ok(vec![]);
+    }
+    let length = env.get_array_length(strings)?;
+    (0..length)
+        .map(|index| {
+            let string = JString::from(env.get_object_array_element(strings, index)?);
+            read_string(env, &string, name)
+        })
+        .collect()
+}
+
+/// Runs `f`, throwing its error or any panic as a Java exception and returning null instead.
+fn guard<T>(env: &mut JNIEnv, f: impl FnOnce(&mut JNIEnv) -> IRJavaResult<*mut T>) -> *mut T {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(env))).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err(IRJavaError::Throw(RUNTIME, format!("panic: {}", message)))
+    });
+    match result {
+        Ok(value) => value,
+        Err(IRJavaError::Pending) => ptr::null_mut(),
+        Err(IRJavaError::Throw(class, message)) => {
+            // Throwing only fails if the JVM is already unusable, so there is nothing left to do.
+            let _ = env.throw_new(class, message);
+            ptr::null_mut()
+        }
+    }
+}
+
+fn generate(
+    env: &mut JNIEnv,
+    ir_module: &IRModule,
+    options: &[String],
+) -> IRJavaResult<jbyteArray> {
+    let output = IRGenerator::generate(ir_module, options).map_err(|error| {
+        let class = match error {
+            IRGenerateError::InvalidOption(_) => ILLEGAL_ARGUMENT,
+            IRGenerateError::Unsupported { .. } => UNSUPPORTED_OPERATION,
+        };
+        IRJavaError::Throw(class, error.to_string())
+    })?;
+    Ok(env.byte_array_from_slice(&output)?.into_raw())
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_lg_rust_binding_IRGenerator_generate<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    module: JString<'local>,
+    options: JObjectArray<'local>,
+) -> jbyteArray {
+    guard(&mut env, |env| {
+        let source = read_string(env, &module, "module")?;
+        let options = read_strings(env, &options, "option")?;
+        let ir_module = parse_module(&source)
+            .map_err(|error| IRJavaError::Throw(ILLEGAL_ARGUMENT, error.to_string()))?;
+        generate(env, &ir_module, &options)
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_lg_rust_binding_IRGenerator_generateBinary<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    module: JByteArray<'local>,
+    options: JObjectArray<'local>,
+) -> jbyteArray {
+    guard(&mut env, |env| {
+        if module.is_null() {
+            return Err(IRJavaError::Throw(
+                NULL_POINTER,
+                "module is null".to_string(),
+            ));
+        }
+        let bytes = env.convert_byte_array(&module)?;
+        let options = read_strings(env, &options, "option")?;
+        let ir_module = read_module(&bytes)
+            .map_err(|error| IRJavaError::Throw(ILLEGAL_ARGUMENT, error.to_string()))?;
+        generate(env, &ir_module, &options)
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_lg_rust_binding_IRGenerator_generateModule<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    module: JObject<'local>,
+    options: JObjectArray<'local>,
+) -> jbyteArray {
+    guard(&mut env, |env| {
+        let options = read_strings(env, &options, "option")?;
+        let ir_module = IRJavaReader::default().module(env, &module)?;
+        generate(env, &ir_module, &options)
+    })
+}
@@ -0,0 +1,1022 @@
+//! Reads a module straight out of the Java compiler's IR objects, for `generateModule`.
+//!
+//! Objects are told apart by the simple name of their class and read through reflection on their
+//! public fields, so the mirror classes may live in any package and declare their fields with any
+//! type that holds the right values; the contract is spelled out in the documentation of the
+//! `java` module.
+
+use super::{ILLEGAL_ARGUMENT, IRJavaError, IRJavaResult, NULL_POINTER};
+use crate::ir::base::{IRBasicBlock, IRControlFlowGraph, IRFunction, IRGlobalData};
+use crate::ir::binary::{CAST_KINDS, CONDITIONS, OPERATORS};
+use crate::ir::instruction::{
+    IRAsm, IRCalculate, IRConditionalJump, IRDecrease, IRElementAddress, IRElementIndex, IRFree,
+    IRGet, IRGoto, IRIncrease, IRInstruction, IRInvoke, IRMalloc, IRNegate, IRNoOperate, IRNot,
+    IRRealloc, IRReturn, IRSet, IRSetVirtualRegister, IRStackAllocate, IRTypeCast,
+};
+use crate::ir::operand::{
+    IRConstant, IRInterfaceTable, IRInterfaceTableEntry, IRMacro, IROperand, IRPhi,
+    IRVirtualRegister, IRVirtualTable,
+};
+use crate::ir::structure::{IRField, IRStructure};
+use crate::ir::types::{
+    IRArrayType, IRDoubleType, IRFloatType, IRFunctionType, IRIntegerType, IRIntegerTypeSize,
+    IRPointerType, IRStructureType, IRType, IRTypeKind, IRVoidType,
+};
+use crate::ir::{IRConstantPoolEntry, IRConstantValue, IRModule, MAX_NESTING_DEPTH};
+use indexmap::IndexMap;
+use jni::JNIEnv;
+use jni::objects::{JByteArray, JObject, JObjectArray, JString, JValue, JValueOwned};
+use std::fmt::Debug;
+
+/// How many local references reading one object may hold; every nested object and every item of
+/// a collection is read in a frame of its own.
+const FRAME_CAPACITY: i32 = 32;
+
+const INTEGER_SIZES: [IRIntegerTypeSize; 5] = [
+    IRIntegerTypeSize::OneBit,
+    IRIntegerTypeSize::OneByte,
+    IRIntegerTypeSize::TwoBytes,
+    IRIntegerTypeSize::FourBytes,
+    IRIntegerTypeSize::EightBytes,
+];
+
+fn illegal(message: String) -> IRJavaError {
+    IRJavaError::Throw(ILLEGAL_ARGUMENT, message)
+}
+
+fn null(what: &str) -> IRJavaError {
+    IRJavaError::Throw(NULL_POINTER, format!("{} is null", what))
+}
+
+fn class_name(env: &mut JNIEnv, object: &JObject) -> IRJavaResult<String> {
+    env.with_local_frame(FRAME_CAPACITY, |env| {
+        let class = env
+            .call_method(object, "getClass", "()Ljava/lang/Class;", &[])?
+            .l()?;
+        let name = env
+            .call_method(&class, "getSimpleName", "()Ljava/lang/String;", &[])?
+            .l()?;
+        Ok(env.get_string(<&JString>::from(&name))?.into())
+    })
+}
+
+/// The value of the public field `name` of `object`, boxed if the field is primitive.
+fn reflect_field<'local>(
+    env: &mut JNIEnv<'local>,
+    object: &JObject,
+    name: &str,
+) -> jni::errors::Result<JObject<'local>> {
+    let class = env
+        .call_method(object, "getClass", "()Ljava/lang/Class;", &[])?
+        .l()?;
+    let name = env.new_string(name)?;
+    let field = env
+        .call_method(
+            &class,
+            "getField",
+            "(Ljava/lang/String;)Ljava/lang/reflect/Field;",
+            &[JValue::Object(&name)],
+        )?
+        .l()?;
+    env.call_method(
+        &field,
+        "get",
+        "(Ljava/lang/Object;)Ljava/lang/Object;",
+        &[JValue::Object(object)],
+    )?
+    .l()
+}
+
+fn string(env: &mut JNIEnv, value: &JObject, what: &str) -> IRJavaResult<String> {
+    if value.is_null() {
+        return Err(null(what));
+    }
+    if !env.is_instance_of(value, "java/lang/String")? {
+        return Err(illegal(format!("{} is not a String", what)));
+    }
+    Ok(env.get_string(<&JString>::from(value))?.into())
+}
+
+fn boolean(env: &mut JNIEnv, value: &JObject, what: &str) -> IRJavaResult<bool> {
+    if value.is_null() {
+        return Err(null(what));
+    }
+    if !env.is_instance_of(value, "java/lang/Boolean")? {
+        return Err(illegal(format!("{} is not a Boolean", what)));
+    }
+    Ok(env.call_method(value, "booleanValue", "()Z", &[])?.z()?)
+}
+
+/// Calls `method`, one of the conversions of `Number`, on `value`.
+fn number<'local>(
+    env: &mut JNIEnv<'local>,
+    value: &JObject,
+    what: &str,
+    method: &str,
+    signature: &str,
+) -> IRJavaResult<JValueOwned<'local>> {
+    if value.is_null() {
+        return Err(null(what));
+    }
+    if !env.is_instance_of(value, "java/lang/Number")? {
+        return Err(illegal(format!("{} is not a Number", what)));
+    }
+    Ok(env.call_method(value, method, signature, &[])?)
+}
+
+fn long(env: &mut JNIEnv, value: &JObject, what: &str) -> IRJavaResult<i64> {
+    Ok(number(env, value, what, "longValue", "()J")?.j()?)
+}
+
+/// The name of the public field that mirrors the Rust field `name`: camel case, without the
+/// leading underscore of `_type`.
+fn java_field_name(name: &str) -> String {
+    let mut java_name = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.trim_start_matches('_').chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            java_name.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            java_name.push(c);
+        }
+    }
+    java_name
+}
+
+/// The variant called `name`, ignoring case and underscores so that both `NOT_EQUAL` and
+/// `NotEqual` name `IRCondition::NotEqual`.
+fn variant_named<T: Clone + Debug>(variants: &[T], name: &str) -> Option<T> {
+    let normalize = |name: &str| name.replace('_', "").to_lowercase();
+    variants
+        .iter()
+        .find(|variant| normalize(&format!("{:?}", variant)) == normalize(name))
+        .cloned()
+}
+
+/// The integer size of `bits` bits.
+fn integer_size(bits: i64) -> Option<IRIntegerTypeSize> {
+    INTEGER_SIZES
+        .into_iter()
+        .find(|size| size.clone() as i64 == bits)
+}
+
+/// The variant named by an enum constant or a string, as matched by `variant_named`.
+fn variant<T: Clone + Debug>(
+    env: &mut JNIEnv,
+    value: &JObject,
+    what: &str,
+    variants: &[T],
+) -> IRJavaResult<T> {
+    let name = if !value.is_null() && env.is_instance_of(value, "java/lang/Enum")? {
+        let name = env
+            .call_method(value, "name", "()Ljava/lang/String;", &[])?
+            .l()?;
+        string(env, &name, what)?
+    } else {
+        string(env, value, what)?
+    };
+    variant_named(variants, &name).ok_or_else(|| illegal(format!("unknown {} {}", what, name)))
+}
+
+/// Reads each item of a `Collection`, the values of a `Map` or the elements of an object array.
+fn items<T>(
+    env: &mut JNIEnv,
+    value: &JObject,
+    what: &str,
+    mut read: impl FnMut(&mut JNIEnv, &JObject) -> IRJavaResult<T>,
+) -> IRJavaResult<Vec<T>> {
+    if value.is_null() {
+        return Err(null(what));
+    }
+    env.with_local_frame(FRAME_CAPACITY, |env| {
+        let array = if env.is_instance_of(value, "java/util/Map")? {
+            let values = env
+                .call_method(value, "values", "()Ljava/util/Collection;", &[])?
+                .l()?;
+            env.call_method(&values, "toArray", "()[Ljava/lang/Object;", &[])?
+                .l()?
+        } else if env.is_instance_of(value, "java/util/Collection")? {
+            env.call_method(value, "toArray", "()[Ljava/lang/Object;", &[])?
+                .l()?
+        } else if env.is_instance_of(value, "[Ljava/lang/Object;")? {
+            env.new_local_ref(value)?
+        } else {
+            return Err(illegal(format!("{} is not a collection", what)));
+        };
+        let array = JObjectArray::from(array);
+        let length = env.get_array_length(&array)?;
+        (0..length)
+            .map(|index| {
+                env.with_local_frame(FRAME_CAPACITY, |env| {
+                    let item = env.get_object_array_element(&array, index)?;
+                    read(env, &item)
+                })
+            })
+            .collect()
+    })
+}
+
+/// Reads each key and value of a `Map`.
+fn entries<T>(
+    env: &mut JNIEnv,
+    value: &JObject,
+    what: &str,
+    mut read: impl FnMut(&mut JNIEnv, &JObject, &JObject) -> IRJavaResult<T>,
+) -> IRJavaResult<Vec<T>> {
+    if value.is_null() {
+        return Err(null(what));
+    }
+    if !env.is_instance_of(value, "java/util/Map")? {
+        return Err(illegal(format!("{} is not a Map", what)));
+    }
+    env.with_local_frame(FRAME_CAPACITY, |env| {
+        let entries = env
+            .call_method(value, "entrySet", "()Ljava/util/Set;", &[])?
+            .l()?;
+        items(env, &entries, what, |env, entry| {
+            let key = env
+                .call_method(entry, "getKey", "()Ljava/lang/Object;", &[])?
+                .l()?;
+            let value = env
+                .call_method(entry, "getValue", "()Ljava/lang/Object;", &[])?
+                .l()?;
+            read(env, &key, &value)
+        })
+    })
+}
+
+/// An object of one of the mirror classes, whose fields are read by name.
+struct IRJavaObject<'a, 'object> {
+    object: &'a JObject<'object>,
+    class: String,
+}
+
+impl<'a, 'object> IRJavaObject<'a, 'object> {
+    fn new(env: &mut JNIEnv, object: &'a JObject<'object>, what: &str) -> IRJavaResult<Self> {
+        if object.is_null() {
+            return Err(null(what));
+        }
+        let class = class_name(env, object)?;
+        Ok(IRJavaObject { object, class })
+    }
+
+    fn what(&self, name: &str) -> String {
+        format!("{}.{}", self.class, java_field_name(name))
+    }
+
+    fn optional<'local>(
+        &self,
+        env: &mut JNIEnv<'local>,
+        name: &str,
+    ) -> IRJavaResult<Option<JObject<'local>>> {
+        let what = self.what(name);
+        let value = env.with_local_frame_returning_local(FRAME_CAPACITY, |env| {
+            reflect_field(env, self.object, &java_field_name(name)).or_else(|error| {
+                if !matches!(error, jni::errors::Error::JavaException) {
+                    return Err(error.into());
+                }
+                // A missing or inaccessible field breaks the contract; anything else, such as a
+                // stack overflow, is thrown on as it is.
+                let exception = env.exception_occurred()?;
+                env.exception_clear()?;
+                if env.is_instance_of(&exception, "java/lang/ReflectiveOperationException")? {
+                    return Err(illegal(format!("cannot read the public field {}", what)));
+                }
+                env.throw(exception)?;
+                Err(IRJavaError::Pending)
+            })
+        })?;
+        Ok((!value.is_null()).then_some(value))
+    }
+
+    fn get<'local>(&self, env: &mut JNIEnv<'local>, name: &str) -> IRJavaResult<JObject<'local>> {
+        self.optional(env, name)?
+            .ok_or_else(|| null(&self.what(name)))
+    }
+
+    fn string(&self, env: &mut JNIEnv, name: &str) -> IRJavaResult<String> {
+        let value = self.get(env, name)?;
+        string(env, &value, &self.what(name))
+    }
+
+    fn optional_string(&self, env: &mut JNIEnv, name: &str) -> IRJavaResult<Option<String>> {
+        match self.optional(env, name)? {
+            Some(value) => Ok(Some(string(env, &value, &self.what(name))?)),
+            None => Ok(None),
+        }
+    }
+
+    fn strings(&self, env: &mut JNIEnv, name: &str) -> IRJavaResult<Vec<String>> {
+        let value = self.get(env, name)?;
+        let what = self.what(name);
+        items(env, &value, &what, |env, item| string(env, item, &what))
+    }
+
+    fn bool(&self, env: &mut JNIEnv, name: &str) -> IRJavaResult<bool> {
+        let value = self.get(env, name)?;
+        boolean(env, &value, &self.what(name))
+    }
+
+    fn unsigned<T: TryFrom<i64>>(&self, env: &mut JNIEnv, name: &str) -> IRJavaResult<T> {
+        let value = self.get(env, name)?;
+        let value = long(env, &value, &self.what(name))?;
+        T::try_from(value)
+            .map_err(|_| illegal(format!("{} is out of range: {}", self.what(name), value)))
+    }
+
+    fn variant<T: Clone + Debug>(
+        &self,
+        env: &mut JNIEnv,
+        name: &str,
+        variants: &[T],
+    ) -> IRJavaResult<T> {
+        let value = self.get(env, name)?;
+        variant(env, &value, &self.what(name), variants)
+    }
+}
+
+/// Reads a module, limiting how deeply its types, operands and constant values nest so that a
+/// cyclic object graph is rejected rather than overflowing the stack.
+#[derive(Default)]
+pub(super) struct IRJavaReader {
+    depth: usize,
+}
+
+impl IRJavaReader {
+    fn nested<T>(
+        &mut self,
+        env: &mut JNIEnv,
+        read: impl FnOnce(&mut Self, &mut JNIEnv) -> IRJavaResult<T>,
+    ) -> IRJavaResult<T> {
+        if self.depth == MAX_NESTING_DEPTH {
+            return Err(illegal("module is nested too deeply".to_string()));
+        }
+        self.depth += 1;
+        let result = env.with_local_frame(FRAME_CAPACITY, |env| read(self, env));
+        self.depth -= 1;
+        result
+    }
+
+    pub(super) fn module(&mut self, env: &mut JNIEnv, object: &JObject) -> IRJavaResult<IRModule> {
+        let ir_object = IRJavaObject::new(env, object, "module")?;
+        let mut ir_module = IRModule::new();
+
+        let structures = ir_object.get(env, "structures")?;
+        let what = ir_object.what("structures");
+        for ir_structure in items(env, &structures, &what, |env, item| {
+            let ir_structure = IRJavaObject::new(env, item, &what)?;
+            let fields = ir_structure.get(env, "fields")?;
+            let fields = items(env, &fields, &ir_structure.what("fields"), |env, item| {
+                self.field(env, item)
+            })?;
+            Ok(IRStructure::new(ir_structure.string(env, "name")?, fields))
+        })? {
+            ir_module.push_struct(ir_structure);
+        }
+
+        let constant_pool = ir_object.get(env, "constant_pool")?;
+        let constant_pool = IRJavaObject::new(env, &constant_pool, "constant pool")?;
+        let constant_pool_entries = constant_pool.get(env, "entries")?;
+        let what = constant_pool.what("entries");
+        for entry in items(env, &constant_pool_entries, &what, |env, item| {
+            let entry = IRJavaObject::new(env, item, &what)?;
+            let _type = self._type(env, &entry, "_type")?;
+            let value = entry.optional(env, "value")?;
+            let value = self.value(
+                env,
+                value.as_ref(),
+                _type.as_ref(),
+                &ir_module.structures,
+                &entry.what("value"),
+            )?;
+            Ok(IRConstantPoolEntry::new(_type, value))
+        })? {
+            ir_module.constant_pool.push(Box::new(entry));
+        }
+
+        let global_data_section = ir_object.get(env, "global_data_section")?;
+        let global_data_section =
+            IRJavaObject::new(env, &global_data_section, "global data section")?;
+        let data = global_data_section.get(env, "data")?;
+        let what = global_data_section.what("data");
+        ir_module.global_data_section.data = items(env, &data, &what, |env, item| {
+            let ir_global_data = IRJavaObject::new(env, item, &what)?;
+            let values = match ir_global_data.optional(env, "values")? {
+                Some(values) => {
+                    Some(self.operand_items(env, &values, &ir_global_data.what("values"))?)
+                }
+                None => None,
+            };
+            Ok(IRGlobalData::new(
+                ir_global_data.string(env, "name")?,
+                self.optional_operand(env, &ir_global_data, "size")?,
+                values,
+            ))
+        })?;
+
+        let global_init_section = ir_object.get(env, "global_init_section")?;
+        ir_module.global_init_section = Box::new(self.control_flow_graph(
+            env,
+            &global_init_section,
+            &ir_object.what("global_init_section"),
+        )?);
+
+        let functions = ir_object.get(env, "functions")?;
+        let what = ir_object.what("functions");
+        for ir_function in items(env, &functions, &what, |env, item| {
+            let ir_function = IRJavaObject::new(env, item, &what)?;
+            let arguments_count = ir_function.unsigned(env, "arguments_count")?;
+            let fields = ir_function.get(env, "fields")?;
+            let fields = items(env, &fields, &ir_function.what("fields"), |env, item| {
+                self.field(env, item).map(Box::new)
+            })?;
+            if arguments_count > fields.len() {
+                return Err(illegal(format!(
+                    "{} is more than the {} fields",
+                    ir_function.what("arguments_count"),
+                    fields.len()
+                )));
+            }
+            let control_flow_graph = ir_function.get(env, "control_flow_graph")?;
+            Ok(IRFunction::new(
+                self._type(env, &ir_function, "return_type")?,
+                ir_function.string(env, "name")?,
+                arguments_count,
+                fields,
+                Box::new(self.control_flow_graph(
+                    env,
+                    &control_flow_graph,
+                    &ir_function.what("control_flow_graph"),
+                )?),
+            ))
+        })? {
+            ir_module.push_function(ir_function);
+        }
+
+        for (name, keys) in [
+            ("name2vtable_keys", &mut ir_module.name2vtable_keys),
+            ("name2itable_keys", &mut ir_module.name2itable_keys),
+        ] {
+            let value = ir_object.get(env, name)?;
+            let what = ir_object.what(name);
+            keys.extend(entries(env, &value, &what, |env, key, value| {
+                Ok((
+                    string(env, key, &what)?,
+                    items(env, value, &what, |env, item| string(env, item, &what))?,
+                ))
+            })?);
+        }
+
+        ir_module.entry_point = ir_object.optional_string(env, "entry_point")?;
+        Ok(ir_module)
+    }
+
+    fn field(&mut self, env: &mut JNIEnv, object: &JObject) -> IRJavaResult<IRField> {
+        let ir_field = IRJavaObject::new(env, object, "field")?;
+        Ok(IRField::new(
+            ir_field.string(env, "name")?,
+            self._type(env, &ir_field, "_type")?,
+        ))
+    }
+
+    /// The constant value of `_type` held by `value`, where null is the null pointer or
+    /// `zeroinitializer`.
+    fn value(
+        &mut self,
+        env: &mut JNIEnv,
+        value: Option<&JObject>,
+        _type: &dyn IRType,
+        structures: &IndexMap<String, Box<IRStructure>>,
+        what: &str,
+    ) -> IRJavaResult<IRConstantValue> {
+        let Some(value) = value else {
+            return Ok(match IRTypeKind::of(_type) {
+                IRTypeKind::Pointer(_) => IRConstantValue::Null,
+                _ => IRConstantValue::ZeroInitializer,
+            });
+        };
+        let mismatch = || illegal(format!("{} does not fit its type {}", what, _type));
+        Ok(match IRTypeKind::of(_type) {
+            IRTypeKind::Integer(integer) => {
+                let value = if env.is_instance_of(value, "java/lang/Boolean")? {
+                    boolean(env, value, what)? as i64
+                } else {
+                    long(env, value, what)?
+                };
+                IRConstantValue::integer(&integer.size, value)
+            }
+            IRTypeKind::Float(_) => {
+                IRConstantValue::F32(number(env, value, what, "floatValue", "()F")?.f()?)
+            }
+            IRTypeKind::Double(_) => {
+                IRConstantValue::F64(number(env, value, what, "doubleValue", "()D")?.d()?)
+            }
+            IRTypeKind::Pointer(_) => {
+                if env.is_instance_of(value, "java/lang/String")? {
+                    IRConstantValue::Bytes(string(env, value, what)?.into_bytes())
+                } else if env.is_instance_of(value, "[B")? {
+                    IRConstantValue::Bytes(env.convert_byte_array(<&JByteArray>::from(value))?)
+                } else {
+                    IRConstantValue::I64(long(env, value, what)?)
+                }
+            }
+            IRTypeKind::Array(array_type) => {
+                let elements = self.nested(env, |reader, env| {
+                    items(env, value, what, |env, item| {
+                        reader.value(
+                            env,
+                            (!item.is_null()).then_some(item),
+                            array_type.element.as_ref(),
+                            structures,
+                            what,
+                        )
+                    })
+                })?;
+                if elements.len() as u64 != array_type.length {
+                    return Err(mismatch());
+                }
+                IRConstantValue::Aggregate(elements)
+            }
+            IRTypeKind::Structure(structure_type) => {
+                let fields = &structures
+                    .get(&structure_type.name)
+                    .ok_or_else(mismatch)?
+                    .fields;
+                let mut field_types = fields.iter().map(|field| field._type.as_ref());
+                let elements = self.nested(env, |reader, env| {
+                    items(env, value, what, |env, item| {
+                        let field_type = field_types.next().ok_or_else(mismatch)?;
+                        reader.value(
+                            env,
+                            (!item.is_null()).then_some(item),
+                            field_type,
+                            structures,
+                            what,
+                        )
+                    })
+                })?;
+                if elements.len() != fields.len() {
+                    return Err(mismatch());
+                }
+                IRConstantValue::Aggregate(elements)
+            }
+            IRTypeKind::Void(_) | IRTypeKind::Function(_) => return Err(mismatch()),
+        })
+    }
+
+    fn control_flow_graph(
+        &mut self,
+        env: &mut JNIEnv,
+        object: &JObject,
+        what: &str,
+    ) -> IRJavaResult<IRControlFlowGraph> {
+        env.with_local_frame(FRAME_CAPACITY, |env| {
+            let ir_object = IRJavaObject::new(env, object, what)?;
+            let basic_blocks = ir_object.get(env, "basic_blocks")?;
+            let what = ir_object.what("basic_blocks");
+            let mut ir_control_flow_graph = IRControlFlowGraph::new();
+            for ir_basic_block in items(env, &basic_blocks, &what, |env, item| {
+                let ir_object = IRJavaObject::new(env, item, &what)?;
+                let mut ir_basic_block = IRBasicBlock::new(ir_object.string(env, "name")?);
+                let instructions = ir_object.get(env, "instructions")?;
+                ir_basic_block.instructions = items(
+                    env,
+                    &instructions,
+                    &ir_object.what("instructions"),
+                    |env, item| self.instruction(env, item),
+                )?;
+                Ok(ir_basic_block)
+            })? {
+                ir_control_flow_graph.add_basic_block(Box::new(ir_basic_block));
+            }
+            Ok(ir_control_flow_graph)
+        })
+    }
+
+    fn instruction(
+        &mut self,
+        env: &mut JNIEnv,
+        object: &JObject,
+    ) -> IRJavaResult<Box<dyn IRInstruction>> {
+        let ir_object = IRJavaObject::new(env, object, "instruction")?;
+        Ok(match ir_object.class.as_str() {
+            "IRGoto" => Box::new(IRGoto::new(ir_object.string(env, "target")?)),
+            "IRConditionalJump" => Box::new(IRConditionalJump::new(
+                ir_object.bool(env, "is_atomic")?,
+                self._type(env, &ir_object, "_type")?,
+                ir_object.variant(env, "condition", &CONDITIONS)?,
+                self.operand(env, &ir_object, "operand1")?,
+                self.optional_operand(env, &ir_object, "operand2")?,
+                ir_object.string(env, "target")?,
+            )),
+            "IRNoOperate" => Box::new(IRNoOperate::new()),
+            "IRReturn" => Box::new(IRReturn::new(
+                self.optional_operand(env, &ir_object, "operand")?,
+            )),
+            "IRMalloc" => Box::new(IRMalloc::new(
+                self.operand(env, &ir_object, "size")?,
+                self.register(env, &ir_object, "target")?,
+            )),
+            "IRFree" => Box::new(IRFree::new(self.operand(env, &ir_object, "ptr")?)),
+            "IRRealloc" => Box::new(IRRealloc::new(
+                self.operand(env, &ir_object, "ptr")?,
+                self.operand(env, &ir_object, "size")?,
+                self.register(env, &ir_object, "target")?,
+            )),
+            "IRSet" => Box::new(IRSet::new(
+                self._type(env, &ir_object, "_type")?,
+                self.operand(env, &ir_object, "address")?,
+                self.operand(env, &ir_object, "value")?,
+            )),
+            "IRGet" => Box::new(IRGet::new(
+                self._type(env, &ir_object, "_type")?,
+                self.operand(env, &ir_object, "address")?,
+                self.register(env, &ir_object, "target")?,
+            )),
+            "IRSetVirtualRegister" => Box::new(IRSetVirtualRegister::new(
+                self.operand(env, &ir_object, "source")?,
+                self.register(env, &ir_object, "target")?,
+            )),
+            "IRTypeCast" => Box::new(IRTypeCast::new(
+                ir_object.variant(env, "kind", &CAST_KINDS)?,
+                self._type(env, &ir_object, "original_type")?,
+                self.operand(env, &ir_object, "source")?,
+                self._type(env, &ir_object, "target_type")?,
+                self.register(env, &ir_object, "target")?,
+            )),
+            "IRStackAllocate" => Box::new(IRStackAllocate::new(
+                self.operand(env, &ir_object, "size")?,
+                self.register(env, &ir_object, "target")?,
+            )),
+            "IRElementAddress" => {
+                let path = ir_object.get(env, "path")?;
+                let what = ir_object.what("path");
+                let path = items(env, &path, &what, |env, item| {
+                    if !item.is_null() && env.is_instance_of(item, "java/lang/String")? {
+                        Ok(IRElementIndex::Field(string(env, item, &what)?))
+                    } else {
+                        Ok(IRElementIndex::Index(
+                            self.operand_object(env, item, &what)?,
+                        ))
+                    }
+                })?;
+                Box::new(IRElementAddress::new(
+                    self._type(env, &ir_object, "_type")?,
+                    self.operand(env, &ir_object, "base")?,
+                    path,
+                    self.register(env, &ir_object, "target")?,
+                ))
+            }
+            "IRCalculate" => Box::new(IRCalculate::new(
+                ir_object.bool(env, "is_atomic")?,
+                ir_object.variant(env, "operator", &OPERATORS)?,
+                self._type(env, &ir_object, "_type")?,
+                self.operand(env, &ir_object, "operand1")?,
+                self.operand(env, &ir_object, "operand2")?,
+                self.register(env, &ir_object, "target")?,
+            )),
+            "IRIncrease" => Box::new(IRIncrease::new(
+                self._type(env, &ir_object, "_type")?,
+                self.operand(env, &ir_object, "operand")?,
+                self.optional_register(env, &ir_object, "target")?,
+            )),
+            "IRDecrease" => Box::new(IRDecrease::new(
+                self._type(env, &ir_object, "_type")?,
+                self.operand(env, &ir_object, "operand")?,
+                self.optional_register(env, &ir_object, "target")?,
+            )),
+            "IRNot" => Box::new(IRNot::new(
+                ir_object.bool(env, "is_atomic")?,
+                self._type(env, &ir_object, "_type")?,
+                self.operand(env, &ir_object, "operand")?,
+                self.register(env, &ir_object, "target")?,
+            )),
+            "IRNegate" => Box::new(IRNegate::new(
+                ir_object.bool(env, "is_atomic")?,
+                self._type(env, &ir_object, "_type")?,
+                self.operand(env, &ir_object, "operand")?,
+                self.register(env, &ir_object, "target")?,
+            )),
+            "IRInvoke" => {
+                let argument_types = self.types(env, &ir_object, "argument_types")?;
+                let arguments = self.operands(env, &ir_object, "arguments")?;
+                if argument_types.len() != arguments.len() {
+                    return Err(illegal(format!(
+                        "{} and {} differ in length",
+                        ir_object.what("argument_types"),
+                        ir_object.what("arguments")
+                    )));
+                }
+                Box::new(IRInvoke::new(
+                    self._type(env, &ir_object, "return_type")?,
+                    self.operand(env, &ir_object, "address")?,
+                    argument_types,
+                    arguments,
+                    self.optional_register(env, &ir_object, "target")?,
+                ))
+            }
+            "IRAsm" => Box::new(IRAsm::new(
+                ir_object.string(env, "code")?,
+                self.types(env, &ir_object, "types")?,
+                self.operands(env, &ir_object, "resources")?,
+                ir_object.strings(env, "names")?,
+            )),
+            class => return Err(illegal(format!("{} is not an instruction", class))),
+        })
+    }
+
+    fn type_object(
+        &mut self,
+        env: &mut JNIEnv,
+        object: &JObject,
+        what: &str,
+    ) -> IRJavaResult<Box<dyn IRType>> {
+        self.nested(env, |reader, env| {
+            let ir_object = IRJavaObject::new(env, object, what)?;
+            let _type: Box<dyn IRType> = match ir_object.class.as_str() {
+                "IRIntegerType" => {
+                    let size = ir_object.get(env, "size")?;
+                    let size_what = ir_object.what("size");
+                    let size = if env.is_instance_of(&size, "java/lang/Number")? {
+                        let bits = long(env, &size, &size_what)?;
+                        integer_size(bits)
+                            .ok_or_else(|| illegal(format!("unknown {} {}", size_what, bits)))?
+                    } else {
+                        variant(env, &size, &size_what, &INTEGER_SIZES)?
+                    };
+                    Box::new(IRIntegerType::new(size, ir_object.bool(env, "unsigned")?))
+                }
+                "IRFloatType" => Box::new(IRFloatType::new()),
+                "IRDoubleType" => Box::new(IRDoubleType::new()),
+                "IRVoidType" => Box::new(IRVoidType::new()),
+                "IRPointerType" => {
+                    Box::new(IRPointerType::new(reader._type(env, &ir_object, "base")?))
+                }
+                "IRStructureType" => Box::new(IRStructureType::new(ir_object.string(env, "name")?)),
+                "IRArrayType" => Box::new(IRArrayType::new(
+                    reader._type(env, &ir_object, "element")?,
+                    ir_object.unsigned(env, "length")?,
+                )),
+                "IRFunctionType" => Box::new(IRFunctionType::new(
+                    reader._type(env, &ir_object, "return_type")?,
+                    reader.types(env, &ir_object, "params")?,
+                    ir_object.bool(env, "variadic")?,
+                )),
+                class => return Err(illegal(format!("{} is a {}, not a type", what, class))),
+            };
+            Ok(_type)
+        })
+    }
+
+    fn _type(
+        &mut self,
+        env: &mut JNIEnv,
+        ir_object: &IRJavaObject,
+        name: &str,
+    ) -> IRJavaResult<Box<dyn IRType>> {
+        let value = ir_object.get(env, name)?;
+        self.type_object(env, &value, &ir_object.what(name))
+    }
+
+    fn types(
+        &mut self,
+        env: &mut JNIEnv,
+        ir_object: &IRJavaObject,
+        name: &str,
+    ) -> IRJavaResult<Vec<Box<dyn IRType>>> {
+        let value = ir_object.get(env, name)?;
+        let what = ir_object.what(name);
+        items(env, &value, &what, |env, item| {
+            self.type_object(env, item, &what)
+        })
+    }
+
+    fn operand_object(
+        &mut self,
+        env: &mut JNIEnv,
+        object: &JObject,
+        what: &str,
+    ) -> IRJavaResult<Box<dyn IROperand>> {
+        self.nested(env, |reader, env| {
+            let ir_object = IRJavaObject::new(env, object, what)?;
+            let ir_operand: Box<dyn IROperand> = match ir_object.class.as_str() {
+                "IRVirtualRegister" => {
+                    Box::new(IRVirtualRegister::new(ir_object.string(env, "name")?))
+                }
+                "IRConstant" => Box::new(IRConstant::new(ir_object.unsigned(env, "index")?)),
+                "IRMacro" => Box::new(IRMacro::new(
+                    ir_object.string(env, "name")?,
+                    ir_object.strings(env, "args")?,
+                    reader.operands(env, &ir_object, "additional_operands")?,
+                )),
+                "IRPhi" => {
+                    let labels = ir_object.strings(env, "labels")?;
+                    let operands = reader.operands(env, &ir_object, "operands")?;
+                    if labels.len() != operands.len() {
+                        return Err(illegal(format!(
+                            "{} and {} differ in length",
+                            ir_object.what("labels"),
+                            ir_object.what("operands")
+                        )));
+                    }
+                    Box::new(IRPhi::new(
+                        reader._type(env, &ir_object, "_type")?,
+                        labels,
+                        operands,
+                    ))
+                }
+                "IRVirtualTable" => {
+                    Box::new(IRVirtualTable::new(ir_object.strings(env, "functions")?))
+                }
+                "IRInterfaceTable" => {
+                    let entries = ir_object.get(env, "entries")?;
+                    let what = ir_object.what("entries");
+                    Box::new(IRInterfaceTable::new(items(
+                        env,
+                        &entries,
+                        &what,
+                        |env, item| {
+                            let entry = IRJavaObject::new(env, item, &what)?;
+                            Ok(IRInterfaceTableEntry::new(
+                                entry.string(env, "name")?,
+                                entry.strings(env, "functions")?,
+                            ))
+                        },
+                    )?))
+                }
+                class => return Err(illegal(format!("{} is a {}, not an operand", what, class))),
+            };
+            Ok(ir_operand)
+        })
+    }
+
+    fn operand(
+        &mut self,
+        env: &mut JNIEnv,
+        ir_object: &IRJavaObject,
+        name: &str,
+    ) -> IRJavaResult<Box<dyn IROperand>> {
+        let value = ir_object.get(env, name)?;
+        self.operand_object(env, &value, &ir_object.what(name))
+    }
+
+    fn optional_operand(
+        &mut self,
+        env: &mut JNIEnv,
+        ir_object: &IRJavaObject,
+        name: &str,
+    ) -> IRJavaResult<Option<Box<dyn IROperand>>> {
+        match ir_object.optional(env, name)? {
+            Some(value) => Ok(Some(self.operand_object(
+                env,
+                &value,
+                &ir_object.what(name),
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    fn operand_items(
+        &mut self,
+        env: &mut JNIEnv,
+        value: &JObject,
+        what: &str,
+    ) -> IRJavaResult<Vec<Box<dyn IROperand>>> {
+        items(env, value, what, |env, item| {
+            self.operand_object(env, item, what)
+        })
+    }
+
+    fn operands(
+        &mut self,
+        env: &mut JNIEnv,
+        ir_object: &IRJavaObject,
+        name: &str,
+    ) -> IRJavaResult<Vec<Box<dyn IROperand>>> {
+        let value = ir_object.get(env, name)?;
+        self.operand_items(env, &value, &ir_object.what(name))
+    }
+
+    fn register_object(
+        &mut self,
+        env: &mut JNIEnv,
+        object: &JObject,
+        what: &str,
+    ) -> IRJavaResult<Box<IRVirtualRegister>> {
+        let ir_object = IRJavaObject::new(env, object, what)?;
+        if ir_object.class != "IRVirtualRegister" {
+            return Err(illegal(format!(
+                "{} is a {}, not a virtual register",
+                what, ir_object.class
+            )));
+        }
+        Ok(Box::new(IRVirtualRegister::new(
+            ir_object.string(env, "name")?,
+        )))
+    }
+
+    fn register(
+        &mut self,
+        env: &mut JNIEnv,
+        ir_object: &IRJavaObject,
+        name: &str,
+    ) -> IRJavaResult<Box<IRVirtualRegister>> {
+        let value = ir_object.get(env, name)?;
+        self.register_object(env, &value, &ir_object.what(name))
+    }
+
+    fn optional_register(
+        &mut self,
+        env: &mut JNIEnv,
+        ir_object: &IRJavaObject,
+        name: &str,
+    ) -> IRJavaResult<Option<Box<IRVirtualRegister>>> {
+        match ir_object.optional(env, name)? {
+            Some(value) => Ok(Some(self.register_object(
+                env,
+                &value,
+                &ir_object.what(name),
+            )?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::base::IRCondition;
+    use crate::ir::instruction::{IRCalculateOperator, IRTypeCastKind};
+
+    /// How a Java enum would name `variant`: `NotEqual` is `NOT_EQUAL`.
+    fn java_constant(variant: &impl Debug) -> String {
+        let mut constant = String::new();
+        for (i, c) in format!("{:?}", variant).chars().enumerate() {
+            if i > 0 && c.is_ascii_uppercase() {
+                constant.push('_');
+            }
+            constant.push(c.to_ascii_uppercase());
+        }
+        constant
+    }
+
+    #[test]
+    fn fields_are_read_by_their_camel_case_name() {
+        for (name, java_name) in [
+            ("name", "name"),
+            ("operand1", "operand1"),
+            ("_type", "type"),
+            ("is_atomic", "isAtomic"),
+            ("arguments_count", "argumentsCount"),
+            ("global_init_section", "globalInitSection"),
+            ("name2vtable_keys", "name2vtableKeys"),
+        ] {
+            assert_eq!(java_field_name(name), java_name);
+        }
+    }
+
+    #[test]
+    fn variants_are_named_like_the_rust_variant() {
+        fn assert_named<T: Clone + Debug + PartialEq>(variants: &[T]) {
+            for variant in variants {
+                let name = format!("{:?}", variant);
+                assert_eq!(variant_named(variants, &name).as_ref(), Some(variant));
+                let constant = java_constant(variant);
+                assert_eq!(variant_named(variants, &constant).as_ref(), Some(variant));
+                let lower = constant.to_lowercase();
+                assert_eq!(variant_named(variants, &lower).as_ref(), Some(variant));
+            }
+            assert_eq!(variant_named(variants, "UNKNOWN"), None);
+        }
+        assert_named(&CONDITIONS);
+        assert_named(&OPERATORS);
+        assert_named(&CAST_KINDS);
+        assert_named(&INTEGER_SIZES);
+        assert_eq!(java_constant(&IRCondition::NotEqual), "NOT_EQUAL");
+        assert_eq!(
+            variant_named(&CONDITIONS, "NOT_EQUAL"),
+            Some(IRCondition::NotEqual)
+        );
+        assert_eq!(
+            variant_named(&OPERATORS, "ushr"),
+            Some(IRCalculateOperator::USHR)
+        );
+        assert_eq!(
+            variant_named(&CAST_KINDS, "INT_TO_FLOAT"),
+            Some(IRTypeCastKind::IntToFloat)
+        );
+    }
+
+    #[test]
+    fn integer_sizes_may_be_given_in_bits() {
+        for size in INTEGER_SIZES {
+            assert_eq!(integer_size(size.clone() as i64), Some(size));
+        }
+        for bits in [0, 2, 7, 128, -8] {
+            assert_eq!(integer_size(bits), None);
+        }
+    }
+}
@@ -1,13 +1,20 @@
 extern crate core;
 
+use crate::backend::{IRGenerateOptions, IRGenerateResult};
 use crate::ir::IRModule;
 
+pub mod backend;
+pub mod ffi;
 pub mod ir;
+#[cfg(feature = "jni")]
+pub mod java;
 
 pub struct IRGenerator {}
 
 impl IRGenerator {
-    pub fn generate(ir_module: &IRModule, options: &Vec<String>) {
-        // TODO
+    /// Generates target code for a module. `options` selects the target, e.g. `--target=x86_64`;
+    /// see `IRGenerateOptions`.
+    pub fn generate(ir_module: &IRModule, options: &[String]) -> IRGenerateResult<Vec<u8>> {
+        backend::generate(ir_module, &IRGenerateOptions::parse(options)?)
     }
 }